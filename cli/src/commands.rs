@@ -0,0 +1,24 @@
+//! Parameters and output of the RPC calls made by `sedly`
+
+use serde_json::Value;
+
+/// Positional parameters from the command line
+///
+/// Each argument is taken as JSON when it parses (numbers, booleans,
+/// arrays, objects, quoted strings) and as a plain string otherwise, so
+/// hashes and hex data need no quoting.
+pub fn parse_params(args: &[String]) -> Value {
+    Value::Array(
+        args.iter()
+            .map(|arg| serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.clone())))
+            .collect(),
+    )
+}
+
+/// Text printed for a result: strings bare, everything else as pretty JSON
+pub fn format_result(result: &Value) -> String {
+    match result {
+        Value::String(text) => text.clone(),
+        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,65 @@
+//! sedly-genesis: deterministic genesis builder for new Sedly networks
+//!
+//! Reads a TOML spec such as
+//!
+//! ```toml
+//! message = "Sedly devnet 2025-01-01"
+//! timestamp = 1735689600
+//! bits = 0x1d00ffff
+//!
+//! [[allocations]]
+//! script = "76a914...88ac"
+//! amount = 100000000
+//! ```
+//!
+//! and prints the genesis hash and the Tendermint `app_state`, optionally
+//! writing it into an existing genesis.json.
+
+use clap::Parser;
+use sedly_core::{GenesisAppState, GenesisSpec};
+use std::path::PathBuf;
+
+/// Sedly genesis builder
+#[derive(Debug, Parser)]
+#[command(name = "sedly-genesis", version)]
+struct Args {
+    /// TOML genesis spec
+    spec: PathBuf,
+    /// Tendermint genesis.json whose app_state is replaced in place
+    #[arg(long)]
+    tendermint_genesis: Option<PathBuf>,
+    /// Write the app_state JSON to this file instead of stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let spec: GenesisSpec = toml::from_str(&std::fs::read_to_string(&args.spec)?)?;
+    let block = spec.build()?;
+    let app_state = GenesisAppState::from_block(&block)?;
+
+    println!("Genesis hash: {}", app_state.genesis_hash);
+    println!("Merkle root:  {}", hex::encode(block.header.merkle_root));
+    println!("Premine:      {} satoshi in {} outputs", spec.premine()?, spec.allocations.len());
+
+    let app_state = serde_json::to_value(&app_state)?;
+    if let Some(path) = &args.tendermint_genesis {
+        let mut genesis: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let Some(fields) = genesis.as_object_mut() else {
+            anyhow::bail!("{} is not a Tendermint genesis document", path.display());
+        };
+        fields.insert("app_state".to_string(), app_state.clone());
+        std::fs::write(path, serde_json::to_string_pretty(&genesis)?)?;
+        println!("Updated app_state in {}", path.display());
+    }
+
+    match &args.output {
+        Some(path) => std::fs::write(path, serde_json::to_string_pretty(&app_state)?)?,
+        None if args.tendermint_genesis.is_none() => println!("{}", serde_json::to_string_pretty(&app_state)?),
+        None => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! sedly: command line client of a Sedly node's JSON-RPC server
+
+use clap::Parser;
+use sedly_rpc::auth::COOKIE_FILE_NAME;
+use sedly_rpc::{RpcAuth, RpcClient};
+use std::path::Path;
+
+mod commands;
+
+/// Call a JSON-RPC method of a running node
+#[derive(Debug, Parser)]
+#[command(name = "sedly", version)]
+struct Args {
+    /// RPC server address of the node
+    #[arg(long, default_value = "127.0.0.1:8545")]
+    rpc_addr: String,
+    /// RPC user, with --rpc-password (default: the cookie in the data directory)
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+    /// RPC password, with --rpc-user
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+    /// Data directory of the node, where it writes the RPC cookie
+    #[arg(long, default_value = "./blockchain_data")]
+    data_dir: String,
+    /// Method to call (e.g. getblockchaininfo)
+    method: String,
+    /// Positional parameters, as JSON or plain strings
+    params: Vec<String>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let mut client = RpcClient::new(args.rpc_addr);
+    // Without credentials only the methods outside the wallet are served
+    let auth = match (args.rpc_user, args.rpc_password) {
+        (Some(user), Some(password)) => Some(RpcAuth::new(user, password)),
+        _ => RpcAuth::read_cookie(&Path::new(&args.data_dir).join(COOKIE_FILE_NAME)).ok(),
+    };
+    if let Some(auth) = auth {
+        client = client.with_auth(auth);
+    }
+    let result = client.call(&args.method, commands::parse_params(&args.params))?;
+    println!("{}", commands::format_result(&result));
+    Ok(())
+}
@@ -0,0 +1,638 @@
+//! sedly-node: Sedly full node, as a Tendermint ABCI application or a standalone proof of work node
+
+use clap::{Parser, Subcommand};
+use sedly_consensus::{
+    ConsensusServer, NotifyConfig, OrderingPolicy, ProductionConfig, RetainConfig, ServerConfig, WebhookConfig,
+    WebhookEvent, DEFAULT_MAX_PRODUCTION_TIME,
+};
+use sedly_core::mempool::MEMPOOL_FILE_NAME;
+use sedly_core::{
+    Alert, AlertSet, Block, BlockHash, BlockValidator, BlockchainDB, ChainParams, FeePolicy, FeeSimulator,
+    GenesisAppState, HardwareReport, Hash256, Mempool, Network, NodeMode, PowKind, Reindexer, Replayer,
+};
+use sedly_network::{initial_peers, AddrNetwork, BootstrapConfig, SystemResolver};
+use sedly_rpc::auth::COOKIE_FILE_NAME;
+use sedly_rpc::{RpcAuth, RpcConfig, RpcContext, RpcServer};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the active network alerts are logged again
+const ALERT_REMINDER_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[cfg(feature = "pprof")]
+mod profiling;
+mod standalone;
+
+/// Sedly full node
+#[derive(Debug, Parser)]
+#[command(name = "sedly-node", version)]
+struct Args {
+    /// Directory of the blockchain database
+    #[arg(long, default_value = "./blockchain_data")]
+    data_dir: String,
+    /// Network to join (mainnet, testnet, regtest)
+    #[arg(long, default_value = "mainnet")]
+    network: Network,
+    /// Tendermint genesis.json whose app_state defines a custom genesis block
+    #[arg(long)]
+    genesis_file: Option<String>,
+    /// Switch proof of work to this algorithm (sha256d, sha3, scratchpad); testnet and regtest only
+    #[arg(long)]
+    pow_algorithm: Option<PowKind>,
+    /// First height mined with --pow-algorithm
+    #[arg(long, default_value_t = 0)]
+    pow_activation_height: u64,
+    /// ABCI bind address
+    #[arg(long, default_value = "127.0.0.1:26658")]
+    abci_addr: String,
+    /// Number of recent blocks Tendermint keeps in its block store (0 keeps all)
+    #[arg(long, default_value_t = 0)]
+    retain_blocks: u64,
+    /// Snapshot interval in blocks; blocks since the latest snapshot are never pruned
+    #[arg(long, default_value_t = 0)]
+    snapshot_interval: u64,
+    /// Verify every N blocks that the UTXO set matches the issued supply (0 disables)
+    #[arg(long, default_value_t = 0)]
+    audit_supply_interval: u64,
+    /// Keep the last N consensus-rule rejections for the debug/rejections query (0 disables)
+    #[arg(long, default_value_t = 0)]
+    rejection_log: usize,
+    /// Keep up to this many MB of low-feerate transactions evicted from a full mempool on disk (0 disables)
+    #[arg(long, default_value_t = 0)]
+    mempool_spill_mb: usize,
+    /// Propose blocks without transactions (mirrored by create_empty_blocks in Tendermint's config.toml)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    create_empty_blocks: bool,
+    /// Seconds after which an empty block is proposed anyway when empty blocks are off (0 waits for transactions)
+    #[arg(long, default_value_t = 0)]
+    empty_blocks_interval: u64,
+    /// Milliseconds spent validating the transactions of a proposed block before leaving the rest for the next one
+    #[arg(long, default_value_t = DEFAULT_MAX_PRODUCTION_TIME.as_millis() as u64)]
+    max_production_ms: u64,
+    /// Order of transactions in this validator's proposals (not enforced on other proposers):
+    /// feerate, first-seen (within feerate bands) or hash-shuffle
+    #[arg(long, default_value = "feerate")]
+    tx_ordering: OrderingPolicy,
+    /// Wipe UTXO set, indexes and metadata, then replay and revalidate all stored blocks
+    #[arg(long)]
+    reindex: bool,
+    /// ZeroMQ endpoint publishing connected block hashes (e.g. tcp://127.0.0.1:28332)
+    #[arg(long)]
+    zmqpubhashblock: Option<String>,
+    /// ZeroMQ endpoint publishing new transaction hashes
+    #[arg(long)]
+    zmqpubhashtx: Option<String>,
+    /// ZeroMQ endpoint publishing serialized connected blocks
+    #[arg(long)]
+    zmqpubrawblock: Option<String>,
+    /// ZeroMQ endpoint publishing serialized new transactions
+    #[arg(long)]
+    zmqpubrawtx: Option<String>,
+    /// HTTP URL receiving webhook notifications as JSON POSTs; repeatable
+    #[arg(long)]
+    webhook: Vec<String>,
+    /// Secret signing webhook bodies (HMAC-SHA256 in the X-Sedly-Signature header)
+    #[arg(long)]
+    webhook_secret: Option<String>,
+    /// Webhook events to send (block, transaction, reorg, error); repeatable, default all
+    #[arg(long)]
+    webhook_event: Vec<WebhookEvent>,
+    /// Hex script whose transactions are sent to the webhooks; repeatable, default all
+    #[arg(long)]
+    webhook_watch: Vec<String>,
+    /// Connect only to this peer (host[:port]); repeatable, disables seeds
+    #[arg(long)]
+    connect: Vec<String>,
+    /// Also connect to this peer (host[:port]); repeatable
+    #[arg(long)]
+    addnode: Vec<String>,
+    /// Do not query the DNS seeds of the network
+    #[arg(long)]
+    nodnsseed: bool,
+    /// Only connect to and accept peers on this network (ipv4, ipv6, onion); repeatable
+    #[arg(long)]
+    onlynet: Vec<AddrNetwork>,
+    /// Consensus mode: tendermint (ABCI application) or standalone (proof of work only, blocks relayed over P2P)
+    #[arg(long, default_value = "tendermint")]
+    mode: NodeMode,
+    /// Serve the JSON-RPC API and the /ready probe on this address (e.g. 127.0.0.1:8545)
+    #[arg(long)]
+    rpc_bind: Option<String>,
+    /// User of the RPC wallet methods, with --rpc-password (default: a random password in <data-dir>/.cookie)
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+    /// Password of the RPC wallet methods, with --rpc-user
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+    /// Browser origin allowed to call the RPC API (CORS); repeatable, never with credentials
+    #[arg(long)]
+    rpc_allow_origin: Vec<String>,
+    /// Standalone mode: P2P bind address (default: 0.0.0.0 on the network's port)
+    #[arg(long)]
+    p2p_addr: Option<String>,
+    /// Standalone mode: mine blocks paying this hex script
+    #[arg(long)]
+    mine_to: Option<String>,
+    /// Standalone mode: hashing threads of the miner
+    #[arg(long, default_value_t = 1)]
+    mining_threads: usize,
+    /// Profile the node and write a CPU flamegraph (SVG) to this file on shutdown
+    #[cfg(feature = "pprof")]
+    #[arg(long)]
+    profile_out: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Offline tools run instead of the node
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Re-execute stored blocks and report the first divergence from the stored chain
+    Replay {
+        /// First height to revalidate; blocks below it are reconnected without validation
+        #[arg(long)]
+        from: u64,
+        /// Last height to revalidate (default: stored tip)
+        #[arg(long)]
+        to: Option<u64>,
+        /// Scratch database rebuilt by the replay, removed afterwards (default: <data-dir>-replay)
+        #[arg(long)]
+        replay_dir: Option<String>,
+    },
+    /// Sign a network alert with a maintainer key and print it as hex for the `sendalert` RPC
+    SignAlert {
+        /// File holding the secret key of one of the network alert keys (hex)
+        #[arg(long)]
+        key_file: String,
+        /// Alert id, unique on the network
+        #[arg(long)]
+        id: u32,
+        /// Id of an alert cancelled by this one; repeatable
+        #[arg(long)]
+        cancel: Vec<u32>,
+        /// UNIX time after which the alert expires
+        #[arg(long)]
+        expiration: u64,
+        /// Priority, highest shown first
+        #[arg(long, default_value_t = 0)]
+        priority: u32,
+        /// Message for node operators (empty for an alert that only cancels others)
+        #[arg(long, default_value = "")]
+        message: String,
+    },
+    /// Measure the hash rate of every proof of work algorithm on this machine
+    PowBench {
+        /// Seconds spent hashing with each algorithm
+        #[arg(long, default_value_t = 3)]
+        seconds: u64,
+    },
+    /// Replay recent blocks and the saved mempool under alternative fee policies and compare the outcomes
+    SimulateFees {
+        /// Recent blocks whose transactions are replayed
+        #[arg(long, default_value_t = 1_000)]
+        blocks: u64,
+        /// Minimum feerate (per 1000 bytes) to simulate; repeatable (default: current policy)
+        #[arg(long)]
+        min_feerate: Vec<u64>,
+        /// Maximum block size in bytes to simulate; repeatable (default: current policy)
+        #[arg(long)]
+        max_block_size: Vec<usize>,
+        /// Print the reports as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let mut params = ChainParams::for_network(args.network);
+    if let Some(algorithm) = args.pow_algorithm {
+        if args.network == Network::Mainnet {
+            anyhow::bail!("--pow-algorithm is only allowed on testnet and regtest");
+        }
+        log::info!("Proof of work {} from height {}", algorithm.name(), args.pow_activation_height);
+        params = params.with_pow_algorithm(args.pow_activation_height, algorithm);
+    }
+    if let Some(Command::PowBench { seconds }) = args.command {
+        pow_bench(Duration::from_secs(seconds));
+        return Ok(());
+    }
+    if let Some(Command::Replay { from, to, replay_dir }) = &args.command {
+        let replay_dir = replay_dir.clone().unwrap_or_else(|| format!("{}-replay", args.data_dir));
+        return replay(&args.data_dir, Path::new(&replay_dir), &params, *from, *to);
+    }
+    if let Some(Command::SimulateFees { blocks, min_feerate, max_block_size, json }) = &args.command {
+        return simulate_fees(&args.data_dir, &params, *blocks, min_feerate, max_block_size, *json);
+    }
+    if let Some(Command::SignAlert { key_file, id, cancel, expiration, priority, message }) = args.command {
+        let alert = Alert { id, cancel, expiration, priority, message };
+        return sign_alert(Path::new(&key_file), alert, &params);
+    }
+    #[cfg(feature = "pprof")]
+    let profiler = args.profile_out.as_deref().map(profiling::Profiler::start).transpose()?;
+
+    if args.reindex {
+        reindex(&args.data_dir, &params)?;
+    }
+
+    let webhooks = webhook_configs(&args)?;
+    let rpc_config = rpc_config(&args)?;
+    let bootstrap = BootstrapConfig {
+        connect: args.connect,
+        add_nodes: args.addnode,
+        no_dns_seed: args.nodnsseed,
+        only_net: args.onlynet.clone(),
+    };
+    let peer_params = params.clone();
+    let peers = tokio::task::spawn_blocking(move || initial_peers(&peer_params, &bootstrap, &SystemResolver)).await?;
+    if peers.is_empty() {
+        log::warn!("No bootstrap peers found; use --addnode or --connect to reach the network");
+    }
+    for peer in &peers {
+        log::info!("Bootstrap peer {}", peer);
+    }
+
+    let genesis = match &args.genesis_file {
+        Some(path) => load_genesis(Path::new(path))?,
+        None => Block::genesis(),
+    };
+    log::info!("Using genesis block {}", genesis.block_hash());
+    log::info!("Hashing backend: {}", sedly_core::hash::backend().name());
+    let hardware_dir = args.data_dir.clone();
+    let hardware_params = params.clone();
+    tokio::task::spawn_blocking(move || check_hardware(Path::new(&hardware_dir), &hardware_params)).await?;
+
+    if args.mode == NodeMode::Standalone {
+        let mine_to = match &args.mine_to {
+            Some(script) => Some(hex::decode(script).map_err(|e| anyhow::anyhow!("Invalid --mine-to script: {}", e))?),
+            None => None,
+        };
+        // The standalone mode connects directly: it has no Tor proxy to reach onion peers through
+        let peers = peers
+            .iter()
+            .map(|peer| {
+                peer.to_socket_addr().map(|addr| addr.to_string()).ok_or_else(|| {
+                    anyhow::anyhow!("Onion peer {} needs a Tor proxy, which the standalone mode does not support", peer)
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let config = standalone::StandaloneConfig {
+            p2p_addr: args.p2p_addr.unwrap_or_else(|| format!("0.0.0.0:{}", params.network.default_port())),
+            peers,
+            only_net: args.onlynet,
+            mine_to,
+            mining_threads: args.mining_threads,
+            mempool_spill: args.mempool_spill_mb * 1_000_000,
+            data_dir: args.data_dir,
+        };
+        let node = standalone::StandaloneNode::open(config, params, &genesis)?;
+        if let Some(config) = rpc_config {
+            serve_rpc(config, node.rpc_context());
+        }
+        tokio::select! {
+            result = node.run() => result?,
+            _ = tokio::signal::ctrl_c() => log::info!("Shutdown requested"),
+        }
+        node.shutdown()?;
+        return Ok(());
+    }
+
+    let config = ServerConfig {
+        abci_addr: args.abci_addr,
+        db_path: args.data_dir,
+        retain: RetainConfig::new(args.retain_blocks).with_snapshot_interval(args.snapshot_interval),
+        production: ProductionConfig {
+            create_empty_blocks: args.create_empty_blocks,
+            empty_blocks_interval: Duration::from_secs(args.empty_blocks_interval),
+            max_production_time: Duration::from_millis(args.max_production_ms),
+            ordering: args.tx_ordering,
+        },
+        audit_supply_interval: args.audit_supply_interval,
+        notify: NotifyConfig {
+            hash_block: args.zmqpubhashblock,
+            hash_tx: args.zmqpubhashtx,
+            raw_block: args.zmqpubrawblock,
+            raw_tx: args.zmqpubrawtx,
+        },
+        webhooks,
+        rejection_log: args.rejection_log,
+        mempool_spill: args.mempool_spill_mb * 1_000_000,
+        ..ServerConfig::default()
+    };
+    let server = ConsensusServer::with_genesis(config, params.clone(), &genesis)?;
+    let app = server.app();
+    // Commit failures panic on purpose: write the mempool and database out first
+    Arc::new(app.crash_flush()).install_panic_hook();
+    tokio::spawn(remind_alerts(app.alerts().clone()));
+    if let Some(config) = rpc_config {
+        let mut context = RpcContext::new(app.db(), params)
+            .with_mempool(app.mempool())
+            .with_alerts(app.alerts().clone())
+            .without_clock_sync()
+            .without_chain_control();
+        if let Some(rejections) = app.rejection_log() {
+            context = context.with_rejection_log(rejections.clone());
+        }
+        serve_rpc(config, context);
+    }
+
+    tokio::select! {
+        result = server.start() => result?,
+        _ = tokio::signal::ctrl_c() => log::info!("Shutdown requested"),
+    }
+
+    // Keep pending transactions across the restart
+    app.save_mempool()?;
+
+    #[cfg(feature = "pprof")]
+    if let Some(profiler) = profiler {
+        profiler.finish()?;
+    }
+
+    Ok(())
+}
+
+/// RPC server configuration of the command line, None without --rpc-bind
+///
+/// Without --rpc-user and --rpc-password the credentials are a random
+/// password written to the cookie file of the data directory, which the
+/// `sedly` client reads.
+fn rpc_config(args: &Args) -> anyhow::Result<Option<RpcConfig>> {
+    let Some(bind) = &args.rpc_bind else {
+        return Ok(None);
+    };
+    let auth = match (&args.rpc_user, &args.rpc_password) {
+        (Some(user), Some(password)) => RpcAuth::new(user, password),
+        _ => {
+            std::fs::create_dir_all(&args.data_dir)?;
+            let path = Path::new(&args.data_dir).join(COOKIE_FILE_NAME);
+            let auth = RpcAuth::write_cookie(&path)
+                .map_err(|e| anyhow::anyhow!("Cannot write the RPC cookie {}: {}", path.display(), e))?;
+            log::info!("RPC credentials in {}", path.display());
+            auth
+        }
+    };
+    Ok(Some(RpcConfig {
+        bind_addr: bind.clone(),
+        auth: Some(auth),
+        cors_origins: args.rpc_allow_origin.clone(),
+        ..RpcConfig::default()
+    }))
+}
+
+/// Serve the JSON-RPC API in the background
+fn serve_rpc(config: RpcConfig, context: RpcContext) {
+    tokio::spawn(async move {
+        if let Err(e) = RpcServer::new(config, context).start().await {
+            log::error!("RPC server stopped: {}", e);
+        }
+    });
+}
+
+/// Measure the machine on first start (reuse the saved measurements after) and log its warnings
+fn check_hardware(data_dir: &Path, params: &ChainParams) {
+    let report = match HardwareReport::load_or_measure(data_dir, params, 0) {
+        Ok(report) => report,
+        Err(e) => {
+            log::warn!("Hardware check failed: {}", e);
+            return;
+        }
+    };
+    log::info!(
+        "Hardware: {} at {:.0} H/s, disk writes at {:.1} MB/s",
+        report.pow_algorithm, report.hash_rate, report.disk_write_rate / 1e6
+    );
+    for warning in &report.warnings {
+        log::warn!("{}", warning);
+    }
+}
+
+/// Log the active network alerts again every `ALERT_REMINDER_INTERVAL`
+///
+/// New alerts are logged when they arrive; the reminder keeps critical
+/// ones (e.g. a required upgrade) visible in long-running node logs.
+async fn remind_alerts(alerts: Arc<Mutex<AlertSet>>) {
+    let mut interval = tokio::time::interval(ALERT_REMINDER_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut alerts = alerts.lock().unwrap();
+        alerts.prune(now);
+        for signed in alerts.active(now) {
+            log::warn!("Network alert {}: {}", signed.alert.id, signed.alert.message);
+        }
+    }
+}
+
+/// Sign `alert` with the key in `key_file` and print the signed alert as hex
+fn sign_alert(key_file: &Path, alert: Alert, params: &ChainParams) -> anyhow::Result<()> {
+    let secret = hex::decode(std::fs::read_to_string(key_file)?.trim())?;
+    let secret_key = secp256k1::SecretKey::from_slice(&secret)?;
+    let secp = secp256k1::Secp256k1::new();
+    let pubkey = secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize().to_vec();
+    if !params.alert_keys.contains(&pubkey) {
+        log::warn!("Key {} is not an alert key of {}", hex::encode(&pubkey), params.network.name());
+    }
+    println!("{}", hex::encode(alert.sign(&secp, &secret_key).to_bytes()));
+    Ok(())
+}
+
+/// Print the hash rate of every proof of work algorithm, hashing the genesis header for `duration` each
+fn pow_bench(duration: Duration) {
+    let mut header = Block::genesis().header;
+    for kind in PowKind::ALL {
+        let start = Instant::now();
+        let mut hashes = 0u64;
+        while start.elapsed() < duration {
+            header.nonce = hashes;
+            std::hint::black_box(header.pow_hash(kind.algorithm()));
+            hashes += 1;
+        }
+        println!("{:<12} {:>14.0} H/s", kind.name(), hashes as f64 / start.elapsed().as_secs_f64());
+    }
+}
+
+/// Replay recent blocks and the saved mempool under every combination of the given feerates and block sizes,
+/// printing the current policy first
+fn simulate_fees(
+    data_dir: &str,
+    params: &ChainParams,
+    blocks: u64,
+    min_feerates: &[u64],
+    block_sizes: &[usize],
+    json: bool,
+) -> anyhow::Result<()> {
+    let db = BlockchainDB::open(data_dir)?;
+    db.check_network_magic(params.magic)?;
+    let validator = BlockValidator::new(params.clone());
+
+    let mut simulator = FeeSimulator::from_chain(&db.snapshot(), blocks, params.target_block_time)?;
+    let (mempool, stats) = Mempool::load(Path::new(data_dir).join(MEMPOOL_FILE_NAME), &validator, &db)?;
+    simulator.add_mempool(&mempool);
+    log::info!(
+        "Simulating {} transactions from {} blocks and {} mempool entries ({} no longer valid, {} expired)",
+        simulator.transactions(),
+        simulator.historical_blocks(),
+        stats.loaded,
+        stats.failed,
+        stats.expired,
+    );
+
+    let current = FeePolicy { min_feerate: 0, max_block_size: validator.max_block_size() };
+    let min_feerates = if min_feerates.is_empty() { &[current.min_feerate][..] } else { min_feerates };
+    let block_sizes = if block_sizes.is_empty() { &[current.max_block_size][..] } else { block_sizes };
+    let mut policies = vec![current];
+    for &min_feerate in min_feerates {
+        for &max_block_size in block_sizes {
+            let policy = FeePolicy { min_feerate, max_block_size };
+            if !policies.contains(&policy) {
+                policies.push(policy);
+            }
+        }
+    }
+
+    let reports: Vec<_> = policies.iter().map(|policy| simulator.run(policy)).collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    println!(
+        "{:>11} {:>10} {:>9} {:>8} {:>11} {:>14} {:>10} {:>6} {:>12} {:>14}",
+        "min_feerate", "block_size", "confirmed", "rejected", "unconfirmed", "fees", "fees/block", "fill",
+        "wait blocks", "wait secs",
+    );
+    for report in &reports {
+        println!(
+            "{:>11} {:>10} {:>9} {:>8} {:>11} {:>14} {:>10} {:>5.1}% {:>12} {:>14}",
+            report.policy.min_feerate,
+            report.policy.max_block_size,
+            report.confirmed,
+            report.rejected,
+            report.unconfirmed,
+            report.fee_revenue,
+            report.fees_per_block(),
+            report.average_fill * 100.0,
+            format!("{}/{}", report.median_wait_blocks, report.p90_wait_blocks),
+            format!("{}/{}", report.median_wait_secs, report.p90_wait_secs),
+        );
+    }
+    println!("Waits are median/p90; the first row is the current policy");
+    Ok(())
+}
+
+/// Webhook targets of the command line, sharing secret, events and watched scripts
+fn webhook_configs(args: &Args) -> anyhow::Result<Vec<WebhookConfig>> {
+    let watch_scripts = args.webhook_watch
+        .iter()
+        .map(|script| hex::decode(script).map_err(|e| anyhow::anyhow!("Invalid --webhook-watch script {}: {}", script, e)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(args.webhook
+        .iter()
+        .map(|url| {
+            let config = WebhookConfig::new(url.as_str())
+                .with_events(args.webhook_event.clone())
+                .with_watch_scripts(watch_scripts.clone());
+            match &args.webhook_secret {
+                Some(secret) => config.with_secret(secret.as_str()),
+                None => config,
+            }
+        })
+        .collect())
+}
+
+/// Genesis block from the app_state of a Tendermint genesis file (built-in genesis if absent)
+fn load_genesis(path: &Path) -> anyhow::Result<Block> {
+    let document: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    match document.get("app_state") {
+        None | Some(serde_json::Value::Null) => Ok(Block::genesis()),
+        Some(serde_json::Value::Object(fields)) if fields.is_empty() => Ok(Block::genesis()),
+        Some(app_state) => {
+            let app_state: GenesisAppState = serde_json::from_value(app_state.clone())?;
+            Ok(app_state.block()?)
+        }
+    }
+}
+
+/// Rebuild derived state from the raw blocks before the node starts
+fn reindex(data_dir: &str, params: &ChainParams) -> anyhow::Result<()> {
+    log::info!("Reindexing chain in {}", data_dir);
+    let db = BlockchainDB::open(data_dir)?;
+    db.check_network_magic(params.magic)?;
+
+    let summary = Reindexer::new(&db, BlockValidator::new(params.clone())).run(|progress| {
+        log::info!(
+            "Reindex: height {}/{} ({:.1}%), {} txs, {:.0} blocks/s",
+            progress.height,
+            progress.target_height,
+            progress.percent(),
+            progress.transactions,
+            progress.blocks_per_second(),
+        );
+    })?;
+
+    log::info!(
+        "Reindex complete: height {} ({}), {} transactions, {} stale blocks skipped in {:.1}s",
+        summary.height,
+        BlockHash::from(summary.best_block_hash),
+        summary.transactions,
+        summary.stale_blocks,
+        summary.elapsed.as_secs_f64(),
+    );
+    Ok(())
+}
+
+/// Re-execute stored blocks on a scratch database and compare the results with the stored chain
+fn replay(data_dir: &str, replay_dir: &Path, params: &ChainParams, from: u64, to: Option<u64>) -> anyhow::Result<()> {
+    if replay_dir.exists() {
+        anyhow::bail!("Replay directory {} already exists; remove it or pass --replay-dir", replay_dir.display());
+    }
+    let source = BlockchainDB::open(data_dir)?;
+    source.check_network_magic(params.magic)?;
+    source.check_db()?;
+    let to = match to {
+        Some(to) => to,
+        None => source.get_height()?,
+    };
+
+    log::info!("Replaying blocks {}..={} from {} into {}", from, to, data_dir, replay_dir.display());
+    let result = {
+        let target = BlockchainDB::open(replay_dir)?;
+        Replayer::new(&source, &target, BlockValidator::new(params.clone())).run(from, to, |block| {
+            log::debug!(
+                "Replayed block {} ({}), {} txs in {}us",
+                block.height,
+                hex::encode(block.app_hash),
+                block.transactions,
+                block.elapsed.as_micros(),
+            );
+        })
+    };
+    if let Err(e) = std::fs::remove_dir_all(replay_dir) {
+        log::warn!("Failed to remove replay directory {}: {}", replay_dir.display(), e);
+    }
+    let report = result?;
+
+    if let Some(commitment) = report.utxo_commitment {
+        log::info!("UTXO commitment at height {}: {}", report.to, Hash256::from(commitment));
+    }
+    match report.divergence {
+        Some(divergence) => anyhow::bail!(
+            "Replay diverged after {} matching blocks: {}",
+            report.blocks,
+            divergence,
+        ),
+        None => {
+            log::info!(
+                "Replay complete: blocks {}..={} match the stored chain ({} transactions) in {:.1}s",
+                report.from,
+                report.to,
+                report.transactions,
+                report.elapsed.as_secs_f64(),
+            );
+            Ok(())
+        }
+    }
+}
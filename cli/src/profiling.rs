@@ -0,0 +1,36 @@
+//! CPU profiling of the node (`--features pprof`)
+//!
+//! Samples every thread of the process while the node runs and writes a
+//! flamegraph when it shuts down.
+
+use std::fs::File;
+use std::path::PathBuf;
+
+/// Sampling frequency in Hz; not a multiple of common timer frequencies
+const FREQUENCY: i32 = 99;
+
+/// Running profiler writing its flamegraph to a file
+pub struct Profiler {
+    guard: pprof::ProfilerGuard<'static>,
+    output: PathBuf,
+}
+
+impl Profiler {
+    /// Start sampling; the flamegraph goes to `output` (SVG)
+    pub fn start(output: &str) -> anyhow::Result<Self> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+        log::info!("CPU profiling at {} Hz, flamegraph will be written to {}", FREQUENCY, output);
+        Ok(Self { guard, output: PathBuf::from(output) })
+    }
+
+    /// Stop sampling and write the flamegraph
+    pub fn finish(self) -> anyhow::Result<()> {
+        let report = self.guard.report().build()?;
+        report.flamegraph(File::create(&self.output)?)?;
+        log::info!("Wrote CPU flamegraph to {}", self.output.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,486 @@
+//! Standalone proof of work mode: mining and block relay without Tendermint
+//!
+//! The node keeps the chain with the most work itself. A mining thread
+//! assembles templates from the mempool and hashes them; peers exchange
+//! blocks and transactions over the relay messages of `sedly_network`
+//! once the version handshake is done, and the addresses they know in
+//! `addr` messages. Every block that extends the
+//! active chain interrupts the current template, so the miner always
+//! works on the tip.
+
+use sedly_core::mempool::MEMPOOL_FILE_NAME;
+use sedly_core::mining::MiningError;
+use sedly_core::{
+    Block, BlockAcceptance, BlockValidator, BlockchainDB, ChainParams, Miner, NetStats, NodeCore, StandaloneChain,
+    StandaloneError, Transaction,
+};
+use sedly_network::{
+    encode_addr, local_services, read_frame, receive_addr, receive_message, receive_version, send_message, AddrManager,
+    AddrNetwork, Misbehavior, PeerAddress, PeerScores, RelayMessage, VersionMessage, ADDR_COMMAND, VERACK_COMMAND,
+    VERSION_COMMAND,
+};
+use sedly_network::address::is_allowed;
+use sedly_network::protocol::MAX_PAYLOAD_LEN;
+use sedly_rpc::{BlockSubmitter, RpcContext};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+/// How long the miner hashes a template before rebuilding it with newer mempool transactions
+const TEMPLATE_REFRESH: Duration = Duration::from_secs(10);
+
+/// Delay before reconnecting to a configured peer
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Time allowed to complete the version handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Relay messages queued per peer; a slower peer misses announcements and catches up with `getblocks`
+const PEER_QUEUE_LEN: usize = 256;
+
+/// Bytes of the `blocks` payload taken by the length of the block list
+const BLOCKS_PAYLOAD_OVERHEAD: usize = 8;
+
+/// Configuration of the standalone mode
+#[derive(Debug, Clone)]
+pub struct StandaloneConfig {
+    /// Directory of the blockchain database
+    pub data_dir: String,
+    /// Address accepting peer connections
+    pub p2p_addr: String,
+    /// Peers to connect to (host:port)
+    pub peers: Vec<String>,
+    /// Networks of the peers accepted and connected to (empty allows all)
+    pub only_net: Vec<AddrNetwork>,
+    /// Script paid by mined coinbases (no mining when absent)
+    pub mine_to: Option<Vec<u8>>,
+    /// Hashing threads of the miner
+    pub mining_threads: usize,
+    /// Bytes of evicted mempool transactions kept on disk (0 disables)
+    pub mempool_spill: usize,
+}
+
+/// State shared by the miner and the peer connections
+struct Node {
+    chain: Mutex<StandaloneChain>,
+    params: ChainParams,
+    stats: Mutex<NetStats>,
+    scores: Mutex<PeerScores>,
+    /// Addresses advertised by the peers
+    addrman: Mutex<AddrManager>,
+    /// Messages for every peer but the origin (None for local blocks and transactions)
+    relay: broadcast::Sender<(Option<String>, RelayMessage)>,
+    /// Set to interrupt the current mining round when the tip changes
+    stop_mining: Arc<AtomicBool>,
+    /// Nonce of our version messages, to detect connections to ourselves
+    nonce: u64,
+}
+
+impl Node {
+    /// Process a mined or received block, relaying it if it extends the active chain
+    fn process_block(&self, origin: Option<&str>, block: Block) -> Result<BlockAcceptance, StandaloneError> {
+        let hash = block.hash();
+        let announcement = block.clone();
+        let acceptance = self.chain.lock().unwrap().accept_block(block)?;
+        if let BlockAcceptance::Connected(update) = &acceptance {
+            self.stop_mining.store(true, Ordering::Relaxed);
+            if !update.disconnected.is_empty() {
+                log::warn!("Reorganization: {} blocks disconnected", update.disconnected.len());
+            }
+            for (rejected, error) in &update.rejected {
+                log::warn!("Block {} rejected while connecting: {}", hex::encode(rejected), error);
+            }
+            if update.connected.contains(&hash) {
+                let height = announcement.header.height;
+                log::info!("New tip {} at height {}", announcement.block_hash(), height);
+                let _ = self.relay.send((origin.map(str::to_string), RelayMessage::Block(announcement)));
+            }
+        }
+        Ok(acceptance)
+    }
+
+    /// Add a received or submitted transaction to the mempool and relay it
+    fn process_transaction(&self, origin: Option<&str>, tx: Transaction) {
+        match self.chain.lock().unwrap().accept_transaction(tx.clone()) {
+            Ok(_) => {
+                let _ = self.relay.send((origin.map(str::to_string), RelayMessage::Transaction(tx)));
+            }
+            Err(e) => log::debug!("Transaction {} refused: {}", hex::encode(tx.hash()), e),
+        }
+    }
+
+    /// Record a misbehavior of `peer`, returning whether it is now banned
+    fn penalize(&self, peer: &str, misbehavior: Misbehavior) -> bool {
+        self.scores.lock().unwrap().penalize(ban_key(peer), misbehavior, unix_now())
+    }
+}
+
+/// Standalone node over an open database
+pub struct StandaloneNode {
+    node: Arc<Node>,
+    config: StandaloneConfig,
+}
+
+impl StandaloneNode {
+    /// Open the database (initializing `genesis` on an empty one) and reload the saved mempool
+    pub fn open(config: StandaloneConfig, params: ChainParams, genesis: &Block) -> anyhow::Result<Self> {
+        let db = Arc::new(BlockchainDB::open(&config.data_dir)?);
+        db.check_network_magic(params.magic)?;
+        db.check_db()?;
+        if db.is_initialized()? {
+            db.check_genesis(&genesis.hash())?;
+        } else {
+            db.initialize_with_genesis(genesis)?;
+        }
+
+        let validator = BlockValidator::new(params.clone()).with_proof_of_work(true);
+        let core = NodeCore::open(db.clone(), validator, Path::new(&config.data_dir).join(MEMPOOL_FILE_NAME))?;
+        core.set_mempool_spillover(config.mempool_spill)?;
+        // A panic in the miner or a peer task must not lose the mempool
+        Arc::new(core.crash_flush()).install_panic_hook();
+        let chain = StandaloneChain::new(core)?;
+        log::info!("Standalone proof of work node at height {}", db.get_height()?);
+
+        let (relay, _) = broadcast::channel(PEER_QUEUE_LEN);
+        let node = Arc::new(Node {
+            chain: Mutex::new(chain),
+            params,
+            stats: Mutex::new(NetStats::new()),
+            scores: Mutex::new(PeerScores::new()),
+            addrman: Mutex::new(AddrManager::new()),
+            relay,
+            stop_mining: Arc::new(AtomicBool::new(false)),
+            nonce: connection_nonce(),
+        });
+        Ok(Self { node, config })
+    }
+
+    /// RPC context over the database and mempool of the node
+    ///
+    /// Submitted blocks go through the node like mined ones, so they update
+    /// its header index and are relayed to the peers.
+    pub fn rpc_context(&self) -> RpcContext {
+        let chain = self.node.chain.lock().unwrap();
+        let node = self.node.clone();
+        let submitter: BlockSubmitter = Arc::new(move |block: Block| {
+            let hash = block.hash();
+            match node.process_block(None, block) {
+                Ok(BlockAcceptance::Connected(update)) if update.connected.contains(&hash) => Ok(None),
+                Ok(BlockAcceptance::Connected(update)) => Ok(update.rejected
+                    .into_iter()
+                    .find(|(rejected, _)| *rejected == hash)
+                    .map(|(_, error)| error.to_string())),
+                Ok(BlockAcceptance::SideBranch | BlockAcceptance::Orphaned { .. }) => {
+                    Ok(Some("inconclusive".to_string()))
+                }
+                Ok(BlockAcceptance::Duplicate) => Ok(Some("duplicate".to_string())),
+                Err(StandaloneError::Storage(e)) => Err(e.to_string()),
+                Err(e) => Ok(Some(e.to_string())),
+            }
+        });
+        RpcContext::new(chain.db().clone(), self.node.params.clone())
+            .with_mempool(chain.mempool().clone())
+            .with_block_submitter(submitter)
+    }
+
+    /// Start the miner and the outbound connections, then accept peers until the listener fails
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(&self.config.p2p_addr).await?;
+        log::info!("Listening for peers on {}", listener.local_addr()?);
+
+        if let Some(script) = self.config.mine_to.clone() {
+            let node = self.node.clone();
+            let threads = self.config.mining_threads;
+            std::thread::spawn(move || mine(node, script, threads));
+        }
+        for peer in &self.config.peers {
+            tokio::spawn(connect_loop(self.node.clone(), peer.clone()));
+        }
+
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let peer = addr.to_string();
+            if !accepts_inbound(&self.config.only_net, addr) {
+                log::debug!("Refused connection from {} outside --onlynet", peer);
+                continue;
+            }
+            if self.node.scores.lock().unwrap().is_banned(ban_key(&peer), unix_now()) {
+                log::debug!("Refused connection from banned peer {}", peer);
+                continue;
+            }
+            let node = self.node.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_peer(&node, stream, &peer, true).await {
+                    log::info!("Peer {} disconnected: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Stop mining and persist the mempool so pending transactions survive a restart
+    pub fn shutdown(&self) -> anyhow::Result<()> {
+        self.node.stop_mining.store(true, Ordering::Relaxed);
+        self.node.chain.lock().unwrap().core().save_mempool()?;
+        Ok(())
+    }
+}
+
+/// Mine templates paying `script` forever, one round of at most `TEMPLATE_REFRESH` each
+fn mine(node: Arc<Node>, script: Vec<u8>, threads: usize) {
+    let mut miner = Miner::new([0; 32], threads);
+    miner.should_stop = node.stop_mining.clone();
+    log::info!("Mining to {} with {} threads", hex::encode(&script), threads);
+    loop {
+        // Reset before reading the tip: a block connected from now on stops this round
+        node.stop_mining.store(false, Ordering::Relaxed);
+        let template = match node.chain.lock().unwrap().block_template(&script) {
+            Ok(template) => template,
+            Err(e) => {
+                log::error!("Cannot build a block template: {}", e);
+                std::thread::sleep(TEMPLATE_REFRESH);
+                continue;
+            }
+        };
+        miner.algorithm = node.params.pow_algorithm(template.header.height);
+        match miner.mine_template(&template, TEMPLATE_REFRESH) {
+            Ok(result) => {
+                log::info!(
+                    "Mined block {} at height {} ({:.0} H/s)",
+                    result.block.block_hash(),
+                    result.block.header.height,
+                    result.hash_rate
+                );
+                if let Err(e) = node.process_block(None, result.block) {
+                    log::error!("Mined block refused: {}", e);
+                }
+            }
+            Err(MiningError::Stopped | MiningError::Timeout) => {}
+            Err(e) => log::error!("Mining failed: {}", e),
+        }
+    }
+}
+
+/// Keep an outbound connection to `peer`, reconnecting after `RECONNECT_DELAY`
+async fn connect_loop(node: Arc<Node>, peer: String) {
+    loop {
+        match TcpStream::connect(&peer).await {
+            Ok(stream) => {
+                if let Err(e) = handle_peer(&node, stream, &peer, false).await {
+                    log::info!("Peer {} disconnected: {}", peer, e);
+                }
+            }
+            Err(e) => log::debug!("Cannot connect to {}: {}", peer, e),
+        }
+        if node.scores.lock().unwrap().is_banned(ban_key(&peer), unix_now()) {
+            log::info!("Peer {} banned, not reconnecting", peer);
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Handshake with `peer`, then relay messages until the connection closes
+async fn handle_peer(node: &Arc<Node>, stream: TcpStream, peer: &str, inbound: bool) -> anyhow::Result<()> {
+    node.stats.lock().unwrap().connect(peer, inbound, unix_now());
+    let (mut reader, writer) = stream.into_split();
+    let (direct, queue) = mpsc::channel(PEER_QUEUE_LEN);
+    let relay = node.relay.subscribe();
+    let writer = tokio::spawn(write_loop(node.clone(), writer, peer.to_string(), queue, relay));
+
+    let result = async {
+        let remote_height = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(node, &mut reader, &direct, peer))
+            .await
+            .map_err(|_| anyhow::anyhow!("Handshake timed out"))??;
+        if remote_height > node.chain.lock().unwrap().db().get_height()? {
+            request_blocks(node, &direct).await?;
+        }
+        let addresses = node.addrman.lock().unwrap().relay_entries();
+        if !addresses.is_empty() {
+            direct.send((ADDR_COMMAND, encode_addr(&addresses)?)).await?;
+        }
+        read_loop(node, &mut reader, &direct, peer).await
+    }
+    .await;
+
+    writer.abort();
+    node.stats.lock().unwrap().disconnect(peer);
+    result
+}
+
+/// Exchange `version` and `verack`, returning the height announced by the peer
+async fn handshake(
+    node: &Node,
+    reader: &mut OwnedReadHalf,
+    direct: &mpsc::Sender<(&'static str, Vec<u8>)>,
+    peer: &str,
+) -> anyhow::Result<u64> {
+    let height = node.chain.lock().unwrap().db().get_height()?;
+    let local = VersionMessage::new(local_services(false, false), height, node.nonce, unix_now());
+    direct.send((VERSION_COMMAND, local.to_bytes())).await?;
+
+    let mut remote_height = None;
+    let mut verack = false;
+    while remote_height.is_none() || !verack {
+        let (command, payload) = read_message(node, reader, peer).await?;
+        match command.as_str() {
+            VERSION_COMMAND => {
+                receive_version(&node.stats, peer, &local, &payload)?;
+                remote_height = Some(VersionMessage::from_bytes(&payload)?.start_height);
+                direct.send((VERACK_COMMAND, Vec::new())).await?;
+            }
+            VERACK_COMMAND => verack = true,
+            _ => log::debug!("Ignoring {} from {} before the handshake", command, peer),
+        }
+    }
+    Ok(remote_height.unwrap_or_default())
+}
+
+/// Handle the relay messages of `peer`
+async fn read_loop(
+    node: &Arc<Node>,
+    reader: &mut OwnedReadHalf,
+    direct: &mpsc::Sender<(&'static str, Vec<u8>)>,
+    peer: &str,
+) -> anyhow::Result<()> {
+    loop {
+        let (command, payload) = read_message(node, reader, peer).await?;
+        if command == ADDR_COMMAND {
+            match receive_addr(&node.addrman, &payload, unix_now()) {
+                Ok(added) => log::debug!("{} new addresses from {}", added, peer),
+                Err(misbehavior) if node.penalize(peer, misbehavior) => {
+                    anyhow::bail!("Banned after a malformed addr message")
+                }
+                Err(_) => {}
+            }
+            continue;
+        }
+        let message = match RelayMessage::decode(&command, &payload) {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                log::debug!("Ignoring {} from {}", command, peer);
+                continue;
+            }
+            Err(misbehavior) => {
+                if node.penalize(peer, misbehavior) {
+                    anyhow::bail!("Banned after a malformed {} message", command);
+                }
+                continue;
+            }
+        };
+
+        match message {
+            RelayMessage::Block(block) => match tokio::task::block_in_place(|| node.process_block(Some(peer), block)) {
+                Ok(BlockAcceptance::Orphaned { .. }) => request_blocks(node, direct).await?,
+                Ok(_) => {}
+                Err(e) => refuse_block(node, peer, &e)?,
+            },
+            RelayMessage::Transaction(tx) => tokio::task::block_in_place(|| node.process_transaction(Some(peer), tx)),
+            RelayMessage::GetBlocks(locator) => {
+                let max_bytes = MAX_PAYLOAD_LEN as usize - BLOCKS_PAYLOAD_OVERHEAD;
+                let blocks =
+                    tokio::task::block_in_place(|| node.chain.lock().unwrap().blocks_after(&locator, max_bytes))?;
+                let answer = RelayMessage::Blocks(blocks);
+                direct.send((answer.command(), answer.payload())).await?;
+            }
+            RelayMessage::Blocks(blocks) => {
+                // Ask for more only while the batch moves our tip, so a peer on a weaker chain cannot loop us
+                let mut connected = false;
+                for block in blocks {
+                    match tokio::task::block_in_place(|| node.process_block(Some(peer), block)) {
+                        Ok(acceptance) => connected |= matches!(acceptance, BlockAcceptance::Connected(_)),
+                        Err(e) => {
+                            refuse_block(node, peer, &e)?;
+                            break;
+                        }
+                    }
+                }
+                if connected {
+                    request_blocks(node, direct).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Log a refused block and penalize `peer` if it broke consensus rules
+fn refuse_block(node: &Node, peer: &str, error: &StandaloneError) -> anyhow::Result<()> {
+    log::info!("Block from {} refused: {}", peer, error);
+    match Misbehavior::from_standalone_error(error) {
+        Some(misbehavior) if node.penalize(peer, misbehavior) => anyhow::bail!("Banned after an invalid block"),
+        _ => Ok(()),
+    }
+}
+
+/// Ask the peer for the blocks after our block locator
+async fn request_blocks(node: &Node, direct: &mpsc::Sender<(&'static str, Vec<u8>)>) -> anyhow::Result<()> {
+    let request = RelayMessage::GetBlocks(node.chain.lock().unwrap().locator());
+    direct.send((request.command(), request.payload())).await?;
+    Ok(())
+}
+
+/// Send the direct answers to `peer` and the messages relayed from other origins
+async fn write_loop(
+    node: Arc<Node>,
+    mut writer: OwnedWriteHalf,
+    peer: String,
+    mut direct: mpsc::Receiver<(&'static str, Vec<u8>)>,
+    mut relay: broadcast::Receiver<(Option<String>, RelayMessage)>,
+) -> anyhow::Result<()> {
+    loop {
+        let (command, payload) = tokio::select! {
+            message = direct.recv() => match message {
+                Some(message) => message,
+                None => return Ok(()),
+            },
+            message = relay.recv() => match message {
+                Ok((origin, _)) if origin.as_deref() == Some(peer.as_str()) => continue,
+                Ok((_, message)) => (message.command(), message.payload()),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::debug!("Peer {} missed {} relay messages", peer, missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        };
+        match send_message(&node.stats, &peer, node.params.magic, command, &payload, unix_now()) {
+            Ok(message) => writer.write_all(&message).await?,
+            Err(e) => log::warn!("Cannot send {} to {}: {}", command, peer, e),
+        }
+    }
+}
+
+/// Read and decode the next message of `peer`
+async fn read_message(node: &Node, reader: &mut OwnedReadHalf, peer: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let frame = read_frame(reader, node.params.magic).await?;
+    Ok(receive_message(&node.stats, peer, &frame, node.params.magic, unix_now())?)
+}
+
+/// Whether `--onlynet` allows an inbound connection from `addr`
+///
+/// Tor hands the connections to our onion service over from the local
+/// proxy, so loopback connections are accepted when onion is allowed.
+fn accepts_inbound(only_net: &[AddrNetwork], addr: SocketAddr) -> bool {
+    is_allowed(only_net, &PeerAddress::from(addr))
+        || (addr.ip().is_loopback() && only_net.contains(&AddrNetwork::Onion))
+}
+
+/// Peers are scored and banned by host, so reconnecting from another port does not reset the score
+fn ban_key(peer: &str) -> &str {
+    peer.rsplit_once(':').map_or(peer, |(host, _)| host)
+}
+
+/// Nonce of our version messages, different for every run of the node
+fn connection_nonce() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_nanos() as u64) ^ (u64::from(std::process::id()) << 32)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
@@ -1,33 +1,86 @@
 //! Tendermint ABCI Application implementation for Sedly
 
 use sedly_core::{
-    Block, Transaction, BlockchainDB, ChainMetadata, DifficultyAdjuster,
-    Miner, INITIAL_BLOCK_REWARD, HALVING_INTERVAL
+    Block, Transaction, BlockchainDB, ChainMetadata, ChainParams, DifficultyAdjuster,
+    Miner, BlockSpends, BlockValidator, CrashFlush, Mempool, MempoolError, NodeCore,
+    GovernanceAction, GenesisAppState, OutPoint, SupplyAuditError, SupplyAuditor,
+    HeaderStatus, decode_transaction, DecodeError, PipelineError,
+    RejectedItem, Rejection, RejectionLog, AlertSet,
 };
+use sedly_core::interpreter::MAX_BLOCK_SCRIPT_COST;
+use sedly_core::mempool::MEMPOOL_FILE_NAME;
 use tendermint_abci::{
     Application, RequestBeginBlock, RequestCheckTx, RequestCommit, RequestDeliverTx,
-    RequestEndBlock, RequestInfo, RequestInitChain, RequestQuery,
+    RequestEndBlock, RequestInfo, RequestInitChain, RequestPrepareProposal, RequestQuery,
     ResponseBeginBlock, ResponseCheckTx, ResponseCommit, ResponseDeliverTx,
-    ResponseEndBlock, ResponseInfo, ResponseInitChain, ResponseQuery,
+    ResponseEndBlock, ResponseInfo, ResponseInitChain, ResponsePrepareProposal, ResponseQuery,
     ConsensusParams, ValidatorUpdate,
 };
 use tendermint::abci::{Code, Event, EventAttribute};
+use tendermint::abci::types::{Misbehavior, MisbehaviorKind};
+use tendermint::PublicKey;
+use crate::governance::{GovernanceEvent, GovernanceParams, GovernedParams, ProposalRecord};
+use crate::handshake::{check_consensus_state, check_next_block, recover_tip, HandshakeError};
+use crate::notify::ZmqNotifier;
+use crate::webhook::WebhookNotifier;
+use crate::production::{assemble_proposal, ProductionConfig};
+use crate::pruning::RetainConfig;
+use crate::slashing::{process_evidence, Evidence, SlashingParams};
+use crate::state::{validator_id, ConsensusState, EvidenceKind, EvidenceRecord, StateManager, TIP_TRACKING_VERSION};
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File in the data directory holding the validator set and evidence records
+const CONSENSUS_STATE_FILE: &str = "consensus_state.dat";
+
+/// File in the data directory where the rejection log is dumped when the node halts
+const REJECTIONS_FILE: &str = "rejections.json";
+
+/// Attempts to write a committed block before the node halts
+const COMMIT_ATTEMPTS: u32 = 3;
+
+/// Pause before retrying a failed block write, doubled on every attempt
+const COMMIT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
 /// Sedly ABCI Application
 pub struct SedlyApp {
-    /// Blockchain database
-    db: Arc<BlockchainDB>,
+    /// Database, validation pipeline, header index and mempool, shared with the standalone node
+    core: NodeCore,
     /// Current block being built
     current_block: Arc<Mutex<Option<BlockBuilder>>>,
-    /// Transaction pool for pending transactions
-    mempool: Arc<Mutex<HashMap<[u8; 32], Transaction>>>,
-    /// Difficulty adjuster
-    difficulty_adjuster: DifficultyAdjuster,
+    /// How many blocks Tendermint must keep in its block store
+    retain: RetainConfig,
+    /// Empty-block policy and time budget of the blocks this validator proposes
+    production: ProductionConfig,
+    /// Periodic money supply check (debug mode, disabled by default)
+    supply_auditor: Option<Mutex<SupplyAuditor>>,
+    /// Validator set and processed evidence
+    state: StateManager,
+    /// File where the validator state is persisted
+    state_path: PathBuf,
+    /// Penalties for misbehaving validators
+    slashing: SlashingParams,
+    /// Validator set changes to report at the end of the current block
+    validator_updates: Arc<Mutex<Vec<ValidatorUpdate>>>,
+    /// Difficulty adjuster (rebuilt when governance changes the retarget algorithm)
+    difficulty_adjuster: Arc<Mutex<DifficultyAdjuster>>,
+    /// Rules of the governance process
+    governance: GovernanceParams,
+    /// Consensus parameters of the network
+    params: ChainParams,
     /// Current chain state
     chain_state: Arc<Mutex<ChainState>>,
+    /// ZeroMQ publisher of connected blocks and accepted transactions
+    notifier: Option<ZmqNotifier>,
+    /// HTTP webhook targets of blocks, transactions, deep reorgs and errors
+    webhooks: Option<Arc<WebhookNotifier>>,
+    /// Consensus-rule rejections kept for debugging forks (disabled by default)
+    rejections: Option<Arc<Mutex<RejectionLog>>>,
+    /// Signed network alerts received from peers or RPC
+    alerts: Arc<Mutex<AlertSet>>,
 }
 
 /// Block being constructed during consensus
@@ -43,6 +96,16 @@ struct BlockBuilder {
     timestamp: u64,
     /// Current difficulty bits
     bits: u32,
+    /// Serialized size of the block so far, header included
+    size: u64,
+    /// Script execution cost of the included transactions
+    script_cost: u64,
+    /// Governed block size limit in force for this block
+    max_size: u64,
+    /// Governance actions carried by the included transactions
+    governance_actions: Vec<([u8; 32], u64, GovernanceAction)>,
+    /// Outputs spent and created by the included transactions
+    spends: BlockSpends,
 }
 
 /// Current state of the blockchain
@@ -65,59 +128,120 @@ struct TxCheckResult {
     valid: bool,
     /// Error message if invalid
     error: Option<String>,
-    /// Gas used (for future fee calculation)
+    /// Serialized size in bytes
+    size: u64,
+    /// Gas used: the script execution cost of the inputs
     gas_used: u64,
 }
 
 impl SedlyApp {
-    /// Create new ABCI application
+    /// Create new ABCI application with mainnet parameters
     pub fn new(db_path: &str) -> Result<Self, ConsensusError> {
+        Self::with_params(db_path, ChainParams::mainnet())
+    }
+
+    /// Create new ABCI application for the given network parameters
+    pub fn with_params(db_path: &str, params: ChainParams) -> Result<Self, ConsensusError> {
+        Self::with_genesis(db_path, params, &Block::genesis())
+    }
+
+    /// Create new ABCI application for a network with a custom genesis block
+    pub fn with_genesis(db_path: &str, params: ChainParams, genesis: &Block) -> Result<Self, ConsensusError> {
         let db = Arc::new(
             BlockchainDB::open(db_path)
                 .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?
         );
 
-        // Initialize with genesis if empty
-        let metadata = db.get_metadata()
+        // Refuse a database created for another network
+        db.check_network_magic(params.magic)
             .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
 
-        let chain_state = if metadata.height == 0 {
-            // Initialize with genesis
-            let genesis = Block::genesis();
-            db.initialize_with_genesis(&genesis)
-                .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+        // Refuse corrupted metadata instead of starting from a wrong tip
+        db.check_db()
+            .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
 
-            ChainState {
-                height: 0,
-                best_block_hash: genesis.hash(),
-                total_transactions: 1, // Genesis transaction
-                current_bits: DifficultyAdjuster::genesis_difficulty(),
-            }
-        } else {
-            ChainState {
-                height: metadata.height,
-                best_block_hash: metadata.best_block_hash,
-                total_transactions: 0, // Will be calculated if needed
-                current_bits: DifficultyAdjuster::genesis_difficulty(), // Will be updated
-            }
+        // Recover the committed tip (initializing genesis on an empty database)
+        let tip = recover_tip(&db, genesis)?;
+        log::info!(
+            "Recovered application state at height {} ({})",
+            tip.height, hex::encode(tip.best_block_hash)
+        );
+
+        let chain_state = ChainState {
+            height: tip.height,
+            best_block_hash: tip.best_block_hash,
+            total_transactions: if tip.height == 0 { 1 } else { 0 }, // Will be calculated if needed
+            current_bits: tip.header.bits,
         };
 
+        // Load the header index and the pending transactions saved at the last shutdown
+        let mempool_path = Path::new(db_path).join(MEMPOOL_FILE_NAME);
+        let core = NodeCore::open(db, BlockValidator::new(params.clone()), mempool_path)
+            .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+
+        let state_path = Path::new(db_path).join(CONSENSUS_STATE_FILE);
+        let mut initial_state = ConsensusState {
+            height: tip.height,
+            best_block_hash: tip.best_block_hash,
+            ..ConsensusState::default()
+        };
+        initial_state.governance.params = GovernedParams::from_chain_params(&params);
+        let state = StateManager::new(initial_state);
+        if state_path.exists() {
+            let data = std::fs::read(&state_path)
+                .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+            let version = state.import_state(&data)
+                .map_err(|e| ConsensusError::DatabaseError(format!("{}: {}", state_path.display(), e)))?;
+            if version < TIP_TRACKING_VERSION {
+                log::info!("Consensus state predates tip tracking, assuming it matches height {}", tip.height);
+                state.update_state(|state| {
+                    state.height = tip.height;
+                    state.best_block_hash = tip.best_block_hash;
+                    Ok(())
+                }).map_err(|e| ConsensusError::ConsensusError(e.to_string()))?;
+            }
+            // Refuse a state saved for another tip instead of losing the changes of a block
+            let recovered = state.get_state();
+            check_consensus_state(recovered.height, &recovered.best_block_hash, &tip)?;
+        }
+
+        let governed = state.get_state().governance.params;
+        core.mempool().lock().unwrap().set_min_fee(governed.min_tx_fee);
+
         Ok(Self {
-            db,
+            core,
             current_block: Arc::new(Mutex::new(None)),
-            mempool: Arc::new(Mutex::new(HashMap::new())),
-            difficulty_adjuster: DifficultyAdjuster::new(),
+            retain: RetainConfig::default(),
+            production: ProductionConfig::default(),
+            supply_auditor: None,
+            state,
+            state_path,
+            slashing: SlashingParams::default(),
+            validator_updates: Arc::new(Mutex::new(Vec::new())),
+            difficulty_adjuster: Arc::new(Mutex::new(DifficultyAdjuster::from_params(&governed.chain_params(&params)))),
+            governance: GovernanceParams::default(),
+            params,
             chain_state: Arc::new(Mutex::new(chain_state)),
+            notifier: None,
+            webhooks: None,
+            rejections: None,
+            alerts: Arc::new(Mutex::new(AlertSet::new())),
         })
     }
 
-    /// Validate transaction against current state
-    fn check_transaction(&self, tx: &Transaction) -> TxCheckResult {
+    /// Validate a transaction for the next block, after the ones recorded in `spends`
+    ///
+    /// Applies the block pipeline's input rules, so a transaction accepted here
+    /// cannot make the committed block fail: inputs spent earlier in the block,
+    /// outputs exceeding inputs, immature coinbases and scripts that do not
+    /// unlock their inputs are all rejected.
+    fn check_transaction(&self, tx: &Transaction, spends: &BlockSpends) -> TxCheckResult {
         // Basic validation
         if !tx.is_valid() {
             return TxCheckResult {
                 valid: false,
                 error: Some("Invalid transaction structure".to_string()),
+                size: 0,
                 gas_used: 0,
             };
         }
@@ -127,74 +251,491 @@ impl SedlyApp {
             return TxCheckResult {
                 valid: false,
                 error: Some("Coinbase transactions not allowed in mempool".to_string()),
+                size: 0,
                 gas_used: 0,
             };
         }
 
-        // Verify inputs exist and are spendable
+        // The version must be allowed in the next block
         let chain_state = self.chain_state.lock().unwrap();
-        for input in &tx.inputs {
-            match self.db.is_utxo_spendable(&input.previous_output, chain_state.height) {
-                Ok(true) => continue,
-                Ok(false) => {
-                    return TxCheckResult {
-                        valid: false,
-                        error: Some("UTXO not found or not spendable".to_string()),
-                        gas_used: 0,
-                    };
-                }
+        let height = chain_state.height + 1;
+        let txid = tx.hash();
+        let validator = self.core.validator();
+        let checked = validator.check_format(tx, txid, height)
+            .and_then(|()| validator.check_block_transaction(tx, txid, height, self.core.db(), spends));
+        drop(chain_state);
+
+        // Gas is the script execution cost, bounded per transaction
+        let gas_used = match checked {
+            Ok(checked) => checked.script_cost,
+            Err(e) => {
+                return TxCheckResult {
+                    valid: false,
+                    error: Some(e.to_string()),
+                    size: 0,
+                    gas_used: 0,
+                };
+            }
+        };
+
+        match tx.size() {
+            Ok(size) => TxCheckResult {
+                valid: true,
+                error: None,
+                size: size as u64,
+                gas_used,
+            },
+            Err(e) => TxCheckResult {
+                valid: false,
+                error: Some(e.to_string()),
+                size: 0,
+                gas_used: 0,
+            },
+        }
+    }
+
+    /// Set the Tendermint block retention policy
+    pub fn with_retain_config(mut self, retain: RetainConfig) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Set the block production policy of this validator
+    pub fn with_production_config(mut self, production: ProductionConfig) -> Self {
+        self.production = production;
+        self
+    }
+
+    /// Publish connected blocks and accepted transactions on ZeroMQ sockets
+    pub fn with_notifier(mut self, notifier: ZmqNotifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Send blocks, transactions, deep reorgs and node errors to HTTP webhooks
+    ///
+    /// Deep reorgs are reported through a hook on the pipeline's reorg alarm.
+    pub fn with_webhooks(mut self, webhooks: WebhookNotifier) -> Self {
+        let webhooks = Arc::new(webhooks);
+        let hook = Arc::clone(&webhooks);
+        let pipeline = self.core.pipeline_mut();
+        let alarm = pipeline.reorg_alarm().clone().with_hook(move |record| hook.notify_reorg(record));
+        pipeline.set_reorg_alarm(alarm);
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Keep the last `capacity` consensus-rule rejections (0 disables)
+    ///
+    /// Blocks rejected by the pipeline, transactions rejected by the mempool
+    /// and transactions dropped from a decided block are recorded with the
+    /// violated rule and a hex dump. The log is served by the `debug/rejections`
+    /// query and written to the data directory if the node halts.
+    pub fn with_rejection_log(mut self, capacity: usize) -> Self {
+        let rejections = (capacity > 0).then(|| Arc::new(Mutex::new(RejectionLog::new(capacity))));
+        self.core.pipeline_mut().set_rejection_log(rejections.clone());
+        self.core.mempool().lock().unwrap().set_rejection_log(rejections.clone());
+        self.rejections = rejections;
+        self
+    }
+
+    /// Rejection log, to share with the RPC server
+    pub fn rejection_log(&self) -> Option<&Arc<Mutex<RejectionLog>>> {
+        self.rejections.as_ref()
+    }
+
+    /// Network alerts, to share with the P2P layer and the RPC server
+    pub fn alerts(&self) -> &Arc<Mutex<AlertSet>> {
+        &self.alerts
+    }
+
+    /// JSON view of the active network alerts, highest priority first
+    fn alerts_json(&self) -> Vec<serde_json::Value> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let alerts = self.alerts.lock().unwrap();
+        let active = alerts.active(now)
+            .into_iter()
+            .map(|signed| serde_json::json!({
+                "id": signed.alert.id,
+                "expiration": signed.alert.expiration,
+                "priority": signed.alert.priority,
+                "message": signed.alert.message,
+            }))
+            .collect();
+        active
+    }
+
+    /// JSON view of the rejection log, most recent first
+    fn rejections_json(&self) -> Vec<serde_json::Value> {
+        self.rejections.as_ref().map_or_else(Vec::new, |rejections| {
+            let log = rejections.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+            log.recent(log.len()).into_iter().map(Rejection::to_json).collect()
+        })
+    }
+
+    /// Write the rejection log next to the database before the node halts
+    fn dump_rejections(&self) {
+        if self.rejections.is_none() {
+            return;
+        }
+        let Some(dir) = self.core.mempool_path().parent() else {
+            return;
+        };
+        let path = dir.join(REJECTIONS_FILE);
+        let result = serde_json::to_vec_pretty(&self.rejections_json())
+            .map_err(std::io::Error::from)
+            .and_then(|data| std::fs::write(&path, data));
+        match result {
+            Ok(()) => log::error!("Rejection log written to {}", path.display()),
+            Err(e) => log::error!("Failed to write the rejection log to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Report a node error to the webhooks (the caller logs it)
+    fn report_error(&self, context: &str, message: &str) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify_error(context, message);
+        }
+    }
+
+    /// Keep up to `max_size` bytes of low-feerate transactions evicted from a full mempool on disk (0 disables)
+    ///
+    /// Spilled transactions go back to memory when committed blocks free
+    /// room, so a spam wave bounds RAM without dropping paying users.
+    pub fn with_mempool_spillover(self, max_size: usize) -> Result<Self, ConsensusError> {
+        self.core.set_mempool_spillover(max_size)
+            .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+        Ok(self)
+    }
+
+    /// Verify the money supply invariant every `interval` blocks (0 disables)
+    pub fn with_supply_audit(mut self, interval: u64) -> Self {
+        self.supply_auditor = (interval > 0).then(|| Mutex::new(SupplyAuditor::new(interval)));
+        self
+    }
+
+    /// Run the supply audit if one is due after committing `height`
+    ///
+    /// A violated invariant halts the node: continuing would build on an
+    /// inflated (or deflated) UTXO set.
+    fn audit_supply(&self, height: u64) {
+        let Some(auditor) = &self.supply_auditor else {
+            return;
+        };
+        let mut auditor = auditor.lock().unwrap();
+        if !auditor.is_due(height) {
+            return;
+        }
+
+        match auditor.audit(self.core.db()) {
+            Ok(report) => log::info!(
+                "Supply audit passed at height {}: {} in {} outputs (issued {}, burned {})",
+                report.height, report.utxo_total, report.txouts, report.issued, report.burned
+            ),
+            Err(SupplyAuditError::Violation(report)) => {
+                log::error!("Supply audit FAILED: {:#?}", report);
+                let error = SupplyAuditError::Violation(report);
+                self.report_error("supply_audit", &error.to_string());
+                panic!("{}", error);
+            }
+            Err(e) => {
+                log::error!("Supply audit at height {} could not run: {}", height, e);
+                self.report_error("supply_audit", &e.to_string());
+            }
+        }
+    }
+
+    /// Set the penalties applied to misbehaving validators
+    pub fn with_slashing_params(mut self, slashing: SlashingParams) -> Self {
+        self.slashing = slashing;
+        self
+    }
+
+    /// Check the `app_state` of the Tendermint genesis against the stored genesis block
+    ///
+    /// An empty app state is accepted for networks using the built-in genesis.
+    fn check_genesis_app_state(&self, app_state: &[u8]) -> Result<(), ConsensusError> {
+        if app_state.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let app_state: serde_json::Value = serde_json::from_slice(app_state)
+            .map_err(|e| ConsensusError::ConsensusError(format!("Invalid genesis app_state: {}", e)))?;
+        if app_state.is_null() || app_state.as_object().is_some_and(|fields| fields.is_empty()) {
+            return Ok(());
+        }
+        let app_state: GenesisAppState = serde_json::from_value(app_state)
+            .map_err(|e| ConsensusError::ConsensusError(format!("Invalid genesis app_state: {}", e)))?;
+        let block = app_state.block()
+            .map_err(|e| ConsensusError::ConsensusError(e.to_string()))?;
+
+        let genesis_hash = self.core.db().get_metadata()
+            .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?
+            .genesis_hash;
+        if block.hash() != genesis_hash {
+            return Err(ConsensusError::ConsensusError(format!(
+                "Tendermint genesis declares block {}, but the database was initialized with {}; start the node with the matching --genesis-file",
+                app_state.genesis_hash, hex::encode(genesis_hash)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Set the rules of the governance process
+    pub fn with_governance_params(mut self, governance: GovernanceParams) -> Self {
+        self.governance = governance;
+        self
+    }
+
+    /// Current values of the governed parameters
+    pub fn governed_params(&self) -> GovernedParams {
+        self.state.get_state().governance.params
+    }
+
+    /// Evidence processed so far, oldest first
+    pub fn evidence(&self) -> Vec<EvidenceRecord> {
+        self.state.get_state().evidence
+    }
+
+    /// Chain database, shared with other services of the node
+    pub fn db(&self) -> Arc<BlockchainDB> {
+        Arc::clone(self.core.db())
+    }
+
+    /// Persist the validator set, evidence records and governance
+    ///
+    /// The state goes to a temporary file, synced to disk and renamed over the
+    /// previous one, so a crash while saving leaves the last complete state.
+    fn save_state(&self) -> Result<(), ConsensusError> {
+        let data = self.state.export_state()
+            .map_err(|e| ConsensusError::ConsensusError(e.to_string()))?;
+        let io = |e: std::io::Error| ConsensusError::DatabaseError(e.to_string());
+        let tmp_path = self.state_path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path).map_err(io)?;
+        file.write_all(&data).map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp_path, &self.state_path).map_err(io)
+    }
+
+    /// Slash validators reported for misbehavior, returning the slashing events
+    fn process_misbehavior(&self, misbehavior: &[Misbehavior], height: u64) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        for item in misbehavior {
+            let kind = match item.kind {
+                MisbehaviorKind::DuplicateVote => EvidenceKind::DuplicateVote,
+                MisbehaviorKind::LightClientAttack => EvidenceKind::LightClientAttack,
+                MisbehaviorKind::Unknown => continue,
+            };
+            let evidence = Evidence {
+                validator_address: item.validator.address,
+                kind,
+                height: item.height.value(),
+            };
+
+            let record = match process_evidence(&self.state, &self.slashing, &evidence, height) {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
                 Err(e) => {
-                    return TxCheckResult {
-                        valid: false,
-                        error: Some(format!("Database error: {}", e)),
-                        gas_used: 0,
-                    };
+                    log::error!("Failed to process evidence: {}", e);
+                    continue;
+                }
+            };
+
+            // Report the reduced power (0 removes the validator from the set)
+            let public_key = self.state.get_state().validators
+                .get(&record.validator_id)
+                .and_then(|info| PublicKey::from_raw_ed25519(&info.public_key));
+            match (public_key, u32::try_from(record.voting_power())) {
+                (Some(pub_key), Ok(power)) => {
+                    self.validator_updates.lock().unwrap().push(ValidatorUpdate { pub_key, power: power.into() });
                 }
+                (Some(_), Err(_)) => {
+                    let message =
+                        format!("Voting power {} of {} out of range", record.voting_power(), record.validator_id);
+                    log::error!("{}", message);
+                    self.report_error("slash", &message);
+                }
+                (None, _) => {}
             }
+
+            events.push(Event {
+                type_str: "slash".to_string(),
+                attributes: vec![
+                    EventAttribute {
+                        key: "validator".to_string(),
+                        value: record.validator_id.clone(),
+                        index: true,
+                    },
+                    EventAttribute {
+                        key: "reason".to_string(),
+                        value: format!("{:?}", record.kind),
+                        index: true,
+                    },
+                    EventAttribute {
+                        key: "evidence_height".to_string(),
+                        value: record.evidence_height.to_string(),
+                        index: false,
+                    },
+                    EventAttribute {
+                        key: "power".to_string(),
+                        value: record.power_after.to_string(),
+                        index: false,
+                    },
+                    EventAttribute {
+                        key: "jailed".to_string(),
+                        value: record.jailed.to_string(),
+                        index: false,
+                    },
+                ],
+            });
+        }
+
+        // Persisted with the rest of the state in Commit: a block Tendermint
+        // replays after a crash finds the evidence not yet applied
+        events
+    }
+
+    /// Weight backing a vote: native value of its outputs that are still unspent
+    fn vote_weight(&self, txid: &[u8; 32]) -> u64 {
+        let Ok(Some((tx, _))) = self.core.db().get_transaction(txid) else {
+            return 0;
+        };
+        tx.outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.is_native_asset() && GovernanceAction::from_script(&output.script_pubkey).is_none())
+            .filter(|(vout, _)| matches!(self.core.db().get_utxo(&OutPoint::new(*txid, *vout as u32)), Ok(Some(_))))
+            .fold(0u64, |weight, (_, output)| weight.saturating_add(output.value.to_sat()))
+    }
+
+    /// Close finished votes and activate parameter changes due at `height`
+    fn process_governance(&self, height: u64) -> Vec<Event> {
+        let mut governance_events = Vec::new();
+        let result = self.state.update_state(|state| {
+            governance_events = state.governance.process_height(&self.governance, height, |txid| self.vote_weight(txid));
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::error!("Failed to process governance at height {}: {}", height, e);
         }
 
-        // TODO: Verify signatures
-        // TODO: Calculate fees and gas
+        let activated = governance_events.iter().any(|event| matches!(event, GovernanceEvent::Activated { .. }));
+        if activated {
+            let governed = self.governed_params();
+            log::info!("Governance parameters changed at height {}: {:?}", height, governed);
+            *self.difficulty_adjuster.lock().unwrap() = DifficultyAdjuster::from_params(&governed.chain_params(&self.params));
+            self.core.mempool().lock().unwrap().set_min_fee(governed.min_tx_fee);
+        }
+
+        governance_events.iter().map(governance_event).collect()
+    }
+
+    /// Add a checked transaction to the mempool, returning its fee
+    fn add_to_mempool(&self, tx: Transaction) -> Result<u64, MempoolError> {
+        let tip_height = self.chain_state.lock().unwrap().height;
 
-        TxCheckResult {
-            valid: true,
-            error: None,
-            gas_used: tx.size() as u64, // Simple gas model
+        match self.core.accept_transaction(tx, tip_height) {
+            // Tendermint rechecks pending transactions after every block
+            Err(MempoolError::AlreadyKnown { txid }) => {
+                Ok(self.core.mempool().lock().unwrap().get(&txid).map(|entry| entry.fee).unwrap_or_default())
+            }
+            result => result,
         }
     }
 
+    /// Mempool, shared with other services of the node
+    pub fn mempool(&self) -> Arc<Mutex<Mempool>> {
+        Arc::clone(self.core.mempool())
+    }
+
+    /// Number of transactions in the mempool
+    pub fn mempool_size(&self) -> usize {
+        self.core.mempool().lock().unwrap().len()
+    }
+
+    /// Persist the mempool so pending transactions survive a restart
+    pub fn save_mempool(&self) -> Result<usize, ConsensusError> {
+        self.core.save_mempool().map_err(|e| ConsensusError::DatabaseError(e.to_string()))
+    }
+
+    /// Emergency flush of the database and mempool, for a panic hook
+    pub fn crash_flush(&self) -> CrashFlush {
+        self.core.crash_flush()
+    }
+
     /// Calculate current block reward
     fn calculate_block_reward(&self, height: u64) -> u64 {
-        let halvings = height / HALVING_INTERVAL;
-        if halvings >= 64 {
-            0 // No more rewards after 64 halvings
-        } else {
-            INITIAL_BLOCK_REWARD >> halvings
-        }
+        self.params.subsidy(height)
     }
 
     /// Create coinbase transaction for block
     fn create_coinbase(&self, height: u64, beneficiary: &[u8]) -> Transaction {
         let reward = self.calculate_block_reward(height);
-        Transaction::coinbase(beneficiary, height, reward)
+        let mut coinbase = Transaction::coinbase(beneficiary, height, reward);
+        if let Some(treasury) = &self.params.treasury {
+            treasury.apply_to_coinbase(&mut coinbase, reward);
+        }
+        coinbase
     }
 
-    /// Update difficulty if needed
-    fn update_difficulty(&self, height: u64) -> u32 {
-        if height % sedly_core::DIFFICULTY_ADJUSTMENT_INTERVAL == 0 && height > 0 {
-            // Get recent blocks for difficulty calculation
-            let start_height = height.saturating_sub(sedly_core::DIFFICULTY_ADJUSTMENT_INTERVAL);
-            let mut recent_blocks = Vec::new();
-
-            for h in start_height..height {
-                if let Ok(Some(block)) = self.db.get_block_by_height(h) {
-                    recent_blocks.push(block);
+    /// Validate and connect a block decided by consensus through the staged pipeline
+    ///
+    /// Storage failures are retried with a growing pause. A write that failed after
+    /// reaching the disk leaves the block as the tip, which counts as connected:
+    /// processing it again would reject it for not extending the tip.
+    fn connect_committed_block(&self, block: &Block, max_size: u64) -> Result<(), PipelineError> {
+        let mut pipeline = self.core.pipeline().lock().unwrap();
+        pipeline.validator_mut().set_max_block_size(max_size as usize);
+
+        let mut attempt = 1;
+        loop {
+            match pipeline.process(block, self.core.db()) {
+                Ok(processed) => {
+                    log::debug!(
+                        "Block {} connected in {}",
+                        processed.height,
+                        processed.timings.iter()
+                            .map(|(stage, elapsed)| format!("{} {}us", stage, elapsed.as_micros()))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    return Ok(());
+                }
+                Err(e @ PipelineError::Storage { .. }) => {
+                    if self.core.db().get_best_block_hash().ok() == Some(block.hash()) {
+                        log::warn!("Block {} reached the disk despite the write error: {}", block.header.height, e);
+                        return Ok(());
+                    }
+                    if attempt == COMMIT_ATTEMPTS {
+                        return Err(e);
+                    }
+                    log::warn!(
+                        "Writing block {} failed (attempt {}/{}): {}",
+                        block.header.height, attempt, COMMIT_ATTEMPTS, e
+                    );
+                    std::thread::sleep(COMMIT_RETRY_DELAY * 2u32.pow(attempt - 1));
+                    attempt += 1;
                 }
+                Err(e) => return Err(e),
             }
+        }
+    }
 
-            if recent_blocks.len() == sedly_core::DIFFICULTY_ADJUSTMENT_INTERVAL as usize {
+    /// Update difficulty if needed
+    fn update_difficulty(&self, height: u64) -> u32 {
+        let interval = self.params.difficulty_adjustment_interval;
+        if height % interval == 0 && height > 0 {
+            let difficulty_adjuster = self.difficulty_adjuster.lock().unwrap();
+            // Get the retarget window (may start in the previous epoch)
+            let start_height = match difficulty_adjuster.window_start_height(height) {
+                Some(start_height) => start_height,
+                None => return self.chain_state.lock().unwrap().current_bits,
+            };
+            let timestamps = self.core.headers().lock().unwrap().timestamps(start_height, height);
+
+            if let Some(timestamps) = timestamps {
                 let current_state = self.chain_state.lock().unwrap();
-                match self.difficulty_adjuster.calculate_next_difficulty(&recent_blocks, current_state.current_bits) {
+                match difficulty_adjuster.calculate_next_difficulty_from_timestamps(&timestamps, current_state.current_bits) {
                     Ok(adjustment) => {
                         log::info!("Difficulty adjustment: {}", adjustment.format_adjustment());
                         return adjustment.new_bits;
@@ -216,6 +757,12 @@ impl Application for SedlyApp {
     fn info(&self, _request: RequestInfo) -> ResponseInfo {
         let chain_state = self.chain_state.lock().unwrap();
 
+        // Tendermint replays any block above this height before resuming consensus
+        log::info!(
+            "Handshake: application at height {} with app hash {}",
+            chain_state.height, hex::encode(chain_state.best_block_hash)
+        );
+
         ResponseInfo {
             data: "Sedly Blockchain".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
@@ -232,6 +779,24 @@ impl Application for SedlyApp {
         // Chain should already be initialized in constructor
         let chain_state = self.chain_state.lock().unwrap();
 
+        // The genesis file must describe the genesis block this node was started with
+        if let Err(e) = self.check_genesis_app_state(&request.app_state_bytes) {
+            log::error!("Refusing genesis: {}", e);
+            panic!("genesis mismatch: {}", e);
+        }
+
+        // Track the genesis validators so evidence can be mapped back to them
+        for update in &request.validators {
+            let public_key = update.pub_key.to_bytes();
+            let power = update.power.value() as i64;
+            if let Err(e) = self.state.update_validator(validator_id(&public_key), public_key, power, power > 0) {
+                log::error!("Failed to register genesis validator: {}", e);
+            }
+        }
+        if let Err(e) = self.save_state() {
+            log::error!("Failed to persist validator state: {}", e);
+        }
+
         ResponseInitChain {
             consensus_params: request.consensus_params,
             validators: vec![], // No validators for PoW
@@ -241,9 +806,32 @@ impl Application for SedlyApp {
 
     /// Check transaction validity
     fn check_tx(&self, request: RequestCheckTx) -> ResponseCheckTx {
-        match bincode::deserialize::<Transaction>(&request.tx) {
+        match decode_transaction(&request.tx) {
             Ok(tx) => {
-                let result = self.check_transaction(&tx);
+                let mut result = self.check_transaction(&tx, &BlockSpends::new());
+                if result.valid {
+                    let notified = (self.notifier.is_some() || self.webhooks.is_some()).then(|| tx.clone());
+                    match self.add_to_mempool(tx) {
+                        Ok(_) => {
+                            if let Some(tx) = notified {
+                                if let Some(notifier) = &self.notifier {
+                                    notifier.notify_transaction(&tx);
+                                }
+                                if let Some(webhooks) = &self.webhooks {
+                                    webhooks.notify_transaction(&tx);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            result = TxCheckResult {
+                                valid: false,
+                                error: Some(e.to_string()),
+                                size: 0,
+                                gas_used: 0,
+                            };
+                        }
+                    }
+                }
 
                 if result.valid {
                     ResponseCheckTx {
@@ -277,7 +865,7 @@ impl Application for SedlyApp {
             }
             Err(e) => {
                 ResponseCheckTx {
-                    code: Code::Err(2),
+                    code: Code::Err(decode_error_code(&e)),
                     data: vec![].into(),
                     log: format!("Failed to decode transaction: {}", e),
                     info: "".to_string(),
@@ -293,6 +881,53 @@ impl Application for SedlyApp {
         }
     }
 
+    /// Pick and order the transactions of a block this validator proposes
+    ///
+    /// Only the proposer runs this, so the time budget and the ordering
+    /// policy do not need to be shared: the other validators execute
+    /// whatever was proposed.
+    /// Transactions left out stay in Tendermint's mempool for the next block.
+    fn prepare_proposal(&self, request: RequestPrepareProposal) -> ResponsePrepareProposal {
+        let height = request.height as u64;
+        let coinbase_size = self.create_coinbase(height, b"sedly_validator").size()
+            .expect("Failed to serialize coinbase") as u64;
+        let previous_hash = self.chain_state.lock().unwrap().best_block_hash;
+        let max_bytes = self.governed_params().max_block_size
+            .saturating_sub(empty_block_size(previous_hash, height) + coinbase_size)
+            .min(request.max_tx_bytes.max(0) as u64);
+
+        let offered = request.txs.len();
+        let txs = {
+            let mempool = self.core.mempool().lock().unwrap();
+            self.production.ordering.order(request.txs, &previous_hash, |txid| {
+                mempool.get(txid).map(|entry| (entry.feerate(), entry.received_at))
+            })
+        };
+        let proposal = assemble_proposal(
+            txs,
+            height,
+            max_bytes,
+            MAX_BLOCK_SCRIPT_COST,
+            self.production.max_production_time,
+            |tx, spends| {
+                let result = self.check_transaction(tx, spends);
+                if result.valid {
+                    Ok(result.gas_used)
+                } else {
+                    Err(result.error.unwrap_or_else(|| "Invalid transaction".to_string()))
+                }
+            },
+        );
+        if proposal.txs.len() < offered {
+            log::info!(
+                "Proposing block {} with {} of {} transactions ({} invalid, {} deferred)",
+                height, proposal.txs.len(), offered, proposal.invalid, proposal.deferred
+            );
+        }
+
+        ResponsePrepareProposal { txs: proposal.txs }
+    }
+
     /// Begin new block construction
     fn begin_block(&self, request: RequestBeginBlock) -> ResponseBeginBlock {
         let height = request.header.height.value();
@@ -300,8 +935,24 @@ impl Application for SedlyApp {
 
         let chain_state = self.chain_state.lock().unwrap();
         let previous_hash = chain_state.best_block_hash;
+
+        // The block must extend exactly the state we committed; anything else means
+        // the databases diverged and continuing would fork the application state
+        if let Err(e) = check_next_block(
+            chain_state.height,
+            &chain_state.best_block_hash,
+            height as u64,
+            request.header.app_hash.as_ref(),
+        ) {
+            log::error!("Refusing block {}: {}", height, e);
+            self.dump_rejections();
+            panic!("irreconcilable application state: {}", e);
+        }
         drop(chain_state);
 
+        // Parameter changes activating at this height apply to this block
+        let governance_events = self.process_governance(height as u64);
+
         // Update difficulty
         let new_bits = self.update_difficulty(height as u64);
 
@@ -312,46 +963,91 @@ impl Application for SedlyApp {
             previous_hash,
             timestamp: request.header.time.seconds as u64,
             bits: new_bits,
+            size: empty_block_size(previous_hash, height as u64),
+            script_cost: 0,
+            max_size: self.governed_params().max_block_size,
+            governance_actions: Vec::new(),
+            spends: BlockSpends::new(),
         };
 
         // Add coinbase transaction
         // TODO: Get proper beneficiary from validator/miner
         let coinbase = self.create_coinbase(height as u64, b"sedly_validator");
         let mut builder = block_builder;
+        builder.size += coinbase.size().expect("Failed to serialize coinbase") as u64;
+        builder.spends.record(&coinbase, coinbase.hash(), builder.height);
         builder.transactions.push(coinbase);
 
         *self.current_block.lock().unwrap() = Some(builder);
 
-        ResponseBeginBlock {
-            events: vec![
-                Event {
-                    type_str: "begin_block".to_string(),
-                    attributes: vec![
-                        EventAttribute {
-                            key: "height".to_string(),
-                            value: height.to_string(),
-                            index: false,
-                        },
-                        EventAttribute {
-                            key: "difficulty".to_string(),
-                            value: format!("0x{:08x}", new_bits),
-                            index: false,
-                        },
-                    ],
-                }
-            ],
-        }
+        let mut events = vec![
+            Event {
+                type_str: "begin_block".to_string(),
+                attributes: vec![
+                    EventAttribute {
+                        key: "height".to_string(),
+                        value: height.to_string(),
+                        index: false,
+                    },
+                    EventAttribute {
+                        key: "difficulty".to_string(),
+                        value: format!("0x{:08x}", new_bits),
+                        index: false,
+                    },
+                ],
+            }
+        ];
+        events.extend(governance_events);
+        events.extend(self.process_misbehavior(&request.byzantine_validators, height as u64));
+
+        ResponseBeginBlock { events }
     }
 
     /// Deliver transaction to be included in block
     fn deliver_tx(&self, request: RequestDeliverTx) -> ResponseDeliverTx {
-        match bincode::deserialize::<Transaction>(&request.tx) {
+        match decode_transaction(&request.tx) {
             Ok(tx) => {
-                let result = self.check_transaction(&tx);
+                // Checked against the block being built, as the pipeline will at Commit
+                let mut current_block = self.current_block.lock().unwrap();
+                let result = match current_block.as_ref() {
+                    Some(builder) => self.check_transaction(&tx, &builder.spends),
+                    None => self.check_transaction(&tx, &BlockSpends::new()),
+                };
 
                 if result.valid {
                     // Add to current block
-                    if let Some(ref mut builder) = self.current_block.lock().unwrap().as_mut() {
+                    if let Some(builder) = current_block.as_mut() {
+                        let tx_size = result.size;
+                        if builder.size + tx_size > builder.max_size {
+                            return ResponseDeliverTx {
+                                code: Code::Err(4),
+                                data: vec![].into(),
+                                log: format!("Block size limit of {} bytes reached", builder.max_size),
+                                info: "".to_string(),
+                                gas_wanted: 0,
+                                gas_used: 0,
+                                events: vec![],
+                                codespace: "sedly".to_string(),
+                            };
+                        }
+                        if builder.script_cost + result.gas_used > MAX_BLOCK_SCRIPT_COST {
+                            return ResponseDeliverTx {
+                                code: Code::Err(7),
+                                data: vec![].into(),
+                                log: format!("Block script budget of {} reached", MAX_BLOCK_SCRIPT_COST),
+                                info: "".to_string(),
+                                gas_wanted: result.gas_used as i64,
+                                gas_used: 0,
+                                events: vec![],
+                                codespace: "sedly".to_string(),
+                            };
+                        }
+                        builder.size += tx_size;
+                        builder.script_cost += result.gas_used;
+                        if let Some((_, burned, action)) = GovernanceAction::from_transaction(&tx) {
+                            builder.governance_actions.push((tx.hash(), burned, action));
+                        }
+                        builder.spends.record(&tx, tx.hash(), builder.height);
                         builder.transactions.push(tx.clone());
 
                         ResponseDeliverTx {
@@ -388,10 +1084,23 @@ impl Application for SedlyApp {
                         }
                     }
                 } else {
+                    let reason = result.error.unwrap_or("Invalid transaction".to_string());
+                    if let Some(rejections) = &self.rejections {
+                        let tip_height = self.chain_state.lock().unwrap().height;
+                        rejections.lock().unwrap().record(Rejection::new(
+                            RejectedItem::Transaction,
+                            tx.hash(),
+                            tip_height + 1,
+                            tip_height,
+                            "deliver-tx",
+                            reason.clone(),
+                            &request.tx,
+                        ));
+                    }
                     ResponseDeliverTx {
                         code: Code::Err(1),
                         data: vec![].into(),
-                        log: result.error.unwrap_or("Invalid transaction".to_string()),
+                        log: reason,
                         info: "".to_string(),
                         gas_wanted: 0,
                         gas_used: 0,
@@ -402,7 +1111,7 @@ impl Application for SedlyApp {
             }
             Err(e) => {
                 ResponseDeliverTx {
-                    code: Code::Err(2),
+                    code: Code::Err(decode_error_code(&e)),
                     data: vec![].into(),
                     log: format!("Failed to decode transaction: {}", e),
                     info: "".to_string(),
@@ -420,21 +1129,46 @@ impl Application for SedlyApp {
         let height = request.height;
         log::info!("Ending block {}", height);
 
-        ResponseEndBlock {
-            validator_updates: vec![], // No validator updates for PoW
-            consensus_param_updates: None,
-            events: vec![
-                Event {
-                    type_str: "end_block".to_string(),
-                    attributes: vec![
-                        EventAttribute {
-                            key: "height".to_string(),
-                            value: height.to_string(),
-                            index: false,
-                        },
-                    ],
+        // Record proposals and votes delivered in this block
+        let actions = self.current_block.lock().unwrap()
+            .as_mut()
+            .map(|builder| std::mem::take(&mut builder.governance_actions))
+            .unwrap_or_default();
+        let mut governance_events = Vec::new();
+        if !actions.is_empty() {
+            let result = self.state.update_state(|state| {
+                for (txid, burned, action) in &actions {
+                    match state.governance.apply_action(&self.governance, *txid, action, *burned, height as u64) {
+                        Ok(event) => governance_events.push(event),
+                        Err(e) => log::info!("Ignoring governance action in {}: {}", hex::encode(txid), e),
+                    }
                 }
+                Ok(())
+            });
+            if let Err(e) = result {
+                log::error!("Failed to record governance actions: {}", e);
+            }
+        }
+
+        // Only slashing changes the validator set for now
+        let validator_updates = std::mem::take(&mut *self.validator_updates.lock().unwrap());
+
+        let mut events = governance_events.iter().map(governance_event).collect::<Vec<_>>();
+        events.push(Event {
+            type_str: "end_block".to_string(),
+            attributes: vec![
+                EventAttribute {
+                    key: "height".to_string(),
+                    value: height.to_string(),
+                    index: false,
+                },
             ],
+        });
+
+        ResponseEndBlock {
+            validator_updates,
+            consensus_param_updates: None,
+            events,
         }
     }
 
@@ -442,37 +1176,79 @@ impl Application for SedlyApp {
     fn commit(&self, _request: RequestCommit) -> ResponseCommit {
         if let Some(builder) = self.current_block.lock().unwrap().take() {
             // Create final block
-            let block = Block::new(
+            let mut block = Block::new(
                 builder.previous_hash,
                 builder.transactions,
                 builder.bits,
                 builder.height,
             );
+            // Consensus time, not the local clock: every node must derive the same block hash
+            block.header.timestamp = builder.timestamp;
 
-            // Store block in database
-            match self.db.store_block(&block) {
+            match self.connect_committed_block(&block, builder.max_size) {
                 Ok(()) => {
+                    match self.core.block_connected(&block) {
+                        Ok(evicted) => log::debug!("Removed {} confirmed or conflicting mempool transactions", evicted),
+                        Err(e) => log::error!("Failed to index header of block {}: {}", builder.height, e),
+                    }
+
                     // Update chain state
                     let mut chain_state = self.chain_state.lock().unwrap();
                     chain_state.height = builder.height;
                     chain_state.best_block_hash = block.hash();
                     chain_state.current_bits = builder.bits;
                     chain_state.total_transactions += block.transactions.len() as u64;
+                    drop(chain_state);
+
+                    // Governance and slashing state follow the committed chain
+                    let result = self.state
+                        .update_state(|state| {
+                            state.height = builder.height;
+                            state.best_block_hash = block.hash();
+                            Ok(())
+                        })
+                        .map_err(|e| ConsensusError::ConsensusError(e.to_string()))
+                        .and_then(|()| self.save_state());
+                    if let Err(e) = result {
+                        log::error!("Failed to persist consensus state: {}", e);
+                        self.report_error("persist_state", &e.to_string());
+                    }
+
+                    self.audit_supply(builder.height);
+
+                    if let Some(notifier) = &self.notifier {
+                        notifier.notify_block(&block);
+                    }
+                    if let Some(webhooks) = &self.webhooks {
+                        webhooks.notify_block(&block);
+                    }
 
                     log::info!("Committed block {} with {} transactions",
                               builder.height, block.transactions.len());
 
+                    let retain_height = self.retain.retain_height(builder.height);
+                    if retain_height > 0 {
+                        log::debug!("Tendermint may prune blocks below height {}", retain_height);
+                    }
+
                     ResponseCommit {
                         data: block.hash().to_vec().into(),
-                        retain_height: 0, // Keep all blocks
+                        retain_height: retain_height as i64,
                     }
                 }
                 Err(e) => {
-                    log::error!("Failed to store block: {}", e);
-                    ResponseCommit {
-                        data: vec![].into(),
-                        retain_height: 0,
+                    log::error!("Failed to commit block {}: {}", builder.height, e);
+                    self.report_error("commit", &format!("Failed to commit block {}: {}", builder.height, e));
+                    if self.core.db().is_block_invalid(&block.hash()).unwrap_or(false) {
+                        let mut headers = self.core.headers().lock().unwrap();
+                        if headers.insert(&block.header).is_ok() {
+                            headers.set_status(&block.hash(), HeaderStatus::Invalid);
+                        }
                     }
+                    // Tendermint has already decided this block: answering without it would leave
+                    // this node on a different state from the rest of the network
+                    self.dump_rejections();
+                    panic!("irreconcilable application state: failed to commit block {}: {}", builder.height, e);
                 }
             }
         } else {
@@ -491,7 +1267,7 @@ impl Application for SedlyApp {
         match path_parts.as_slice() {
             ["block", height_str] => {
                 if let Ok(height) = height_str.parse::<u64>() {
-                    match self.db.get_block_by_height(height) {
+                    match self.core.db().get_block_by_height(height) {
                         Ok(Some(block)) => {
                             match bincode::serialize(&block) {
                                 Ok(data) => ResponseQuery {
@@ -555,6 +1331,38 @@ impl Application for SedlyApp {
                     }
                 }
             }
+            ["debug", "rejections"] => {
+                json_query(serde_json::to_vec(&self.rejections_json()), "Consensus-rule rejections")
+            }
+            ["alerts"] => json_query(serde_json::to_vec(&self.alerts_json()), "Network alerts"),
+            ["pipeline", "metrics"] => {
+                json_query(serde_json::to_vec(self.core.pipeline().lock().unwrap().metrics()), "Block pipeline metrics")
+            }
+            ["governance", "params"] => {
+                json_query(serde_json::to_vec(&self.governed_params()), "Governance parameters")
+            }
+            ["governance", "proposals"] => {
+                let governance = self.state.get_state().governance;
+                let proposals: Vec<_> = governance.proposals.values().map(ProposalRecord::to_json).collect();
+                json_query(serde_json::to_vec(&proposals), "Governance proposals")
+            }
+            ["governance", "proposal", id] => {
+                let governance = self.state.get_state().governance;
+                match governance.proposals.get(&id.to_lowercase()) {
+                    Some(proposal) => json_query(serde_json::to_vec(&proposal.to_json()), "Proposal found"),
+                    None => ResponseQuery {
+                        code: Code::Err(2),
+                        log: "Proposal not found".to_string(),
+                        info: "".to_string(),
+                        index: 0,
+                        key: vec![].into(),
+                        value: vec![].into(),
+                        proof_ops: None,
+                        height: 0,
+                        codespace: "sedly".to_string(),
+                    },
+                }
+            }
             ["info"] => {
                 let chain_state = self.chain_state.lock().unwrap();
                 let info = format!(
@@ -590,6 +1398,89 @@ impl Application for SedlyApp {
     }
 }
 
+/// ABCI event for a governance state change
+fn governance_event(event: &GovernanceEvent) -> Event {
+    let mut attributes = vec![EventAttribute {
+        key: "proposal".to_string(),
+        value: hex::encode(event.proposal_id()),
+        index: true,
+    }];
+    let details = match event {
+        GovernanceEvent::Submitted { change, activation_height, .. } => vec![
+            ("change", format!("{:?}", change)),
+            ("activation_height", activation_height.to_string()),
+        ],
+        GovernanceEvent::Voted { txid, approve, .. } => vec![
+            ("txid", hex::encode(txid)),
+            ("approve", approve.to_string()),
+        ],
+        GovernanceEvent::Passed { yes_weight, no_weight, .. }
+        | GovernanceEvent::Rejected { yes_weight, no_weight, .. } => vec![
+            ("yes_weight", yes_weight.to_string()),
+            ("no_weight", no_weight.to_string()),
+        ],
+        GovernanceEvent::Activated { change, .. } => vec![("change", format!("{:?}", change))],
+    };
+    attributes.extend(details.into_iter().map(|(key, value)| EventAttribute {
+        key: key.to_string(),
+        value,
+        index: false,
+    }));
+
+    Event {
+        type_str: event.kind().to_string(),
+        attributes,
+    }
+}
+
+/// Serialized size of a block at `height` with no transactions
+///
+/// The header and the length prefix of the transaction list count towards
+/// the size limit the pipeline enforces at Commit.
+fn empty_block_size(previous_hash: [u8; 32], height: u64) -> u64 {
+    Block::new(previous_hash, Vec::new(), 0, height).size()
+        .expect("Failed to serialize empty block") as u64
+}
+
+/// ABCI code of a transaction that cannot be decoded
+///
+/// Payloads over `MAX_TX_DECODE_SIZE` get their own code, so clients and
+/// operators can tell a size violation from a malformed transaction.
+fn decode_error_code(error: &DecodeError) -> u32 {
+    match error {
+        DecodeError::Malformed(_) | DecodeError::UnknownVersion(_) => 2,
+        DecodeError::Oversized { .. } => 6,
+    }
+}
+
+/// Query response carrying a JSON document
+fn json_query(data: serde_json::Result<Vec<u8>>, log: &str) -> ResponseQuery {
+    match data {
+        Ok(data) => ResponseQuery {
+            code: Code::Ok,
+            log: log.to_string(),
+            info: "".to_string(),
+            index: 0,
+            key: vec![].into(),
+            value: data.into(),
+            proof_ops: None,
+            height: 0,
+            codespace: "".to_string(),
+        },
+        Err(e) => ResponseQuery {
+            code: Code::Err(1),
+            log: format!("Serialization error: {}", e),
+            info: "".to_string(),
+            index: 0,
+            key: vec![].into(),
+            value: vec![].into(),
+            proof_ops: None,
+            height: 0,
+            codespace: "sedly".to_string(),
+        },
+    }
+}
+
 /// Consensus errors
 #[derive(Debug, thiserror::Error)]
 pub enum ConsensusError {
@@ -604,11 +1495,15 @@ pub enum ConsensusError {
 
     #[error("Consensus error: {0}")]
     ConsensusError(String),
+
+    #[error("Handshake failed: {0}")]
+    Handshake(#[from] HandshakeError),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sedly_core::{HALVING_INTERVAL, INITIAL_BLOCK_REWARD};
     use tempfile::TempDir;
 
     fn create_test_app() -> (SedlyApp, TempDir) {
@@ -641,6 +1536,107 @@ mod tests {
         assert_eq!(response.last_block_height, 0);
     }
 
+    #[test]
+    fn test_prepare_proposal_drops_invalid_transactions() {
+        let (app, _temp) = create_test_app();
+        let unknown_input = Transaction::new(
+            vec![sedly_core::TxInput::new(OutPoint::new([1; 32], 0), vec![1; 64])],
+            vec![sedly_core::TxOutput::to_address(1_000, &[7; 20])],
+            0,
+        );
+
+        let response = app.prepare_proposal(RequestPrepareProposal {
+            max_tx_bytes: 1_000_000,
+            txs: vec![b"garbage".to_vec().into(), bincode::serialize(&unknown_input).unwrap().into()],
+            height: 1,
+            ..Default::default()
+        });
+        assert!(response.txs.is_empty());
+    }
+
+    #[test]
+    fn test_deliver_tx_rejects_in_block_double_spend() {
+        let allocation = sedly_core::TxOutput::to_address(5_000, &sedly_core::anyone_can_spend(b"alice"));
+        let genesis = Block::genesis_with_allocations(vec![allocation]);
+        let temp_dir = TempDir::new().unwrap();
+        let params = ChainParams::regtest().with_mature_genesis_allocations();
+        let app = SedlyApp::with_genesis(temp_dir.path().to_str().unwrap(), params, &genesis).unwrap();
+        let allocation = OutPoint::new(genesis.transactions[0].hash(), 0);
+        let spend = |value| {
+            let tx = Transaction::new(
+                vec![sedly_core::TxInput::new(allocation.clone(), vec![])],
+                vec![sedly_core::TxOutput::to_address(value, &[2; 20])],
+                0,
+            );
+            RequestDeliverTx { tx: bincode::serialize(&tx).unwrap().into() }
+        };
+
+        let mut request = RequestBeginBlock::default();
+        request.header.height = 1u64.try_into().unwrap();
+        request.header.time.seconds = genesis.header.timestamp as i64 + 60;
+        request.header.app_hash = genesis.hash().to_vec().into();
+        app.begin_block(request);
+
+        let greedy = app.deliver_tx(spend(6_000));
+        assert!(matches!(greedy.code, Code::Err(_)), "{}", greedy.log);
+        assert_eq!(app.deliver_tx(spend(4_000)).code, Code::Ok);
+        let double_spend = app.deliver_tx(spend(3_000));
+        assert!(matches!(double_spend.code, Code::Err(_)), "{}", double_spend.log);
+
+        // Only the valid spend reaches the block, so Commit succeeds
+        app.end_block(RequestEndBlock { height: 1 });
+        app.commit(RequestCommit {});
+        let chain_state = app.chain_state.lock().unwrap();
+        assert_eq!((chain_state.height, chain_state.total_transactions), (1, 3));
+    }
+
+    #[test]
+    fn test_mempool_persisted_across_restart() {
+        let (app, temp) = create_test_app();
+        assert_eq!(app.save_mempool().unwrap(), 0);
+        assert!(temp.path().join(MEMPOOL_FILE_NAME).exists());
+        drop(app);
+
+        let app = SedlyApp::new(temp.path().to_str().unwrap()).unwrap();
+        assert_eq!(app.mempool_size(), 0);
+    }
+
+    #[test]
+    fn test_custom_genesis_app_state() {
+        let spec = sedly_core::GenesisSpec {
+            message: "Sedly devnet".to_string(),
+            timestamp: 1_735_689_600,
+            bits: 0x1d00ffff,
+            allocations: vec![],
+        };
+        let genesis = spec.build().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let app = SedlyApp::with_genesis(temp_dir.path().to_str().unwrap(), ChainParams::regtest(), &genesis).unwrap();
+        assert_eq!(app.chain_state.lock().unwrap().best_block_hash, genesis.hash());
+
+        let app_state = serde_json::to_vec(&GenesisAppState::from_block(&genesis).unwrap()).unwrap();
+        assert!(app.check_genesis_app_state(&app_state).is_ok());
+        assert!(app.check_genesis_app_state(b"").is_ok());
+
+        let other = serde_json::to_vec(&GenesisAppState::from_block(&Block::genesis()).unwrap()).unwrap();
+        assert!(app.check_genesis_app_state(&other).is_err());
+    }
+
+    #[test]
+    fn test_restart_recovers_committed_tip() {
+        let (app, temp) = create_test_app();
+        let genesis_hash = app.chain_state.lock().unwrap().best_block_hash;
+        let block = Block::new(genesis_hash, vec![app.create_coinbase(1, b"miner")], 0x1d00ffff, 1);
+        app.core.db().store_block(&block).unwrap();
+        drop(app);
+
+        let app = SedlyApp::new(temp.path().to_str().unwrap()).unwrap();
+        let chain_state = app.chain_state.lock().unwrap();
+        assert_eq!(chain_state.height, 1);
+        assert_eq!(chain_state.best_block_hash, block.hash());
+        assert_eq!(chain_state.current_bits, 0x1d00ffff);
+    }
+
     #[test]
     fn test_block_reward_calculation() {
         let (app, _temp) = create_test_app();
@@ -663,6 +1659,6 @@ mod tests {
 
         assert!(coinbase.is_coinbase());
         assert_eq!(coinbase.outputs.len(), 1);
-        assert_eq!(coinbase.outputs[0].value, INITIAL_BLOCK_REWARD);
+        assert_eq!(coinbase.outputs[0].value.to_sat(), INITIAL_BLOCK_REWARD);
     }
 }
\ No newline at end of file
@@ -0,0 +1,442 @@
+//! On-chain governance: proposals, coin-locked voting and parameter activation
+//!
+//! Proposals and votes are carried by regular transactions (see
+//! `sedly_core::governance`). A vote is weighted by the native value of the
+//! vote transaction's outputs that are still unspent when voting ends, so
+//! moving coins after voting (including to vote again) withdraws that weight.
+
+use sedly_core::{ChainParams, GovernanceAction, ParameterChange, RetargetWindow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Basis points in one unit (100%)
+const BPS: u128 = 10_000;
+
+/// Smallest block size governance may set
+pub const MIN_GOVERNED_BLOCK_SIZE: u64 = 100_000;
+
+/// Largest block size governance may set: larger blocks would be refused
+/// by the decoder before validation
+pub const MAX_GOVERNED_BLOCK_SIZE: u64 = sedly_core::codec::MAX_BLOCK_DECODE_SIZE as u64;
+
+/// Rules of the governance process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GovernanceParams {
+    /// Blocks during which a proposal can be voted on
+    pub voting_period: u64,
+    /// Minimum blocks between the end of voting and activation
+    pub min_activation_delay: u64,
+    /// Minimum value burned by a proposal transaction
+    pub proposal_deposit: u64,
+    /// Minimum total vote weight for a proposal to pass
+    pub quorum: u64,
+    /// Share of the vote weight that must approve, in basis points
+    pub approval_threshold_bps: u32,
+}
+
+impl Default for GovernanceParams {
+    fn default() -> Self {
+        Self {
+            voting_period: 10_080,           // ~2 weeks at 2 minute blocks
+            min_activation_delay: 1_440,     // ~2 days
+            proposal_deposit: 1_000_000_000, // 10 SLY
+            quorum: 100_000_000_000,         // 1000 SLY
+            approval_threshold_bps: 6_667,
+        }
+    }
+}
+
+/// Parameters that governance can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GovernedParams {
+    /// Minimum fee for a mempool transaction
+    pub min_tx_fee: u64,
+    /// Maximum serialized block size
+    pub max_block_size: u64,
+    /// Difficulty retarget algorithm
+    pub retarget_window: RetargetWindow,
+}
+
+impl GovernedParams {
+    /// Initial values for a network
+    pub fn from_chain_params(params: &ChainParams) -> Self {
+        Self {
+            min_tx_fee: sedly_core::MIN_TX_FEE,
+            max_block_size: sedly_core::MAX_BLOCK_SIZE as u64,
+            retarget_window: params.retarget_window,
+        }
+    }
+
+    /// Apply an activated parameter change
+    pub fn apply(&mut self, change: &ParameterChange) {
+        match *change {
+            ParameterChange::MinTxFee(fee) => self.min_tx_fee = fee,
+            ParameterChange::MaxBlockSize(size) => self.max_block_size = size,
+            ParameterChange::RetargetWindow(window) => self.retarget_window = window,
+        }
+    }
+
+    /// Chain parameters with the governed values applied
+    pub fn chain_params(&self, base: &ChainParams) -> ChainParams {
+        ChainParams {
+            retarget_window: self.retarget_window,
+            ..base.clone()
+        }
+    }
+}
+
+impl Default for GovernedParams {
+    fn default() -> Self {
+        Self::from_chain_params(&ChainParams::mainnet())
+    }
+}
+
+/// Lifecycle of a proposal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    /// Accepting votes
+    Voting,
+    /// Approved, waiting for the activation height
+    Passed,
+    /// Quorum or approval threshold not reached
+    Rejected,
+    /// Change applied
+    Activated,
+}
+
+/// Vote recorded on a proposal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteRecord {
+    /// Transaction carrying the vote
+    pub txid: [u8; 32],
+    /// Whether the vote approves the proposal
+    pub approve: bool,
+    /// Height of the block including the vote
+    pub height: u64,
+}
+
+/// Proposal tracked in consensus state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposalRecord {
+    /// Proposal ID (txid of the proposal transaction)
+    pub id: [u8; 32],
+    /// Requested parameter change
+    pub change: ParameterChange,
+    /// Height of the block including the proposal
+    pub submitted_height: u64,
+    /// Last height at which votes are counted
+    pub voting_end_height: u64,
+    /// Height at which the change applies if approved
+    pub activation_height: u64,
+    /// Current status
+    pub status: ProposalStatus,
+    /// Votes received
+    pub votes: Vec<VoteRecord>,
+    /// Approving weight at the end of voting
+    pub yes_weight: u64,
+    /// Rejecting weight at the end of voting
+    pub no_weight: u64,
+}
+
+impl ProposalRecord {
+    /// JSON view with hex-encoded IDs, as returned by ABCI queries
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": hex::encode(self.id),
+            "change": self.change,
+            "submitted_height": self.submitted_height,
+            "voting_end_height": self.voting_end_height,
+            "activation_height": self.activation_height,
+            "status": self.status,
+            "votes": self.votes.iter().map(|vote| serde_json::json!({
+                "txid": hex::encode(vote.txid),
+                "approve": vote.approve,
+                "height": vote.height,
+            })).collect::<Vec<_>>(),
+            "yes_weight": self.yes_weight,
+            "no_weight": self.no_weight,
+        })
+    }
+}
+
+/// Governance section of the consensus state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GovernanceState {
+    /// Proposals by hex ID
+    pub proposals: BTreeMap<String, ProposalRecord>,
+    /// Current values of the governed parameters
+    pub params: GovernedParams,
+}
+
+/// Governance state change, reported as an ABCI event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GovernanceEvent {
+    Submitted { id: [u8; 32], change: ParameterChange, activation_height: u64 },
+    Voted { id: [u8; 32], txid: [u8; 32], approve: bool },
+    Passed { id: [u8; 32], yes_weight: u64, no_weight: u64 },
+    Rejected { id: [u8; 32], yes_weight: u64, no_weight: u64 },
+    Activated { id: [u8; 32], change: ParameterChange },
+}
+
+impl GovernanceEvent {
+    /// ABCI event type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GovernanceEvent::Submitted { .. } => "proposal_submitted",
+            GovernanceEvent::Voted { .. } => "proposal_vote",
+            GovernanceEvent::Passed { .. } => "proposal_passed",
+            GovernanceEvent::Rejected { .. } => "proposal_rejected",
+            GovernanceEvent::Activated { .. } => "proposal_activated",
+        }
+    }
+
+    /// Proposal the event refers to
+    pub fn proposal_id(&self) -> [u8; 32] {
+        match self {
+            GovernanceEvent::Submitted { id, .. }
+            | GovernanceEvent::Voted { id, .. }
+            | GovernanceEvent::Passed { id, .. }
+            | GovernanceEvent::Rejected { id, .. }
+            | GovernanceEvent::Activated { id, .. } => *id,
+        }
+    }
+}
+
+impl GovernanceState {
+    /// Proposal by ID
+    pub fn proposal(&self, id: &[u8; 32]) -> Option<&ProposalRecord> {
+        self.proposals.get(&hex::encode(id))
+    }
+
+    /// Apply a governance action carried by transaction `txid` at `height`
+    ///
+    /// `burned` is the value of the output carrying the action.
+    pub fn apply_action(
+        &mut self,
+        params: &GovernanceParams,
+        txid: [u8; 32],
+        action: &GovernanceAction,
+        burned: u64,
+        height: u64,
+    ) -> Result<GovernanceEvent, GovernanceError> {
+        match *action {
+            GovernanceAction::Propose { change, activation_height } => {
+                self.submit(params, txid, change, activation_height, burned, height)
+            }
+            GovernanceAction::Vote { proposal, approve } => self.vote(proposal, txid, approve, height),
+        }
+    }
+
+    /// Register a new proposal
+    fn submit(
+        &mut self,
+        params: &GovernanceParams,
+        id: [u8; 32],
+        change: ParameterChange,
+        activation_height: u64,
+        deposit: u64,
+        height: u64,
+    ) -> Result<GovernanceEvent, GovernanceError> {
+        if deposit < params.proposal_deposit {
+            return Err(GovernanceError::InsufficientDeposit { deposit, required: params.proposal_deposit });
+        }
+
+        let voting_end_height = height + params.voting_period;
+        let earliest = voting_end_height + params.min_activation_delay;
+        if activation_height < earliest {
+            return Err(GovernanceError::ActivationTooEarly { activation_height, earliest });
+        }
+
+        if let ParameterChange::MaxBlockSize(size) = change {
+            if !(MIN_GOVERNED_BLOCK_SIZE..=MAX_GOVERNED_BLOCK_SIZE).contains(&size) {
+                return Err(GovernanceError::InvalidChange(format!("block size {} out of range", size)));
+            }
+        }
+
+        let key = hex::encode(id);
+        if self.proposals.contains_key(&key) {
+            return Err(GovernanceError::DuplicateProposal { id });
+        }
+        self.proposals.insert(key, ProposalRecord {
+            id,
+            change,
+            submitted_height: height,
+            voting_end_height,
+            activation_height,
+            status: ProposalStatus::Voting,
+            votes: Vec::new(),
+            yes_weight: 0,
+            no_weight: 0,
+        });
+
+        Ok(GovernanceEvent::Submitted { id, change, activation_height })
+    }
+
+    /// Record a vote on an open proposal
+    fn vote(
+        &mut self,
+        id: [u8; 32],
+        txid: [u8; 32],
+        approve: bool,
+        height: u64,
+    ) -> Result<GovernanceEvent, GovernanceError> {
+        let proposal = self.proposals
+            .get_mut(&hex::encode(id))
+            .ok_or(GovernanceError::UnknownProposal { id })?;
+        if proposal.status != ProposalStatus::Voting || height > proposal.voting_end_height {
+            return Err(GovernanceError::VotingClosed { id });
+        }
+
+        proposal.votes.push(VoteRecord { txid, approve, height });
+        Ok(GovernanceEvent::Voted { id, txid, approve })
+    }
+
+    /// Close voting ended before `height` and activate changes due at `height`
+    ///
+    /// Called before the block at `height` is executed, so every vote is
+    /// already stored and changes apply to the activation block itself.
+    /// `vote_weight` returns the weight still backing a vote transaction.
+    pub fn process_height<F>(&mut self, params: &GovernanceParams, height: u64, vote_weight: F) -> Vec<GovernanceEvent>
+    where
+        F: Fn(&[u8; 32]) -> u64,
+    {
+        let mut events = Vec::new();
+
+        for proposal in self.proposals.values_mut() {
+            if proposal.status == ProposalStatus::Voting && height > proposal.voting_end_height {
+                let (mut yes, mut no) = (0u64, 0u64);
+                for vote in &proposal.votes {
+                    let weight = vote_weight(&vote.txid);
+                    if vote.approve {
+                        yes = yes.saturating_add(weight);
+                    } else {
+                        no = no.saturating_add(weight);
+                    }
+                }
+                proposal.yes_weight = yes;
+                proposal.no_weight = no;
+
+                let total = yes as u128 + no as u128;
+                let approved = total >= params.quorum as u128
+                    && yes as u128 * BPS >= total * params.approval_threshold_bps as u128;
+                let id = proposal.id;
+                if approved {
+                    proposal.status = ProposalStatus::Passed;
+                    events.push(GovernanceEvent::Passed { id, yes_weight: yes, no_weight: no });
+                } else {
+                    proposal.status = ProposalStatus::Rejected;
+                    events.push(GovernanceEvent::Rejected { id, yes_weight: yes, no_weight: no });
+                }
+            }
+
+            if proposal.status == ProposalStatus::Passed && height >= proposal.activation_height {
+                self.params.apply(&proposal.change);
+                proposal.status = ProposalStatus::Activated;
+                events.push(GovernanceEvent::Activated { id: proposal.id, change: proposal.change });
+            }
+        }
+        events
+    }
+}
+
+/// Reasons a governance action is ignored
+#[derive(Debug, thiserror::Error)]
+pub enum GovernanceError {
+    #[error("Proposal deposit {deposit} below required {required}")]
+    InsufficientDeposit { deposit: u64, required: u64 },
+
+    #[error("Activation height {activation_height} is before the earliest allowed {earliest}")]
+    ActivationTooEarly { activation_height: u64, earliest: u64 },
+
+    #[error("Invalid parameter change: {0}")]
+    InvalidChange(String),
+
+    #[error("Proposal {} already exists", hex::encode(id))]
+    DuplicateProposal { id: [u8; 32] },
+
+    #[error("Unknown proposal {}", hex::encode(id))]
+    UnknownProposal { id: [u8; 32] },
+
+    #[error("Voting on proposal {} is closed", hex::encode(id))]
+    VotingClosed { id: [u8; 32] },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn test_params() -> GovernanceParams {
+        GovernanceParams {
+            voting_period: 10,
+            min_activation_delay: 5,
+            proposal_deposit: 100,
+            quorum: 1_000,
+            approval_threshold_bps: 6_667,
+        }
+    }
+
+    #[test]
+    fn test_proposal_lifecycle() {
+        let params = test_params();
+        let mut state = GovernanceState::default();
+        let id = [1u8; 32];
+        let propose = GovernanceAction::Propose {
+            change: ParameterChange::MinTxFee(5_000),
+            activation_height: 120,
+        };
+
+        assert!(matches!(
+            state.apply_action(&params, id, &propose, 10, 100),
+            Err(GovernanceError::InsufficientDeposit { .. })
+        ));
+        let early = GovernanceAction::Propose { change: ParameterChange::MinTxFee(5_000), activation_height: 110 };
+        assert!(matches!(
+            state.apply_action(&params, id, &early, 100, 100),
+            Err(GovernanceError::ActivationTooEarly { earliest: 115, .. })
+        ));
+        state.apply_action(&params, id, &propose, 100, 100).unwrap();
+
+        let weights: HashMap<[u8; 32], u64> = [([2; 32], 900), ([3; 32], 300), ([4; 32], 0)].into();
+        for (txid, approve) in [([2u8; 32], true), ([3; 32], false), ([4; 32], false)] {
+            state.apply_action(&params, txid, &GovernanceAction::Vote { proposal: id, approve }, 1, 105).unwrap();
+        }
+        assert!(matches!(
+            state.apply_action(&params, [5; 32], &GovernanceAction::Vote { proposal: id, approve: true }, 1, 111),
+            Err(GovernanceError::VotingClosed { .. })
+        ));
+
+        let weight = |txid: &[u8; 32]| weights.get(txid).copied().unwrap_or_default();
+        assert!(state.process_height(&params, 110, weight).is_empty());
+        assert_eq!(
+            state.process_height(&params, 111, weight),
+            vec![GovernanceEvent::Passed { id, yes_weight: 900, no_weight: 300 }]
+        );
+        assert_eq!(state.params.min_tx_fee, sedly_core::MIN_TX_FEE);
+
+        assert_eq!(
+            state.process_height(&params, 120, weight),
+            vec![GovernanceEvent::Activated { id, change: ParameterChange::MinTxFee(5_000) }]
+        );
+        assert_eq!(state.params.min_tx_fee, 5_000);
+        assert_eq!(state.proposal(&id).unwrap().status, ProposalStatus::Activated);
+    }
+
+    #[test]
+    fn test_proposal_rejected_without_quorum() {
+        let params = test_params();
+        let mut state = GovernanceState::default();
+        let id = [1u8; 32];
+        let propose = GovernanceAction::Propose {
+            change: ParameterChange::RetargetWindow(RetargetWindow::Overlapping),
+            activation_height: 200,
+        };
+        state.apply_action(&params, id, &propose, 100, 100).unwrap();
+        state.apply_action(&params, [2; 32], &GovernanceAction::Vote { proposal: id, approve: true }, 1, 101).unwrap();
+
+        let events = state.process_height(&params, 111, |_| 999);
+        assert_eq!(events, vec![GovernanceEvent::Rejected { id, yes_weight: 999, no_weight: 0 }]);
+        assert!(state.process_height(&params, 200, |_| 999).is_empty());
+        assert_eq!(state.params.retarget_window, RetargetWindow::EpochAligned);
+    }
+}
@@ -0,0 +1,231 @@
+//! Crash recovery handshake between the application database and Tendermint
+//!
+//! On startup the application recovers its tip from the database and reports
+//! it through `Info`. Tendermint then replays any block the application has
+//! not committed yet (at most the last one after a crash mid-commit). Each
+//! delivered block is checked against the recovered state so that a diverged
+//! or wiped database stops the node instead of silently forking.
+
+use sedly_core::{Block, BlockHeader, BlockchainDB, StorageError};
+
+/// Tip recovered from the application database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredTip {
+    /// Height of the last committed block
+    pub height: u64,
+    /// Hash of the last committed block (reported as app hash)
+    pub best_block_hash: [u8; 32],
+    /// Header of the last committed block
+    pub header: BlockHeader,
+}
+
+/// Recover the committed tip, initializing a fresh database with the genesis block
+///
+/// Fails if the database belongs to another chain or its metadata and
+/// block indexes disagree, rather than resetting the chain state.
+pub fn recover_tip(db: &BlockchainDB, genesis: &Block) -> Result<RecoveredTip, HandshakeError> {
+    let mut metadata = db.get_metadata()?;
+    let genesis_hash = genesis.hash();
+
+    if !db.is_initialized()? {
+        if metadata.height != 0 || metadata.best_block_hash != [0; 32] {
+            return Err(HandshakeError::MissingGenesis { height: metadata.height });
+        }
+        log::info!("Empty database, initializing with genesis {}", hex::encode(genesis_hash));
+        db.initialize_with_genesis(genesis)?;
+        metadata = db.get_metadata()?;
+    }
+
+    if metadata.genesis_hash != genesis_hash {
+        return Err(HandshakeError::GenesisMismatch {
+            expected: genesis_hash,
+            found: metadata.genesis_hash,
+        });
+    }
+
+    let tip = db.get_block(&metadata.best_block_hash)?.ok_or(HandshakeError::MissingTip {
+        height: metadata.height,
+        hash: metadata.best_block_hash,
+    })?;
+    if tip.header.height != metadata.height {
+        return Err(HandshakeError::TipHeightMismatch {
+            metadata: metadata.height,
+            block: tip.header.height,
+        });
+    }
+
+    let indexed = db.get_header_by_height(metadata.height)?.map(|header| header.hash());
+    if indexed != Some(metadata.best_block_hash) {
+        return Err(HandshakeError::HeightIndexMismatch { height: metadata.height });
+    }
+
+    Ok(RecoveredTip {
+        height: metadata.height,
+        best_block_hash: metadata.best_block_hash,
+        header: tip.header,
+    })
+}
+
+/// Check that the consensus state saved at the last commit belongs to the recovered tip
+///
+/// The state is written after the block reaches the database, so a crash in
+/// between leaves it one block behind. Governance and validator changes of
+/// that block would be lost, hence the node refuses to start.
+pub fn check_consensus_state(
+    state_height: u64,
+    state_hash: &[u8; 32],
+    tip: &RecoveredTip,
+) -> Result<(), HandshakeError> {
+    if state_height != tip.height || *state_hash != tip.best_block_hash {
+        return Err(HandshakeError::StateMismatch { state_height, tip_height: tip.height });
+    }
+    Ok(())
+}
+
+/// Check that a block delivered by consensus extends the application state
+///
+/// `block_app_hash` is the app hash recorded in the block header, i.e. the
+/// hash the application reported after committing the previous block.
+pub fn check_next_block(
+    app_height: u64,
+    app_hash: &[u8; 32],
+    block_height: u64,
+    block_app_hash: &[u8],
+) -> Result<(), HandshakeError> {
+    if block_height <= app_height {
+        return Err(HandshakeError::AppAhead { app_height, block_height });
+    }
+    if block_height > app_height + 1 {
+        return Err(HandshakeError::AppBehind { app_height, block_height });
+    }
+    if block_app_hash != app_hash {
+        return Err(HandshakeError::AppHashMismatch {
+            height: app_height,
+            app_hash: *app_hash,
+            consensus_hash: block_app_hash.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+/// Irreconcilable differences between the database and consensus
+#[derive(Debug, thiserror::Error)]
+pub enum HandshakeError {
+    #[error(
+        "Database was created for genesis {}, but this network expects {}; check --data-dir and --network",
+        hex::encode(found), hex::encode(expected)
+    )]
+    GenesisMismatch { expected: [u8; 32], found: [u8; 32] },
+
+    #[error("Database has chain state at height {height} but no genesis hash; run with --reindex")]
+    MissingGenesis { height: u64 },
+
+    #[error("Best block {} at height {height} is missing from the database; run with --reindex", hex::encode(hash))]
+    MissingTip { height: u64, hash: [u8; 32] },
+
+    #[error("Metadata height {metadata} does not match best block height {block}; run with --reindex")]
+    TipHeightMismatch { metadata: u64, block: u64 },
+
+    #[error("Height index at {height} does not point to the best block; run with --reindex")]
+    HeightIndexMismatch { height: u64 },
+
+    #[error(
+        "Consensus delivered block {block_height} but the application already committed height {app_height}; \
+         the Tendermint data directory is older than the application database"
+    )]
+    AppAhead { app_height: u64, block_height: u64 },
+
+    #[error(
+        "Consensus delivered block {block_height} but the application is at height {app_height}; \
+         blocks in between were never replayed"
+    )]
+    AppBehind { app_height: u64, block_height: u64 },
+
+    #[error(
+        "Application state at height {height} has app hash {}, but consensus expects {}; \
+         the application database has diverged from the chain",
+        hex::encode(app_hash), hex::encode(consensus_hash)
+    )]
+    AppHashMismatch { height: u64, app_hash: [u8; 32], consensus_hash: Vec<u8> },
+
+    #[error(
+        "Consensus state was saved at height {state_height} but the database tip is at height {tip_height}; \
+         the node stopped before saving the consensus state of the last block"
+    )]
+    StateMismatch { state_height: u64, tip_height: u64 },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::Transaction;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_recover_tip() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+
+        let tip = recover_tip(&db, &genesis).unwrap();
+        assert_eq!(tip.height, 0);
+        assert_eq!(tip.best_block_hash, genesis.hash());
+
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 1)], 0x1d00ffff, 1);
+        db.store_block(&block).unwrap();
+        let tip = recover_tip(&db, &genesis).unwrap();
+        assert_eq!(tip.height, 1);
+        assert_eq!(tip.header, block.header);
+
+        // Same database opened against a different genesis
+        let other = Block::new([0; 32], vec![Transaction::coinbase(b"other", 0, 1)], 0x1d00ffff, 0);
+        assert!(matches!(
+            recover_tip(&db, &other),
+            Err(HandshakeError::GenesisMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_consensus_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        recover_tip(&db, &genesis).unwrap();
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 1)], 0x1d00ffff, 1);
+        db.store_block(&block).unwrap();
+        let tip = recover_tip(&db, &genesis).unwrap();
+
+        assert!(check_consensus_state(1, &block.hash(), &tip).is_ok());
+        // Crash after storing the block, before saving its consensus state
+        assert!(matches!(
+            check_consensus_state(0, &genesis.hash(), &tip),
+            Err(HandshakeError::StateMismatch { state_height: 0, tip_height: 1 })
+        ));
+        assert!(matches!(
+            check_consensus_state(1, &[9; 32], &tip),
+            Err(HandshakeError::StateMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_next_block() {
+        let app_hash = [7u8; 32];
+
+        assert!(check_next_block(4, &app_hash, 5, &app_hash).is_ok());
+        assert!(matches!(
+            check_next_block(4, &app_hash, 4, &app_hash),
+            Err(HandshakeError::AppAhead { .. })
+        ));
+        assert!(matches!(
+            check_next_block(4, &app_hash, 7, &app_hash),
+            Err(HandshakeError::AppBehind { .. })
+        ));
+        assert!(matches!(
+            check_next_block(4, &app_hash, 5, &[8u8; 32]),
+            Err(HandshakeError::AppHashMismatch { height: 4, .. })
+        ));
+    }
+}
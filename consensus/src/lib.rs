@@ -1,12 +1,28 @@
 //! Sedly Consensus - Tendermint ABCI integration
 
 pub mod abci;
+pub mod governance;
+pub mod handshake;
+pub mod notify;
+pub mod production;
+pub mod pruning;
 pub mod server;
+pub mod simnet;
+pub mod slashing;
 pub mod state;
+pub mod webhook;
 
 pub use abci::{SedlyApp, ConsensusError};
+pub use governance::{GovernanceParams, GovernanceState, GovernedParams, ProposalStatus};
+pub use handshake::{HandshakeError, RecoveredTip};
+pub use notify::{NotifyConfig, NotifyError, ZmqNotifier};
+pub use production::{OrderingPolicy, ProductionConfig, DEFAULT_MAX_PRODUCTION_TIME};
+pub use pruning::RetainConfig;
 pub use server::{ConsensusServer, ServerConfig};
-pub use state::{ConsensusState, StateManager};
+pub use simnet::{SimError, SimNetwork};
+pub use slashing::SlashingParams;
+pub use state::{ConsensusState, EvidenceKind, EvidenceRecord, StateManager};
+pub use webhook::{WebhookConfig, WebhookError, WebhookEvent, WebhookNotifier};
 
 #[cfg(test)]
 mod tests {
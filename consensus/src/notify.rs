@@ -0,0 +1,267 @@
+//! ZeroMQ notifications of connected blocks and accepted transactions
+//!
+//! Mirrors bitcoind's `-zmqpub*` interface so existing subscribers work with
+//! minimal changes: PUB sockets emit multipart messages `[topic, body,
+//! sequence]`, where the sequence is a per-topic little-endian u32 that lets
+//! subscribers detect dropped messages. Topics:
+//!
+//! - `hashblock` / `rawblock`: hash or serialized bytes of each connected block
+//! - `hashtx` / `rawtx`: hash or serialized bytes of each transaction accepted
+//!   into the mempool or confirmed in a connected block
+//!
+//! Each topic is enabled by giving it an endpoint; topics may share one.
+
+use sedly_core::{Block, Transaction};
+use std::collections::HashMap;
+use std::sync::mpsc as std_mpsc;
+use std::thread::JoinHandle;
+use tokio::sync::mpsc;
+use zeromq::{PubSocket, Socket, SocketSend, ZmqMessage};
+
+/// Topic carrying the hash of each connected block
+pub const TOPIC_HASH_BLOCK: &str = "hashblock";
+/// Topic carrying the hash of each new transaction
+pub const TOPIC_HASH_TX: &str = "hashtx";
+/// Topic carrying each connected block, serialized
+pub const TOPIC_RAW_BLOCK: &str = "rawblock";
+/// Topic carrying each new transaction, serialized
+pub const TOPIC_RAW_TX: &str = "rawtx";
+/// Messages waiting for the publishing thread before new ones are dropped
+pub const NOTIFY_QUEUE_CAPACITY: usize = 10_000;
+
+/// Endpoints of the notification topics (None disables the topic)
+#[derive(Debug, Clone, Default)]
+pub struct NotifyConfig {
+    /// Endpoint for `hashblock`, e.g. `tcp://127.0.0.1:28332`
+    pub hash_block: Option<String>,
+    /// Endpoint for `hashtx`
+    pub hash_tx: Option<String>,
+    /// Endpoint for `rawblock`
+    pub raw_block: Option<String>,
+    /// Endpoint for `rawtx`
+    pub raw_tx: Option<String>,
+}
+
+impl NotifyConfig {
+    /// Whether no topic is enabled
+    pub fn is_empty(&self) -> bool {
+        self.topics().is_empty()
+    }
+
+    /// Enabled topics with their endpoint
+    fn topics(&self) -> Vec<(&'static str, String)> {
+        [
+            (TOPIC_HASH_BLOCK, &self.hash_block),
+            (TOPIC_HASH_TX, &self.hash_tx),
+            (TOPIC_RAW_BLOCK, &self.raw_block),
+            (TOPIC_RAW_TX, &self.raw_tx),
+        ]
+        .into_iter()
+        .filter_map(|(topic, endpoint)| endpoint.clone().map(|endpoint| (topic, endpoint)))
+        .collect()
+    }
+}
+
+/// Message queued for publication
+struct Notification {
+    topic: &'static str,
+    body: Vec<u8>,
+}
+
+/// Publisher of block and transaction notifications
+///
+/// Sockets are served by a background thread, so notifying never blocks
+/// block connection or transaction checks. The queue towards the thread
+/// holds at most `NOTIFY_QUEUE_CAPACITY` messages and drops new ones when
+/// full, and messages for subscribers that are not keeping up are dropped
+/// by ZeroMQ; the sequence numbers reveal the gap.
+pub struct ZmqNotifier {
+    /// Queue towards the publishing thread (closed on drop)
+    sender: Option<mpsc::Sender<Notification>>,
+    /// Topics with an endpoint
+    topics: Vec<&'static str>,
+    /// Publishing thread
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ZmqNotifier {
+    /// Bind the PUB sockets of the enabled topics and start publishing
+    pub fn start(config: &NotifyConfig) -> Result<Self, NotifyError> {
+        let topics = config.topics();
+        let (sender, receiver) = mpsc::channel(NOTIFY_QUEUE_CAPACITY);
+        let (ready_sender, ready) = std_mpsc::channel();
+
+        let enabled = topics.iter().map(|(topic, _)| *topic).collect();
+        let thread = std::thread::Builder::new()
+            .name("zmq-notify".to_string())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(NotifyError::Runtime(e.to_string())));
+                        return;
+                    }
+                };
+                runtime.block_on(publish(topics, receiver, ready_sender));
+            })
+            .map_err(|e| NotifyError::Runtime(e.to_string()))?;
+
+        match ready.recv() {
+            Ok(Ok(())) => Ok(Self { sender: Some(sender), topics: enabled, thread: Some(thread) }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(NotifyError::Runtime("Notification thread exited".to_string())),
+        }
+    }
+
+    /// Notify a block connected to the active chain
+    ///
+    /// Its transactions are published on the transaction topics as well.
+    pub fn notify_block(&self, block: &Block) {
+        self.queue(TOPIC_HASH_BLOCK, || block.hash().to_vec());
+        self.queue(TOPIC_RAW_BLOCK, || bincode::serialize(block).unwrap_or_default());
+        for tx in &block.transactions {
+            self.notify_transaction(tx);
+        }
+    }
+
+    /// Notify a transaction accepted into the mempool
+    pub fn notify_transaction(&self, tx: &Transaction) {
+        self.queue(TOPIC_HASH_TX, || tx.hash().to_vec());
+        self.queue(TOPIC_RAW_TX, || bincode::serialize(tx).unwrap_or_default());
+    }
+
+    /// Queue a message if its topic is enabled; the body is built lazily
+    ///
+    /// The message is dropped when the queue is full.
+    fn queue(&self, topic: &'static str, body: impl FnOnce() -> Vec<u8>) {
+        if !self.topics.contains(&topic) {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            if let Err(mpsc::error::TrySendError::Full(_)) = sender.try_send(Notification { topic, body: body() }) {
+                log::debug!("Notification queue full, dropping {} notification", topic);
+            }
+        }
+    }
+}
+
+impl Drop for ZmqNotifier {
+    fn drop(&mut self) {
+        // Closing the queue ends the publishing loop once it is drained
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Bind the sockets, report the outcome on `ready`, then publish until the queue closes
+async fn publish(
+    topics: Vec<(&'static str, String)>,
+    mut receiver: mpsc::Receiver<Notification>,
+    ready: std_mpsc::Sender<Result<(), NotifyError>>,
+) {
+    // One socket per endpoint, shared by the topics bound to it
+    let mut sockets: Vec<PubSocket> = Vec::new();
+    let mut endpoints: HashMap<String, usize> = HashMap::new();
+    let mut routes: HashMap<&'static str, usize> = HashMap::new();
+    for (topic, endpoint) in topics {
+        let socket_index = match endpoints.get(&endpoint) {
+            Some(index) => *index,
+            None => {
+                let mut socket = PubSocket::new();
+                if let Err(e) = socket.bind(&endpoint).await {
+                    let _ = ready.send(Err(NotifyError::Bind { endpoint, reason: e.to_string() }));
+                    return;
+                }
+                log::info!("Publishing ZMQ notifications on {}", endpoint);
+                sockets.push(socket);
+                endpoints.insert(endpoint, sockets.len() - 1);
+                sockets.len() - 1
+            }
+        };
+        routes.insert(topic, socket_index);
+    }
+    let _ = ready.send(Ok(()));
+
+    let mut sequences: HashMap<&'static str, u32> = HashMap::new();
+    while let Some(notification) = receiver.recv().await {
+        let Some(socket) = routes.get(notification.topic).map(|index| &mut sockets[*index]) else {
+            continue;
+        };
+        let sequence = sequences.entry(notification.topic).or_default();
+        let mut message = ZmqMessage::from(notification.topic.as_bytes().to_vec());
+        message.push_back(notification.body.into());
+        message.push_back(sequence.to_le_bytes().to_vec().into());
+        *sequence = sequence.wrapping_add(1);
+
+        if let Err(e) = socket.send(message).await {
+            log::warn!("Failed to publish {} notification: {}", notification.topic, e);
+        }
+    }
+}
+
+/// Notification errors
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyError {
+    #[error("Failed to bind ZMQ endpoint {endpoint}: {reason}")]
+    Bind { endpoint: String, reason: String },
+
+    #[error("Notification thread error: {0}")]
+    Runtime(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeromq::{SocketRecv, SubSocket};
+
+    fn free_endpoint() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("tcp://{}", listener.local_addr().unwrap())
+    }
+
+    #[test]
+    fn test_block_notifications() {
+        let endpoint = free_endpoint();
+        let config = NotifyConfig {
+            hash_block: Some(endpoint.clone()),
+            raw_tx: Some(endpoint.clone()),
+            ..NotifyConfig::default()
+        };
+        let notifier = ZmqNotifier::start(&config).unwrap();
+        let block = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let frames = runtime.block_on(async {
+            let mut subscriber = SubSocket::new();
+            subscriber.connect(&endpoint).await.unwrap();
+            subscriber.subscribe("").await.unwrap();
+
+            // The subscription reaches the publisher asynchronously
+            let mut frames = Vec::new();
+            while frames.len() < 2 {
+                notifier.notify_block(&block);
+                let received = tokio::time::timeout(std::time::Duration::from_millis(200), subscriber.recv()).await;
+                if let Ok(message) = received {
+                    frames.push(message.unwrap().into_vec());
+                }
+            }
+            frames
+        });
+
+        let topics: Vec<&[u8]> = frames.iter().map(|message| message[0].as_ref()).collect();
+        assert!(topics.contains(&TOPIC_HASH_BLOCK.as_bytes()));
+        assert!(topics.contains(&TOPIC_RAW_TX.as_bytes()));
+        let hash_block = frames.iter().find(|message| message[0].as_ref() == TOPIC_HASH_BLOCK.as_bytes()).unwrap();
+        assert_eq!(hash_block[1].as_ref(), block.hash());
+        assert_eq!(hash_block[2].len(), 4);
+    }
+
+    #[test]
+    fn test_bind_failure() {
+        let config = NotifyConfig { hash_tx: Some("tcp://256.0.0.1:1".to_string()), ..NotifyConfig::default() };
+        assert!(matches!(ZmqNotifier::start(&config), Err(NotifyError::Bind { .. })));
+        assert!(NotifyConfig::default().is_empty());
+    }
+}
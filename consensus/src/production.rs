@@ -0,0 +1,323 @@
+//! Block production policy in Tendermint mode
+//!
+//! Tendermint decides when a block is proposed: with `create_empty_blocks`
+//! off it waits for transactions in its mempool (or for the optional
+//! interval) before proposing, which the node configures in Tendermint's
+//! `config.toml`. The application decides what goes in the proposal:
+//! `prepare_proposal` runs the transactions through [`assemble_proposal`],
+//! which stops validating once the production budget is spent so a large
+//! mempool cannot stretch the block time, and leaves the remaining
+//! transactions in the mempool for the next block.
+//!
+//! Before assembly the proposer puts the transactions in the order of its
+//! [`OrderingPolicy`]. The policy is advisory: it is local configuration,
+//! `process_proposal` does not check it, and a proposer running other code
+//! can order its block any way it likes. It only describes how an honest
+//! validator fills its own proposals. Pure feerate order lets anyone place
+//! a transaction by picking its fee; feerate bands with arrival order
+//! inside a band narrow that. The shuffle seed is the previous block hash,
+//! which the previous proposer can grind and every submitter knows in
+//! advance (txids can be ground against it), so it is no defence against a
+//! determined orderer either.
+
+use sedly_core::{decode_transaction, BlockSpends, Transaction};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Default time spent validating the transactions of a proposal
+pub const DEFAULT_MAX_PRODUCTION_TIME: Duration = Duration::from_secs(5);
+
+/// Block production policy of a validator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProductionConfig {
+    /// Propose blocks without transactions
+    pub create_empty_blocks: bool,
+    /// With `create_empty_blocks` off, still propose an empty block after this long (zero waits forever)
+    pub empty_blocks_interval: Duration,
+    /// Time the proposer spends validating transactions before trimming the rest
+    pub max_production_time: Duration,
+    /// Order of the transactions in proposed blocks
+    pub ordering: OrderingPolicy,
+}
+
+impl Default for ProductionConfig {
+    fn default() -> Self {
+        Self {
+            create_empty_blocks: true,
+            empty_blocks_interval: Duration::ZERO,
+            max_production_time: DEFAULT_MAX_PRODUCTION_TIME,
+            ordering: OrderingPolicy::default(),
+        }
+    }
+}
+
+impl ProductionConfig {
+    /// Settings of the `[consensus]` section of Tendermint's `config.toml` enforcing the empty-block policy
+    pub fn tendermint_settings(&self) -> String {
+        format!(
+            "create_empty_blocks = {}\ncreate_empty_blocks_interval = \"{}s\"",
+            self.create_empty_blocks,
+            self.empty_blocks_interval.as_secs()
+        )
+    }
+}
+
+/// Order of the transactions in a proposed block
+///
+/// Applied by the proposer only; other validators accept any order (see
+/// the module documentation). Transactions unknown to the local mempool
+/// count as feerate 0, seen last; malformed ones go to the end and are
+/// dropped by the assembly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrderingPolicy {
+    /// Highest feerate first
+    #[default]
+    Feerate,
+    /// Feerate bands (powers of two) highest first, first seen first within a band
+    FirstSeen,
+    /// Shuffle seeded by the hash of the block being extended (a public, grindable seed)
+    HashShuffle,
+}
+
+impl OrderingPolicy {
+    /// All ordering policies
+    pub const ALL: [OrderingPolicy; 3] = [Self::Feerate, Self::FirstSeen, Self::HashShuffle];
+
+    /// Name of the policy (as accepted by `FromStr`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Feerate => "feerate",
+            Self::FirstSeen => "first-seen",
+            Self::HashShuffle => "hash-shuffle",
+        }
+    }
+
+    /// Put `txs` in proposal order on top of the block `previous_hash`
+    ///
+    /// `seen` returns the feerate (per 1000 bytes) and arrival time of a
+    /// transaction in the local mempool. Equal keys keep the txid order,
+    /// so the result does not depend on the order Tendermint passed.
+    pub fn order<T, F>(self, txs: Vec<T>, previous_hash: &[u8; 32], seen: F) -> Vec<T>
+    where
+        T: AsRef<[u8]>,
+        F: Fn(&[u8; 32]) -> Option<(u64, u64)>,
+    {
+        let mut keyed: Vec<_> = txs
+            .into_iter()
+            .map(|raw| {
+                let key = match decode_transaction(raw.as_ref()) {
+                    Ok(tx) => {
+                        let txid = tx.hash();
+                        let (feerate, received_at) = seen(&txid).unwrap_or((0, u64::MAX));
+                        match self {
+                            Self::Feerate => (false, u64::MAX - feerate, 0, txid),
+                            Self::FirstSeen => (false, feerate.leading_zeros() as u64, received_at, txid),
+                            Self::HashShuffle => {
+                                let seeded = Sha256::new().chain_update(previous_hash).chain_update(txid).finalize();
+                                (false, 0, 0, seeded.into())
+                            }
+                        }
+                    }
+                    Err(_) => (true, 0, 0, [0; 32]),
+                };
+                (key, raw)
+            })
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        keyed.into_iter().map(|(_, raw)| raw).collect()
+    }
+}
+
+impl FromStr for OrderingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| format!("Unknown ordering policy: {}", s))
+    }
+}
+
+/// Transactions picked for a proposal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proposal<T> {
+    /// Transactions to propose, in mempool order
+    pub txs: Vec<T>,
+    /// Transactions left out for being malformed, invalid or conflicting with an earlier one
+    pub invalid: usize,
+    /// Valid transactions left for a later block: over the size or script limits, or past the time budget
+    pub deferred: usize,
+}
+
+/// Pick the transactions of a proposal for the block at `height` from `txs`, in order
+///
+/// `check` validates a transaction against the chain, after the ones
+/// already in the proposal recorded in the given [`BlockSpends`], and
+/// returns its script cost. The proposal holds at most `max_bytes` of
+/// transactions and `max_script_cost` of scripts; once `budget` has
+/// elapsed the remaining transactions are deferred without being checked.
+pub fn assemble_proposal<T, F>(
+    txs: Vec<T>,
+    height: u64,
+    max_bytes: u64,
+    max_script_cost: u64,
+    budget: Duration,
+    mut check: F,
+) -> Proposal<T>
+where
+    T: AsRef<[u8]>,
+    F: FnMut(&Transaction, &BlockSpends) -> Result<u64, String>,
+{
+    let start = Instant::now();
+    let mut proposal = Proposal { txs: Vec::new(), invalid: 0, deferred: 0 };
+    let mut spends = BlockSpends::new();
+    let (mut size, mut script_cost) = (0u64, 0u64);
+    let count = txs.len();
+
+    for (index, raw) in txs.into_iter().enumerate() {
+        if start.elapsed() >= budget {
+            proposal.deferred += count - index;
+            break;
+        }
+        let tx_size = raw.as_ref().len() as u64;
+        if size + tx_size > max_bytes {
+            proposal.deferred += 1;
+            continue;
+        }
+        let tx = match decode_transaction(raw.as_ref()) {
+            Ok(tx) => tx,
+            Err(_) => {
+                proposal.invalid += 1;
+                continue;
+            }
+        };
+        if tx.inputs.iter().any(|input| spends.is_spent(&input.previous_output)) {
+            proposal.invalid += 1;
+            continue;
+        }
+        let cost = match check(&tx, &spends) {
+            Ok(cost) => cost,
+            Err(e) => {
+                log::debug!("Leaving {} out of the proposal: {}", hex::encode(tx.hash()), e);
+                proposal.invalid += 1;
+                continue;
+            }
+        };
+        if script_cost + cost > max_script_cost {
+            proposal.deferred += 1;
+            continue;
+        }
+        spends.record(&tx, tx.hash(), height);
+        size += tx_size;
+        script_cost += cost;
+        proposal.txs.push(raw);
+    }
+    proposal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{OutPoint, TxInput, TxOutput};
+
+    fn spend(txid: u8, vout: u32) -> Vec<u8> {
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([txid; 32], vout), vec![1; 64])],
+            vec![TxOutput::to_address(1_000, &[7; 20])],
+            0,
+        );
+        bincode::serialize(&tx).unwrap()
+    }
+
+    #[test]
+    fn test_tendermint_settings() {
+        let config = ProductionConfig {
+            create_empty_blocks: false,
+            empty_blocks_interval: Duration::from_secs(300),
+            ..ProductionConfig::default()
+        };
+        assert_eq!(config.tendermint_settings(), "create_empty_blocks = false\ncreate_empty_blocks_interval = \"300s\"");
+    }
+
+    #[test]
+    fn test_ordering_policy() {
+        let txs = vec![spend(1, 0), b"garbage".to_vec(), spend(2, 0), spend(3, 0), spend(4, 0)];
+        let txid = |raw: &Vec<u8>| decode_transaction(raw).unwrap().hash();
+        // Feerate and arrival in the local mempool; the fourth spend is not in the mempool
+        let seen = |id: &[u8; 32]| {
+            [(&txs[0], (1_500, 30)), (&txs[2], (1_100, 20)), (&txs[3], (5_000, 10))]
+                .into_iter()
+                .find(|(raw, _)| txid(raw) == *id)
+                .map(|(_, seen)| seen)
+        };
+        let order = |policy: OrderingPolicy, seed: u8| -> Vec<Vec<u8>> { policy.order(txs.clone(), &[seed; 32], seen) };
+
+        let expected = vec![txs[3].clone(), txs[0].clone(), txs[2].clone(), txs[4].clone(), txs[1].clone()];
+        assert_eq!(order(OrderingPolicy::Feerate, 0), expected);
+        // 1_500 and 1_100 fall in the same band: the earlier arrival wins
+        let expected = vec![txs[3].clone(), txs[2].clone(), txs[0].clone(), txs[4].clone(), txs[1].clone()];
+        assert_eq!(order(OrderingPolicy::FirstSeen, 0), expected);
+
+        // The shuffle depends on the seed only, not on the input order
+        let shuffled = order(OrderingPolicy::HashShuffle, 1);
+        let mut reversed = txs.clone();
+        reversed.reverse();
+        assert_eq!(OrderingPolicy::HashShuffle.order(reversed, &[1; 32], seen), shuffled);
+        assert_ne!(order(OrderingPolicy::HashShuffle, 2), shuffled);
+        assert_eq!(shuffled.last(), Some(&txs[1]));
+
+        for policy in OrderingPolicy::ALL {
+            assert_eq!(policy.name().parse::<OrderingPolicy>(), Ok(policy));
+        }
+        assert!("random".parse::<OrderingPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_assemble_proposal() {
+        let txs = vec![spend(1, 0), b"garbage".to_vec(), spend(1, 0), spend(2, 0), spend(3, 0)];
+        let all = assemble_proposal(txs.clone(), 1, u64::MAX, u64::MAX, Duration::MAX, |_, _| Ok(10));
+        // The duplicate of the first spend and the undecodable bytes are left out
+        assert_eq!(all.txs, vec![txs[0].clone(), txs[3].clone(), txs[4].clone()]);
+        assert_eq!((all.invalid, all.deferred), (2, 0));
+
+        // Script and size limits defer transactions to the next block
+        let by_cost = assemble_proposal(txs.clone(), 1, u64::MAX, 25, Duration::MAX, |_, _| Ok(10));
+        assert_eq!((by_cost.txs.len(), by_cost.deferred), (2, 1));
+        let by_size = assemble_proposal(txs.clone(), 1, txs[0].len() as u64, u64::MAX, Duration::MAX, |_, _| Ok(10));
+        assert_eq!((by_size.txs.len(), by_size.deferred), (1, 4));
+
+        let rejected = assemble_proposal(txs.clone(), 1, u64::MAX, u64::MAX, Duration::MAX, |tx, _| {
+            if tx.inputs[0].previous_output.txid == [2; 32] { Err("missing input".to_string()) } else { Ok(10) }
+        });
+        assert_eq!((rejected.txs.len(), rejected.invalid), (2, 3));
+
+        // Once the budget is spent the remaining transactions are not even checked
+        let mut checked = 0;
+        let timed_out = assemble_proposal(txs.clone(), 1, u64::MAX, u64::MAX, Duration::from_millis(20), |_, _| {
+            checked += 1;
+            std::thread::sleep(Duration::from_millis(30));
+            Ok(10)
+        });
+        assert_eq!((timed_out.txs.len(), timed_out.deferred, checked), (1, 4, 1));
+
+        // Each check sees the spends and outputs of the transactions accepted before it
+        let parent = decode_transaction(&txs[0]).unwrap().hash();
+        let child = bincode::serialize(&Transaction::new(
+            vec![TxInput::new(OutPoint::new(parent, 0), vec![1; 64])],
+            vec![TxOutput::to_address(500, &[7; 20])],
+            0,
+        )).unwrap();
+        let proposed = vec![txs[0].clone(), child, txs[3].clone()];
+        let mut seen = Vec::new();
+        let chained = assemble_proposal(proposed, 1, u64::MAX, u64::MAX, Duration::MAX, |tx, spends| {
+            let created = spends.created(&OutPoint::new(parent, 0)).is_some();
+            seen.push((spends.is_spent(&OutPoint::new([1; 32], 0)), created));
+            if tx.inputs[0].previous_output.txid == [2; 32] { Err("missing input".to_string()) } else { Ok(10) }
+        });
+        assert_eq!(chained.txs.len(), 2);
+        assert_eq!(seen, vec![(false, false), (true, true), (true, true)]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Tendermint block pruning via `ResponseCommit::retain_height`
+//!
+//! Every block is also stored in the application database, so Tendermint
+//! only needs its own copy for crash replay, peers in block sync and nodes
+//! restoring from a snapshot. The policy below bounds the consensus-side
+//! block store accordingly.
+
+use serde::{Deserialize, Serialize};
+
+/// Fewest Tendermint blocks kept when pruning is enabled
+///
+/// The handshake may need to replay the last committed block after a crash.
+pub const MIN_RETAIN_BLOCKS: u64 = 2;
+
+/// Block retention policy for the Tendermint block store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetainConfig {
+    /// Number of recent blocks to keep (0 keeps every block)
+    pub min_retain_blocks: u64,
+    /// Application snapshot interval in blocks (0 if snapshots are disabled);
+    /// blocks since the latest snapshot are kept so it can be restored and caught up
+    pub snapshot_interval: u64,
+}
+
+impl RetainConfig {
+    /// Keep the last `min_retain_blocks` blocks
+    pub fn new(min_retain_blocks: u64) -> Self {
+        Self {
+            min_retain_blocks,
+            snapshot_interval: 0,
+        }
+    }
+
+    /// Keep blocks since the latest snapshot taken every `interval` blocks
+    pub fn with_snapshot_interval(mut self, interval: u64) -> Self {
+        self.snapshot_interval = interval;
+        self
+    }
+
+    /// Whether Tendermint is allowed to prune blocks at all
+    pub fn is_pruning(&self) -> bool {
+        self.min_retain_blocks > 0
+    }
+
+    /// Height below which Tendermint may prune blocks after committing `height`
+    ///
+    /// Returns 0 (retain everything) when pruning is disabled or nothing can be pruned yet.
+    pub fn retain_height(&self, height: u64) -> u64 {
+        if !self.is_pruning() {
+            return 0;
+        }
+
+        let keep = self.min_retain_blocks.max(MIN_RETAIN_BLOCKS);
+        let mut retain = (height + 1).saturating_sub(keep);
+
+        if self.snapshot_interval > 0 {
+            let last_snapshot = height - height % self.snapshot_interval;
+            retain = retain.min(last_snapshot);
+        }
+
+        if retain <= 1 {
+            0
+        } else {
+            retain
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retain_height() {
+        assert_eq!(RetainConfig::default().retain_height(10_000), 0);
+
+        let config = RetainConfig::new(100);
+        assert_eq!(config.retain_height(50), 0);
+        assert_eq!(config.retain_height(100), 0);
+        assert_eq!(config.retain_height(1_000), 901);
+
+        // Never below the blocks needed for crash replay
+        assert_eq!(RetainConfig::new(1).retain_height(1_000), 999);
+
+        // Blocks since the latest snapshot are kept
+        let config = RetainConfig::new(100).with_snapshot_interval(500);
+        assert_eq!(config.retain_height(1_050), 951);
+        assert_eq!(config.retain_height(1_400), 1_000);
+    }
+}
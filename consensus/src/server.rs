@@ -1,6 +1,11 @@
 //! Tendermint ABCI Server for Sedly
 
 use crate::abci::{SedlyApp, ConsensusError};
+use crate::notify::{NotifyConfig, ZmqNotifier};
+use crate::webhook::{WebhookConfig, WebhookNotifier};
+use crate::production::ProductionConfig;
+use crate::pruning::RetainConfig;
+use sedly_core::{Block, ChainParams};
 use tendermint_abci::{Application, Server, ServerBuilder};
 use tokio::net::TcpListener;
 use std::sync::Arc;
@@ -14,6 +19,20 @@ pub struct ServerConfig {
     pub db_path: String,
     /// Maximum number of connections
     pub max_connections: usize,
+    /// Tendermint block retention policy
+    pub retain: RetainConfig,
+    /// Empty-block policy and time budget of proposed blocks
+    pub production: ProductionConfig,
+    /// Check the money supply invariant every N blocks (0 disables)
+    pub audit_supply_interval: u64,
+    /// ZeroMQ notification endpoints (all disabled by default)
+    pub notify: NotifyConfig,
+    /// HTTP webhook targets (none by default)
+    pub webhooks: Vec<WebhookConfig>,
+    /// Consensus-rule rejections kept for debugging forks (0 disables)
+    pub rejection_log: usize,
+    /// Bytes of evicted mempool transactions kept on disk (0 disables)
+    pub mempool_spill: usize,
 }
 
 impl Default for ServerConfig {
@@ -22,6 +41,13 @@ impl Default for ServerConfig {
             abci_addr: "127.0.0.1:26658".to_string(),
             db_path: "./blockchain_data".to_string(),
             max_connections: 100,
+            retain: RetainConfig::default(),
+            production: ProductionConfig::default(),
+            audit_supply_interval: 0,
+            notify: NotifyConfig::default(),
+            webhooks: Vec::new(),
+            rejection_log: 0,
+            mempool_spill: 0,
         }
     }
 }
@@ -37,7 +63,33 @@ pub struct ConsensusServer {
 impl ConsensusServer {
     /// Create new consensus server
     pub fn new(config: ServerConfig) -> Result<Self, ConsensusError> {
-        let app = Arc::new(SedlyApp::new(&config.db_path)?);
+        Self::with_params(config, ChainParams::mainnet())
+    }
+
+    /// Create new consensus server for the given network parameters
+    pub fn with_params(config: ServerConfig, params: ChainParams) -> Result<Self, ConsensusError> {
+        Self::with_genesis(config, params, &Block::genesis())
+    }
+
+    /// Create new consensus server for a network with a custom genesis block
+    pub fn with_genesis(config: ServerConfig, params: ChainParams, genesis: &Block) -> Result<Self, ConsensusError> {
+        let mut app = SedlyApp::with_genesis(&config.db_path, params, genesis)?
+            .with_retain_config(config.retain)
+            .with_production_config(config.production)
+            .with_supply_audit(config.audit_supply_interval)
+            .with_rejection_log(config.rejection_log)
+            .with_mempool_spillover(config.mempool_spill)?;
+        if !config.notify.is_empty() {
+            let notifier = ZmqNotifier::start(&config.notify)
+                .map_err(|e| ConsensusError::ConsensusError(e.to_string()))?;
+            app = app.with_notifier(notifier);
+        }
+        if !config.webhooks.is_empty() {
+            let webhooks = WebhookNotifier::start(config.webhooks.clone())
+                .map_err(|e| ConsensusError::ConsensusError(e.to_string()))?;
+            app = app.with_webhooks(webhooks);
+        }
+        let app = Arc::new(app);
 
         Ok(Self {
             config,
@@ -47,7 +99,13 @@ impl ConsensusServer {
 
     /// Start the ABCI server
     pub async fn start(&self) -> Result<(), ConsensusError> {
-        log::info!("Starting Sedly consensus server on {}", self.config.abci_addr);
+        let tip = self.app.db().get_metadata_async()
+            .await
+            .map_err(|e| ConsensusError::DatabaseError(e.to_string()))?;
+        log::info!(
+            "Starting Sedly consensus server on {} at height {} ({})",
+            self.config.abci_addr, tip.height, hex::encode(tip.best_block_hash),
+        );
 
         // Create TCP listener
         let listener = TcpListener::bind(&self.config.abci_addr)
@@ -55,6 +113,13 @@ impl ConsensusServer {
             .map_err(|e| ConsensusError::ConsensusError(format!("Failed to bind ABCI server: {}", e)))?;
 
         log::info!("ABCI server listening on {}", self.config.abci_addr);
+        let production = &self.config.production;
+        if !production.create_empty_blocks || !production.empty_blocks_interval.is_zero() {
+            log::info!(
+                "The block production policy needs these [consensus] settings in Tendermint's config.toml:\n{}",
+                production.tendermint_settings()
+            );
+        }
 
         // Create server with our application
         let server = ServerBuilder::default()
@@ -112,6 +177,48 @@ impl ConsensusServerBuilder {
         self
     }
 
+    /// Set how many recent blocks Tendermint keeps (0 keeps all)
+    pub fn retain_blocks(mut self, blocks: u64) -> Self {
+        self.config.retain.min_retain_blocks = blocks;
+        self
+    }
+
+    /// Set the snapshot interval the retention policy must honor
+    pub fn snapshot_interval(mut self, interval: u64) -> Self {
+        self.config.retain.snapshot_interval = interval;
+        self
+    }
+
+    /// Set the empty-block policy and time budget of proposed blocks
+    pub fn production(mut self, production: ProductionConfig) -> Self {
+        self.config.production = production;
+        self
+    }
+
+    /// Check the money supply invariant every `interval` blocks
+    pub fn audit_supply_interval(mut self, interval: u64) -> Self {
+        self.config.audit_supply_interval = interval;
+        self
+    }
+
+    /// Keep up to `max_size` bytes of evicted mempool transactions on disk (0 disables)
+    pub fn mempool_spill(mut self, max_size: usize) -> Self {
+        self.config.mempool_spill = max_size;
+        self
+    }
+
+    /// Set the ZeroMQ notification endpoints
+    pub fn notify(mut self, notify: NotifyConfig) -> Self {
+        self.config.notify = notify;
+        self
+    }
+
+    /// Add an HTTP webhook target
+    pub fn webhook(mut self, webhook: WebhookConfig) -> Self {
+        self.config.webhooks.push(webhook);
+        self
+    }
+
     /// Build the consensus server
     pub fn build(self) -> Result<ConsensusServer, ConsensusError> {
         ConsensusServer::new(self.config)
@@ -126,16 +233,18 @@ impl Default for ConsensusServerBuilder {
 
 /// Start a basic consensus server with default configuration
 pub async fn start_server(db_path: &str) -> Result<(), ConsensusError> {
-    let server = ConsensusServerBuilder::new()
-        .db_path(db_path)
-        .build()?;
-
-    server.start().await
+    let config = ServerConfig { db_path: db_path.to_string(), ..ServerConfig::default() };
+    start_server_with_config(config).await
 }
 
 /// Start consensus server with custom configuration
+///
+/// Opening the database loads the header index and the mempool, so the
+/// server is built on the blocking thread pool.
 pub async fn start_server_with_config(config: ServerConfig) -> Result<(), ConsensusError> {
-    let server = ConsensusServer::new(config)?;
+    let server = tokio::task::spawn_blocking(move || ConsensusServer::new(config))
+        .await
+        .map_err(|e| ConsensusError::ConsensusError(e.to_string()))??;
     server.start().await
 }
 
@@ -150,6 +259,13 @@ mod tests {
             abci_addr: "127.0.0.1:9999".to_string(),
             db_path: "/tmp/test".to_string(),
             max_connections: 50,
+            retain: RetainConfig::default(),
+            production: ProductionConfig::default(),
+            audit_supply_interval: 0,
+            notify: NotifyConfig::default(),
+            webhooks: Vec::new(),
+            rejection_log: 0,
+            mempool_spill: 0,
         };
 
         assert_eq!(config.abci_addr, "127.0.0.1:9999");
@@ -165,11 +281,13 @@ mod tests {
             .abci_addr("127.0.0.1:8888")
             .db_path(temp_dir.path().to_str().unwrap())
             .max_connections(25)
+            .retain_blocks(1000)
             .build()
             .unwrap();
 
         assert_eq!(server.config().abci_addr, "127.0.0.1:8888");
         assert_eq!(server.config().max_connections, 25);
+        assert_eq!(server.config().retain, RetainConfig::new(1000));
     }
 
     #[test]
@@ -179,6 +297,13 @@ mod tests {
             abci_addr: "127.0.0.1:26658".to_string(),
             db_path: temp_dir.path().to_str().unwrap().to_string(),
             max_connections: 100,
+            retain: RetainConfig::default(),
+            production: ProductionConfig::default(),
+            audit_supply_interval: 0,
+            notify: NotifyConfig::default(),
+            webhooks: Vec::new(),
+            rejection_log: 0,
+            mempool_spill: 0,
         };
 
         let server = ConsensusServer::new(config);
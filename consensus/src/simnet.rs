@@ -0,0 +1,474 @@
+//! In-process multi-node network driven by a simulated consensus engine
+//!
+//! Several [`SedlyApp`] instances, each with its own data directory, are
+//! driven through the same ABCI calls Tendermint would make: transactions
+//! are gossiped to every mempool with `CheckTx`, then each block is
+//! delivered to every running node in the same order and committed. After
+//! each block the app hashes returned by `Commit` must agree.
+//!
+//! Faults are injected between blocks: a node can be crashed (and later
+//! restarted, replaying the blocks it missed as Tendermint's handshake
+//! would), crashed in the middle of a block before `Commit`, or handed a
+//! different block than the rest of the network. A node whose state no
+//! longer matches the network halts on its next block, like a real node.
+
+use crate::abci::SedlyApp;
+use sedly_core::{Block, ChainParams};
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use tendermint_abci::{
+    Application, RequestBeginBlock, RequestCheckTx, RequestCommit, RequestDeliverTx, RequestEndBlock, RequestInfo,
+};
+
+/// Block agreed by the network
+#[derive(Debug, Clone)]
+struct SimBlock {
+    /// Consensus time of the block
+    time: u64,
+    /// Transactions in delivery order
+    txs: Vec<Vec<u8>>,
+    /// App hash returned by the nodes that committed it
+    app_hash: [u8; 32],
+}
+
+/// Node of the simulated network
+struct SimNode {
+    /// Data directory, kept across restarts
+    dir: PathBuf,
+    /// Running application, None while crashed or halted
+    app: Option<SedlyApp>,
+    /// Why the node stopped on a mismatched block, if it did
+    halted: Option<String>,
+    /// Crash after `EndBlock` of the next block, before `Commit`
+    crash_before_commit: bool,
+    /// Transactions delivered to this node instead of the network's next block
+    fork: Option<Vec<Vec<u8>>>,
+}
+
+/// Network of in-process applications
+pub struct SimNetwork {
+    nodes: Vec<SimNode>,
+    params: ChainParams,
+    /// Genesis block of every node
+    genesis: Block,
+    /// App hash of the genesis state
+    genesis_hash: [u8; 32],
+    /// Consensus time of the genesis block
+    genesis_time: u64,
+    /// Blocks committed so far, from height 1
+    blocks: Vec<SimBlock>,
+    /// Transactions accepted by a mempool and waiting for the next block
+    pending: Vec<Vec<u8>>,
+}
+
+impl SimNetwork {
+    /// Start `nodes` applications with data directories under `root`
+    pub fn new(root: &Path, nodes: usize, params: ChainParams) -> Result<Self, SimError> {
+        Self::with_genesis(root, nodes, params, Block::genesis())
+    }
+
+    /// Like [`SimNetwork::new`], with every node starting from `genesis`
+    pub fn with_genesis(root: &Path, nodes: usize, params: ChainParams, genesis: Block) -> Result<Self, SimError> {
+        let mut network = Self {
+            nodes: Vec::with_capacity(nodes),
+            params,
+            genesis,
+            genesis_hash: [0; 32],
+            genesis_time: 0,
+            blocks: Vec::new(),
+            pending: Vec::new(),
+        };
+        for index in 0..nodes {
+            let dir = root.join(format!("node{}", index));
+            let app = network.open(&dir)?;
+            network.nodes.push(SimNode {
+                dir,
+                app: Some(app),
+                halted: None,
+                crash_before_commit: false,
+                fork: None,
+            });
+        }
+
+        let app = network.nodes.first().and_then(|node| node.app.as_ref()).ok_or(SimError::NoNodes)?;
+        let genesis = app.db().get_block_by_height(0)
+            .map_err(|e| SimError::Node { node: 0, message: e.to_string() })?
+            .ok_or_else(|| SimError::Node { node: 0, message: "Genesis block missing".to_string() })?;
+        network.genesis_hash = genesis.hash();
+        network.genesis_time = genesis.header.timestamp;
+        Ok(network)
+    }
+
+    fn open(&self, dir: &Path) -> Result<SedlyApp, SimError> {
+        let path = dir.to_str().ok_or_else(|| SimError::Open(format!("Non UTF-8 path {}", dir.display())))?;
+        SedlyApp::with_genesis(path, self.params.clone(), &self.genesis).map_err(|e| SimError::Open(e.to_string()))
+    }
+
+    /// Number of nodes, running or not
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the network has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Height of the last block committed by the network
+    pub fn height(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// App hash of the last block committed by the network
+    pub fn app_hash(&self) -> [u8; 32] {
+        self.blocks.last().map_or(self.genesis_hash, |block| block.app_hash)
+    }
+
+    /// Running application of a node
+    pub fn app(&self, node: usize) -> Option<&SedlyApp> {
+        self.nodes.get(node).and_then(|node| node.app.as_ref())
+    }
+
+    /// Why a node halted, None if it did not
+    pub fn halted(&self, node: usize) -> Option<&str> {
+        self.nodes.get(node).and_then(|node| node.halted.as_deref())
+    }
+
+    /// Gossip a transaction to every running mempool
+    ///
+    /// The transaction is queued for the next block if at least one node
+    /// accepted it. Returns how many nodes accepted it.
+    pub fn submit(&mut self, tx: Vec<u8>) -> usize {
+        let accepted = self.nodes
+            .iter()
+            .filter_map(|node| node.app.as_ref())
+            .filter(|app| app.check_tx(RequestCheckTx { tx: tx.clone().into(), ..Default::default() }).code.is_ok())
+            .count();
+        if accepted > 0 {
+            self.pending.push(tx);
+        }
+        accepted
+    }
+
+    /// Stop a node as if its process was killed between blocks
+    pub fn crash(&mut self, node: usize) {
+        self.nodes[node].app = None;
+    }
+
+    /// Stop a node during the next block, after `EndBlock` and before `Commit`
+    pub fn crash_before_commit(&mut self, node: usize) {
+        self.nodes[node].crash_before_commit = true;
+    }
+
+    /// Deliver `txs` to a node instead of the network's next block
+    pub fn fork(&mut self, node: usize, txs: Vec<Vec<u8>>) {
+        self.nodes[node].fork = Some(txs);
+    }
+
+    /// Restart a stopped node and replay the blocks it has not committed
+    pub fn restart(&mut self, node: usize) -> Result<(), SimError> {
+        let app = self.open(&self.nodes[node].dir)?;
+        let info = app.info(RequestInfo {
+            version: String::new(),
+            block_version: 0,
+            p2p_version: 0,
+            abci_version: String::new(),
+        });
+        self.nodes[node].halted = None;
+        self.nodes[node].app = Some(app);
+
+        for height in info.last_block_height as u64 + 1..=self.height() {
+            let block = self.blocks[height as usize - 1].clone();
+            let app_hash = self.run_block(node, height, block.time, &block.txs)?;
+            if app_hash != block.app_hash {
+                return Err(SimError::Diverged { height, app_hashes: vec![(node, app_hash)] });
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver and commit the next block on every running node
+    ///
+    /// The pending transactions are delivered in submission order. The app
+    /// hash of the block is the one most nodes committed: if any node
+    /// disagrees the block is still recorded, and the error lists each
+    /// node's hash.
+    pub fn produce_block(&mut self) -> Result<[u8; 32], SimError> {
+        let height = self.height() + 1;
+        let time = self.genesis_time + height * self.params.target_block_time;
+        let txs = std::mem::take(&mut self.pending);
+
+        let mut app_hashes = Vec::new();
+        for node in 0..self.nodes.len() {
+            if self.nodes[node].app.is_none() {
+                continue;
+            }
+            let node_txs = self.nodes[node].fork.take().unwrap_or_else(|| txs.clone());
+            match self.run_block(node, height, time, &node_txs) {
+                Ok(app_hash) => app_hashes.push((node, app_hash)),
+                Err(SimError::Crashed { .. }) => {}
+                Err(SimError::Halted { node, reason }) => self.nodes[node].halted = Some(reason),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut votes: HashMap<[u8; 32], usize> = HashMap::new();
+        for (_, app_hash) in &app_hashes {
+            *votes.entry(*app_hash).or_default() += 1;
+        }
+        let app_hash = votes.iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(app_hash, _)| *app_hash)
+            .ok_or(SimError::NoNodes)?;
+        self.blocks.push(SimBlock { time, txs, app_hash });
+
+        if votes.len() > 1 {
+            return Err(SimError::Diverged { height, app_hashes });
+        }
+        Ok(app_hash)
+    }
+
+    /// Produce `count` blocks, stopping at the first divergence
+    pub fn produce_blocks(&mut self, count: u64) -> Result<[u8; 32], SimError> {
+        let mut app_hash = self.app_hash();
+        for _ in 0..count {
+            app_hash = self.produce_block()?;
+        }
+        Ok(app_hash)
+    }
+
+    /// Check that every running node reports the network's height and app hash
+    pub fn assert_converged(&self) -> Result<(), SimError> {
+        let mut app_hashes = Vec::new();
+        for (node, app) in self.nodes.iter().enumerate().filter_map(|(index, node)| Some((index, node.app.as_ref()?))) {
+            let info = app.info(RequestInfo {
+                version: String::new(),
+                block_version: 0,
+                p2p_version: 0,
+                abci_version: String::new(),
+            });
+            let app_hash: [u8; 32] = info.last_block_app_hash.as_ref().try_into().unwrap_or([0; 32]);
+            if info.last_block_height as u64 != self.height() {
+                return Err(SimError::Behind { node, height: info.last_block_height as u64, network: self.height() });
+            }
+            app_hashes.push((node, app_hash));
+        }
+        if app_hashes.iter().any(|(_, app_hash)| *app_hash != self.app_hash()) {
+            return Err(SimError::Diverged { height: self.height(), app_hashes });
+        }
+        Ok(())
+    }
+
+    /// Run one block on a node: BeginBlock, DeliverTx for each transaction,
+    /// EndBlock and Commit
+    ///
+    /// A panic inside the application halts the node, as it would stop a
+    /// real one; the node stays down until restarted.
+    fn run_block(&mut self, node: usize, height: u64, time: u64, txs: &[Vec<u8>]) -> Result<[u8; 32], SimError> {
+        let previous_app_hash = (height as usize)
+            .checked_sub(2)
+            .and_then(|index| self.blocks.get(index))
+            .map_or(self.genesis_hash, |block| block.app_hash);
+        let crash_before_commit = std::mem::take(&mut self.nodes[node].crash_before_commit);
+        let app = self.nodes[node].app.as_ref().expect("Node is running");
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            app.begin_block(begin_block_request(height, time, &previous_app_hash));
+            for tx in txs {
+                app.deliver_tx(RequestDeliverTx { tx: tx.clone().into() });
+            }
+            app.end_block(RequestEndBlock { height: height as i64 });
+            if crash_before_commit {
+                return None;
+            }
+            Some(app.commit(RequestCommit {}))
+        }));
+
+        match result {
+            Ok(Some(response)) => response.data.as_ref().try_into().map_err(|_| SimError::Node {
+                node,
+                message: format!("Commit of block {} returned no app hash", height),
+            }),
+            Ok(None) => {
+                self.nodes[node].app = None;
+                Err(SimError::Crashed { node, height })
+            }
+            Err(panic) => {
+                self.nodes[node].app = None;
+                let reason = panic.downcast_ref::<String>().cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|reason| reason.to_string()))
+                    .unwrap_or_else(|| "Application panicked".to_string());
+                Err(SimError::Halted { node, reason })
+            }
+        }
+    }
+}
+
+/// `BeginBlock` of the block at `height` extending the state `app_hash`
+fn begin_block_request(height: u64, time: u64, app_hash: &[u8; 32]) -> RequestBeginBlock {
+    let mut request = RequestBeginBlock::default();
+    request.header.height = height.try_into().expect("Height fits in a block header");
+    request.header.time.seconds = time as i64;
+    request.header.app_hash = app_hash.to_vec().into();
+    request
+}
+
+/// Simulated network errors
+#[derive(Debug, thiserror::Error)]
+pub enum SimError {
+    #[error("Network has no running node")]
+    NoNodes,
+
+    #[error("Failed to open node: {0}")]
+    Open(String),
+
+    #[error("Node {node}: {message}")]
+    Node { node: usize, message: String },
+
+    #[error("Node {node} crashed during block {height}")]
+    Crashed { node: usize, height: u64 },
+
+    #[error("Node {node} halted: {reason}")]
+    Halted { node: usize, reason: String },
+
+    #[error("Node {node} is at height {height}, the network at {network}")]
+    Behind { node: usize, height: u64, network: u64 },
+
+    #[error("App hashes diverged at height {height}: {}", format_app_hashes(app_hashes))]
+    Diverged { height: u64, app_hashes: Vec<(usize, [u8; 32])> },
+}
+
+fn format_app_hashes(app_hashes: &[(usize, [u8; 32])]) -> String {
+    app_hashes.iter()
+        .map(|(node, app_hash)| format!("node {} {}", node, hex::encode(app_hash)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{
+        anyone_can_spend, FaultConfig, FaultInjector, OutPoint, Transaction, TxInput, TxOutput, MIN_TX_FEE,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Network one block past a genesis allocation anyone can spend, and a transaction spending it
+    fn mature_network(nodes: usize) -> (SimNetwork, Vec<u8>, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let allocation = TxOutput::to_address(1_000_000, &anyone_can_spend(b"faucet"));
+        let genesis = Block::genesis_with_allocations(vec![allocation.clone()]);
+        let params = ChainParams::regtest().with_mature_genesis_allocations();
+        let mut network = SimNetwork::with_genesis(temp_dir.path(), nodes, params, genesis.clone()).unwrap();
+        network.produce_block().unwrap();
+
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new(genesis.transactions[0].hash(), 0), vec![])],
+            vec![TxOutput::to_address(allocation.value.to_sat() - MIN_TX_FEE, b"alice")],
+            0,
+        );
+        (network, bincode::serialize(&tx).unwrap(), temp_dir)
+    }
+
+    #[test]
+    fn test_nodes_converge() {
+        let (mut network, tx, _temp) = mature_network(3);
+        network.assert_converged().unwrap();
+
+        assert_eq!(network.submit(tx.clone()), 3);
+        network.produce_block().unwrap();
+        network.assert_converged().unwrap();
+        for node in 0..network.len() {
+            assert_eq!(network.app(node).unwrap().mempool_size(), 0);
+        }
+
+        // Already spent: no mempool accepts it
+        assert_eq!(network.submit(tx), 0);
+    }
+
+    #[test]
+    fn test_crashed_nodes_catch_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut network = SimNetwork::new(temp_dir.path(), 3, ChainParams::regtest()).unwrap();
+        network.produce_blocks(2).unwrap();
+
+        network.crash(1);
+        network.crash_before_commit(2);
+        network.produce_blocks(3).unwrap();
+        assert!(network.app(1).is_none() && network.app(2).is_none());
+        network.assert_converged().unwrap();
+
+        // On restart the missing blocks are replayed, including the one interrupted before its commit
+        network.restart(1).unwrap();
+        network.restart(2).unwrap();
+        network.assert_converged().unwrap();
+        network.produce_block().unwrap();
+        network.assert_converged().unwrap();
+    }
+
+    #[test]
+    fn test_forked_node_diverges_and_halts() {
+        let (mut network, tx, _temp) = mature_network(3);
+        network.submit(tx);
+        network.fork(2, Vec::new());
+
+        let Err(SimError::Diverged { height, app_hashes }) = network.produce_block() else {
+            panic!("Fork was not detected");
+        };
+        assert_eq!(height, 2);
+        assert_eq!(app_hashes[0].1, app_hashes[1].1);
+        assert_ne!(app_hashes[0].1, app_hashes[2].1);
+        assert_eq!(network.app_hash(), app_hashes[0].1);
+
+        // The next block does not extend its state: the node stops
+        network.produce_block().unwrap();
+        assert!(network.halted(2).unwrap().contains("irreconcilable"));
+        assert!(network.app(2).is_none());
+        network.assert_converged().unwrap();
+    }
+
+    #[test]
+    fn test_transient_storage_faults_are_retried() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut network = SimNetwork::new(temp_dir.path(), 3, ChainParams::regtest()).unwrap();
+        network.produce_blocks(2).unwrap();
+
+        // A failed write, one applied but reported as failed, slow reads
+        let failing = Arc::new(FaultInjector::new(FaultConfig::new(1).with_write_errors(1.0).with_max_faults(1)));
+        let partial = Arc::new(FaultInjector::new(FaultConfig::new(2).with_partial_writes(1.0).with_max_faults(1)));
+        let slow = Arc::new(FaultInjector::new(FaultConfig::new(3).with_slow_reads(0.5, Duration::from_millis(1))));
+        network.app(0).unwrap().db().inject_faults(Some(failing.clone()));
+        network.app(1).unwrap().db().inject_faults(Some(partial.clone()));
+        network.app(2).unwrap().db().inject_faults(Some(slow.clone()));
+
+        network.produce_blocks(3).unwrap();
+        network.assert_converged().unwrap();
+        assert_eq!(failing.stats().write_errors, 1);
+        assert_eq!(partial.stats().partial_writes, 1);
+        assert!(slow.stats().slow_reads > 0);
+    }
+
+    #[test]
+    fn test_persistent_storage_failure_halts_node() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut network = SimNetwork::new(temp_dir.path(), 3, ChainParams::regtest()).unwrap();
+        network.produce_blocks(2).unwrap();
+
+        let broken = Arc::new(FaultInjector::new(FaultConfig::new(1).with_write_errors(1.0)));
+        network.app(2).unwrap().db().inject_faults(Some(broken.clone()));
+
+        // The node stops instead of answering the commit without the block
+        network.produce_block().unwrap();
+        assert!(network.halted(2).unwrap().contains("failed to commit block 3"));
+        assert_eq!(broken.stats().write_errors, 3);
+        network.produce_block().unwrap();
+        network.assert_converged().unwrap();
+
+        // With the storage repaired the restart recovers the missing blocks
+        network.restart(2).unwrap();
+        network.assert_converged().unwrap();
+    }
+}
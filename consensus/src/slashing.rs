@@ -0,0 +1,141 @@
+//! Slashing of validators reported for misbehavior in BeginBlock evidence
+
+use crate::state::{EvidenceKind, EvidenceRecord, StateError, StateManager};
+use serde::{Deserialize, Serialize};
+
+/// Basis points in one unit (100%)
+const BPS: i64 = 10_000;
+
+/// Penalties applied to misbehaving validators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashingParams {
+    /// Fraction of voting power removed, in basis points
+    pub slash_fraction_bps: u32,
+    /// Remove the validator from the active set after slashing
+    pub jail: bool,
+}
+
+impl Default for SlashingParams {
+    fn default() -> Self {
+        Self {
+            slash_fraction_bps: 500, // 5%
+            jail: true,
+        }
+    }
+}
+
+/// Misbehavior reported by Tendermint, decoupled from the ABCI types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evidence {
+    /// Tendermint address of the offending validator
+    pub validator_address: [u8; 20],
+    /// Kind of misbehavior
+    pub kind: EvidenceKind,
+    /// Height at which the misbehavior happened
+    pub height: u64,
+}
+
+/// Apply the penalty for `evidence` reported in the block at `current_height`
+///
+/// Evidence already processed and evidence against validators that are not
+/// in the set are ignored, so replaying a block after a crash is harmless.
+pub fn process_evidence(
+    state: &StateManager,
+    params: &SlashingParams,
+    evidence: &Evidence,
+    current_height: u64,
+) -> Result<Option<EvidenceRecord>, StateError> {
+    let validator_id = hex::encode(evidence.validator_address);
+    let mut record = None;
+
+    state.update_state(|state| {
+        let already_processed = state.evidence.iter().any(|processed| {
+            processed.validator_id == validator_id
+                && processed.kind == evidence.kind
+                && processed.evidence_height == evidence.height
+        });
+        if already_processed {
+            return Ok(());
+        }
+
+        let Some(validator) = state.validators.get_mut(&validator_id) else {
+            log::warn!("Evidence against unknown validator {}", validator_id);
+            return Ok(());
+        };
+
+        let power_before = validator.power;
+        let slashed = power_before.saturating_mul(params.slash_fraction_bps as i64) / BPS;
+        validator.power = power_before - slashed;
+        if params.jail {
+            validator.active = false;
+        }
+
+        let processed = EvidenceRecord {
+            validator_id: validator_id.clone(),
+            kind: evidence.kind,
+            evidence_height: evidence.height,
+            processed_height: current_height,
+            power_before,
+            power_after: validator.power,
+            jailed: !validator.active,
+        };
+        state.evidence.push(processed.clone());
+        record = Some(processed);
+        Ok(())
+    })?;
+
+    if let Some(record) = &record {
+        log::warn!(
+            "Slashed validator {} for {:?} at height {}: power {} -> {}{}",
+            record.validator_id,
+            record.kind,
+            record.evidence_height,
+            record.power_before,
+            record.power_after,
+            if record.jailed { ", jailed" } else { "" },
+        );
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{validator_id, ConsensusState};
+
+    #[test]
+    fn test_process_double_sign_evidence() {
+        let manager = StateManager::new(ConsensusState::default());
+        let public_key = vec![9u8; 32];
+        let id = validator_id(&public_key);
+        manager.update_validator(id.clone(), public_key, 1_000, true).unwrap();
+
+        let mut validator_address = [0u8; 20];
+        validator_address.copy_from_slice(&hex::decode(&id).unwrap());
+        let evidence = Evidence {
+            validator_address,
+            kind: EvidenceKind::DuplicateVote,
+            height: 40,
+        };
+
+        let record = process_evidence(&manager, &SlashingParams::default(), &evidence, 42)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.power_before, 1_000);
+        assert_eq!(record.power_after, 950);
+        assert!(record.jailed);
+        assert_eq!(record.voting_power(), 0);
+        assert!(manager.get_active_validators().is_empty());
+
+        // Replayed evidence is not slashed twice
+        assert!(process_evidence(&manager, &SlashingParams::default(), &evidence, 42)
+            .unwrap()
+            .is_none());
+        assert_eq!(manager.get_state().evidence.len(), 1);
+
+        let unknown = Evidence { validator_address: [1; 20], ..evidence };
+        assert!(process_evidence(&manager, &SlashingParams::default(), &unknown, 42)
+            .unwrap()
+            .is_none());
+    }
+}
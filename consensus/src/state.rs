@@ -1,9 +1,24 @@
 //! Consensus state management
 
+use crate::governance::GovernanceState;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+/// Version of the encoding written by `StateManager::export_state`
+pub const STATE_FORMAT_VERSION: u32 = 2;
+
+/// First version whose height and best block follow the committed chain
+///
+/// Version 1 has the same encoding, but left them at the genesis.
+pub const TIP_TRACKING_VERSION: u32 = 2;
+
+/// Prefix of a versioned export, followed by the version (little endian)
+///
+/// Exports without it predate governance and are migrated on import.
+const STATE_MAGIC: [u8; 4] = *b"SCST";
+
 /// Consensus state snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsensusState {
@@ -19,6 +34,22 @@ pub struct ConsensusState {
     pub validators: HashMap<String, ValidatorInfo>,
     /// Application state hash
     pub app_hash: [u8; 32],
+    /// Misbehavior evidence processed so far
+    pub evidence: Vec<EvidenceRecord>,
+    /// Governance proposals and governed parameters
+    pub governance: GovernanceState,
+}
+
+/// Consensus state exported before the format was versioned, without governance
+#[derive(Deserialize)]
+struct UnversionedState {
+    height: u64,
+    best_block_hash: [u8; 32],
+    difficulty_bits: u32,
+    total_transactions: u64,
+    validators: HashMap<String, ValidatorInfo>,
+    app_hash: [u8; 32],
+    evidence: Vec<EvidenceRecord>,
 }
 
 /// Validator information
@@ -32,6 +63,53 @@ pub struct ValidatorInfo {
     pub active: bool,
 }
 
+/// Kind of validator misbehavior reported by Tendermint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvidenceKind {
+    /// Two conflicting votes for the same height and round
+    DuplicateVote,
+    /// Conflicting header signed for light clients
+    LightClientAttack,
+}
+
+/// Evidence handled by the application and the resulting penalty
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceRecord {
+    /// Offending validator ID
+    pub validator_id: String,
+    /// Kind of misbehavior
+    pub kind: EvidenceKind,
+    /// Height at which the misbehavior happened
+    pub evidence_height: u64,
+    /// Height of the block that reported the evidence
+    pub processed_height: u64,
+    /// Voting power before the penalty
+    pub power_before: i64,
+    /// Voting power after the penalty
+    pub power_after: i64,
+    /// Whether the validator was removed from the active set
+    pub jailed: bool,
+}
+
+impl EvidenceRecord {
+    /// Voting power to report to Tendermint (0 removes the validator)
+    pub fn voting_power(&self) -> i64 {
+        if self.jailed {
+            0
+        } else {
+            self.power_after
+        }
+    }
+}
+
+/// Validator ID derived from a Tendermint ed25519 public key
+///
+/// Matches the Tendermint validator address (first 20 bytes of SHA-256) so
+/// evidence, which only carries the address, can be mapped back to a validator.
+pub fn validator_id(public_key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..20])
+}
+
 /// State manager for consensus
 pub struct StateManager {
     /// Current state
@@ -121,7 +199,7 @@ impl StateManager {
             }
 
             // Update app hash (simple combination of block hash + height)
-            let mut hasher = sha2::Sha256::new();
+            let mut hasher = Sha256::new();
             hasher.update(&new_block_hash);
             hasher.update(&state.height.to_be_bytes());
             let hash_result = hasher.finalize();
@@ -205,16 +283,52 @@ impl StateManager {
     }
 
     /// Export state for backup/migration
+    ///
+    /// The encoding is tagged with `STATE_FORMAT_VERSION`.
     pub fn export_state(&self) -> Result<Vec<u8>, StateError> {
         let state = self.state.read().unwrap();
-        bincode::serialize(&*state)
-            .map_err(|e| StateError::SerializationError(e.to_string()))
+        let mut data = STATE_MAGIC.to_vec();
+        data.extend_from_slice(&STATE_FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, &*state)
+            .map_err(|e| StateError::SerializationError(e.to_string()))?;
+        Ok(data)
     }
 
     /// Import state from backup
-    pub fn import_state(&self, data: &[u8]) -> Result<(), StateError> {
-        let new_state: ConsensusState = bincode::deserialize(data)
-            .map_err(|e| StateError::SerializationError(e.to_string()))?;
+    ///
+    /// An unversioned export has no governance section: it keeps the
+    /// governance of the current state, so the governed parameters start
+    /// from the values the manager was created with. Returns the version of
+    /// `data` (0 when unversioned).
+    pub fn import_state(&self, data: &[u8]) -> Result<u32, StateError> {
+        let (version, new_state) = match data.strip_prefix(&STATE_MAGIC) {
+            Some(versioned) => {
+                let (version, encoded) = versioned.split_first_chunk::<4>()
+                    .ok_or_else(|| StateError::SerializationError("Truncated state version".to_string()))?;
+                let version = u32::from_le_bytes(*version);
+                if version == 0 || version > STATE_FORMAT_VERSION {
+                    return Err(StateError::UnsupportedVersion(version));
+                }
+                let state = bincode::deserialize(encoded)
+                    .map_err(|e| StateError::SerializationError(e.to_string()))?;
+                (version, state)
+            }
+            None => {
+                let legacy: UnversionedState = bincode::deserialize(data)
+                    .map_err(|e| StateError::SerializationError(e.to_string()))?;
+                log::info!("Migrating unversioned consensus state at height {}", legacy.height);
+                (0, ConsensusState {
+                    height: legacy.height,
+                    best_block_hash: legacy.best_block_hash,
+                    difficulty_bits: legacy.difficulty_bits,
+                    total_transactions: legacy.total_transactions,
+                    validators: legacy.validators,
+                    app_hash: legacy.app_hash,
+                    evidence: legacy.evidence,
+                    governance: self.state.read().unwrap().governance.clone(),
+                })
+            }
+        };
 
         // Validate imported state
         let temp_manager = StateManager::new(new_state.clone());
@@ -224,7 +338,7 @@ impl StateManager {
         let mut state = self.state.write().unwrap();
         *state = new_state;
 
-        Ok(())
+        Ok(version)
     }
 
     /// Get state statistics
@@ -271,6 +385,8 @@ impl Default for ConsensusState {
             total_transactions: 0,
             validators: HashMap::new(),
             app_hash: [0; 32],
+            evidence: Vec::new(),
+            governance: GovernanceState::default(),
         }
     }
 }
@@ -292,6 +408,9 @@ pub enum StateError {
 
     #[error("State update failed: {0}")]
     UpdateFailed(String),
+
+    #[error("Unsupported state format version {0}")]
+    UnsupportedVersion(u32),
 }
 
 #[cfg(test)]
@@ -408,6 +527,7 @@ mod tests {
         // Update state
         manager1.update_state(|state| {
             state.height = 42;
+            state.best_block_hash = [42; 32];
             state.total_transactions = 100;
             Ok(())
         }).unwrap();
@@ -418,12 +538,56 @@ mod tests {
         // Import to new manager
         let new_state = ConsensusState::default();
         let manager2 = StateManager::new(new_state);
-        manager2.import_state(&exported).unwrap();
+        assert_eq!(manager2.import_state(&exported).unwrap(), STATE_FORMAT_VERSION);
 
         // Verify
         let imported_state = manager2.get_state();
         assert_eq!(imported_state.height, 42);
         assert_eq!(imported_state.total_transactions, 100);
+
+        // Versions from a newer node are refused
+        let mut newer = exported.clone();
+        newer[4..8].copy_from_slice(&(STATE_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(manager2.import_state(&newer), Err(StateError::UnsupportedVersion(_))));
+
+        // Version 1 has the same encoding
+        let mut older = exported.clone();
+        older[4..8].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(manager2.import_state(&older).unwrap(), 1);
+        assert_eq!(manager2.get_state().height, 42);
+    }
+
+    #[test]
+    fn test_import_unversioned_state() {
+        #[derive(Serialize)]
+        struct Unversioned {
+            height: u64,
+            best_block_hash: [u8; 32],
+            difficulty_bits: u32,
+            total_transactions: u64,
+            validators: HashMap<String, ValidatorInfo>,
+            app_hash: [u8; 32],
+            evidence: Vec<EvidenceRecord>,
+        }
+        let legacy = bincode::serialize(&Unversioned {
+            height: 7,
+            best_block_hash: [7; 32],
+            difficulty_bits: 0x1d00ffff,
+            total_transactions: 8,
+            validators: HashMap::new(),
+            app_hash: [0; 32],
+            evidence: Vec::new(),
+        }).unwrap();
+
+        // The governance section of the current state survives the migration
+        let mut initial_state = ConsensusState::default();
+        initial_state.governance.params.min_tx_fee = 1_234;
+        let manager = StateManager::new(initial_state);
+        assert_eq!(manager.import_state(&legacy).unwrap(), 0);
+
+        let state = manager.get_state();
+        assert_eq!((state.height, state.best_block_hash), (7, [7; 32]));
+        assert_eq!(state.governance.params.min_tx_fee, 1_234);
     }
 
     #[test]
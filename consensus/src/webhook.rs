@@ -0,0 +1,548 @@
+//! HTTP webhook notifications of blocks, transactions, reorgs and node errors
+//!
+//! An alternative to the ZeroMQ interface for services that would rather
+//! receive a POST than keep a socket open. Every event is a JSON document:
+//!
+//! ```json
+//! {"id": 7, "event": "block", "timestamp": 1700000000, "data": {...}}
+//! ```
+//!
+//! Events:
+//!
+//! - `block`: each block connected to the active chain
+//! - `transaction`: each transaction accepted into the mempool or confirmed,
+//!   restricted to the target's watched scripts when it has any
+//! - `reorg`: each reorganization deep enough to raise the reorg alarm
+//! - `error`: failures the node could not recover from on its own
+//!
+//! Block hashes and txids are hex in display order (byte-reversed, as in
+//! Bitcoin and the JSON-RPC interface).
+//!
+//! When a target has a secret, requests carry `X-Sedly-Signature:
+//! sha256=<hex>`, the HMAC-SHA256 of the body under that secret, so the
+//! receiver can authenticate them. Failed deliveries (connection errors and
+//! non-2xx responses) are retried with exponential backoff.
+
+use hmac::{Hmac, Mac};
+use sedly_core::{Block, BlockHash, OutPoint, ReorgRecord, Transaction};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::mpsc as std_mpsc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+/// Header carrying the HMAC signature of the body
+pub const SIGNATURE_HEADER: &str = "X-Sedly-Signature";
+/// Header carrying the event name
+pub const EVENT_HEADER: &str = "X-Sedly-Event";
+
+/// Delivery attempts after the first one
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Delay before the first retry; doubled at each further attempt
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the delay between attempts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Timeout of a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kind of webhook event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEvent {
+    Block,
+    Transaction,
+    Reorg,
+    Error,
+}
+
+impl WebhookEvent {
+    /// All event kinds
+    pub const ALL: [WebhookEvent; 4] = [Self::Block, Self::Transaction, Self::Reorg, Self::Error];
+
+    /// Name used in payloads and the event header
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Transaction => "transaction",
+            Self::Reorg => "reorg",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = WebhookError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| WebhookError::UnknownEvent(s.to_string()))
+    }
+}
+
+/// Webhook target
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// URL receiving the POST requests (`http://` only)
+    pub url: String,
+    /// Secret signing the bodies (None sends them unsigned)
+    pub secret: Option<String>,
+    /// Events sent to the target (empty sends all of them)
+    pub events: Vec<WebhookEvent>,
+    /// Scripts whose transactions are sent (empty sends every transaction)
+    pub watch_scripts: Vec<Vec<u8>>,
+    /// Delivery attempts after the first one
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+}
+
+impl WebhookConfig {
+    /// Target receiving every event, unsigned, with the default retry policy
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret: None,
+            events: Vec::new(),
+            watch_scripts: Vec::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+        }
+    }
+
+    /// Sign the bodies with `secret`
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Send only the given events
+    pub fn with_events(mut self, events: Vec<WebhookEvent>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Send only transactions paying or spending from these scripts
+    pub fn with_watch_scripts(mut self, scripts: Vec<Vec<u8>>) -> Self {
+        self.watch_scripts = scripts;
+        self
+    }
+
+    /// Set the retry policy
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Whether the target receives `event`
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Delay before retry number `retry` (1-based)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+}
+
+/// HMAC-SHA256 signature of `body`, as sent in the signature header
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Check a signature header against `body`, in constant time
+pub fn verify(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some(digest) = signature.strip_prefix("sha256=").and_then(|digest| hex::decode(digest).ok()) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&digest).is_ok()
+}
+
+/// Request queued for delivery
+struct Delivery {
+    target: usize,
+    event: WebhookEvent,
+    body: Vec<u8>,
+}
+
+/// Per-target state of the transaction filter
+struct Target {
+    config: WebhookConfig,
+    /// Outputs paying a watched script, so spending them is relevant too
+    ///
+    /// Kept in memory: after a restart only spends of outputs seen since
+    /// then are recognized.
+    watched_outputs: Mutex<HashSet<OutPoint>>,
+}
+
+impl Target {
+    /// Whether `tx` pays or spends from a watched script, recording its watched outputs
+    fn is_relevant(&self, tx: &Transaction) -> bool {
+        if self.config.watch_scripts.is_empty() {
+            return true;
+        }
+        let mut watched = self.watched_outputs.lock().unwrap();
+        let mut relevant = false;
+        for input in &tx.inputs {
+            relevant |= watched.remove(&input.previous_output);
+        }
+        let txid = tx.hash();
+        for (vout, output) in tx.outputs.iter().enumerate() {
+            if self.config.watch_scripts.contains(&output.script_pubkey) {
+                watched.insert(OutPoint::new(txid, vout as u32));
+                relevant = true;
+            }
+        }
+        relevant
+    }
+}
+
+/// Sender of webhook notifications
+///
+/// Requests are made by a background thread, so notifying never blocks
+/// block connection or transaction checks. Each delivery retries on its
+/// own, so a slow target does not hold back the others; pending deliveries
+/// are completed (or run out of retries) when the notifier is dropped.
+pub struct WebhookNotifier {
+    /// Targets with their transaction filter
+    targets: Vec<Target>,
+    /// Queue towards the delivery thread (closed on drop)
+    sender: Option<mpsc::UnboundedSender<Delivery>>,
+    /// Identifier of the next event
+    next_id: Mutex<u64>,
+    /// Delivery thread
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WebhookNotifier {
+    /// Start delivering to the given targets
+    pub fn start(configs: Vec<WebhookConfig>) -> Result<Self, WebhookError> {
+        if let Some(config) = configs.iter().find(|config| !config.url.starts_with("http://")) {
+            return Err(WebhookError::UnsupportedUrl(config.url.clone()));
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| WebhookError::Runtime(e.to_string()))?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (ready_sender, ready) = std_mpsc::channel();
+        let targets: Vec<WebhookConfig> = configs.clone();
+        let thread = std::thread::Builder::new()
+            .name("webhook-notify".to_string())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(WebhookError::Runtime(e.to_string())));
+                        return;
+                    }
+                };
+                let _ = ready_sender.send(Ok(()));
+                runtime.block_on(deliver_all(client, targets, receiver));
+            })
+            .map_err(|e| WebhookError::Runtime(e.to_string()))?;
+
+        match ready.recv() {
+            Ok(Ok(())) => {
+                for config in &configs {
+                    log::info!("Sending webhook notifications to {}", config.url);
+                }
+                Ok(Self {
+                    targets: configs
+                        .into_iter()
+                        .map(|config| Target { config, watched_outputs: Mutex::new(HashSet::new()) })
+                        .collect(),
+                    sender: Some(sender),
+                    next_id: Mutex::new(0),
+                    thread: Some(thread),
+                })
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(WebhookError::Runtime("Webhook thread exited".to_string())),
+        }
+    }
+
+    /// Notify a block connected to the active chain
+    ///
+    /// Its transactions are notified as confirmed as well.
+    pub fn notify_block(&self, block: &Block) {
+        let block_hash = block.block_hash().to_string();
+        self.queue(WebhookEvent::Block, None, || json!({
+            "hash": block_hash,
+            "height": block.header.height,
+            "previous_hash": BlockHash::from(block.header.previous_hash),
+            "time": block.header.timestamp,
+            "transactions": block.transactions.iter().map(Transaction::txid).collect::<Vec<_>>(),
+        }));
+        for tx in &block.transactions {
+            self.queue(WebhookEvent::Transaction, Some(tx), || json!({
+                "txid": tx.txid(),
+                "status": "confirmed",
+                "block_hash": block_hash,
+                "block_height": block.header.height,
+                "outputs": outputs_json(tx),
+            }));
+        }
+    }
+
+    /// Notify a transaction accepted into the mempool
+    pub fn notify_transaction(&self, tx: &Transaction) {
+        self.queue(WebhookEvent::Transaction, Some(tx), || json!({
+            "txid": tx.txid(),
+            "status": "mempool",
+            "outputs": outputs_json(tx),
+        }));
+    }
+
+    /// Notify a deep reorganization (see `ReorgAlarm`)
+    pub fn notify_reorg(&self, record: &ReorgRecord) {
+        self.queue(WebhookEvent::Reorg, None, || json!({
+            "old_tip": record.old_tip,
+            "old_height": record.old_height,
+            "new_tip": record.new_tip,
+            "new_height": record.new_height,
+            "fork_height": record.fork_height,
+            "depth": record.depth,
+            "connected": record.connected,
+        }));
+    }
+
+    /// Notify a node error
+    pub fn notify_error(&self, context: &str, message: &str) {
+        self.queue(WebhookEvent::Error, None, || json!({
+            "context": context,
+            "message": message,
+        }));
+    }
+
+    /// Queue an event for the targets that want it; the data is built lazily
+    ///
+    /// Transaction events also go through each target's script filter.
+    fn queue(&self, event: WebhookEvent, tx: Option<&Transaction>, data: impl FnOnce() -> Value) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let recipients: Vec<usize> = self
+            .targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| target.config.wants(event) && tx.is_none_or(|tx| target.is_relevant(tx)))
+            .map(|(index, _)| index)
+            .collect();
+        if recipients.is_empty() {
+            return;
+        }
+
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let body = json!({ "id": id, "event": event.name(), "timestamp": timestamp, "data": data() }).to_string();
+        for target in recipients {
+            let _ = sender.send(Delivery { target, event, body: body.clone().into_bytes() });
+        }
+    }
+}
+
+impl Drop for WebhookNotifier {
+    fn drop(&mut self) {
+        // Closing the queue ends the delivery loop once pending requests are done
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Outputs of a transaction as JSON
+fn outputs_json(tx: &Transaction) -> Vec<Value> {
+    tx.outputs
+        .iter()
+        .map(|output| json!({
+            "value": output.value,
+            "asset_id": hex::encode(output.asset_id),
+            "script_pubkey": hex::encode(&output.script_pubkey),
+        }))
+        .collect()
+}
+
+/// Deliver queued events until the queue closes, then wait for pending ones
+async fn deliver_all(
+    client: reqwest::Client,
+    targets: Vec<WebhookConfig>,
+    mut receiver: mpsc::UnboundedReceiver<Delivery>,
+) {
+    let mut pending = JoinSet::new();
+    while let Some(delivery) = receiver.recv().await {
+        let client = client.clone();
+        let config = targets[delivery.target].clone();
+        pending.spawn(async move { deliver(&client, &config, delivery).await });
+        // Reap finished deliveries so the set does not grow unbounded
+        while pending.try_join_next().is_some() {}
+    }
+    while pending.join_next().await.is_some() {}
+}
+
+/// Deliver one event, retrying with exponential backoff
+async fn deliver(client: &reqwest::Client, config: &WebhookConfig, delivery: Delivery) {
+    let signature = config.secret.as_ref().map(|secret| sign(secret.as_bytes(), &delivery.body));
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(config.backoff(attempt)).await;
+        }
+        let mut request = client
+            .post(&config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, delivery.event.name())
+            .body(delivery.body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => log::debug!(
+                "Webhook {} answered {} to {} event (attempt {})",
+                config.url, response.status(), delivery.event.name(), attempt + 1
+            ),
+            Err(e) => log::debug!(
+                "Webhook {} unreachable for {} event (attempt {}): {}",
+                config.url, delivery.event.name(), attempt + 1, e
+            ),
+        }
+    }
+    log::warn!(
+        "Dropping {} event for webhook {} after {} attempts",
+        delivery.event.name(), config.url, config.max_retries + 1
+    );
+}
+
+/// Webhook errors
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Unsupported webhook URL {0} (only http:// is supported)")]
+    UnsupportedUrl(String),
+
+    #[error("Unknown webhook event {0} (expected block, transaction, reorg or error)")]
+    UnknownEvent(String),
+
+    #[error("Webhook thread error: {0}")]
+    Runtime(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{TxInput, TxOutput};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Read one HTTP request and answer with `status`, returning its headers and body
+    fn serve_one(listener: &TcpListener, status: &str) -> (Vec<String>, Vec<u8>) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            headers.push(line.trim_end().to_ascii_lowercase());
+        }
+        let length = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("content-length: "))
+            .map_or(0, |length| length.parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        write!(stream, "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status).unwrap();
+        (headers, body)
+    }
+
+    #[test]
+    fn test_signature() {
+        let signature = sign(b"secret", b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert!(verify(b"secret", b"{}", &signature));
+        assert!(!verify(b"other", b"{}", &signature));
+        assert!(!verify(b"secret", b"{ }", &signature));
+        assert!(!verify(b"secret", b"{}", "md5=00"));
+    }
+
+    #[test]
+    fn test_backoff_and_events() {
+        let config = WebhookConfig::new("http://127.0.0.1:1").with_retries(10, Duration::from_secs(2));
+        assert_eq!(config.backoff(1), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(8));
+        assert_eq!(config.backoff(40), MAX_BACKOFF);
+
+        assert!(config.wants(WebhookEvent::Error));
+        let config = config.with_events(vec!["block".parse().unwrap()]);
+        assert!(config.wants(WebhookEvent::Block) && !config.wants(WebhookEvent::Reorg));
+        assert!("mempool".parse::<WebhookEvent>().is_err());
+        assert!(matches!(
+            WebhookNotifier::start(vec![WebhookConfig::new("https://example.com")]),
+            Err(WebhookError::UnsupportedUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_watched_scripts() {
+        let target = Target {
+            config: WebhookConfig::new("http://127.0.0.1:1").with_watch_scripts(vec![b"alice".to_vec()]),
+            watched_outputs: Mutex::new(HashSet::new()),
+        };
+        let funding = Transaction::coinbase(b"alice", 1, 50);
+        assert!(target.is_relevant(&funding));
+        assert!(!target.is_relevant(&Transaction::coinbase(b"bob", 2, 50)));
+
+        // Spending an output of alice is relevant even when it pays bob
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(funding.hash(), 0), vec![1])],
+            vec![TxOutput::to_address(40, b"bob")],
+            0,
+        );
+        assert!(target.is_relevant(&spend));
+    }
+
+    #[test]
+    fn test_delivery_with_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let config = WebhookConfig::new(url)
+            .with_secret("secret")
+            .with_events(vec![WebhookEvent::Error])
+            .with_retries(2, Duration::from_millis(10));
+        let notifier = WebhookNotifier::start(vec![config]).unwrap();
+
+        notifier.notify_block(&Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0));
+        notifier.notify_error("commit", "disk full");
+
+        // The first attempt fails, the second one is accepted
+        let (_, first) = serve_one(&listener, "503 Service Unavailable");
+        let (headers, body) = serve_one(&listener, "200 OK");
+        assert_eq!(first, body);
+        assert!(headers.contains(&"x-sedly-event: error".to_string()));
+        let signature = headers.iter().find_map(|header| header.strip_prefix("x-sedly-signature: ")).unwrap();
+        assert!(verify(b"secret", &body, signature));
+
+        let payload: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["event"], "error");
+        assert_eq!(payload["data"]["message"], "disk full");
+        drop(notifier);
+    }
+}
@@ -0,0 +1,174 @@
+//! Benchmark dei percorsi critici del nodo
+//!
+//! `cargo bench -p sedly-core --bench chain` misura hashing degli header,
+//! merkle root, validazione dei block, applicazione al UTXO set e
+//! accettazione in mempool. Con `-- --profile-time <secondi>` ogni
+//! benchmark gira sotto pprof e lascia un flamegraph in
+//! `target/criterion/<benchmark>/profile/flamegraph.svg`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use sedly_core::validation::block_subsidy;
+use sedly_core::{
+    Block, BlockHeader, BlockValidator, BlockchainDB, ChainParams, Mempool, MerkleTree, OutPoint, Transaction, TxInput,
+    TxOutput, COINBASE_MATURITY,
+};
+use tempfile::TempDir;
+
+/// Transazioni del block di spesa
+const SPENDS: usize = 1_000;
+
+/// Fee pagata da ogni spesa
+const FEE: u64 = 1_000;
+
+/// Chain con `SPENDS` output maturi e un block che li spende tutti
+struct Fixture {
+    db: BlockchainDB,
+    _temp: TempDir,
+    /// Header del tip
+    tip: BlockHeader,
+    /// Block valido sopra il tip
+    block: Block,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let temp = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        // Coinbase del block 1 divisa in SPENDS output
+        let value = block_subsidy(1) / SPENDS as u64;
+        let mut funding = Transaction::coinbase(b"miner", 1, 0);
+        funding.outputs = (0..SPENDS).map(|_| TxOutput::to_address(value, b"miner")).collect();
+        let funding_txid = funding.hash();
+
+        let mut tip = Block::new(genesis.hash(), vec![funding], 0x1d00ffff, 1);
+        db.store_block(&tip).unwrap();
+        for height in 2..=COINBASE_MATURITY + 1 {
+            let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+            tip = Block::new(tip.hash(), vec![coinbase], 0x1d00ffff, height);
+            db.store_block(&tip).unwrap();
+        }
+
+        let height = tip.header.height + 1;
+        let spends: Vec<Transaction> = (0..SPENDS as u32)
+            .map(|vout| spend(OutPoint::new(funding_txid, vout), value - FEE))
+            .collect();
+        let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height) + FEE * SPENDS as u64);
+        let transactions = std::iter::once(coinbase).chain(spends).collect();
+        let block = Block::new(tip.hash(), transactions, 0x1d00ffff, height);
+
+        Self { db, _temp: temp, tip: tip.header, block }
+    }
+
+    /// Spese del block, senza coinbase
+    fn spends(&self) -> &[Transaction] {
+        &self.block.transactions[1..]
+    }
+}
+
+fn spend(outpoint: OutPoint, value: u64) -> Transaction {
+    Transaction::new(vec![TxInput::new(outpoint, vec![])], vec![TxOutput::to_address(value, b"alice")], 0)
+}
+
+fn header_hashing(c: &mut Criterion) {
+    let header = Block::genesis().header;
+    c.bench_function("header_hash", |b| b.iter(|| black_box(&header).hash()));
+}
+
+fn merkle_root(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merkle_root");
+    for count in [1, 100, 1_000, 4_000] {
+        let transactions: Vec<Transaction> = (0..count)
+            .map(|index| spend(OutPoint::new([1; 32], index as u32), 50))
+            .collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &transactions, |b, transactions| {
+            b.iter(|| Block::calculate_merkle_root(black_box(transactions)))
+        });
+    }
+    // Refresh della coinbase con il tree in cache
+    let leaves = (0..4_000).map(|index| spend(OutPoint::new([1; 32], index), 50).hash()).collect();
+    let mut tree = MerkleTree::new(leaves);
+    group.bench_function("update_coinbase/4000", |b| {
+        b.iter(|| {
+            tree.update_leaf(0, black_box([2; 32]));
+            tree.root()
+        })
+    });
+    group.finish();
+}
+
+fn block_validation(c: &mut Criterion) {
+    let fixture = Fixture::new();
+    let validator = BlockValidator::new(ChainParams::regtest());
+    assert!(validator.validate_block(&fixture.block, Some(&fixture.tip), &fixture.db).is_ok());
+
+    let mut group = c.benchmark_group("block_validation");
+    group.throughput(Throughput::Elements(SPENDS as u64));
+    group.bench_function("validate_block", |b| {
+        b.iter(|| validator.validate_block(black_box(&fixture.block), Some(&fixture.tip), &fixture.db).unwrap())
+    });
+    group.finish();
+}
+
+fn utxo_application(c: &mut Criterion) {
+    let fixture = Fixture::new();
+
+    let mut group = c.benchmark_group("utxo_application");
+    group.throughput(Throughput::Elements(SPENDS as u64));
+    // Solo la preparazione del batch, senza scrittura
+    group.bench_function("connect_block", |b| {
+        b.iter(|| fixture.db.connect_block(black_box(&fixture.block)).unwrap())
+    });
+    // Scrittura e ripristino del tip: il database resta uguale tra le iterazioni
+    group.bench_function("store_and_disconnect", |b| {
+        b.iter(|| {
+            fixture.db.store_block(&fixture.block).unwrap();
+            fixture.db.disconnect_tip().unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn mempool_acceptance(c: &mut Criterion) {
+    let fixture = Fixture::new();
+    let validator = BlockValidator::new(ChainParams::regtest());
+    let tip_height = fixture.tip.height;
+
+    let mut group = c.benchmark_group("mempool");
+    group.throughput(Throughput::Elements(SPENDS as u64));
+    group.bench_function("accept", |b| {
+        b.iter_batched(
+            || fixture.spends().to_vec(),
+            |transactions| {
+                let mut mempool = Mempool::new();
+                for tx in transactions {
+                    mempool.add(tx, tip_height, &validator, &fixture.db).unwrap();
+                }
+                mempool
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+#[cfg(unix)]
+fn config() -> Criterion {
+    use pprof::criterion::{Output, PProfProfiler};
+    Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)))
+}
+
+#[cfg(not(unix))]
+fn config() -> Criterion {
+    Criterion::default()
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = header_hashing, merkle_root, block_validation, utxo_application, mempool_acceptance
+}
+criterion_main!(benches);
@@ -0,0 +1,284 @@
+//! Alert di rete firmati dai maintainer
+//!
+//! Un alert è un messaggio critico per gli operatori dei nodi (es.
+//! "aggiornare prima dell'altezza X") firmato con una delle chiavi di
+//! `ChainParams::alert_keys`, propagato tra i peer e inviabile via RPC.
+//! Ogni alert ha un id, una scadenza (timestamp UNIX) e una priorità, e
+//! può cancellare alert precedenti elencandone gli id: un alert con il
+//! messaggio vuoto serve solo a cancellare. Un alert cancellato o scaduto
+//! non torna attivo anche se un peer lo ritrasmette.
+
+use crate::hash::sha256d;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signing};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Lunghezza massima del messaggio in bytes
+pub const MAX_ALERT_MESSAGE_LEN: usize = 256;
+
+/// Numero massimo di alert cancellati da un singolo alert
+pub const MAX_ALERT_CANCELS: usize = 64;
+
+/// Contenuto di un alert
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// Identificativo, unico per rete
+    pub id: u32,
+    /// Id degli alert cancellati da questo
+    pub cancel: Vec<u32>,
+    /// Timestamp UNIX dopo il quale l'alert non è più mostrato né propagato
+    pub expiration: u64,
+    /// Priorità (gli alert più prioritari sono mostrati per primi)
+    pub priority: u32,
+    /// Messaggio per gli operatori (vuoto per i soli alert di cancellazione)
+    pub message: String,
+}
+
+impl Alert {
+    /// Digest firmato: doppio SHA-256 della serializzazione
+    pub fn digest(&self) -> [u8; 32] {
+        sha256d(&bincode::serialize(self).expect("Alert serialization cannot fail"))
+    }
+
+    /// Firma l'alert con la chiave segreta di un maintainer
+    pub fn sign<C: Signing>(self, secp: &Secp256k1<C>, secret_key: &SecretKey) -> SignedAlert {
+        let message = Message::from_slice(&self.digest()).expect("Digest is 32 bytes");
+        let signature = secp.sign_ecdsa(&message, secret_key).serialize_der().to_vec();
+        SignedAlert { alert: self, signature }
+    }
+}
+
+/// Alert con la firma DER di una delle chiavi di rete
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAlert {
+    /// Contenuto firmato
+    pub alert: Alert,
+    /// Firma ECDSA (DER) del digest
+    pub signature: Vec<u8>,
+}
+
+impl SignedAlert {
+    /// Serializzazione usata nei messaggi P2P e in RPC
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Alert serialization cannot fail")
+    }
+
+    /// Decodifica un alert ricevuto, senza verificarne la firma
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AlertError> {
+        bincode::deserialize(bytes).map_err(|e| AlertError::Malformed(e.to_string()))
+    }
+
+    /// Se la firma è di una delle chiavi pubbliche `keys`
+    pub fn is_signed_by_any(&self, keys: &[Vec<u8>]) -> bool {
+        let Ok(mut signature) = Signature::from_der(&self.signature) else {
+            return false;
+        };
+        signature.normalize_s();
+        let message = Message::from_slice(&self.alert.digest()).expect("Digest is 32 bytes");
+        let secp = Secp256k1::verification_only();
+        keys.iter()
+            .filter_map(|key| PublicKey::from_slice(key).ok())
+            .any(|key| secp.verify_ecdsa(&message, &signature, &key).is_ok())
+    }
+
+    /// Se l'alert è scaduto al tempo `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.alert.expiration <= now
+    }
+}
+
+/// Alert noti al nodo
+#[derive(Debug, Clone, Default)]
+pub struct AlertSet {
+    /// Alert accettati e non cancellati, per id
+    alerts: BTreeMap<u32, SignedAlert>,
+    /// Id cancellati, mai più accettati
+    cancelled: BTreeSet<u32>,
+}
+
+impl AlertSet {
+    /// Nessun alert noto
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifica e registra un alert ricevuto al tempo `now`
+    ///
+    /// Ritorna true se l'alert è nuovo e va ritrasmesso ai peer, false se
+    /// era già noto. Gli alert cancellati dal nuovo vengono rimossi.
+    pub fn process(&mut self, alert: SignedAlert, keys: &[Vec<u8>], now: u64) -> Result<bool, AlertError> {
+        if keys.is_empty() {
+            return Err(AlertError::Disabled);
+        }
+        let id = alert.alert.id;
+        if alert.alert.message.len() > MAX_ALERT_MESSAGE_LEN {
+            return Err(AlertError::MessageTooLong(alert.alert.message.len()));
+        }
+        if alert.alert.cancel.len() > MAX_ALERT_CANCELS {
+            return Err(AlertError::TooManyCancels(alert.alert.cancel.len()));
+        }
+        if !alert.is_signed_by_any(keys) {
+            return Err(AlertError::InvalidSignature(id));
+        }
+        if alert.is_expired(now) {
+            return Err(AlertError::Expired(id));
+        }
+        if self.cancelled.contains(&id) {
+            return Err(AlertError::Cancelled(id));
+        }
+        if self.alerts.contains_key(&id) {
+            return Ok(false);
+        }
+
+        for cancelled in alert.alert.cancel.iter().filter(|cancelled| **cancelled != id) {
+            if self.alerts.remove(cancelled).is_some() {
+                log::info!("Alert {} cancelled by alert {}", cancelled, id);
+            }
+            self.cancelled.insert(*cancelled);
+        }
+        if !alert.alert.message.is_empty() {
+            log::warn!("Network alert {}: {}", id, alert.alert.message);
+        }
+        self.alerts.insert(id, alert);
+        Ok(true)
+    }
+
+    /// Alert da mostrare al tempo `now`, dal più prioritario
+    pub fn active(&self, now: u64) -> Vec<&SignedAlert> {
+        let mut active: Vec<&SignedAlert> = self
+            .relayable(now)
+            .into_iter()
+            .filter(|alert| !alert.alert.message.is_empty())
+            .collect();
+        active.sort_by_key(|alert| (std::cmp::Reverse(alert.alert.priority), alert.alert.id));
+        active
+    }
+
+    /// Alert non scaduti da inviare ai peer, cancellazioni comprese
+    pub fn relayable(&self, now: u64) -> Vec<&SignedAlert> {
+        self.alerts.values().filter(|alert| !alert.is_expired(now)).collect()
+    }
+
+    /// Alert con id `id`, se noto e non cancellato
+    pub fn get(&self, id: u32) -> Option<&SignedAlert> {
+        self.alerts.get(&id)
+    }
+
+    /// Se l'alert `id` è stato cancellato
+    pub fn is_cancelled(&self, id: u32) -> bool {
+        self.cancelled.contains(&id)
+    }
+
+    /// Rimuove gli alert scaduti al tempo `now`
+    pub fn prune(&mut self, now: u64) {
+        self.alerts.retain(|_, alert| !alert.is_expired(now));
+    }
+}
+
+/// Errori di verifica di un alert
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AlertError {
+    #[error("Network has no alert keys")]
+    Disabled,
+
+    #[error("Malformed alert: {0}")]
+    Malformed(String),
+
+    #[error("Alert message too long: {0} bytes")]
+    MessageTooLong(usize),
+
+    #[error("Alert cancels too many alerts: {0}")]
+    TooManyCancels(usize),
+
+    #[error("Alert {0} is not signed by a network alert key")]
+    InvalidSignature(u32),
+
+    #[error("Alert {0} has expired")]
+    Expired(u32),
+
+    #[error("Alert {0} has been cancelled")]
+    Cancelled(u32),
+}
+
+impl AlertError {
+    /// Se l'errore indica un alert falsificato o malformato (e non solo
+    /// vecchio): il peer che lo ha inviato si comporta male
+    pub fn is_misbehavior(&self) -> bool {
+        matches!(
+            self,
+            AlertError::Malformed(_)
+                | AlertError::MessageTooLong(_)
+                | AlertError::TooManyCancels(_)
+                | AlertError::InvalidSignature(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> (SecretKey, Vec<u8>) {
+        let secret_key = SecretKey::from_slice(&[byte; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize().to_vec();
+        (secret_key, pubkey)
+    }
+
+    fn alert(id: u32, cancel: Vec<u32>, message: &str, secret_key: &SecretKey) -> SignedAlert {
+        Alert { id, cancel, expiration: 1_000, priority: id, message: message.to_string() }
+            .sign(&Secp256k1::new(), secret_key)
+    }
+
+    #[test]
+    fn test_signature() {
+        let (secret_key, pubkey) = key(1);
+        let (other_key, other_pubkey) = key(2);
+        let keys = [pubkey];
+        let signed = alert(1, Vec::new(), "Upgrade before height 10000", &secret_key);
+        assert!(signed.is_signed_by_any(&[other_pubkey.clone(), keys[0].clone()]));
+        assert!(!signed.is_signed_by_any(&[other_pubkey]));
+        assert_eq!(SignedAlert::from_bytes(&signed.to_bytes()), Ok(signed.clone()));
+
+        // Il contenuto non si può cambiare senza invalidare la firma
+        let mut tampered = signed.clone();
+        tampered.alert.expiration = u64::MAX;
+        assert!(!tampered.is_signed_by_any(&keys));
+
+        let mut alerts = AlertSet::new();
+        let forged = alert(2, Vec::new(), "Send your coins to ...", &other_key);
+        assert_eq!(alerts.process(forged, &keys, 0), Err(AlertError::InvalidSignature(2)));
+        assert_eq!(alerts.process(signed.clone(), &[], 0), Err(AlertError::Disabled));
+        assert!(AlertError::InvalidSignature(2).is_misbehavior());
+        assert!(!AlertError::Expired(2).is_misbehavior());
+    }
+
+    #[test]
+    fn test_cancellation_and_expiry() {
+        let (secret_key, pubkey) = key(1);
+        let keys = [pubkey];
+        let mut alerts = AlertSet::new();
+
+        let first = alert(1, Vec::new(), "Upgrade before height 10000", &secret_key);
+        assert_eq!(alerts.process(first.clone(), &keys, 0), Ok(true));
+        assert_eq!(alerts.process(first.clone(), &keys, 0), Ok(false));
+        let second = alert(2, Vec::new(), "Critical bug in 0.1.0", &secret_key);
+        assert_eq!(alerts.process(second.clone(), &keys, 0), Ok(true));
+        assert_eq!(alerts.active(0), vec![&second, &first]);
+
+        // Un alert di sola cancellazione non viene mostrato ma si propaga
+        let cancel = alert(3, vec![1], "", &secret_key);
+        assert_eq!(alerts.process(cancel.clone(), &keys, 0), Ok(true));
+        assert_eq!(alerts.active(0), vec![&second]);
+        assert_eq!(alerts.relayable(0), vec![&second, &cancel]);
+        assert!(alerts.is_cancelled(1));
+        assert_eq!(alerts.process(first, &keys, 0), Err(AlertError::Cancelled(1)));
+
+        assert!(alerts.active(1_000).is_empty());
+        let late = alert(4, Vec::new(), "Too late", &secret_key);
+        assert_eq!(alerts.process(late, &keys, 1_000), Err(AlertError::Expired(4)));
+        alerts.prune(1_000);
+        assert!(alerts.get(2).is_none());
+        assert!(alerts.is_cancelled(1));
+    }
+}
@@ -0,0 +1,195 @@
+//! Importi in satoshi e conversione da e verso SLY
+//!
+//! `Amount` avvolge un valore in satoshi (1 SLY = 100.000.000 satoshi) per
+//! non confondere le unità: le operazioni aritmetiche sono solo checked o
+//! saturating, e la forma testuale è sempre in SLY con otto decimali
+//! (`12.34500000 SLY`). In serde l'importo è il numero di satoshi, come il
+//! `u64` che sostituisce: database, messaggi di rete e JSON non cambiano.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Satoshi in uno SLY
+pub const SATOSHI_PER_SLY: u64 = 100_000_000;
+
+/// Decimali della forma in SLY
+const DECIMALS: usize = 8;
+
+/// Importo in satoshi
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    /// Importo nullo
+    pub const ZERO: Amount = Amount(0);
+    /// Un satoshi
+    pub const ONE_SAT: Amount = Amount(1);
+    /// Uno SLY
+    pub const ONE_SLY: Amount = Amount(SATOSHI_PER_SLY);
+    /// Importo massimo rappresentabile
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    /// Importo di `satoshi` satoshi
+    pub const fn from_sat(satoshi: u64) -> Self {
+        Amount(satoshi)
+    }
+
+    /// Importo di `sly` SLY interi, None in caso di overflow
+    pub const fn from_sly(sly: u64) -> Option<Self> {
+        match sly.checked_mul(SATOSHI_PER_SLY) {
+            Some(satoshi) => Some(Amount(satoshi)),
+            None => None,
+        }
+    }
+
+    /// Valore in satoshi
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Somma, None in caso di overflow
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    /// Differenza, None se `other` è maggiore
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Prodotto per uno scalare, None in caso di overflow
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    /// Quoziente per uno scalare, None se `divisor` è zero
+    pub fn checked_div(self, divisor: u64) -> Option<Amount> {
+        self.0.checked_div(divisor).map(Amount)
+    }
+
+    /// Somma limitata a `Amount::MAX`
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    /// Differenza limitata a zero
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Somma di più importi, None in caso di overflow
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(satoshi: u64) -> Self {
+        Amount(satoshi)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for Amount {
+    /// Forma in SLY: `12.34500000 SLY`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:0width$} SLY",
+            self.0 / SATOSHI_PER_SLY,
+            self.0 % SATOSHI_PER_SLY,
+            width = DECIMALS
+        )
+    }
+}
+
+/// Errore di parsing di un importo in SLY
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AmountParseError {
+    #[error("Invalid amount: {0}")]
+    Invalid(String),
+
+    #[error("Amount has more than {DECIMALS} decimals: {0}")]
+    TooPrecise(String),
+
+    #[error("Amount too large: {0}")]
+    Overflow(String),
+}
+
+impl FromStr for Amount {
+    type Err = AmountParseError;
+
+    /// Parsing di un importo in SLY (`12.345`, `12.34500000 SLY`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AmountParseError::Invalid(s.to_string());
+        let number = s.trim().strip_suffix("SLY").map_or(s.trim(), str::trim_end);
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole.bytes().chain(fraction.bytes()).all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if fraction.len() > DECIMALS {
+            return Err(AmountParseError::TooPrecise(s.to_string()));
+        }
+
+        let overflow = || AmountParseError::Overflow(s.to_string());
+        let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| overflow())? };
+        let fraction: u64 = format!("{:0<width$}", fraction, width = DECIMALS).parse().map_err(|_| invalid())?;
+        whole
+            .checked_mul(SATOSHI_PER_SLY)
+            .and_then(|satoshi| satoshi.checked_add(fraction))
+            .map(Amount)
+            .ok_or_else(overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse() {
+        let amount = Amount::from_sat(1_234_500_000);
+        assert_eq!(amount.to_string(), "12.34500000 SLY");
+        assert_eq!(Amount::ZERO.to_string(), "0.00000000 SLY");
+        assert_eq!(Amount::from_sly(50), Some(Amount::from_sat(crate::INITIAL_BLOCK_REWARD)));
+
+        for text in ["12.345", "12.34500000 SLY", " 12.345SLY", "12.345 SLY"] {
+            assert_eq!(text.parse::<Amount>(), Ok(amount), "{}", text);
+        }
+        assert_eq!(".5".parse::<Amount>(), Ok(Amount::from_sat(50_000_000)));
+        assert_eq!("3".parse::<Amount>(), Ok(Amount::from_sly(3).unwrap()));
+        assert_eq!(amount.to_string().parse::<Amount>(), Ok(amount));
+
+        assert!(matches!("1.000000001".parse::<Amount>(), Err(AmountParseError::TooPrecise(_))));
+        assert!(matches!("184467440738".parse::<Amount>(), Err(AmountParseError::Overflow(_))));
+        for text in ["", ".", "-1", "1e8", "1.2.3", "SLY"] {
+            assert!(matches!(text.parse::<Amount>(), Err(AmountParseError::Invalid(_))), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_checked_arithmetic_and_serde() {
+        let fee = Amount::from_sat(crate::MIN_TX_FEE);
+        assert_eq!(Amount::ONE_SLY.checked_sub(fee), Some(Amount::from_sat(99_999_000)));
+        assert_eq!(fee.checked_sub(Amount::ONE_SLY), None);
+        assert_eq!(Amount::MAX.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::MAX.saturating_add(Amount::ONE_SAT), Amount::MAX);
+        assert_eq!(Amount::checked_sum([fee, fee, fee]), Some(fee.checked_mul(3).unwrap()));
+        assert_eq!(Amount::checked_sum([Amount::MAX, fee]), None);
+
+        // Codificato come il u64 in satoshi che sostituisce
+        assert_eq!(bincode::serialize(&fee).unwrap(), bincode::serialize(&crate::MIN_TX_FEE).unwrap());
+        assert_eq!(serde_json::to_string(&fee).unwrap(), "1000");
+        assert_eq!(serde_json::from_str::<Amount>("1000").unwrap(), fee);
+    }
+}
@@ -0,0 +1,223 @@
+//! Audit dell'invariante di emissione della moneta
+//!
+//! Ogni `interval` block il totale SLY nativo del UTXO set deve essere
+//! uguale al supply emesso (premine del genesis più i subsidy) meno quanto
+//! bruciato, cioè la parte di subsidy e fee non riscossa dalle coinbase.
+//! Un bug di inflazione (o di perdita di UTXO) rompe l'uguaglianza.
+//!
+//! Emissione e bruciato sono accumulati block per block dai block salvati;
+//! il primo audit dopo l'avvio ripercorre la chain dal genesis.
+
+use crate::storage::{BlockchainDB, CancellationToken, ChainSnapshot, StorageError};
+use crate::validation::block_subsidy;
+use crate::{Block, Transaction};
+
+/// Esito di un audit del supply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupplyReport {
+    /// Altezza del tip verificato
+    pub height: u64,
+    /// Hash del tip verificato
+    pub best_block_hash: [u8; 32],
+    /// Supply emesso (premine + subsidy)
+    pub issued: u64,
+    /// Subsidy e fee non riscossi dalle coinbase
+    pub burned: u64,
+    /// Totale atteso nel UTXO set (`issued - burned`)
+    pub expected: u64,
+    /// Totale effettivo nel UTXO set
+    pub utxo_total: u64,
+    /// Numero di output non spesi
+    pub txouts: u64,
+}
+
+impl SupplyReport {
+    /// Differenza tra UTXO set e atteso (positiva = inflazione)
+    pub fn discrepancy(&self) -> i128 {
+        self.utxo_total as i128 - self.expected as i128
+    }
+
+    /// Se l'invariante è rispettata
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancy() == 0
+    }
+}
+
+/// Verifica periodica dell'invariante di supply
+#[derive(Debug, Clone)]
+pub struct SupplyAuditor {
+    /// Ogni quanti block eseguire l'audit (0 = mai)
+    interval: u64,
+    /// Prossima altezza da contabilizzare
+    next_height: u64,
+    /// Supply emesso fino a `next_height - 1`
+    issued: u64,
+    /// Bruciato fino a `next_height - 1`
+    burned: u64,
+}
+
+impl SupplyAuditor {
+    /// Crea un auditor che verifica ogni `interval` block
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            next_height: 0,
+            issued: 0,
+            burned: 0,
+        }
+    }
+
+    /// Se dopo il block a `height` va eseguito un audit
+    pub fn is_due(&self, height: u64) -> bool {
+        self.interval > 0 && height.is_multiple_of(self.interval)
+    }
+
+    /// Verifica l'invariante al tip corrente
+    ///
+    /// Ritorna il report anche in caso di violazione, dentro l'errore.
+    pub fn audit(&mut self, db: &BlockchainDB) -> Result<SupplyReport, SupplyAuditError> {
+        // Block e UTXO set letti dallo stesso snapshot: un block connesso
+        // durante l'audit non può produrre una falsa violazione
+        let snapshot = db.snapshot();
+        let stats = snapshot.utxo_set_stats(&CancellationToken::new(), |_| {})?;
+        self.advance(&snapshot, stats.height)?;
+
+        let report = SupplyReport {
+            height: stats.height,
+            best_block_hash: stats.best_block_hash,
+            issued: self.issued,
+            burned: self.burned,
+            expected: self.issued.saturating_sub(self.burned),
+            utxo_total: stats.total_amount,
+            txouts: stats.txouts,
+        };
+        if report.is_consistent() {
+            Ok(report)
+        } else {
+            Err(SupplyAuditError::Violation(report))
+        }
+    }
+
+    /// Contabilizza i block fino a `height` compreso
+    fn advance(&mut self, db: &ChainSnapshot<'_>, height: u64) -> Result<(), SupplyAuditError> {
+        while self.next_height <= height {
+            let block = db.get_block_by_height(self.next_height)?
+                .ok_or(SupplyAuditError::MissingBlock(self.next_height))?;
+            let (issued, burned) = Self::block_supply(db, &block)?;
+            self.issued = self.issued.saturating_add(issued);
+            self.burned = self.burned.saturating_add(burned);
+            self.next_height += 1;
+        }
+        Ok(())
+    }
+
+    /// Supply emesso e bruciato da un block
+    fn block_supply(db: &ChainSnapshot<'_>, block: &Block) -> Result<(u64, u64), SupplyAuditError> {
+        let Some(coinbase) = block.transactions.first() else {
+            return Err(SupplyAuditError::MissingCoinbase(block.header.height));
+        };
+        let claimed = native_output_value(coinbase);
+
+        // Il genesis emette esattamente le sue allocazioni
+        if block.header.height == 0 {
+            return Ok((claimed, 0));
+        }
+
+        let mut fees = 0u64;
+        for tx in &block.transactions[1..] {
+            let mut input_value = 0u64;
+            for input in &tx.inputs {
+                let outpoint = &input.previous_output;
+                let (previous, _) = db.get_transaction(&outpoint.txid)?
+                    .ok_or(SupplyAuditError::MissingInput { txid: outpoint.txid })?;
+                let output = previous.outputs.get(outpoint.vout as usize)
+                    .ok_or(SupplyAuditError::MissingInput { txid: outpoint.txid })?;
+                if output.is_native_asset() {
+                    input_value = input_value.saturating_add(output.value.to_sat());
+                }
+            }
+            fees = fees.saturating_add(input_value.saturating_sub(native_output_value(tx)));
+        }
+
+        let subsidy = block_subsidy(block.header.height);
+        let available = subsidy.saturating_add(fees);
+        Ok((subsidy, available.saturating_sub(claimed)))
+    }
+}
+
+/// Valore SLY nativo degli output di una transazione
+fn native_output_value(tx: &Transaction) -> u64 {
+    tx.outputs
+        .iter()
+        .filter(|output| output.is_native_asset())
+        .fold(0u64, |total, output| total.saturating_add(output.value.to_sat()))
+}
+
+/// Errori dell'audit del supply
+#[derive(Debug, thiserror::Error)]
+pub enum SupplyAuditError {
+    #[error(
+        "Supply invariant violated at height {} ({}): UTXO set holds {}, expected {} (issued {} - burned {}), discrepancy {}",
+        .0.height, hex::encode(.0.best_block_hash), .0.utxo_total, .0.expected, .0.issued, .0.burned, .0.discrepancy()
+    )]
+    Violation(SupplyReport),
+
+    #[error("Block at height {0} is missing")]
+    MissingBlock(u64),
+
+    #[error("Block at height {0} has no coinbase")]
+    MissingCoinbase(u64),
+
+    #[error("Spent transaction {} is missing from the index", hex::encode(txid))]
+    MissingInput { txid: [u8; 32] },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutPoint, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_supply_audit() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        // Block 1 reclama tutto, block 2 lascia 10 satoshi non riscossi
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1));
+        let block1 = Block::new(genesis.hash(), vec![coinbase.clone()], 0x1d00ffff, 1);
+        db.store_block(&block1).unwrap();
+
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase.hash(), 0), vec![])],
+            vec![TxOutput::to_address(block_subsidy(1) - 1_000, b"alice")],
+            0,
+        );
+        let reward = Transaction::coinbase(b"miner", 2, block_subsidy(2) + 1_000 - 10);
+        let block2 = Block::new(block1.hash(), vec![reward, spend], 0x1d00ffff, 2);
+        db.store_block(&block2).unwrap();
+
+        let mut auditor = SupplyAuditor::new(2);
+        assert!(auditor.is_due(2));
+        assert!(!auditor.is_due(3));
+
+        let report = auditor.audit(&db).unwrap();
+        assert_eq!(report.height, 2);
+        assert_eq!(report.issued, block_subsidy(1) + block_subsidy(2));
+        assert_eq!(report.burned, 10);
+        assert_eq!(report.utxo_total, report.expected);
+
+        // Un block che conia più del dovuto viola l'invariante
+        let inflated = Transaction::coinbase(b"miner", 3, block_subsidy(3) + 1);
+        db.store_block(&Block::new(block2.hash(), vec![inflated], 0x1d00ffff, 3)).unwrap();
+        match auditor.audit(&db) {
+            Err(SupplyAuditError::Violation(report)) => assert_eq!(report.discrepancy(), 1),
+            other => panic!("expected violation, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Merge mining (AuxPoW)
+//!
+//! Con l'AuxPoW un block Sedly può essere minato insieme a una chain
+//! SHA-256 più grande (la parent chain, es. Bitcoin): il miner inserisce
+//! l'hash del block Sedly nella coinbase del proprio block parent e, se
+//! l'header parent soddisfa il target Sedly, il block Sedly è valido senza
+//! un proprio nonce. La sicurezza di Sedly si appoggia così all'hash rate
+//! della parent chain.
+//!
+//! La prova ([`AuxPow`]) contiene la coinbase parent, il ramo merkle che la
+//! collega alla merkle root dell'header parent, il ramo che collega il block
+//! Sedly alla radice del merkle tree delle chain ausiliarie e l'header parent
+//! (80 bytes, formato Bitcoin).
+//!
+//! L'impegno segue il formato standard di Namecoin, quindi i pool che già
+//! fanno merge mining di più chain possono includere Sedly senza modifiche:
+//! la coinbase contiene una sola volta [`MERGED_MINING_MAGIC`] seguito dalla
+//! radice del tree delle chain ausiliarie (byte invertiti), dalla dimensione
+//! del tree e da un nonce, entrambi u32 little-endian. La foglia Sedly deve
+//! stare nello slot derivato da nonce e [`AUXPOW_CHAIN_ID`]
+//! ([`expected_index`]), così la stessa prova non vale per due block Sedly
+//! concorrenti. A differenza di Namecoin non è accettata la forma legacy
+//! senza magic, e il chain ID non è codificato nella versione dell'header.
+//!
+//! Un block con AuxPoW ha il bit [`AUXPOW_VERSION_FLAG`] nella versione
+//! dell'header, quindi l'hash impegnato nella coinbase parent copre anche
+//! il flag. La prova viaggia dopo le transazioni del block ed è accettata
+//! solo dall'altezza di attivazione in `ChainParams::auxpow_activation_height`.
+//! La prova non fa parte dell'header hashato: una prova non valida dice che
+//! quella prova è sbagliata, non che il block lo sia.
+
+use crate::hash::sha256d;
+use serde::{Deserialize, Serialize};
+
+/// Marker che precede la radice del tree delle chain ausiliarie nella coinbase parent
+pub const MERGED_MINING_MAGIC: [u8; 4] = [0xfa, 0xbe, b'm', b'm'];
+
+/// Identificativo di Sedly tra le chain ausiliarie dello stesso parent
+pub const AUXPOW_CHAIN_ID: u32 = 0x5ed1;
+
+/// Bit della versione dell'header dei block con AuxPoW
+pub const AUXPOW_VERSION_FLAG: u32 = 1 << 8;
+
+/// Dimensione di un header parent (formato Bitcoin)
+pub const PARENT_HEADER_SIZE: usize = 80;
+
+/// Dimensione massima della coinbase parent
+pub const MAX_PARENT_COINBASE_SIZE: usize = 100_000;
+
+/// Profondità massima del ramo merkle della coinbase parent
+pub const MAX_COINBASE_BRANCH: usize = 32;
+
+/// Profondità massima del tree delle chain ausiliarie (come Namecoin)
+pub const MAX_CHAIN_BRANCH: usize = 30;
+
+/// Posizione della merkle root nell'header parent
+const PARENT_MERKLE_ROOT: std::ops::Range<usize> = 36..68;
+
+/// Prova di lavoro ausiliaria di un block minato su una parent chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuxPow {
+    /// Coinbase del block parent serializzata (senza witness)
+    pub parent_coinbase: Vec<u8>,
+    /// Fratelli della coinbase (prima foglia) fino alla merkle root parent
+    pub coinbase_branch: Vec<[u8; 32]>,
+    /// Fratelli del block Sedly fino alla radice del tree delle chain ausiliarie
+    pub chain_branch: Vec<[u8; 32]>,
+    /// Slot del block Sedly tra le foglie del tree delle chain ausiliarie
+    pub chain_index: u32,
+    /// Header del block parent
+    pub parent_header: Vec<u8>,
+}
+
+/// Slot di una chain nel tree delle chain ausiliarie di `2^height` foglie
+///
+/// Stesso generatore lineare congruenziale di Namecoin: il pool sceglie
+/// `nonce` in modo che le chain che mina non collidano.
+pub fn expected_index(nonce: u32, chain_id: u32, height: usize) -> u32 {
+    let mut rand = nonce.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+    rand = rand.wrapping_add(chain_id);
+    rand = rand.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+    rand % (1 << height)
+}
+
+/// Radice di un ramo merkle Bitcoin per la foglia in posizione `index`
+fn branch_root(leaf: [u8; 32], branch: &[[u8; 32]], index: u32) -> [u8; 32] {
+    branch.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+        if index >> level & 1 == 1 {
+            sha256d(&[*sibling, node].concat())
+        } else {
+            sha256d(&[node, *sibling].concat())
+        }
+    })
+}
+
+impl AuxPow {
+    /// Bytes da inserire nella coinbase parent per impegnare la radice
+    /// `chain_root` di un tree di `2^height` chain ausiliarie
+    pub fn commitment(chain_root: &[u8; 32], height: usize, nonce: u32) -> Vec<u8> {
+        let mut root = *chain_root;
+        root.reverse();
+        let size = 1u32 << height;
+        [&MERGED_MINING_MAGIC[..], &root, &size.to_le_bytes(), &nonce.to_le_bytes()].concat()
+    }
+
+    /// Radice del tree delle chain ausiliarie che contiene `block_hash`
+    pub fn chain_root(&self, block_hash: &[u8; 32]) -> [u8; 32] {
+        branch_root(*block_hash, &self.chain_branch, self.chain_index)
+    }
+
+    /// Hash dell'header parent (ordine interno, come gli hash Bitcoin)
+    pub fn parent_hash(&self) -> [u8; 32] {
+        sha256d(&self.parent_header)
+    }
+
+    /// Verifica che la prova impegni `block_hash` e soddisfi `target`
+    ///
+    /// Come in Bitcoin, l'hash parent è confrontato con il target come
+    /// numero little-endian.
+    pub fn check(&self, block_hash: &[u8; 32], target: &[u8; 32]) -> Result<(), AuxPowError> {
+        if self.parent_header.len() != PARENT_HEADER_SIZE {
+            return Err(AuxPowError::BadParentHeader(self.parent_header.len()));
+        }
+        if self.parent_coinbase.len() > MAX_PARENT_COINBASE_SIZE {
+            return Err(AuxPowError::CoinbaseTooLarge(self.parent_coinbase.len()));
+        }
+        if self.coinbase_branch.len() > MAX_COINBASE_BRANCH {
+            return Err(AuxPowError::BranchTooLong(self.coinbase_branch.len()));
+        }
+        if self.chain_branch.len() > MAX_CHAIN_BRANCH {
+            return Err(AuxPowError::ChainBranchTooLong(self.chain_branch.len()));
+        }
+
+        let markers: Vec<usize> = self.parent_coinbase
+            .windows(MERGED_MINING_MAGIC.len())
+            .enumerate()
+            .filter(|(_, window)| *window == MERGED_MINING_MAGIC)
+            .map(|(position, _)| position)
+            .collect();
+        let position = match markers[..] {
+            [] => return Err(AuxPowError::MissingCommitment),
+            [position] => position + MERGED_MINING_MAGIC.len(),
+            _ => return Err(AuxPowError::MultipleCommitments),
+        };
+        let commitment = self.parent_coinbase.get(position..position + 40).ok_or(AuxPowError::TruncatedCommitment)?;
+        let mut root = self.chain_root(block_hash);
+        root.reverse();
+        if commitment[..32] != root {
+            return Err(AuxPowError::WrongCommitment);
+        }
+        let size = u32::from_le_bytes(commitment[32..36].try_into().expect("4 bytes"));
+        if size != 1 << self.chain_branch.len() {
+            return Err(AuxPowError::WrongTreeSize(size));
+        }
+        let nonce = u32::from_le_bytes(commitment[36..40].try_into().expect("4 bytes"));
+        if self.chain_index != expected_index(nonce, AUXPOW_CHAIN_ID, self.chain_branch.len()) {
+            return Err(AuxPowError::WrongChainIndex(self.chain_index));
+        }
+
+        // La coinbase è sempre la prima foglia del tree parent
+        let parent_root = branch_root(sha256d(&self.parent_coinbase), &self.coinbase_branch, 0);
+        if self.parent_header[PARENT_MERKLE_ROOT] != parent_root {
+            return Err(AuxPowError::BadMerkleBranch);
+        }
+
+        let mut work = self.parent_hash();
+        work.reverse();
+        if work > *target {
+            return Err(AuxPowError::InsufficientWork);
+        }
+        Ok(())
+    }
+}
+
+/// Errori di verifica di una prova AuxPoW
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AuxPowError {
+    #[error("Parent header of {0} bytes, expected 80")]
+    BadParentHeader(usize),
+
+    #[error("Parent coinbase of {0} bytes is too large")]
+    CoinbaseTooLarge(usize),
+
+    #[error("Coinbase merkle branch of {0} hashes is too long")]
+    BranchTooLong(usize),
+
+    #[error("Aux chain merkle branch of {0} hashes is too long")]
+    ChainBranchTooLong(usize),
+
+    #[error("Parent coinbase has no merged mining commitment")]
+    MissingCommitment,
+
+    #[error("Parent coinbase has more than one merged mining commitment")]
+    MultipleCommitments,
+
+    #[error("Merged mining commitment is truncated")]
+    TruncatedCommitment,
+
+    #[error("Parent coinbase commits to another block")]
+    WrongCommitment,
+
+    #[error("Aux chain tree size {0} does not match the branch")]
+    WrongTreeSize(u32),
+
+    #[error("Aux chain index {0} is not the slot assigned to this chain")]
+    WrongChainIndex(u32),
+
+    #[error("Coinbase merkle branch does not match the parent merkle root")]
+    BadMerkleBranch,
+
+    #[error("Parent header does not meet the target")]
+    InsufficientWork,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Header parent con merkle root `root`, con un nonce che rispetta `target`
+    pub(crate) fn mine_parent(root: &[u8; 32], target: &[u8; 32]) -> Vec<u8> {
+        let mut header = vec![0u8; PARENT_HEADER_SIZE];
+        header[PARENT_MERKLE_ROOT].copy_from_slice(root);
+        for nonce in 0u32.. {
+            header[76..].copy_from_slice(&nonce.to_le_bytes());
+            let mut work = sha256d(&header);
+            work.reverse();
+            if work <= *target {
+                return header;
+            }
+        }
+        unreachable!("Nonce space exhausted")
+    }
+
+    /// Prova per `block_hash` minato insieme ad altre tre chain ausiliarie,
+    /// con la coinbase in un tree di due transazioni
+    pub(crate) fn proof(block_hash: &[u8; 32], target: &[u8; 32]) -> AuxPow {
+        let nonce = 7;
+        let chain_branch = vec![[3; 32], [4; 32]];
+        let chain_index = expected_index(nonce, AUXPOW_CHAIN_ID, chain_branch.len());
+        let chain_root = branch_root(*block_hash, &chain_branch, chain_index);
+        let commitment = AuxPow::commitment(&chain_root, chain_branch.len(), nonce);
+        let parent_coinbase = [&b"parent coinbase "[..], &commitment, b" tail"].concat();
+        let sibling = [9; 32];
+        let root = sha256d(&[sha256d(&parent_coinbase), sibling].concat());
+        AuxPow {
+            parent_coinbase,
+            coinbase_branch: vec![sibling],
+            chain_branch,
+            chain_index,
+            parent_header: mine_parent(&root, target),
+        }
+    }
+
+    #[test]
+    fn test_auxpow_check() {
+        let mut target = [0xff; 32];
+        target[0] = 0x00;
+        let block_hash = [1; 32];
+        let auxpow = proof(&block_hash, &target);
+        assert_eq!(auxpow.check(&block_hash, &target), Ok(()));
+        assert_eq!(auxpow.check(&[2; 32], &target), Err(AuxPowError::WrongCommitment));
+        assert_eq!(auxpow.check(&block_hash, &[0; 32]), Err(AuxPowError::InsufficientWork));
+
+        // Ramo, coinbase e impegni manomessi
+        let mut tampered = auxpow.clone();
+        tampered.coinbase_branch[0] = [8; 32];
+        assert_eq!(tampered.check(&block_hash, &target), Err(AuxPowError::BadMerkleBranch));
+        let mut doubled = auxpow.clone();
+        doubled.parent_coinbase.extend(AuxPow::commitment(&[2; 32], 0, 0));
+        assert_eq!(doubled.check(&block_hash, &target), Err(AuxPowError::MultipleCommitments));
+        let mut missing = auxpow.clone();
+        missing.parent_coinbase = b"no commitment".to_vec();
+        assert_eq!(missing.check(&block_hash, &target), Err(AuxPowError::MissingCommitment));
+        let mut truncated = auxpow.clone();
+        truncated.parent_coinbase.truncate(truncated.parent_coinbase.len() - 10);
+        assert_eq!(truncated.check(&block_hash, &target), Err(AuxPowError::TruncatedCommitment));
+        let mut short = auxpow;
+        short.parent_header.pop();
+        assert_eq!(short.check(&block_hash, &target), Err(AuxPowError::BadParentHeader(79)));
+    }
+
+    #[test]
+    fn test_auxpow_chain_slot() {
+        // Con una sola chain ausiliaria lo slot è sempre la radice
+        assert_eq!(expected_index(12_345, AUXPOW_CHAIN_ID, 0), 0);
+        let nonce = 7;
+        let slot = expected_index(nonce, AUXPOW_CHAIN_ID, 2);
+        assert!(slot < 4);
+
+        // Lo stesso block in un altro slot del tree non è valido: un pool non
+        // può impegnare due block Sedly concorrenti nella stessa coinbase
+        let target = [0xff; 32];
+        let block_hash = [1; 32];
+        let chain_branch = vec![[3; 32], [4; 32]];
+        let other = (slot + 1) % 4;
+        let chain_root = branch_root(block_hash, &chain_branch, other);
+        let parent_coinbase = AuxPow::commitment(&chain_root, 2, nonce);
+        let root = sha256d(&parent_coinbase);
+        let auxpow = AuxPow {
+            parent_coinbase,
+            coinbase_branch: Vec::new(),
+            chain_branch,
+            chain_index: other,
+            parent_header: mine_parent(&root, &target),
+        };
+        assert_eq!(auxpow.check(&block_hash, &target), Err(AuxPowError::WrongChainIndex(other)));
+
+        // Dimensione del tree diversa dalla profondità del ramo
+        let mut resized = auxpow.clone();
+        resized.chain_branch.pop();
+        resized.chain_index = expected_index(nonce, AUXPOW_CHAIN_ID, 1);
+        let chain_root = resized.chain_root(&block_hash);
+        resized.parent_coinbase = [&AuxPow::commitment(&chain_root, 1, nonce)[..32 + 4], &4u32.to_le_bytes(),
+            &nonce.to_le_bytes()].concat();
+        resized.parent_header = mine_parent(&sha256d(&resized.parent_coinbase), &target);
+        assert_eq!(resized.check(&block_hash, &target), Err(AuxPowError::WrongTreeSize(4)));
+    }
+}
@@ -1,8 +1,13 @@
 //! Block e BlockHeader structures per Sedly blockchain
 
-use crate::transaction::Transaction;
-use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use crate::auxpow::{AuxPow, AUXPOW_VERSION_FLAG};
+use crate::pow::PowAlgorithm;
+use crate::transaction::{SerializationError, Transaction, TxOutput};
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Block header contenente metadati del block
@@ -25,12 +30,84 @@ pub struct BlockHeader {
 }
 
 /// Block completo con header + transazioni
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// La prova AuxPoW è serializzata dopo le transazioni solo se la versione
+/// dell'header ha `AUXPOW_VERSION_FLAG`: i block senza flag mantengono il
+/// formato precedente.
+#[derive(Debug, Clone)]
 pub struct Block {
     /// Header del block
     pub header: BlockHeader,
     /// Lista delle transazioni nel block
     pub transactions: Vec<Transaction>,
+    /// Prova di merge mining, se il block è minato su una parent chain
+    pub auxpow: Option<AuxPow>,
+}
+
+/// Campi serializzati di un block
+const BLOCK_FIELDS: &[&str] = &["header", "transactions", "auxpow"];
+
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.header.has_auxpow_flag() != self.auxpow.is_some() {
+            return Err(ser::Error::custom("AuxPoW version flag does not match the proof"));
+        }
+        let mut state = serializer.serialize_struct("Block", 2 + self.auxpow.is_some() as usize)?;
+        state.serialize_field("header", &self.header)?;
+        state.serialize_field("transactions", &self.transactions)?;
+        if let Some(auxpow) = &self.auxpow {
+            state.serialize_field("auxpow", auxpow)?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Block", BLOCK_FIELDS, BlockVisitor)
+    }
+}
+
+/// Decodifica un block leggendo la prova AuxPoW solo se l'header ha il flag
+struct BlockVisitor;
+
+impl<'de> Visitor<'de> for BlockVisitor {
+    type Value = Block;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a block")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Block, A::Error> {
+        let header: BlockHeader = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let transactions = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let auxpow = if header.has_auxpow_flag() {
+            Some(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?)
+        } else {
+            None
+        };
+        Ok(Block { header, transactions, auxpow })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Block, A::Error> {
+        let (mut header, mut transactions, mut auxpow) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "header" => header = Some(map.next_value::<BlockHeader>()?),
+                "transactions" => transactions = Some(map.next_value()?),
+                "auxpow" => auxpow = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let header = header.ok_or_else(|| de::Error::missing_field("header"))?;
+        let transactions = transactions.ok_or_else(|| de::Error::missing_field("transactions"))?;
+        if header.has_auxpow_flag() != auxpow.is_some() {
+            return Err(de::Error::custom("AuxPoW version flag does not match the proof"));
+        }
+        Ok(Block { header, transactions, auxpow })
+    }
 }
 
 impl BlockHeader {
@@ -54,6 +131,7 @@ impl BlockHeader {
     }
 
     /// Timestamp corrente in secondi Unix
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
     pub fn current_timestamp() -> u64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -61,16 +139,24 @@ impl BlockHeader {
             .as_secs()
     }
 
+    /// Timestamp corrente in secondi Unix (dall'orologio del browser:
+    /// `SystemTime` non è disponibile su wasm32-unknown-unknown)
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    pub fn current_timestamp() -> u64 {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+
     /// Calcola hash del header (double SHA-256 come Bitcoin)
     pub fn hash(&self) -> [u8; 32] {
         let header_bytes = bincode::serialize(self)
             .expect("Failed to serialize header");
 
-        // Double SHA-256
-        let hash1 = Sha256::digest(&header_bytes);
-        let hash2 = Sha256::digest(&hash1);
+        crate::hash::sha256d(&header_bytes)
+    }
 
-        hash2.into()
+    /// Hash dell'header come `BlockHash`
+    pub fn block_hash(&self) -> crate::BlockHash {
+        self.hash().into()
     }
 
     /// Converte bits in target hash per difficulty check
@@ -78,12 +164,30 @@ impl BlockHeader {
         bits_to_target(self.bits)
     }
 
+    /// Se il block è minato con AuxPoW (`AUXPOW_VERSION_FLAG` nella versione)
+    pub fn has_auxpow_flag(&self) -> bool {
+        self.version & AUXPOW_VERSION_FLAG != 0
+    }
+
     /// Verifica se il hash soddisfa la difficulty
     pub fn meets_difficulty(&self) -> bool {
         let hash = self.hash();
         let target = self.target();
         hash <= target
     }
+
+    /// Hash di proof of work dell'header con `algorithm`
+    pub fn pow_hash(&self, algorithm: &dyn PowAlgorithm) -> [u8; 32] {
+        let header_bytes = bincode::serialize(self)
+            .expect("Failed to serialize header");
+
+        algorithm.pow_hash(&header_bytes)
+    }
+
+    /// Verifica se il hash di proof of work con `algorithm` soddisfa la difficulty
+    pub fn meets_difficulty_with(&self, algorithm: &dyn PowAlgorithm) -> bool {
+        self.pow_hash(algorithm) <= self.target()
+    }
 }
 
 impl Block {
@@ -106,51 +210,52 @@ impl Block {
         Self {
             header,
             transactions,
+            auxpow: None,
         }
     }
 
+    /// Prepara il block al merge mining impostando `AUXPOW_VERSION_FLAG`
+    ///
+    /// Il flag cambia l'hash del block: va impostato prima di impegnare
+    /// l'hash nella coinbase parent, poi la prova si aggiunge con
+    /// [`Block::set_auxpow`].
+    pub fn enable_auxpow(&mut self) {
+        self.header.version |= AUXPOW_VERSION_FLAG;
+    }
+
+    /// Aggiunge la prova AuxPoW a un block preparato con [`Block::enable_auxpow`]
+    pub fn set_auxpow(&mut self, auxpow: AuxPow) {
+        debug_assert!(self.header.has_auxpow_flag(), "AuxPoW set on a block without the version flag");
+        self.auxpow = Some(auxpow);
+    }
+
     /// Hash del block (hash dell'header)
     pub fn hash(&self) -> [u8; 32] {
         self.header.hash()
     }
 
+    /// Hash del block come `BlockHash`
+    pub fn block_hash(&self) -> crate::BlockHash {
+        self.header.block_hash()
+    }
+
+    /// Txid delle transazioni, nell'ordine del block
+    ///
+    /// Ogni `Transaction::hash` riserializza la transazione: i percorsi che
+    /// toccano tutte le transazioni (validazione, merkle root, UTXO set) li
+    /// calcolano una volta sola qui e se li passano.
+    pub fn txids(&self) -> Vec<[u8; 32]> {
+        self.transactions.iter().map(Transaction::hash).collect()
+    }
+
     /// Calcola merkle root delle transazioni
     pub fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
-        if transactions.is_empty() {
-            return [0; 32];
-        }
-
-        let mut hashes: Vec<[u8; 32]> = transactions
+        let txids: Vec<[u8; 32]> = transactions
             .iter()
             .map(|tx| tx.hash())
             .collect();
 
-        // Semplice merkle tree (TODO: implementazione completa)
-        while hashes.len() > 1 {
-            let mut next_level = Vec::new();
-
-            for chunk in hashes.chunks(2) {
-                let combined_hash = if chunk.len() == 2 {
-                    let mut combined = [0u8; 64];
-                    combined[..32].copy_from_slice(&chunk[0]);
-                    combined[32..].copy_from_slice(&chunk[1]);
-                    combined
-                } else {
-                    // Se numero dispari, duplica l'ultimo hash
-                    let mut combined = [0u8; 64];
-                    combined[..32].copy_from_slice(&chunk[0]);
-                    combined[32..].copy_from_slice(&chunk[0]);
-                    combined
-                };
-
-                let hash = Sha256::digest(&combined_hash);
-                next_level.push(hash.into());
-            }
-
-            hashes = next_level;
-        }
-
-        hashes[0]
+        crate::hash::merkle_root(&txids)
     }
 
     /// Verifica che il block sia valido
@@ -176,17 +281,26 @@ impl Block {
         true
     }
 
-    /// Dimensione del block in bytes
-    pub fn size(&self) -> usize {
-        bincode::serialize(self)
-            .map(|bytes| bytes.len())
-            .unwrap_or(0)
+    /// Dimensione del block serializzato in bytes
+    pub fn size(&self) -> Result<usize, SerializationError> {
+        crate::transaction::serialized_size(self)
     }
 
     /// Crea genesis block
     pub fn genesis() -> Self {
-        let genesis_tx = Transaction::genesis();
+        Self::genesis_with_transaction(Transaction::genesis())
+    }
+
+    /// Genesis di default con allocazioni iniziali (premine/airdrop) `allocations`
+    ///
+    /// Per reti con messaggio, timestamp o bits propri si usa `GenesisSpec`.
+    pub fn genesis_with_allocations(allocations: Vec<TxOutput>) -> Self {
+        let message = &Transaction::genesis().inputs[0].script_sig;
+        Self::genesis_with_transaction(Transaction::genesis_coinbase(message, allocations))
+    }
 
+    /// Genesis di default con coinbase `genesis_tx`
+    fn genesis_with_transaction(genesis_tx: Transaction) -> Self {
         Self {
             header: BlockHeader {
                 version: crate::PROTOCOL_VERSION,
@@ -198,6 +312,7 @@ impl Block {
                 height: 0,
             },
             transactions: vec![genesis_tx],
+            auxpow: None,
         }
     }
 }
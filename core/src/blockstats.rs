@@ -0,0 +1,193 @@
+//! Statistiche per block (`getblockstats`)
+//!
+//! Calcolate su richiesta da un block salvato: le fee richiedono il valore
+//! degli output spesi, letti dall'indice delle transazioni dello snapshot.
+
+use crate::storage::{ChainSnapshot, StorageError};
+use crate::supply::subsidy_at;
+use crate::{Block, SerializationError, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// Fattore tra weight e dimensione: senza dati witness il weight è 4 × size
+pub const WITNESS_SCALE_FACTOR: u64 = 4;
+
+/// Statistiche di un block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStats {
+    /// Altezza del block
+    pub height: u64,
+    /// Hash del block
+    pub hash: [u8; 32],
+    /// Timestamp del block
+    pub time: u64,
+    /// Numero di transazioni (coinbase inclusa)
+    pub txs: u64,
+    /// Numero di input (coinbase esclusa)
+    pub ins: u64,
+    /// Numero di output (coinbase inclusa)
+    pub outs: u64,
+    /// Dimensione serializzata del block in bytes
+    pub total_size: u64,
+    /// Weight del block
+    pub total_weight: u64,
+    /// Valore SLY nativo degli output (coinbase esclusa)
+    pub total_out: u64,
+    /// Somma delle fee
+    pub total_fee: u64,
+    /// Subsidy del block
+    pub subsidy: u64,
+    /// Fee minima di una transazione
+    pub min_fee: u64,
+    /// Fee massima di una transazione
+    pub max_fee: u64,
+    /// Fee rate minimo (satoshi per byte)
+    pub min_feerate: u64,
+    /// Fee rate mediano (satoshi per byte)
+    pub median_feerate: u64,
+    /// Fee rate massimo (satoshi per byte)
+    pub max_feerate: u64,
+}
+
+/// Calcola le statistiche di un block della chain dello snapshot
+///
+/// Fee e fee rate considerano solo SLY nativo e sono zero per un block
+/// con la sola coinbase.
+pub fn block_stats(db: &ChainSnapshot<'_>, block: &Block) -> Result<BlockStats, BlockStatsError> {
+    let total_size = block.size()? as u64;
+    let mut stats = BlockStats {
+        height: block.header.height,
+        hash: block.hash(),
+        time: block.header.timestamp,
+        txs: block.transactions.len() as u64,
+        ins: 0,
+        outs: 0,
+        total_size,
+        total_weight: total_size * WITNESS_SCALE_FACTOR,
+        total_out: 0,
+        total_fee: 0,
+        subsidy: subsidy_at(block.header.height),
+        min_fee: 0,
+        max_fee: 0,
+        min_feerate: 0,
+        median_feerate: 0,
+        max_feerate: 0,
+    };
+
+    let mut fees = Vec::new();
+    let mut feerates = Vec::new();
+    for tx in &block.transactions {
+        stats.outs += tx.outputs.len() as u64;
+        if tx.is_coinbase() {
+            continue;
+        }
+        stats.ins += tx.inputs.len() as u64;
+
+        stats.total_out = stats.total_out.saturating_add(native_output_value(tx));
+
+        let fee = native_fee(db, tx)?;
+        stats.total_fee = stats.total_fee.saturating_add(fee);
+        fees.push(fee);
+        feerates.push(fee / (tx.size()? as u64).max(1));
+    }
+
+    if !fees.is_empty() {
+        feerates.sort_unstable();
+        stats.min_fee = fees.iter().copied().min().unwrap_or_default();
+        stats.max_fee = fees.iter().copied().max().unwrap_or_default();
+        stats.min_feerate = feerates[0];
+        stats.max_feerate = feerates[feerates.len() - 1];
+        stats.median_feerate = median(&feerates);
+    }
+    Ok(stats)
+}
+
+/// Mediana di valori ordinati (media dei due centrali per un numero pari)
+fn median(sorted: &[u64]) -> u64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        ((sorted[mid - 1] as u128 + sorted[mid] as u128) / 2) as u64
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Fee in SLY nativo di una transazione confermata, dai suoi input letti dall'indice
+pub(crate) fn native_fee(db: &ChainSnapshot<'_>, tx: &Transaction) -> Result<u64, BlockStatsError> {
+    let mut input_value = 0u64;
+    for input in &tx.inputs {
+        let outpoint = &input.previous_output;
+        let (previous, _) = db.get_transaction(&outpoint.txid)?
+            .ok_or(BlockStatsError::MissingInput { txid: outpoint.txid })?;
+        let output = previous.outputs.get(outpoint.vout as usize)
+            .ok_or(BlockStatsError::MissingInput { txid: outpoint.txid })?;
+        if output.is_native_asset() {
+            input_value = input_value.saturating_add(output.value.to_sat());
+        }
+    }
+    Ok(input_value.saturating_sub(native_output_value(tx)))
+}
+
+/// Valore SLY nativo degli output di una transazione
+fn native_output_value(tx: &Transaction) -> u64 {
+    tx.outputs
+        .iter()
+        .filter(|output| output.is_native_asset())
+        .fold(0u64, |total, output| total.saturating_add(output.value.to_sat()))
+}
+
+/// Errori del calcolo delle statistiche
+#[derive(Debug, thiserror::Error)]
+pub enum BlockStatsError {
+    #[error("Spent transaction {} is missing from the index", hex::encode(txid))]
+    MissingInput { txid: [u8; 32] },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockchainDB, OutPoint, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    fn spend(prev: &Transaction, value: u64) -> Transaction {
+        Transaction::new(
+            vec![TxInput::new(OutPoint::new(prev.hash(), 0), vec![1])],
+            vec![TxOutput::to_address(value, b"bob")],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+
+        let funding = [Transaction::coinbase(b"alice", 0, 100_000), Transaction::coinbase(b"carol", 0, 100_000)];
+        let block0 = Block::new([0; 32], funding.to_vec(), 0x1d00ffff, 0);
+        db.store_block(&block0).unwrap();
+
+        let cheap = spend(&funding[0], 100_000 - 1_000);
+        let pricey = spend(&funding[1], 100_000 - 10_000);
+        let coinbase = Transaction::coinbase(b"miner", 1, subsidy_at(1) + 11_000);
+        let block1 = Block::new(block0.hash(), vec![coinbase, cheap.clone(), pricey.clone()], 0x1d00ffff, 1);
+        db.store_block(&block1).unwrap();
+
+        let stats = block_stats(&db.snapshot(), &block1).unwrap();
+        assert_eq!((stats.txs, stats.ins, stats.outs), (3, 2, 3));
+        assert_eq!(stats.total_fee, 11_000);
+        assert_eq!((stats.min_fee, stats.max_fee), (1_000, 10_000));
+        assert_eq!(stats.min_feerate, 1_000 / cheap.size().unwrap() as u64);
+        assert_eq!(stats.max_feerate, 10_000 / pricey.size().unwrap() as u64);
+        assert_eq!(stats.median_feerate, (stats.min_feerate + stats.max_feerate) / 2);
+        assert_eq!(stats.total_out, 200_000 - 11_000);
+        assert_eq!(stats.total_weight, stats.total_size * WITNESS_SCALE_FACTOR);
+
+        let empty = block_stats(&db.snapshot(), &block0).unwrap();
+        assert_eq!((empty.total_fee, empty.median_feerate), (0, 0));
+    }
+}
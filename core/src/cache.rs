@@ -0,0 +1,201 @@
+//! Cache LRU di block e header davanti a RocksDB
+//!
+//! I block recenti vengono riletti di continuo durante i reorg, la
+//! costruzione dei template di mining e i picchi di richieste RPC. La cache
+//! dei block è indicizzata per hash: un block salvato non cambia mai, quindi
+//! le voci non vanno invalidate. La cache degli header è indicizzata per
+//! altezza e segue la chain attiva: ogni block connesso o scollegato
+//! invalida la propria altezza.
+
+use crate::{Block, BlockHeader};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+
+/// Capacità delle cache (numero di voci, 0 disabilita)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Block completi per hash
+    pub blocks: usize,
+    /// Header della chain attiva per altezza
+    pub headers: usize,
+}
+
+impl CacheConfig {
+    /// Cache disabilitate: ogni lettura va al database
+    pub fn disabled() -> Self {
+        Self { blocks: 0, headers: 0 }
+    }
+}
+
+impl Default for CacheConfig {
+    /// 256 block (al massimo ~1 GB con block da 4 MB, tipicamente pochi MB)
+    /// e 50.000 header (~10 MB)
+    fn default() -> Self {
+        Self { blocks: 256, headers: 50_000 }
+    }
+}
+
+/// Contatori di hit/miss delle cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Block serviti dalla cache
+    pub block_hits: u64,
+    /// Block letti dal database
+    pub block_misses: u64,
+    /// Header serviti dalla cache
+    pub header_hits: u64,
+    /// Header letti dal database
+    pub header_misses: u64,
+    /// Block attualmente in cache
+    pub cached_blocks: usize,
+    /// Header attualmente in cache
+    pub cached_headers: usize,
+}
+
+impl CacheStats {
+    /// Frazione delle letture di block servite dalla cache
+    pub fn block_hit_rate(&self) -> f64 {
+        hit_rate(self.block_hits, self.block_misses)
+    }
+
+    /// Frazione delle letture di header servite dalla cache
+    pub fn header_hit_rate(&self) -> f64 {
+        hit_rate(self.header_hits, self.header_misses)
+    }
+}
+
+fn hit_rate(hits: u64, misses: u64) -> f64 {
+    match hits + misses {
+        0 => 0.0,
+        total => hits as f64 / total as f64,
+    }
+}
+
+/// Cache di block e header con i relativi contatori
+pub(crate) struct ChainCache {
+    blocks: Option<LruCache<[u8; 32], Block>>,
+    headers: Option<LruCache<u64, BlockHeader>>,
+    /// Incrementata a ogni invalidazione degli header: un lettore che ha
+    /// letto dal database prima di un'invalidazione non inserisce dati vecchi
+    generation: u64,
+    stats: CacheStats,
+}
+
+impl ChainCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            blocks: NonZeroUsize::new(config.blocks).map(LruCache::new),
+            headers: NonZeroUsize::new(config.headers).map(LruCache::new),
+            generation: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Block in cache, contando hit o miss
+    pub(crate) fn block(&mut self, hash: &[u8; 32]) -> Option<Block> {
+        let block = self.blocks.as_mut()?.get(hash).cloned();
+        match block {
+            Some(_) => self.stats.block_hits += 1,
+            None => self.stats.block_misses += 1,
+        }
+        block
+    }
+
+    pub(crate) fn insert_block(&mut self, block: &Block) {
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.put(block.hash(), block.clone());
+        }
+    }
+
+    /// Header in cache per altezza, contando hit o miss
+    pub(crate) fn header(&mut self, height: u64) -> Option<BlockHeader> {
+        let header = self.headers.as_mut()?.get(&height).cloned();
+        match header {
+            Some(_) => self.stats.header_hits += 1,
+            None => self.stats.header_misses += 1,
+        }
+        header
+    }
+
+    /// Generazione corrente, da passare a `insert_header`
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Inserisce un header letto quando la generazione era `generation`
+    pub(crate) fn insert_header(&mut self, height: u64, header: BlockHeader, generation: u64) {
+        if generation != self.generation {
+            return;
+        }
+        if let Some(headers) = self.headers.as_mut() {
+            headers.put(height, header);
+        }
+    }
+
+    /// Invalida l'header della chain attiva a `height`
+    pub(crate) fn invalidate_height(&mut self, height: u64) {
+        self.generation += 1;
+        if let Some(headers) = self.headers.as_mut() {
+            headers.pop(&height);
+        }
+    }
+
+    /// Svuota la cache degli header (es. dopo la cancellazione dell'indice)
+    pub(crate) fn invalidate_headers(&mut self) {
+        self.generation += 1;
+        if let Some(headers) = self.headers.as_mut() {
+            headers.clear();
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached_blocks: self.blocks.as_ref().map_or(0, LruCache::len),
+            cached_headers: self.headers.as_ref().map_or(0, LruCache::len),
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+
+    fn block(height: u64) -> Block {
+        Block::new([0; 32], vec![Transaction::coinbase(b"miner", height, 50)], 0x1d00ffff, height)
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = ChainCache::new(CacheConfig { blocks: 2, headers: 2 });
+        let blocks: Vec<Block> = (0..3).map(block).collect();
+        for block in &blocks {
+            cache.insert_block(block);
+        }
+
+        // Il block meno usato di recente è uscito
+        assert!(cache.block(&blocks[0].hash()).is_none());
+        assert_eq!(cache.block(&blocks[2].hash()).unwrap().hash(), blocks[2].hash());
+        let stats = cache.stats();
+        assert_eq!((stats.block_hits, stats.block_misses, stats.cached_blocks), (1, 1, 2));
+        assert_eq!(stats.block_hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_stale_header_not_inserted() {
+        let mut cache = ChainCache::new(CacheConfig::default());
+        let generation = cache.generation();
+        cache.invalidate_height(5);
+        cache.insert_header(5, block(5).header, generation);
+        assert!(cache.header(5).is_none());
+
+        cache.insert_header(5, block(5).header, cache.generation());
+        assert!(cache.header(5).is_some());
+
+        let mut disabled = ChainCache::new(CacheConfig::disabled());
+        disabled.insert_block(&block(1));
+        assert!(disabled.block(&block(1).hash()).is_none());
+        assert_eq!(disabled.stats(), CacheStats::default());
+    }
+}
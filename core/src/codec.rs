@@ -0,0 +1,114 @@
+//! Decodifica limitata di transazioni e block
+//!
+//! I bytes ricevuti da peer, client RPC e Tendermint non sono fidati: la
+//! loro dimensione viene confrontata con un limite prima della decodifica
+//! bincode, e il decoder stesso non legge oltre quel limite, così una
+//! lunghezza dichiarata enorme non fa allocare memoria. Un payload troppo
+//! grande ha un errore distinto da quello dei dati malformati, in modo che
+//! ABCI e RPC possano rispondere con codici diversi.
+//!
+//! Il layout di una transazione dipende dalla sua versione (i primi 4
+//! bytes): `decode_transaction` sceglie il decoder dal `TxFormat` e
+//! rifiuta le versioni che questo nodo non conosce. Le transazioni dentro
+//! un block sono verificate dalla validazione.
+
+use crate::{Block, Transaction, TxFormat, MAX_BLOCK_SIZE};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+
+/// Dimensione massima di una transazione da decodificare: deve entrare in un block
+pub const MAX_TX_DECODE_SIZE: usize = MAX_BLOCK_SIZE;
+
+/// Dimensione massima di un block da decodificare
+///
+/// La governance può alzare la dimensione dei block fino a 32 volte quella
+/// iniziale: un block più grande non può comunque essere valido.
+pub const MAX_BLOCK_DECODE_SIZE: usize = 32 * MAX_BLOCK_SIZE;
+
+/// Decodifica una transazione di al massimo `MAX_TX_DECODE_SIZE` bytes
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, DecodeError> {
+    if bytes.len() > MAX_TX_DECODE_SIZE {
+        return Err(DecodeError::Oversized { size: bytes.len(), max: MAX_TX_DECODE_SIZE });
+    }
+    let Some(version) = bytes.get(..4).map(|prefix| u32::from_le_bytes(prefix.try_into().unwrap())) else {
+        return Err(DecodeError::Malformed("transaction shorter than its version".to_string()));
+    };
+    match TxFormat::from_version(version) {
+        Some(TxFormat::V1 | TxFormat::V2) => decode_with_limit(bytes, MAX_TX_DECODE_SIZE),
+        None => Err(DecodeError::UnknownVersion(version)),
+    }
+}
+
+/// Decodifica un block di al massimo `MAX_BLOCK_DECODE_SIZE` bytes
+pub fn decode_block(bytes: &[u8]) -> Result<Block, DecodeError> {
+    decode_with_limit(bytes, MAX_BLOCK_DECODE_SIZE)
+}
+
+/// Decodifica bincode (stesso formato di `bincode::deserialize`) con un
+/// limite di `limit` bytes
+pub fn decode_with_limit<T: DeserializeOwned>(bytes: &[u8], limit: usize) -> Result<T, DecodeError> {
+    if bytes.len() > limit {
+        return Err(DecodeError::Oversized { size: bytes.len(), max: limit });
+    }
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit as u64)
+        .deserialize(bytes)
+        .map_err(|e| DecodeError::Malformed(e.to_string()))
+}
+
+/// Errori di decodifica
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Payload of {size} bytes exceeds the maximum of {max} bytes")]
+    Oversized { size: usize, max: usize },
+
+    #[error("Malformed payload: {0}")]
+    Malformed(String),
+
+    #[error("Unknown transaction version {0}")]
+    UnknownVersion(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutPoint, TxInput, TxOutput};
+
+    #[test]
+    fn test_decode_limits() {
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([1; 32], 0), vec![7; 64])],
+            vec![TxOutput::to_address(50, b"alice")],
+            0,
+        );
+        let bytes = bincode::serialize(&tx).unwrap();
+        assert_eq!(decode_transaction(&bytes).unwrap(), tx);
+
+        assert_eq!(
+            decode_with_limit::<Transaction>(&bytes, bytes.len() - 1),
+            Err(DecodeError::Oversized { size: bytes.len(), max: bytes.len() - 1 })
+        );
+        assert!(matches!(decode_transaction(&vec![0; MAX_TX_DECODE_SIZE + 1]), Err(DecodeError::Oversized { .. })));
+        assert!(matches!(decode_block(b"garbage"), Err(DecodeError::Malformed(_))));
+
+        // Lunghezza dichiarata oltre il limite con pochi bytes reali
+        let mut huge = 1u32.to_le_bytes().to_vec();
+        huge.extend_from_slice(&[0xff; 8]);
+        huge.extend_from_slice(&[0; 16]);
+        assert!(matches!(decode_transaction(&huge), Err(DecodeError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_decode_dispatches_by_version() {
+        let mut tx = Transaction::new(vec![TxInput::new(OutPoint::new([1; 32], 0), vec![])], vec![], 0);
+        assert_eq!(tx.format(), Some(TxFormat::CURRENT));
+        let expiring = tx.clone().with_expiry(50);
+        assert_eq!(decode_transaction(&bincode::serialize(&expiring).unwrap()), Ok(expiring));
+        tx.version = 3;
+        let bytes = bincode::serialize(&tx).unwrap();
+        assert_eq!(decode_transaction(&bytes), Err(DecodeError::UnknownVersion(3)));
+        assert!(matches!(decode_transaction(&[1, 0]), Err(DecodeError::Malformed(_))));
+    }
+}
@@ -1,6 +1,9 @@
 //! Difficulty adjustment algorithm per Sedly blockchain
 
+use crate::params::{ChainParams, RetargetWindow};
+use crate::uint::U256;
 use crate::{Block, BlockHeader};
+use serde::{Deserialize, Serialize};
 use std::cmp;
 
 /// Difficulty adjustment manager
@@ -13,6 +16,29 @@ pub struct DifficultyAdjuster {
     max_adjustment_factor: f64,
     /// Minimo moltiplicatore per adjustment (0.25 = 1/4)
     min_adjustment_factor: f64,
+    /// Finestra di block misurata ad ogni retarget
+    retarget_window: RetargetWindow,
+}
+
+/// Riepilogo di un'epoca di difficulty per serie storiche
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochSummary {
+    /// Indice dell'epoca (height / adjustment_interval)
+    pub epoch: u64,
+    /// Prima altezza dell'epoca inclusa nel riepilogo
+    pub start_height: u64,
+    /// Ultima altezza dell'epoca inclusa nel riepilogo
+    pub end_height: u64,
+    /// Timestamp del primo block dell'epoca
+    pub start_time: u64,
+    /// Difficulty bits dell'epoca
+    pub bits: u32,
+    /// Difficulty relativa al genesis
+    pub difficulty: f64,
+    /// Hash rate stimato (H/s) dal lavoro per block e intervallo medio
+    pub estimated_hashrate: f64,
+    /// Intervallo medio tra block in secondi (0 se non misurabile)
+    pub average_block_interval: f64,
 }
 
 /// Risultato del calcolo di difficulty adjustment
@@ -44,9 +70,20 @@ impl DifficultyAdjuster {
             adjustment_interval: crate::DIFFICULTY_ADJUSTMENT_INTERVAL,
             max_adjustment_factor: crate::MAX_DIFFICULTY_ADJUSTMENT,
             min_adjustment_factor: 1.0 / crate::MAX_DIFFICULTY_ADJUSTMENT,
+            retarget_window: RetargetWindow::EpochAligned,
         }
     }
 
+    /// Crea difficulty adjuster dai parametri di una rete
+    pub fn from_params(params: &ChainParams) -> Self {
+        Self::with_params(
+            params.target_block_time,
+            params.difficulty_adjustment_interval,
+            params.max_difficulty_adjustment,
+        )
+        .with_retarget_window(params.retarget_window)
+    }
+
     /// Crea difficulty adjuster con parametri custom
     pub fn with_params(
         target_block_time: u64,
@@ -58,45 +95,114 @@ impl DifficultyAdjuster {
             adjustment_interval,
             max_adjustment_factor,
             min_adjustment_factor: 1.0 / max_adjustment_factor,
+            retarget_window: RetargetWindow::EpochAligned,
         }
     }
 
+    /// Imposta la finestra di retarget
+    pub fn with_retarget_window(mut self, retarget_window: RetargetWindow) -> Self {
+        self.retarget_window = retarget_window;
+        self
+    }
+
+    /// Finestra di retarget in uso
+    pub fn retarget_window(&self) -> RetargetWindow {
+        self.retarget_window
+    }
+
+    /// Numero di block necessari per un retarget
+    pub fn window_len(&self) -> usize {
+        self.retarget_window.window_len(self.adjustment_interval)
+    }
+
+    /// Altezza del primo block della finestra per il retarget all'altezza data
+    ///
+    /// Ritorna `None` se la finestra partirebbe prima del genesis.
+    pub fn window_start_height(&self, retarget_height: u64) -> Option<u64> {
+        retarget_height.checked_sub(self.window_len() as u64)
+    }
+
+    /// Calcola il fattore di aggiustamento (già limitato) dai timestamp della finestra
+    pub fn calculate_adjustment_factor(&self, timestamps: &[u64]) -> Result<f64, DifficultyError> {
+        let window_len = self.window_len();
+        if timestamps.len() < window_len {
+            return Err(DifficultyError::InsufficientBlocks {
+                required: window_len,
+                provided: timestamps.len(),
+            });
+        }
+
+        let window = &timestamps[timestamps.len() - window_len..];
+        let actual_time = window[window_len - 1].saturating_sub(window[0]);
+        let expected_time = self.expected_timespan();
+
+        let raw_adjustment_factor = expected_time as f64 / actual_time as f64;
+
+        Ok(raw_adjustment_factor
+            .max(self.min_adjustment_factor)
+            .min(self.max_adjustment_factor))
+    }
+
+    /// Tempo atteso per la finestra di retarget
+    fn expected_timespan(&self) -> u64 {
+        self.target_block_time * self.retarget_window.expected_intervals(self.adjustment_interval)
+    }
+
     /// Calcola la nuova difficulty basata sui block recenti
+    ///
+    /// Vengono usati gli ultimi `window_len()` block della slice.
     pub fn calculate_next_difficulty(
         &self,
         recent_blocks: &[Block],
         current_bits: u32,
     ) -> Result<DifficultyAdjustment, DifficultyError> {
         // Verifica che abbiamo abbastanza blocks
-        if recent_blocks.len() < self.adjustment_interval as usize {
+        let window_len = self.window_len();
+        if recent_blocks.len() < window_len {
             return Err(DifficultyError::InsufficientBlocks {
-                required: self.adjustment_interval as usize,
+                required: window_len,
                 provided: recent_blocks.len(),
             });
         }
+        let window = &recent_blocks[recent_blocks.len() - window_len..];
 
         // Verifica che i block siano in ordine crescente di altezza
-        if !self.verify_block_sequence(recent_blocks)? {
+        if !self.verify_block_sequence(window)? {
             return Err(DifficultyError::InvalidBlockSequence);
         }
 
-        // Calcola il tempo effettivo trascorso
-        let first_block = &recent_blocks[0];
-        let last_block = &recent_blocks[recent_blocks.len() - 1];
+        let timestamps: Vec<u64> = window.iter().map(|block| block.header.timestamp).collect();
+        self.calculate_next_difficulty_from_timestamps(&timestamps, current_bits)
+    }
 
-        let actual_time = last_block.header.timestamp - first_block.header.timestamp;
-        let expected_time = self.target_block_time * (self.adjustment_interval - 1);
+    /// Calcola la nuova difficulty dai timestamp di block consecutivi
+    ///
+    /// Vengono usati gli ultimi `window_len()` timestamp; il chiamante
+    /// garantisce che appartengano a block di altezze consecutive.
+    pub fn calculate_next_difficulty_from_timestamps(
+        &self,
+        timestamps: &[u64],
+        current_bits: u32,
+    ) -> Result<DifficultyAdjustment, DifficultyError> {
+        let window_len = self.window_len();
+        if timestamps.len() < window_len {
+            return Err(DifficultyError::InsufficientBlocks {
+                required: window_len,
+                provided: timestamps.len(),
+            });
+        }
+        let timestamps = &timestamps[timestamps.len() - window_len..];
+        if timestamps.windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(DifficultyError::InvalidBlockSequence);
+        }
 
         // Calcola tempo medio per block
-        let actual_time_per_block = actual_time as f64 / (self.adjustment_interval - 1) as f64;
+        let measured_intervals = self.retarget_window.measured_intervals(self.adjustment_interval);
+        let actual_time = timestamps[window_len - 1] - timestamps[0];
+        let actual_time_per_block = actual_time as f64 / measured_intervals as f64;
 
-        // Calcola fattore di aggiustamento
-        let raw_adjustment_factor = expected_time as f64 / actual_time as f64;
-
-        // Applica limiti di aggiustamento
-        let adjustment_factor = raw_adjustment_factor
-            .max(self.min_adjustment_factor)
-            .min(self.max_adjustment_factor);
+        // Calcola fattore di aggiustamento (con limiti)
+        let adjustment_factor = self.calculate_adjustment_factor(timestamps)?;
 
         // Calcola nuova difficulty
         let new_bits = if adjustment_factor == 1.0 {
@@ -200,17 +306,62 @@ impl DifficultyAdjuster {
 
     /// Calcola hash rate stimato per una difficulty
     pub fn estimate_network_hashrate(&self, bits: u32, actual_block_time: f64) -> f64 {
-        let target = crate::block::bits_to_target(bits);
-        let target_u64 = u64::from_be_bytes([
-            target[24], target[25], target[26], target[27],
-            target[28], target[29], target[30], target[31]
-        ]);
+        // Hash rate = hash attesi per block / tempo
+        block_work(bits).to_f64() / actual_block_time
+    }
 
-        // Hash rate = difficulty / tempo
-        let max_target = u64::MAX;
-        let difficulty = max_target as f64 / target_u64 as f64;
+    /// Riepiloga per epoca una sequenza consecutiva di header
+    ///
+    /// L'intervallo tra l'ultimo header di un'epoca e il primo della successiva
+    /// viene attribuito alla successiva.
+    pub fn summarize_epochs(&self, headers: &[BlockHeader]) -> Vec<EpochSummary> {
+        let mut summaries: Vec<EpochSummary> = Vec::new();
+        let mut interval_sum = 0u64;
+        let mut interval_count = 0u64;
+
+        for (i, header) in headers.iter().enumerate() {
+            let epoch = header.height / self.adjustment_interval;
+
+            if summaries.last().map(|summary| summary.epoch) != Some(epoch) {
+                Self::finish_epoch(summaries.last_mut(), interval_sum, interval_count);
+                interval_sum = 0;
+                interval_count = 0;
+
+                summaries.push(EpochSummary {
+                    epoch,
+                    start_height: header.height,
+                    end_height: header.height,
+                    start_time: header.timestamp,
+                    bits: header.bits,
+                    difficulty: difficulty_from_bits(header.bits),
+                    estimated_hashrate: 0.0,
+                    average_block_interval: 0.0,
+                });
+            }
 
-        difficulty / actual_block_time
+            if i > 0 {
+                interval_sum += header.timestamp.saturating_sub(headers[i - 1].timestamp);
+                interval_count += 1;
+            }
+
+            if let Some(summary) = summaries.last_mut() {
+                summary.end_height = header.height;
+            }
+        }
+
+        Self::finish_epoch(summaries.last_mut(), interval_sum, interval_count);
+        summaries
+    }
+
+    /// Completa intervallo medio e hash rate di un'epoca
+    fn finish_epoch(summary: Option<&mut EpochSummary>, interval_sum: u64, interval_count: u64) {
+        if let Some(summary) = summary {
+            if interval_count > 0 && interval_sum > 0 {
+                summary.average_block_interval = interval_sum as f64 / interval_count as f64;
+                summary.estimated_hashrate =
+                    block_work(summary.bits).to_f64() / summary.average_block_interval;
+            }
+        }
     }
 
     /// Predice il prossimo aggiustamento in base ai tempi correnti
@@ -251,6 +402,26 @@ impl DifficultyAdjuster {
     }
 }
 
+/// Lavoro atteso (numero di hash) per trovare un block con i bits dati
+///
+/// Calcolato come 2^256 / (target + 1) con aritmetica a 256 bit.
+pub fn block_work(bits: u32) -> U256 {
+    let target = U256::from_be_bytes(&crate::block::bits_to_target(bits));
+    match target.checked_add(&U256::ONE) {
+        Some(divisor) if !target.is_zero() => (!target / divisor) + U256::ONE,
+        _ => U256::ZERO,
+    }
+}
+
+/// Difficulty relativa al target del genesis (1.0 = difficulty iniziale)
+pub fn difficulty_from_bits(bits: u32) -> f64 {
+    let genesis_work = block_work(DifficultyAdjuster::genesis_difficulty()).to_f64();
+    if genesis_work == 0.0 {
+        return 0.0;
+    }
+    block_work(bits).to_f64() / genesis_work
+}
+
 /// Errori del difficulty adjustment
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum DifficultyError {
@@ -322,7 +493,7 @@ impl DifficultyAdjuster {
         let first_timestamp = blocks.first().unwrap().header.timestamp;
         let last_timestamp = blocks.last().unwrap().header.timestamp;
         let actual_time = last_timestamp - first_timestamp;
-        let expected_time = self.target_block_time * (self.adjustment_interval - 1);
+        let expected_time = self.expected_timespan();
 
         format!(
             "Difficulty Adjustment Debug:\n\
@@ -475,6 +646,40 @@ mod tests {
         assert!(hashrate > 0.0);
     }
 
+    #[test]
+    fn test_block_work() {
+        // Target più basso (exponent minore) = più lavoro
+        let easy = block_work(0x1d00ffff);
+        let hard = block_work(0x1c00ffff);
+        assert!(!easy.is_zero());
+        assert_eq!(hard / easy, U256::from(256u64));
+        assert_eq!(block_work(0), U256::ZERO);
+
+        assert_eq!(difficulty_from_bits(0x1d00ffff), 1.0);
+        assert!((difficulty_from_bits(0x1c00ffff) - 256.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_summarize_epochs() {
+        let adjuster = DifficultyAdjuster::with_params(120, 10, 4.0);
+        let headers: Vec<BlockHeader> = create_test_blocks(25, 60, 0x1d00ffff)
+            .into_iter()
+            .map(|block| block.header)
+            .collect();
+
+        let summaries = adjuster.summarize_epochs(&headers);
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0].start_height, 0);
+        assert_eq!(summaries[0].end_height, 9);
+        assert_eq!(summaries[2].start_height, 20);
+        assert_eq!(summaries[2].end_height, 24);
+        assert_eq!(summaries[1].average_block_interval, 60.0);
+        assert_eq!(
+            summaries[1].estimated_hashrate,
+            block_work(0x1d00ffff).to_f64() / 60.0
+        );
+    }
+
     #[test]
     fn test_adjustment_formatting() {
         let adjustment = DifficultyAdjustment {
@@ -490,6 +695,83 @@ mod tests {
         assert!(formatted.contains("50.00%"));
     }
 
+    /// Simula `epochs` epoche con hashrate costante e ritorna il block time medio
+    /// dell'ultima epoca
+    fn simulate_average_block_time(window: RetargetWindow, epochs: usize) -> f64 {
+        let adjuster = DifficultyAdjuster::new().with_retarget_window(window);
+        let interval = crate::DIFFICULTY_ADJUSTMENT_INTERVAL as usize;
+
+        // Difficulty relativa: 1.0 = un block ogni TARGET_BLOCK_TIME con l'hashrate simulato
+        let mut difficulty = 2.0f64;
+        let mut time = 1704067200.0f64;
+        let mut timestamps = vec![time as u64];
+        let mut last_epoch_average = 0.0;
+
+        for _ in 0..epochs {
+            let spacing = crate::TARGET_BLOCK_TIME as f64 * difficulty;
+            let epoch_start = time;
+
+            for _ in 0..interval {
+                time += spacing;
+                timestamps.push(time.round() as u64);
+            }
+
+            last_epoch_average = (time - epoch_start) / interval as f64;
+            difficulty *= adjuster.calculate_adjustment_factor(&timestamps).unwrap();
+        }
+
+        last_epoch_average
+    }
+
+    #[test]
+    fn test_retarget_window_drift_simulation() {
+        let target = crate::TARGET_BLOCK_TIME as f64;
+        let drift = |window| (simulate_average_block_time(window, 10) - target) / target;
+
+        // Legacy misura 143 intervalli contro 144 attesi: block ~0.70% più lenti
+        let legacy_drift = drift(RetargetWindow::Legacy);
+        assert!((legacy_drift - 1.0 / 143.0).abs() < 1e-4, "legacy drift {}", legacy_drift);
+
+        // Le finestre corrette convergono al target
+        assert!(drift(RetargetWindow::EpochAligned).abs() < 1e-4);
+        assert!(drift(RetargetWindow::Overlapping).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_overlapping_window_measures_epoch_boundary() {
+        let interval = crate::DIFFICULTY_ADJUSTMENT_INTERVAL;
+
+        // Intervallo anomalo tra l'ultimo block dell'epoca precedente e il primo di questa
+        let mut timestamps = vec![0u64];
+        for i in 0..interval {
+            timestamps.push(10_000 + i * 120);
+        }
+
+        let aligned = DifficultyAdjuster::new().with_retarget_window(RetargetWindow::EpochAligned);
+        assert_eq!(aligned.calculate_adjustment_factor(&timestamps).unwrap(), 1.0);
+
+        let overlapping = DifficultyAdjuster::new().with_retarget_window(RetargetWindow::Overlapping);
+        assert!(overlapping.calculate_adjustment_factor(&timestamps).unwrap() < 1.0);
+    }
+
+    #[test]
+    fn test_overlapping_window_requires_extra_block() {
+        let adjuster = DifficultyAdjuster::from_params(&crate::ChainParams::testnet());
+        assert_eq!(adjuster.window_len(), 145);
+        assert_eq!(adjuster.window_start_height(144), None);
+        assert_eq!(adjuster.window_start_height(288), Some(143));
+
+        let blocks = create_test_blocks(144, 120, 0x1d00ffff);
+        match adjuster.calculate_next_difficulty(&blocks, 0x1d00ffff) {
+            Err(DifficultyError::InsufficientBlocks { required: 145, provided: 144 }) => (),
+            other => panic!("Unexpected result: {:?}", other.map(|a| a.new_bits)),
+        }
+
+        let blocks = create_test_blocks(145, 120, 0x1d00ffff);
+        let adjustment = adjuster.calculate_next_difficulty(&blocks, 0x1d00ffff).unwrap();
+        assert!(!adjustment.needs_adjustment);
+    }
+
     #[test]
     fn test_prediction() {
         let adjuster = DifficultyAdjuster::new();
@@ -0,0 +1,268 @@
+//! Iniezione di guasti nello storage per i test di robustezza
+//!
+//! Un `FaultInjector` installato su un `BlockchainDB` fa fallire le
+//! scritture dei block, le fa fallire dopo averle applicate (il batch è
+//! su disco ma il chiamante riceve un errore, come per un fsync fallito)
+//! e rallenta le letture, ognuno con una probabilità configurabile. Il
+//! generatore è deterministico dato il seed, così un test che fallisce si
+//! riproduce. Disponibile nei test e con la feature `fault-injection`.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Probabilità e parametri dei guasti
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    /// Seed del generatore pseudo-casuale
+    pub seed: u64,
+    /// Probabilità che una scrittura fallisca senza applicare il batch
+    pub write_error: f64,
+    /// Probabilità che una scrittura fallisca dopo aver applicato il batch
+    pub partial_write: f64,
+    /// Probabilità che una lettura venga rallentata
+    pub slow_read: f64,
+    /// Ritardo di una lettura lenta
+    pub read_delay: Duration,
+    /// Guasti iniettati al massimo, poi lo storage torna affidabile (None = illimitati)
+    pub max_faults: Option<u64>,
+}
+
+impl FaultConfig {
+    /// Nessun guasto, generatore inizializzato con `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            write_error: 0.0,
+            partial_write: 0.0,
+            slow_read: 0.0,
+            read_delay: Duration::from_millis(10),
+            max_faults: None,
+        }
+    }
+
+    /// Imposta la probabilità di scritture fallite
+    pub fn with_write_errors(mut self, probability: f64) -> Self {
+        self.write_error = probability;
+        self
+    }
+
+    /// Imposta la probabilità di scritture fallite dopo essere state applicate
+    pub fn with_partial_writes(mut self, probability: f64) -> Self {
+        self.partial_write = probability;
+        self
+    }
+
+    /// Imposta probabilità e durata delle letture lente
+    pub fn with_slow_reads(mut self, probability: f64, delay: Duration) -> Self {
+        self.slow_read = probability;
+        self.read_delay = delay;
+        self
+    }
+
+    /// Limita il numero di guasti iniettati
+    pub fn with_max_faults(mut self, max_faults: u64) -> Self {
+        self.max_faults = Some(max_faults);
+        self
+    }
+}
+
+/// Guasti iniettati finora
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    /// Scritture fallite senza effetti
+    pub write_errors: u64,
+    /// Scritture applicate ma riportate come fallite
+    pub partial_writes: u64,
+    /// Letture rallentate
+    pub slow_reads: u64,
+}
+
+impl FaultStats {
+    /// Totale dei guasti iniettati
+    pub fn total(&self) -> u64 {
+        self.write_errors + self.partial_writes + self.slow_reads
+    }
+}
+
+/// Guasto di una scrittura
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteFault {
+    /// Il batch non viene scritto
+    Error,
+    /// Il batch viene scritto ma la scrittura risulta fallita
+    Partial,
+}
+
+/// Decide quali operazioni dello storage falliscono
+#[derive(Debug)]
+pub struct FaultInjector {
+    config: FaultConfig,
+    /// Stato del generatore (splitmix64) e contatori
+    state: Mutex<(u64, FaultStats)>,
+}
+
+impl FaultInjector {
+    /// Crea un injector con la configurazione data
+    pub fn new(config: FaultConfig) -> Self {
+        let seed = config.seed;
+        Self {
+            config,
+            state: Mutex::new((seed, FaultStats::default())),
+        }
+    }
+
+    /// Guasti iniettati finora
+    pub fn stats(&self) -> FaultStats {
+        self.lock().1
+    }
+
+    /// Guasto da applicare alla prossima scrittura
+    pub(crate) fn write_fault(&self) -> Option<WriteFault> {
+        let mut state = self.lock();
+        if self.exhausted(&state.1) {
+            return None;
+        }
+        if roll(&mut state.0, self.config.write_error) {
+            state.1.write_errors += 1;
+            Some(WriteFault::Error)
+        } else if roll(&mut state.0, self.config.partial_write) {
+            state.1.partial_writes += 1;
+            Some(WriteFault::Partial)
+        } else {
+            None
+        }
+    }
+
+    /// Rallenta la lettura corrente se estratta
+    pub(crate) fn delay_read(&self) {
+        let slow = {
+            let mut state = self.lock();
+            let slow = !self.exhausted(&state.1) && roll(&mut state.0, self.config.slow_read);
+            if slow {
+                state.1.slow_reads += 1;
+            }
+            slow
+        };
+        if slow {
+            std::thread::sleep(self.config.read_delay);
+        }
+    }
+
+    fn exhausted(&self, stats: &FaultStats) -> bool {
+        self.config.max_faults.is_some_and(|max| stats.total() >= max)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (u64, FaultStats)> {
+        self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Estrae un evento con la probabilità data (splitmix64)
+fn roll(state: &mut u64, probability: f64) -> bool {
+    if probability <= 0.0 {
+        return false;
+    }
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    ((z >> 11) as f64 / (1u64 << 53) as f64) < probability
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BlockchainDB, CancellationToken, StorageError};
+    use crate::validation::block_subsidy;
+    use crate::{Block, OutPoint, Transaction};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn next_block(db: &BlockchainDB) -> Block {
+        let height = db.get_height().unwrap() + 1;
+        let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+        Block::new(db.get_best_block_hash().unwrap(), vec![coinbase], 0x1d00ffff, height)
+    }
+
+    fn utxo_commitment(db: &BlockchainDB) -> [u8; 32] {
+        db.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap().hash
+    }
+
+    #[test]
+    fn test_roll_is_deterministic() {
+        let draws = |seed| {
+            let mut state = seed;
+            (0..1_000).map(|_| roll(&mut state, 0.3)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(7), draws(8));
+        let hits = draws(7).iter().filter(|hit| **hit).count();
+        assert!((250..350).contains(&hits), "{} hits", hits);
+
+        let mut state = 1;
+        assert!((0..100).all(|_| !roll(&mut state, 0.0)));
+        assert!((0..100).all(|_| roll(&mut state, 1.0)));
+    }
+
+    #[test]
+    fn test_failed_write_leaves_state_untouched() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        db.store_block(&next_block(&db)).unwrap();
+        let (tip, commitment) = (db.get_best_block_hash().unwrap(), utxo_commitment(&db));
+
+        let injector = Arc::new(FaultInjector::new(FaultConfig::new(1).with_write_errors(1.0).with_max_faults(2)));
+        db.inject_faults(Some(injector.clone()));
+        let block = next_block(&db);
+        for _ in 0..2 {
+            assert!(matches!(db.store_block(&block), Err(StorageError::Write(_))));
+            assert_eq!((db.get_best_block_hash().unwrap(), utxo_commitment(&db)), (tip, commitment));
+            assert!(db.get_block(&block.hash()).unwrap().is_none());
+        }
+
+        // Esauriti i guasti lo stesso block si scrive normalmente
+        db.store_block(&block).unwrap();
+        assert_eq!(db.get_best_block_hash().unwrap(), block.hash());
+        assert_eq!(injector.stats(), FaultStats { write_errors: 2, ..FaultStats::default() });
+    }
+
+    #[test]
+    fn test_partial_write_is_detectable() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        db.inject_faults(Some(Arc::new(FaultInjector::new(FaultConfig::new(1).with_partial_writes(1.0)))));
+
+        // La scrittura risulta fallita ma il block è connesso per intero
+        let block = next_block(&db);
+        assert!(matches!(db.store_block(&block), Err(StorageError::Write(_))));
+        assert_eq!(db.get_best_block_hash().unwrap(), block.hash());
+        let coinbase = OutPoint::new(block.transactions[0].hash(), 0);
+        assert!(db.get_utxo(&coinbase).unwrap().is_some());
+
+        // Riscrivere lo stesso block è rifiutato senza toccare la chain
+        db.inject_faults(None);
+        assert!(matches!(db.store_block(&block), Err(StorageError::DuplicateBlock { .. })));
+        assert_eq!(db.get_height().unwrap(), 1);
+        assert_eq!(db.get_block_by_height(1).unwrap().unwrap().hash(), block.hash());
+    }
+
+    #[test]
+    fn test_slow_reads_return_correct_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        let block = next_block(&db);
+        db.store_block(&block).unwrap();
+
+        let injector = Arc::new(FaultInjector::new(
+            FaultConfig::new(3).with_slow_reads(1.0, Duration::from_millis(1)),
+        ));
+        db.inject_faults(Some(injector.clone()));
+        assert_eq!(db.get_block_by_height(1).unwrap().unwrap().hash(), block.hash());
+        assert_eq!(db.get_height().unwrap(), 1);
+        assert!(injector.stats().slow_reads > 0);
+    }
+}
@@ -0,0 +1,345 @@
+//! Simulazione di politiche alternative di fee e dimensione dei block
+//!
+//! Prima di cambiare fee rate minimo o dimensione dei block la governance
+//! vuole sapere cosa sarebbe successo con valori diversi. Il simulatore
+//! riprende le transazioni degli ultimi block, disponibili dal timestamp del
+//! block precedente, e quelle della mempool, disponibili dalla ricezione, e
+//! le rimette in block agli stessi orari riempiendoli per fee rate come fa
+//! il miner. Dopo l'ultimo block storico ne segue uno ogni
+//! `block_interval` secondi finché la coda non si svuota (al massimo
+//! [`MAX_EXTRA_BLOCKS`]).
+//!
+//! Le dipendenze tra transazioni non sono modellate: un figlio può entrare
+//! in un block prima del padre. Per tempi di conferma e ricavi aggregati
+//! l'approssimazione basta.
+
+use crate::blockstats::{native_fee, BlockStatsError};
+use crate::mempool::Mempool;
+use crate::standalone::COINBASE_RESERVED_SIZE;
+use crate::storage::{ChainSnapshot, StorageError};
+use crate::SerializationError;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Block simulati al massimo dopo l'ultimo block storico
+pub const MAX_EXTRA_BLOCKS: u64 = 1_000;
+
+/// Transazioni che non entrano nello spazio rimasto dopo le quali il block è considerato pieno
+const MAX_SKIPPED: usize = 1_000;
+
+/// Transazione da rimettere in block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimTx {
+    /// Fee in SLY nativo
+    pub fee: u64,
+    /// Dimensione serializzata in bytes
+    pub size: usize,
+    /// Momento (secondi UNIX) da cui la transazione può entrare in un block
+    pub arrival: u64,
+}
+
+impl SimTx {
+    /// Fee per 1000 bytes serializzati, come [`crate::mempool::MempoolEntry::feerate`]
+    pub fn feerate(&self) -> u64 {
+        (self.fee as u128 * 1_000 / self.size.max(1) as u128) as u64
+    }
+}
+
+/// Parametri simulati
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// Fee rate minimo (per 1000 bytes) per entrare in pool
+    pub min_feerate: u64,
+    /// Dimensione massima dei block in bytes
+    pub max_block_size: usize,
+}
+
+/// Esito della simulazione di una politica
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeSimReport {
+    /// Politica simulata
+    pub policy: FeePolicy,
+    /// Transazioni simulate
+    pub transactions: usize,
+    /// Transazioni rifiutate: fee rate sotto il minimo o più grandi di un block
+    pub rejected: usize,
+    /// Fee delle transazioni rifiutate
+    pub rejected_fees: u64,
+    /// Transazioni confermate
+    pub confirmed: usize,
+    /// Transazioni ancora in attesa dopo l'ultimo block simulato
+    pub unconfirmed: usize,
+    /// Block simulati, storici e successivi
+    pub blocks: u64,
+    /// Fee delle transazioni confermate
+    pub fee_revenue: u64,
+    /// Frazione media dello spazio dei block occupata dalle transazioni
+    pub average_fill: f64,
+    /// Attesa mediana in block (1 = il primo block dopo l'arrivo)
+    pub median_wait_blocks: u64,
+    /// Attesa in block entro cui è confermato il 90% delle transazioni
+    pub p90_wait_blocks: u64,
+    /// Attesa mediana in secondi
+    pub median_wait_secs: u64,
+    /// Attesa in secondi entro cui è confermato il 90% delle transazioni
+    pub p90_wait_secs: u64,
+}
+
+impl FeeSimReport {
+    /// Fee medie per block simulato
+    pub fn fees_per_block(&self) -> u64 {
+        self.fee_revenue / self.blocks.max(1)
+    }
+}
+
+/// Transazioni e orari dei block da simulare
+#[derive(Debug, Clone, Default)]
+pub struct FeeSimulator {
+    /// Transazioni da rimettere in block
+    txs: Vec<SimTx>,
+    /// Timestamp dei block storici, non decrescenti
+    block_times: Vec<u64>,
+    /// Secondi tra i block simulati dopo l'ultimo storico
+    block_interval: u64,
+}
+
+impl FeeSimulator {
+    /// Simulatore vuoto con un block ogni `block_interval` secondi dopo quelli storici
+    pub fn new(block_interval: u64) -> Self {
+        Self { txs: Vec::new(), block_times: Vec::new(), block_interval: block_interval.max(1) }
+    }
+
+    /// Transazioni e orari degli ultimi `blocks` block della chain dello snapshot
+    pub fn from_chain(db: &ChainSnapshot<'_>, blocks: u64, block_interval: u64) -> Result<Self, FeeSimError> {
+        let mut simulator = Self::new(block_interval);
+        let tip = db.get_metadata()?.height;
+        let start = tip.saturating_sub(blocks.saturating_sub(1)).max(1);
+        if start > tip {
+            return Ok(simulator);
+        }
+
+        let parent = db.get_block_by_height(start - 1)?.ok_or(FeeSimError::MissingBlock(start - 1))?;
+        let mut available_from = parent.header.timestamp;
+        for height in start..=tip {
+            let block = db.get_block_by_height(height)?.ok_or(FeeSimError::MissingBlock(height))?;
+            for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                simulator.add_transaction(SimTx { fee: native_fee(db, tx)?, size: tx.size()?, arrival: available_from });
+            }
+            simulator.add_block(block.header.timestamp);
+            available_from = block.header.timestamp;
+        }
+        Ok(simulator)
+    }
+
+    /// Aggiunge le transazioni in pool, disponibili dal momento di ricezione
+    pub fn add_mempool(&mut self, mempool: &Mempool) {
+        for entry in mempool.entries() {
+            self.add_transaction(SimTx { fee: entry.fee, size: entry.size, arrival: entry.received_at });
+        }
+    }
+
+    /// Aggiunge una transazione
+    pub fn add_transaction(&mut self, tx: SimTx) {
+        self.txs.push(tx);
+    }
+
+    /// Aggiunge un block storico; un timestamp precedente al block prima vale come quello
+    pub fn add_block(&mut self, time: u64) {
+        let time = self.block_times.last().map_or(time, |last| time.max(*last));
+        self.block_times.push(time);
+    }
+
+    /// Numero di transazioni da simulare
+    pub fn transactions(&self) -> usize {
+        self.txs.len()
+    }
+
+    /// Numero di block storici
+    pub fn historical_blocks(&self) -> usize {
+        self.block_times.len()
+    }
+
+    /// Rimette le transazioni in block secondo `policy`
+    pub fn run(&self, policy: &FeePolicy) -> FeeSimReport {
+        let capacity = policy.max_block_size.saturating_sub(COINBASE_RESERVED_SIZE);
+        let mut report = FeeSimReport {
+            policy: *policy,
+            transactions: self.txs.len(),
+            rejected: 0,
+            rejected_fees: 0,
+            confirmed: 0,
+            unconfirmed: 0,
+            blocks: 0,
+            fee_revenue: 0,
+            average_fill: 0.0,
+            median_wait_blocks: 0,
+            p90_wait_blocks: 0,
+            median_wait_secs: 0,
+            p90_wait_secs: 0,
+        };
+
+        let mut order: Vec<usize> = (0..self.txs.len()).collect();
+        order.sort_by_key(|index| self.txs[*index].arrival);
+        let mut arrivals = order.into_iter().peekable();
+        // Fee rate più alto prima, a parità il primo arrivato; l'ultimo campo è il primo block possibile
+        let mut pool: BinaryHeap<(u64, Reverse<u64>, Reverse<usize>, u64)> = BinaryHeap::new();
+        let mut wait_blocks = Vec::new();
+        let mut wait_secs = Vec::new();
+        let mut used = 0u64;
+        let mut time = self.block_times.first().copied().unwrap_or_default();
+
+        loop {
+            let block = report.blocks;
+            time = match self.block_times.get(block as usize) {
+                Some(historical) => *historical,
+                None if block - self.block_times.len() as u64 >= MAX_EXTRA_BLOCKS => break,
+                None => match (pool.is_empty(), arrivals.peek()) {
+                    (true, None) => break,
+                    // Senza transazioni in attesa il block successivo segue il prossimo arrivo
+                    (true, Some(index)) => time.max(self.txs[*index].arrival) + self.block_interval,
+                    (false, _) => time + self.block_interval,
+                },
+            };
+
+            while let Some(index) = arrivals.next_if(|index| self.txs[*index].arrival <= time) {
+                let tx = &self.txs[index];
+                if tx.feerate() < policy.min_feerate || tx.size > capacity {
+                    report.rejected += 1;
+                    report.rejected_fees = report.rejected_fees.saturating_add(tx.fee);
+                } else {
+                    pool.push((tx.feerate(), Reverse(tx.arrival), Reverse(index), block));
+                }
+            }
+
+            let mut free = capacity;
+            let mut skipped = Vec::new();
+            while free > 0 && skipped.len() < MAX_SKIPPED {
+                let Some(waiting) = pool.pop() else { break };
+                let (_, _, Reverse(index), first_block) = waiting;
+                let tx = &self.txs[index];
+                if tx.size > free {
+                    skipped.push(waiting);
+                    continue;
+                }
+                free -= tx.size;
+                report.confirmed += 1;
+                report.fee_revenue = report.fee_revenue.saturating_add(tx.fee);
+                wait_blocks.push(block - first_block + 1);
+                wait_secs.push(time.saturating_sub(tx.arrival));
+            }
+            pool.extend(skipped);
+            used += (capacity - free) as u64;
+            report.blocks += 1;
+        }
+
+        report.unconfirmed = pool.len() + arrivals.count();
+        if report.blocks > 0 && capacity > 0 {
+            report.average_fill = used as f64 / (capacity as u64 * report.blocks) as f64;
+        }
+        wait_blocks.sort_unstable();
+        wait_secs.sort_unstable();
+        report.median_wait_blocks = percentile(&wait_blocks, 50);
+        report.p90_wait_blocks = percentile(&wait_blocks, 90);
+        report.median_wait_secs = percentile(&wait_secs, 50);
+        report.p90_wait_secs = percentile(&wait_secs, 90);
+        report
+    }
+}
+
+/// Percentile `percent` di valori ordinati per rango più vicino (zero se vuoti)
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    match sorted.len() {
+        0 => 0,
+        len => sorted[(len * percent).div_ceil(100).max(1) - 1],
+    }
+}
+
+/// Errori della lettura della chain da simulare
+#[derive(Debug, thiserror::Error)]
+pub enum FeeSimError {
+    #[error("Block at height {0} is missing")]
+    MissingBlock(u64),
+
+    #[error(transparent)]
+    Fee(#[from] BlockStatsError),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supply::subsidy_at;
+    use crate::{Block, BlockchainDB, OutPoint, Transaction, TxInput, TxOutput, MAX_BLOCK_SIZE};
+    use tempfile::TempDir;
+
+    fn tx(fee: u64, size: usize, arrival: u64) -> SimTx {
+        SimTx { fee, size, arrival }
+    }
+
+    #[test]
+    fn test_fee_policies() {
+        let mut simulator = FeeSimulator::new(600);
+        simulator.add_block(1_000);
+        simulator.add_block(1_600);
+        // Due transazioni grandi in attesa del primo block, una piccola a basso fee rate
+        simulator.add_transaction(tx(50_000, 10_000, 900));
+        simulator.add_transaction(tx(20_000, 10_000, 900));
+        simulator.add_transaction(tx(100, 1_000, 900));
+
+        let baseline = simulator.run(&FeePolicy { min_feerate: 0, max_block_size: MAX_BLOCK_SIZE });
+        assert_eq!((baseline.confirmed, baseline.rejected, baseline.unconfirmed), (3, 0, 0));
+        assert_eq!((baseline.blocks, baseline.fee_revenue), (2, 70_100));
+        assert_eq!((baseline.median_wait_blocks, baseline.p90_wait_blocks), (1, 1));
+        assert_eq!(baseline.median_wait_secs, 100);
+
+        // Il fee rate minimo rifiuta la transazione piccola
+        let strict = simulator.run(&FeePolicy { min_feerate: 1_000, max_block_size: MAX_BLOCK_SIZE });
+        assert_eq!((strict.confirmed, strict.rejected, strict.rejected_fees), (2, 1, 100));
+
+        // Con block da una transazione quella a fee rate più basso aspetta, anche oltre i block storici
+        let small = simulator.run(&FeePolicy { min_feerate: 0, max_block_size: 10_000 + COINBASE_RESERVED_SIZE });
+        assert_eq!((small.confirmed, small.unconfirmed, small.blocks), (3, 0, 3));
+        assert_eq!((small.median_wait_blocks, small.p90_wait_blocks), (2, 3));
+        assert_eq!(small.p90_wait_secs, 2_200 - 900);
+        assert_eq!(small.fees_per_block(), 70_100 / 3);
+        assert!(small.average_fill > baseline.average_fill);
+
+        // Una transazione più grande dei block non entrerà mai
+        let tiny = simulator.run(&FeePolicy { min_feerate: 0, max_block_size: 5_000 });
+        assert_eq!((tiny.confirmed, tiny.rejected), (1, 2));
+    }
+
+    #[test]
+    fn test_simulator_from_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+
+        let funding = Transaction::coinbase(b"alice", 0, 100_000);
+        let mut block0 = Block::new([0; 32], vec![funding.clone()], 0x1d00ffff, 0);
+        block0.header.timestamp = 1_000;
+        db.store_block(&block0).unwrap();
+
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(funding.hash(), 0), vec![1])],
+            vec![TxOutput::to_address(100_000 - 2_000, b"bob")],
+            0,
+        );
+        let coinbase = Transaction::coinbase(b"miner", 1, subsidy_at(1) + 2_000);
+        let mut block1 = Block::new(block0.hash(), vec![coinbase, spend.clone()], 0x1d00ffff, 1);
+        block1.header.timestamp = 1_600;
+        db.store_block(&block1).unwrap();
+
+        let simulator = FeeSimulator::from_chain(&db.snapshot(), 10, 600).unwrap();
+        assert_eq!((simulator.transactions(), simulator.historical_blocks()), (1, 1));
+        assert_eq!(simulator.txs[0], tx(2_000, spend.size().unwrap(), 1_000));
+
+        let report = simulator.run(&FeePolicy { min_feerate: 0, max_block_size: MAX_BLOCK_SIZE });
+        assert_eq!((report.confirmed, report.fee_revenue, report.median_wait_secs), (1, 2_000, 600));
+    }
+}
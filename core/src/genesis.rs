@@ -0,0 +1,193 @@
+//! Costruzione deterministica del genesis block di una nuova rete
+//!
+//! Una `GenesisSpec` (letta tipicamente da TOML da `sedly-genesis`) descrive
+//! messaggio, timestamp, bits e allocazioni iniziali. Dalla stessa spec si
+//! ottengono sempre lo stesso block e lo stesso `app_state` per il
+//! genesis.json di Tendermint, che il nodo verifica in `InitChain`.
+
+use crate::{Block, BlockHeader, Transaction, TxOutput};
+use serde::{Deserialize, Serialize};
+
+/// Lunghezza massima del messaggio nello script_sig della coinbase
+pub const MAX_GENESIS_MESSAGE_LEN: usize = 100;
+
+/// Allocazione iniziale (premine) del genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+    /// Locking script del destinatario (hex)
+    pub script: String,
+    /// Valore in satoshi
+    pub amount: u64,
+}
+
+/// Specifica del genesis block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisSpec {
+    /// Messaggio inserito nello script_sig della coinbase
+    pub message: String,
+    /// Timestamp Unix del block
+    pub timestamp: u64,
+    /// Difficulty iniziale (formato compact)
+    pub bits: u32,
+    /// Allocazioni iniziali (nessuna: tutto il supply viene dal mining)
+    #[serde(default)]
+    pub allocations: Vec<GenesisAllocation>,
+}
+
+impl GenesisSpec {
+    /// Spec del genesis di default (`Block::genesis`)
+    pub fn sedly() -> Self {
+        let genesis = Block::genesis();
+        Self {
+            message: String::from_utf8_lossy(&genesis.transactions[0].inputs[0].script_sig).into_owned(),
+            timestamp: genesis.header.timestamp,
+            bits: genesis.header.bits,
+            allocations: Vec::new(),
+        }
+    }
+
+    /// Somma delle allocazioni
+    pub fn premine(&self) -> Result<u64, GenesisError> {
+        self.allocations.iter().try_fold(0u64, |total, allocation| {
+            total.checked_add(allocation.amount).ok_or(GenesisError::PremineOverflow)
+        })
+    }
+
+    /// Costruisce il genesis block
+    pub fn build(&self) -> Result<Block, GenesisError> {
+        if self.message.is_empty() || self.message.len() > MAX_GENESIS_MESSAGE_LEN {
+            return Err(GenesisError::InvalidMessage(self.message.len()));
+        }
+        self.premine()?;
+
+        let outputs = self.allocations
+            .iter()
+            .enumerate()
+            .map(|(index, allocation)| {
+                let script = hex::decode(&allocation.script)
+                    .ok()
+                    .filter(|script| !script.is_empty())
+                    .ok_or(GenesisError::InvalidScript(index))?;
+                if allocation.amount == 0 {
+                    return Err(GenesisError::ZeroAllocation(index));
+                }
+                Ok(TxOutput::to_address(allocation.amount, &script))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let transactions = vec![Transaction::genesis_coinbase(self.message.as_bytes(), outputs)];
+
+        Ok(Block {
+            header: BlockHeader {
+                version: crate::PROTOCOL_VERSION,
+                previous_hash: [0; 32],
+                merkle_root: Block::calculate_merkle_root(&transactions),
+                timestamp: self.timestamp,
+                bits: self.bits,
+                nonce: 0,
+                height: 0,
+            },
+            transactions,
+            auxpow: None,
+        })
+    }
+}
+
+/// `app_state` del genesis.json di Tendermint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAppState {
+    /// Hash del genesis block (hex)
+    pub genesis_hash: String,
+    /// Genesis block serializzato con bincode (hex)
+    pub genesis_block: String,
+}
+
+impl GenesisAppState {
+    /// App state per un genesis block
+    pub fn from_block(block: &Block) -> Result<Self, GenesisError> {
+        let bytes = bincode::serialize(block).map_err(|e| GenesisError::Decode(e.to_string()))?;
+        Ok(Self {
+            genesis_hash: hex::encode(block.hash()),
+            genesis_block: hex::encode(bytes),
+        })
+    }
+
+    /// Decodifica il genesis block verificandone l'hash
+    pub fn block(&self) -> Result<Block, GenesisError> {
+        let bytes = hex::decode(&self.genesis_block).map_err(|e| GenesisError::Decode(e.to_string()))?;
+        let block: Block = bincode::deserialize(&bytes).map_err(|e| GenesisError::Decode(e.to_string()))?;
+        let hash = hex::encode(block.hash());
+        if hash != self.genesis_hash {
+            return Err(GenesisError::HashMismatch { expected: self.genesis_hash.clone(), found: hash });
+        }
+        if block.header.height != 0 || block.header.previous_hash != [0; 32] {
+            return Err(GenesisError::NotGenesis);
+        }
+        Ok(block)
+    }
+}
+
+/// Errori di costruzione del genesis
+#[derive(Debug, thiserror::Error)]
+pub enum GenesisError {
+    #[error("Genesis message must be 1-{} bytes, got {0}", MAX_GENESIS_MESSAGE_LEN)]
+    InvalidMessage(usize),
+
+    #[error("Allocation {0} has an invalid or empty script")]
+    InvalidScript(usize),
+
+    #[error("Allocation {0} has zero amount")]
+    ZeroAllocation(usize),
+
+    #[error("Total premine overflows")]
+    PremineOverflow,
+
+    #[error("Cannot decode genesis block: {0}")]
+    Decode(String),
+
+    #[error("Genesis hash mismatch: app state declares {expected}, block hashes to {found}")]
+    HashMismatch { expected: String, found: String },
+
+    #[error("Block in app state is not a genesis block")]
+    NotGenesis,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_spec_matches_genesis() {
+        let block = GenesisSpec::sedly().build().unwrap();
+        assert_eq!(block.hash(), Block::genesis().hash());
+    }
+
+    #[test]
+    fn test_genesis_with_allocations() {
+        let spec = GenesisSpec {
+            message: "Sedly testnet".to_string(),
+            timestamp: 1_735_689_600,
+            bits: 0x207fffff,
+            allocations: vec![
+                GenesisAllocation { script: hex::encode(b"alice"), amount: 1_000 },
+                GenesisAllocation { script: hex::encode(b"bob"), amount: 2_000 },
+            ],
+        };
+        let block = spec.build().unwrap();
+        assert_eq!(block.hash(), spec.build().unwrap().hash());
+        assert_eq!(block.transactions[0].outputs.len(), 2);
+        assert_eq!(spec.premine().unwrap(), 3_000);
+
+        let app_state = GenesisAppState::from_block(&block).unwrap();
+        assert_eq!(app_state.block().unwrap().hash(), block.hash());
+
+        let tampered = GenesisAppState { genesis_hash: hex::encode([0u8; 32]), ..app_state };
+        assert!(matches!(tampered.block(), Err(GenesisError::HashMismatch { .. })));
+
+        let invalid = GenesisSpec {
+            allocations: vec![GenesisAllocation { script: "zz".to_string(), amount: 1 }],
+            ..spec
+        };
+        assert!(matches!(invalid.build(), Err(GenesisError::InvalidScript(0))));
+    }
+}
@@ -0,0 +1,105 @@
+//! Transazioni di governance per il cambio on-chain dei parametri
+//!
+//! Un'azione di governance (proposta o voto) è codificata in un output
+//! `OP_RETURN "SGOV" <payload>` della transazione che la trasporta. Il
+//! valore di quell'output è bruciato: per le proposte fa da deposito.
+
+use crate::params::RetargetWindow;
+use crate::script::{opcodes::OP_RETURN, push_data};
+use crate::{Transaction, TxOutput};
+use serde::{Deserialize, Serialize};
+
+/// Marker all'inizio del payload di governance
+pub const GOVERNANCE_MAGIC: [u8; 4] = *b"SGOV";
+
+/// Parametro modificabile tramite governance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterChange {
+    /// Nuova fee minima per transazione (satoshi)
+    MinTxFee(u64),
+    /// Nuova dimensione massima del block (bytes)
+    MaxBlockSize(u64),
+    /// Nuovo algoritmo di retarget della difficulty
+    RetargetWindow(RetargetWindow),
+}
+
+/// Azione di governance trasportata da una transazione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GovernanceAction {
+    /// Proposta di cambio parametro da attivare a un'altezza data
+    Propose {
+        change: ParameterChange,
+        activation_height: u64,
+    },
+    /// Voto su una proposta (identificata dal txid della proposta)
+    Vote {
+        proposal: [u8; 32],
+        approve: bool,
+    },
+}
+
+impl GovernanceAction {
+    /// Script `OP_RETURN` che codifica l'azione
+    pub fn to_script(&self) -> Vec<u8> {
+        let mut payload = GOVERNANCE_MAGIC.to_vec();
+        payload.extend(bincode::serialize(self).expect("governance action is serializable"));
+
+        let mut script = vec![OP_RETURN];
+        push_data(&mut script, &payload);
+        script
+    }
+
+    /// Output che trasporta l'azione bruciando `value`
+    pub fn to_output(&self, value: u64) -> TxOutput {
+        TxOutput::new(value, [0; 32], self.to_script())
+    }
+
+    /// Decodifica un'azione da uno script, None se non è di governance
+    pub fn from_script(script: &[u8]) -> Option<Self> {
+        let (&op, rest) = script.split_first()?;
+        if op != OP_RETURN {
+            return None;
+        }
+        let (&len, payload) = rest.split_first()?;
+        // Il payload è sempre abbastanza piccolo per un push diretto
+        if len as usize != payload.len() {
+            return None;
+        }
+        let body = payload.strip_prefix(&GOVERNANCE_MAGIC)?;
+        bincode::deserialize(body).ok()
+    }
+
+    /// Prima azione di governance di una transazione con l'indice e il valore dell'output
+    pub fn from_transaction(tx: &Transaction) -> Option<(u32, u64, Self)> {
+        tx.outputs.iter().enumerate().find_map(|(vout, output)| {
+            Self::from_script(&output.script_pubkey).map(|action| (vout as u32, output.value.to_sat(), action))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutPoint, TxInput};
+
+    #[test]
+    fn test_governance_script_roundtrip() {
+        let propose = GovernanceAction::Propose {
+            change: ParameterChange::MaxBlockSize(2_000_000),
+            activation_height: 50_000,
+        };
+        let vote = GovernanceAction::Vote { proposal: [3; 32], approve: false };
+
+        assert_eq!(GovernanceAction::from_script(&propose.to_script()), Some(propose));
+        assert_eq!(GovernanceAction::from_script(&vote.to_script()), Some(vote));
+        assert_eq!(GovernanceAction::from_script(b"\x6a\x04SGO"), None);
+        assert_eq!(GovernanceAction::from_script(b"miner"), None);
+
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([1; 32], 0), vec![])],
+            vec![TxOutput::to_address(5_000, b"alice"), vote.to_output(1)],
+            0,
+        );
+        assert_eq!(GovernanceAction::from_transaction(&tx), Some((1, 1, vote)));
+    }
+}
@@ -0,0 +1,196 @@
+//! Doppio SHA-256 e merkle tree
+//!
+//! Header, transazioni e sighash usano il doppio SHA-256, i livelli del
+//! merkle tree il SHA-256 di una coppia di hash (64 byte). Con la feature
+//! `fast-hash`, se la CPU ha le estensioni SHA (SHA-NI su x86, SHA2 su
+//! ARMv8, rilevate a runtime) questi hash chiamano direttamente la funzione
+//! di compressione con il padding già pronto, saltando buffer e
+//! finalizzazione dell'hasher generico: con la compressione in hardware è
+//! questo il costo dominante. Il risultato è identico bit per bit.
+
+use sha2::{Digest, Sha256};
+
+/// Implementazione usata per gli hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashBackend {
+    /// Hasher generico del crate sha2
+    Generic,
+    /// Compressione diretta con le istruzioni SHA-NI
+    ShaNi,
+    /// Compressione diretta con le estensioni SHA2 di ARMv8
+    ArmSha2,
+}
+
+impl HashBackend {
+    /// Nome per log e diagnostica
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashBackend::Generic => "generic",
+            HashBackend::ShaNi => "sha-ni",
+            HashBackend::ArmSha2 => "armv8-sha2",
+        }
+    }
+}
+
+/// Backend in uso, rilevato alla prima chiamata
+pub fn backend() -> HashBackend {
+    #[cfg(feature = "fast-hash")]
+    {
+        static BACKEND: std::sync::OnceLock<HashBackend> = std::sync::OnceLock::new();
+        *BACKEND.get_or_init(detect)
+    }
+    #[cfg(not(feature = "fast-hash"))]
+    HashBackend::Generic
+}
+
+#[cfg(feature = "fast-hash")]
+fn detect() -> HashBackend {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("sha")
+        && is_x86_feature_detected!("sse2")
+        && is_x86_feature_detected!("ssse3")
+        && is_x86_feature_detected!("sse4.1")
+    {
+        return HashBackend::ShaNi;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("sha2") {
+        return HashBackend::ArmSha2;
+    }
+    HashBackend::Generic
+}
+
+/// Doppio SHA-256 di `data`
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    let first: [u8; 32] = Sha256::digest(data).into();
+    #[cfg(feature = "fast-hash")]
+    if backend() != HashBackend::Generic {
+        return fast::sha256_32(&first);
+    }
+    Sha256::digest(first).into()
+}
+
+/// Livello successivo del merkle tree: SHA-256 di ogni coppia di hash,
+/// con l'ultimo duplicato se il numero è dispari
+pub fn merkle_level(hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    #[cfg(feature = "fast-hash")]
+    if backend() != HashBackend::Generic {
+        return hashes.chunks(2).map(|pair| fast::sha256_64(&concat(pair))).collect();
+    }
+    hashes.chunks(2).map(|pair| Sha256::digest(concat(pair)).into()).collect()
+}
+
+/// Nodo padre di due nodi del merkle tree
+pub fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let combined = concat(&[*left, *right]);
+    #[cfg(feature = "fast-hash")]
+    if backend() != HashBackend::Generic {
+        return fast::sha256_64(&combined);
+    }
+    Sha256::digest(combined).into()
+}
+
+/// Merkle root di una lista di hash (zero se vuota)
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+    let mut hashes = leaves.to_vec();
+    while hashes.len() > 1 {
+        hashes = merkle_level(&hashes);
+    }
+    hashes[0]
+}
+
+/// Concatenazione di una coppia; un hash da solo viene duplicato
+fn concat(pair: &[[u8; 32]]) -> [u8; 64] {
+    let mut combined = [0u8; 64];
+    combined[..32].copy_from_slice(&pair[0]);
+    combined[32..].copy_from_slice(&pair[pair.len() - 1]);
+    combined
+}
+
+/// SHA-256 di messaggi a lunghezza fissa sulla sola funzione di compressione
+///
+/// `sha2::compress256` sceglie a sua volta SHA-NI/ARMv8 a runtime.
+#[cfg(feature = "fast-hash")]
+mod fast {
+    use sha2::digest::generic_array::GenericArray;
+
+    /// Stato iniziale di SHA-256
+    const IV: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    /// Block di padding di un messaggio da 64 byte (lunghezza 512 bit)
+    const PADDING_64: [u8; 64] = {
+        let mut block = [0u8; 64];
+        block[0] = 0x80;
+        block[62] = 0x02;
+        block
+    };
+
+    /// SHA-256 di 64 byte: il messaggio e il block di padding
+    pub(super) fn sha256_64(data: &[u8; 64]) -> [u8; 32] {
+        let mut state = IV;
+        sha2::compress256(
+            &mut state,
+            &[GenericArray::clone_from_slice(data), GenericArray::clone_from_slice(&PADDING_64)],
+        );
+        to_bytes(&state)
+    }
+
+    /// SHA-256 di 32 byte: messaggio e padding (lunghezza 256 bit) in un block
+    pub(super) fn sha256_32(data: &[u8; 32]) -> [u8; 32] {
+        let mut block = [0u8; 64];
+        block[..32].copy_from_slice(data);
+        block[32] = 0x80;
+        block[62] = 0x01;
+        let mut state = IV;
+        sha2::compress256(&mut state, &[GenericArray::clone_from_slice(&block)]);
+        to_bytes(&state)
+    }
+
+    fn to_bytes(state: &[u32; 8]) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256d_vector() {
+        assert_eq!(
+            hex::encode(sha256d(b"")),
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"
+        );
+    }
+
+    #[test]
+    fn test_merkle_root_duplicates_last() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let pair = |a: &[u8; 32], b: &[u8; 32]| -> [u8; 32] { Sha256::digest([&a[..], &b[..]].concat()).into() };
+        let expected = pair(&pair(&leaves[0], &leaves[1]), &pair(&leaves[2], &leaves[2]));
+
+        assert_eq!(merkle_root(&leaves), expected);
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&[]), [0; 32]);
+    }
+
+    #[cfg(feature = "fast-hash")]
+    #[test]
+    fn test_fast_matches_generic() {
+        for seed in 0..=255u8 {
+            let data: [u8; 64] = std::array::from_fn(|i| seed.wrapping_mul(31).wrapping_add(i as u8));
+            let half: [u8; 32] = data[..32].try_into().unwrap();
+            assert_eq!(fast::sha256_64(&data), <[u8; 32]>::from(Sha256::digest(data)));
+            assert_eq!(fast::sha256_32(&half), <[u8; 32]>::from(Sha256::digest(half)));
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Tipi per gli hash a 32 byte e convenzioni di ordine dei byte
+//!
+//! Internamente (database, merkle tree, riferimenti tra block e input) un
+//! hash è l'output del doppio SHA-256 così com'è. Come in Bitcoin, la forma
+//! mostrata agli utenti (RPC, explorer, log) è l'esadecimale dei byte in
+//! ordine inverso. `Txid` e `BlockHash` distinguono i due usi più comuni,
+//! `Hash256` tutti gli altri (commitment, merkle root).
+//!
+//! `Display` e `FromStr` usano l'ordine inverso, così come serde nei formati
+//! leggibili (JSON). Nei formati binari (bincode) il tipo è codificato come
+//! il `[u8; 32]` interno: database e messaggi di rete non cambiano.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Errore di parsing di un hash esadecimale
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HashParseError {
+    #[error("Invalid hex: {0}")]
+    InvalidHex(String),
+
+    #[error("Expected 32 bytes, got {0}")]
+    InvalidLength(usize),
+}
+
+macro_rules! hash_newtype {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            /// Hash con tutti i byte a zero
+            pub const ZERO: Self = Self([0; 32]);
+
+            /// Hash dai byte in ordine interno
+            pub const fn from_byte_array(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            /// Byte in ordine interno
+            pub const fn to_byte_array(self) -> [u8; 32] {
+                self.0
+            }
+
+            /// Riferimento ai byte in ordine interno
+            pub const fn as_byte_array(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            /// Esadecimale in ordine interno (chiavi del database, payload ABCI)
+            pub fn to_internal_hex(&self) -> String {
+                hex::encode(self.0)
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(hash: $name) -> Self {
+                hash.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl PartialEq<[u8; 32]> for $name {
+            fn eq(&self, other: &[u8; 32]) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut reversed = self.0;
+                reversed.reverse();
+                f.write_str(&hex::encode(reversed))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = HashParseError;
+
+            /// Parsing della forma mostrata (ordine inverso)
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = hex::decode(s).map_err(|e| HashParseError::InvalidHex(e.to_string()))?;
+                let mut array: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|bytes: Vec<u8>| HashParseError::InvalidLength(bytes.len()))?;
+                array.reverse();
+                Ok(Self(array))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(self)
+                } else {
+                    self.0.serialize(serializer)
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    let s = String::deserialize(deserializer)?;
+                    s.parse().map_err(D::Error::custom)
+                } else {
+                    <[u8; 32]>::deserialize(deserializer).map(Self)
+                }
+            }
+        }
+    };
+}
+
+hash_newtype! {
+    /// Hash generico a 32 byte (merkle root, commitment del UTXO set)
+    Hash256
+}
+
+hash_newtype! {
+    /// Identificativo di una transazione (doppio SHA-256 della serializzazione)
+    Txid
+}
+
+hash_newtype! {
+    /// Hash di un block (doppio SHA-256 dell'header)
+    BlockHash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_is_reversed() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0xab;
+        let txid = Txid::from(bytes);
+        let shown = txid.to_string();
+        assert!(shown.ends_with("ab") && shown.starts_with("00"));
+        assert_eq!(txid.to_internal_hex(), hex::encode(bytes));
+        assert_eq!(shown.parse::<Txid>(), Ok(txid));
+        assert_eq!(format!("{:?}", BlockHash::ZERO), format!("BlockHash({})", "0".repeat(64)));
+
+        assert!(matches!("zz".parse::<BlockHash>(), Err(HashParseError::InvalidHex(_))));
+        assert_eq!("abcd".parse::<BlockHash>(), Err(HashParseError::InvalidLength(2)));
+    }
+
+    #[test]
+    fn test_serde_formats() {
+        let hash = BlockHash::from([7; 32]);
+        // Nei formati binari la codifica è quella dell'array
+        assert_eq!(bincode::serialize(&hash).unwrap(), bincode::serialize(&[7u8; 32]).unwrap());
+        assert_eq!(bincode::deserialize::<BlockHash>(&[7; 32]).unwrap(), hash);
+
+        let json = serde_json::to_value(hash).unwrap();
+        assert_eq!(json, serde_json::Value::String(hash.to_string()));
+        assert_eq!(serde_json::from_value::<BlockHash>(json).unwrap(), hash);
+        assert!(serde_json::from_str::<Txid>("\"00\"").is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! Indice in memoria degli header
+//!
+//! Tiene per ogni block noto una voce compatta (altezza, parent, bits,
+//! timestamp, chainwork) indicizzata per hash, più gli hash della chain
+//! attiva per altezza. Caricato all'avvio dai block salvati e aggiornato a
+//! ogni block connesso, permette di rilevare fork, costruire locator e
+//! calcolare il retarget senza letture dal database.
+//!
+//! Ogni voce ricorda anche se il block è stato validato, se se ne conosce
+//! solo l'header (es. block in staging) o se è invalido: da qui derivano i
+//! tip della chain riportati da `getchaintips`.
+
+use crate::difficulty::block_work;
+use crate::storage::{BlockchainDB, StorageError};
+use crate::uint::U256;
+use crate::BlockHeader;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Block recenti da cui [`HeaderCache::sync_progress`] stima il lavoro per block
+pub const SYNC_PROGRESS_WINDOW: u64 = 144;
+
+/// Stato di validazione di un block nell'indice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeaderStatus {
+    /// Block completo, validato e salvato
+    Valid,
+    /// Solo l'header è noto
+    HeadersOnly,
+    /// Block rifiutato dalla validazione
+    Invalid,
+}
+
+/// Stato di un tip della chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TipStatus {
+    /// Tip della chain attiva
+    Active,
+    /// Ramo laterale interamente validato
+    ValidFork,
+    /// Ramo con block di cui è noto solo l'header
+    HeadersOnly,
+    /// Ramo che contiene un block invalido
+    Invalid,
+}
+
+/// Tip (block senza figli noti) di una chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTip {
+    /// Hash del tip
+    pub hash: [u8; 32],
+    /// Altezza del tip
+    pub height: u64,
+    /// Lunghezza del ramo dal punto di fork con la chain attiva (0 per il tip attivo)
+    pub branch_len: u64,
+    /// Lavoro cumulativo
+    pub chainwork: U256,
+    /// Stato del ramo
+    pub status: TipStatus,
+}
+
+/// Voce compatta dell'indice (l'hash è la chiave)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderEntry {
+    /// Altezza del block
+    pub height: u64,
+    /// Hash del parent
+    pub parent: [u8; 32],
+    /// Difficulty (formato compact)
+    pub bits: u32,
+    /// Timestamp del block
+    pub timestamp: u64,
+    /// Lavoro cumulativo della chain fino a questo block compreso
+    pub chainwork: U256,
+    /// Stato di validazione
+    pub status: HeaderStatus,
+}
+
+/// Indice in memoria degli header
+#[derive(Debug, Clone, Default)]
+pub struct HeaderCache {
+    /// Voci per hash
+    entries: HashMap<[u8; 32], HeaderEntry>,
+    /// Hash della chain attiva per altezza
+    active: Vec<[u8; 32]>,
+}
+
+impl HeaderCache {
+    /// Crea un indice vuoto
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Carica tutti i block salvati e in staging e la chain attiva fino al tip
+    pub fn load(db: &BlockchainDB) -> Result<Self, StorageError> {
+        let mut headers = db.get_stored_headers()?;
+        headers.sort_by_key(|header| header.height);
+        let mut staged = db.get_staged_headers()?;
+        staged.sort_by_key(|header| header.height);
+
+        // Header senza parent noto (es. residui di un reindex) non entrano nell'indice
+        let mut cache = Self::new();
+        for header in &headers {
+            if cache.insert(header).is_ok() {
+                cache.set_status(&header.hash(), HeaderStatus::Valid);
+            }
+        }
+        for header in &staged {
+            let _ = cache.insert(header);
+        }
+        for (hash, _) in db.get_invalid_blocks()? {
+            cache.set_status(&hash, HeaderStatus::Invalid);
+        }
+
+        let tip = db.get_best_block_hash()?;
+        if cache.entries.contains_key(&tip) {
+            cache.set_active_tip(tip);
+        }
+        Ok(cache)
+    }
+
+    /// Numero di header nell'indice
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Se l'indice è vuoto
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Voce di un block
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&HeaderEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Se un block è nell'indice
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Tip della chain attiva
+    pub fn tip(&self) -> Option<([u8; 32], &HeaderEntry)> {
+        let hash = *self.active.last()?;
+        self.entries.get(&hash).map(|entry| (hash, entry))
+    }
+
+    /// Hash del block della chain attiva a una data altezza
+    pub fn hash_at(&self, height: u64) -> Option<[u8; 32]> {
+        self.active.get(usize::try_from(height).ok()?).copied()
+    }
+
+    /// Se un block appartiene alla chain attiva
+    pub fn is_active(&self, hash: &[u8; 32]) -> bool {
+        self.entries
+            .get(hash)
+            .is_some_and(|entry| self.hash_at(entry.height) == Some(*hash))
+    }
+
+    /// Aggiorna lo stato di validazione di un block, se presente
+    pub fn set_status(&mut self, hash: &[u8; 32], status: HeaderStatus) -> bool {
+        match self.entries.get_mut(hash) {
+            Some(entry) => {
+                entry.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rimuove un header fuori dalla chain attiva (es. block scartato dalla staging)
+    ///
+    /// Ritorna false se l'header non c'è o è nella chain attiva.
+    pub fn remove(&mut self, hash: &[u8; 32]) -> bool {
+        if self.is_active(hash) {
+            return false;
+        }
+        self.entries.remove(hash).is_some()
+    }
+
+    /// Aggiunge un header il cui parent è già nell'indice (o un genesis)
+    ///
+    /// Un header nuovo entra come `HeadersOnly`.
+    pub fn insert(&mut self, header: &BlockHeader) -> Result<HeaderEntry, HeaderCacheError> {
+        let hash = header.hash();
+        if let Some(entry) = self.entries.get(&hash) {
+            return Ok(*entry);
+        }
+
+        let parent_work = if header.height == 0 {
+            U256::ZERO
+        } else {
+            let parent = self.entries
+                .get(&header.previous_hash)
+                .ok_or(HeaderCacheError::UnknownParent(header.previous_hash))?;
+            if parent.height + 1 != header.height {
+                return Err(HeaderCacheError::BadHeight { expected: parent.height + 1, got: header.height });
+            }
+            parent.chainwork
+        };
+
+        let entry = HeaderEntry {
+            height: header.height,
+            parent: header.previous_hash,
+            bits: header.bits,
+            timestamp: header.timestamp,
+            chainwork: parent_work.saturating_add(&block_work(header.bits)),
+            status: HeaderStatus::HeadersOnly,
+        };
+        self.entries.insert(hash, entry);
+        Ok(entry)
+    }
+
+    /// Aggiunge un header di un block validato e connesso e lo rende il tip
+    /// della chain attiva
+    pub fn connect(&mut self, header: &BlockHeader) -> Result<HeaderEntry, HeaderCacheError> {
+        let mut entry = self.insert(header)?;
+        let hash = header.hash();
+        entry.status = HeaderStatus::Valid;
+        self.set_status(&hash, HeaderStatus::Valid);
+        self.set_active_tip(hash);
+        Ok(entry)
+    }
+
+    /// Tutti i tip noti, dal più alto
+    pub fn tips(&self) -> Vec<ChainTip> {
+        let parents: HashSet<[u8; 32]> = self.entries.values().map(|entry| entry.parent).collect();
+        let active_tip = self.active.last().copied();
+
+        let mut tips: Vec<ChainTip> = self.entries
+            .iter()
+            .filter(|(hash, _)| !parents.contains(*hash))
+            .map(|(hash, entry)| {
+                let fork_height = active_tip
+                    .and_then(|active_tip| self.find_fork(hash, &active_tip))
+                    .and_then(|fork| self.entries.get(&fork))
+                    .map(|fork| fork.height);
+                ChainTip {
+                    hash: *hash,
+                    height: entry.height,
+                    branch_len: fork_height.map_or(entry.height + 1, |fork_height| entry.height - fork_height),
+                    chainwork: entry.chainwork,
+                    status: self.tip_status(hash, fork_height),
+                }
+            })
+            .collect();
+        tips.sort_by(|a, b| b.height.cmp(&a.height).then(a.hash.cmp(&b.hash)));
+        tips
+    }
+
+    /// Tip validato con più lavoro cumulativo, candidato per un reorg
+    pub fn best_valid_tip(&self) -> Option<ChainTip> {
+        self.tips()
+            .into_iter()
+            .filter(|tip| matches!(tip.status, TipStatus::Active | TipStatus::ValidFork))
+            .max_by(|a, b| a.chainwork.cmp(&b.chainwork).then(b.branch_len.cmp(&a.branch_len)))
+    }
+
+    /// Stato del ramo che termina in `tip` sopra il punto di fork
+    fn tip_status(&self, tip: &[u8; 32], fork_height: Option<u64>) -> TipStatus {
+        if self.active.last() == Some(tip) {
+            return TipStatus::Active;
+        }
+
+        let mut status = TipStatus::ValidFork;
+        let mut current = *tip;
+        while let Some(entry) = self.entries.get(&current) {
+            if fork_height.is_some_and(|fork_height| entry.height <= fork_height) {
+                break;
+            }
+            match entry.status {
+                HeaderStatus::Invalid => return TipStatus::Invalid,
+                HeaderStatus::HeadersOnly => status = TipStatus::HeadersOnly,
+                HeaderStatus::Valid => {}
+            }
+            if entry.height == 0 {
+                break;
+            }
+            current = entry.parent;
+        }
+        status
+    }
+
+    /// Antenato di un block a una data altezza
+    pub fn ancestor(&self, hash: &[u8; 32], height: u64) -> Option<[u8; 32]> {
+        let mut current = *hash;
+        let mut entry = self.entries.get(&current)?;
+        if height > entry.height {
+            return None;
+        }
+        // Sulla chain attiva basta l'indice per altezza
+        if self.hash_at(entry.height) == Some(current) {
+            return self.hash_at(height);
+        }
+        while entry.height > height {
+            current = entry.parent;
+            entry = self.entries.get(&current)?;
+            if self.hash_at(entry.height) == Some(current) {
+                return self.hash_at(height);
+            }
+        }
+        Some(current)
+    }
+
+    /// Se `ancestor` è antenato di `descendant` (o coincide)
+    pub fn is_ancestor(&self, ancestor: &[u8; 32], descendant: &[u8; 32]) -> bool {
+        match self.entries.get(ancestor) {
+            Some(entry) => self.ancestor(descendant, entry.height) == Some(*ancestor),
+            None => false,
+        }
+    }
+
+    /// Ultimo antenato comune di due block
+    pub fn find_fork(&self, a: &[u8; 32], b: &[u8; 32]) -> Option<[u8; 32]> {
+        let height = self.entries.get(a)?.height.min(self.entries.get(b)?.height);
+        let mut a = self.ancestor(a, height)?;
+        let mut b = self.ancestor(b, height)?;
+        while a != b {
+            a = self.entries.get(&a)?.parent;
+            b = self.entries.get(&b)?.parent;
+        }
+        Some(a)
+    }
+
+    /// Locator della chain attiva: i 10 block più recenti, poi passi
+    /// raddoppiati fino al genesis
+    pub fn locator(&self) -> Vec<[u8; 32]> {
+        let Some(mut height) = self.active.len().checked_sub(1) else {
+            return Vec::new();
+        };
+
+        let mut locator = Vec::new();
+        let mut step = 1;
+        loop {
+            locator.push(self.active[height]);
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    /// Timestamp dei block della chain attiva nell'intervallo `[from, to)`
+    ///
+    /// None se l'intervallo supera il tip.
+    pub fn timestamps(&self, from: u64, to: u64) -> Option<Vec<u64>> {
+        (from..to)
+            .map(|height| self.hash_at(height).and_then(|hash| self.entries.get(&hash)).map(|entry| entry.timestamp))
+            .collect()
+    }
+
+    /// Timestamp degli antenati di `hash` (compreso) nell'intervallo `[from, to)`
+    ///
+    /// Come `timestamps`, ma segue anche i rami laterali. None se manca un antenato.
+    pub fn branch_timestamps(&self, hash: &[u8; 32], from: u64, to: u64) -> Option<Vec<u64>> {
+        (from..to)
+            .map(|height| {
+                self.ancestor(hash, height).and_then(|hash| self.entries.get(&hash)).map(|entry| entry.timestamp)
+            })
+            .collect()
+    }
+
+    /// Stima da 0 a 1 di quanto la chain attiva è vicina a quella della rete a `now`
+    ///
+    /// Rapporta il lavoro cumulativo del tip a quello atteso: al lavoro del
+    /// tip si aggiunge, per ogni `target_block_time` trascorso dal suo
+    /// timestamp, il lavoro medio degli ultimi [`SYNC_PROGRESS_WINDOW`]
+    /// block. Il primo intervallo dopo il tip non conta: un block che la rete
+    /// non ha ancora trovato non è un ritardo del nodo.
+    ///
+    /// La stima presume block a intervalli regolari, come nella proof of
+    /// work: con Tendermint e `create_empty_blocks = false` una chain senza
+    /// transazioni resta ferma e sembrerebbe indietro. Lì si usa
+    /// [`HeaderCache::header_progress`].
+    pub fn sync_progress(&self, now: u64, target_block_time: u64) -> f64 {
+        let Some((_, tip)) = self.tip() else {
+            return 0.0;
+        };
+        let target_block_time = target_block_time.max(1);
+        let window = tip.height.min(SYNC_PROGRESS_WINDOW);
+        let base = self.hash_at(tip.height - window).and_then(|hash| self.entries.get(&hash));
+        let work_per_block = match base {
+            Some(base) if window > 0 => (tip.chainwork - base.chainwork).to_f64() / window as f64,
+            _ => block_work(tip.bits).to_f64(),
+        };
+
+        let behind = now.saturating_sub(tip.timestamp).saturating_sub(target_block_time);
+        let work = tip.chainwork.to_f64();
+        let expected = work + work_per_block * behind as f64 / target_block_time as f64;
+        if expected <= 0.0 {
+            return 0.0;
+        }
+        (work / expected).clamp(0.0, 1.0)
+    }
+
+    /// Stima da 0 a 1 di quanto la chain attiva è vicina al miglior header noto
+    ///
+    /// Rapporta il lavoro cumulativo del tip a quello del tip non invalido
+    /// con più lavoro, senza guardare l'orologio: vale quando i block non
+    /// arrivano a intervalli regolari.
+    pub fn header_progress(&self) -> f64 {
+        let Some((_, tip)) = self.tip() else {
+            return 0.0;
+        };
+        let best = self.tips()
+            .into_iter()
+            .filter(|tip| tip.status != TipStatus::Invalid)
+            .map(|tip| tip.chainwork)
+            .max()
+            .unwrap_or(tip.chainwork)
+            .max(tip.chainwork)
+            .to_f64();
+        if best <= 0.0 {
+            return 0.0;
+        }
+        (tip.chainwork.to_f64() / best).clamp(0.0, 1.0)
+    }
+
+    /// Rende `tip` il tip della chain attiva, sostituendo il ramo precedente
+    fn set_active_tip(&mut self, tip: [u8; 32]) {
+        let Some(entry) = self.entries.get(&tip) else {
+            return;
+        };
+        let height = entry.height as usize;
+        self.active.resize(height + 1, [0; 32]);
+
+        let mut current = tip;
+        for slot in (0..=height).rev() {
+            if self.active[slot] == current {
+                break;
+            }
+            self.active[slot] = current;
+            match self.entries.get(&current) {
+                Some(entry) if slot > 0 => current = entry.parent,
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Errori dell'indice degli header
+#[derive(Debug, thiserror::Error)]
+pub enum HeaderCacheError {
+    #[error("Unknown parent {}", hex::encode(.0))]
+    UnknownParent([u8; 32]),
+
+    #[error("Bad height: expected {expected}, got {got}")]
+    BadHeight { expected: u64, got: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, Transaction};
+    use tempfile::TempDir;
+
+    fn build_chain(parent: &Block, count: u64, miner: &[u8]) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..count {
+            let previous = blocks.last().unwrap_or(parent);
+            let height = previous.header.height + 1;
+            let coinbase = Transaction::coinbase(miner, height, 0);
+            blocks.push(Block::new(previous.hash(), vec![coinbase], parent.header.bits, height));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_load_and_connect() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+        let blocks = build_chain(&genesis, 3, b"miner");
+        for block in &blocks[..2] {
+            db.store_block(block).unwrap();
+        }
+
+        let mut cache = HeaderCache::load(&db).unwrap();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.tip().unwrap().0, blocks[1].hash());
+
+        let entry = cache.connect(&blocks[2].header).unwrap();
+        assert_eq!(entry.height, 3);
+        let work = block_work(genesis.header.bits);
+        assert_eq!(entry.chainwork, work.saturating_add(&work).saturating_add(&work).saturating_add(&work));
+        assert_eq!(cache.hash_at(3), Some(blocks[2].hash()));
+        assert_eq!(
+            cache.timestamps(1, 4).unwrap(),
+            blocks.iter().map(|block| block.header.timestamp).collect::<Vec<_>>()
+        );
+        assert!(cache.timestamps(1, 5).is_none());
+        assert!(matches!(
+            cache.insert(&build_chain(&blocks[2], 2, b"miner")[1].header),
+            Err(HeaderCacheError::UnknownParent(_))
+        ));
+
+        // Sul ramo laterale i timestamp seguono gli antenati del block
+        let side = build_chain(&blocks[0], 1, b"side").remove(0);
+        cache.insert(&side.header).unwrap();
+        assert_eq!(
+            cache.branch_timestamps(&side.hash(), 0, 3).unwrap(),
+            vec![genesis.header.timestamp, blocks[0].header.timestamp, side.header.timestamp]
+        );
+        assert!(cache.branch_timestamps(&side.hash(), 1, 4).is_none());
+    }
+
+    #[test]
+    fn test_fork_detection_and_locator() {
+        let genesis = Block::genesis();
+        let main = build_chain(&genesis, 20, b"miner");
+        let fork = build_chain(&main[4], 3, b"other");
+
+        let mut cache = HeaderCache::new();
+        cache.connect(&genesis.header).unwrap();
+        for block in &main {
+            cache.connect(&block.header).unwrap();
+        }
+        for block in &fork {
+            cache.insert(&block.header).unwrap();
+        }
+
+        let fork_tip = fork[2].hash();
+        assert!(!cache.is_active(&fork_tip));
+        assert!(cache.is_ancestor(&main[4].hash(), &fork_tip));
+        assert!(!cache.is_ancestor(&main[5].hash(), &fork_tip));
+        assert_eq!(cache.find_fork(&fork_tip, &main[19].hash()), Some(main[4].hash()));
+        assert_eq!(cache.ancestor(&fork_tip, 6), Some(fork[0].hash()));
+
+        let locator = cache.locator();
+        assert_eq!(locator[0], main[19].hash());
+        assert_eq!(*locator.last().unwrap(), genesis.hash());
+        assert!(locator.len() < 20);
+
+        let tips = cache.tips();
+        assert_eq!(tips.len(), 2);
+        assert_eq!((tips[0].hash, tips[0].status, tips[0].branch_len), (main[19].hash(), TipStatus::Active, 0));
+        assert_eq!((tips[1].hash, tips[1].status, tips[1].branch_len), (fork_tip, TipStatus::HeadersOnly, 3));
+
+        for block in &fork {
+            cache.set_status(&block.hash(), HeaderStatus::Valid);
+        }
+        assert_eq!(cache.tips()[1].status, TipStatus::ValidFork);
+        cache.set_status(&fork[1].hash(), HeaderStatus::Invalid);
+        assert_eq!(cache.tips()[1].status, TipStatus::Invalid);
+        assert_eq!(cache.best_valid_tip().unwrap().hash, main[19].hash());
+
+        // Il fork diventa attivo: il ramo principale sopra il punto di fork esce
+        cache.set_active_tip(fork_tip);
+        assert!(cache.is_active(&fork_tip));
+        assert!(!cache.is_active(&main[5].hash()));
+        assert_eq!(cache.tip().unwrap().1.height, 8);
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let mut cache = HeaderCache::new();
+        assert_eq!(cache.sync_progress(1_000, 600), 0.0);
+
+        let genesis = Block::genesis();
+        cache.connect(&genesis.header).unwrap();
+        let main = build_chain(&genesis, 9, b"miner");
+        for block in &main {
+            cache.connect(&block.header).unwrap();
+        }
+        let tip_time = cache.tip().unwrap().1.timestamp;
+
+        // Entro un intervallo dal tip il nodo è in pari
+        assert_eq!(cache.sync_progress(tip_time, 600), 1.0);
+        assert_eq!(cache.sync_progress(tip_time + 600, 600), 1.0);
+        // Dieci block mancanti allo stesso lavoro dei dieci presenti
+        assert_eq!(cache.sync_progress(tip_time + 11 * 600, 600), 0.5);
+        assert!(cache.sync_progress(tip_time + 100 * 600, 600) < 0.1);
+
+        // Senza orologio una chain ferma resta in pari finché non arrivano header più avanti
+        let chain: Vec<Block> = std::iter::once(genesis.clone()).chain(main).collect();
+        assert_eq!(cache.header_progress(), 1.0);
+        for block in &build_chain(&chain[9], 10, b"other") {
+            cache.insert(&block.header).unwrap();
+        }
+        assert_eq!(cache.header_progress(), 0.5);
+    }
+}
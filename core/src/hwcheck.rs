@@ -0,0 +1,187 @@
+//! Benchmark di avvio e avviso di hardware insufficiente
+//!
+//! Un nodo su una macchina troppo lenta resta indietro senza errori
+//! evidenti. Al primo avvio il nodo misura in pochi secondi l'hash rate
+//! dell'algoritmo di proof of work attivo e la velocità di scrittura nella
+//! directory dei dati, e salva le misure in [`HARDWARE_REPORT_FILE`]; agli
+//! avvii successivi le riusa e ricontrolla solo lo spazio libero. Gli
+//! avvisi finiscono nel log e in `getnodeinfo`.
+
+use crate::{ChainParams, MAX_BLOCK_SIZE};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// File nella directory dei dati con le misure del primo avvio
+pub const HARDWARE_REPORT_FILE: &str = "hardware.json";
+
+/// Block al secondo che il initial block download deve poter verificare e scrivere
+pub const IBD_BLOCKS_PER_SECOND: u64 = 10;
+
+/// Giorni di block pieni per cui deve bastare lo spazio libero
+pub const MIN_FREE_DISK_DAYS: u64 = 30;
+
+/// Durata della misura dell'hash rate
+const HASH_BENCH_TIME: Duration = Duration::from_millis(500);
+
+/// Byte scritti dalla misura del disco, a blocchi da `DISK_BENCH_CHUNK`
+const DISK_BENCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// Dimensione di ogni scrittura della misura del disco (un block pieno)
+const DISK_BENCH_CHUNK: usize = MAX_BLOCK_SIZE;
+
+/// Misure dell'hardware del nodo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardwareReport {
+    /// Momento della misura (secondi UNIX)
+    pub measured_at: u64,
+    /// Algoritmo di proof of work misurato
+    pub pow_algorithm: String,
+    /// Hash al secondo su un thread
+    pub hash_rate: f64,
+    /// Byte al secondo scritti e sincronizzati nella directory dei dati
+    pub disk_write_rate: f64,
+    /// Byte liberi nella directory dei dati (None se non misurabili)
+    pub disk_available: Option<u64>,
+    /// Avvisi per l'operatore, vuoti se la macchina basta
+    pub warnings: Vec<String>,
+}
+
+impl HardwareReport {
+    /// Misura hash rate, scrittura e spazio libero in `data_dir`
+    pub fn measure(data_dir: &Path, params: &ChainParams, height: u64) -> std::io::Result<Self> {
+        let kind = params.pow_algorithm(height + 1);
+        let mut report = Self {
+            measured_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            pow_algorithm: kind.name().to_string(),
+            hash_rate: hash_rate(kind, HASH_BENCH_TIME),
+            disk_write_rate: disk_write_rate(data_dir)?,
+            disk_available: fs2::available_space(data_dir).ok(),
+            warnings: Vec::new(),
+        };
+        report.warnings = report.check(params);
+        Ok(report)
+    }
+
+    /// Report salvato in `data_dir`, con spazio libero e avvisi aggiornati,
+    /// oppure (al primo avvio) misurato e salvato
+    pub fn load_or_measure(data_dir: &Path, params: &ChainParams, height: u64) -> std::io::Result<Self> {
+        let path = data_dir.join(HARDWARE_REPORT_FILE);
+        let saved = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Self>(&data).ok());
+        if let Some(mut report) = saved {
+            report.disk_available = fs2::available_space(data_dir).ok();
+            report.warnings = report.check(params);
+            return Ok(report);
+        }
+
+        log::info!("Measuring hardware on first start");
+        let report = Self::measure(data_dir, params, height)?;
+        std::fs::write(&path, serde_json::to_vec_pretty(&report)?)?;
+        Ok(report)
+    }
+
+    /// Avvisi per le misure sotto i requisiti di `params`
+    pub fn check(&self, params: &ChainParams) -> Vec<String> {
+        let mut warnings = Vec::new();
+        // Ogni block del IBD richiede almeno un hash di proof of work
+        let min_hash_rate = IBD_BLOCKS_PER_SECOND as f64;
+        if self.hash_rate < min_hash_rate {
+            warnings.push(format!(
+                "{} hashing at {:.0} H/s is below {:.0} H/s: verifying the chain will be slow",
+                self.pow_algorithm, self.hash_rate, min_hash_rate
+            ));
+        }
+        let min_write_rate = (IBD_BLOCKS_PER_SECOND * MAX_BLOCK_SIZE as u64) as f64;
+        if self.disk_write_rate < min_write_rate {
+            warnings.push(format!(
+                "Disk writes at {:.1} MB/s are below {:.1} MB/s: initial sync will fall behind",
+                self.disk_write_rate / 1e6, min_write_rate / 1e6
+            ));
+        }
+        let blocks = MIN_FREE_DISK_DAYS * 24 * 60 * 60 / params.target_block_time.max(1);
+        let min_available = blocks * MAX_BLOCK_SIZE as u64;
+        if let Some(available) = self.disk_available.filter(|available| *available < min_available) {
+            warnings.push(format!(
+                "{:.1} GB free in the data directory, less than {} days of full blocks ({:.1} GB)",
+                available as f64 / 1e9, MIN_FREE_DISK_DAYS, min_available as f64 / 1e9
+            ));
+        }
+        warnings
+    }
+}
+
+/// Hash al secondo di `kind` su un thread, hashando header per `duration`
+fn hash_rate(kind: crate::PowKind, duration: Duration) -> f64 {
+    let mut header = crate::Block::genesis().header;
+    let start = Instant::now();
+    let mut hashes = 0u64;
+    while start.elapsed() < duration {
+        header.nonce = hashes;
+        std::hint::black_box(header.pow_hash(kind.algorithm()));
+        hashes += 1;
+    }
+    hashes as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Byte al secondo scritti e portati su disco in un file temporaneo di `data_dir`
+fn disk_write_rate(data_dir: &Path) -> std::io::Result<f64> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = data_dir.join(".hardware-bench.tmp");
+    let start = Instant::now();
+    let written = write_bench_file(&path);
+    let _ = std::fs::remove_file(&path);
+    Ok(written? as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON))
+}
+
+/// Scrive `DISK_BENCH_BYTES` in `path` sincronizzando ogni blocco, restituisce i byte scritti
+fn write_bench_file(path: &Path) -> std::io::Result<usize> {
+    let chunk = vec![0x5a; DISK_BENCH_CHUNK];
+    let chunks = DISK_BENCH_BYTES.div_ceil(DISK_BENCH_CHUNK);
+    let mut file = std::fs::File::create(path)?;
+    for _ in 0..chunks {
+        file.write_all(&chunk)?;
+        file.sync_data()?;
+    }
+    Ok(chunks * DISK_BENCH_CHUNK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Network;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hardware_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let params = ChainParams::for_network(Network::Regtest);
+        let report = HardwareReport::load_or_measure(temp_dir.path(), &params, 0).unwrap();
+        assert!(report.hash_rate > 0.0 && report.disk_write_rate > 0.0);
+        assert!(temp_dir.path().join(HARDWARE_REPORT_FILE).exists());
+        assert!(!temp_dir.path().join(".hardware-bench.tmp").exists());
+
+        // Agli avvii successivi le misure salvate sono riusate (il JSON può arrotondare l'ultima cifra)
+        let reloaded = HardwareReport::load_or_measure(temp_dir.path(), &params, 0).unwrap();
+        assert_eq!(reloaded.measured_at, report.measured_at);
+        assert!((reloaded.hash_rate - report.hash_rate).abs() <= report.hash_rate * 1e-9);
+
+        // Macchina lenta e disco quasi pieno
+        let slow = HardwareReport {
+            hash_rate: 5.0,
+            disk_write_rate: 1e6,
+            disk_available: Some(1_000_000_000),
+            ..report.clone()
+        };
+        assert_eq!(slow.check(&params).len(), 3);
+        let fast = HardwareReport {
+            hash_rate: 1e6,
+            disk_write_rate: 1e9,
+            disk_available: None,
+            ..report
+        };
+        assert!(fast.check(&params).is_empty());
+    }
+}
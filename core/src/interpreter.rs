@@ -0,0 +1,983 @@
+//! Interprete degli script di sblocco e di locking
+//!
+//! Esegue lo script_sig e poi lo script_pubkey sullo stesso stack, come
+//! Bitcoin prima di P2SH: lo spend è valido se alla fine l'elemento in cima
+//! è vero. Gli opcode supportati sono quelli dei template standard (P2PK,
+//! P2PKH, multisig), `OP_CHECKSTATEVERIFY` per gli output di stato e pochi
+//! opcode di servizio; ogni altro opcode fallisce con `BAD_OPCODE`. Le
+//! firme sono verificate da un [`SignatureChecker`]: [`TransactionChecker`]
+//! usa i digest di [`crate::sighash`].
+//!
+//! Oltre ai limiti di Bitcoin (opcode, stack, dimensioni) l'esecuzione
+//! consuma un [`ExecutionBudget`]: ogni istruzione, gli hash in proporzione
+//! ai dati e soprattutto le verifiche di firma hanno un costo, così il
+//! costo di validazione di una transazione e di un block è limitato.
+//!
+//! I vettori di test in `tests/fixtures/script_tests.json` seguono il
+//! formato di `script_tests.json` di Bitcoin Core.
+
+use crate::script::opcodes::*;
+use crate::script::{hash160, MAX_SCRIPT_SIZE};
+use crate::sighash::{signature_hash_with_type, SighashType};
+use crate::state::validator_of;
+use crate::Transaction;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Message, PublicKey, Secp256k1, VerifyOnly};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::ops::BitOr;
+use std::str::FromStr;
+
+/// Dimensione massima di un elemento dello stack
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Opcode non push eseguibili da uno script
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// Elementi massimi dello stack
+pub const MAX_STACK_SIZE: usize = 1_000;
+
+/// Chiavi massime di un `OP_CHECKMULTISIG`
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+/// Costo di ogni istruzione (push compresi)
+pub const COST_OPCODE: u64 = 1;
+/// Costo aggiuntivo per ogni 64 bytes spinti o passati a un hash
+pub const COST_PER_64_BYTES: u64 = 1;
+/// Costo fisso di un opcode di hash
+pub const COST_HASH: u64 = 10;
+/// Costo di una verifica di firma
+pub const COST_SIGCHECK: u64 = 100;
+
+/// Budget di esecuzione degli script di una transazione
+///
+/// Circa 16.000 verifiche di firma, come `MAX_STANDARD_TX_SIGOPS_COST`.
+pub const MAX_TX_SCRIPT_COST: u64 = 1_600_000;
+/// Budget di esecuzione degli script di un block
+pub const MAX_BLOCK_SCRIPT_COST: u64 = 8_000_000;
+
+/// Budget di esecuzione consumato dagli script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionBudget {
+    /// Costo massimo
+    limit: u64,
+    /// Costo consumato
+    used: u64,
+}
+
+impl ExecutionBudget {
+    /// Budget di `limit` unità di costo
+    pub fn new(limit: u64) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    /// Budget senza limite, per misurare il costo
+    pub fn unlimited() -> Self {
+        Self::new(u64::MAX)
+    }
+
+    /// Costo consumato finora
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Costo ancora disponibile
+    pub fn remaining(&self) -> u64 {
+        self.limit - self.used
+    }
+
+    /// Consuma `cost`; fallisce senza consumare se supera il limite
+    pub fn charge(&mut self, cost: u64) -> Result<(), InterpreterError> {
+        let used = self.used.saturating_add(cost);
+        if used > self.limit {
+            return Err(InterpreterError::BudgetExceeded { limit: self.limit });
+        }
+        self.used = used;
+        Ok(())
+    }
+}
+
+/// Costo proporzionale alla dimensione dei dati
+fn data_cost(len: usize) -> u64 {
+    (len as u64).div_ceil(64) * COST_PER_64_BYTES
+}
+
+/// Regole di verifica opzionali (combinabili con `|`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    /// Nessuna regola opzionale
+    pub const NONE: Self = Self(0);
+    /// Sighash type definito, firme DER e chiavi pubbliche ben codificate
+    pub const STRICTENC: Self = Self(1 << 0);
+    /// Push con l'opcode più corto possibile
+    pub const MINIMALDATA: Self = Self(1 << 1);
+    /// Elemento extra di `OP_CHECKMULTISIG` vuoto
+    pub const NULLDUMMY: Self = Self(1 << 2);
+    /// Un solo elemento sullo stack a fine esecuzione
+    pub const CLEANSTACK: Self = Self(1 << 3);
+    /// Script_sig composto solo da push
+    pub const SIGPUSHONLY: Self = Self(1 << 4);
+    /// Regole applicate alle transazioni standard
+    pub const STANDARD: Self = Self(0b1_1111);
+
+    /// Nomi delle regole, nell'ordine dei bit
+    const NAMES: [(&'static str, Self); 5] = [
+        ("STRICTENC", Self::STRICTENC),
+        ("MINIMALDATA", Self::MINIMALDATA),
+        ("NULLDUMMY", Self::NULLDUMMY),
+        ("CLEANSTACK", Self::CLEANSTACK),
+        ("SIGPUSHONLY", Self::SIGPUSHONLY),
+    ];
+
+    /// Se tutte le regole di `other` sono attive
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl FromStr for VerifyFlags {
+    type Err = InterpreterError;
+
+    /// Nomi separati da virgola; vuoto o `NONE` per nessuna regola,
+    /// `STANDARD` per tutte
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::NONE;
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty() && *name != "NONE") {
+            if name == "STANDARD" {
+                flags = flags | Self::STANDARD;
+                continue;
+            }
+            let (_, flag) = Self::NAMES
+                .iter()
+                .find(|(known, _)| *known == name)
+                .ok_or_else(|| InterpreterError::UnknownFlag(name.to_string()))?;
+            flags = flags | *flag;
+        }
+        Ok(flags)
+    }
+}
+
+impl fmt::Display for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> =
+            Self::NAMES.iter().filter(|(_, flag)| self.contains(*flag)).map(|(name, _)| *name).collect();
+        if names.is_empty() {
+            f.write_str("NONE")
+        } else {
+            f.write_str(&names.join(","))
+        }
+    }
+}
+
+/// Verifica delle firme di `OP_CHECKSIG` e `OP_CHECKMULTISIG` e della
+/// continuazione di `OP_CHECKSTATEVERIFY`
+pub trait SignatureChecker {
+    /// Se `signature` (DER seguito dal sighash type) è valida per `pubkey`
+    /// sul digest calcolato con `script_code`
+    fn check_signature(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> bool;
+
+    /// Se la transazione ha un output con lo stesso validator di `script_code`
+    fn check_continuation(&self, _script_code: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Checker senza transazione: ogni firma è invalida
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSignatureChecker;
+
+impl SignatureChecker for NoSignatureChecker {
+    fn check_signature(&self, _signature: &[u8], _pubkey: &[u8], _script_code: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Checker delle firme dell'input `input_index` di una transazione
+pub struct TransactionChecker<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    secp: Secp256k1<VerifyOnly>,
+}
+
+impl<'a> TransactionChecker<'a> {
+    /// Checker dell'input `input_index` di `tx`
+    pub fn new(tx: &'a Transaction, input_index: usize) -> Self {
+        Self { tx, input_index, secp: Secp256k1::verification_only() }
+    }
+}
+
+impl SignatureChecker for TransactionChecker<'_> {
+    fn check_signature(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> bool {
+        let Some((&sighash_byte, der)) = signature.split_last() else {
+            return false;
+        };
+        let Some(sighash_type) = SighashType::from_byte(sighash_byte) else {
+            return false;
+        };
+        let Ok(digest) = signature_hash_with_type(self.tx, self.input_index, script_code, sighash_type) else {
+            return false;
+        };
+        let (Ok(mut signature), Ok(pubkey)) = (Signature::from_der(der), PublicKey::from_slice(pubkey)) else {
+            return false;
+        };
+        // Le firme con S alto restano valide come in Bitcoin senza LOW_S
+        signature.normalize_s();
+        let message = Message::from_slice(&digest).expect("Digest is 32 bytes");
+        self.secp.verify_ecdsa(&message, &signature, &pubkey).is_ok()
+    }
+
+    fn check_continuation(&self, script_code: &[u8]) -> bool {
+        let validator = validator_of(script_code);
+        self.tx.outputs.iter().any(|output| validator_of(&output.script_pubkey) == validator)
+    }
+}
+
+/// Verifica che `script_sig` sblocchi `script_pubkey`
+pub fn verify_script(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> Result<(), InterpreterError> {
+    verify_script_with_budget(script_sig, script_pubkey, flags, checker, &mut ExecutionBudget::unlimited())
+}
+
+/// Come [`verify_script`], consumando `budget`
+pub fn verify_script_with_budget(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    budget: &mut ExecutionBudget,
+) -> Result<(), InterpreterError> {
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !is_push_only(script_sig)? {
+        return Err(InterpreterError::SigPushOnly);
+    }
+
+    let mut stack = Vec::new();
+    eval_script(&mut stack, script_sig, flags, checker, budget)?;
+    eval_script(&mut stack, script_pubkey, flags, checker, budget)?;
+
+    match stack.last() {
+        Some(top) if cast_to_bool(top) => {}
+        _ => return Err(InterpreterError::EvalFalse),
+    }
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(InterpreterError::CleanStack);
+    }
+    Ok(())
+}
+
+/// Se lo script contiene solo push (OP_1NEGATE e OP_1..OP_16 compresi)
+pub fn is_push_only(script: &[u8]) -> Result<bool, InterpreterError> {
+    let mut pc = 0;
+    while pc < script.len() {
+        let (opcode, _) = next_instruction(script, &mut pc)?;
+        if opcode > OP_16 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifica gli script di tutti gli input di `tx` e ne ritorna il costo di
+/// esecuzione; `spent_scripts` in ordine di input
+///
+/// Il primo input il cui script fallisce, o esaurisce `budget`, rende
+/// invalida la transazione.
+pub fn transaction_script_cost(
+    tx: &Transaction,
+    spent_scripts: &[Vec<u8>],
+    flags: VerifyFlags,
+    budget: &mut ExecutionBudget,
+) -> Result<u64, InterpreterError> {
+    assert_eq!(spent_scripts.len(), tx.inputs.len(), "spent scripts do not match the inputs");
+    let start = budget.used();
+    for (index, (input, script_pubkey)) in tx.inputs.iter().zip(spent_scripts).enumerate() {
+        let checker = TransactionChecker::new(tx, index);
+        verify_script_with_budget(&input.script_sig, script_pubkey, flags, &checker, budget)?;
+    }
+    Ok(budget.used() - start)
+}
+
+/// Esegue uno script sullo stack, consumando `budget`
+pub fn eval_script(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    budget: &mut ExecutionBudget,
+) -> Result<(), InterpreterError> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(InterpreterError::ScriptSize);
+    }
+
+    let mut op_count = 0;
+    let mut pc = 0;
+    while pc < script.len() {
+        let (opcode, data) = next_instruction(script, &mut pc)?;
+        budget.charge(COST_OPCODE + data.map_or(0, |data| data_cost(data.len())))?;
+        if let Some(data) = data {
+            if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(InterpreterError::PushSize);
+            }
+            if flags.contains(VerifyFlags::MINIMALDATA) && !is_minimal_push(opcode, data) {
+                return Err(InterpreterError::MinimalData);
+            }
+            stack.push(data.to_vec());
+        } else {
+            if opcode > OP_16 {
+                op_count += 1;
+                if op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(InterpreterError::OpCount);
+                }
+            }
+            execute(opcode, stack, script, flags, checker, budget, &mut op_count)?;
+        }
+
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(InterpreterError::StackSize);
+        }
+    }
+    Ok(())
+}
+
+/// Legge l'istruzione in `pc` e avanza; i push ritornano i propri dati
+pub(crate) fn next_instruction<'s>(
+    script: &'s [u8],
+    pc: &mut usize,
+) -> Result<(u8, Option<&'s [u8]>), InterpreterError> {
+    let opcode = script[*pc];
+    *pc += 1;
+    let len = match opcode {
+        OP_0 => 0,
+        len if len < OP_PUSHDATA1 => len as usize,
+        OP_PUSHDATA1 => read_le(script, pc, 1)?,
+        OP_PUSHDATA2 => read_le(script, pc, 2)?,
+        OP_PUSHDATA4 => read_le(script, pc, 4)?,
+        _ => return Ok((opcode, None)),
+    };
+    let data = script.get(*pc..*pc + len).ok_or(InterpreterError::BadPush)?;
+    *pc += len;
+    Ok((opcode, Some(data)))
+}
+
+/// Legge una lunghezza little-endian di `bytes` bytes
+fn read_le(script: &[u8], pc: &mut usize, bytes: usize) -> Result<usize, InterpreterError> {
+    let field = script.get(*pc..*pc + bytes).ok_or(InterpreterError::BadPush)?;
+    *pc += bytes;
+    Ok(field.iter().rev().fold(0usize, |len, byte| (len << 8) | *byte as usize))
+}
+
+/// Se i dati sono spinti con l'opcode più corto possibile
+fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data {
+        [] => opcode == OP_0,
+        [value @ 1..=16] => opcode == OP_1 + value - 1,
+        [0x81] => opcode == OP_1NEGATE,
+        _ if data.len() < OP_PUSHDATA1 as usize => opcode as usize == data.len(),
+        _ if data.len() <= 0xff => opcode == OP_PUSHDATA1,
+        _ if data.len() <= 0xffff => opcode == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+/// Valore di verità di un elemento: falso se tutto zero (anche "-0")
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    match element.split_last() {
+        Some((&last, rest)) => rest.iter().any(|byte| *byte != 0) || (last != 0 && last != 0x80),
+        None => false,
+    }
+}
+
+/// Numero di script di al massimo 4 bytes (magnitudine little-endian, segno nel bit alto)
+fn decode_num(element: &[u8]) -> Result<i64, InterpreterError> {
+    if element.len() > 4 {
+        return Err(InterpreterError::NumOverflow);
+    }
+    let Some((&last, _)) = element.split_last() else {
+        return Ok(0);
+    };
+    let magnitude = element
+        .iter()
+        .enumerate()
+        .fold(0i64, |value, (index, byte)| value | (*byte as i64) << (8 * index));
+    if last & 0x80 != 0 {
+        Ok(-(magnitude & !(0x80i64 << (8 * (element.len() - 1)))))
+    } else {
+        Ok(magnitude)
+    }
+}
+
+/// Elemento booleano dello stack
+fn bool_element(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        Vec::new()
+    }
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, InterpreterError> {
+    stack.pop().ok_or(InterpreterError::InvalidStackOperation)
+}
+
+/// Esegue un opcode non push
+fn execute(
+    opcode: u8,
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    budget: &mut ExecutionBudget,
+    op_count: &mut usize,
+) -> Result<(), InterpreterError> {
+    match opcode {
+        OP_1NEGATE => stack.push(vec![0x81]),
+        OP_1..=OP_16 => stack.push(vec![opcode - OP_1 + 1]),
+        OP_NOP => {}
+        OP_VERIFY => {
+            if !cast_to_bool(&pop(stack)?) {
+                return Err(InterpreterError::Verify);
+            }
+        }
+        OP_RETURN => return Err(InterpreterError::OpReturn),
+        OP_DROP => {
+            pop(stack)?;
+        }
+        OP_DUP => {
+            let top = stack.last().ok_or(InterpreterError::InvalidStackOperation)?.clone();
+            stack.push(top);
+        }
+        OP_EQUAL | OP_EQUALVERIFY => {
+            let (b, a) = (pop(stack)?, pop(stack)?);
+            if opcode == OP_EQUALVERIFY {
+                if a != b {
+                    return Err(InterpreterError::EqualVerify);
+                }
+            } else {
+                stack.push(bool_element(a == b));
+            }
+        }
+        OP_SHA256 => {
+            let element = pop(stack)?;
+            budget.charge(COST_HASH + data_cost(element.len()))?;
+            stack.push(Sha256::digest(&element).to_vec());
+        }
+        OP_HASH160 => {
+            let element = pop(stack)?;
+            budget.charge(COST_HASH + data_cost(element.len()))?;
+            stack.push(hash160(&element).to_vec());
+        }
+        OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+            let (pubkey, signature) = (pop(stack)?, pop(stack)?);
+            check_signature_encoding(&signature, flags)?;
+            check_pubkey_encoding(&pubkey, flags)?;
+            if !signature.is_empty() {
+                budget.charge(COST_SIGCHECK)?;
+            }
+            let valid = !signature.is_empty() && checker.check_signature(&signature, &pubkey, script);
+            if opcode == OP_CHECKSIGVERIFY {
+                if !valid {
+                    return Err(InterpreterError::CheckSigVerify);
+                }
+            } else {
+                stack.push(bool_element(valid));
+            }
+        }
+        OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+            let valid = check_multisig(stack, script, flags, checker, budget, op_count)?;
+            if opcode == OP_CHECKMULTISIGVERIFY {
+                if !valid {
+                    return Err(InterpreterError::CheckMultisigVerify);
+                }
+            } else {
+                stack.push(bool_element(valid));
+            }
+        }
+        OP_CHECKSTATEVERIFY => {
+            budget.charge(COST_HASH + data_cost(script.len()))?;
+            if !checker.check_continuation(script) {
+                return Err(InterpreterError::Continuation);
+            }
+        }
+        _ => return Err(InterpreterError::BadOpcode(opcode)),
+    }
+    Ok(())
+}
+
+/// `OP_CHECKMULTISIG`: `<dummy> <sig>... <m> <pubkey>... <n>`
+///
+/// Firme e chiavi sono confrontate nello stesso ordine, come in Bitcoin:
+/// ogni firma deve corrispondere a una chiave successiva a quella della
+/// firma precedente. L'elemento dummy resta consumato per compatibilità.
+fn check_multisig(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    budget: &mut ExecutionBudget,
+    op_count: &mut usize,
+) -> Result<bool, InterpreterError> {
+    let keys = decode_num(&pop(stack)?)?;
+    if keys < 0 || keys as usize > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(InterpreterError::PubkeyCount);
+    }
+    *op_count += keys as usize;
+    if *op_count > MAX_OPS_PER_SCRIPT {
+        return Err(InterpreterError::OpCount);
+    }
+    if stack.len() < keys as usize {
+        return Err(InterpreterError::InvalidStackOperation);
+    }
+    let pubkeys = stack.split_off(stack.len() - keys as usize);
+
+    let threshold = decode_num(&pop(stack)?)?;
+    if threshold < 0 || threshold > keys {
+        return Err(InterpreterError::SigCount);
+    }
+    if stack.len() < threshold as usize {
+        return Err(InterpreterError::InvalidStackOperation);
+    }
+    let signatures = stack.split_off(stack.len() - threshold as usize);
+
+    // Dal fondo: l'ultima firma contro l'ultima chiave, come Bitcoin Core
+    let mut remaining_keys = pubkeys.iter().rev();
+    let mut valid = true;
+    for (matched, signature) in signatures.iter().rev().enumerate() {
+        check_signature_encoding(signature, flags)?;
+        loop {
+            // Mancano più firme che chiavi: lo spend non può riuscire
+            if remaining_keys.len() < signatures.len() - matched {
+                valid = false;
+                break;
+            }
+            let pubkey = remaining_keys.next().expect("Keys remain for each signature");
+            check_pubkey_encoding(pubkey, flags)?;
+            if signature.is_empty() {
+                continue;
+            }
+            budget.charge(COST_SIGCHECK)?;
+            if checker.check_signature(signature, pubkey, script) {
+                break;
+            }
+        }
+        if !valid {
+            break;
+        }
+    }
+
+    let dummy = pop(stack)?;
+    if flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+        return Err(InterpreterError::SigNullDummy);
+    }
+    Ok(valid)
+}
+
+/// Con STRICTENC, una firma non vuota deve essere DER con un sighash type definito
+fn check_signature_encoding(signature: &[u8], flags: VerifyFlags) -> Result<(), InterpreterError> {
+    if signature.is_empty() || !flags.contains(VerifyFlags::STRICTENC) {
+        return Ok(());
+    }
+    let (&sighash_byte, der) = signature.split_last().expect("Signature is not empty");
+    if Signature::from_der(der).is_err() {
+        return Err(InterpreterError::SigDer);
+    }
+    if SighashType::from_byte(sighash_byte).is_none() {
+        return Err(InterpreterError::SigHashType);
+    }
+    Ok(())
+}
+
+/// Con STRICTENC, una chiave deve essere compressa (02/03) o non compressa (04)
+fn check_pubkey_encoding(pubkey: &[u8], flags: VerifyFlags) -> Result<(), InterpreterError> {
+    if !flags.contains(VerifyFlags::STRICTENC) {
+        return Ok(());
+    }
+    match (pubkey.len(), pubkey.first()) {
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => Ok(()),
+        _ => Err(InterpreterError::PubkeyType),
+    }
+}
+
+/// Errori di esecuzione degli script
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InterpreterError {
+    #[error("Script evaluated without error but finished with a false/empty top stack element")]
+    EvalFalse,
+
+    #[error("OP_RETURN was encountered")]
+    OpReturn,
+
+    #[error("Script is too big")]
+    ScriptSize,
+
+    #[error("Push value size limit exceeded")]
+    PushSize,
+
+    #[error("Operation limit exceeded")]
+    OpCount,
+
+    #[error("Stack size limit exceeded")]
+    StackSize,
+
+    #[error("Signature count negative or greater than pubkey count")]
+    SigCount,
+
+    #[error("Pubkey count negative or limit exceeded")]
+    PubkeyCount,
+
+    #[error("Script failed an OP_VERIFY operation")]
+    Verify,
+
+    #[error("Script failed an OP_EQUALVERIFY operation")]
+    EqualVerify,
+
+    #[error("Script failed an OP_CHECKSIGVERIFY operation")]
+    CheckSigVerify,
+
+    #[error("Script failed an OP_CHECKMULTISIGVERIFY operation")]
+    CheckMultisigVerify,
+
+    #[error("Opcode 0x{0:02x} missing or not understood")]
+    BadOpcode(u8),
+
+    #[error("Push past the end of the script")]
+    BadPush,
+
+    #[error("Operation not valid with the current stack size")]
+    InvalidStackOperation,
+
+    #[error("Script number overflow")]
+    NumOverflow,
+
+    #[error("Only push operators allowed in signatures")]
+    SigPushOnly,
+
+    #[error("Stack size must be exactly one after execution")]
+    CleanStack,
+
+    #[error("Signature hash type missing or not understood")]
+    SigHashType,
+
+    #[error("Non-canonical DER signature")]
+    SigDer,
+
+    #[error("Public key is neither compressed or uncompressed")]
+    PubkeyType,
+
+    #[error("Dummy CHECKMULTISIG argument must be zero")]
+    SigNullDummy,
+
+    #[error("Data push larger than necessary")]
+    MinimalData,
+
+    #[error("Unknown script verification flag {0}")]
+    UnknownFlag(String),
+
+    #[error("Script execution budget of {limit} exceeded")]
+    BudgetExceeded { limit: u64 },
+
+    #[error("Spending transaction does not recreate the state output")]
+    Continuation,
+}
+
+impl InterpreterError {
+    /// Codice dell'errore nei vettori di test, come in Bitcoin Core
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EvalFalse => "EVAL_FALSE",
+            Self::OpReturn => "OP_RETURN",
+            Self::ScriptSize => "SCRIPT_SIZE",
+            Self::PushSize => "PUSH_SIZE",
+            Self::OpCount => "OP_COUNT",
+            Self::StackSize => "STACK_SIZE",
+            Self::SigCount => "SIG_COUNT",
+            Self::PubkeyCount => "PUBKEY_COUNT",
+            Self::Verify => "VERIFY",
+            Self::EqualVerify => "EQUALVERIFY",
+            Self::CheckSigVerify => "CHECKSIGVERIFY",
+            Self::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            Self::BadOpcode(_) | Self::BadPush => "BAD_OPCODE",
+            Self::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            Self::NumOverflow => "UNKNOWN_ERROR",
+            Self::SigPushOnly => "SIG_PUSHONLY",
+            Self::CleanStack => "CLEANSTACK",
+            Self::SigHashType => "SIG_HASHTYPE",
+            Self::SigDer => "SIG_DER",
+            Self::PubkeyType => "PUBKEYTYPE",
+            Self::SigNullDummy => "SIG_NULLDUMMY",
+            Self::MinimalData => "MINIMALDATA",
+            Self::UnknownFlag(_) => "UNKNOWN_ERROR",
+            Self::BudgetExceeded { .. } => "BUDGET_EXCEEDED",
+            Self::Continuation => "CONTINUATION",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::push_data;
+    use crate::{OutPoint, TxInput, TxOutput};
+    use secp256k1::SecretKey;
+    use serde_json::Value;
+
+    /// Vettori nel formato di Bitcoin Core: `[scriptSig, scriptPubKey, flags, expected, comment?]`,
+    /// le righe con un solo elemento sono commenti
+    const SCRIPT_TESTS: &str = include_str!("../../tests/fixtures/script_tests.json");
+
+    /// Opcode riconosciuti dall'assembler dei vettori
+    const OPCODE_NAMES: [(&str, u8); 18] = [
+        ("PUSHDATA1", OP_PUSHDATA1),
+        ("PUSHDATA2", OP_PUSHDATA2),
+        ("PUSHDATA4", OP_PUSHDATA4),
+        ("1NEGATE", OP_1NEGATE),
+        ("NOP", OP_NOP),
+        ("VERIFY", OP_VERIFY),
+        ("RETURN", OP_RETURN),
+        ("DROP", OP_DROP),
+        ("DUP", OP_DUP),
+        ("EQUAL", OP_EQUAL),
+        ("EQUALVERIFY", OP_EQUALVERIFY),
+        ("SHA256", OP_SHA256),
+        ("HASH160", OP_HASH160),
+        ("CHECKSIG", OP_CHECKSIG),
+        ("CHECKSIGVERIFY", OP_CHECKSIGVERIFY),
+        ("CHECKMULTISIG", OP_CHECKMULTISIG),
+        ("CHECKMULTISIGVERIFY", OP_CHECKMULTISIGVERIFY),
+        ("CHECKSTATEVERIFY", OP_CHECKSTATEVERIFY),
+    ];
+
+    /// Chiave `n` dei segnaposto `SIG(n)`, `PUBKEY(n)` e `PUBKEYHASH(n)`
+    fn test_key(n: u8) -> SecretKey {
+        SecretKey::from_slice(&[n; 32]).unwrap()
+    }
+
+    fn test_pubkey(n: u8) -> Vec<u8> {
+        PublicKey::from_secret_key(&Secp256k1::new(), &test_key(n)).serialize().to_vec()
+    }
+
+    /// Transazione che crea l'output speso, come in Bitcoin Core
+    fn crediting_tx(script_pubkey: &[u8]) -> Transaction {
+        Transaction::new(
+            vec![TxInput::new(OutPoint::new([0; 32], u32::MAX), vec![OP_0, OP_0])],
+            vec![TxOutput::new(0, [0; 32], script_pubkey.to_vec())],
+            0,
+        )
+    }
+
+    /// Transazione che spende l'output di `crediting`
+    fn spending_tx(crediting: &Transaction) -> Transaction {
+        Transaction::new(
+            vec![TxInput::new(OutPoint::new(crediting.hash(), 0), Vec::new())],
+            vec![TxOutput::new(0, [0; 32], Vec::new())],
+            0,
+        )
+    }
+
+    /// Push di un numero di script con la codifica minima
+    fn push_number(script: &mut Vec<u8>, number: i64) {
+        match number {
+            -1 => script.push(OP_1NEGATE),
+            0 => script.push(OP_0),
+            1..=16 => script.push(OP_1 + number as u8 - 1),
+            _ => {
+                let mut magnitude = number.unsigned_abs();
+                let mut bytes = Vec::new();
+                while magnitude > 0 {
+                    bytes.push(magnitude as u8);
+                    magnitude >>= 8;
+                }
+                if bytes.last().is_some_and(|last| last & 0x80 != 0) {
+                    bytes.push(0);
+                }
+                if number < 0 {
+                    *bytes.last_mut().unwrap() |= 0x80;
+                }
+                push_data(script, &bytes);
+            }
+        }
+    }
+
+    /// Firma `SIG(n,type)` dell'input 0 di `spending` (DER + sighash type)
+    fn placeholder_signature(args: &str, spending: &Transaction, script_code: &[u8]) -> Vec<u8> {
+        let (key, sighash) = args.split_once(',').unwrap_or((args, "ALL"));
+        // Un type numerico non definito firma il digest ALL ma porta il proprio byte
+        let sighash_byte = match sighash.strip_prefix("0x") {
+            Some(byte) => u8::from_str_radix(byte, 16).unwrap(),
+            None => sighash.parse::<SighashType>().unwrap().to_byte(),
+        };
+        let sighash_type = SighashType::from_byte(sighash_byte).unwrap_or_default();
+        let digest = signature_hash_with_type(spending, 0, script_code, sighash_type).unwrap();
+        let secp = Secp256k1::new();
+        let message = Message::from_slice(&digest).unwrap();
+        let mut signature = secp.sign_ecdsa(&message, &test_key(key.parse().unwrap())).serialize_der().to_vec();
+        signature.push(sighash_byte);
+        signature
+    }
+
+    /// Assembla uno script: numeri, nomi di opcode (con o senza `OP_`),
+    /// bytes grezzi `0x..`, stringhe `'..'` e i segnaposto delle chiavi
+    fn parse_asm(asm: &str, signing: Option<(&Transaction, &[u8])>) -> Vec<u8> {
+        let mut script = Vec::new();
+        for token in asm.split_whitespace() {
+            if let Some(hex) = token.strip_prefix("0x") {
+                script.extend_from_slice(&hex::decode(hex).unwrap());
+            } else if let Some(text) = token.strip_prefix('\'').and_then(|token| token.strip_suffix('\'')) {
+                push_data(&mut script, text.as_bytes());
+            } else if let Ok(number) = token.parse::<i64>() {
+                push_number(&mut script, number);
+            } else if let Some(args) = token.strip_prefix("SIG(").and_then(|token| token.strip_suffix(')')) {
+                let (spending, script_code) = signing.expect("SIG() only in scriptSig");
+                push_data(&mut script, &placeholder_signature(args, spending, script_code));
+            } else if let Some(n) = token.strip_prefix("PUBKEY(").and_then(|token| token.strip_suffix(')')) {
+                push_data(&mut script, &test_pubkey(n.parse().unwrap()));
+            } else if let Some(n) = token.strip_prefix("PUBKEYHASH(").and_then(|token| token.strip_suffix(')')) {
+                push_data(&mut script, &hash160(&test_pubkey(n.parse().unwrap())));
+            } else {
+                let name = token.strip_prefix("OP_").unwrap_or(token);
+                let (_, opcode) = OPCODE_NAMES
+                    .iter()
+                    .find(|(known, _)| *known == name)
+                    .unwrap_or_else(|| panic!("Unknown opcode {}", token));
+                script.push(*opcode);
+            }
+        }
+        script
+    }
+
+    /// Vettori del file (commenti esclusi)
+    fn script_vectors() -> Vec<Vec<String>> {
+        let rows: Vec<Vec<Value>> = serde_json::from_str(SCRIPT_TESTS).unwrap();
+        rows.into_iter()
+            .filter(|row| row.len() > 1)
+            .map(|row| row.iter().map(|field| field.as_str().unwrap().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_script_vectors() {
+        let mut failures = Vec::new();
+        let vectors = script_vectors();
+        for vector in &vectors {
+            let [script_sig, script_pubkey, flags, expected] = &vector[..4] else { unreachable!() };
+            let script_pubkey = parse_asm(script_pubkey, None);
+            let crediting = crediting_tx(&script_pubkey);
+            let mut spending = spending_tx(&crediting);
+            let script_sig = parse_asm(script_sig, Some((&spending, &script_pubkey)));
+            spending.inputs[0].script_sig = script_sig.clone();
+
+            let flags: VerifyFlags = flags.parse().unwrap();
+            let checker = TransactionChecker::new(&spending, 0);
+            let result = match verify_script(&script_sig, &script_pubkey, flags, &checker) {
+                Ok(()) => "OK",
+                Err(e) => e.code(),
+            };
+            if result != expected {
+                failures.push(format!("{:?}: got {}", vector, result));
+            }
+        }
+        assert!(failures.is_empty(), "{} of {} vectors failed:\n{}", failures.len(), vectors.len(), failures.join("\n"));
+    }
+
+    #[test]
+    fn test_vectors_cover_opcodes() {
+        // Ogni opcode eseguibile compare in almeno un vettore
+        let mut seen = std::collections::HashSet::new();
+        for vector in script_vectors() {
+            let crediting = crediting_tx(&[]);
+            let spending = spending_tx(&crediting);
+            for asm in &vector[..2] {
+                let script = parse_asm(asm, Some((&spending, &[])));
+                let mut pc = 0;
+                while pc < script.len() {
+                    match next_instruction(&script, &mut pc) {
+                        Ok((opcode, _)) => seen.insert(opcode),
+                        Err(_) => break,
+                    };
+                }
+            }
+        }
+        let executable = [OP_0, OP_1NEGATE]
+            .into_iter()
+            .chain(OP_1..=OP_16)
+            .chain(OPCODE_NAMES.iter().map(|(_, opcode)| *opcode));
+        for opcode in executable {
+            assert!(seen.contains(&opcode), "No vector for opcode 0x{:02x}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_execution_budget() {
+        let script_pubkey = parse_asm("DUP HASH160 PUBKEYHASH(1) EQUALVERIFY CHECKSIG", None);
+        let crediting = crediting_tx(&script_pubkey);
+        let mut spending = spending_tx(&crediting);
+        spending.inputs[0].script_sig = parse_asm("SIG(1) PUBKEY(1)", Some((&spending, &script_pubkey)));
+        let checker = TransactionChecker::new(&spending, 0);
+
+        // 2 push, DUP, HASH160 di 33 bytes, push di 20, EQUALVERIFY e CHECKSIG
+        let mut budget = ExecutionBudget::unlimited();
+        let script_sig = &spending.inputs[0].script_sig;
+        verify_script_with_budget(script_sig, &script_pubkey, VerifyFlags::STANDARD, &checker, &mut budget).unwrap();
+        assert_eq!(budget.used(), 3 + 2 + 1 + (1 + COST_HASH + 1) + 2 + 1 + (1 + COST_SIGCHECK));
+
+        // Il budget si esaurisce prima della verifica della firma
+        let mut budget = ExecutionBudget::new(COST_SIGCHECK);
+        assert_eq!(
+            verify_script_with_budget(script_sig, &script_pubkey, VerifyFlags::STANDARD, &checker, &mut budget),
+            Err(InterpreterError::BudgetExceeded { limit: COST_SIGCHECK })
+        );
+        assert!(budget.remaining() < COST_SIGCHECK);
+
+        let spent = vec![script_pubkey.clone()];
+        let mut budget = ExecutionBudget::new(MAX_TX_SCRIPT_COST);
+        let cost = transaction_script_cost(&spending, &spent, VerifyFlags::STANDARD, &mut budget).unwrap();
+        assert_eq!(cost, budget.used());
+        let mut budget = ExecutionBudget::new(cost - 1);
+        assert!(matches!(
+            transaction_script_cost(&spending, &spent, VerifyFlags::STANDARD, &mut budget),
+            Err(InterpreterError::BudgetExceeded { .. })
+        ));
+
+        // Una firma che non copre la transazione la rende invalida
+        let mut tampered = spending.clone();
+        tampered.outputs[0].value = crate::Amount::from_sat(tampered.outputs[0].value.to_sat() + 1);
+        let mut budget = ExecutionBudget::new(MAX_TX_SCRIPT_COST);
+        assert_eq!(
+            transaction_script_cost(&tampered, &spent, VerifyFlags::STANDARD, &mut budget),
+            Err(InterpreterError::EvalFalse)
+        );
+        let mut two_inputs = spending.clone();
+        two_inputs.inputs.push(TxInput::new(OutPoint::new([9; 32], 0), vec![OP_0]));
+        let spent = vec![script_pubkey.clone(), script_pubkey.clone()];
+        let mut budget = ExecutionBudget::new(MAX_TX_SCRIPT_COST);
+        assert!(transaction_script_cost(&two_inputs, &spent, VerifyFlags::STANDARD, &mut budget).is_err());
+    }
+
+    #[test]
+    fn test_flags_and_bool() {
+        let flags: VerifyFlags = "STRICTENC,NULLDUMMY".parse().unwrap();
+        assert!(flags.contains(VerifyFlags::STRICTENC) && !flags.contains(VerifyFlags::CLEANSTACK));
+        assert_eq!(flags.to_string(), "STRICTENC,NULLDUMMY");
+        assert_eq!("".parse::<VerifyFlags>().unwrap(), VerifyFlags::NONE);
+        assert_eq!(VerifyFlags::STANDARD.to_string().parse::<VerifyFlags>().unwrap(), VerifyFlags::STANDARD);
+        assert!(matches!("P2SH".parse::<VerifyFlags>(), Err(InterpreterError::UnknownFlag(_))));
+
+        assert!(!cast_to_bool(&[]) && !cast_to_bool(&[0, 0]) && !cast_to_bool(&[0, 0x80]));
+        assert!(cast_to_bool(&[0, 1]) && cast_to_bool(&[0x80, 0]));
+        assert_eq!(decode_num(&[0x81]), Ok(-1));
+        assert_eq!(decode_num(&[0xff, 0x00]), Ok(255));
+        assert_eq!(decode_num(&[1, 2, 3, 4, 5]), Err(InterpreterError::NumOverflow));
+    }
+}
@@ -1,21 +1,156 @@
 //! Sedly Core - Strutture dati fondamentali della blockchain
+//!
+//! La feature `node` (default) include database, mempool, mining e
+//! elaborazione dei block. Senza di essa restano block, transazioni,
+//! script e firma, compilabili per `wasm32-unknown-unknown`; la feature
+//! `wasm` aggiunge i binding wasm-bindgen per i wallet web. La feature
+//! `fault-injection` espone l'iniezione di guasti nello storage ai test
+//! degli altri crate.
 
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fmt;
 
 // Re-export dei moduli principali
+pub mod alert;
+pub mod amount;
+pub mod auxpow;
 pub mod block;
 pub mod transaction;
+pub mod hash;
+pub mod hashes;
+pub mod merkle;
+pub mod codec;
+#[cfg(feature = "node")]
 pub mod mining;
 pub mod difficulty;
+#[cfg(feature = "node")]
 pub mod validation;
+#[cfg(feature = "node")]
 pub mod storage;  // <- Aggiungi questa riga
+#[cfg(feature = "node")]
+pub mod cache;
+pub mod params;
+pub mod uint;
+pub mod muhash;
+pub mod pow;
+#[cfg(feature = "node")]
+pub mod reindex;
+#[cfg(all(feature = "node", any(test, feature = "fault-injection")))]
+pub mod fault;
+#[cfg(feature = "node")]
+pub mod replay;
+pub mod script;
+pub mod sighash;
+pub mod interpreter;
+pub mod state;
+#[cfg(feature = "node")]
+pub mod mempool;
+#[cfg(all(test, feature = "node"))]
+mod mempool_fuzz;
+#[cfg(all(test, feature = "node"))]
+mod reorg_chaos;
+pub mod governance;
+pub mod genesis;
+pub mod supply;
+#[cfg(feature = "node")]
+pub mod audit;
+#[cfg(feature = "node")]
+pub mod blockstats;
+#[cfg(feature = "node")]
+pub mod feesim;
+#[cfg(feature = "node")]
+pub mod pipeline;
+#[cfg(feature = "node")]
+pub mod staging;
+#[cfg(feature = "node")]
+pub mod orphan;
+#[cfg(feature = "node")]
+pub mod headers;
+#[cfg(feature = "node")]
+pub mod reorg;
+#[cfg(feature = "node")]
+pub mod rejects;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "node")]
+pub mod standalone;
+#[cfg(feature = "node")]
+pub mod hwcheck;
+#[cfg(feature = "node")]
+pub mod spentfilter;
+pub mod netstats;
+pub mod page;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 // Re-export dei tipi principali
+pub use alert::{Alert, AlertError, AlertSet, SignedAlert};
+pub use amount::{Amount, AmountParseError, SATOSHI_PER_SLY};
+pub use auxpow::{AuxPow, AuxPowError};
 pub use block::{Block, BlockHeader};
-pub use transaction::{Transaction, TxInput, TxOutput, OutPoint};
-pub use storage::{BlockchainDB, ChainMetadata, UtxoEntry, DatabaseStats, StorageError};  // <- Aggiungi questa riga
+pub use transaction::{SerializationError, Transaction, TxFormat, TxInput, TxOutput, OutPoint};
+#[cfg(feature = "node")]
+pub use storage::{BlockchainDB, CancellationToken, ChainSnapshot, ChainMetadata, InvalidBlock, PendingBlock, ReorgRecord, UtxoEntry, UtxoScan, UtxoSetStats, DatabaseStats, StorageError};  // <- Aggiungi questa riga
+pub use params::{ChainParams, Network, RetargetWindow, SoftFork, TreasuryParams, TxVersionRule};
+pub use difficulty::{DifficultyAdjuster, EpochSummary};
+pub use supply::{estimate_next_halving, subsidy_at, supply_at, HalvingEstimate};
+pub use uint::U256;
+pub use muhash::MuHash;
+pub use pow::{PowAlgorithm, PowKind, PowRule};
+pub use hash::HashBackend;
+pub use hashes::{BlockHash, Hash256, HashParseError, Txid};
+pub use merkle::MerkleTree;
+pub use codec::{decode_block, decode_transaction, DecodeError};
+#[cfg(feature = "node")]
+pub use mining::{BlockTemplate, LongPollId, Miner, LONGPOLL_FEE_INCREASE_PERCENT};
+pub use script::{anyone_can_spend, script_asm, ScriptError, ScriptTemplate};
+pub use interpreter::{
+    transaction_script_cost, verify_script, ExecutionBudget, InterpreterError, SignatureChecker, TransactionChecker, VerifyFlags,
+};
+pub use state::{StateError, StateScript};
+#[cfg(feature = "node")]
+pub use validation::{BlockSpends, BlockValidator, CheckedInputs, ValidationError};
+pub use governance::{GovernanceAction, ParameterChange};
+pub use genesis::{GenesisAllocation, GenesisAppState, GenesisError, GenesisSpec};
+#[cfg(feature = "node")]
+pub use cache::{CacheConfig, CacheStats};
+#[cfg(feature = "node")]
+pub use mempool::{Mempool, MempoolEntry, MempoolError, MempoolLoadStats, MempoolReorgStats, DEFAULT_MEMPOOL_MAX_SIZE};
+pub use netstats::{NetStats, NetTotals, PeerStats, ServiceFlags};
+pub use page::Page;
+#[cfg(feature = "node")]
+pub use audit::{SupplyAuditError, SupplyAuditor, SupplyReport};
+#[cfg(feature = "node")]
+pub use blockstats::{block_stats, BlockStats, BlockStatsError};
+#[cfg(feature = "node")]
+pub use feesim::{FeePolicy, FeeSimError, FeeSimReport, FeeSimulator, SimTx};
+#[cfg(feature = "node")]
+pub use pipeline::{BlockPipeline, PipelineError, PipelineMetrics, ProcessedBlock, Stage, StageMetrics};
+#[cfg(feature = "node")]
+pub use staging::{BlockStaging, ConnectReport, StagingError};
+#[cfg(feature = "node")]
+pub use orphan::{BlockOutcome, OrphanPool};
+#[cfg(feature = "node")]
+pub use headers::{ChainTip, HeaderCache, HeaderCacheError, HeaderEntry, HeaderStatus, TipStatus};
+#[cfg(feature = "node")]
+pub use reorg::{ReorgAlarm, ReorgError, ReorgReport};
+#[cfg(feature = "node")]
+pub use node::{CrashFlush, CrashFlushReport, NodeCore, NodeMode};
+#[cfg(feature = "node")]
+pub use standalone::{BlockAcceptance, ChainUpdate, StandaloneChain, StandaloneError};
+#[cfg(feature = "node")]
+pub use hwcheck::{HardwareReport, HARDWARE_REPORT_FILE};
+#[cfg(feature = "node")]
+pub use spentfilter::{SpentFilter, SpentFilterStats, DEFAULT_SPENT_FILTER_BLOCKS};
+#[cfg(feature = "node")]
+pub use rejects::{RejectedItem, Rejection, RejectionLog, DEFAULT_REJECTION_LOG_CAPACITY, MAX_REJECTION_DUMP};
+#[cfg(feature = "node")]
+pub use reindex::{Reindexer, ReindexError, ReindexProgress, ReindexSummary};
+#[cfg(feature = "node")]
+pub use replay::{Divergence, ReplayError, ReplayReport, ReplayedBlock, Replayer};
+#[cfg(all(feature = "node", any(test, feature = "fault-injection")))]
+pub use fault::{FaultConfig, FaultInjector, FaultStats};
 
 /// Versione attuale del protocollo
 pub const PROTOCOL_VERSION: u32 = 1;
@@ -44,6 +179,9 @@ pub const MAX_BLOCK_SIZE: usize = 1_000_000;
 /// Fee minima per transazione (1000 satoshi)
 pub const MIN_TX_FEE: u64 = 1000;
 
+/// Blocks prima che un output coinbase sia spendibile
+pub const COINBASE_MATURITY: u64 = 100;
+
 #[cfg(test)]
 mod tests {
     use super::*;
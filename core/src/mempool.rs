@@ -0,0 +1,1368 @@
+//! Pool delle transazioni non confermate e formato di persistenza su disco
+
+use crate::codec::decode_transaction;
+use crate::rejects::{Rejection, RejectionLog};
+use crate::spentfilter::{SpentFilter, SpentFilterStats};
+use crate::state::datum_surcharge;
+use crate::storage::{BlockchainDB, StorageError, UtxoEntry};
+use crate::validation::{BlockValidator, CheckedInputs, ValidationError};
+use crate::{Block, OutPoint, Transaction};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Magic bytes all'inizio del file di mempool
+pub const MEMPOOL_FILE_MAGIC: [u8; 4] = *b"SMPL";
+
+/// Versione corrente del formato del file di mempool
+pub const MEMPOOL_FORMAT_VERSION: u32 = 1;
+
+/// Nome del file di mempool nella data directory
+pub const MEMPOOL_FILE_NAME: &str = "mempool.dat";
+
+/// Età massima (secondi) oltre la quale una transazione salvata non viene ricaricata
+pub const MEMPOOL_EXPIRY: u64 = 14 * 24 * 60 * 60;
+
+/// Dimensione massima di default della pool (somma delle transazioni serializzate)
+pub const DEFAULT_MEMPOOL_MAX_SIZE: usize = 300_000_000;
+
+/// Transazione in attesa di conferma
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    /// Transazione
+    pub tx: Transaction,
+    /// Timestamp UNIX di ricezione
+    pub received_at: u64,
+    /// Fee pagata in SLY nativo
+    pub fee: u64,
+    /// Altezza del tip quando la transazione è stata ricevuta
+    pub height: u64,
+    /// Dimensione serializzata in bytes, calcolata all'ingresso in pool
+    pub size: usize,
+    /// Costo di esecuzione degli script degli input
+    pub script_cost: u64,
+}
+
+impl MempoolEntry {
+    /// Fee per 1000 bytes serializzati
+    pub fn feerate(&self) -> u64 {
+        (self.fee as u128 * 1_000 / self.size.max(1) as u128) as u64
+    }
+
+    /// Confronta le fee per byte senza arrotondamenti
+    pub fn cmp_feerate(&self, other: &MempoolEntry) -> Ordering {
+        (self.fee as u128 * other.size as u128).cmp(&(other.fee as u128 * self.size as u128))
+    }
+}
+
+/// Entry nel file di mempool (versione 1)
+///
+/// La transazione è salvata come bytes serializzati: un cambio futuro del
+/// formato delle transazioni invalida solo le entry, non l'intero file.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedEntry {
+    tx: Vec<u8>,
+    received_at: u64,
+    fee: u64,
+    height: u64,
+}
+
+/// Bytes di una `PersistedEntry` serializzata oltre alla transazione (lunghezza e tre u64)
+const PERSISTED_ENTRY_OVERHEAD: usize = 32;
+
+/// Esito del caricamento di una mempool salvata
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MempoolLoadStats {
+    /// Transazioni ricaricate
+    pub loaded: usize,
+    /// Transazioni scartate perché non più valide (confermate, in conflitto, input spesi)
+    pub failed: usize,
+    /// Transazioni scartate perché più vecchie di `MEMPOOL_EXPIRY`
+    pub expired: usize,
+}
+
+/// Esito dell'aggiornamento della pool dopo un reorg
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MempoolReorgStats {
+    /// Transazioni dei block scollegati tornate in pool
+    pub returned: usize,
+    /// Transazioni dei block scollegati non più valide (coinbase, già confermate
+    /// nel nuovo ramo o in conflitto con esso)
+    pub dropped: usize,
+    /// Transazioni già in pool espulse perché non più valide sul nuovo tip
+    pub evicted: usize,
+}
+
+/// Livello su disco della mempool
+///
+/// Le transazioni espulse dalla pool piena finiscono in una column family
+/// del database invece di essere scartate. In memoria resta solo l'indice
+/// per fee per byte, qualche decina di bytes per transazione.
+struct Spillover {
+    /// Database che ospita il livello
+    db: Arc<BlockchainDB>,
+    /// Dimensione massima del livello (somma delle transazioni serializzate)
+    max_size: usize,
+    /// Dimensione delle transazioni su disco per (fee per byte, txid)
+    index: BTreeMap<(u64, [u8; 32]), usize>,
+    /// Fee per byte delle transazioni su disco per txid
+    feerates: HashMap<[u8; 32], u64>,
+    /// Somma delle dimensioni su disco
+    size: usize,
+}
+
+impl fmt::Debug for Spillover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spillover")
+            .field("max_size", &self.max_size)
+            .field("transactions", &self.index.len())
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Spillover {
+    /// Salva una entry su disco e la indicizza
+    fn put(&mut self, feerate: u64, txid: [u8; 32], data: &[u8]) -> Result<(), StorageError> {
+        self.db.spill_transaction(feerate, &txid, data)?;
+        self.index_entry(feerate, txid, data.len().saturating_sub(PERSISTED_ENTRY_OVERHEAD));
+        Ok(())
+    }
+
+    /// Indicizza una entry già su disco
+    fn index_entry(&mut self, feerate: u64, txid: [u8; 32], size: usize) {
+        if self.index.insert((feerate, txid), size).is_none() {
+            self.size += size;
+        }
+        self.feerates.insert(txid, feerate);
+    }
+
+    /// Legge e toglie una entry dal disco
+    fn take(&mut self, feerate: u64, txid: [u8; 32]) -> Result<Option<Vec<u8>>, StorageError> {
+        if let Some(size) = self.index.remove(&(feerate, txid)) {
+            self.size -= size;
+        }
+        self.feerates.remove(&txid);
+        self.db.take_spilled_transaction(feerate, &txid)
+    }
+
+    /// Scarta le transazioni con la fee per byte più bassa finché il livello
+    /// non rientra nella dimensione massima, ritornando quante ne ha scartate
+    fn trim(&mut self) -> Result<usize, StorageError> {
+        let mut dropped = 0;
+        while self.size > self.max_size {
+            let Some(&(feerate, txid)) = self.index.keys().next() else {
+                break;
+            };
+            self.take(feerate, txid)?;
+            dropped += 1;
+        }
+        Ok(dropped)
+    }
+}
+
+/// Pool delle transazioni non confermate
+///
+/// La pool occupa al massimo `max_size` bytes di transazioni serializzate:
+/// oltre il limite vengono espulse le transazioni con la fee per byte più
+/// bassa, insieme ai loro discendenti. Con il livello su disco attivo (vedi
+/// [`Mempool::set_spillover`]) le transazioni espulse vengono salvate nel
+/// database e tornano in pool quando si libera spazio.
+#[derive(Debug)]
+pub struct Mempool {
+    /// Transazioni per txid
+    entries: HashMap<[u8; 32], MempoolEntry>,
+    /// Transazioni per (fee per byte, txid): la prima è la prossima da espellere
+    by_feerate: BTreeSet<(u64, [u8; 32])>,
+    /// Outpoint spesi dalle transazioni in pool
+    spent: HashMap<OutPoint, [u8; 32]>,
+    /// Filtro degli outpoint spesi dalla pool e dai block recenti
+    spent_filter: SpentFilter,
+    /// Fee minima per l'accettazione di nuove transazioni
+    min_fee: u64,
+    /// Somma delle dimensioni delle transazioni in pool
+    total_size: usize,
+    /// Dimensione massima della pool
+    max_size: usize,
+    /// Registro delle transazioni rifiutate, se attivo
+    rejections: Option<Arc<Mutex<RejectionLog>>>,
+    /// Livello su disco per le transazioni espulse, se attivo
+    spillover: Option<Spillover>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_feerate: BTreeSet::new(),
+            spent: HashMap::new(),
+            spent_filter: SpentFilter::default(),
+            min_fee: 0,
+            total_size: 0,
+            max_size: DEFAULT_MEMPOOL_MAX_SIZE,
+            rejections: None,
+            spillover: None,
+        }
+    }
+}
+
+impl Mempool {
+    /// Crea mempool vuota
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Imposta la fee minima richiesta alle nuove transazioni
+    ///
+    /// Le transazioni con datum oltre `DATUM_FREE_BYTES` pagano in più
+    /// `DATUM_FEE_PER_BYTE` per ogni byte eccedente.
+    pub fn set_min_fee(&mut self, min_fee: u64) {
+        self.min_fee = min_fee;
+    }
+
+    /// Collega o scollega il registro delle transazioni rifiutate
+    ///
+    /// Vengono annotate solo le violazioni delle regole di consenso, non i
+    /// rifiuti di policy (fee, conflitti, pool piena).
+    pub fn set_rejection_log(&mut self, rejections: Option<Arc<Mutex<RejectionLog>>>) {
+        self.rejections = rejections;
+    }
+
+    /// Imposta la dimensione massima, espellendo subito le transazioni in eccesso
+    pub fn set_max_size(&mut self, max_size: usize) -> Vec<MempoolEntry> {
+        self.max_size = max_size;
+        self.trim_to_size()
+    }
+
+    /// Dimensione massima della pool in bytes
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Attiva il livello su disco in `db`, fino a `max_size` bytes di transazioni (0 lo disattiva)
+    ///
+    /// Le transazioni rimaste su disco da un'esecuzione precedente vengono
+    /// riprese e rivalidate quando tornano in memoria.
+    pub fn set_spillover(&mut self, db: Arc<BlockchainDB>, max_size: usize) -> Result<(), MempoolError> {
+        if max_size == 0 {
+            self.spillover = None;
+            return Ok(());
+        }
+        let mut spillover = Spillover {
+            db,
+            max_size,
+            index: BTreeMap::new(),
+            feerates: HashMap::new(),
+            size: 0,
+        };
+        for (feerate, txid, size) in spillover.db.spilled_transactions()? {
+            spillover.index_entry(feerate, txid, size.saturating_sub(PERSISTED_ENTRY_OVERHEAD));
+        }
+        let dropped = spillover.trim()?;
+        if dropped > 0 {
+            log::debug!("Mempool spillover over {} bytes: dropped {} transactions", max_size, dropped);
+        }
+        self.spillover = Some(spillover);
+        Ok(())
+    }
+
+    /// Transazioni nel livello su disco
+    pub fn spilled_len(&self) -> usize {
+        self.spillover.as_ref().map_or(0, |spillover| spillover.index.len())
+    }
+
+    /// Somma delle transazioni serializzate nel livello su disco
+    pub fn spilled_size(&self) -> usize {
+        self.spillover.as_ref().map_or(0, |spillover| spillover.size)
+    }
+
+    /// Contatori del filtro degli outpoint spesi di recente
+    pub fn spent_filter_stats(&self) -> SpentFilterStats {
+        self.spent_filter.stats()
+    }
+
+    /// Somma delle dimensioni delle transazioni in pool
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Numero di transazioni in pool
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Se la pool è vuota
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Se la transazione è in pool
+    pub fn contains(&self, txid: &[u8; 32]) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    /// Ottiene una transazione in pool
+    pub fn get(&self, txid: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    /// Itera sulle transazioni in pool
+    pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
+        self.entries.values()
+    }
+
+    /// Transazione in pool che spende `outpoint`, se esiste
+    pub fn spender(&self, outpoint: &OutPoint) -> Option<&[u8; 32]> {
+        self.spent.get(outpoint)
+    }
+
+    /// Transazioni da includere in un block, entro `max_size` bytes e
+    /// `max_script_cost` di esecuzione degli script
+    ///
+    /// Le transazioni sono scelte per fee per byte decrescente; una
+    /// transazione che spende output di un'altra in pool entra solo dopo il
+    /// parent, quindi l'ordine ritornato è valido per il block. I block
+    /// validi non superano `BlockValidator::max_block_script_cost`.
+    pub fn select_for_block(&self, max_size: usize, max_script_cost: u64) -> Vec<&MempoolEntry> {
+        let mut candidates: Vec<(&[u8; 32], &MempoolEntry)> = self.entries.iter().collect();
+        candidates.sort_by(|(a_txid, a), (b_txid, b)| b.cmp_feerate(a).then_with(|| a_txid.cmp(b_txid)));
+
+        let mut selected = Vec::new();
+        let mut included = HashSet::new();
+        let mut size = 0;
+        let mut script_cost = 0;
+        // Ogni passata include almeno un parent: i figli rimandati entrano nella successiva
+        loop {
+            let before = selected.len();
+            candidates.retain(|(txid, entry)| {
+                let waiting = entry.tx.inputs.iter().any(|input| {
+                    self.entries.contains_key(&input.previous_output.txid)
+                        && !included.contains(&input.previous_output.txid)
+                });
+                if waiting {
+                    return true;
+                }
+                if size + entry.size <= max_size && script_cost + entry.script_cost <= max_script_cost {
+                    size += entry.size;
+                    script_cost += entry.script_cost;
+                    included.insert(**txid);
+                    selected.push(*entry);
+                }
+                false
+            });
+            if selected.len() == before {
+                return selected;
+            }
+        }
+    }
+
+    /// Valida e aggiunge una transazione ricevuta ora
+    ///
+    /// `tip_height` è l'altezza del tip corrente: la transazione viene validata
+    /// come se entrasse nel block successivo. Ritorna la fee pagata.
+    pub fn add(
+        &mut self,
+        tx: Transaction,
+        tip_height: u64,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Result<u64, MempoolError> {
+        let Some(rejections) = self.rejections.clone() else {
+            return self.accept(tx, unix_now(), tip_height, tip_height, validator, db);
+        };
+        let result = self.accept(tx.clone(), unix_now(), tip_height, tip_height, validator, db);
+        if let Err(MempoolError::Invalid(error)) = &result {
+            if !matches!(error, ValidationError::Storage(_)) {
+                rejections.lock().unwrap().record(Rejection::transaction(&tx, tip_height, error));
+            }
+        }
+        result
+    }
+
+    /// Valida una transazione contro UTXO set e pool e la inserisce
+    fn accept(
+        &mut self,
+        tx: Transaction,
+        received_at: u64,
+        height: u64,
+        tip_height: u64,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Result<u64, MempoolError> {
+        let txid = tx.hash();
+        if self.is_known(&txid) {
+            return Err(MempoolError::AlreadyKnown { txid });
+        }
+        let size = tx.size().map_err(ValidationError::from)?;
+        let mut created = self.pool_inputs(&tx, tip_height, &Package::default())?;
+
+        // Il filtro contiene anche le spese della pool, già escluse da `pool_inputs`: un
+        // positivo qui è una spesa di un block recente o un falso positivo. L'UTXO set lo
+        // decide prima di firme e script, e la voce letta passa alla validazione, che non
+        // rilegge l'outpoint
+        for input in &tx.inputs {
+            let outpoint = &input.previous_output;
+            if created.contains_key(outpoint) || !self.spent_filter.may_contain(outpoint) {
+                continue;
+            }
+            let Some(entry) = db.get_utxo(outpoint)? else {
+                return Err(ValidationError::DoubleSpend { outpoint: outpoint.clone() }.into());
+            };
+            self.spent_filter.record_false_positive();
+            created.insert(outpoint.clone(), entry);
+        }
+
+        let CheckedInputs { fee, script_cost } = validator.validate_transaction(&tx, tip_height + 1, db, &created)?;
+        self.check_min_fee(&tx, fee)?;
+
+        for input in &tx.inputs {
+            self.spent.insert(input.previous_output.clone(), txid);
+            self.spent_filter.insert(&input.previous_output);
+        }
+        self.grow_spent_filter();
+        let entry = MempoolEntry { tx, received_at, fee, height, size, script_cost };
+        self.by_feerate.insert((entry.feerate(), txid));
+        self.entries.insert(txid, entry);
+        self.total_size += size;
+
+        // Con la pool piena la transazione deve pagare più di quelle che espelle
+        let evicted = self.trim_to_size();
+        if !evicted.is_empty() {
+            log::debug!("Mempool full: evicted {} transactions", evicted.len());
+        }
+        let spilled = self.spillover.as_ref().is_some_and(|spillover| spillover.feerates.contains_key(&txid));
+        if !self.entries.contains_key(&txid) && !spilled {
+            return Err(MempoolError::Full { max_size: self.max_size });
+        }
+        Ok(fee)
+    }
+
+    /// Controlla se `txs` entrerebbero in pool, senza inserirle né toccare
+    /// il registro dei rifiuti
+    ///
+    /// Le transazioni sono valutate in ordine come un pacchetto: ognuna può
+    /// spendere gli output delle precedenti accettate e non può spendere gli
+    /// stessi output. Per ogni transazione ritorna la voce che entrerebbe in
+    /// pool o il motivo del rifiuto. Con la pool piena il controllo è una
+    /// stima: la transazione deve pagare per byte più della peggiore in pool
+    /// o, con il livello su disco attivo, trovarci posto come in `add`.
+    pub fn test_accept(
+        &self,
+        txs: &[Transaction],
+        tip_height: u64,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Vec<Result<MempoolEntry, MempoolError>> {
+        let mut package = Package::default();
+        let mut results = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let result = self.test_accept_one(tx, tip_height, validator, db, &package);
+            if let Ok(entry) = &result {
+                package.spent.extend(tx.inputs.iter().map(|input| input.previous_output.clone()));
+                package.txs.insert(tx.hash(), tx);
+                package.size += entry.size;
+            }
+            results.push(result);
+        }
+        results
+    }
+
+    /// Controlli di `accept` per una transazione di un pacchetto, senza inserirla
+    fn test_accept_one(
+        &self,
+        tx: &Transaction,
+        tip_height: u64,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+        package: &Package<'_>,
+    ) -> Result<MempoolEntry, MempoolError> {
+        let txid = tx.hash();
+        if self.is_known(&txid) || package.txs.contains_key(&txid) {
+            return Err(MempoolError::AlreadyKnown { txid });
+        }
+        let size = tx.size().map_err(ValidationError::from)?;
+        let created = self.pool_inputs(tx, tip_height, package)?;
+        let CheckedInputs { fee, script_cost } = validator.validate_transaction(tx, tip_height + 1, db, &created)?;
+        self.check_min_fee(tx, fee)?;
+
+        let entry = MempoolEntry {
+            tx: tx.clone(),
+            received_at: unix_now(),
+            fee,
+            height: tip_height,
+            size,
+            script_cost,
+        };
+        if self.total_size + package.size + size > self.max_size {
+            let outbids = self.by_feerate
+                .first()
+                .and_then(|(_, worst)| self.entries.get(worst))
+                .is_some_and(|worst| entry.cmp_feerate(worst) == Ordering::Greater);
+            if !outbids && !self.would_spill(&entry, txid) {
+                return Err(MempoolError::Full { max_size: self.max_size });
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Se `entry`, espulsa dalla pool piena, resterebbe nel livello su disco
+    ///
+    /// Come in `spill`, il livello scarta prima le transazioni a fee per byte
+    /// più bassa: `entry` resta se ci sta insieme a quelle che pagano di più.
+    fn would_spill(&self, entry: &MempoolEntry, txid: [u8; 32]) -> bool {
+        self.spillover.as_ref().is_some_and(|spillover| {
+            let above: usize = spillover.index.range((entry.feerate(), txid)..).map(|(_, size)| size).sum();
+            above + entry.size <= spillover.max_size
+        })
+    }
+
+    /// Se la transazione è già in pool o nel livello su disco
+    fn is_known(&self, txid: &[u8; 32]) -> bool {
+        let spilled = self.spillover.as_ref().is_some_and(|spillover| spillover.feerates.contains_key(txid));
+        self.entries.contains_key(txid) || spilled
+    }
+
+    /// Output delle transazioni in pool (e del pacchetto) spesi da `tx`, come
+    /// voci del UTXO set; errore se un input è già speso in pool
+    fn pool_inputs(
+        &self,
+        tx: &Transaction,
+        tip_height: u64,
+        package: &Package<'_>,
+    ) -> Result<HashMap<OutPoint, UtxoEntry>, MempoolError> {
+        // Gli output delle transazioni in pool sono spendibili (catene di transazioni)
+        let mut created = HashMap::new();
+        for input in &tx.inputs {
+            let outpoint = &input.previous_output;
+            if self.spent.contains_key(outpoint) || package.spent.contains(outpoint) {
+                return Err(MempoolError::Conflict { outpoint: outpoint.clone() });
+            }
+            let parent = self.entries.get(&outpoint.txid)
+                .map(|entry| &entry.tx)
+                .or_else(|| package.txs.get(&outpoint.txid).copied());
+            if let Some(output) = parent.and_then(|parent| parent.outputs.get(outpoint.vout as usize)) {
+                created.insert(outpoint.clone(), UtxoEntry {
+                    output: output.clone(),
+                    block_height: tip_height + 1,
+                    is_coinbase: false,
+                });
+            }
+        }
+        Ok(created)
+    }
+
+    /// Errore se `fee` è sotto la fee minima della pool
+    fn check_min_fee(&self, tx: &Transaction, fee: u64) -> Result<(), MempoolError> {
+        // I datum grandi pagano lo spazio che occupano nell'UTXO set
+        let min_fee = self.min_fee.saturating_add(datum_surcharge(tx));
+        if fee < min_fee {
+            return Err(MempoolError::FeeTooLow { fee, min_fee });
+        }
+        Ok(())
+    }
+
+    /// Espelle le transazioni con la fee per byte più bassa finché la pool
+    /// non rientra nella dimensione massima
+    ///
+    /// Ogni transazione espulsa porta con sé i discendenti, che senza il
+    /// parent non sarebbero più validi. Ritorna le transazioni espulse.
+    fn trim_to_size(&mut self) -> Vec<MempoolEntry> {
+        let mut evicted = Vec::new();
+        while self.total_size > self.max_size {
+            let Some(&(_, txid)) = self.by_feerate.first() else {
+                break;
+            };
+            evicted.extend(self.remove(&txid));
+        }
+        self.spill(&evicted);
+        evicted
+    }
+
+    /// Salva nel livello su disco, se attivo, le transazioni espulse
+    fn spill(&mut self, evicted: &[MempoolEntry]) {
+        let Some(spillover) = self.spillover.as_mut() else {
+            return;
+        };
+        for entry in evicted {
+            let txid = entry.tx.hash();
+            let data = bincode::serialize(&entry.tx)
+                .and_then(|tx| bincode::serialize(&PersistedEntry {
+                    tx,
+                    received_at: entry.received_at,
+                    fee: entry.fee,
+                    height: entry.height,
+                }))
+                .map_err(|e| StorageError::Serialization(e.to_string()));
+            if let Err(e) = data.and_then(|data| spillover.put(entry.feerate(), txid, &data)) {
+                log::warn!("Failed to spill mempool transaction {} to disk: {}", hex::encode(txid), e);
+            }
+        }
+        match spillover.trim() {
+            Ok(0) => {}
+            Ok(dropped) => log::debug!("Mempool spillover full: dropped {} transactions", dropped),
+            Err(e) => log::warn!("Failed to trim the mempool spillover: {}", e),
+        }
+    }
+
+    /// Riporta in memoria le transazioni su disco che ci stanno o che pagano
+    /// più della peggiore in pool, rivalidandole sopra `tip_height`
+    ///
+    /// Va chiamata dopo ogni block, che libera spazio in pool. Le transazioni
+    /// non più valide vengono scartate; quelle che spendono una transazione
+    /// ancora su disco ci restano, in attesa del parent. Ritorna il numero di
+    /// transazioni tornate in memoria.
+    pub fn promote_spilled(
+        &mut self,
+        tip_height: u64,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Result<usize, MempoolError> {
+        let Some(spillover) = self.spillover.as_ref() else {
+            return Ok(0);
+        };
+        let floor = self.entries.values().map(MempoolEntry::feerate).min().unwrap_or(0);
+        let mut room = self.max_size.saturating_sub(self.total_size);
+        let mut budget = self.max_size;
+        let mut candidates = Vec::new();
+        for (&(feerate, txid), &size) in spillover.index.iter().rev() {
+            if size > budget {
+                break;
+            }
+            if size <= room {
+                room -= size;
+            } else if feerate <= floor {
+                break;
+            }
+            budget -= size;
+            candidates.push((feerate, txid));
+        }
+
+        let mut spilled = Vec::with_capacity(candidates.len());
+        for (feerate, txid) in candidates {
+            let Some(spillover) = self.spillover.as_mut() else {
+                break;
+            };
+            let data = match spillover.take(feerate, txid) {
+                Ok(Some(data)) => data,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.restore_spilled(spilled.into_iter().map(|(feerate, txid, _, data)| (feerate, txid, data)));
+                    return Err(e.into());
+                }
+            };
+            match bincode::deserialize::<PersistedEntry>(&data) {
+                Ok(entry) => spilled.push((feerate, txid, entry, data)),
+                Err(e) => log::debug!("Dropping undecodable spilled transaction {}: {}", hex::encode(txid), e),
+            }
+        }
+        // I parent sono sempre arrivati prima dei figli
+        spilled.sort_by_key(|(_, _, entry, _)| entry.received_at);
+
+        let now = unix_now();
+        let mut promoted = 0;
+        let mut spilled = spilled.into_iter();
+        while let Some((feerate, txid, entry, data)) = spilled.next() {
+            if now.saturating_sub(entry.received_at) > MEMPOOL_EXPIRY {
+                continue;
+            }
+            let tx = match decode_transaction(&entry.tx) {
+                Ok(tx) => tx,
+                Err(e) => {
+                    log::debug!("Dropping undecodable spilled transaction {}: {}", hex::encode(txid), e);
+                    continue;
+                }
+            };
+            let waits_for_parent = self.spillover.as_ref().is_some_and(|spillover| {
+                tx.inputs.iter().any(|input| spillover.feerates.contains_key(&input.previous_output.txid))
+            });
+            match self.accept(tx, entry.received_at, entry.height, tip_height, validator, db) {
+                Ok(_) => promoted += 1,
+                Err(MempoolError::Storage(e)) => {
+                    // Le transazioni già tolte dal disco e non ancora provate ci tornano
+                    let rest = spilled.map(|(feerate, txid, _, data)| (feerate, txid, data));
+                    self.restore_spilled(std::iter::once((feerate, txid, data)).chain(rest));
+                    return Err(e.into());
+                }
+                Err(MempoolError::Invalid(ValidationError::MissingInput { .. })) if waits_for_parent => {
+                    if let Some(spillover) = self.spillover.as_mut() {
+                        spillover.put(feerate, txid, &data)?;
+                    }
+                }
+                Err(e) => log::debug!("Dropping spilled mempool transaction {}: {}", hex::encode(txid), e),
+            }
+        }
+        Ok(promoted)
+    }
+
+    /// Rimette nel livello su disco le transazioni tolte da una promozione interrotta
+    fn restore_spilled(&mut self, entries: impl Iterator<Item = (u64, [u8; 32], Vec<u8>)>) {
+        let Some(spillover) = self.spillover.as_mut() else {
+            return;
+        };
+        for (feerate, txid, data) in entries {
+            if let Err(e) = spillover.put(feerate, txid, &data) {
+                log::warn!("Failed to return spilled transaction {} to disk: {}", hex::encode(txid), e);
+            }
+        }
+    }
+
+    /// Rimuove una transazione (e le transazioni che ne spendono gli output)
+    pub fn remove(&mut self, txid: &[u8; 32]) -> Vec<MempoolEntry> {
+        let mut removed = Vec::new();
+        let mut pending = vec![*txid];
+
+        while let Some(txid) = pending.pop() {
+            let Some(entry) = self.entries.remove(&txid) else {
+                continue;
+            };
+            self.by_feerate.remove(&(entry.feerate(), txid));
+            self.total_size -= entry.size;
+            for input in &entry.tx.inputs {
+                self.spent.remove(&input.previous_output);
+                self.spent_filter.remove(&input.previous_output);
+            }
+            for vout in 0..entry.tx.outputs.len() {
+                if let Some(child) = self.spent.get(&OutPoint::new(txid, vout as u32)) {
+                    pending.push(*child);
+                }
+            }
+            removed.push(entry);
+        }
+        removed
+    }
+
+    /// Aggiorna la pool dopo la conferma di un block
+    ///
+    /// Rimuove le transazioni incluse, quelle in conflitto con il block e
+    /// quelle che scadono prima del block successivo, insieme ai loro
+    /// discendenti. Ritorna il numero di transazioni rimosse.
+    pub fn remove_for_block(&mut self, block: &Block) -> usize {
+        let mut removed = 0;
+        for tx in &block.transactions {
+            let txid = tx.hash();
+            if let Some(entry) = self.entries.remove(&txid) {
+                self.by_feerate.remove(&(entry.feerate(), txid));
+                self.total_size -= entry.size;
+                for input in &entry.tx.inputs {
+                    self.spent.remove(&input.previous_output);
+                    self.spent_filter.remove(&input.previous_output);
+                }
+                removed += 1;
+            }
+
+            for input in &tx.inputs {
+                if let Some(conflict) = self.spent.get(&input.previous_output).copied() {
+                    removed += self.remove(&conflict).len();
+                }
+            }
+        }
+        self.spent_filter.connect_block(block);
+        self.grow_spent_filter();
+        removed + self.remove_expired(block.header.height + 1).len()
+    }
+
+    /// Ricostruisce il filtro degli outpoint spesi se un inserimento non ha trovato posto
+    fn grow_spent_filter(&mut self) {
+        if self.spent_filter.is_saturated() {
+            self.spent_filter.rebuild(self.spent.keys());
+        }
+    }
+
+    /// Rimuove le transazioni scadute per il block ad altezza `height` (e i loro discendenti)
+    pub fn remove_expired(&mut self, height: u64) -> Vec<MempoolEntry> {
+        let expired: Vec<[u8; 32]> = self.entries
+            .iter()
+            .filter(|(_, entry)| entry.tx.is_expired(height))
+            .map(|(txid, _)| *txid)
+            .collect();
+        let removed: Vec<MempoolEntry> = expired.iter().flat_map(|txid| self.remove(txid)).collect();
+        if !removed.is_empty() {
+            log::debug!("Dropped {} expired mempool transactions", removed.len());
+        }
+        removed
+    }
+
+    /// Aggiorna la pool dopo un reorg, con il database già sul nuovo tip
+    ///
+    /// Le transazioni dei block scollegati tornano in pool, in ordine di
+    /// altezza e di posizione nel block, prima di quelle già presenti (che
+    /// possono spenderne gli output). Tutto viene rivalidato contro il nuovo
+    /// tip: le transazioni confermate dal nuovo ramo, quelle in conflitto con
+    /// esso e i loro discendenti escono dalla pool.
+    pub fn update_for_reorg(
+        &mut self,
+        disconnected: &[Block],
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Result<MempoolReorgStats, MempoolError> {
+        let tip_height = db.get_height()?;
+        let previous: Vec<MempoolEntry> = self.ordered_entries().into_iter().cloned().collect();
+        for outpoint in self.spent.keys() {
+            self.spent_filter.remove(outpoint);
+        }
+        if let Some(fork_height) = disconnected.iter().map(|block| block.header.height).min() {
+            self.spent_filter.disconnect_from(fork_height);
+        }
+        self.entries.clear();
+        self.by_feerate.clear();
+        self.spent.clear();
+        self.total_size = 0;
+
+        let mut stats = MempoolReorgStats::default();
+        let mut blocks: Vec<&Block> = disconnected.iter().collect();
+        blocks.sort_by_key(|block| block.header.height);
+        let now = unix_now();
+        for block in blocks {
+            stats.dropped += block.transactions.iter().filter(|tx| tx.is_coinbase()).count();
+            for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                match self.accept(tx.clone(), now, tip_height, tip_height, validator, db) {
+                    Ok(_) => stats.returned += 1,
+                    Err(MempoolError::Storage(e)) => return Err(e.into()),
+                    Err(e) => {
+                        log::debug!("Dropping disconnected transaction {}: {}", hex::encode(tx.hash()), e);
+                        stats.dropped += 1;
+                    }
+                }
+            }
+        }
+
+        for entry in previous {
+            let txid = entry.tx.hash();
+            match self.accept(entry.tx, entry.received_at, entry.height, tip_height, validator, db) {
+                Ok(_) => {}
+                Err(MempoolError::Storage(e)) => return Err(e.into()),
+                Err(e) => {
+                    log::debug!("Evicting mempool transaction {} after reorg: {}", hex::encode(txid), e);
+                    stats.evicted += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Transazioni in ordine di ricezione, con i parent in pool sempre prima dei figli
+    fn ordered_entries(&self) -> Vec<&MempoolEntry> {
+        // Le chiavi della pool sono i txid: nessun hash da ricalcolare
+        let mut by_time: Vec<(&[u8; 32], &MempoolEntry)> = self.entries.iter().collect();
+        by_time.sort_by_key(|(_, entry)| entry.received_at);
+
+        let mut ordered = Vec::with_capacity(by_time.len());
+        let mut visited = HashSet::new();
+        for (txid, entry) in by_time {
+            let mut stack = vec![(txid, entry, false)];
+            while let Some((txid, entry, parents_done)) = stack.pop() {
+                if parents_done {
+                    if visited.insert(*txid) {
+                        ordered.push(entry);
+                    }
+                    continue;
+                }
+                if visited.contains(txid) {
+                    continue;
+                }
+                stack.push((txid, entry, true));
+                for input in &entry.tx.inputs {
+                    let parent_txid = &input.previous_output.txid;
+                    if let Some(parent) = self.entries.get(parent_txid) {
+                        if !visited.contains(parent_txid) {
+                            stack.push((parent_txid, parent, false));
+                        }
+                    }
+                }
+            }
+        }
+        ordered
+    }
+
+    /// Salva la pool su file in modo atomico, ritorna il numero di transazioni salvate
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<usize, MempoolError> {
+        let path = path.as_ref();
+
+        let persisted = self
+            .ordered_entries()
+            .into_iter()
+            .map(|entry| {
+                Ok(PersistedEntry {
+                    tx: bincode::serialize(&entry.tx)
+                        .map_err(|e| MempoolError::Corrupted(e.to_string()))?,
+                    received_at: entry.received_at,
+                    fee: entry.fee,
+                    height: entry.height,
+                })
+            })
+            .collect::<Result<Vec<_>, MempoolError>>()?;
+        let body = bincode::serialize(&persisted)
+            .map_err(|e| MempoolError::Corrupted(e.to_string()))?;
+
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&MEMPOOL_FILE_MAGIC)?;
+        file.write_all(&MEMPOOL_FORMAT_VERSION.to_le_bytes())?;
+        file.write_all(&body)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(persisted.len())
+    }
+
+    /// Carica una pool salvata rivalidando ogni transazione contro il UTXO set corrente
+    ///
+    /// Un file mancante produce una pool vuota. Le transazioni non più valide
+    /// o scadute vengono scartate e conteggiate nelle statistiche.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        validator: &BlockValidator,
+        db: &BlockchainDB,
+    ) -> Result<(Self, MempoolLoadStats), MempoolError> {
+        let mut mempool = Self::new();
+        let mut stats = MempoolLoadStats::default();
+
+        let data = match fs::read(path.as_ref()) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((mempool, stats)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entries = decode_file(&data)?;
+        let tip_height = db.get_height()?;
+        let now = unix_now();
+
+        for entry in entries {
+            if now.saturating_sub(entry.received_at) > MEMPOOL_EXPIRY {
+                stats.expired += 1;
+                continue;
+            }
+
+            let tx = match decode_transaction(&entry.tx) {
+                Ok(tx) => tx,
+                Err(e) => {
+                    log::debug!("Dropping undecodable mempool entry: {}", e);
+                    stats.failed += 1;
+                    continue;
+                }
+            };
+
+            let txid = tx.hash();
+            match mempool.accept(tx, entry.received_at, entry.height, tip_height, validator, db) {
+                Ok(_) => stats.loaded += 1,
+                Err(MempoolError::Storage(e)) => return Err(e.into()),
+                Err(e) => {
+                    log::debug!("Dropping mempool transaction {}: {}", hex::encode(txid), e);
+                    stats.failed += 1;
+                }
+            }
+        }
+
+        Ok((mempool, stats))
+    }
+}
+
+/// Decodifica il contenuto del file di mempool
+fn decode_file(data: &[u8]) -> Result<Vec<PersistedEntry>, MempoolError> {
+    if data.len() < 8 || data[..4] != MEMPOOL_FILE_MAGIC {
+        return Err(MempoolError::InvalidMagic);
+    }
+
+    let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    match version {
+        1 => bincode::deserialize(&data[8..]).map_err(|e| MempoolError::Corrupted(e.to_string())),
+        version => Err(MempoolError::UnsupportedVersion(version)),
+    }
+}
+
+/// Timestamp UNIX corrente in secondi
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Transazioni già accettate da un [`Mempool::test_accept`], come se fossero in pool
+#[derive(Default)]
+struct Package<'a> {
+    /// Transazioni per txid
+    txs: HashMap<[u8; 32], &'a Transaction>,
+    /// Outpoint spesi
+    spent: HashSet<OutPoint>,
+    /// Somma delle dimensioni serializzate
+    size: usize,
+}
+
+/// Errori della mempool
+#[derive(Debug, thiserror::Error)]
+pub enum MempoolError {
+    #[error("Transaction already in mempool: {}", hex::encode(txid))]
+    AlreadyKnown { txid: [u8; 32] },
+
+    #[error("Input already spent by a mempool transaction: {outpoint:?}")]
+    Conflict { outpoint: OutPoint },
+
+    #[error("Fee {fee} below minimum {min_fee}")]
+    FeeTooLow { fee: u64, min_fee: u64 },
+
+    #[error("Mempool full ({max_size} bytes) and the transaction feerate is too low to evict others")]
+    Full { max_size: usize },
+
+    #[error("Invalid transaction: {0}")]
+    Invalid(#[from] ValidationError),
+
+    #[error("Not a mempool file")]
+    InvalidMagic,
+
+    #[error("Unsupported mempool file version {0} (max {MEMPOOL_FORMAT_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("Corrupted mempool file: {0}")]
+    Corrupted(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::ChainParams;
+    use crate::validation::block_subsidy;
+    use crate::{anyone_can_spend, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    /// Chain in cui i coinbase dei block 1 e 2 sono spendibili al block successivo
+    fn create_chain() -> (BlockchainDB, Vec<Block>, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path().join("db")).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let mut chain = vec![genesis];
+        for height in 1..=crate::COINBASE_MATURITY + 1 {
+            let coinbase = Transaction::coinbase(&anyone_can_spend(b"miner"), height, block_subsidy(height));
+            let block = Block::new(chain.last().unwrap().hash(), vec![coinbase], 0x1d00ffff, height);
+            db.store_block(&block).unwrap();
+            chain.push(block);
+        }
+        (db, chain, temp_dir)
+    }
+
+    fn spend(outpoint: OutPoint, value: u64) -> Transaction {
+        Transaction::new(
+            vec![TxInput::new(outpoint, vec![])],
+            vec![TxOutput::to_address(value, &anyone_can_spend(b"alice"))],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_add_chain_and_conflicts() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+
+        let coinbase = OutPoint::new(chain[1].transactions[0].hash(), 0);
+        let parent = spend(coinbase.clone(), block_subsidy(1) - 5_000);
+        let child = spend(OutPoint::new(parent.hash(), 0), block_subsidy(1) - 8_000);
+
+        mempool.set_min_fee(5_001);
+        assert!(matches!(
+            mempool.add(parent.clone(), tip, &validator, &db),
+            Err(MempoolError::FeeTooLow { fee: 5_000, .. })
+        ));
+        mempool.set_min_fee(1_000);
+        assert_eq!(mempool.add(parent.clone(), tip, &validator, &db).unwrap(), 5_000);
+        assert_eq!(mempool.add(child.clone(), tip, &validator, &db).unwrap(), 3_000);
+        assert!(matches!(
+            mempool.add(parent.clone(), tip, &validator, &db),
+            Err(MempoolError::AlreadyKnown { .. })
+        ));
+        assert!(matches!(
+            mempool.add(spend(coinbase, 1_000), tip, &validator, &db),
+            Err(MempoolError::Conflict { .. })
+        ));
+
+        // Rimuovere il parent rimuove anche il figlio
+        assert_eq!(mempool.remove(&parent.hash()).len(), 2);
+        assert!(mempool.is_empty());
+        assert!(mempool.by_feerate.is_empty());
+    }
+
+    #[test]
+    fn test_accept_dry_run() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+        mempool.set_min_fee(1_000);
+
+        let coinbase = OutPoint::new(chain[1].transactions[0].hash(), 0);
+        let parent = spend(coinbase.clone(), block_subsidy(1) - 5_000);
+        let child = spend(OutPoint::new(parent.hash(), 0), block_subsidy(1) - 8_000);
+        let double = spend(coinbase.clone(), block_subsidy(1) - 9_000);
+        let cheap = spend(OutPoint::new(chain[2].transactions[0].hash(), 0), block_subsidy(2) - 10);
+
+        // Il figlio spende il parent del pacchetto, la doppia spesa no
+        let results = mempool.test_accept(&[parent.clone(), child.clone(), double, cheap], tip, &validator, &db);
+        assert_eq!(results[0].as_ref().unwrap().fee, 5_000);
+        assert_eq!(results[1].as_ref().unwrap().fee, 3_000);
+        assert!(matches!(results[2], Err(MempoolError::Conflict { .. })));
+        assert!(matches!(results[3], Err(MempoolError::FeeTooLow { fee: 10, .. })));
+        assert!(mempool.is_empty());
+
+        // Senza il parent il figlio non ha input
+        let results = mempool.test_accept(std::slice::from_ref(&child), tip, &validator, &db);
+        assert!(matches!(results[0], Err(MempoolError::Invalid(_))));
+
+        mempool.add(parent.clone(), tip, &validator, &db).unwrap();
+        let results = mempool.test_accept(&[parent, child], tip, &validator, &db);
+        assert!(matches!(results[0], Err(MempoolError::AlreadyKnown { .. })));
+        assert!(results[1].is_ok());
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_select_for_block_script_budget() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+        mempool.set_min_fee(1_000);
+
+        let first = spend(OutPoint::new(chain[1].transactions[0].hash(), 0), block_subsidy(1) - 9_000);
+        let second = spend(OutPoint::new(chain[2].transactions[0].hash(), 0), block_subsidy(2) - 5_000);
+        mempool.add(first.clone(), tip, &validator, &db).unwrap();
+        mempool.add(second, tip, &validator, &db).unwrap();
+        let cost = mempool.get(&first.hash()).unwrap().script_cost;
+        assert!(cost > 0);
+
+        let max_size = crate::MAX_BLOCK_SIZE;
+        assert_eq!(mempool.select_for_block(max_size, validator.max_block_script_cost()).len(), 2);
+        assert_eq!(mempool.select_for_block(max_size, 2 * cost).len(), 2);
+        // Entra solo la transazione con fee per byte più alta
+        let selected = mempool.select_for_block(max_size, 2 * cost - 1);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].tx.hash(), first.hash());
+        assert!(mempool.select_for_block(max_size, cost - 1).is_empty());
+    }
+
+    #[test]
+    fn test_expired_transactions_dropped() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest().with_tx_versions(0, 1..=2));
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+
+        // Scaduta già per il block successivo al tip
+        let stale = spend(OutPoint::new(chain[1].transactions[0].hash(), 0), 1_000).with_expiry(tip);
+        assert!(matches!(
+            mempool.add(stale, tip, &validator, &db),
+            Err(MempoolError::Invalid(ValidationError::ExpiredTransaction { .. }))
+        ));
+
+        let expiring = spend(OutPoint::new(chain[1].transactions[0].hash(), 0), 1_000).with_expiry(tip + 1);
+        let child = spend(OutPoint::new(expiring.hash(), 0), 500);
+        let lasting = spend(OutPoint::new(chain[2].transactions[0].hash(), 0), 1_000).with_expiry(tip + 10);
+        for tx in [expiring, child, lasting.clone()] {
+            mempool.add(tx, tip, &validator, &db).unwrap();
+        }
+
+        // Un block che non la include la fa scadere, insieme al figlio
+        let coinbase = Transaction::coinbase(b"miner", tip + 1, block_subsidy(tip + 1));
+        let block = Block::new(chain[tip as usize].hash(), vec![coinbase], 0x1d00ffff, tip + 1);
+        assert_eq!(mempool.remove_for_block(&block), 2);
+        assert_eq!(mempool.entries().map(|entry| entry.tx.hash()).collect::<Vec<_>>(), vec![lasting.hash()]);
+    }
+
+    #[test]
+    fn test_recent_spends_filtered() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+
+        let coinbase = OutPoint::new(chain[1].transactions[0].hash(), 0);
+        let confirmed = spend(coinbase.clone(), 1_000);
+        mempool.add(confirmed.clone(), tip, &validator, &db).unwrap();
+        let block = Block::new(
+            chain[tip as usize].hash(),
+            vec![Transaction::coinbase(b"miner", tip + 1, block_subsidy(tip + 1)), confirmed],
+            0x1d00ffff,
+            tip + 1,
+        );
+        db.store_block(&block).unwrap();
+        assert_eq!(mempool.remove_for_block(&block), 1);
+
+        // Il filtro segnala l'output speso dal block, l'UTXO set lo conferma
+        assert!(matches!(
+            mempool.add(spend(coinbase, 2_000), tip + 1, &validator, &db),
+            Err(MempoolError::Invalid(ValidationError::DoubleSpend { .. }))
+        ));
+        // Output mai speso: il negativo del filtro esclude una spesa recente
+        mempool.add(spend(OutPoint::new(chain[2].transactions[0].hash(), 0), 1_000), tip + 1, &validator, &db).unwrap();
+
+        let stats = mempool.spent_filter_stats();
+        assert_eq!((stats.lookups, stats.negatives, stats.false_positives), (3, 2, 0));
+        // La spesa confermata e quella in pool
+        assert_eq!(stats.items, 2);
+    }
+
+    #[test]
+    fn test_spillover_to_disk() {
+        let (db, chain, _temp) = create_chain();
+        let db = Arc::new(db);
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+        mempool.set_spillover(db.clone(), 1_000_000).unwrap();
+
+        let paying = spend(OutPoint::new(chain[1].transactions[0].hash(), 0), 1_000);
+        let cheap = spend(OutPoint::new(chain[2].transactions[0].hash(), 0), block_subsidy(2) - 10);
+        mempool.set_max_size(paying.size().unwrap());
+        mempool.add(paying.clone(), tip, &validator, &db).unwrap();
+
+        // Con la pool piena la transazione a fee bassa è accettata su disco
+        let results = mempool.test_accept(std::slice::from_ref(&cheap), tip, &validator, &db);
+        assert_eq!(results[0].as_ref().unwrap().fee, 10);
+        assert_eq!(mempool.add(cheap.clone(), tip, &validator, &db).unwrap(), 10);
+        assert_eq!((mempool.len(), mempool.spilled_len()), (1, 1));
+        assert_eq!(mempool.spilled_size(), cheap.size().unwrap());
+        assert!(matches!(
+            mempool.add(cheap.clone(), tip, &validator, &db),
+            Err(MempoolError::AlreadyKnown { .. })
+        ));
+
+        // Un'altra pool sullo stesso database ritrova il livello
+        let mut reopened = Mempool::new();
+        reopened.set_spillover(db.clone(), 1_000_000).unwrap();
+        assert_eq!(reopened.spilled_size(), mempool.spilled_size());
+
+        // Il block conferma la prima e libera spazio per la seconda
+        let block = Block::new(
+            chain[tip as usize].hash(),
+            vec![Transaction::coinbase(b"miner", tip + 1, block_subsidy(tip + 1)), paying],
+            0x1d00ffff,
+            tip + 1,
+        );
+        db.store_block(&block).unwrap();
+        mempool.remove_for_block(&block);
+        assert_eq!(mempool.promote_spilled(tip + 1, &validator, &db).unwrap(), 1);
+        assert_eq!(mempool.entries().map(|entry| entry.tx.hash()).collect::<Vec<_>>(), vec![cheap.hash()]);
+        assert_eq!((mempool.spilled_len(), mempool.spilled_size()), (0, 0));
+        assert!(db.spilled_transactions().unwrap().is_empty());
+
+        // Oltre la dimensione del livello si scartano quelle a fee per byte più bassa
+        let mut previous = block.hash();
+        for height in tip + 2..tip + 4 {
+            let next = Block::new(previous, vec![Transaction::coinbase(b"miner", height, 0)], 0x1d00ffff, height);
+            db.store_block(&next).unwrap();
+            previous = next.hash();
+        }
+        let best = spend(OutPoint::new(chain[3].transactions[0].hash(), 0), block_subsidy(3) - 5_000);
+        let middle = spend(OutPoint::new(chain[4].transactions[0].hash(), 0), block_subsidy(4) - 3_000);
+        mempool.add(best.clone(), tip + 3, &validator, &db).unwrap();
+        mempool.add(middle.clone(), tip + 3, &validator, &db).unwrap();
+        assert_eq!(mempool.entries().map(|entry| entry.tx.hash()).collect::<Vec<_>>(), vec![best.hash()]);
+        assert_eq!(mempool.spilled_len(), 2);
+        // L'indice per fee per byte segue le transazioni in memoria
+        assert_eq!(mempool.by_feerate.iter().map(|(_, txid)| *txid).collect::<Vec<_>>(), vec![best.hash()]);
+
+        mempool.set_spillover(db.clone(), middle.size().unwrap()).unwrap();
+        assert_eq!(mempool.spilled_len(), 1);
+        let on_disk = db.spilled_transactions().unwrap();
+        assert_eq!(on_disk.iter().map(|(_, txid, _)| *txid).collect::<Vec<_>>(), vec![middle.hash()]);
+    }
+
+    #[test]
+    fn test_large_datum_pays_surcharge() {
+        use crate::state::{state_script, DATUM_FEE_PER_BYTE, DATUM_FREE_BYTES};
+
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let mut mempool = Mempool::new();
+        mempool.set_min_fee(1_000);
+
+        let script = state_script(&[7; DATUM_FREE_BYTES + 100], b"validator").unwrap();
+        let with_fee = |fee: u64| Transaction::new(
+            vec![TxInput::new(OutPoint::new(chain[1].transactions[0].hash(), 0), vec![])],
+            vec![TxOutput::new(block_subsidy(1) - fee, [0; 32], script.clone())],
+            0,
+        );
+        let min_fee = 1_000 + 100 * DATUM_FEE_PER_BYTE;
+        assert!(matches!(
+            mempool.add(with_fee(min_fee - 1), tip, &validator, &db),
+            Err(MempoolError::FeeTooLow { min_fee: required, .. }) if required == min_fee
+        ));
+        assert_eq!(mempool.add(with_fee(min_fee), tip, &validator, &db).unwrap(), min_fee);
+    }
+
+    #[test]
+    fn test_rejection_log_records_rule_violations() {
+        let (db, chain, _temp) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let rejections = Arc::new(Mutex::new(RejectionLog::new(10)));
+        let mut mempool = Mempool::new();
+        mempool.set_min_fee(1_000);
+        mempool.set_rejection_log(Some(rejections.clone()));
+
+        let missing = spend(OutPoint::new([9; 32], 0), 1_000);
+        assert!(matches!(mempool.add(missing.clone(), tip, &validator, &db), Err(MempoolError::Invalid(_))));
+        // Fee troppo bassa: rifiuto di policy, non annotato
+        let coinbase = OutPoint::new(chain[1].transactions[0].hash(), 0);
+        assert!(mempool.add(spend(coinbase, block_subsidy(1)), tip, &validator, &db).is_err());
+
+        let log = rejections.lock().unwrap();
+        assert_eq!(log.total(), 1);
+        let entry = log.recent(1)[0];
+        assert_eq!((entry.hash, entry.rule.as_str(), entry.height), (missing.hash(), "missing-input", tip + 1));
+    }
+
+    #[test]
+    fn test_save_and_load_revalidates() {
+        let (db, chain, temp_dir) = create_chain();
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let tip = chain.len() as u64 - 1;
+        let path = temp_dir.path().join(MEMPOOL_FILE_NAME);
+
+        let mut mempool = Mempool::new();
+        let first = spend(OutPoint::new(chain[1].transactions[0].hash(), 0), 1_000);
+        let second = spend(OutPoint::new(chain[2].transactions[0].hash(), 0), 1_000);
+        mempool.add(first.clone(), tip, &validator, &db).unwrap();
+        let child = spend(OutPoint::new(second.hash(), 0), 500);
+        mempool.add(second.clone(), tip, &validator, &db).unwrap();
+        mempool.add(child.clone(), tip, &validator, &db).unwrap();
+        assert_eq!(mempool.save(&path).unwrap(), 3);
+
+        // Il block successivo conferma la prima transazione
+        let coinbase = Transaction::coinbase(b"miner", tip + 1, block_subsidy(tip + 1));
+        let block = Block::new(chain[tip as usize].hash(), vec![coinbase, first.clone()], 0x1d00ffff, tip + 1);
+        db.store_block(&block).unwrap();
+
+        let (loaded, stats) = Mempool::load(&path, &validator, &db).unwrap();
+        assert_eq!(stats, MempoolLoadStats { loaded: 2, failed: 1, expired: 0 });
+        assert!(loaded.contains(&second.hash()) && loaded.contains(&child.hash()));
+        assert_eq!(loaded.get(&second.hash()).unwrap().height, tip);
+
+        // File mancante: pool vuota; versione futura: errore esplicito
+        let (empty, _) = Mempool::load(temp_dir.path().join("missing.dat"), &validator, &db).unwrap();
+        assert!(empty.is_empty());
+
+        let mut data = fs::read(&path).unwrap();
+        data[4..8].copy_from_slice(&(MEMPOOL_FORMAT_VERSION + 1).to_le_bytes());
+        fs::write(&path, data).unwrap();
+        assert!(matches!(
+            Mempool::load(&path, &validator, &db),
+            Err(MempoolError::UnsupportedVersion(_))
+        ));
+    }
+}
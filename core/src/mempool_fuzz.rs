@@ -0,0 +1,349 @@
+//! Generatori di transazioni avversarie e fuzz test della mempool
+//!
+//! A ogni round un generatore produce transazioni ostili verso la pool:
+//! catene in conflitto sullo stesso outpoint, fee-bump di transazioni già
+//! in pool, transazioni vicine ai limiti di dimensione, tempeste di output
+//! dust e spese casuali (anche invalide). Ogni tanto un block conferma
+//! parte della pool e una spesa in conflitto con essa. Dopo ogni
+//! operazione vengono verificati gli invarianti della pool.
+//!
+//! Il test lungo è `#[ignore]`: `cargo test -p sedly-core mempool_fuzz -- --ignored`.
+//! `SEDLY_MEMPOOL_FUZZ_SEED` e `SEDLY_MEMPOOL_FUZZ_ROUNDS` riproducono una sessione.
+
+use crate::mempool::{Mempool, MempoolEntry, MempoolError};
+use crate::params::ChainParams;
+use crate::script::MAX_SCRIPT_SIZE;
+use crate::storage::BlockchainDB;
+use crate::validation::{block_subsidy, BlockValidator};
+use crate::{anyone_can_spend, Amount, Block, OutPoint, Transaction, TxInput, TxOutput, MIN_TX_FEE};
+use std::collections::{HashMap, HashSet};
+use tempfile::TempDir;
+
+/// Valore di ogni output della transazione di funding
+const COIN_VALUE: u64 = 10_000_000;
+
+/// Dimensione massima della pool nei test: piccola per forzare le espulsioni
+const FUZZ_MEMPOOL_SIZE: usize = 50_000;
+
+/// Generatore pseudo-casuale deterministico (splitmix64)
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Intero in `0..n` (n > 0)
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Intero in `low..=high`
+    fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.below(high - low + 1)
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        (!items.is_empty()).then(|| &items[self.below(items.len() as u64) as usize])
+    }
+}
+
+/// Outpoint spendibile con il suo valore
+type Coin = (OutPoint, u64);
+
+/// Chain di test e UTXO confermati noti ai generatori
+struct Fixture {
+    db: BlockchainDB,
+    validator: BlockValidator,
+    tip: Block,
+    confirmed: Vec<Coin>,
+    _temp_dir: TempDir,
+}
+
+impl Fixture {
+    /// Genesis più un block con una transazione di funding da `coins` output
+    fn new(coins: usize) -> Self {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let funding = Transaction::new(
+            vec![TxInput::new(OutPoint::new([0xfe; 32], 0), vec![])],
+            (0..coins)
+                .map(|i| TxOutput::to_address(COIN_VALUE, &anyone_can_spend(format!("coin{}", i).as_bytes())))
+                .collect(),
+            0,
+        );
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1));
+        let block = Block::new(genesis.hash(), vec![coinbase, funding.clone()], 0x1d00ffff, 1);
+        db.store_block(&block).unwrap();
+
+        let funding_txid = funding.hash();
+        Self {
+            db,
+            validator: BlockValidator::new(ChainParams::regtest()),
+            confirmed: (0..coins).map(|vout| (OutPoint::new(funding_txid, vout as u32), COIN_VALUE)).collect(),
+            tip: block,
+            _temp_dir: temp_dir,
+        }
+    }
+
+    fn height(&self) -> u64 {
+        self.tip.header.height
+    }
+
+    /// Coin spendibili: confermati e output della pool, spesi o no
+    fn coins(&self, pool: &Mempool) -> Vec<Coin> {
+        let mut coins = self.confirmed.clone();
+        for entry in pool.entries() {
+            let txid = entry.tx.hash();
+            coins.extend(entry.tx.outputs.iter().enumerate().map(|(vout, output)| {
+                (OutPoint::new(txid, vout as u32), output.value.to_sat())
+            }));
+        }
+        coins
+    }
+
+    /// Conferma in un block alcune transazioni della pool senza parent in pool,
+    /// più una spesa confermata in conflitto con una di quelle rimaste
+    fn mine(&mut self, rng: &mut Rng, pool: &mut Mempool) {
+        let pool_txids: HashSet<[u8; 32]> = pool.entries().map(|entry| entry.tx.hash()).collect();
+        let mut included: Vec<Transaction> = pool
+            .entries()
+            .filter(|entry| entry.tx.inputs.iter().all(|input| !pool_txids.contains(&input.previous_output.txid)))
+            .filter(|_| rng.chance(50))
+            .map(|entry| entry.tx.clone())
+            .collect();
+        included.sort_by_key(Transaction::hash);
+
+        let spent: HashSet<OutPoint> = included
+            .iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.previous_output.clone()))
+            .collect();
+        let conflict = pool
+            .entries()
+            .flat_map(|entry| entry.tx.inputs.iter().map(|input| input.previous_output.clone()))
+            .find(|outpoint| !spent.contains(outpoint) && self.confirmed.iter().any(|(coin, _)| coin == outpoint));
+        if let Some(outpoint) = conflict {
+            let value = self.confirmed.iter().find(|(coin, _)| *coin == outpoint).unwrap().1;
+            included.push(spend(vec![(outpoint, value)], 1, MIN_TX_FEE, b"double"));
+        }
+
+        let height = self.height() + 1;
+        let mut transactions = vec![Transaction::coinbase(b"miner", height, block_subsidy(height))];
+        transactions.extend(included);
+        let block = Block::new(self.tip.hash(), transactions, 0x1d00ffff, height);
+        self.db.store_block(&block).unwrap();
+        pool.remove_for_block(&block);
+
+        for tx in &block.transactions[1..] {
+            self.confirmed.retain(|(coin, _)| tx.inputs.iter().all(|input| input.previous_output != *coin));
+            let txid = tx.hash();
+            self.confirmed.extend(tx.outputs.iter().enumerate().map(|(vout, output)| {
+                (OutPoint::new(txid, vout as u32), output.value.to_sat())
+            }));
+        }
+        self.tip = block;
+    }
+}
+
+/// Transazione che spende `inputs` in `outputs` output uguali pagando `fee`
+fn spend(inputs: Vec<Coin>, outputs: u64, fee: u64, address: &[u8]) -> Transaction {
+    let total: u64 = inputs.iter().map(|(_, value)| value).sum();
+    let value = total.saturating_sub(fee) / outputs.max(1);
+    Transaction::new(
+        inputs.into_iter().map(|(outpoint, _)| TxInput::new(outpoint, vec![])).collect(),
+        (0..outputs).map(|_| TxOutput::to_address(value, &anyone_can_spend(address))).collect(),
+        0,
+    )
+}
+
+/// Due catene che spendono lo stesso coin, con fee diverse
+fn conflicting_chains(rng: &mut Rng, coins: &[Coin]) -> Vec<Transaction> {
+    let Some(coin) = rng.pick(coins).cloned() else {
+        return Vec::new();
+    };
+    let mut txs = Vec::new();
+    for branch in 0..2u8 {
+        let mut input = coin.clone();
+        for _ in 0..rng.range(1, 4) {
+            let tx = spend(vec![input], 1, rng.range(MIN_TX_FEE, 20 * MIN_TX_FEE), &[b'c', branch]);
+            input = (OutPoint::new(tx.hash(), 0), tx.outputs[0].value.to_sat());
+            txs.push(tx);
+        }
+    }
+    txs
+}
+
+/// Ripropone una transazione in pool con gli stessi input e una fee più alta
+fn fee_bump(rng: &mut Rng, pool: &Mempool) -> Vec<Transaction> {
+    let entries: Vec<&MempoolEntry> = pool.entries().collect();
+    let Some(entry) = rng.pick(&entries) else {
+        return Vec::new();
+    };
+    let mut tx = entry.tx.clone();
+    let bump = rng.range(1, 10 * MIN_TX_FEE);
+    tx.outputs[0].value = tx.outputs[0].value.saturating_sub(bump.into()).max(Amount::ONE_SAT);
+    vec![tx]
+}
+
+/// Transazioni con script vicini al limite, grandi rispetto alla pool
+fn near_limit(rng: &mut Rng, coins: &[Coin]) -> Vec<Transaction> {
+    let Some(coin) = rng.pick(coins).cloned() else {
+        return Vec::new();
+    };
+    let outputs = rng.range(1, 3);
+    let mut tx = spend(vec![coin], outputs, rng.range(MIN_TX_FEE, 100 * MIN_TX_FEE), b"big");
+    for output in &mut tx.outputs {
+        output.script_pubkey = vec![0x6a; MAX_SCRIPT_SIZE - rng.below(16) as usize];
+    }
+    vec![tx]
+}
+
+/// Molti output minuscoli, poi transazioni che li raccolgono
+fn dust_storm(rng: &mut Rng, coins: &[Coin]) -> Vec<Transaction> {
+    let Some(coin) = rng.pick(coins).cloned() else {
+        return Vec::new();
+    };
+    let outputs = rng.range(20, 60);
+    let fee = coin.1.saturating_sub(outputs);
+    let storm = spend(vec![coin], outputs, fee, b"dust");
+    let txid = storm.hash();
+
+    let mut txs = vec![storm];
+    for chunk in (0..outputs as u32).collect::<Vec<_>>().chunks(8) {
+        let dust = chunk.iter().map(|vout| (OutPoint::new(txid, *vout), 1)).collect();
+        // Senza fee sufficiente: la pool deve rifiutarle
+        txs.push(spend(dust, 1, rng.below(2), b"sweep"));
+    }
+    txs
+}
+
+/// Spese casuali, anche oltre il valore degli input o con input duplicati
+fn random_spend(rng: &mut Rng, coins: &[Coin]) -> Vec<Transaction> {
+    let inputs: Vec<Coin> = (0..rng.range(1, 3)).filter_map(|_| rng.pick(coins).cloned()).collect();
+    let total: u64 = inputs.iter().map(|(_, value)| value).sum();
+    let fee = match rng.below(4) {
+        0 => rng.below(MIN_TX_FEE),
+        1 => total + 1,
+        _ => rng.range(MIN_TX_FEE, 50 * MIN_TX_FEE),
+    };
+    vec![spend(inputs, rng.range(1, 4), fee, b"random")]
+}
+
+/// Verifica gli invarianti della pool contro il UTXO set
+fn check_invariants(pool: &Mempool, db: &BlockchainDB) {
+    let txids: HashMap<[u8; 32], &MempoolEntry> = pool.entries().map(|entry| (entry.tx.hash(), entry)).collect();
+    let mut reserved = HashSet::new();
+    let mut total_size = 0;
+    for entry in pool.entries() {
+        total_size += entry.size;
+        for input in &entry.tx.inputs {
+            let outpoint = &input.previous_output;
+            assert!(reserved.insert(outpoint.clone()), "outpoint {:?} reserved twice", outpoint);
+
+            // Ogni input esiste: output di una transazione in pool o UTXO confermato
+            let in_pool = txids
+                .get(&outpoint.txid)
+                .is_some_and(|parent| (outpoint.vout as usize) < parent.tx.outputs.len());
+            assert!(in_pool || db.get_utxo(outpoint).unwrap().is_some(), "orphan input {:?}", outpoint);
+        }
+    }
+    assert_eq!(pool.total_size(), total_size);
+    assert!(pool.total_size() <= pool.max_size(), "{} > {}", pool.total_size(), pool.max_size());
+}
+
+/// Le transazioni espulse da un inserimento pagano al massimo quanto quelle rimaste
+///
+/// I discendenti escono con il parent: conta solo la fee per byte delle
+/// radici espulse.
+fn check_eviction_order(before: &HashMap<[u8; 32], MempoolEntry>, pool: &Mempool) {
+    let evicted: HashMap<&[u8; 32], &MempoolEntry> =
+        before.iter().filter(|(txid, _)| !pool.contains(txid)).collect();
+    for root in evicted.values() {
+        if root.tx.inputs.iter().any(|input| evicted.contains_key(&input.previous_output.txid)) {
+            continue;
+        }
+        for kept in pool.entries() {
+            assert_ne!(
+                root.cmp_feerate(kept),
+                std::cmp::Ordering::Greater,
+                "evicted feerate {} above kept {}",
+                root.feerate(),
+                kept.feerate(),
+            );
+        }
+    }
+}
+
+/// Esegue `rounds` round di fuzzing con il seed dato
+fn fuzz_mempool(seed: u64, rounds: u64) {
+    let mut rng = Rng(seed);
+    let mut fixture = Fixture::new(64);
+    let mut pool = Mempool::new();
+    pool.set_min_fee(MIN_TX_FEE);
+    pool.set_max_size(FUZZ_MEMPOOL_SIZE);
+
+    let mut accepted = 0;
+    let mut full = 0;
+    for round in 0..rounds {
+        if round % 25 == 24 {
+            fixture.mine(&mut rng, &mut pool);
+            check_invariants(&pool, &fixture.db);
+            continue;
+        }
+
+        // Le tempeste di dust riempiono la lista di coin minuscoli: gli altri
+        // generatori partono da coin che possono pagare una fee
+        let coins = fixture.coins(&pool);
+        let funded: Vec<Coin> = coins.iter().filter(|(_, value)| *value > 100 * MIN_TX_FEE).cloned().collect();
+        let txs = match rng.below(5) {
+            0 => conflicting_chains(&mut rng, &funded),
+            1 => fee_bump(&mut rng, &pool),
+            2 => near_limit(&mut rng, &funded),
+            3 => dust_storm(&mut rng, &funded),
+            _ => random_spend(&mut rng, &coins),
+        };
+        for tx in txs {
+            let before: HashMap<[u8; 32], MempoolEntry> =
+                pool.entries().map(|entry| (entry.tx.hash(), entry.clone())).collect();
+            match pool.add(tx, fixture.height(), &fixture.validator, &fixture.db) {
+                Ok(_) => accepted += 1,
+                Err(MempoolError::Full { .. }) => full += 1,
+                Err(MempoolError::Storage(e)) => panic!("storage error: {}", e),
+                Err(_) => assert!(before.keys().all(|txid| pool.contains(txid)), "rejection changed the pool"),
+            }
+            check_eviction_order(&before, &pool);
+            check_invariants(&pool, &fixture.db);
+        }
+    }
+    assert!(accepted > 0 && full > 0, "seed {}: {} accepted, {} rejected as full", seed, accepted, full);
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+#[test]
+fn test_mempool_fuzz() {
+    let seed = env_u64("SEDLY_MEMPOOL_FUZZ_SEED").unwrap_or(1);
+    fuzz_mempool(seed, env_u64("SEDLY_MEMPOOL_FUZZ_ROUNDS").unwrap_or(100));
+}
+
+#[test]
+#[ignore = "long-running fuzz session"]
+fn test_mempool_fuzz_long() {
+    let rounds = env_u64("SEDLY_MEMPOOL_FUZZ_ROUNDS").unwrap_or(5_000);
+    for seed in 1..=20 {
+        fuzz_mempool(seed, rounds);
+    }
+}
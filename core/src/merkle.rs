@@ -0,0 +1,107 @@
+//! Merkle tree con aggiornamento incrementale delle foglie
+//!
+//! La costruzione dei template di mining cambia solo la coinbase (foglia 0)
+//! a ogni refresh dell'extranonce. Il tree tiene in memoria tutti i livelli,
+//! quindi cambiare una foglia ricalcola solo il percorso fino alla root
+//! (log2 n hash) invece di tutto il tree.
+
+use crate::hash::{merkle_level, merkle_parent};
+use crate::Transaction;
+
+/// Merkle tree di una lista di hash, con tutti i livelli in memoria
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// Livelli dalle foglie alla root; vuoto se non ci sono foglie
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Costruisce il tree sulle foglie
+    pub fn new(leaves: Vec<[u8; 32]>) -> Self {
+        if leaves.is_empty() {
+            return Self { levels: Vec::new() };
+        }
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = merkle_level(&levels[levels.len() - 1]);
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Tree sugli hash delle transazioni
+    pub fn from_transactions(transactions: &[Transaction]) -> Self {
+        Self::new(transactions.iter().map(Transaction::hash).collect())
+    }
+
+    /// Merkle root (zero se non ci sono foglie, come `Block::calculate_merkle_root`)
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().map_or([0; 32], |level| level[0])
+    }
+
+    /// Numero di foglie
+    pub fn len(&self) -> usize {
+        self.levels.first().map_or(0, Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Sostituisce la foglia `index` e ricalcola il suo percorso fino alla root
+    ///
+    /// I fratelli lungo il percorso sono quelli in cache: per la foglia 0
+    /// (la coinbase) sono i rami a destra, che non cambiano.
+    ///
+    /// # Panics
+    ///
+    /// Se `index` non è una foglia del tree.
+    pub fn update_leaf(&mut self, index: usize, hash: [u8; 32]) {
+        assert!(index < self.len(), "leaf index {} out of range ({} leaves)", index, self.len());
+        self.levels[0][index] = hash;
+
+        let mut index = index;
+        for depth in 1..self.levels.len() {
+            let below = &self.levels[depth - 1];
+            let left = index & !1;
+            // Con un numero dispari di nodi l'ultimo è accoppiato con se stesso
+            let right = (left + 1).min(below.len() - 1);
+            let parent = merkle_parent(&below[left], &below[right]);
+            index /= 2;
+            self.levels[depth][index] = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::merkle_root;
+
+    fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn test_update_leaf_matches_rebuild() {
+        for count in 1..=9u8 {
+            let mut leaves = leaves(count);
+            let mut tree = MerkleTree::new(leaves.clone());
+            assert_eq!(tree.root(), merkle_root(&leaves));
+
+            // Ogni foglia, inclusa l'ultima di un livello dispari
+            for index in 0..leaves.len() {
+                leaves[index] = [0xaa ^ index as u8; 32];
+                tree.update_leaf(index, leaves[index]);
+                assert_eq!(tree, MerkleTree::new(leaves.clone()), "count {} index {}", count, index);
+            }
+        }
+        assert_eq!(MerkleTree::new(Vec::new()).root(), [0; 32]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_update_leaf_out_of_range() {
+        MerkleTree::new(leaves(3)).update_leaf(3, [0; 32]);
+    }
+}
@@ -1,11 +1,16 @@
 //! Mining SHA-256 implementation per Sedly blockchain
 
-use crate::{Block, BlockHeader, Transaction};
+use crate::{Block, BlockHeader, MerkleTree, PowKind, Transaction};
 use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+/// Nonce provati da un thread di `Miner::mine_template` tra due controlli dello stop
+const TEMPLATE_NONCE_BATCH: u64 = 1_000;
+
 /// Miner per il mining di nuovi blocks
 pub struct Miner {
     /// Target difficulty corrente
@@ -16,6 +21,8 @@ pub struct Miner {
     pub should_stop: Arc<AtomicBool>,
     /// Nonce counter globale per evitare duplicati
     pub nonce_counter: Arc<AtomicU64>,
+    /// Algoritmo di proof of work (`ChainParams::pow_algorithm` all'altezza minata)
+    pub algorithm: PowKind,
 }
 
 /// Risultato del mining
@@ -54,9 +61,16 @@ impl Miner {
             threads,
             should_stop: Arc::new(AtomicBool::new(false)),
             nonce_counter: Arc::new(AtomicU64::new(0)),
+            algorithm: PowKind::Sha256d,
         }
     }
 
+    /// Usa l'algoritmo di proof of work `algorithm`
+    pub fn with_pow_algorithm(mut self, algorithm: PowKind) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
     /// Crea miner con difficulty bits
     pub fn with_difficulty_bits(bits: u32, threads: usize) -> Self {
         let target = crate::block::bits_to_target(bits);
@@ -114,6 +128,7 @@ impl Miner {
                     let block = Block {
                         header,
                         transactions,
+                        auxpow: None,
                     };
 
                     return Ok(MiningResult {
@@ -151,7 +166,7 @@ impl Miner {
 
     /// Controlla se l'header soddisfa la proof of work
     fn check_proof_of_work(&self, header: &BlockHeader) -> bool {
-        let hash = header.hash();
+        let hash = header.pow_hash(self.algorithm.algorithm());
         hash <= self.target
     }
 
@@ -222,6 +237,7 @@ impl Miner {
             let tx = tx.clone();
             let template = header_template.clone();
             let target = self.target;
+            let algorithm = self.algorithm.algorithm();
             let should_stop = Arc::clone(&self.should_stop);
             let nonce_counter = Arc::clone(&self.nonce_counter);
             let transactions = transactions.clone();
@@ -242,12 +258,13 @@ impl Miner {
                         header.nonce = start_nonce + nonce_offset;
                         local_hashes += 1;
 
-                        let hash = header.hash();
+                        let hash = header.pow_hash(algorithm);
                         if hash <= target {
                             // Found solution!
                             let block = Block {
                                 header,
                                 transactions: transactions.clone(),
+                                auxpow: None,
                             };
 
                             let result = MiningResult {
@@ -292,6 +309,61 @@ impl Miner {
         result
     }
 
+    /// Mina un template con il target dei suoi bits
+    ///
+    /// Ogni thread prova nonce distinti finché uno soddisfa il target, finché
+    /// `should_stop` diventa true o finché passa `max_time`. Il flag di stop
+    /// non viene azzerato: il chiamante lo azzera prima di costruire il
+    /// template, così uno stop chiesto nel frattempo (es. nuovo tip) non va perso.
+    pub fn mine_template(&self, template: &BlockTemplate, max_time: Duration) -> Result<MiningResult, MiningError> {
+        let start_time = Instant::now();
+        let target = template.header.target();
+        let algorithm = self.algorithm.algorithm();
+        let nonce_counter = AtomicU64::new(0);
+        let found = std::sync::Mutex::new(None);
+        let total_hashes = AtomicU64::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| {
+                    let mut header = template.header.clone();
+                    let mut local_hashes = 0u64;
+                    while !self.should_stop.load(Ordering::Relaxed)
+                        && start_time.elapsed() < max_time
+                        && found.lock().unwrap().is_none()
+                    {
+                        let start_nonce = nonce_counter.fetch_add(TEMPLATE_NONCE_BATCH, Ordering::Relaxed);
+                        for nonce in start_nonce..start_nonce + TEMPLATE_NONCE_BATCH {
+                            header.nonce = nonce;
+                            local_hashes += 1;
+                            if header.pow_hash(algorithm) <= target {
+                                found.lock().unwrap().get_or_insert(header.clone());
+                                break;
+                            }
+                        }
+                    }
+                    total_hashes.fetch_add(local_hashes, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let header = match found.into_inner().unwrap() {
+            Some(header) => header,
+            None if self.should_stop.load(Ordering::Relaxed) => return Err(MiningError::Stopped),
+            None => return Err(MiningError::Timeout),
+        };
+        let mut block = template.clone().into_block();
+        block.header = header;
+        let mining_time = start_time.elapsed();
+        let hashes_calculated = total_hashes.into_inner();
+        Ok(MiningResult {
+            block,
+            hashes_calculated,
+            mining_time,
+            hash_rate: hashes_calculated as f64 / mining_time.as_secs_f64(),
+        })
+    }
+
     /// Verifica se un block hash soddisfa il target
     pub fn verify_block_hash(block: &Block, target: &[u8; 32]) -> bool {
         let hash = block.hash();
@@ -312,6 +384,125 @@ impl Miner {
     }
 }
 
+/// Block in costruzione con il merkle tree in cache
+///
+/// Cambiare l'extranonce riscrive lo script della coinbase e ricalcola solo
+/// il ramo sinistro del merkle tree: il refresh del template costa log2 n
+/// hash invece di uno per transazione.
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    /// Header con la merkle root corrente
+    pub header: BlockHeader,
+    transactions: Vec<Transaction>,
+    merkle: MerkleTree,
+    /// Script della coinbase senza extranonce
+    coinbase_script: Vec<u8>,
+}
+
+impl BlockTemplate {
+    /// Template sopra `previous_hash`; la prima transazione deve essere la coinbase
+    pub fn new(
+        previous_hash: [u8; 32],
+        transactions: Vec<Transaction>,
+        bits: u32,
+        height: u64,
+    ) -> Result<Self, MiningError> {
+        let coinbase = transactions.first()
+            .filter(|tx| tx.is_coinbase())
+            .ok_or_else(|| MiningError::InvalidTemplate("first transaction is not a coinbase".to_string()))?;
+        let coinbase_script = coinbase.inputs[0].script_sig.clone();
+        let merkle = MerkleTree::from_transactions(&transactions);
+        let header = BlockHeader::new(crate::PROTOCOL_VERSION, previous_hash, merkle.root(), bits, height);
+
+        Ok(Self {
+            header,
+            transactions,
+            merkle,
+            coinbase_script,
+        })
+    }
+
+    /// Accoda `extra_nonce` allo script originale della coinbase
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) {
+        let mut coinbase = self.transactions[0].clone();
+        coinbase.inputs[0].script_sig = [&self.coinbase_script[..], &extra_nonce.to_le_bytes()].concat();
+        self.replace_coinbase(coinbase);
+    }
+
+    /// Sostituisce la coinbase (es. nuovo beneficiario o fee aggiornate)
+    pub fn set_coinbase(&mut self, coinbase: Transaction) -> Result<(), MiningError> {
+        if !coinbase.is_coinbase() {
+            return Err(MiningError::InvalidTemplate("replacement is not a coinbase".to_string()));
+        }
+        self.coinbase_script = coinbase.inputs[0].script_sig.clone();
+        self.replace_coinbase(coinbase);
+        Ok(())
+    }
+
+    fn replace_coinbase(&mut self, coinbase: Transaction) {
+        self.merkle.update_leaf(0, coinbase.hash());
+        self.header.merkle_root = self.merkle.root();
+        self.transactions[0] = coinbase;
+    }
+
+    /// Transazioni del template, coinbase inclusa
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Block con l'header corrente
+    pub fn into_block(self) -> Block {
+        Block {
+            header: self.header,
+            transactions: self.transactions,
+            auxpow: None,
+        }
+    }
+}
+
+/// Aumento percentuale delle fee del template che lo rende obsoleto
+pub const LONGPOLL_FEE_INCREASE_PERCENT: u64 = 10;
+
+/// Stato della chain e della mempool da cui è stato costruito un template
+///
+/// I miner esterni ripassano il `longpollid` a `getblocktemplate`: la
+/// richiesta resta in attesa finché il template non è obsoleto, cioè finché
+/// non cambia il tip o le fee raccoglibili non crescono di almeno
+/// `LONGPOLL_FEE_INCREASE_PERCENT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LongPollId {
+    /// Tip sopra cui è costruito il template
+    pub tip: [u8; 32],
+    /// Fee totali delle transazioni del template
+    pub fees: u64,
+}
+
+impl LongPollId {
+    /// Se un template costruito ora su `tip` con `fees` sostituisce questo
+    pub fn is_stale(&self, tip: &[u8; 32], fees: u64) -> bool {
+        let threshold = (self.fees as u128 * LONGPOLL_FEE_INCREASE_PERCENT as u128 / 100) as u64;
+        *tip != self.tip || (fees > self.fees && fees - self.fees >= threshold)
+    }
+}
+
+impl fmt::Display for LongPollId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", hex::encode(self.tip), self.fees)
+    }
+}
+
+impl FromStr for LongPollId {
+    type Err = MiningError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MiningError::InvalidLongPollId(s.to_string());
+        let (tip, fees) = (s.get(..64).ok_or_else(invalid)?, &s[64..]);
+        let mut id = LongPollId { tip: [0; 32], fees: fees.parse().map_err(|_| invalid())? };
+        hex::decode_to_slice(tip, &mut id.tip).map_err(|_| invalid())?;
+        Ok(id)
+    }
+}
+
 /// Converte array di 32 bytes in approssimazione u64 per calcoli
 fn u256_from_bytes(bytes: &[u8; 32]) -> u64 {
     // Prende solo gli ultimi 8 bytes per approssimazione
@@ -330,6 +521,8 @@ pub enum MiningError {
     Timeout,
     #[error("Invalid block template: {0}")]
     InvalidTemplate(String),
+    #[error("Invalid long poll id: {0}")]
+    InvalidLongPollId(String),
 }
 
 /// Utility functions
@@ -430,6 +623,64 @@ mod tests {
         assert_eq!(stats.format_hash_rate(), "1.50 MH/s");
     }
 
+    #[test]
+    fn test_template_extra_nonce() {
+        let coinbase = Transaction::coinbase(b"miner", 1, 50);
+        let spend = Transaction::new(Vec::new(), vec![crate::TxOutput::to_address(10, b"alice")], 0);
+        let mut template = BlockTemplate::new([1; 32], vec![coinbase, spend.clone(), spend.clone()], 0x1d00ffff, 1).unwrap();
+
+        template.set_extra_nonce(7);
+        template.set_extra_nonce(8);
+        let block = template.clone().into_block();
+        assert!(block.transactions[0].inputs[0].script_sig.ends_with(&8u64.to_le_bytes()));
+        assert_eq!(block.header.merkle_root, Block::calculate_merkle_root(&block.transactions));
+
+        // La nuova coinbase diventa la base dell'extranonce
+        assert!(template.set_coinbase(spend).is_err());
+        template.set_coinbase(Transaction::coinbase(b"other", 1, 50)).unwrap();
+        template.set_extra_nonce(1);
+        let block = template.into_block();
+        assert_eq!(block.transactions[0].outputs[0].script_pubkey, b"other".to_vec());
+        assert_eq!(block.header.merkle_root, Block::calculate_merkle_root(&block.transactions));
+        assert!(BlockTemplate::new([1; 32], Vec::new(), 0x1d00ffff, 1).is_err());
+    }
+
+    #[test]
+    fn test_mine_template() {
+        let coinbase = Transaction::coinbase(b"miner", 1, 50);
+        let template = BlockTemplate::new([1; 32], vec![coinbase], 0x207fffff, 1).unwrap();
+        let miner = Miner::new([0; 32], 2).with_pow_algorithm(PowKind::Sha3);
+
+        // Il target viene dai bits del template, non dal miner
+        let result = miner.mine_template(&template, Duration::from_secs(60)).unwrap();
+        assert!(result.block.header.meets_difficulty_with(PowKind::Sha3.algorithm()));
+        assert_eq!(result.block.transactions, template.transactions());
+        assert!(result.hashes_calculated > 0);
+
+        // Uno stop chiesto prima dell'avvio non viene azzerato
+        miner.stop();
+        let hard = BlockTemplate::new([1; 32], template.transactions().to_vec(), 0x1d00ffff, 1).unwrap();
+        assert!(matches!(miner.mine_template(&hard, Duration::from_secs(60)), Err(MiningError::Stopped)));
+        miner.should_stop.store(false, Ordering::Relaxed);
+        assert!(matches!(miner.mine_template(&hard, Duration::ZERO), Err(MiningError::Timeout)));
+    }
+
+    #[test]
+    fn test_long_poll_id() {
+        let id = LongPollId { tip: [7; 32], fees: 1_000 };
+        assert_eq!(id.to_string().parse::<LongPollId>().unwrap(), id);
+        assert!("07".parse::<LongPollId>().is_err());
+        assert!(format!("{}x", "07".repeat(32)).parse::<LongPollId>().is_err());
+
+        // Solo un nuovo tip o un aumento delle fee di almeno il 10% rendono obsoleto il template
+        assert!(!id.is_stale(&[7; 32], 1_099));
+        assert!(!id.is_stale(&[7; 32], 500));
+        assert!(id.is_stale(&[7; 32], 1_100));
+        assert!(id.is_stale(&[8; 32], 1_000));
+        let empty = LongPollId { tip: [7; 32], fees: 0 };
+        assert!(!empty.is_stale(&[7; 32], 0) && empty.is_stale(&[7; 32], 1));
+    }
+
     #[test]
     fn test_target_to_bits_conversion() {
         let bits = 0x1d00ffff;
@@ -0,0 +1,247 @@
+//! Hash rolling del UTXO set (MuHash)
+//!
+//! Ogni output non speso è mappato su un elemento del gruppo
+//! moltiplicativo modulo il primo `2^3072 - 1103717` (lo stesso di
+//! MuHash3072): l'insieme è il prodotto dei suoi elementi. Aggiungere o
+//! togliere un output costa una moltiplicazione, in qualunque ordine, per
+//! cui il hash segue il UTXO set block per block senza rileggerlo. Le
+//! rimozioni si accumulano in un denominatore separato e la divisione (un
+//! inverso modulare, costoso) avviene solo in [`MuHash::finalize`].
+//!
+//! Gli elementi sono espansi da SHA-256 in modalità contatore invece che
+//! con ChaCha20: il valore non è confrontabile con quello di Bitcoin Core,
+//! solo tra nodi Sedly.
+
+use sha2::{Digest, Sha256};
+
+/// Limb da 64 bit di un numero a 3072 bit
+const LIMBS: usize = 48;
+
+/// Bytes di un numero a 3072 bit
+const NUM_BYTES: usize = LIMBS * 8;
+
+/// Il modulo è `2^3072 - MODULUS_OFFSET`
+const MODULUS_OFFSET: u64 = 1_103_717;
+
+/// Intero modulo `2^3072 - MODULUS_OFFSET` (limb little-endian)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Num3072([u64; LIMBS]);
+
+impl Num3072 {
+    /// Uno
+    const ONE: Num3072 = {
+        let mut limbs = [0; LIMBS];
+        limbs[0] = 1;
+        Num3072(limbs)
+    };
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0u64; LIMBS];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        }
+        let mut num = Num3072(limbs);
+        num.reduce();
+        num
+    }
+
+    fn to_le_bytes(self) -> [u8; NUM_BYTES] {
+        let mut bytes = [0u8; NUM_BYTES];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.0) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Porta in `[0, p)` un valore in `[0, 2^3072)`
+    fn reduce(&mut self) {
+        let overflow = self.0[0] > u64::MAX - MODULUS_OFFSET && self.0[1..].iter().all(|limb| *limb == u64::MAX);
+        if overflow {
+            // x - p = x + MODULUS_OFFSET - 2^3072: il riporto finale è 2^3072
+            self.add_small(MODULUS_OFFSET as u128);
+        }
+    }
+
+    /// Somma `value` (minore di 2^64 * MODULUS_OFFSET), restituisce il riporto oltre 2^3072
+    fn add_small(&mut self, mut value: u128) -> u128 {
+        for limb in self.0.iter_mut() {
+            if value == 0 {
+                break;
+            }
+            let sum = *limb as u128 + value;
+            *limb = sum as u64;
+            value = sum >> 64;
+        }
+        value
+    }
+
+    /// Prodotto modulo p
+    fn mul(&self, other: &Num3072) -> Num3072 {
+        let mut wide = [0u64; 2 * LIMBS];
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, b) in other.0.iter().enumerate() {
+                let product = wide[i + j] as u128 + *a as u128 * *b as u128 + carry;
+                wide[i + j] = product as u64;
+                carry = product >> 64;
+            }
+            wide[i + LIMBS] = carry as u64;
+        }
+
+        // 2^3072 ≡ MODULUS_OFFSET: la metà alta rientra moltiplicata per l'offset
+        let mut result = Num3072([0; LIMBS]);
+        let mut carry = 0u128;
+        for i in 0..LIMBS {
+            let sum = wide[i] as u128 + wide[i + LIMBS] as u128 * MODULUS_OFFSET as u128 + carry;
+            result.0[i] = sum as u64;
+            carry = sum >> 64;
+        }
+        while carry > 0 {
+            carry = result.add_small(carry * MODULUS_OFFSET as u128);
+        }
+        result.reduce();
+        result
+    }
+
+    /// Inverso modulo p (`self^(p-2)`, finestre da 4 bit)
+    fn inverse(&self) -> Num3072 {
+        let mut powers = [Num3072::ONE; 16];
+        for i in 1..16 {
+            powers[i] = powers[i - 1].mul(self);
+        }
+        // p - 2: limb alti tutti a uno, il più basso 2^64 - MODULUS_OFFSET - 2
+        let mut exponent = [u64::MAX; LIMBS];
+        exponent[0] = u64::MAX - MODULUS_OFFSET - 1;
+
+        let mut result = Num3072::ONE;
+        for limb in exponent.iter().rev() {
+            for shift in (0..16).rev() {
+                for _ in 0..4 {
+                    result = result.mul(&result);
+                }
+                let window = (limb >> (shift * 4)) & 0xf;
+                if window != 0 {
+                    result = result.mul(&powers[window as usize]);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Elemento del gruppo associato a `data`
+fn element(data: &[u8]) -> Num3072 {
+    let seed = Sha256::digest(data);
+    let mut bytes = [0u8; NUM_BYTES];
+    for (counter, chunk) in bytes.chunks_exact_mut(32).enumerate() {
+        chunk.copy_from_slice(&Sha256::new().chain_update(seed).chain_update([counter as u8]).finalize());
+    }
+    Num3072::from_le_bytes(&bytes)
+}
+
+/// Hash di un multinsieme aggiornabile per inserimenti e rimozioni
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MuHash {
+    /// Prodotto degli elementi inseriti
+    numerator: Num3072,
+    /// Prodotto degli elementi rimossi
+    denominator: Num3072,
+}
+
+impl Default for MuHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for MuHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MuHash").finish_non_exhaustive()
+    }
+}
+
+impl MuHash {
+    /// Bytes di [`Self::to_bytes`]
+    pub const SERIALIZED_SIZE: usize = 2 * NUM_BYTES;
+
+    /// Hash dell'insieme vuoto
+    pub fn new() -> Self {
+        Self { numerator: Num3072::ONE, denominator: Num3072::ONE }
+    }
+
+    /// Aggiunge `data` all'insieme
+    pub fn insert(&mut self, data: &[u8]) {
+        self.numerator = self.numerator.mul(&element(data));
+    }
+
+    /// Toglie `data`, che deve essere stato aggiunto
+    pub fn remove(&mut self, data: &[u8]) {
+        self.denominator = self.denominator.mul(&element(data));
+    }
+
+    /// Hash a 32 byte dell'insieme: SHA-256 del quoziente in little-endian
+    pub fn finalize(&self) -> [u8; 32] {
+        let quotient = self.numerator.mul(&self.denominator.inverse());
+        Sha256::digest(quotient.to_le_bytes()).into()
+    }
+
+    /// Stato serializzato (numeratore e denominatore), per riprendere gli aggiornamenti
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SERIALIZED_SIZE);
+        bytes.extend_from_slice(&self.numerator.to_le_bytes());
+        bytes.extend_from_slice(&self.denominator.to_le_bytes());
+        bytes
+    }
+
+    /// Stato da [`Self::to_bytes`], None se la lunghezza non torna
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SERIALIZED_SIZE {
+            return None;
+        }
+        Some(Self {
+            numerator: Num3072::from_le_bytes(&bytes[..NUM_BYTES]),
+            denominator: Num3072::from_le_bytes(&bytes[NUM_BYTES..]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverse_and_reduction() {
+        let x = element(b"x");
+        assert_eq!(x.mul(&x.inverse()), Num3072::ONE);
+
+        // p - 1 ≡ -1: il quadrato è 1
+        let mut minus_one = Num3072([u64::MAX; LIMBS]);
+        minus_one.0[0] = u64::MAX - MODULUS_OFFSET;
+        assert_eq!(minus_one.mul(&minus_one), Num3072::ONE);
+        // p stesso si riduce a zero
+        let mut modulus = minus_one;
+        modulus.0[0] += 1;
+        modulus.reduce();
+        assert_eq!(modulus, Num3072([0; LIMBS]));
+    }
+
+    #[test]
+    fn test_muhash_set_semantics() {
+        let mut forward = MuHash::new();
+        for data in [&b"a"[..], b"b", b"c"] {
+            forward.insert(data);
+        }
+        // L'ordine non conta, una rimozione annulla l'inserimento
+        let mut shuffled = MuHash::new();
+        for data in [&b"c"[..], b"d", b"a", b"b"] {
+            shuffled.insert(data);
+        }
+        shuffled.remove(b"d");
+        assert_eq!(shuffled.finalize(), forward.finalize());
+        assert_ne!(forward.finalize(), MuHash::new().finalize());
+
+        let restored = MuHash::from_bytes(&shuffled.to_bytes()).unwrap();
+        assert_eq!(restored, shuffled);
+        assert!(MuHash::from_bytes(&[0; 10]).is_none());
+    }
+}
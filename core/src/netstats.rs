@@ -0,0 +1,295 @@
+//! Statistiche di traffico per peer
+//!
+//! Il livello di rete registra byte e messaggi scambiati, latenza dei ping
+//! e altezza di sync di ogni peer; i totali includono anche i peer già
+//! disconnessi. Come la mempool, la tabella è condivisa con il server RPC
+//! (`getpeerinfo`, `getnettotals`) dietro un `Arc<Mutex<_>>`.
+//!
+//! Dopo l'handshake la tabella conserva anche versione del protocollo e
+//! servizi annunciati da ogni peer, usati per scegliere a chi chiedere
+//! block (IBD) o filtri.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::BitOr;
+
+/// Servizi annunciati da un nodo nell'handshake
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ServiceFlags(u64);
+
+impl ServiceFlags {
+    /// Nessun servizio
+    pub const NONE: Self = Self(0);
+    /// Serve l'intera chain (nodo non pruned)
+    pub const NETWORK: Self = Self(1 << 0);
+    /// Supporta i compact block
+    pub const COMPACT_BLOCKS: Self = Self(1 << 1);
+    /// Serve i filtri compatti dei block
+    pub const COMPACT_FILTERS: Self = Self(1 << 2);
+    /// Nodo pruned: serve solo i block recenti
+    pub const NETWORK_LIMITED: Self = Self(1 << 3);
+
+    /// Nomi dei servizi, nell'ordine dei bit
+    const NAMES: [(&'static str, Self); 4] = [
+        ("NETWORK", Self::NETWORK),
+        ("COMPACT_BLOCKS", Self::COMPACT_BLOCKS),
+        ("COMPACT_FILTERS", Self::COMPACT_FILTERS),
+        ("NETWORK_LIMITED", Self::NETWORK_LIMITED),
+    ];
+
+    /// Servizi dai bit annunciati (i bit sconosciuti sono conservati)
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Bit dei servizi
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Se tutti i servizi di `other` sono annunciati
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Nomi dei servizi noti annunciati
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES.iter().filter(|(_, flag)| self.contains(*flag)).map(|(name, _)| *name).collect()
+    }
+}
+
+impl BitOr for ServiceFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for ServiceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.names();
+        if names.is_empty() {
+            f.write_str("NONE")
+        } else {
+            f.write_str(&names.join(","))
+        }
+    }
+}
+
+/// Traffico e stato di un peer connesso
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerStats {
+    /// Indirizzo del peer
+    pub addr: String,
+    /// Connessione aperta dal peer
+    pub inbound: bool,
+    /// Istante di connessione (secondi UNIX)
+    pub connected_at: u64,
+    /// Ultimo messaggio inviato (secondi UNIX, 0 se nessuno)
+    pub last_send: u64,
+    /// Ultimo messaggio ricevuto (secondi UNIX, 0 se nessuno)
+    pub last_recv: u64,
+    /// Byte inviati, header inclusi
+    pub bytes_sent: u64,
+    /// Byte ricevuti, header inclusi
+    pub bytes_recv: u64,
+    /// Messaggi inviati per comando
+    pub messages_sent: BTreeMap<String, u64>,
+    /// Messaggi ricevuti per comando
+    pub messages_recv: BTreeMap<String, u64>,
+    /// Latenza dell'ultimo ping in microsecondi
+    pub ping_micros: Option<u64>,
+    /// Latenza minima osservata in microsecondi
+    pub min_ping_micros: Option<u64>,
+    /// Altezza dell'ultimo block ricevuto dal peer
+    pub sync_height: Option<u64>,
+    /// Versione del protocollo concordata (None prima dell'handshake)
+    pub version: Option<u32>,
+    /// Servizi annunciati dal peer
+    pub services: ServiceFlags,
+    /// User agent annunciato dal peer
+    pub user_agent: String,
+    /// Altezza del peer al momento dell'handshake
+    pub start_height: u64,
+}
+
+/// Totali di traffico del nodo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetTotals {
+    /// Byte inviati da tutti i peer
+    pub bytes_sent: u64,
+    /// Byte ricevuti da tutti i peer
+    pub bytes_recv: u64,
+    /// Messaggi inviati
+    pub messages_sent: u64,
+    /// Messaggi ricevuti
+    pub messages_recv: u64,
+}
+
+/// Statistiche dei peer connessi e totali del nodo
+#[derive(Debug, Clone, Default)]
+pub struct NetStats {
+    /// Statistiche per indirizzo
+    peers: HashMap<String, PeerStats>,
+    /// Totali dall'avvio del nodo
+    totals: NetTotals,
+}
+
+impl NetStats {
+    /// Crea una tabella vuota
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra una nuova connessione al tempo `now`
+    pub fn connect(&mut self, peer: &str, inbound: bool, now: u64) {
+        self.peers.insert(peer.to_string(), PeerStats {
+            addr: peer.to_string(),
+            inbound,
+            connected_at: now,
+            ..PeerStats::default()
+        });
+    }
+
+    /// Rimuove un peer; il suo traffico resta nei totali
+    pub fn disconnect(&mut self, peer: &str) -> Option<PeerStats> {
+        self.peers.remove(peer)
+    }
+
+    /// Registra un messaggio di `bytes` byte inviato al peer
+    pub fn record_sent(&mut self, peer: &str, command: &str, bytes: u64, now: u64) {
+        self.totals.bytes_sent += bytes;
+        self.totals.messages_sent += 1;
+        if let Some(stats) = self.peers.get_mut(peer) {
+            stats.bytes_sent += bytes;
+            *stats.messages_sent.entry(command.to_string()).or_insert(0) += 1;
+            stats.last_send = now;
+        }
+    }
+
+    /// Registra un messaggio di `bytes` byte ricevuto dal peer
+    pub fn record_received(&mut self, peer: &str, command: &str, bytes: u64, now: u64) {
+        self.totals.bytes_recv += bytes;
+        self.totals.messages_recv += 1;
+        if let Some(stats) = self.peers.get_mut(peer) {
+            stats.bytes_recv += bytes;
+            *stats.messages_recv.entry(command.to_string()).or_insert(0) += 1;
+            stats.last_recv = now;
+        }
+    }
+
+    /// Registra la latenza di una risposta a un ping
+    pub fn record_ping(&mut self, peer: &str, micros: u64) {
+        if let Some(stats) = self.peers.get_mut(peer) {
+            stats.ping_micros = Some(micros);
+            stats.min_ping_micros = Some(stats.min_ping_micros.map_or(micros, |min| min.min(micros)));
+        }
+    }
+
+    /// Aggiorna l'altezza di sync del peer; non scende mai
+    pub fn record_height(&mut self, peer: &str, height: u64) {
+        if let Some(stats) = self.peers.get_mut(peer) {
+            stats.sync_height = Some(stats.sync_height.map_or(height, |current| current.max(height)));
+        }
+    }
+
+    /// Registra l'esito dell'handshake: versione concordata e dati annunciati dal peer
+    pub fn record_version(&mut self, peer: &str, version: u32, services: ServiceFlags, user_agent: &str, height: u64) {
+        if let Some(stats) = self.peers.get_mut(peer) {
+            stats.version = Some(version);
+            stats.services = services;
+            stats.user_agent = user_agent.to_string();
+            stats.start_height = height;
+        }
+    }
+
+    /// Peer che hanno completato l'handshake annunciando tutti i servizi
+    /// `required`, dal più avanti nella chain (a parità, dal più veloce)
+    pub fn peers_with_services(&self, required: ServiceFlags) -> Vec<&PeerStats> {
+        let mut peers: Vec<_> = self.peers
+            .values()
+            .filter(|peer| peer.version.is_some() && peer.services.contains(required))
+            .collect();
+        peers.sort_by_key(|peer| {
+            let height = peer.sync_height.unwrap_or(0).max(peer.start_height);
+            (std::cmp::Reverse(height), peer.min_ping_micros.unwrap_or(u64::MAX), peer.addr.clone())
+        });
+        peers
+    }
+
+    /// Statistiche di un peer connesso
+    pub fn peer(&self, peer: &str) -> Option<&PeerStats> {
+        self.peers.get(peer)
+    }
+
+    /// Peer connessi in ordine di indirizzo
+    pub fn peers(&self) -> Vec<&PeerStats> {
+        let mut peers: Vec<_> = self.peers.values().collect();
+        peers.sort_by(|a, b| a.addr.cmp(&b.addr));
+        peers
+    }
+
+    /// Totali dall'avvio del nodo
+    pub fn totals(&self) -> NetTotals {
+        self.totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_accounting() {
+        let mut stats = NetStats::new();
+        stats.connect("10.0.0.1:9333", false, 1_000);
+        stats.record_sent("10.0.0.1:9333", "ping", 32, 1_001);
+        stats.record_received("10.0.0.1:9333", "pong", 32, 1_002);
+        stats.record_received("10.0.0.1:9333", "block", 1_024, 1_003);
+        stats.record_ping("10.0.0.1:9333", 800);
+        stats.record_ping("10.0.0.1:9333", 500);
+        stats.record_ping("10.0.0.1:9333", 900);
+        stats.record_height("10.0.0.1:9333", 10);
+        stats.record_height("10.0.0.1:9333", 7);
+
+        let peer = stats.peer("10.0.0.1:9333").unwrap();
+        assert_eq!((peer.bytes_sent, peer.bytes_recv), (32, 1_056));
+        assert_eq!(peer.messages_recv.get("block"), Some(&1));
+        assert_eq!((peer.last_send, peer.last_recv), (1_001, 1_003));
+        assert_eq!((peer.ping_micros, peer.min_ping_micros), (Some(900), Some(500)));
+        assert_eq!(peer.sync_height, Some(10));
+
+        // I totali sopravvivono alla disconnessione
+        stats.disconnect("10.0.0.1:9333");
+        assert!(stats.peers().is_empty());
+        assert_eq!(stats.totals(), NetTotals { bytes_sent: 32, bytes_recv: 1_056, messages_sent: 1, messages_recv: 2 });
+    }
+
+    #[test]
+    fn test_peers_with_services() {
+        let full = ServiceFlags::NETWORK | ServiceFlags::COMPACT_FILTERS;
+        assert_eq!(full.to_string(), "NETWORK,COMPACT_FILTERS");
+        assert_eq!(ServiceFlags::from_bits(1 << 40).to_string(), "NONE");
+
+        let mut stats = NetStats::new();
+        for peer in ["a", "b", "c", "d"] {
+            stats.connect(peer, false, 0);
+        }
+        stats.record_version("a", 2, ServiceFlags::NETWORK, "/sedly:0.1.0/", 100);
+        stats.record_version("b", 2, full, "/sedly:0.1.0/", 90);
+        stats.record_height("b", 120);
+        stats.record_version("c", 2, ServiceFlags::NETWORK_LIMITED, "/sedly:0.1.0/", 150);
+        // "d" non ha completato l'handshake
+
+        fn addrs(peers: Vec<&PeerStats>) -> Vec<&str> {
+            peers.into_iter().map(|peer| peer.addr.as_str()).collect()
+        }
+        assert_eq!(addrs(stats.peers_with_services(ServiceFlags::NETWORK)), vec!["b", "a"]);
+        assert_eq!(addrs(stats.peers_with_services(ServiceFlags::COMPACT_FILTERS)), vec!["b"]);
+        assert_eq!(addrs(stats.peers_with_services(ServiceFlags::NONE)), vec!["c", "b", "a"]);
+        assert_eq!(stats.peer("c").unwrap().services.names(), vec!["NETWORK_LIMITED"]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Nucleo del nodo condiviso tra le modalità di consenso
+//!
+//! Validazione, storage, indice degli header e mempool non dipendono da chi
+//! decide l'ordine dei block. [`NodeCore`] li tiene in un solo stato, usato
+//! sia dall'applicazione ABCI (i block li decide Tendermint) sia dal nodo
+//! standalone (li decide la proof of work): gli adapter aggiungono solo le
+//! regole del proprio consenso. [`NodeMode`] sceglie l'adapter all'avvio.
+//!
+//! [`CrashFlush`] salva mempool e database se un thread va in panic, così
+//! il riavvio dopo un crash non perde le transazioni in attesa.
+
+use crate::headers::{HeaderCache, HeaderCacheError};
+use crate::mempool::{Mempool, MempoolError};
+use crate::pipeline::BlockPipeline;
+use crate::storage::{BlockchainDB, StorageError};
+use crate::validation::BlockValidator;
+use crate::{Block, ChainParams, Transaction};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, TryLockError};
+
+/// Modalità di consenso del nodo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeMode {
+    /// Applicazione ABCI: Tendermint ordina e finalizza i block
+    #[default]
+    Tendermint,
+    /// Solo proof of work: il nodo mina e segue la chain con più lavoro
+    Standalone,
+}
+
+impl NodeMode {
+    /// Nome della modalità (come accettato da `FromStr`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeMode::Tendermint => "tendermint",
+            NodeMode::Standalone => "standalone",
+        }
+    }
+}
+
+impl std::str::FromStr for NodeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tendermint" | "abci" => Ok(NodeMode::Tendermint),
+            "standalone" | "pow" => Ok(NodeMode::Standalone),
+            other => Err(format!("Unknown node mode: {}", other)),
+        }
+    }
+}
+
+/// Stato del nodo comune a tutte le modalità di consenso
+///
+/// Pipeline e indice degli header sono dietro un `Mutex` perché l'adapter
+/// ABCI li usa da `&self`; chi ha accesso esclusivo (il nodo standalone)
+/// passa da `pipeline_mut` e `headers_mut` senza lock.
+pub struct NodeCore {
+    db: Arc<BlockchainDB>,
+    validator: BlockValidator,
+    pipeline: Mutex<BlockPipeline>,
+    headers: Mutex<HeaderCache>,
+    mempool: Arc<Mutex<Mempool>>,
+    mempool_path: PathBuf,
+}
+
+impl NodeCore {
+    /// Nucleo su un database già inizializzato con il genesis
+    ///
+    /// La mempool salvata in `mempool_path` all'ultimo spegnimento viene
+    /// ricaricata e rivalidata con `validator`; se il file non è leggibile
+    /// il nodo parte con la mempool vuota.
+    pub fn open(db: Arc<BlockchainDB>, validator: BlockValidator, mempool_path: PathBuf) -> Result<Self, StorageError> {
+        let headers = HeaderCache::load(&db)?;
+        log::info!("Loaded {} block headers into the header cache", headers.len());
+
+        let mempool = match Mempool::load(&mempool_path, &validator, &db) {
+            Ok((mempool, stats)) => {
+                log::info!(
+                    "Loaded {} mempool transactions ({} no longer valid, {} expired)",
+                    stats.loaded, stats.failed, stats.expired
+                );
+                mempool
+            }
+            Err(e) => {
+                log::warn!("Failed to load mempool from {}: {}", mempool_path.display(), e);
+                Mempool::new()
+            }
+        };
+
+        Ok(Self {
+            db,
+            pipeline: Mutex::new(BlockPipeline::new(validator.clone())),
+            validator,
+            headers: Mutex::new(headers),
+            mempool: Arc::new(Mutex::new(mempool)),
+            mempool_path,
+        })
+    }
+
+    /// Database della chain
+    pub fn db(&self) -> &Arc<BlockchainDB> {
+        &self.db
+    }
+
+    /// Validatore di block e transazioni
+    pub fn validator(&self) -> &BlockValidator {
+        &self.validator
+    }
+
+    /// Parametri di consenso della rete
+    pub fn params(&self) -> &ChainParams {
+        self.validator.params()
+    }
+
+    /// Pipeline di validazione e connessione dei block
+    pub fn pipeline(&self) -> &Mutex<BlockPipeline> {
+        &self.pipeline
+    }
+
+    /// Pipeline con accesso esclusivo, senza lock
+    pub fn pipeline_mut(&mut self) -> &mut BlockPipeline {
+        self.pipeline.get_mut().unwrap()
+    }
+
+    /// Indice degli header
+    pub fn headers(&self) -> &Mutex<HeaderCache> {
+        &self.headers
+    }
+
+    /// Indice degli header con accesso esclusivo, senza lock
+    pub fn headers_mut(&mut self) -> &mut HeaderCache {
+        self.headers.get_mut().unwrap()
+    }
+
+    /// Mempool del nodo
+    pub fn mempool(&self) -> &Arc<Mutex<Mempool>> {
+        &self.mempool
+    }
+
+    /// File in cui la mempool viene salvata allo spegnimento
+    pub fn mempool_path(&self) -> &Path {
+        &self.mempool_path
+    }
+
+    /// Attiva il livello su disco della mempool, fino a `max_size` bytes nel database del nodo (0 lo disattiva)
+    pub fn set_mempool_spillover(&self, max_size: usize) -> Result<(), MempoolError> {
+        self.mempool.lock().unwrap().set_spillover(self.db.clone(), max_size)
+    }
+
+    /// Valida e aggiunge alla mempool una transazione per il block sopra `tip_height`, ritornando la fee
+    pub fn accept_transaction(&self, tx: Transaction, tip_height: u64) -> Result<u64, MempoolError> {
+        self.mempool.lock().unwrap().add(tx, tip_height, &self.validator, &self.db)
+    }
+
+    /// Aggiorna indice degli header e mempool dopo la connessione di `block` sopra il tip
+    ///
+    /// Ritorna il numero di transazioni tolte dalla mempool perché
+    /// confermate o in conflitto con il block. Le transazioni nel livello su
+    /// disco tornano in memoria nello spazio liberato.
+    pub fn block_connected(&self, block: &Block) -> Result<usize, HeaderCacheError> {
+        let mut mempool = self.mempool.lock().unwrap();
+        let evicted = mempool.remove_for_block(block);
+        if let Err(e) = mempool.promote_spilled(block.header.height, &self.validator, &self.db) {
+            log::warn!("Failed to promote spilled mempool transactions: {}", e);
+        }
+        drop(mempool);
+        self.headers.lock().unwrap().connect(&block.header)?;
+        Ok(evicted)
+    }
+
+    /// Ricostruisce l'indice degli header dal database (es. dopo un reorg)
+    pub fn reload_headers(&mut self) -> Result<(), StorageError> {
+        *self.headers_mut() = HeaderCache::load(&self.db)?;
+        Ok(())
+    }
+
+    /// Salva la mempool in `mempool_path`, ritornando le transazioni salvate
+    pub fn save_mempool(&self) -> Result<usize, MempoolError> {
+        let saved = self.mempool.lock().unwrap().save(&self.mempool_path)?;
+        log::info!("Saved {} mempool transactions to {}", saved, self.mempool_path.display());
+        Ok(saved)
+    }
+
+    /// Flush d'emergenza di database e mempool di questo nucleo
+    pub fn crash_flush(&self) -> CrashFlush {
+        CrashFlush {
+            db: self.db.clone(),
+            mempool: self.mempool.clone(),
+            mempool_path: self.mempool_path.clone(),
+            flushed: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Salvataggio di database e mempool dopo un panic
+///
+/// Il panic hook gira nel thread che va in panic prima dell'unwinding, con
+/// i lock di quel thread ancora presi: la mempool si prende con `try_lock`
+/// e se è occupata, o avvelenata da un panic precedente, resta su disco il
+/// file dell'ultimo salvataggio invece di una pool forse a metà di un
+/// aggiornamento. Il database non ha scritture a metà da completare
+/// (`BlockchainDB::flush`).
+pub struct CrashFlush {
+    db: Arc<BlockchainDB>,
+    mempool: Arc<Mutex<Mempool>>,
+    mempool_path: PathBuf,
+    flushed: AtomicBool,
+}
+
+/// Esito di un flush d'emergenza
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrashFlushReport {
+    /// Transazioni salvate (None se la mempool era occupata o il salvataggio è fallito)
+    pub mempool_saved: Option<usize>,
+    /// Database portato su disco
+    pub database_flushed: bool,
+}
+
+impl CrashFlush {
+    /// Scrive mempool e database su disco; `None` se un flush è già stato fatto
+    pub fn flush(&self) -> Option<CrashFlushReport> {
+        if self.flushed.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        let mempool_saved = match self.mempool.try_lock() {
+            Ok(mempool) => match mempool.save(&self.mempool_path) {
+                Ok(saved) => Some(saved),
+                Err(e) => {
+                    log::error!("Failed to save the mempool to {}: {}", self.mempool_path.display(), e);
+                    None
+                }
+            },
+            Err(TryLockError::WouldBlock) => {
+                log::error!("Mempool in use by the failing thread, keeping the last saved file");
+                None
+            }
+            Err(TryLockError::Poisoned(_)) => {
+                log::error!("Mempool poisoned by an earlier panic, keeping the last saved file");
+                None
+            }
+        };
+        let database_flushed = match self.db.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Failed to flush the database: {}", e);
+                false
+            }
+        };
+        Some(CrashFlushReport { mempool_saved, database_flushed })
+    }
+
+    /// Installa un panic hook che, dopo l'hook precedente (il messaggio del
+    /// panic), esegue il flush
+    ///
+    /// Basta il primo panic: il flush non si ripete sugli altri thread.
+    pub fn install_panic_hook(self: Arc<Self>) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if let Some(report) = self.flush() {
+                log::error!(
+                    "Panic: flushed database ({}) and saved {} mempool transactions",
+                    if report.database_flushed { "ok" } else { "failed" },
+                    report.mempool_saved.map_or("no".to_string(), |saved| saved.to_string())
+                );
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Network;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_node_mode() {
+        assert_eq!("standalone".parse::<NodeMode>().unwrap(), NodeMode::Standalone);
+        assert_eq!("ABCI".parse::<NodeMode>().unwrap(), NodeMode::Tendermint);
+        assert_eq!(NodeMode::default().name().parse::<NodeMode>().unwrap(), NodeMode::Tendermint);
+        assert!("raft".parse::<NodeMode>().is_err());
+    }
+
+    #[test]
+    fn test_core_connects_and_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path().join("db")).unwrap());
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"genesis", 0, 50)], 0x207fffff, 0);
+        db.initialize_with_genesis(&genesis).unwrap();
+        let validator = BlockValidator::new(ChainParams::for_network(Network::Regtest));
+        let mempool_path = temp_dir.path().join("mempool.dat");
+
+        // Un file di mempool corrotto non impedisce l'avvio
+        std::fs::write(&mempool_path, b"garbage").unwrap();
+        let core = NodeCore::open(db.clone(), validator.clone(), mempool_path.clone()).unwrap();
+        assert!(core.mempool().lock().unwrap().is_empty());
+        assert_eq!(core.headers().lock().unwrap().len(), 1);
+
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 50)], 0x207fffff, 1);
+        db.store_block(&block).unwrap();
+        assert_eq!(core.block_connected(&block).unwrap(), 0);
+        assert_eq!(core.headers().lock().unwrap().tip().unwrap().0, block.hash());
+
+        assert_eq!(core.save_mempool().unwrap(), 0);
+        let core = NodeCore::open(db, validator, mempool_path).unwrap();
+        assert_eq!(core.headers().lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_crash_flush() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path().join("db")).unwrap());
+        let validator = BlockValidator::new(ChainParams::for_network(Network::Regtest));
+        let mempool_path = temp_dir.path().join("mempool.dat");
+        let core = NodeCore::open(db, validator, mempool_path.clone()).unwrap();
+
+        // Mempool presa dal thread in panic: resta il file precedente
+        let flush = core.crash_flush();
+        let guard = core.mempool().lock().unwrap();
+        let report = flush.flush().unwrap();
+        drop(guard);
+        assert_eq!(report, CrashFlushReport { mempool_saved: None, database_flushed: true });
+        assert!(!mempool_path.exists());
+        assert!(flush.flush().is_none());
+
+        let report = core.crash_flush().flush().unwrap();
+        assert_eq!(report.mempool_saved, Some(0));
+        assert!(mempool_path.exists());
+    }
+}
@@ -0,0 +1,354 @@
+//! Pool dei block orfani
+//!
+//! Un block il cui parent non è ancora noto viene trattenuto nel pool invece
+//! di essere perso. Il chiamante richiede ai peer l'antenato mancante
+//! indicato da `BlockOutcome::Orphaned` e, quando il parent viene connesso,
+//! i figli in attesa vengono connessi a cascata con la `BlockPipeline`.
+//!
+//! Il pool è limitato nel numero di block e nel tempo di permanenza: oltre il
+//! limite viene scartato l'orfano ricevuto per primo.
+
+use crate::pipeline::{check_known_invalid, BlockPipeline, PipelineError, Stage};
+use crate::staging::ConnectReport;
+use crate::storage::BlockchainDB;
+use crate::Block;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Numero massimo di default di block orfani
+pub const DEFAULT_MAX_ORPHAN_BLOCKS: usize = 100;
+
+/// Tempo massimo di permanenza di un orfano nel pool (20 minuti)
+pub const ORPHAN_BLOCK_EXPIRY: u64 = 20 * 60;
+
+/// Block orfano in attesa del parent
+#[derive(Debug, Clone)]
+struct OrphanBlock {
+    /// Block ricevuto
+    block: Block,
+    /// Timestamp UNIX di ricezione
+    received: u64,
+}
+
+/// Esito della ricezione di un block
+#[derive(Debug)]
+pub enum BlockOutcome {
+    /// Il block (ed eventuali orfani figli) è stato connesso o scartato
+    Processed(ConnectReport),
+    /// Parent sconosciuto: il block resta nel pool in attesa di `request`
+    Orphaned {
+        /// Antenato mancante da richiedere ai peer
+        request: [u8; 32],
+    },
+    /// Block già nel pool degli orfani
+    AlreadyOrphaned,
+}
+
+/// Pool dei block orfani
+#[derive(Debug, Clone)]
+pub struct OrphanPool {
+    /// Numero massimo di orfani
+    max_orphans: usize,
+    /// Orfani per hash
+    orphans: HashMap<[u8; 32], OrphanBlock>,
+    /// Hash degli orfani per hash del parent
+    by_parent: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+impl OrphanPool {
+    /// Crea un pool vuoto con al più `max_orphans` block
+    pub fn new(max_orphans: usize) -> Self {
+        Self {
+            max_orphans,
+            orphans: HashMap::new(),
+            by_parent: HashMap::new(),
+        }
+    }
+
+    /// Numero di orfani
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Se il pool è vuoto
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    /// Se un block è nel pool
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    /// Riceve un block: lo connette se estende il tip, altrimenti lo
+    /// trattiene se il parent è sconosciuto
+    ///
+    /// Un block il cui parent è noto ma non è il tip viene passato comunque
+    /// alla pipeline, che lo rifiuta.
+    pub fn process_block(
+        &mut self,
+        block: Block,
+        pipeline: &mut BlockPipeline,
+        db: &BlockchainDB,
+    ) -> Result<BlockOutcome, PipelineError> {
+        let hash = block.hash();
+        if self.contains(&hash) {
+            return Ok(BlockOutcome::AlreadyOrphaned);
+        }
+
+        check_known_invalid(&block, db)?;
+
+        let parent = block.header.previous_hash;
+        let parent_known = db.get_block(&parent)
+            .map_err(|error| PipelineError::Storage { stage: Stage::Header, error })?
+            .is_some();
+        if !parent_known && block.header.height > 0 {
+            // Un orfano senza lavoro occuperebbe il pool gratis
+            pipeline.validator()
+                .check_orphan_work(&block)
+                .map_err(|error| PipelineError::Invalid { stage: Stage::Header, error })?;
+            self.add(block, unix_now());
+            return Ok(BlockOutcome::Orphaned { request: self.missing_ancestor(parent) });
+        }
+
+        let processed = pipeline.process(&block, db)?;
+        let mut report = self.connect_children(hash, pipeline, db);
+        report.connected.insert(0, processed);
+        Ok(BlockOutcome::Processed(report))
+    }
+
+    /// Connette a cascata gli orfani che discendono da `parent`, appena connesso
+    ///
+    /// Un orfano rifiutato dalla pipeline viene scartato con i suoi discendenti.
+    pub fn connect_children(&mut self, parent: [u8; 32], pipeline: &mut BlockPipeline, db: &BlockchainDB) -> ConnectReport {
+        let mut report = ConnectReport::default();
+        let mut queue = VecDeque::from([parent]);
+
+        while let Some(parent) = queue.pop_front() {
+            for hash in self.by_parent.remove(&parent).unwrap_or_default() {
+                let Some(orphan) = self.orphans.remove(&hash) else {
+                    continue;
+                };
+                match pipeline.process(&orphan.block, db) {
+                    Ok(processed) => {
+                        report.connected.push(processed);
+                        queue.push_back(hash);
+                    }
+                    Err(error) => {
+                        self.remove_descendants(hash);
+                        report.rejected.push((hash, error));
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Toglie dal pool gli orfani figli di `parent`, arrivato su un ramo laterale
+    ///
+    /// `connect_children` li connetterebbe sopra il tip; chi li riceve li
+    /// processa invece come block del ramo del parent.
+    pub fn take_children(&mut self, parent: &[u8; 32]) -> Vec<Block> {
+        self.by_parent
+            .remove(parent)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|hash| self.orphans.remove(hash))
+            .map(|orphan| orphan.block)
+            .collect()
+    }
+
+    /// Hash dei parent mancanti da richiedere ai peer (uno per catena di orfani)
+    pub fn missing_parents(&self) -> Vec<[u8; 32]> {
+        let mut missing: Vec<[u8; 32]> = self.by_parent
+            .keys()
+            .filter(|parent| !self.orphans.contains_key(*parent))
+            .copied()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    /// Scarta gli orfani ricevuti più di `ORPHAN_BLOCK_EXPIRY` secondi prima di `now`
+    pub fn expire(&mut self, now: u64) -> usize {
+        let expired: Vec<[u8; 32]> = self.orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_sub(orphan.received) > ORPHAN_BLOCK_EXPIRY)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired.len()
+    }
+
+    /// Aggiunge un orfano, scartando il più vecchio se il pool è pieno
+    fn add(&mut self, block: Block, received: u64) {
+        if self.max_orphans == 0 {
+            return;
+        }
+        while self.orphans.len() >= self.max_orphans {
+            let oldest = self.orphans
+                .iter()
+                .min_by_key(|(hash, orphan)| (orphan.received, **hash))
+                .map(|(hash, _)| *hash);
+            match oldest {
+                Some(hash) => self.remove(&hash),
+                None => break,
+            }
+        }
+
+        let hash = block.hash();
+        self.by_parent.entry(block.header.previous_hash).or_default().push(hash);
+        self.orphans.insert(hash, OrphanBlock { block, received });
+    }
+
+    /// Primo antenato non presente nel pool risalendo da `parent`
+    fn missing_ancestor(&self, mut parent: [u8; 32]) -> [u8; 32] {
+        while let Some(orphan) = self.orphans.get(&parent) {
+            parent = orphan.block.header.previous_hash;
+        }
+        parent
+    }
+
+    /// Rimuove un orfano
+    fn remove(&mut self, hash: &[u8; 32]) {
+        if let Some(orphan) = self.orphans.remove(hash) {
+            let parent = orphan.block.header.previous_hash;
+            if let Some(children) = self.by_parent.get_mut(&parent) {
+                children.retain(|child| child != hash);
+                if children.is_empty() {
+                    self.by_parent.remove(&parent);
+                }
+            }
+        }
+    }
+
+    /// Rimuove tutti i discendenti di un block
+    fn remove_descendants(&mut self, hash: [u8; 32]) {
+        let mut queue = VecDeque::from([hash]);
+        while let Some(parent) = queue.pop_front() {
+            for child in self.by_parent.remove(&parent).unwrap_or_default() {
+                self.orphans.remove(&child);
+                queue.push_back(child);
+            }
+        }
+    }
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ORPHAN_BLOCKS)
+    }
+}
+
+/// Timestamp UNIX corrente in secondi
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::{block_subsidy, ValidationError};
+    use crate::{BlockValidator, ChainParams, Transaction};
+    use tempfile::TempDir;
+
+    fn build_chain(parent: &Block, count: u64) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..count {
+            let previous = blocks.last().unwrap_or(parent);
+            let height = previous.header.height + 1;
+            let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+            blocks.push(Block::new(previous.hash(), vec![coinbase], parent.header.bits, height));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_orphans_connect_when_parent_arrives() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+        let mut pool = OrphanPool::default();
+        let blocks = build_chain(&genesis, 3);
+
+        // 3 e 2 arrivano prima di 1: si richiede sempre il block 1
+        match pool.process_block(blocks[2].clone(), &mut pipeline, &db).unwrap() {
+            BlockOutcome::Orphaned { request } => assert_eq!(request, blocks[1].hash()),
+            other => panic!("expected orphan, got {:?}", other),
+        }
+        match pool.process_block(blocks[1].clone(), &mut pipeline, &db).unwrap() {
+            BlockOutcome::Orphaned { request } => assert_eq!(request, blocks[0].hash()),
+            other => panic!("expected orphan, got {:?}", other),
+        }
+        assert!(matches!(
+            pool.process_block(blocks[1].clone(), &mut pipeline, &db).unwrap(),
+            BlockOutcome::AlreadyOrphaned
+        ));
+        assert_eq!(pool.missing_parents(), vec![blocks[0].hash()]);
+
+        match pool.process_block(blocks[0].clone(), &mut pipeline, &db).unwrap() {
+            BlockOutcome::Processed(report) => {
+                assert_eq!(report.connected.iter().map(|block| block.height).collect::<Vec<_>>(), vec![1, 2, 3]);
+            }
+            other => panic!("expected connection, got {:?}", other),
+        }
+        assert!(pool.is_empty());
+        assert_eq!(db.get_best_block_hash().unwrap(), blocks[2].hash());
+    }
+
+    #[test]
+    fn test_orphans_need_proof_of_work() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let validator = BlockValidator::new(ChainParams::regtest()).with_proof_of_work(true);
+        let mut pipeline = BlockPipeline::new(validator.clone());
+        let mut pool = OrphanPool::default();
+        let blocks = build_chain(&genesis, 2);
+
+        // Senza lavoro l'orfano è scartato prima di entrare nel pool
+        assert!(matches!(
+            pool.process_block(blocks[1].clone(), &mut pipeline, &db),
+            Err(PipelineError::Invalid { error: ValidationError::InsufficientWork, .. })
+        ));
+        assert!(pool.is_empty());
+
+        let coinbase = Transaction::coinbase(b"miner", 2, block_subsidy(2));
+        let mut mined = Block::new(blocks[0].hash(), vec![coinbase], 0x207fffff, 2);
+        while validator.check_orphan_work(&mined).is_err() {
+            mined.header.nonce += 1;
+        }
+        assert!(matches!(
+            pool.process_block(mined.clone(), &mut pipeline, &db).unwrap(),
+            BlockOutcome::Orphaned { .. }
+        ));
+        assert!(pool.contains(&mined.hash()));
+    }
+
+    #[test]
+    fn test_orphan_pool_limits() {
+        let genesis = Block::genesis();
+        let blocks = build_chain(&genesis, 4);
+
+        let mut pool = OrphanPool::new(2);
+        pool.add(blocks[1].clone(), 100);
+        pool.add(blocks[2].clone(), 200);
+        pool.add(blocks[3].clone(), 300);
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&blocks[1].hash()));
+
+        assert_eq!(pool.expire(200 + ORPHAN_BLOCK_EXPIRY + 1), 1);
+        assert!(pool.contains(&blocks[3].hash()));
+        assert_eq!(pool.missing_parents(), vec![blocks[2].hash()]);
+    }
+}
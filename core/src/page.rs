@@ -0,0 +1,19 @@
+//! Pagina dei metodi di elenco
+//!
+//! I metodi RPC di elenco e gli endpoint dell'explorer restituiscono la
+//! stessa pagina: il client passa `next_cursor` come `cursor` per ottenere
+//! la successiva.
+
+use serde::{Deserialize, Serialize};
+
+/// Pagina di un elenco
+///
+/// `next_cursor` è None sull'ultima pagina. I cursori sono stringhe opache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Page<T> {
+    /// Elementi della pagina
+    pub items: Vec<T>,
+    /// Cursore della pagina successiva, se c'è
+    pub next_cursor: Option<String>,
+}
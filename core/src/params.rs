@@ -0,0 +1,546 @@
+//! Parametri di consenso per rete (mainnet, testnet, regtest)
+
+use crate::pow::{PowKind, PowRule};
+use crate::script::{ScriptError, ScriptTemplate};
+use crate::{Transaction, TxOutput};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Basis point in un'unità (100%)
+pub const BPS: u64 = 10_000;
+
+/// Rete Sedly a cui appartengono i parametri
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Network {
+    /// Rete principale
+    Mainnet,
+    /// Rete di test pubblica
+    Testnet,
+    /// Rete locale per regression test
+    Regtest,
+}
+
+impl std::str::FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" | "main" => Ok(Network::Mainnet),
+            "testnet" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            other => Err(format!("Unknown network: {}", other)),
+        }
+    }
+}
+
+impl Network {
+    /// Nome della rete (come accettato da `FromStr`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /// Magic bytes della rete: prefisso dei messaggi P2P e marker del database
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => [0x5e, 0xd1, 0xc0, 0xd1],
+            Network::Testnet => [0x5e, 0xd1, 0x7e, 0x57],
+            Network::Regtest => [0x5e, 0xd1, 0xfe, 0xed],
+        }
+    }
+
+    /// Porta P2P di default
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Network::Mainnet => 9333,
+            Network::Testnet => 19333,
+            Network::Regtest => 19444,
+        }
+    }
+
+    /// Rete con i magic bytes dati
+    pub fn from_magic(magic: [u8; 4]) -> Option<Self> {
+        [Network::Mainnet, Network::Testnet, Network::Regtest]
+            .into_iter()
+            .find(|network| network.magic() == magic)
+    }
+}
+
+/// Finestra di block usata dal retarget della difficulty
+///
+/// Bitcoin misura `interval - 1` intervalli ma li confronta con `interval`
+/// intervalli attesi: le varianti corrette eliminano questo off-by-one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetargetWindow {
+    /// Comportamento Bitcoin: `interval` block, `interval - 1` intervalli misurati
+    /// contro `interval` attesi (block time medio più lungo del target di un fattore ~interval/(interval - 1))
+    Legacy,
+    /// `interval` block dell'epoca con tempo atteso scalato a `interval - 1` intervalli.
+    /// Nessun bias, ma l'intervallo a cavallo tra due epoche non viene mai misurato
+    EpochAligned,
+    /// `interval + 1` block a partire dall'ultimo block dell'epoca precedente: misura
+    /// esattamente `interval` intervalli e le finestre si sovrappongono di un block
+    Overlapping,
+}
+
+impl RetargetWindow {
+    /// Nome della finestra (come mostrato da RPC e log)
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetargetWindow::Legacy => "legacy",
+            RetargetWindow::EpochAligned => "epoch-aligned",
+            RetargetWindow::Overlapping => "overlapping",
+        }
+    }
+
+    /// Numero di block richiesti nella finestra
+    pub fn window_len(&self, interval: u64) -> usize {
+        match self {
+            RetargetWindow::Legacy | RetargetWindow::EpochAligned => interval as usize,
+            RetargetWindow::Overlapping => interval as usize + 1,
+        }
+    }
+
+    /// Numero di intervalli misurati dalla finestra
+    pub fn measured_intervals(&self, interval: u64) -> u64 {
+        self.window_len(interval) as u64 - 1
+    }
+
+    /// Numero di intervalli con cui confrontare il tempo misurato
+    pub fn expected_intervals(&self, interval: u64) -> u64 {
+        match self {
+            RetargetWindow::Legacy => interval,
+            RetargetWindow::EpochAligned | RetargetWindow::Overlapping => {
+                self.measured_intervals(interval)
+            }
+        }
+    }
+}
+
+/// Treasury finanziata da una quota del subsidy di ogni block
+///
+/// La coinbase deve pagare almeno `allocation(subsidy)` allo script della
+/// treasury (tipicamente un multisig m-of-n dei custodi).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryParams {
+    /// Locking script della treasury
+    pub script_pubkey: Vec<u8>,
+    /// Quota del subsidy destinata alla treasury, in basis point
+    pub share_bps: u64,
+}
+
+impl TreasuryParams {
+    /// Treasury controllata da un multisig `threshold`-of-n
+    pub fn multisig(threshold: usize, pubkeys: &[Vec<u8>], share_bps: u64) -> Result<Self, ScriptError> {
+        if share_bps > BPS {
+            return Err(ScriptError::InvalidShare(share_bps));
+        }
+        Ok(Self {
+            script_pubkey: ScriptTemplate::multisig(threshold, pubkeys)?,
+            share_bps,
+        })
+    }
+
+    /// Quota del subsidy dovuta alla treasury
+    pub fn allocation(&self, subsidy: u64) -> u64 {
+        (subsidy as u128 * self.share_bps.min(BPS) as u128 / BPS as u128) as u64
+    }
+
+    /// Valore SLY nativo pagato alla treasury da una transazione
+    pub fn paid_by(&self, tx: &Transaction) -> u64 {
+        tx.outputs
+            .iter()
+            .filter(|output| output.is_native_asset() && output.script_pubkey == self.script_pubkey)
+            .fold(0u64, |total, output| total.saturating_add(output.value.to_sat()))
+    }
+
+    /// Sposta la quota della treasury dal primo output della coinbase a un output dedicato
+    pub fn apply_to_coinbase(&self, coinbase: &mut Transaction, subsidy: u64) {
+        let allocation = self.allocation(subsidy);
+        if allocation == 0 {
+            return;
+        }
+        if let Some(reward) = coinbase.outputs.first_mut() {
+            reward.value = reward.value.saturating_sub(allocation.into());
+        }
+        coinbase.outputs.push(TxOutput::to_address(allocation, &self.script_pubkey));
+    }
+}
+
+/// Soft fork con altezza di attivazione fissata per rete
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoftFork {
+    /// Nome della deployment
+    pub name: String,
+    /// Primo block in cui valgono le nuove regole
+    pub activation_height: u64,
+}
+
+impl SoftFork {
+    /// Verifica se le regole valgono per il block ad altezza `height`
+    pub fn is_active(&self, height: u64) -> bool {
+        height >= self.activation_height
+    }
+}
+
+/// Versioni di transazione ammesse da un'altezza di attivazione in poi
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxVersionRule {
+    /// Primo block in cui vale la regola
+    pub activation_height: u64,
+    /// Versione minima ammessa
+    pub min_version: u32,
+    /// Versione massima ammessa
+    pub max_version: u32,
+}
+
+impl TxVersionRule {
+    /// Versioni ammesse dalla regola
+    pub fn versions(&self) -> RangeInclusive<u32> {
+        self.min_version..=self.max_version
+    }
+}
+
+/// Versioni ammesse dal genesis: solo il formato iniziale
+fn default_tx_versions() -> Vec<TxVersionRule> {
+    vec![TxVersionRule { activation_height: 0, min_version: 1, max_version: 1 }]
+}
+
+/// Parametri di consenso di una rete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainParams {
+    /// Rete di appartenenza
+    pub network: Network,
+    /// Magic bytes (distinguono messaggi e database delle diverse reti)
+    pub magic: [u8; 4],
+    /// Target time per block in secondi
+    pub target_block_time: u64,
+    /// Blocks per difficulty adjustment
+    pub difficulty_adjustment_interval: u64,
+    /// Massimo adjustment della difficulty per periodo
+    pub max_difficulty_adjustment: f64,
+    /// Finestra usata per misurare il tempo di un'epoca
+    pub retarget_window: RetargetWindow,
+    /// Treasury finanziata dal subsidy (None se disattivata)
+    #[serde(default)]
+    pub treasury: Option<TreasuryParams>,
+    /// Hostname dei DNS seed: risolvono negli indirizzi di peer attivi
+    /// sulla porta di default
+    #[serde(default)]
+    pub dns_seeds: Vec<String>,
+    /// Peer fissi (`host:port`) usati se nessun DNS seed risponde
+    #[serde(default)]
+    pub fixed_seeds: Vec<String>,
+    /// Soft fork programmati, in ordine di attivazione
+    #[serde(default)]
+    pub soft_forks: Vec<SoftFork>,
+    /// Versioni di transazione ammesse, in ordine di attivazione
+    #[serde(default = "default_tx_versions")]
+    pub tx_versions: Vec<TxVersionRule>,
+    /// Chiavi pubbliche (compresse) dei maintainer che firmano gli alert di
+    /// rete; senza chiavi gli alert sono rifiutati
+    #[serde(default)]
+    pub alert_keys: Vec<Vec<u8>>,
+    /// Altezza da cui i block possono essere minati con AuxPoW (merge
+    /// mining); None se disattivato. La prova parent è SHA-256d: alle
+    /// altezze in cui `pow_algorithms` prescrive un altro algoritmo
+    /// l'AuxPoW resta disattivato (vedi `is_auxpow_active`)
+    #[serde(default)]
+    pub auxpow_activation_height: Option<u64>,
+    /// Algoritmi di proof of work, in ordine di attivazione; senza regole
+    /// si usa SHA-256d
+    #[serde(default)]
+    pub pow_algorithms: Vec<PowRule>,
+    /// Se le allocazioni del genesis (premine/airdrop) sono spendibili
+    /// subito invece di attendere la maturazione delle coinbase
+    #[serde(default)]
+    pub mature_genesis_allocations: bool,
+    /// Blocchi prima che un output coinbase sia spendibile
+    #[serde(default = "default_coinbase_maturity")]
+    pub coinbase_maturity: u64,
+    /// Blocchi tra due halving del subsidy
+    #[serde(default = "default_halving_interval")]
+    pub halving_interval: u64,
+}
+
+fn default_coinbase_maturity() -> u64 {
+    crate::COINBASE_MATURITY
+}
+
+fn default_halving_interval() -> u64 {
+    crate::HALVING_INTERVAL
+}
+
+impl ChainParams {
+    /// Parametri mainnet (valori delle costanti di crate)
+    pub fn mainnet() -> Self {
+        Self {
+            network: Network::Mainnet,
+            magic: Network::Mainnet.magic(),
+            target_block_time: crate::TARGET_BLOCK_TIME,
+            difficulty_adjustment_interval: crate::DIFFICULTY_ADJUSTMENT_INTERVAL,
+            max_difficulty_adjustment: crate::MAX_DIFFICULTY_ADJUSTMENT,
+            retarget_window: RetargetWindow::EpochAligned,
+            treasury: None,
+            dns_seeds: vec!["seed1.sedly.it".to_string(), "seed2.sedly.it".to_string()],
+            fixed_seeds: vec!["node1.sedly.it:9333".to_string(), "node2.sedly.it:9333".to_string()],
+            soft_forks: Vec::new(),
+            tx_versions: default_tx_versions(),
+            alert_keys: Vec::new(),
+            auxpow_activation_height: None,
+            pow_algorithms: Vec::new(),
+            mature_genesis_allocations: false,
+            coinbase_maturity: crate::COINBASE_MATURITY,
+            halving_interval: crate::HALVING_INTERVAL,
+        }
+    }
+
+    /// Attiva la treasury
+    pub fn with_treasury(mut self, treasury: TreasuryParams) -> Self {
+        self.treasury = Some(treasury);
+        self
+    }
+
+    /// Aggiunge un soft fork
+    pub fn with_soft_fork(mut self, name: &str, activation_height: u64) -> Self {
+        self.soft_forks.push(SoftFork { name: name.to_string(), activation_height });
+        self
+    }
+
+    /// Aggiunge una chiave pubblica che può firmare gli alert di rete
+    pub fn with_alert_key(mut self, pubkey: Vec<u8>) -> Self {
+        self.alert_keys.push(pubkey);
+        self
+    }
+
+    /// Accetta i block minati con AuxPoW dall'altezza `activation_height`,
+    /// finché l'algoritmo in vigore è SHA-256d
+    pub fn with_auxpow(mut self, activation_height: u64) -> Self {
+        self.auxpow_activation_height = Some(activation_height);
+        self
+    }
+
+    /// Se un block ad altezza `height` può essere minato con AuxPoW
+    ///
+    /// La prova parent è SHA-256d, quindi l'AuxPoW vale solo finché è in
+    /// vigore quell'algoritmo.
+    pub fn is_auxpow_active(&self, height: u64) -> bool {
+        self.auxpow_activation_height.is_some_and(|activation| height >= activation)
+            && self.pow_algorithm(height) == PowKind::Sha256d
+    }
+
+    /// Usa l'algoritmo `algorithm` per la proof of work dall'altezza `activation_height`
+    pub fn with_pow_algorithm(mut self, activation_height: u64, algorithm: PowKind) -> Self {
+        self.pow_algorithms.push(PowRule { activation_height, algorithm });
+        self.pow_algorithms.sort_by_key(|rule| rule.activation_height);
+        self
+    }
+
+    /// Algoritmo di proof of work dei block ad altezza `height`
+    pub fn pow_algorithm(&self, height: u64) -> PowKind {
+        self.pow_algorithms
+            .iter()
+            .rev()
+            .find(|rule| height >= rule.activation_height)
+            .map_or(PowKind::Sha256d, |rule| rule.algorithm)
+    }
+
+    /// Rende spendibili da subito le allocazioni del genesis
+    pub fn with_mature_genesis_allocations(mut self) -> Self {
+        self.mature_genesis_allocations = true;
+        self
+    }
+
+    /// Imposta i blocchi di maturazione delle coinbase (es. 1 in regtest)
+    pub fn with_coinbase_maturity(mut self, coinbase_maturity: u64) -> Self {
+        self.coinbase_maturity = coinbase_maturity;
+        self
+    }
+
+    /// Imposta l'intervallo di halving (es. pochi block in regtest)
+    pub fn with_halving_interval(mut self, halving_interval: u64) -> Self {
+        assert!(halving_interval > 0, "Halving interval must be positive");
+        self.halving_interval = halving_interval;
+        self
+    }
+
+    /// Blocchi di maturazione di un output coinbase creato ad altezza `created_height`
+    pub fn coinbase_maturity_at(&self, created_height: u64) -> u64 {
+        if created_height == 0 && self.mature_genesis_allocations {
+            0
+        } else {
+            self.coinbase_maturity
+        }
+    }
+
+    /// Subsidy del block ad altezza `height` con l'intervallo di halving della rete
+    pub fn subsidy(&self, height: u64) -> u64 {
+        crate::supply::subsidy_with_interval(height, self.halving_interval)
+    }
+
+    /// Verifica se il soft fork `name` è attivo all'altezza `height`
+    pub fn is_soft_fork_active(&self, name: &str, height: u64) -> bool {
+        self.soft_forks.iter().any(|fork| fork.name == name && fork.is_active(height))
+    }
+
+    /// Ammette le versioni `versions` dal block `activation_height`
+    ///
+    /// Una regola successiva sostituisce le precedenti: prima
+    /// dell'attivazione le nuove versioni restano invalide.
+    pub fn with_tx_versions(mut self, activation_height: u64, versions: RangeInclusive<u32>) -> Self {
+        self.tx_versions.push(TxVersionRule {
+            activation_height,
+            min_version: *versions.start(),
+            max_version: *versions.end(),
+        });
+        self.tx_versions.sort_by_key(|rule| rule.activation_height);
+        self
+    }
+
+    /// Versioni di transazione ammesse nel block ad altezza `height`
+    pub fn allowed_tx_versions(&self, height: u64) -> RangeInclusive<u32> {
+        self.tx_versions
+            .iter()
+            .rev()
+            .find(|rule| rule.activation_height <= height)
+            // Nessuna regola attiva: nessuna versione ammessa
+            .map_or(RangeInclusive::new(1, 0), TxVersionRule::versions)
+    }
+
+    /// Parametri testnet
+    pub fn testnet() -> Self {
+        Self {
+            network: Network::Testnet,
+            magic: Network::Testnet.magic(),
+            retarget_window: RetargetWindow::Overlapping,
+            dns_seeds: vec!["testnet-seed.sedly.it".to_string()],
+            fixed_seeds: vec!["testnet-node1.sedly.it:19333".to_string()],
+            ..Self::mainnet()
+        }
+    }
+
+    /// Parametri regtest (epoche corte per i test)
+    pub fn regtest() -> Self {
+        Self {
+            network: Network::Regtest,
+            magic: Network::Regtest.magic(),
+            difficulty_adjustment_interval: 10,
+            retarget_window: RetargetWindow::Overlapping,
+            // Rete locale: i peer si indicano esplicitamente
+            dns_seeds: Vec::new(),
+            fixed_seeds: Vec::new(),
+            ..Self::mainnet()
+        }
+    }
+
+    /// Parametri per una rete data
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::mainnet(),
+            Network::Testnet => Self::testnet(),
+            Network::Regtest => Self::regtest(),
+        }
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mainnet_matches_constants() {
+        let params = ChainParams::mainnet();
+        assert_eq!(params.target_block_time, crate::TARGET_BLOCK_TIME);
+        assert_eq!(params.difficulty_adjustment_interval, crate::DIFFICULTY_ADJUSTMENT_INTERVAL);
+        assert_eq!(params.retarget_window, RetargetWindow::EpochAligned);
+    }
+
+    #[test]
+    fn test_network_from_str() {
+        assert_eq!("mainnet".parse::<Network>(), Ok(Network::Mainnet));
+        assert_eq!("Regtest".parse::<Network>(), Ok(Network::Regtest));
+        assert!("signet".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn test_network_magic() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            assert_eq!(ChainParams::for_network(network).magic, network.magic());
+            assert_eq!(Network::from_magic(network.magic()), Some(network));
+        }
+        assert_ne!(Network::Mainnet.magic(), Network::Testnet.magic());
+        assert_eq!(Network::from_magic(*b"SEDL"), None);
+    }
+
+    #[test]
+    fn test_bootstrap_peers() {
+        for network in [Network::Mainnet, Network::Testnet] {
+            let params = ChainParams::for_network(network);
+            assert!(!params.dns_seeds.is_empty());
+            let suffix = format!(":{}", network.default_port());
+            assert!(params.fixed_seeds.iter().all(|peer| peer.ends_with(&suffix)));
+        }
+        assert!(ChainParams::regtest().dns_seeds.is_empty());
+        assert!(ChainParams::regtest().fixed_seeds.is_empty());
+    }
+
+    #[test]
+    fn test_window_intervals() {
+        assert_eq!(RetargetWindow::Legacy.window_len(144), 144);
+        assert_eq!(RetargetWindow::Legacy.measured_intervals(144), 143);
+        assert_eq!(RetargetWindow::Legacy.expected_intervals(144), 144);
+
+        assert_eq!(RetargetWindow::EpochAligned.expected_intervals(144), 143);
+
+        assert_eq!(RetargetWindow::Overlapping.window_len(144), 145);
+        assert_eq!(RetargetWindow::Overlapping.measured_intervals(144), 144);
+        assert_eq!(RetargetWindow::Overlapping.expected_intervals(144), 144);
+    }
+
+    #[test]
+    fn test_soft_fork_activation() {
+        assert!(ChainParams::mainnet().soft_forks.is_empty());
+
+        let params = ChainParams::regtest().with_soft_fork("strictsig", 100);
+        assert!(!params.is_soft_fork_active("strictsig", 99));
+        assert!(params.is_soft_fork_active("strictsig", 100));
+        assert!(!params.is_soft_fork_active("unknown", 100));
+    }
+
+    #[test]
+    fn test_tx_version_activation() {
+        assert_eq!(ChainParams::mainnet().allowed_tx_versions(1_000_000), 1..=1);
+
+        let params = ChainParams::regtest().with_tx_versions(100, 1..=2);
+        assert!(!params.allowed_tx_versions(99).contains(&2));
+        assert_eq!(params.allowed_tx_versions(100), 1..=2);
+
+        // Parametri salvati prima delle regole sulle versioni
+        let mut json = serde_json::to_value(ChainParams::mainnet()).unwrap();
+        json.as_object_mut().unwrap().remove("tx_versions");
+        let params: ChainParams = serde_json::from_value(json).unwrap();
+        assert_eq!(params.allowed_tx_versions(0), 1..=1);
+    }
+
+    #[test]
+    fn test_treasury_allocation() {
+        let keys = vec![vec![0x02; 33], vec![0x03; 33], vec![0x04; 33]];
+        let treasury = TreasuryParams::multisig(2, &keys, 1_000).unwrap();
+        assert_eq!(treasury.allocation(crate::INITIAL_BLOCK_REWARD), 500_000_000);
+        assert!(TreasuryParams::multisig(2, &keys, 10_001).is_err());
+
+        let mut coinbase = Transaction::coinbase(b"miner", 1, crate::INITIAL_BLOCK_REWARD);
+        treasury.apply_to_coinbase(&mut coinbase, crate::INITIAL_BLOCK_REWARD);
+        assert_eq!(coinbase.outputs[0].value.to_sat(), 4_500_000_000);
+        assert_eq!(treasury.paid_by(&coinbase), 500_000_000);
+    }
+}
@@ -0,0 +1,528 @@
+//! Pipeline di validazione e connessione dei block
+//!
+//! Un block attraversa stadi espliciti, dal più economico al più costoso:
+//! decodifica, header (con bits del retarget, timestamp e controlli di
+//! struttura indipendenti dal contesto), controlli contestuali sul UTXO set, script, connessione
+//! (preparazione delle scritture) e flush su disco. Il primo stadio che
+//! fallisce scarta il block; ogni stadio accumula esecuzioni, fallimenti e
+//! tempi in `PipelineMetrics`.
+//!
+//! Il block deve estendere il tip corrente del database.
+//!
+//! Un block che viola le regole di consenso viene marcato come invalido nel
+//! database, insieme ai discendenti che arrivano in seguito: non viene più
+//! rivalidato finché la marcatura non viene rimossa (`reconsiderblock`).
+//! Un corpo che non corrisponde all'header (merkle root, transazioni
+//! duplicate) scarta solo quella copia: l'hash resta accettabile.
+//! Con un `RejectionLog` collegato, ogni block rifiutato vi viene annotato
+//! con la regola violata.
+
+use crate::codec::{decode_block, DecodeError};
+use crate::rejects::{Rejection, RejectionLog};
+use crate::reorg::ReorgAlarm;
+use crate::storage::{BlockchainDB, InvalidBlock, ReorgRecord, StorageError};
+use crate::validation::{chain_timestamps, BlockValidator, ValidationError};
+use crate::{Block, BlockHeader};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Stadio della pipeline, nell'ordine di esecuzione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Decodifica del block serializzato
+    Decode,
+    /// Header, collegamento al tip e struttura del block
+    Header,
+    /// Spese contro il UTXO set con gli script che le sbloccano, coinbase e treasury
+    Contextual,
+    /// Limiti di dimensione degli script
+    Scripts,
+    /// Preparazione delle scritture su block, indici e UTXO set
+    Connect,
+    /// Scrittura atomica su disco
+    Flush,
+}
+
+impl Stage {
+    /// Tutti gli stadi, nell'ordine di esecuzione
+    pub const ALL: [Stage; 6] = [
+        Stage::Decode,
+        Stage::Header,
+        Stage::Contextual,
+        Stage::Scripts,
+        Stage::Connect,
+        Stage::Flush,
+    ];
+
+    /// Nome dello stadio
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Header => "header",
+            Stage::Contextual => "contextual",
+            Stage::Scripts => "scripts",
+            Stage::Connect => "connect",
+            Stage::Flush => "flush",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Statistiche di uno stadio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StageMetrics {
+    /// Numero di esecuzioni
+    pub runs: u64,
+    /// Esecuzioni fallite
+    pub failures: u64,
+    /// Tempo totale in microsecondi
+    pub total_micros: u64,
+    /// Esecuzione più lenta in microsecondi
+    pub max_micros: u64,
+}
+
+impl StageMetrics {
+    /// Registra un'esecuzione
+    fn record(&mut self, elapsed: Duration, success: bool) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.runs += 1;
+        if !success {
+            self.failures += 1;
+        }
+        self.total_micros = self.total_micros.saturating_add(micros);
+        self.max_micros = self.max_micros.max(micros);
+    }
+
+    /// Durata media in microsecondi
+    pub fn average_micros(&self) -> u64 {
+        self.total_micros.checked_div(self.runs).unwrap_or(0)
+    }
+}
+
+/// Statistiche cumulative della pipeline
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PipelineMetrics {
+    /// Block connessi
+    pub blocks_connected: u64,
+    /// Block scartati
+    pub blocks_rejected: u64,
+    /// Riorganizzazioni della chain attiva
+    pub reorgs: u64,
+    /// Riorganizzazioni che hanno fatto scattare l'allarme
+    pub deep_reorgs: u64,
+    /// Profondità massima di una riorganizzazione
+    pub max_reorg_depth: u64,
+    /// Statistiche per stadio (solo stadi eseguiti almeno una volta)
+    pub stages: BTreeMap<Stage, StageMetrics>,
+}
+
+impl PipelineMetrics {
+    /// Statistiche di uno stadio
+    pub fn stage(&self, stage: Stage) -> StageMetrics {
+        self.stages.get(&stage).copied().unwrap_or_default()
+    }
+}
+
+/// Block connesso dalla pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessedBlock {
+    /// Hash del block
+    pub hash: [u8; 32],
+    /// Altezza del block
+    pub height: u64,
+    /// Somma delle fee (SLY nativo)
+    pub total_fees: u64,
+    /// Durata di ogni stadio eseguito, in ordine
+    pub timings: Vec<(Stage, Duration)>,
+}
+
+/// Pipeline di validazione e connessione
+#[derive(Debug, Clone)]
+pub struct BlockPipeline {
+    /// Regole di validazione
+    validator: BlockValidator,
+    /// Statistiche cumulative
+    metrics: PipelineMetrics,
+    /// Allarme sulle riorganizzazioni profonde
+    reorg_alarm: ReorgAlarm,
+    /// Registro dei block rifiutati, se attivo
+    rejections: Option<Arc<Mutex<RejectionLog>>>,
+}
+
+impl BlockPipeline {
+    /// Crea una pipeline con il validatore dato
+    pub fn new(validator: BlockValidator) -> Self {
+        Self {
+            validator,
+            metrics: PipelineMetrics::default(),
+            reorg_alarm: ReorgAlarm::default(),
+            rejections: None,
+        }
+    }
+
+    /// Annota i block rifiutati nel registro dato
+    pub fn with_rejection_log(mut self, rejections: Arc<Mutex<RejectionLog>>) -> Self {
+        self.rejections = Some(rejections);
+        self
+    }
+
+    /// Collega o scollega il registro dei block rifiutati
+    pub fn set_rejection_log(&mut self, rejections: Option<Arc<Mutex<RejectionLog>>>) {
+        self.rejections = rejections;
+    }
+
+    /// Imposta l'allarme sulle riorganizzazioni profonde
+    pub fn with_reorg_alarm(mut self, alarm: ReorgAlarm) -> Self {
+        self.reorg_alarm = alarm;
+        self
+    }
+
+    /// Sostituisce l'allarme sulle riorganizzazioni profonde
+    pub fn set_reorg_alarm(&mut self, alarm: ReorgAlarm) {
+        self.reorg_alarm = alarm;
+    }
+
+    /// Allarme sulle riorganizzazioni profonde
+    pub fn reorg_alarm(&self) -> &ReorgAlarm {
+        &self.reorg_alarm
+    }
+
+    /// Aggiorna le metriche con una riorganizzazione e verifica l'allarme
+    pub(crate) fn record_reorg(&mut self, record: &ReorgRecord) {
+        self.metrics.reorgs += 1;
+        self.metrics.max_reorg_depth = self.metrics.max_reorg_depth.max(record.depth);
+        if self.reorg_alarm.check(record) {
+            self.metrics.deep_reorgs += 1;
+        }
+    }
+
+    /// Validatore usato
+    pub fn validator(&self) -> &BlockValidator {
+        &self.validator
+    }
+
+    /// Validatore usato, modificabile (es. limiti cambiati dalla governance)
+    pub fn validator_mut(&mut self) -> &mut BlockValidator {
+        &mut self.validator
+    }
+
+    /// Statistiche cumulative
+    pub fn metrics(&self) -> &PipelineMetrics {
+        &self.metrics
+    }
+
+    /// Decodifica (bincode), valida e connette un block
+    pub fn process_bytes(&mut self, bytes: &[u8], db: &BlockchainDB) -> Result<ProcessedBlock, PipelineError> {
+        let mut timings = Vec::with_capacity(Stage::ALL.len());
+        let block = run_stage(&mut self.metrics, Stage::Decode, &mut timings, || {
+            decode_block(bytes).map_err(PipelineError::Decode)
+        })?;
+        self.run(&block, db, timings)
+    }
+
+    /// Valida e connette un block sopra il tip del database
+    pub fn process(&mut self, block: &Block, db: &BlockchainDB) -> Result<ProcessedBlock, PipelineError> {
+        self.run(block, db, Vec::with_capacity(Stage::ALL.len()))
+    }
+
+    /// Esegue gli stadi successivi alla decodifica, marcando il block come
+    /// invalido se viola le regole di consenso
+    fn run(
+        &mut self,
+        block: &Block,
+        db: &BlockchainDB,
+        timings: Vec<(Stage, Duration)>,
+    ) -> Result<ProcessedBlock, PipelineError> {
+        let result = self.run_stages(block, db, timings);
+        if let (Err(PipelineError::Invalid { error, .. }), Some(rejections)) = (&result, &self.rejections) {
+            let tip_height = db.get_height().unwrap_or_else(|_| block.header.height.saturating_sub(1));
+            rejections.lock().unwrap().record(Rejection::block(block, tip_height, error));
+        }
+        if let Err(PipelineError::Invalid { error, .. }) = &result {
+            if error.invalidates_hash() {
+                let record = InvalidBlock {
+                    height: block.header.height,
+                    previous_hash: block.header.previous_hash,
+                    reason: error.to_string(),
+                };
+                if let Err(e) = db.mark_block_invalid(&block.hash(), &record) {
+                    log::warn!("Failed to mark block {} as invalid: {}", hex::encode(block.hash()), e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Esegue in ordine gli stadi successivi alla decodifica
+    fn run_stages(
+        &mut self,
+        block: &Block,
+        db: &BlockchainDB,
+        mut timings: Vec<(Stage, Duration)>,
+    ) -> Result<ProcessedBlock, PipelineError> {
+        let validator = &self.validator;
+        let metrics = &mut self.metrics;
+
+        // Txid calcolati una volta per tutti gli stadi
+        let txids = run_stage(metrics, Stage::Header, &mut timings, || {
+            check_known_invalid(block, db)?;
+            let parent = tip_header(db).map_err(|error| PipelineError::storage(Stage::Header, error))?;
+            let txids = block.txids();
+            validator.check_header(block, parent.as_ref())
+                .and_then(|()| match &parent {
+                    Some(parent) => validator.check_header_context(block, parent, |from, to| {
+                        chain_timestamps(db, from, to)
+                    }),
+                    None => Ok(()),
+                })
+                .and_then(|()| validator.check_structure_with_txids(block, &txids))
+                .map_err(|error| PipelineError::invalid(Stage::Header, error))?;
+            Ok(txids)
+        })?;
+
+        let validated = run_stage(metrics, Stage::Contextual, &mut timings, || {
+            validator.check_transactions_with_txids(block, &txids, db)
+                .map_err(|error| PipelineError::invalid(Stage::Contextual, error))
+        })?;
+
+        run_stage(metrics, Stage::Scripts, &mut timings, || {
+            validator.check_scripts(block)
+                .map_err(|error| PipelineError::invalid(Stage::Scripts, error))
+        })?;
+
+        let pending = run_stage(metrics, Stage::Connect, &mut timings, || {
+            db.connect_block_with_txids(block, &txids).map_err(|error| PipelineError::storage(Stage::Connect, error))
+        })?;
+
+        run_stage(metrics, Stage::Flush, &mut timings, || {
+            db.flush_block(pending).map_err(|error| PipelineError::storage(Stage::Flush, error))
+        })?;
+
+        metrics.blocks_connected += 1;
+        Ok(ProcessedBlock {
+            hash: validated.hash,
+            height: block.header.height,
+            total_fees: validated.total_fees,
+            timings,
+        })
+    }
+}
+
+/// Esegue uno stadio misurandone la durata
+fn run_stage<T>(
+    metrics: &mut PipelineMetrics,
+    stage: Stage,
+    timings: &mut Vec<(Stage, Duration)>,
+    f: impl FnOnce() -> Result<T, PipelineError>,
+) -> Result<T, PipelineError> {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    metrics.stages.entry(stage).or_default().record(elapsed, result.is_ok());
+    timings.push((stage, elapsed));
+    if result.is_err() {
+        metrics.blocks_rejected += 1;
+    }
+    result
+}
+
+/// Rifiuta senza rivalidarli i block già marcati come invalidi, e marca
+/// quelli che discendono da un block invalido
+pub fn check_known_invalid(block: &Block, db: &BlockchainDB) -> Result<(), PipelineError> {
+    let hash = block.hash();
+    if let Some(record) = db.get_invalid_block(&hash).map_err(|error| PipelineError::storage(Stage::Header, error))? {
+        return Err(PipelineError::KnownInvalid { hash, reason: record.reason });
+    }
+
+    let parent = block.header.previous_hash;
+    if db.is_block_invalid(&parent).map_err(|error| PipelineError::storage(Stage::Header, error))? {
+        let record = InvalidBlock {
+            height: block.header.height,
+            previous_hash: parent,
+            reason: format!("descends from invalid block {}", hex::encode(parent)),
+        };
+        db.mark_block_invalid(&hash, &record).map_err(|error| PipelineError::storage(Stage::Header, error))?;
+        return Err(PipelineError::KnownInvalid { hash, reason: record.reason });
+    }
+    Ok(())
+}
+
+/// Header del tip corrente (None su database vuoto)
+fn tip_header(db: &BlockchainDB) -> Result<Option<BlockHeader>, StorageError> {
+    let metadata = db.get_metadata()?;
+    if metadata.best_block_hash == [0; 32] {
+        return Ok(None);
+    }
+    db.get_header_by_height(metadata.height)
+}
+
+/// Errori della pipeline
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("Cannot decode block: {0}")]
+    Decode(DecodeError),
+
+    #[error("Block rejected at {stage} stage: {error}")]
+    Invalid { stage: Stage, error: ValidationError },
+
+    #[error("Block {} is marked invalid: {reason}", hex::encode(hash))]
+    KnownInvalid { hash: [u8; 32], reason: String },
+
+    #[error("Storage error at {stage} stage: {error}")]
+    Storage { stage: Stage, error: StorageError },
+}
+
+impl PipelineError {
+    /// Errore di validazione in uno stadio
+    fn invalid(stage: Stage, error: ValidationError) -> Self {
+        PipelineError::Invalid { stage, error }
+    }
+
+    /// Errore di storage in uno stadio
+    fn storage(stage: Stage, error: StorageError) -> Self {
+        PipelineError::Storage { stage, error }
+    }
+
+    /// Stadio in cui il block è stato scartato
+    pub fn stage(&self) -> Stage {
+        match self {
+            PipelineError::Decode(_) => Stage::Decode,
+            PipelineError::KnownInvalid { .. } => Stage::Header,
+            PipelineError::Invalid { stage, .. } | PipelineError::Storage { stage, .. } => *stage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::block_subsidy;
+    use crate::{ChainParams, Transaction};
+    use tempfile::TempDir;
+
+    fn setup() -> (BlockchainDB, Block, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+        (db, genesis, temp_dir)
+    }
+
+    #[test]
+    fn test_pipeline_connects_block() {
+        let (db, genesis, _temp_dir) = setup();
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1));
+        let block = Block::new(genesis.hash(), vec![coinbase], genesis.header.bits, 1);
+        let bytes = bincode::serialize(&block).unwrap();
+
+        let processed = pipeline.process_bytes(&bytes, &db).unwrap();
+        assert_eq!(processed.hash, block.hash());
+        assert_eq!(processed.height, 1);
+        assert_eq!(processed.timings.iter().map(|(stage, _)| *stage).collect::<Vec<_>>(), Stage::ALL);
+        assert_eq!(db.get_best_block_hash().unwrap(), block.hash());
+
+        let metrics = pipeline.metrics();
+        assert_eq!(metrics.blocks_connected, 1);
+        assert_eq!(metrics.blocks_rejected, 0);
+        assert!(Stage::ALL.iter().all(|stage| metrics.stage(*stage).runs == 1));
+    }
+
+    #[test]
+    fn test_pipeline_short_circuits() {
+        let (db, genesis, _temp_dir) = setup();
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+
+        // Un block che non estende il tip si ferma allo stadio header
+        let coinbase = Transaction::coinbase(b"miner", 2, block_subsidy(2));
+        let orphan = Block::new([7; 32], vec![coinbase], genesis.header.bits, 2);
+        let error = pipeline.process(&orphan, &db).unwrap_err();
+        assert_eq!(error.stage(), Stage::Header);
+
+        // Una coinbase che reclama troppo si ferma ai controlli contestuali
+        let greedy = Transaction::coinbase(b"miner", 1, block_subsidy(1) + 1);
+        let block = Block::new(genesis.hash(), vec![greedy], genesis.header.bits, 1);
+        let error = pipeline.process(&block, &db).unwrap_err();
+        assert_eq!(error.stage(), Stage::Contextual);
+
+        assert!(matches!(pipeline.process_bytes(b"garbage", &db), Err(PipelineError::Decode(_))));
+
+        // Il block invalido resta marcato, con i suoi discendenti; l'orfano no
+        assert!(db.is_block_invalid(&block.hash()).unwrap());
+        assert!(!db.is_block_invalid(&orphan.hash()).unwrap());
+        assert!(matches!(pipeline.process(&block, &db), Err(PipelineError::KnownInvalid { .. })));
+        let child = Block::new(block.hash(), vec![Transaction::coinbase(b"miner", 2, 0)], genesis.header.bits, 2);
+        assert!(matches!(check_known_invalid(&child, &db), Err(PipelineError::KnownInvalid { .. })));
+        assert_eq!(db.clear_invalid_block(&block.hash()).unwrap(), vec![block.hash(), child.hash()]);
+        assert!(db.get_invalid_blocks().unwrap().is_empty());
+
+        let metrics = pipeline.metrics();
+        assert_eq!(metrics.blocks_rejected, 4);
+        assert_eq!(metrics.stage(Stage::Header).failures, 2);
+        assert_eq!(metrics.stage(Stage::Contextual).runs, 1);
+        assert_eq!(metrics.stage(Stage::Scripts).runs, 0);
+        assert_eq!(db.get_height().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_mutated_body_does_not_invalidate_hash() {
+        let (db, genesis, _temp_dir) = setup();
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1));
+        let block = Block::new(genesis.hash(), vec![coinbase.clone()], genesis.header.bits, 1);
+
+        // Stesso header, corpo che non corrisponde alla merkle root o vuoto
+        let mut mutated = block.clone();
+        mutated.transactions.push(coinbase);
+        let mut empty = block.clone();
+        empty.transactions.clear();
+        for copy in [&mutated, &empty] {
+            assert_eq!(copy.hash(), block.hash());
+            let PipelineError::Invalid { error, .. } = pipeline.process(copy, &db).unwrap_err() else {
+                panic!("Mutated body was not rejected by the rules");
+            };
+            assert!(error.is_block_invalid() && !error.invalidates_hash());
+        }
+        assert!(!db.is_block_invalid(&block.hash()).unwrap());
+
+        // La copia intatta si collega
+        pipeline.process(&block, &db).unwrap();
+        assert_eq!(db.get_best_block_hash().unwrap(), block.hash());
+    }
+
+    #[test]
+    fn test_pipeline_records_rejections() {
+        let (db, genesis, _temp_dir) = setup();
+        let rejections = Arc::new(Mutex::new(RejectionLog::new(10)));
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()))
+            .with_rejection_log(rejections.clone());
+
+        let orphan = Block::new([7; 32], vec![Transaction::coinbase(b"miner", 2, 1)], genesis.header.bits, 2);
+        let greedy = Transaction::coinbase(b"miner", 1, block_subsidy(1) + 1);
+        let block = Block::new(genesis.hash(), vec![greedy], genesis.header.bits, 1);
+        for block in [&orphan, &block, &block] {
+            pipeline.process(block, &db).unwrap_err();
+        }
+        assert!(pipeline.process_bytes(b"garbage", &db).is_err());
+
+        // Solo le violazioni delle regole: non i dati indecodificabili né i block già marcati
+        let log = rejections.lock().unwrap();
+        let entries = log.recent(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].hash, entries[0].rule.as_str()), (block.hash(), "excessive-coinbase"));
+        assert_eq!((entries[0].height, entries[0].tip_height), (1, 0));
+        assert_eq!(entries[0].data, bincode::serialize(&block).unwrap());
+        assert_eq!((entries[1].hash, entries[1].rule.as_str()), (orphan.hash(), "bad-parent"));
+    }
+}
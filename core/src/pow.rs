@@ -0,0 +1,178 @@
+//! Funzioni di hash della proof of work
+//!
+//! L'identità di un block resta sempre il doppio SHA-256 dell'header
+//! (`BlockHeader::hash`); la proof of work invece usa l'algoritmo in vigore
+//! all'altezza del block secondo `ChainParams::pow_algorithms`. Così una
+//! rete di test può passare a un altro algoritmo da un'altezza data e la
+//! community può valutare un cambio di PoW (es. per resistere agli ASIC)
+//! senza un fork del codice.
+//!
+//! Gli algoritmi alternativi servono a questa valutazione: [`Scratchpad`]
+//! è una costruzione semplice che richiede memoria, non una funzione
+//! memory-hard analizzata come scrypt o Argon2.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+use std::str::FromStr;
+
+/// Hash di proof of work di un header serializzato
+pub trait PowAlgorithm: Send + Sync {
+    /// Nome dell'algoritmo (come in `PowKind::name`)
+    fn name(&self) -> &'static str;
+
+    /// Hash da confrontare con il target (big-endian, come `BlockHeader::hash`)
+    fn pow_hash(&self, header: &[u8]) -> [u8; 32];
+}
+
+/// Doppio SHA-256, l'algoritmo dal genesis
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256d;
+
+impl PowAlgorithm for Sha256d {
+    fn name(&self) -> &'static str {
+        PowKind::Sha256d.name()
+    }
+
+    fn pow_hash(&self, header: &[u8]) -> [u8; 32] {
+        crate::hash::sha256d(header)
+    }
+}
+
+/// SHA3-256 (Keccak): nessun ASIC SHA-256 esistente lo calcola
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha3;
+
+impl PowAlgorithm for Sha3 {
+    fn name(&self) -> &'static str {
+        PowKind::Sha3.name()
+    }
+
+    fn pow_hash(&self, header: &[u8]) -> [u8; 32] {
+        Sha3_256::digest(header).into()
+    }
+}
+
+/// Celle da 32 bytes dello scratchpad (256 KiB)
+pub const SCRATCHPAD_CELLS: usize = 8_192;
+
+/// Letture casuali dello scratchpad per hash
+pub const SCRATCHPAD_READS: usize = 8_192;
+
+/// Hash con scratchpad: ogni tentativo riempie 256 KiB derivati dall'header
+/// e li rilegge in ordine dipendente dai dati
+///
+/// Senza tenere in memoria lo scratchpad un miner deve ricalcolare le celle
+/// a ogni lettura, quindi il costo è dominato dalla memoria e non dai core
+/// SHA-256.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scratchpad;
+
+impl PowAlgorithm for Scratchpad {
+    fn name(&self) -> &'static str {
+        PowKind::Scratchpad.name()
+    }
+
+    fn pow_hash(&self, header: &[u8]) -> [u8; 32] {
+        let mut cells = Vec::with_capacity(SCRATCHPAD_CELLS);
+        let mut cell: [u8; 32] = Sha256::digest(header).into();
+        for _ in 0..SCRATCHPAD_CELLS {
+            cells.push(cell);
+            cell = Sha256::digest(cell).into();
+        }
+        let mut state = cell;
+        for _ in 0..SCRATCHPAD_READS {
+            let index = u32::from_le_bytes(state[..4].try_into().expect("State is 32 bytes")) as usize % SCRATCHPAD_CELLS;
+            state = Sha256::digest([state, cells[index]].concat()).into();
+        }
+        crate::hash::sha256d(&state)
+    }
+}
+
+/// Algoritmo di proof of work selezionabile nei parametri di rete
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowKind {
+    /// Doppio SHA-256 (Bitcoin)
+    Sha256d,
+    /// SHA3-256
+    Sha3,
+    /// Scratchpad da 256 KiB
+    Scratchpad,
+}
+
+impl PowKind {
+    /// Tutti gli algoritmi
+    pub const ALL: [PowKind; 3] = [PowKind::Sha256d, PowKind::Sha3, PowKind::Scratchpad];
+
+    /// Nome dell'algoritmo (come accettato da `FromStr`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowKind::Sha256d => "sha256d",
+            PowKind::Sha3 => "sha3",
+            PowKind::Scratchpad => "scratchpad",
+        }
+    }
+
+    /// Implementazione dell'algoritmo
+    pub fn algorithm(&self) -> &'static dyn PowAlgorithm {
+        match self {
+            PowKind::Sha256d => &Sha256d,
+            PowKind::Sha3 => &Sha3,
+            PowKind::Scratchpad => &Scratchpad,
+        }
+    }
+}
+
+impl FromStr for PowKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s.to_ascii_lowercase())
+            .ok_or_else(|| format!("Unknown PoW algorithm: {}", s))
+    }
+}
+
+/// Algoritmo di proof of work in vigore da un'altezza di attivazione in poi
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowRule {
+    /// Primo block minato con l'algoritmo
+    pub activation_height: u64,
+    /// Algoritmo
+    pub algorithm: PowKind,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, ChainParams, Transaction};
+
+    #[test]
+    fn test_pow_algorithms() {
+        let header = Block::genesis().header;
+        let bytes = bincode::serialize(&header).unwrap();
+        assert_eq!(Sha256d.pow_hash(&bytes), header.hash());
+        for kind in PowKind::ALL {
+            assert_eq!(kind.name().parse::<PowKind>(), Ok(kind));
+            assert_eq!(kind.algorithm().name(), kind.name());
+            assert_eq!(kind.algorithm().pow_hash(&bytes), kind.algorithm().pow_hash(&bytes));
+        }
+        let hashes: std::collections::HashSet<[u8; 32]> =
+            PowKind::ALL.iter().map(|kind| kind.algorithm().pow_hash(&bytes)).collect();
+        assert_eq!(hashes.len(), 3);
+        assert!("x11".parse::<PowKind>().is_err());
+
+        // Cambio di algoritmo a un'altezza: prima resta SHA-256d
+        let params = ChainParams::regtest().with_pow_algorithm(10, PowKind::Sha3);
+        assert_eq!(params.pow_algorithm(9), PowKind::Sha256d);
+        assert_eq!(params.pow_algorithm(10), PowKind::Sha3);
+
+        let mut block = Block::new([1; 32], vec![Transaction::coinbase(b"miner", 10, 50)], 0x207fffff, 10);
+        while !block.header.meets_difficulty_with(PowKind::Sha3.algorithm()) {
+            block.header.nonce += 1;
+        }
+        assert_eq!(block.header.pow_hash(&Sha3), Sha3.pow_hash(&bincode::serialize(&block.header).unwrap()));
+    }
+}
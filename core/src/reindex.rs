@@ -0,0 +1,291 @@
+//! Ricostruzione dello stato derivato a partire dai block salvati
+
+use crate::difficulty::block_work;
+use crate::storage::{BlockchainDB, StorageError};
+use crate::uint::U256;
+use crate::validation::{BlockValidator, ValidationError};
+use crate::BlockHeader;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Ogni quanti block viene riportato il progresso (default)
+pub const DEFAULT_PROGRESS_INTERVAL: u64 = 1_000;
+
+/// Stato di avanzamento di un reindex
+#[derive(Debug, Clone)]
+pub struct ReindexProgress {
+    /// Ultima altezza riapplicata
+    pub height: u64,
+    /// Altezza finale della chain da riapplicare
+    pub target_height: u64,
+    /// Transazioni riapplicate finora
+    pub transactions: u64,
+    /// Tempo trascorso dall'inizio
+    pub elapsed: Duration,
+}
+
+impl ReindexProgress {
+    /// Percentuale completata (0-100)
+    pub fn percent(&self) -> f64 {
+        if self.target_height == 0 {
+            100.0
+        } else {
+            self.height as f64 / self.target_height as f64 * 100.0
+        }
+    }
+
+    /// Block riapplicati al secondo
+    pub fn blocks_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            (self.height + 1) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Riepilogo di un reindex completato
+#[derive(Debug, Clone)]
+pub struct ReindexSummary {
+    /// Altezza del tip ricostruito
+    pub height: u64,
+    /// Hash del tip ricostruito
+    pub best_block_hash: [u8; 32],
+    /// Transazioni riapplicate
+    pub transactions: u64,
+    /// Block salvati ma fuori dalla best chain (ignorati)
+    pub stale_blocks: u64,
+    /// Durata totale
+    pub elapsed: Duration,
+}
+
+/// Ricostruisce UTXO set, indici e metadati riapplicando i block salvati
+///
+/// La best chain viene scelta dai soli block presenti nel database (lavoro
+/// cumulativo maggiore a partire dal genesis), ignorando l'indice per altezza
+/// che potrebbe essere corrotto. Ogni block viene rivalidato prima di essere
+/// riapplicato; al primo errore il database resta consistente fino al parent.
+pub struct Reindexer<'a> {
+    /// Database da ricostruire
+    db: &'a BlockchainDB,
+    /// Validatore usato per ogni block
+    validator: BlockValidator,
+    /// Ogni quanti block riportare il progresso
+    progress_interval: u64,
+}
+
+impl<'a> Reindexer<'a> {
+    /// Crea reindexer per il database dato
+    pub fn new(db: &'a BlockchainDB, validator: BlockValidator) -> Self {
+        Self {
+            db,
+            validator,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+
+    /// Imposta ogni quanti block riportare il progresso
+    pub fn with_progress_interval(mut self, interval: u64) -> Self {
+        self.progress_interval = interval.max(1);
+        self
+    }
+
+    /// Esegue il reindex chiamando `on_progress` periodicamente e alla fine
+    pub fn run<F>(&self, mut on_progress: F) -> Result<ReindexSummary, ReindexError>
+    where
+        F: FnMut(&ReindexProgress),
+    {
+        let start = Instant::now();
+        let mut headers = self.db.get_stored_headers()?;
+        let total_blocks = headers.len() as u64;
+        // I block marcati come invalidi (anche dall'operatore) restano fuori dalla best chain
+        let invalid: HashSet<[u8; 32]> = self.db.get_invalid_blocks()?.into_iter().map(|(hash, _)| hash).collect();
+        headers.retain(|header| !invalid.contains(&header.hash()));
+        let chain = select_best_chain(headers).ok_or(ReindexError::NoGenesis)?;
+        let target_height = chain.len() as u64 - 1;
+
+        log::info!(
+            "Reindexing {} blocks (tip height {}, {} stale)",
+            chain.len(), target_height, total_blocks - chain.len() as u64
+        );
+        self.db.clear_derived_state()?;
+
+        let mut parent: Option<BlockHeader> = None;
+        let mut transactions = 0u64;
+        for hash in &chain {
+            let block = self.db.get_block(hash)?
+                .ok_or(StorageError::BlockNotFound { hash: *hash })?;
+            let height = block.header.height;
+
+            self.validator
+                .validate_block(&block, parent.as_ref(), self.db)
+                .map_err(|source| ReindexError::InvalidBlock { height, hash: *hash, source })?;
+
+            if parent.is_none() {
+                self.db.initialize_with_genesis(&block)?;
+            } else {
+                self.db.store_block(&block)?;
+            }
+            transactions += block.transactions.len() as u64;
+
+            if height % self.progress_interval == 0 || height == target_height {
+                on_progress(&ReindexProgress {
+                    height,
+                    target_height,
+                    transactions,
+                    elapsed: start.elapsed(),
+                });
+            }
+            parent = Some(block.header);
+        }
+
+        Ok(ReindexSummary {
+            height: target_height,
+            best_block_hash: *chain.last().expect("chain contains genesis"),
+            transactions,
+            stale_blocks: total_blocks - chain.len() as u64,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+/// Sceglie la chain con più lavoro cumulativo a partire da un genesis
+///
+/// Ritorna gli hash in ordine di altezza, None se non esiste un genesis.
+fn select_best_chain(headers: Vec<BlockHeader>) -> Option<Vec<[u8; 32]>> {
+    let mut children: HashMap<[u8; 32], Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    let hashes: Vec<[u8; 32]> = headers.iter().map(|header| header.hash()).collect();
+
+    for (index, header) in headers.iter().enumerate() {
+        if header.previous_hash == [0; 32] && header.height == 0 {
+            roots.push(index);
+        } else {
+            children.entry(header.previous_hash).or_default().push(index);
+        }
+    }
+
+    // Visita iterativa dai genesis accumulando il lavoro
+    let mut parents: HashMap<usize, usize> = HashMap::new();
+    let mut best: Option<(U256, usize)> = None;
+    let mut stack: Vec<(usize, U256)> = roots
+        .iter()
+        .map(|&index| (index, block_work(headers[index].bits)))
+        .collect();
+
+    while let Some((index, work)) = stack.pop() {
+        if best.is_none_or(|(best_work, _)| work > best_work) {
+            best = Some((work, index));
+        }
+        for &child in children.get(&hashes[index]).map(Vec::as_slice).unwrap_or_default() {
+            parents.insert(child, index);
+            stack.push((child, work.saturating_add(&block_work(headers[child].bits))));
+        }
+    }
+
+    let (_, mut index) = best?;
+    let mut chain = vec![hashes[index]];
+    while let Some(&parent) = parents.get(&index) {
+        chain.push(hashes[parent]);
+        index = parent;
+    }
+    chain.reverse();
+    Some(chain)
+}
+
+/// Errori del reindex
+#[derive(Debug, thiserror::Error)]
+pub enum ReindexError {
+    #[error("No genesis block found in block storage")]
+    NoGenesis,
+
+    #[error("Block {} at height {height} failed validation: {source}", hex::encode(hash))]
+    InvalidBlock {
+        height: u64,
+        hash: [u8; 32],
+        source: ValidationError,
+    },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::ChainParams;
+    use crate::validation::block_subsidy;
+    use crate::{Block, OutPoint, Transaction};
+    use tempfile::TempDir;
+
+    fn build_chain(db: &BlockchainDB, blocks: u64) -> Vec<Block> {
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let mut chain = vec![genesis];
+        for height in 1..=blocks {
+            let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+            let block = Block::new(chain.last().unwrap().hash(), vec![coinbase], 0x1d00ffff, height);
+            db.store_block(&block).unwrap();
+            chain.push(block);
+        }
+        chain
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let chain = build_chain(&db, 12);
+
+        // Stale fork below the tip and a corrupted metadata state
+        let fork = Block::new(chain[10].hash(), vec![Transaction::coinbase(b"other", 11, 1)], 0x1d00ffff, 11);
+        db.disconnect_tip().unwrap();
+        db.disconnect_tip().unwrap();
+        db.store_block(&fork).unwrap();
+        db.disconnect_tip().unwrap();
+        db.store_block(&chain[11]).unwrap();
+        db.store_block(&chain[12]).unwrap();
+        db.clear_derived_state().unwrap();
+        assert_eq!(db.get_height().unwrap(), 0);
+
+        let mut reports = Vec::new();
+        let summary = Reindexer::new(&db, BlockValidator::new(ChainParams::regtest()))
+            .with_progress_interval(5)
+            .run(|progress| reports.push(progress.height))
+            .unwrap();
+
+        assert_eq!(summary.height, 12);
+        assert_eq!(summary.best_block_hash, chain[12].hash());
+        assert_eq!(summary.stale_blocks, 1);
+        assert_eq!(reports, vec![0, 5, 10, 12]);
+
+        let metadata = db.get_metadata().unwrap();
+        assert_eq!(metadata.height, 12);
+        assert_eq!(metadata.genesis_hash, chain[0].hash());
+        assert_eq!(db.get_block_by_height(7).unwrap().unwrap().hash(), chain[7].hash());
+
+        let coinbase_txid = chain[3].transactions[0].hash();
+        assert!(db.get_utxo(&OutPoint::new(coinbase_txid, 0)).unwrap().is_some());
+        assert!(db.get_transaction(&coinbase_txid).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reindex_stops_at_invalid_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let chain = build_chain(&db, 3);
+
+        let greedy = Transaction::coinbase(b"miner", 4, block_subsidy(4) * 2);
+        let invalid = Block::new(chain[3].hash(), vec![greedy], 0x1d00ffff, 4);
+        db.store_block(&invalid).unwrap();
+
+        let result = Reindexer::new(&db, BlockValidator::new(ChainParams::regtest())).run(|_| {});
+        assert!(matches!(result, Err(ReindexError::InvalidBlock { height: 4, .. })));
+
+        // Lo stato resta consistente fino al parent del block invalido
+        assert_eq!(db.get_height().unwrap(), 3);
+        assert_eq!(db.get_best_block_hash().unwrap(), chain[3].hash());
+    }
+}
@@ -0,0 +1,252 @@
+//! Registro dei block e delle transazioni rifiutati per il debug dei fork
+//!
+//! Con il registro attivo, pipeline e mempool annotano ogni rifiuto per
+//! violazione delle regole di consenso: regola violata, altezza del
+//! rifiutato e del tip, dump esadecimale dei dati. Le voci stanno in un
+//! ring buffer di capacità fissa (le più vecchie escono per prime). Come
+//! la mempool, il registro è condiviso con il server RPC dietro un
+//! `Arc<Mutex<_>>`: un operatore può così capire perché il suo nodo ha
+//! lasciato la chain del resto della rete.
+
+use crate::validation::ValidationError;
+use crate::{Block, Transaction};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Voci conservate di default
+pub const DEFAULT_REJECTION_LOG_CAPACITY: usize = 100;
+
+/// Bytes conservati del dump di ogni voce: i block più grandi vengono troncati
+pub const MAX_REJECTION_DUMP: usize = 64 * 1024;
+
+/// Tipo di dato rifiutato
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectedItem {
+    Block,
+    Transaction,
+}
+
+impl RejectedItem {
+    /// Nome usato nei dump
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectedItem::Block => "block",
+            RejectedItem::Transaction => "transaction",
+        }
+    }
+}
+
+/// Block o transazione rifiutati
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    /// Tipo di dato
+    pub item: RejectedItem,
+    /// Hash del block o txid
+    pub hash: [u8; 32],
+    /// Altezza del block, o altezza a cui la transazione sarebbe stata inclusa
+    pub height: u64,
+    /// Altezza del tip al momento del rifiuto
+    pub tip_height: u64,
+    /// Regola violata (es. `bad-merkle-root`)
+    pub rule: String,
+    /// Messaggio completo dell'errore
+    pub reason: String,
+    /// Dimensione serializzata
+    pub size: usize,
+    /// Dati serializzati, al massimo `MAX_REJECTION_DUMP` bytes
+    pub data: Vec<u8>,
+    /// Timestamp UNIX del rifiuto
+    pub timestamp: u64,
+}
+
+impl Rejection {
+    /// Voce per un dato serializzato in `data`
+    pub fn new(
+        item: RejectedItem,
+        hash: [u8; 32],
+        height: u64,
+        tip_height: u64,
+        rule: &str,
+        reason: String,
+        data: &[u8],
+    ) -> Self {
+        Self {
+            item,
+            hash,
+            height,
+            tip_height,
+            rule: rule.to_string(),
+            reason,
+            size: data.len(),
+            data: data[..data.len().min(MAX_REJECTION_DUMP)].to_vec(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        }
+    }
+
+    /// Block rifiutato dalla validazione con il tip a `tip_height`
+    pub fn block(block: &Block, tip_height: u64, error: &ValidationError) -> Self {
+        let data = bincode::serialize(block).unwrap_or_default();
+        Self::new(
+            RejectedItem::Block,
+            block.hash(),
+            block.header.height,
+            tip_height,
+            error.rule(),
+            error.to_string(),
+            &data,
+        )
+    }
+
+    /// Transazione rifiutata con il tip a `tip_height`
+    pub fn transaction(tx: &Transaction, tip_height: u64, error: &ValidationError) -> Self {
+        let data = bincode::serialize(tx).unwrap_or_default();
+        Self::new(
+            RejectedItem::Transaction,
+            tx.hash(),
+            tip_height + 1,
+            tip_height,
+            error.rule(),
+            error.to_string(),
+            &data,
+        )
+    }
+
+    /// Se il dump non contiene tutti i dati
+    pub fn is_truncated(&self) -> bool {
+        self.data.len() < self.size
+    }
+
+    /// Vista JSON con hash (in ordine di visualizzazione) e dati in esadecimale
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "item": self.item.as_str(),
+            "hash": crate::Hash256::from(self.hash),
+            "height": self.height,
+            "tip_height": self.tip_height,
+            "rule": self.rule,
+            "reason": self.reason,
+            "size": self.size,
+            "truncated": self.is_truncated(),
+            "hex": hex::encode(&self.data),
+            "timestamp": self.timestamp,
+        })
+    }
+}
+
+/// Ring buffer degli ultimi rifiuti
+#[derive(Debug, Clone)]
+pub struct RejectionLog {
+    /// Voci dalla più vecchia alla più recente
+    entries: VecDeque<Rejection>,
+    /// Voci conservate al massimo
+    capacity: usize,
+    /// Rifiuti registrati dall'avvio, compresi quelli usciti dal buffer
+    total: u64,
+}
+
+impl Default for RejectionLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_REJECTION_LOG_CAPACITY)
+    }
+}
+
+impl RejectionLog {
+    /// Registro che conserva le ultime `capacity` voci
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity.min(DEFAULT_REJECTION_LOG_CAPACITY)),
+            capacity,
+            total: 0,
+        }
+    }
+
+    /// Voci conservate al massimo
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Voci conservate
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Se il registro è vuoto
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Rifiuti registrati dall'avvio
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Aggiunge un rifiuto, scartando il più vecchio se il buffer è pieno
+    pub fn record(&mut self, rejection: Rejection) {
+        log::debug!(
+            "Rejected {} {} at height {}: {} ({})",
+            rejection.item.as_str(), hex::encode(rejection.hash), rejection.height, rejection.rule, rejection.reason
+        );
+        self.total += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(rejection);
+    }
+
+    /// Le ultime `limit` voci, dalla più recente
+    pub fn recent(&self, limit: usize) -> Vec<&Rejection> {
+        self.entries.iter().rev().take(limit).collect()
+    }
+
+    /// Svuota il buffer
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(height: u64) -> Rejection {
+        Rejection::new(RejectedItem::Block, [height as u8; 32], height, height - 1, "bad-height", "test".into(), &[1])
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_latest() {
+        let mut log = RejectionLog::new(3);
+        for height in 1..=5 {
+            log.record(rejection(height));
+        }
+        assert_eq!((log.len(), log.total()), (3, 5));
+        let heights: Vec<u64> = log.recent(10).iter().map(|entry| entry.height).collect();
+        assert_eq!(heights, vec![5, 4, 3]);
+        assert_eq!(log.recent(1)[0].height, 5);
+
+        log.clear();
+        assert!(log.is_empty());
+        assert_eq!(log.total(), 5);
+    }
+
+    #[test]
+    fn test_block_dump_is_truncated() {
+        let tx = Transaction::coinbase(b"miner", 1, 50);
+        let mut block = Block::new([7; 32], vec![tx.clone()], 0x1d00ffff, 1);
+        block.transactions[0].outputs[0].script_pubkey = vec![0x6a; MAX_REJECTION_DUMP];
+
+        let entry = Rejection::block(&block, 0, &ValidationError::BadMerkleRoot);
+        assert_eq!((entry.rule.as_str(), entry.height, entry.tip_height), ("bad-merkle-root", 1, 0));
+        assert!(entry.is_truncated());
+        assert_eq!(entry.data.len(), MAX_REJECTION_DUMP);
+        assert_eq!(entry.to_json()["truncated"], true);
+
+        let entry = Rejection::transaction(&tx, 4, &ValidationError::MissingCoinbase);
+        assert_eq!((entry.item, entry.height), (RejectedItem::Transaction, 5));
+        assert!(!entry.is_truncated());
+        assert_eq!(entry.data, bincode::serialize(&tx).unwrap());
+        assert_eq!(entry.to_json()["hex"], hex::encode(&entry.data));
+    }
+}
@@ -0,0 +1,416 @@
+//! Riorganizzazione della chain attiva
+//!
+//! Strumenti per spostare il tip del database su un altro ramo di block già
+//! salvati: scollegamento dei block fino al punto di fork e connessione del
+//! nuovo ramo con la `BlockPipeline`. Se un block del nuovo ramo viene
+//! rifiutato, il ramo precedente viene ripristinato.
+//!
+//! Usati dalle RPC operative `invalidateblock` e `preciousblock`.
+//!
+//! Ogni riorganizzazione viene registrata nel database (`getreorgs`); oltre
+//! la profondità configurata nel `ReorgAlarm` della pipeline scatta un
+//! allarme: log di warning, metrica e hook registrati (es. webhook).
+
+use crate::headers::{HeaderCache, TipStatus};
+use crate::pipeline::{BlockPipeline, PipelineError, ProcessedBlock};
+use crate::storage::{BlockchainDB, InvalidBlock, ReorgRecord, StorageError};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Profondità oltre la quale una riorganizzazione fa scattare l'allarme
+pub const DEFAULT_REORG_ALARM_DEPTH: u64 = 6;
+
+/// Hook chiamato per ogni riorganizzazione profonda
+pub type ReorgHook = Arc<dyn Fn(&ReorgRecord) + Send + Sync>;
+
+/// Allarme sulle riorganizzazioni profonde
+#[derive(Clone)]
+pub struct ReorgAlarm {
+    /// Profondità minima che fa scattare l'allarme
+    threshold: u64,
+    /// Hook chiamati quando scatta l'allarme
+    hooks: Vec<ReorgHook>,
+}
+
+impl ReorgAlarm {
+    /// Allarme per riorganizzazioni di almeno `threshold` block
+    pub fn new(threshold: u64) -> Self {
+        Self { threshold, hooks: Vec::new() }
+    }
+
+    /// Aggiunge un hook chiamato quando scatta l'allarme
+    pub fn with_hook(mut self, hook: impl Fn(&ReorgRecord) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Profondità minima che fa scattare l'allarme
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Se una riorganizzazione è abbastanza profonda da far scattare l'allarme
+    pub fn is_deep(&self, record: &ReorgRecord) -> bool {
+        record.depth >= self.threshold
+    }
+
+    /// Segnala una riorganizzazione; ritorna se l'allarme è scattato
+    pub fn check(&self, record: &ReorgRecord) -> bool {
+        if !self.is_deep(record) {
+            return false;
+        }
+        log::warn!(
+            "Deep reorg of {} blocks at height {}: {} -> {}",
+            record.depth, record.fork_height, record.old_tip, record.new_tip
+        );
+        for hook in &self.hooks {
+            hook(record);
+        }
+        true
+    }
+}
+
+impl Default for ReorgAlarm {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORG_ALARM_DEPTH)
+    }
+}
+
+impl fmt::Debug for ReorgAlarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReorgAlarm")
+            .field("threshold", &self.threshold)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+/// Esito di una riorganizzazione
+#[derive(Debug, Default)]
+pub struct ReorgReport {
+    /// Hash dei block scollegati, dal tip verso il punto di fork
+    pub disconnected: Vec<[u8; 32]>,
+    /// Block connessi, in ordine di altezza
+    pub connected: Vec<ProcessedBlock>,
+}
+
+/// Rende `target` il tip della chain attiva
+///
+/// Tutti i block del ramo devono essere salvati nel database o in staging
+/// (ramo laterale ricevuto da un peer); quelli connessi escono dallo
+/// staging. Se la pipeline rifiuta un block il ramo precedente viene
+/// ricollegato e l'errore ritornato.
+pub fn activate_chain(db: &BlockchainDB, pipeline: &mut BlockPipeline, target: [u8; 32]) -> Result<ReorgReport, ReorgError> {
+    // Risale dal target fino al primo block della chain attiva
+    let mut branch = Vec::new();
+    let mut current = target;
+    let fork_height = loop {
+        let block = match db.get_block(&current)? {
+            Some(block) => block,
+            None => db.get_staged_block(&current)?.ok_or(ReorgError::UnknownBlock(current))?,
+        };
+        let height = block.header.height;
+        if active_hash_at(db, height)? == Some(current) {
+            break height;
+        }
+        if height == 0 {
+            return Err(ReorgError::UnknownBlock(current));
+        }
+        current = block.header.previous_hash;
+        branch.push(block);
+    };
+
+    let started_at = unix_time();
+    let old_tip = db.get_metadata()?;
+    let mut report = ReorgReport::default();
+    let mut old_branch = Vec::new();
+    while db.get_height()? > fork_height {
+        let block = db.disconnect_tip()?;
+        report.disconnected.push(block.hash());
+        old_branch.push(block);
+    }
+
+    for block in branch.iter().rev() {
+        match pipeline.process(block, db) {
+            Ok(processed) => {
+                db.remove_staged_block(&processed.hash)?;
+                report.connected.push(processed);
+            }
+            Err(error) => {
+                log::warn!("Reorg to {} failed, restoring previous branch: {}", hex::encode(target), error);
+                while db.get_height()? > fork_height {
+                    db.disconnect_tip()?;
+                }
+                for block in old_branch.iter().rev() {
+                    db.store_block(block)?;
+                }
+                return Err(ReorgError::Rejected { hash: block.hash(), error });
+            }
+        }
+    }
+
+    if !report.disconnected.is_empty() {
+        log::info!(
+            "Reorganized chain at height {}: {} blocks disconnected, {} connected",
+            fork_height, report.disconnected.len(), report.connected.len()
+        );
+        let record = ReorgRecord {
+            old_tip: old_tip.best_block_hash.into(),
+            old_height: old_tip.height,
+            new_tip: target.into(),
+            new_height: db.get_height()?,
+            fork_height,
+            depth: report.disconnected.len() as u64,
+            connected: report.connected.len() as u64,
+            started_at,
+            finished_at: unix_time(),
+        };
+        db.record_reorg(&record)?;
+        pipeline.record_reorg(&record);
+    }
+    Ok(report)
+}
+
+/// Marca un block come invalido e, se è nella chain attiva, scollega il
+/// block e i suoi discendenti
+///
+/// I discendenti scollegati vengono marcati anch'essi. Ritorna gli hash
+/// dei block scollegati, dal vecchio tip in giù. Lo scollegamento viene
+/// registrato come riorganizzazione ma, voluto dall'operatore, non fa
+/// scattare l'allarme.
+pub fn invalidate_block(db: &BlockchainDB, hash: [u8; 32], reason: &str) -> Result<Vec<[u8; 32]>, ReorgError> {
+    let block = db.get_block(&hash)?.ok_or(ReorgError::UnknownBlock(hash))?;
+    let height = block.header.height;
+    if height == 0 {
+        return Err(ReorgError::Genesis);
+    }
+    db.mark_block_invalid(&hash, &InvalidBlock {
+        height,
+        previous_hash: block.header.previous_hash,
+        reason: reason.to_string(),
+    })?;
+
+    let mut disconnected = Vec::new();
+    if active_hash_at(db, height)? != Some(hash) {
+        return Ok(disconnected);
+    }
+    let started_at = unix_time();
+    let old_tip = db.get_metadata()?;
+    while db.get_height()? >= height {
+        let block = db.disconnect_tip()?;
+        let disconnected_hash = block.hash();
+        if disconnected_hash != hash {
+            db.mark_block_invalid(&disconnected_hash, &InvalidBlock {
+                height: block.header.height,
+                previous_hash: block.header.previous_hash,
+                reason: format!("descends from invalid block {}", hex::encode(hash)),
+            })?;
+        }
+        disconnected.push(disconnected_hash);
+    }
+    log::info!("Invalidated block {}: {} blocks disconnected", hex::encode(hash), disconnected.len());
+    db.record_reorg(&ReorgRecord {
+        old_tip: old_tip.best_block_hash.into(),
+        old_height: old_tip.height,
+        new_tip: block.header.previous_hash.into(),
+        new_height: height - 1,
+        fork_height: height - 1,
+        depth: disconnected.len() as u64,
+        connected: 0,
+        started_at,
+        finished_at: unix_time(),
+    })?;
+    Ok(disconnected)
+}
+
+/// Attiva il ramo validato con più lavoro cumulativo, se supera la chain attiva
+///
+/// Un ramo rifiutato dalla pipeline resta marcato come invalido e si passa
+/// al candidato successivo.
+pub fn activate_best_chain(db: &BlockchainDB, pipeline: &mut BlockPipeline) -> Result<ReorgReport, ReorgError> {
+    loop {
+        let headers = HeaderCache::load(db)?;
+        let Some(best) = headers.best_valid_tip() else {
+            return Ok(ReorgReport::default());
+        };
+        let active_work = headers.tip().map(|(_, entry)| entry.chainwork);
+        if best.status == TipStatus::Active || active_work.is_some_and(|work| best.chainwork <= work) {
+            return Ok(ReorgReport::default());
+        }
+
+        match activate_chain(db, pipeline, best.hash) {
+            Err(ReorgError::Rejected { error: PipelineError::Invalid { error, .. }, .. })
+                if error.invalidates_hash() => {}
+            Err(ReorgError::Rejected { error: PipelineError::KnownInvalid { .. }, .. }) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Hash del block della chain attiva a una data altezza
+fn active_hash_at(db: &BlockchainDB, height: u64) -> Result<Option<[u8; 32]>, StorageError> {
+    if height > db.get_height()? {
+        return Ok(None);
+    }
+    Ok(db.get_header_by_height(height)?.map(|header| header.hash()))
+}
+
+/// Ora corrente in secondi Unix
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+/// Errori della riorganizzazione
+#[derive(Debug, thiserror::Error)]
+pub enum ReorgError {
+    #[error("Block {} is not stored", hex::encode(.0))]
+    UnknownBlock([u8; 32]),
+
+    #[error("The genesis block cannot be invalidated")]
+    Genesis,
+
+    #[error("Block {} rejected during reorg: {error}", hex::encode(hash))]
+    Rejected { hash: [u8; 32], error: PipelineError },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::block_subsidy;
+    use crate::{Block, BlockValidator, ChainParams, OutPoint, Transaction};
+    use tempfile::TempDir;
+
+    fn build_chain(parent: &Block, count: u64, miner: &[u8]) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..count {
+            let previous = blocks.last().unwrap_or(parent);
+            let height = previous.header.height + 1;
+            let coinbase = Transaction::coinbase(miner, height, block_subsidy(height));
+            blocks.push(Block::new(previous.hash(), vec![coinbase], parent.header.bits, height));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_invalidate_and_reorg() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+
+        let main = build_chain(&genesis, 3, b"miner");
+        for block in &main {
+            pipeline.process(block, &db).unwrap();
+        }
+
+        // Il block 2 e il suo discendente escono dalla chain attiva
+        let disconnected = invalidate_block(&db, main[1].hash(), "test").unwrap();
+        assert_eq!(disconnected, vec![main[2].hash(), main[1].hash()]);
+        assert!(db.is_block_invalid(&main[2].hash()).unwrap());
+        assert_eq!(db.get_best_block_hash().unwrap(), main[0].hash());
+        let coinbase = OutPoint::new(main[1].transactions[0].hash(), 0);
+        assert!(db.get_utxo(&coinbase).unwrap().is_none());
+        assert!(activate_best_chain(&db, &mut pipeline).unwrap().disconnected.is_empty());
+
+        let fork = build_chain(&main[0], 3, b"other");
+        for block in &fork {
+            pipeline.process(block, &db).unwrap();
+        }
+
+        // Il ramo riconsiderato ha meno lavoro: si attiva solo esplicitamente
+        db.clear_invalid_block(&main[1].hash()).unwrap();
+        assert!(activate_best_chain(&db, &mut pipeline).unwrap().connected.is_empty());
+        let report = activate_chain(&db, &mut pipeline, main[2].hash()).unwrap();
+        assert_eq!(report.disconnected.len(), 3);
+        assert_eq!(report.connected.len(), 2);
+        assert_eq!(db.get_best_block_hash().unwrap(), main[2].hash());
+        assert!(db.get_utxo(&coinbase).unwrap().is_some());
+
+        let report = activate_best_chain(&db, &mut pipeline).unwrap();
+        assert_eq!(report.connected.len(), 3);
+        assert_eq!(db.get_best_block_hash().unwrap(), fork[2].hash());
+
+        // Registro dal più recente: due riorganizzazioni e l'invalidazione
+        let reorgs = db.get_reorgs(10).unwrap();
+        assert_eq!(reorgs.len(), 3);
+        assert_eq!((reorgs[0].old_tip, reorgs[0].new_tip), (main[2].block_hash(), fork[2].block_hash()));
+        assert_eq!((reorgs[0].depth, reorgs[0].connected, reorgs[0].fork_height), (2, 3, 1));
+        assert_eq!((reorgs[2].depth, reorgs[2].new_tip), (2, main[0].block_hash()));
+        assert_eq!(db.get_reorgs(1).unwrap(), reorgs[..1]);
+    }
+
+    #[test]
+    fn test_deep_reorg_alarm() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let alarms = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let counter = Arc::clone(&alarms);
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()))
+            .with_reorg_alarm(ReorgAlarm::new(3).with_hook(move |record| {
+                counter.fetch_add(record.depth, std::sync::atomic::Ordering::SeqCst);
+            }));
+
+        // Rami salvati e scollegati: attivi genesis, main[0..2] e shallow
+        let deep = build_chain(&genesis, 4, b"third");
+        let main = build_chain(&genesis, 3, b"miner");
+        let shallow = build_chain(&main[1], 2, b"other");
+        for block in &deep {
+            db.store_block(block).unwrap();
+        }
+        for _ in &deep {
+            db.disconnect_tip().unwrap();
+        }
+        for block in &main {
+            db.store_block(block).unwrap();
+        }
+        db.disconnect_tip().unwrap();
+        for block in &shallow {
+            db.store_block(block).unwrap();
+        }
+
+        // Profondità 2: registrata senza allarme
+        activate_chain(&db, &mut pipeline, main[2].hash()).unwrap();
+        assert_eq!(alarms.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        activate_chain(&db, &mut pipeline, deep[3].hash()).unwrap();
+        assert_eq!(alarms.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(pipeline.metrics().reorgs, 2);
+        assert_eq!(pipeline.metrics().deep_reorgs, 1);
+        assert_eq!(pipeline.metrics().max_reorg_depth, 3);
+    }
+
+    #[test]
+    fn test_rejected_branch_is_rolled_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+        let mut pipeline = BlockPipeline::new(BlockValidator::new(ChainParams::mainnet()));
+
+        // Un block invalido salvato senza validazione e poi scollegato
+        let greedy = Transaction::coinbase(b"miner", 1, block_subsidy(1) + 1);
+        let bad = Block::new(genesis.hash(), vec![greedy], genesis.header.bits, 1);
+        db.store_block(&bad).unwrap();
+        assert_eq!(db.disconnect_tip().unwrap().hash(), bad.hash());
+        assert!(matches!(db.disconnect_tip(), Err(StorageError::InvalidData(_))));
+
+        let main = build_chain(&genesis, 1, b"miner");
+        pipeline.process(&main[0], &db).unwrap();
+
+        assert!(matches!(
+            activate_chain(&db, &mut pipeline, bad.hash()),
+            Err(ReorgError::Rejected { .. })
+        ));
+        assert_eq!(db.get_best_block_hash().unwrap(), main[0].hash());
+        assert!(db.is_block_invalid(&bad.hash()).unwrap());
+        assert!(matches!(invalidate_block(&db, genesis.hash(), "test"), Err(ReorgError::Genesis)));
+    }
+}
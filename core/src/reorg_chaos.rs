@@ -0,0 +1,244 @@
+//! Scenario di riorganizzazioni ripetute con la mempool del nodo
+//!
+//! Due rami concorrenti sopra lo stesso block di funding: il ramo A
+//! conferma una catena di pagamenti, il ramo B (più lungo) conferma una
+//! parte delle stesse transazioni, una double-spend di un pagamento di A e
+//! una transazione che su A era ancora in pool. Il nodo passa da un ramo
+//! all'altro più volte, aggiornando la pool come farebbe dopo ogni reorg.
+//!
+//! Alla fine pool, UTXO set e saldi del wallet devono coincidere con quelli
+//! di un nodo che ha visto solo il ramo vincente.
+
+use crate::mempool::Mempool;
+use crate::params::ChainParams;
+use crate::pipeline::BlockPipeline;
+use crate::reorg::{activate_chain, invalidate_block};
+use crate::storage::{BlockchainDB, CancellationToken};
+use crate::validation::{block_subsidy, BlockValidator};
+use crate::{anyone_can_spend, Block, OutPoint, Transaction, TxInput, TxOutput, MIN_TX_FEE};
+use std::collections::{BTreeMap, HashSet};
+use tempfile::TempDir;
+
+/// Valore di ogni output della transazione di funding
+const COIN_VALUE: u64 = 1_000_000;
+
+/// Indirizzi del wallet
+const WALLET: [&[u8]; 4] = [b"alice", b"bob", b"carol", b"dave"];
+
+/// Nodo con la sua pool, sopra genesis e block di funding
+struct Node {
+    db: BlockchainDB,
+    pipeline: BlockPipeline,
+    pool: Mempool,
+    _temp_dir: TempDir,
+}
+
+impl Node {
+    fn new(funding: &Block) -> Self {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        // Il funding spende un outpoint inesistente: salvato senza validazione
+        db.store_block(funding).unwrap();
+
+        let mut pool = Mempool::new();
+        pool.set_min_fee(MIN_TX_FEE);
+        Self {
+            db,
+            pipeline: BlockPipeline::new(BlockValidator::new(ChainParams::regtest())),
+            pool,
+            _temp_dir: temp_dir,
+        }
+    }
+
+    fn submit(&mut self, tx: &Transaction) {
+        let height = self.db.get_height().unwrap();
+        self.pool.add(tx.clone(), height, self.pipeline.validator(), &self.db).unwrap();
+    }
+
+    /// Connette un block sopra il tip, come alla ricezione dalla rete
+    fn connect(&mut self, block: &Block) {
+        self.pipeline.process(block, &self.db).unwrap();
+        self.pool.remove_for_block(block);
+    }
+
+    /// Aggiorna la pool dopo un reorg che ha scollegato `disconnected`
+    fn after_reorg(&mut self, disconnected: &[[u8; 32]]) -> crate::MempoolReorgStats {
+        let blocks: Vec<Block> = disconnected.iter().map(|hash| self.db.get_block(hash).unwrap().unwrap()).collect();
+        let stats = self.pool.update_for_reorg(&blocks, self.pipeline.validator(), &self.db).unwrap();
+        check_pool(&self.pool, &self.db);
+        stats
+    }
+
+    fn pool_txids(&self) -> HashSet<[u8; 32]> {
+        self.pool.entries().map(|entry| entry.tx.hash()).collect()
+    }
+
+    /// Saldo confermato e saldo con le transazioni in pool per indirizzo
+    fn balances(&self) -> BTreeMap<Vec<u8>, (u64, u64)> {
+        let mut balances = BTreeMap::new();
+        for address in WALLET {
+            let script = anyone_can_spend(address);
+            let confirmed: u64 = self.db
+                .scan_utxos(&CancellationToken::new(), |_, entry| entry.output.script_pubkey == script)
+                .unwrap()
+                .matches
+                .iter()
+                .map(|(_, entry)| entry.output.value.to_sat())
+                .sum();
+
+            let mut pending = confirmed as i128;
+            for entry in self.pool.entries() {
+                for output in entry.tx.outputs.iter().filter(|output| output.script_pubkey == script) {
+                    pending += output.value.to_sat() as i128;
+                }
+                for input in &entry.tx.inputs {
+                    let spent = self.resolve(&input.previous_output);
+                    if spent.script_pubkey == script {
+                        pending -= spent.value.to_sat() as i128;
+                    }
+                }
+            }
+            assert!(pending >= 0, "negative balance for {}", String::from_utf8_lossy(address));
+            balances.insert(address.to_vec(), (confirmed, pending as u64));
+        }
+        balances
+    }
+
+    /// Output speso da un input della pool: confermato o creato in pool
+    fn resolve(&self, outpoint: &OutPoint) -> TxOutput {
+        if let Some(entry) = self.db.get_utxo(outpoint).unwrap() {
+            return entry.output;
+        }
+        let parent = self.pool.get(&outpoint.txid).expect("input resolves in the pool");
+        parent.tx.outputs[outpoint.vout as usize].clone()
+    }
+}
+
+/// Ogni input della pool esiste e nessun outpoint è speso due volte
+fn check_pool(pool: &Mempool, db: &BlockchainDB) {
+    let mut reserved = HashSet::new();
+    for entry in pool.entries() {
+        for input in &entry.tx.inputs {
+            let outpoint = &input.previous_output;
+            assert!(reserved.insert(outpoint.clone()), "outpoint {:?} spent twice", outpoint);
+            let in_pool = pool
+                .get(&outpoint.txid)
+                .is_some_and(|parent| (outpoint.vout as usize) < parent.tx.outputs.len());
+            assert!(in_pool || db.get_utxo(outpoint).unwrap().is_some(), "orphan input {:?}", outpoint);
+        }
+    }
+}
+
+/// Transazione che sposta `outpoint` di valore `value` su `address` pagando `MIN_TX_FEE`
+fn pay(outpoint: OutPoint, value: u64, address: &[u8]) -> Transaction {
+    Transaction::new(
+        vec![TxInput::new(outpoint, vec![])],
+        vec![TxOutput::to_address(value - MIN_TX_FEE, &anyone_can_spend(address))],
+        0,
+    )
+}
+
+fn output(tx: &Transaction) -> (OutPoint, u64) {
+    (OutPoint::new(tx.hash(), 0), tx.outputs[0].value.to_sat())
+}
+
+fn mine(parent: &Block, txs: &[&Transaction], miner: &[u8]) -> Block {
+    let height = parent.header.height + 1;
+    let mut transactions = vec![Transaction::coinbase(miner, height, block_subsidy(height))];
+    transactions.extend(txs.iter().map(|tx| (*tx).clone()));
+    Block::new(parent.hash(), transactions, parent.header.bits, height)
+}
+
+#[test]
+fn test_reorg_back_and_forth_keeps_mempool_consistent() {
+    let genesis = Block::genesis();
+    let funding_tx = Transaction::new(
+        vec![TxInput::new(OutPoint::new([0xfe; 32], 0), vec![])],
+        (0..4).map(|_| TxOutput::to_address(COIN_VALUE, &anyone_can_spend(b"alice"))).collect(),
+        0,
+    );
+    let funding = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 0), funding_tx.clone()],
+        genesis.header.bits, 1);
+    let coin = |vout| (OutPoint::new(funding_tx.hash(), vout), COIN_VALUE);
+
+    // Pagamenti confermati su A: t3 spende t1, t4 finisce nel secondo block
+    let pay_coin = |(outpoint, value): (OutPoint, u64), address: &[u8]| pay(outpoint, value, address);
+    let t1 = pay_coin(coin(0), b"bob");
+    let t2 = pay_coin(coin(1), b"carol");
+    let t3 = pay_coin(output(&t1), b"carol");
+    let t4 = pay_coin(coin(3), b"carol");
+    // In pool mentre A è attivo
+    let p1 = pay_coin(coin(2), b"bob");
+    let p2 = pay_coin(output(&t3), b"alice");
+    let p3 = pay_coin(output(&t4), b"bob");
+    // Su B: double-spend di t1 e conferma di p1
+    let d1 = pay_coin(coin(0), b"dave");
+
+    let a2 = mine(&funding, &[&t1, &t2], b"miner-a");
+    let a3 = mine(&a2, &[&t3, &t4], b"miner-a");
+    let b2 = mine(&funding, &[&t2, &d1], b"miner-b");
+    let b3 = mine(&b2, &[&p1], b"miner-b");
+    let b4 = mine(&b3, &[], b"miner-b");
+
+    let mut node = Node::new(&funding);
+    node.connect(&a2);
+    node.connect(&a3);
+    for tx in [&p1, &p2, &p3] {
+        node.submit(tx);
+    }
+    let on_a = node.balances();
+
+    // A -> B: l'operatore invalida A, il nodo mina B con la pool tornata indietro
+    let disconnected = invalidate_block(&node.db, a2.hash(), "test").unwrap();
+    let stats = node.after_reorg(&disconnected);
+    assert_eq!((stats.returned, stats.dropped, stats.evicted), (4, 2, 0));
+    assert_eq!(node.pool.len(), 7);
+    for block in [&b2, &b3, &b4] {
+        node.connect(block);
+    }
+    // d1 espelle t1 e i suoi discendenti, p1 e t2 sono confermate
+    assert_eq!(node.pool_txids(), HashSet::from([t4.hash(), p3.hash()]));
+    check_pool(&node.pool, &node.db);
+
+    // B -> A: le transazioni di B non confermate da A tornano in pool
+    node.db.clear_invalid_block(&a2.hash()).unwrap();
+    let report = activate_chain(&node.db, &mut node.pipeline, a3.hash()).unwrap();
+    assert_eq!(report.disconnected, vec![b4.hash(), b3.hash(), b2.hash()]);
+    let stats = node.after_reorg(&report.disconnected);
+    // p1 torna; d1 è in conflitto con t1, t2 è già confermata; t4 è confermata da A
+    assert_eq!((stats.returned, stats.dropped, stats.evicted), (1, 5, 1));
+    assert_eq!(node.pool_txids(), HashSet::from([p1.hash(), p3.hash()]));
+    // p2 è stata espulsa sul ramo B: rispetto al primo passaggio su A manca solo lei
+    let mut expected = on_a.clone();
+    let (alice, carol) = (on_a[&b"alice".to_vec()], on_a[&b"carol".to_vec()]);
+    expected.insert(b"alice".to_vec(), (alice.0, alice.1 - p2.outputs[0].value.to_sat()));
+    expected.insert(b"carol".to_vec(), (carol.0, carol.1 + t3.outputs[0].value.to_sat()));
+    assert_eq!(node.balances(), expected);
+
+    // A -> B ancora: t1 e t3 restano fuori, t4 torna sotto p3
+    let report = activate_chain(&node.db, &mut node.pipeline, b4.hash()).unwrap();
+    let stats = node.after_reorg(&report.disconnected);
+    assert_eq!((stats.returned, stats.dropped, stats.evicted), (1, 5, 1));
+    assert_eq!(node.pool_txids(), HashSet::from([t4.hash(), p3.hash()]));
+
+    // Stesso stato di un nodo che ha visto solo B e ha ricevuto le stesse transazioni
+    let mut reference = Node::new(&funding);
+    for block in [&b2, &b3, &b4] {
+        reference.connect(block);
+    }
+    reference.submit(&t4);
+    reference.submit(&p3);
+    let cancel = CancellationToken::new();
+    assert_eq!(
+        node.db.utxo_set_stats(&cancel, |_| {}).unwrap().hash,
+        reference.db.utxo_set_stats(&cancel, |_| {}).unwrap().hash,
+    );
+    assert_eq!(node.pool_txids(), reference.pool_txids());
+    assert_eq!(node.pool.total_size(), reference.pool.total_size());
+    assert_eq!(node.balances(), reference.balances());
+
+    let balances = node.balances();
+    assert_eq!(balances[&b"dave".to_vec()], (COIN_VALUE - MIN_TX_FEE, COIN_VALUE - MIN_TX_FEE));
+    assert_eq!(balances[&b"bob".to_vec()].1, p1.outputs[0].value.to_sat() + p3.outputs[0].value.to_sat());
+}
@@ -0,0 +1,279 @@
+//! Riesecuzione deterministica dei block salvati per il debug del consenso
+//!
+//! I block della best chain di un database vengono riapplicati su un
+//! database separato: quelli sotto l'altezza iniziale sono connessi senza
+//! rivalidarli, quelli dell'intervallo passano dalla pipeline di
+//! validazione come nel nodo. Dopo ogni block l'app hash ottenuto viene
+//! confrontato con quello salvato; se l'intervallo arriva al tip viene
+//! confrontato anche il commitment del UTXO set. Il database di origine
+//! non viene mai modificato.
+
+use crate::pipeline::{BlockPipeline, PipelineError};
+use crate::storage::{BlockchainDB, CancellationToken, StorageError};
+use crate::validation::BlockValidator;
+use crate::Block;
+use std::time::{Duration, Instant};
+
+/// Block riapplicato
+#[derive(Debug, Clone)]
+pub struct ReplayedBlock {
+    /// Altezza del block
+    pub height: u64,
+    /// App hash salvato (hash del block)
+    pub app_hash: [u8; 32],
+    /// Transazioni del block
+    pub transactions: usize,
+    /// Durata della validazione e connessione
+    pub elapsed: Duration,
+}
+
+/// Prima differenza trovata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Il block salvato non supera più la validazione
+    Rejected { height: u64, app_hash: [u8; 32], reason: String },
+    /// Il tip dopo la riesecuzione non è il block salvato
+    AppHash { height: u64, expected: [u8; 32], actual: [u8; 32] },
+    /// Il UTXO set ricostruito differisce da quello del database
+    UtxoCommitment { height: u64, expected: [u8; 32], actual: [u8; 32] },
+}
+
+impl Divergence {
+    /// Altezza a cui la chain riapplicata diverge
+    pub fn height(&self) -> u64 {
+        match self {
+            Self::Rejected { height, .. }
+            | Self::AppHash { height, .. }
+            | Self::UtxoCommitment { height, .. } => *height,
+        }
+    }
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rejected { height, app_hash, reason } => {
+                write!(f, "block {} at height {} rejected: {}", hex::encode(app_hash), height, reason)
+            }
+            Self::AppHash { height, expected, actual } => write!(
+                f,
+                "app hash at height {} is {}, stored {}",
+                height, hex::encode(actual), hex::encode(expected)
+            ),
+            Self::UtxoCommitment { height, expected, actual } => write!(
+                f,
+                "UTXO commitment at height {} is {}, stored {}",
+                height, hex::encode(actual), hex::encode(expected)
+            ),
+        }
+    }
+}
+
+/// Esito di una riesecuzione
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    /// Prima altezza rivalidata
+    pub from: u64,
+    /// Ultima altezza richiesta
+    pub to: u64,
+    /// Block rivalidati senza differenze
+    pub blocks: u64,
+    /// Transazioni dei block rivalidati
+    pub transactions: u64,
+    /// Commitment del UTXO set ricostruito, se l'intervallo arriva al tip
+    pub utxo_commitment: Option<[u8; 32]>,
+    /// Prima differenza, None se la chain riapplicata coincide
+    pub divergence: Option<Divergence>,
+    /// Durata totale, compresi i block connessi senza validazione
+    pub elapsed: Duration,
+}
+
+/// Riesegue un intervallo di block di `source` su un database vuoto
+pub struct Replayer<'a> {
+    /// Database con i block salvati (solo lettura)
+    source: &'a BlockchainDB,
+    /// Database vuoto su cui viene ricostruito lo stato
+    target: &'a BlockchainDB,
+    /// Pipeline usata per i block dell'intervallo
+    pipeline: BlockPipeline,
+}
+
+impl<'a> Replayer<'a> {
+    /// Crea un replayer da `source` verso `target`
+    pub fn new(source: &'a BlockchainDB, target: &'a BlockchainDB, validator: BlockValidator) -> Self {
+        Self {
+            source,
+            target,
+            pipeline: BlockPipeline::new(validator),
+        }
+    }
+
+    /// Riesegue i block da `from` a `to` inclusi, fermandosi alla prima differenza
+    ///
+    /// `on_block` riceve ogni block dell'intervallo rivalidato senza differenze.
+    pub fn run<F>(&mut self, from: u64, to: u64, mut on_block: F) -> Result<ReplayReport, ReplayError>
+    where
+        F: FnMut(&ReplayedBlock),
+    {
+        let start = Instant::now();
+        let tip = self.source.get_height()?;
+        let from = from.max(1);
+        if from > to || to > tip {
+            return Err(ReplayError::InvalidRange { from, to, tip });
+        }
+        if self.target.get_best_block_hash()? != [0; 32] {
+            return Err(ReplayError::TargetNotEmpty);
+        }
+
+        // Stato fino al parent del primo block, fidandosi dei block salvati
+        self.target.initialize_with_genesis(&self.stored_block(0)?)?;
+        for height in 1..from {
+            self.target.store_block(&self.stored_block(height)?)?;
+        }
+
+        let mut report = ReplayReport {
+            from,
+            to,
+            blocks: 0,
+            transactions: 0,
+            utxo_commitment: None,
+            divergence: None,
+            elapsed: Duration::ZERO,
+        };
+        for height in from..=to {
+            let block = self.stored_block(height)?;
+            let app_hash = block.hash();
+            let block_start = Instant::now();
+
+            let divergence = match self.pipeline.process(&block, self.target) {
+                Ok(_) => {
+                    let actual = self.target.get_best_block_hash()?;
+                    (actual != app_hash).then_some(Divergence::AppHash { height, expected: app_hash, actual })
+                }
+                Err(PipelineError::Storage { error, .. }) => return Err(error.into()),
+                Err(e) => Some(Divergence::Rejected { height, app_hash, reason: e.to_string() }),
+            };
+            if divergence.is_some() {
+                report.divergence = divergence;
+                report.elapsed = start.elapsed();
+                return Ok(report);
+            }
+
+            report.blocks += 1;
+            report.transactions += block.transactions.len() as u64;
+            on_block(&ReplayedBlock {
+                height,
+                app_hash,
+                transactions: block.transactions.len(),
+                elapsed: block_start.elapsed(),
+            });
+        }
+
+        // Il UTXO set salvato è quello del tip: confrontabile solo se l'intervallo ci arriva
+        if to == tip {
+            let cancel = CancellationToken::new();
+            let expected = self.source.utxo_set_stats(&cancel, |_| {})?.hash;
+            let actual = self.target.utxo_set_stats(&cancel, |_| {})?.hash;
+            report.utxo_commitment = Some(actual);
+            if actual != expected {
+                report.divergence = Some(Divergence::UtxoCommitment { height: to, expected, actual });
+            }
+        }
+        report.elapsed = start.elapsed();
+        Ok(report)
+    }
+
+    fn stored_block(&self, height: u64) -> Result<Block, ReplayError> {
+        self.source.get_block_by_height(height)?.ok_or(ReplayError::MissingBlock(height))
+    }
+}
+
+/// Errori della riesecuzione
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Invalid replay range {from}..={to} (stored tip at height {tip})")]
+    InvalidRange { from: u64, to: u64, tip: u64 },
+
+    #[error("Replay database already contains a chain")]
+    TargetNotEmpty,
+
+    #[error("No stored block at height {0}")]
+    MissingBlock(u64),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::ChainParams;
+    use crate::validation::block_subsidy;
+    use crate::Transaction;
+    use tempfile::TempDir;
+
+    fn build_chain(db: &BlockchainDB, blocks: u64) -> Vec<Block> {
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let mut chain = vec![genesis];
+        for height in 1..=blocks {
+            let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+            let block = Block::new(chain.last().unwrap().hash(), vec![coinbase], 0x1d00ffff, height);
+            db.store_block(&block).unwrap();
+            chain.push(block);
+        }
+        chain
+    }
+
+    #[test]
+    fn test_replay_matches_stored_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = BlockchainDB::open(temp_dir.path().join("source")).unwrap();
+        let target = BlockchainDB::open(temp_dir.path().join("target")).unwrap();
+        let chain = build_chain(&source, 8);
+
+        let mut replayed = Vec::new();
+        let report = Replayer::new(&source, &target, BlockValidator::new(ChainParams::regtest()))
+            .run(5, 8, |block| replayed.push(block.height))
+            .unwrap();
+
+        assert_eq!(report.divergence, None);
+        assert_eq!((report.blocks, report.transactions), (4, 4));
+        assert_eq!(replayed, vec![5, 6, 7, 8]);
+        assert_eq!(target.get_best_block_hash().unwrap(), chain[8].hash());
+        let stats = source.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap();
+        assert_eq!(report.utxo_commitment, Some(stats.hash));
+
+        // Il database di destinazione deve essere vuoto
+        let result = Replayer::new(&source, &target, BlockValidator::new(ChainParams::regtest())).run(1, 2, |_| {});
+        assert!(matches!(result, Err(ReplayError::TargetNotEmpty)));
+        let result = Replayer::new(&source, &target, BlockValidator::new(ChainParams::regtest())).run(3, 9, |_| {});
+        assert!(matches!(result, Err(ReplayError::InvalidRange { tip: 8, .. })));
+    }
+
+    #[test]
+    fn test_replay_reports_first_divergence() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = BlockchainDB::open(temp_dir.path().join("source")).unwrap();
+        let target = BlockchainDB::open(temp_dir.path().join("target")).unwrap();
+        let chain = build_chain(&source, 3);
+
+        // Un block accettato allora ma che viola le regole attuali
+        let greedy = Transaction::coinbase(b"miner", 4, block_subsidy(4) * 2);
+        let invalid = Block::new(chain[3].hash(), vec![greedy], 0x1d00ffff, 4);
+        source.store_block(&invalid).unwrap();
+        source.store_block(&Block::new(invalid.hash(), vec![Transaction::coinbase(b"miner", 5, 1)], 0x1d00ffff, 5))
+            .unwrap();
+
+        let report = Replayer::new(&source, &target, BlockValidator::new(ChainParams::regtest()))
+            .run(2, 5, |_| {})
+            .unwrap();
+        let divergence = report.divergence.unwrap();
+        assert!(matches!(divergence, Divergence::Rejected { height: 4, .. }));
+        assert_eq!(divergence.height(), 4);
+        assert_eq!(report.blocks, 2);
+        assert_eq!(report.utxo_commitment, None);
+        assert_eq!(target.get_best_block_hash().unwrap(), chain[3].hash());
+    }
+}
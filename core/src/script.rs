@@ -0,0 +1,345 @@
+//! Script standard (opcodes e template) per i locking script degli output
+
+use sha2::{Digest, Sha256};
+
+/// Opcodes usati dai template standard
+pub mod opcodes {
+    /// Push di un vettore vuoto / numero 0
+    pub const OP_0: u8 = 0x00;
+    /// Push del prossimo byte come lunghezza
+    pub const OP_PUSHDATA1: u8 = 0x4c;
+    /// Push dei prossimi 2 bytes come lunghezza
+    pub const OP_PUSHDATA2: u8 = 0x4d;
+    /// Push dei prossimi 4 bytes come lunghezza
+    pub const OP_PUSHDATA4: u8 = 0x4e;
+    /// Numero -1
+    pub const OP_1NEGATE: u8 = 0x4f;
+    /// Numero 1 (OP_2..OP_16 seguono in sequenza)
+    pub const OP_1: u8 = 0x51;
+    /// Numero 16
+    pub const OP_16: u8 = 0x60;
+    /// Nessuna operazione
+    pub const OP_NOP: u8 = 0x61;
+    /// Fallisce se l'elemento in cima allo stack è falso
+    pub const OP_VERIFY: u8 = 0x69;
+    /// Output non spendibile (dati)
+    pub const OP_RETURN: u8 = 0x6a;
+    /// Rimuove l'elemento in cima allo stack
+    pub const OP_DROP: u8 = 0x75;
+    /// Duplica l'elemento in cima allo stack
+    pub const OP_DUP: u8 = 0x76;
+    /// Uguaglianza
+    pub const OP_EQUAL: u8 = 0x87;
+    /// Uguaglianza seguita da verify
+    pub const OP_EQUALVERIFY: u8 = 0x88;
+    /// SHA256(x)
+    pub const OP_SHA256: u8 = 0xa8;
+    /// RIPEMD160(SHA256(x))
+    pub const OP_HASH160: u8 = 0xa9;
+    /// Verifica firma
+    pub const OP_CHECKSIG: u8 = 0xac;
+    /// Verifica firma seguita da verify
+    pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+    /// Verifica m-of-n firme
+    pub const OP_CHECKMULTISIG: u8 = 0xae;
+    /// Verifica m-of-n firme seguita da verify
+    pub const OP_CHECKMULTISIGVERIFY: u8 = 0xaf;
+    /// Fallisce se la transazione non ricrea l'output di stato (vedi [`crate::state`])
+    pub const OP_CHECKSTATEVERIFY: u8 = 0xb3;
+}
+
+use opcodes::*;
+
+/// Dimensione massima di uno script in byte
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Numero massimo di chiavi in un multisig standard
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+/// Lunghezza di una chiave pubblica compressa
+pub const COMPRESSED_PUBKEY_LEN: usize = 33;
+
+/// RIPEMD160(SHA256(data)), usato per i pubkey hash
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    use ripemd::Ripemd160;
+    let sha = Sha256::digest(data);
+    Ripemd160::digest(sha).into()
+}
+
+/// Aggiunge un push dei dati allo script
+pub fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len if len < OP_PUSHDATA1 as usize => script.push(len as u8),
+        len if len <= 0xff => {
+            script.push(OP_PUSHDATA1);
+            script.push(len as u8);
+        }
+        len => {
+            script.push(OP_PUSHDATA2);
+            script.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+    }
+    script.extend_from_slice(data);
+}
+
+/// Script che chiunque sblocca con uno script_sig vuoto (`OP_1 <tag> OP_DROP`)
+///
+/// `tag` distingue gli output tra loro; utile su regtest e nei test, dove
+/// gli output non hanno un proprietario.
+pub fn anyone_can_spend(tag: &[u8]) -> Vec<u8> {
+    let mut script = vec![OP_1];
+    push_data(&mut script, tag);
+    script.push(OP_DROP);
+    script
+}
+
+/// Nome di un opcode non push (es. `OP_DUP`), None se sconosciuto
+pub fn opcode_name(opcode: u8) -> Option<&'static str> {
+    Some(match opcode {
+        OP_NOP => "OP_NOP",
+        OP_VERIFY => "OP_VERIFY",
+        OP_RETURN => "OP_RETURN",
+        OP_DROP => "OP_DROP",
+        OP_DUP => "OP_DUP",
+        OP_EQUAL => "OP_EQUAL",
+        OP_EQUALVERIFY => "OP_EQUALVERIFY",
+        OP_SHA256 => "OP_SHA256",
+        OP_HASH160 => "OP_HASH160",
+        OP_CHECKSIG => "OP_CHECKSIG",
+        OP_CHECKSIGVERIFY => "OP_CHECKSIGVERIFY",
+        OP_CHECKMULTISIG => "OP_CHECKMULTISIG",
+        OP_CHECKMULTISIGVERIFY => "OP_CHECKMULTISIGVERIFY",
+        OP_CHECKSTATEVERIFY => "OP_CHECKSTATEVERIFY",
+        _ => return None,
+    })
+}
+
+/// Script in forma leggibile, come l'`asm` di Bitcoin Core
+///
+/// I dati spinti sono in hex, i numeri piccoli in decimale e gli opcode
+/// sconosciuti `OP_UNKNOWN<0x..>`; un push troncato chiude con `[error]`.
+pub fn script_asm(script: &[u8]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut pc = 0;
+    while pc < script.len() {
+        let opcode = script[pc];
+        pc += 1;
+        let len = match opcode {
+            OP_0 => {
+                parts.push("0".to_string());
+                continue;
+            }
+            len if len < OP_PUSHDATA1 => len as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                // Lunghezza little-endian di 1, 2 o 4 bytes
+                let width = 1 << (opcode - OP_PUSHDATA1);
+                let Some(field) = script.get(pc..pc + width) else {
+                    parts.push("[error]".to_string());
+                    break;
+                };
+                pc += width;
+                field.iter().rev().fold(0usize, |len, byte| (len << 8) | *byte as usize)
+            }
+            OP_1NEGATE => {
+                parts.push("-1".to_string());
+                continue;
+            }
+            OP_1..=OP_16 => {
+                parts.push((opcode - OP_1 + 1).to_string());
+                continue;
+            }
+            _ => {
+                parts.push(opcode_name(opcode).map_or_else(|| format!("OP_UNKNOWN<0x{:02x}>", opcode), str::to_string));
+                continue;
+            }
+        };
+        match script.get(pc..).and_then(|rest| rest.get(..len)) {
+            Some(data) => {
+                parts.push(hex::encode(data));
+                pc += len;
+            }
+            None => {
+                parts.push("[error]".to_string());
+                break;
+            }
+        }
+    }
+    parts.join(" ")
+}
+
+/// Opcode per un numero piccolo (0-16)
+fn small_int(n: usize) -> u8 {
+    if n == 0 {
+        OP_0
+    } else {
+        OP_1 + (n as u8 - 1)
+    }
+}
+
+/// Template di locking script riconosciuti
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptTemplate {
+    /// `<pubkey> OP_CHECKSIG`
+    PayToPubkey(Vec<u8>),
+    /// `OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG`
+    PayToPubkeyHash([u8; 20]),
+    /// `<m> <pubkey>... <n> OP_CHECKMULTISIG`
+    Multisig { threshold: usize, pubkeys: Vec<Vec<u8>> },
+    /// Qualsiasi altro script
+    NonStandard,
+}
+
+impl ScriptTemplate {
+    /// Costruisce lo script `<pubkey> OP_CHECKSIG`
+    pub fn p2pk(pubkey: &[u8]) -> Vec<u8> {
+        let mut script = Vec::with_capacity(pubkey.len() + 2);
+        push_data(&mut script, pubkey);
+        script.push(OP_CHECKSIG);
+        script
+    }
+
+    /// Costruisce lo script pay-to-pubkey-hash
+    pub fn p2pkh(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+        let mut script = Vec::with_capacity(25);
+        script.extend_from_slice(&[OP_DUP, OP_HASH160]);
+        push_data(&mut script, pubkey_hash);
+        script.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+        script
+    }
+
+    /// Costruisce uno script multisig m-of-n
+    pub fn multisig(threshold: usize, pubkeys: &[Vec<u8>]) -> Result<Vec<u8>, ScriptError> {
+        if pubkeys.is_empty() || pubkeys.len() > MAX_MULTISIG_KEYS {
+            return Err(ScriptError::InvalidKeyCount(pubkeys.len()));
+        }
+        if threshold == 0 || threshold > pubkeys.len() {
+            return Err(ScriptError::InvalidThreshold { threshold, keys: pubkeys.len() });
+        }
+
+        let mut script = vec![small_int(threshold)];
+        for pubkey in pubkeys {
+            push_data(&mut script, pubkey);
+        }
+        script.push(small_int(pubkeys.len()));
+        script.push(OP_CHECKMULTISIG);
+        Ok(script)
+    }
+
+    /// Nome del template (come il `type` di Bitcoin Core)
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptTemplate::PayToPubkey(_) => "pubkey",
+            ScriptTemplate::PayToPubkeyHash(_) => "pubkeyhash",
+            ScriptTemplate::Multisig { .. } => "multisig",
+            ScriptTemplate::NonStandard => "nonstandard",
+        }
+    }
+
+    /// Riconosce il template di uno script
+    pub fn classify(script: &[u8]) -> Self {
+        let key_len = COMPRESSED_PUBKEY_LEN;
+
+        if script.len() == key_len + 2 && script[0] as usize == key_len && script[key_len + 1] == OP_CHECKSIG {
+            return ScriptTemplate::PayToPubkey(script[1..=key_len].to_vec());
+        }
+
+        if script.len() == 25
+            && script[..3] == [OP_DUP, OP_HASH160, 20]
+            && script[23..] == [OP_EQUALVERIFY, OP_CHECKSIG]
+        {
+            let mut hash = [0u8; 20];
+            hash.copy_from_slice(&script[3..23]);
+            return ScriptTemplate::PayToPubkeyHash(hash);
+        }
+
+        Self::classify_multisig(script).unwrap_or(ScriptTemplate::NonStandard)
+    }
+
+    fn classify_multisig(script: &[u8]) -> Option<Self> {
+        let (&last, rest) = script.split_last()?;
+        let (&n_op, body) = rest.split_last()?;
+        let (&m_op, mut keys) = body.split_first()?;
+        if last != OP_CHECKMULTISIG || !(OP_1..=OP_16).contains(&m_op) || !(OP_1..=OP_16).contains(&n_op) {
+            return None;
+        }
+
+        let mut pubkeys = Vec::new();
+        while let Some((&len, tail)) = keys.split_first() {
+            if len as usize != COMPRESSED_PUBKEY_LEN || tail.len() < COMPRESSED_PUBKEY_LEN {
+                return None;
+            }
+            pubkeys.push(tail[..COMPRESSED_PUBKEY_LEN].to_vec());
+            keys = &tail[COMPRESSED_PUBKEY_LEN..];
+        }
+
+        let threshold = (m_op - OP_1 + 1) as usize;
+        if pubkeys.len() != (n_op - OP_1 + 1) as usize || threshold > pubkeys.len() {
+            return None;
+        }
+        Some(ScriptTemplate::Multisig { threshold, pubkeys })
+    }
+}
+
+/// Errori di costruzione script
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    #[error("Invalid number of keys: {0}")]
+    InvalidKeyCount(usize),
+
+    #[error("Invalid threshold {threshold} for {keys} keys")]
+    InvalidThreshold { threshold: usize, keys: usize },
+
+    #[error("Invalid treasury share: {0} basis points")]
+    InvalidShare(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash160() {
+        // Vettore noto: hash160 della stringa vuota
+        assert_eq!(hex::encode(hash160(b"")), "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb");
+    }
+
+    #[test]
+    fn test_templates_roundtrip() {
+        let pubkey = vec![0x02; 33];
+        let other = vec![0x03; 33];
+
+        let p2pk = ScriptTemplate::p2pk(&pubkey);
+        assert_eq!(ScriptTemplate::classify(&p2pk), ScriptTemplate::PayToPubkey(pubkey.clone()));
+
+        let hash = hash160(&pubkey);
+        let p2pkh = ScriptTemplate::p2pkh(&hash);
+        assert_eq!(p2pkh.len(), 25);
+        assert_eq!(ScriptTemplate::classify(&p2pkh), ScriptTemplate::PayToPubkeyHash(hash));
+
+        let multi = ScriptTemplate::multisig(1, &[pubkey.clone(), other.clone()]).unwrap();
+        assert_eq!(
+            ScriptTemplate::classify(&multi),
+            ScriptTemplate::Multisig { threshold: 1, pubkeys: vec![pubkey, other] }
+        );
+
+        assert_eq!(ScriptTemplate::classify(b"miner"), ScriptTemplate::NonStandard);
+        assert!(ScriptTemplate::multisig(3, &[vec![0x02; 33]]).is_err());
+    }
+
+    #[test]
+    fn test_script_asm() {
+        let p2pkh = ScriptTemplate::p2pkh(&[0xab; 20]);
+        assert_eq!(script_asm(&p2pkh), format!("OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIG", "ab".repeat(20)));
+        let multi = ScriptTemplate::multisig(1, &[vec![0x02; 33]]).unwrap();
+        assert_eq!(script_asm(&multi), format!("1 {} 1 OP_CHECKMULTISIG", "02".repeat(33)));
+
+        let mut data = vec![OP_0, OP_1NEGATE, OP_16, 0xff];
+        push_data(&mut data, &[7; 80]);
+        assert_eq!(script_asm(&data), format!("0 -1 16 OP_UNKNOWN<0xff> {}", "07".repeat(80)));
+
+        // Push troncati
+        assert_eq!(script_asm(&[OP_RETURN, 5, 1, 2]), "OP_RETURN [error]");
+        assert_eq!(script_asm(&[OP_PUSHDATA2, 1]), "[error]");
+        assert_eq!(script_asm(&[]), "");
+    }
+}
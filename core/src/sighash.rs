@@ -0,0 +1,395 @@
+//! Digest firmato dagli input e firma degli output standard
+//!
+//! Gli input sono firmati sul digest legacy: la transazione con tutti gli
+//! script di sblocco vuoti, lo script speso al posto dello script di
+//! sblocco dell'input firmato e il sighash type in coda, con doppio
+//! SHA-256. Il sighash type sceglie quali parti della transazione la firma
+//! impegna:
+//!
+//! - `ALL`: tutti gli input e tutti gli output
+//! - `NONE`: nessun output; le sequence degli altri input sono azzerate
+//! - `SINGLE`: solo l'output con lo stesso indice dell'input; gli output
+//!   precedenti restano come segnaposto nulli
+//! - `ANYONECANPAY` (flag combinabile): solo l'input firmato, così altri
+//!   possono aggiungere input (es. crowdfunding)
+
+use crate::hash::sha256d;
+use crate::script::{hash160, push_data};
+use crate::{ScriptTemplate, Transaction, TxOutput};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signing};
+use std::fmt;
+use std::str::FromStr;
+
+/// Sighash type che impegna tutti gli input e gli output
+pub const SIGHASH_ALL: u8 = 0x01;
+/// Sighash type che non impegna nessun output
+pub const SIGHASH_NONE: u8 = 0x02;
+/// Sighash type che impegna solo l'output con l'indice dell'input
+pub const SIGHASH_SINGLE: u8 = 0x03;
+/// Flag che impegna solo l'input firmato
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// Output impegnati da una firma
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SighashOutputs {
+    /// Tutti gli output
+    #[default]
+    All,
+    /// Nessun output
+    None,
+    /// L'output con lo stesso indice dell'input
+    Single,
+}
+
+/// Sighash type di una firma: output impegnati e flag `ANYONECANPAY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SighashType {
+    /// Output impegnati
+    pub outputs: SighashOutputs,
+    /// Se la firma impegna solo il proprio input
+    pub anyone_can_pay: bool,
+}
+
+impl SighashType {
+    /// `SIGHASH_ALL`, il default
+    pub const ALL: Self = Self { outputs: SighashOutputs::All, anyone_can_pay: false };
+    /// `SIGHASH_NONE`
+    pub const NONE: Self = Self { outputs: SighashOutputs::None, anyone_can_pay: false };
+    /// `SIGHASH_SINGLE`
+    pub const SINGLE: Self = Self { outputs: SighashOutputs::Single, anyone_can_pay: false };
+
+    /// Stesso type con il flag `ANYONECANPAY`
+    pub fn anyone_can_pay(self) -> Self {
+        Self { anyone_can_pay: true, ..self }
+    }
+
+    /// Byte accodato alla firma
+    pub fn to_byte(self) -> u8 {
+        let base = match self.outputs {
+            SighashOutputs::All => SIGHASH_ALL,
+            SighashOutputs::None => SIGHASH_NONE,
+            SighashOutputs::Single => SIGHASH_SINGLE,
+        };
+        if self.anyone_can_pay {
+            base | SIGHASH_ANYONECANPAY
+        } else {
+            base
+        }
+    }
+
+    /// Sighash type di un byte di firma (None per valori non standard)
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        let outputs = match byte & !SIGHASH_ANYONECANPAY {
+            SIGHASH_ALL => SighashOutputs::All,
+            SIGHASH_NONE => SighashOutputs::None,
+            SIGHASH_SINGLE => SighashOutputs::Single,
+            _ => return None,
+        };
+        Some(Self { outputs, anyone_can_pay: byte & SIGHASH_ANYONECANPAY != 0 })
+    }
+}
+
+impl fmt::Display for SighashType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = match self.outputs {
+            SighashOutputs::All => "ALL",
+            SighashOutputs::None => "NONE",
+            SighashOutputs::Single => "SINGLE",
+        };
+        if self.anyone_can_pay {
+            write!(f, "{}|ANYONECANPAY", base)
+        } else {
+            f.write_str(base)
+        }
+    }
+}
+
+impl FromStr for SighashType {
+    type Err = SighashError;
+
+    /// Accetta i nomi di `signrawtransaction`: `ALL`, `NONE`, `SINGLE`,
+    /// eventualmente seguiti da `|ANYONECANPAY`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        let (base, anyone_can_pay) = match upper.strip_suffix("|ANYONECANPAY") {
+            Some(base) => (base, true),
+            None => (upper.as_str(), false),
+        };
+        let outputs = match base {
+            "ALL" => SighashOutputs::All,
+            "NONE" => SighashOutputs::None,
+            "SINGLE" => SighashOutputs::Single,
+            _ => return Err(SighashError::UnknownType(s.to_string())),
+        };
+        Ok(Self { outputs, anyone_can_pay })
+    }
+}
+
+/// Digest `SIGHASH_ALL` firmato dall'input `input_index` che spende `script_pubkey`
+pub fn signature_hash(tx: &Transaction, input_index: usize, script_pubkey: &[u8]) -> [u8; 32] {
+    signature_hash_with_type(tx, input_index, script_pubkey, SighashType::ALL)
+        .expect("SIGHASH_ALL commits to any input")
+}
+
+/// Digest firmato dall'input `input_index` con il sighash type dato
+///
+/// `SINGLE` richiede un output con l'indice dell'input: il digest costante
+/// che Bitcoin usa in quel caso renderebbe la firma riutilizzabile.
+pub fn signature_hash_with_type(
+    tx: &Transaction,
+    input_index: usize,
+    script_pubkey: &[u8],
+    sighash_type: SighashType,
+) -> Result<[u8; 32], SighashError> {
+    if sighash_type.anyone_can_pay && input_index >= tx.inputs.len() {
+        return Err(SighashError::InputOutOfRange { input_index, inputs: tx.inputs.len() });
+    }
+    let mut unsigned = tx.clone();
+    for (index, input) in unsigned.inputs.iter_mut().enumerate() {
+        if index == input_index {
+            input.script_sig = script_pubkey.to_vec();
+            continue;
+        }
+        input.script_sig = Vec::new();
+        // Con NONE e SINGLE gli altri firmatari possono aggiornare le proprie sequence
+        if sighash_type.outputs != SighashOutputs::All {
+            input.sequence = 0;
+        }
+    }
+
+    match sighash_type.outputs {
+        SighashOutputs::All => {}
+        SighashOutputs::None => unsigned.outputs.clear(),
+        SighashOutputs::Single => {
+            if input_index >= unsigned.outputs.len() {
+                return Err(SighashError::SingleWithoutOutput { input_index });
+            }
+            unsigned.outputs.truncate(input_index + 1);
+            for output in &mut unsigned.outputs[..input_index] {
+                *output = TxOutput::new(u64::MAX, [0; 32], Vec::new());
+            }
+        }
+    }
+    if sighash_type.anyone_can_pay {
+        unsigned.inputs = vec![unsigned.inputs.swap_remove(input_index)];
+    }
+
+    let mut bytes = bincode::serialize(&unsigned).expect("Failed to serialize transaction");
+    bytes.extend_from_slice(&(sighash_type.to_byte() as u32).to_le_bytes());
+
+    Ok(sha256d(&bytes))
+}
+
+/// Script di sblocco `SIGHASH_ALL` dell'input `input_index` che spende `script_pubkey`
+///
+/// Supporta P2PK (`<firma>`) e P2PKH (`<firma> <pubkey>`); None se lo
+/// script non è di uno di questi tipi o non appartiene alla chiave.
+pub fn sign_input<C: Signing>(
+    secp: &Secp256k1<C>,
+    secret_key: &SecretKey,
+    tx: &Transaction,
+    input_index: usize,
+    script_pubkey: &[u8],
+) -> Option<Vec<u8>> {
+    sign_input_with_type(secp, secret_key, tx, input_index, script_pubkey, SighashType::ALL)
+}
+
+/// Script di sblocco dell'input `input_index` firmato con il sighash type dato
+///
+/// Come [`sign_input`]; None anche se il sighash type non può firmare
+/// l'input (`SINGLE` senza output corrispondente).
+pub fn sign_input_with_type<C: Signing>(
+    secp: &Secp256k1<C>,
+    secret_key: &SecretKey,
+    tx: &Transaction,
+    input_index: usize,
+    script_pubkey: &[u8],
+    sighash_type: SighashType,
+) -> Option<Vec<u8>> {
+    let pubkey = PublicKey::from_secret_key(secp, secret_key).serialize();
+    let with_pubkey = match ScriptTemplate::classify(script_pubkey) {
+        ScriptTemplate::PayToPubkey(key) if key == pubkey => false,
+        ScriptTemplate::PayToPubkeyHash(hash) if hash == hash160(&pubkey) => true,
+        _ => return None,
+    };
+
+    let digest = signature_hash_with_type(tx, input_index, script_pubkey, sighash_type).ok()?;
+    let message = Message::from_slice(&digest).expect("Digest is 32 bytes");
+    let mut signature = secp.sign_ecdsa(&message, secret_key).serialize_der().to_vec();
+    signature.push(sighash_type.to_byte());
+
+    let mut script_sig = Vec::new();
+    push_data(&mut script_sig, &signature);
+    if with_pubkey {
+        push_data(&mut script_sig, &pubkey);
+    }
+    Some(script_sig)
+}
+
+/// Errori del calcolo del digest
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SighashError {
+    #[error("Input {input_index} out of range ({inputs} inputs)")]
+    InputOutOfRange { input_index: usize, inputs: usize },
+
+    #[error("SIGHASH_SINGLE input {input_index} has no corresponding output")]
+    SingleWithoutOutput { input_index: usize },
+
+    #[error("Unknown sighash type {0}")]
+    UnknownType(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Amount, OutPoint, TxInput, TxOutput};
+    use secp256k1::ecdsa::Signature;
+
+    #[test]
+    fn test_sign_p2pkh_input() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+        let script_pubkey = ScriptTemplate::p2pkh(&hash160(&pubkey.serialize()));
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([1; 32], 0), vec![])],
+            vec![TxOutput::to_address(40, b"alice")],
+            0,
+        );
+
+        // script_sig = <firma DER + sighash> <pubkey compressa>
+        let script_sig = sign_input(&secp, &secret_key, &tx, 0, &script_pubkey).unwrap();
+        let signature_len = script_sig[0] as usize;
+        assert_eq!(script_sig[signature_len], SIGHASH_ALL);
+        assert_eq!(&script_sig[signature_len + 2..], pubkey.serialize().as_slice());
+
+        // La firma copre il digest calcolato senza gli script di sblocco
+        let mut signed = tx.clone();
+        signed.inputs[0].script_sig = script_sig.clone();
+        let digest = Message::from_slice(&signature_hash(&signed, 0, &script_pubkey)).unwrap();
+        let signature = Signature::from_der(&script_sig[1..signature_len]).unwrap();
+        assert!(secp.verify_ecdsa(&digest, &signature, &pubkey).is_ok());
+
+        // Output di altre chiavi o non standard non vengono firmati
+        let other = ScriptTemplate::p2pkh(&[0; 20]);
+        assert!(sign_input(&secp, &secret_key, &tx, 0, &other).is_none());
+        assert!(sign_input(&secp, &secret_key, &tx, 0, b"alice").is_none());
+    }
+
+    fn two_by_two() -> Transaction {
+        Transaction::new(
+            vec![
+                TxInput::new(OutPoint::new([1; 32], 0), vec![]),
+                TxInput::new(OutPoint::new([2; 32], 0), vec![]),
+            ],
+            vec![TxOutput::to_address(40, b"alice"), TxOutput::to_address(30, b"bob")],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_sighash_type_encoding() {
+        for byte in [0x01, 0x02, 0x03, 0x81, 0x82, 0x83] {
+            let sighash_type = SighashType::from_byte(byte).unwrap();
+            assert_eq!(sighash_type.to_byte(), byte);
+            assert_eq!(sighash_type.to_string().parse::<SighashType>().unwrap(), sighash_type);
+        }
+        assert_eq!(SighashType::default(), SighashType::ALL);
+        assert_eq!("single|anyonecanpay".parse::<SighashType>().unwrap(), SighashType::SINGLE.anyone_can_pay());
+        assert!(SighashType::from_byte(0x04).is_none());
+        assert!(matches!("EVERYTHING".parse::<SighashType>(), Err(SighashError::UnknownType(_))));
+    }
+
+    #[test]
+    fn test_sighash_all_and_none() {
+        let tx = two_by_two();
+        let digest = |tx: &Transaction, sighash_type| signature_hash_with_type(tx, 0, b"script", sighash_type).unwrap();
+        assert_eq!(digest(&tx, SighashType::ALL), signature_hash(&tx, 0, b"script"));
+
+        let mut other_outputs = tx.clone();
+        other_outputs.outputs[1].value = Amount::ONE_SAT;
+        assert_ne!(digest(&tx, SighashType::ALL), digest(&other_outputs, SighashType::ALL));
+
+        // NONE: output e sequence degli altri input liberi, i loro outpoint no
+        let mut changed = other_outputs.clone();
+        changed.outputs.push(TxOutput::to_address(5, b"carol"));
+        changed.inputs[1].sequence = 7;
+        assert_eq!(digest(&tx, SighashType::NONE), digest(&changed, SighashType::NONE));
+        changed.inputs[1].previous_output = OutPoint::new([3; 32], 0);
+        assert_ne!(digest(&tx, SighashType::NONE), digest(&changed, SighashType::NONE));
+        assert_ne!(digest(&tx, SighashType::NONE), digest(&tx, SighashType::ALL));
+    }
+
+    #[test]
+    fn test_sighash_single() {
+        let tx = two_by_two();
+        let digest = |tx: &Transaction, input_index| {
+            signature_hash_with_type(tx, input_index, b"script", SighashType::SINGLE).unwrap()
+        };
+
+        // L'input 1 impegna solo l'output 1: l'output 0 è un segnaposto
+        let mut changed = tx.clone();
+        changed.outputs[0].value = Amount::ONE_SAT;
+        changed.outputs.push(TxOutput::to_address(5, b"carol"));
+        assert_eq!(digest(&tx, 1), digest(&changed, 1));
+        changed.outputs[1].value = Amount::ONE_SAT;
+        assert_ne!(digest(&tx, 1), digest(&changed, 1));
+
+        // L'input 0 impegna l'output 0
+        let mut changed = tx.clone();
+        changed.outputs[1].value = Amount::ONE_SAT;
+        assert_eq!(digest(&tx, 0), digest(&changed, 0));
+
+        let mut extra_input = tx.clone();
+        extra_input.inputs.push(TxInput::new(OutPoint::new([3; 32], 0), vec![]));
+        assert_eq!(
+            signature_hash_with_type(&extra_input, 2, b"script", SighashType::SINGLE),
+            Err(SighashError::SingleWithoutOutput { input_index: 2 })
+        );
+    }
+
+    #[test]
+    fn test_sighash_anyone_can_pay() {
+        let tx = two_by_two();
+        let sighash_type = SighashType::ALL.anyone_can_pay();
+        let digest = |tx: &Transaction, input_index| {
+            signature_hash_with_type(tx, input_index, b"script", sighash_type).unwrap()
+        };
+
+        // Crowdfunding: altri contributori aggiungono input senza invalidare la firma
+        let mut funded = tx.clone();
+        funded.inputs.push(TxInput::new(OutPoint::new([3; 32], 0), vec![]));
+        funded.inputs[1].previous_output = OutPoint::new([4; 32], 0);
+        assert_eq!(digest(&tx, 0), digest(&funded, 0));
+        assert_ne!(digest(&tx, 0), signature_hash(&tx, 0, b"script"));
+
+        // Gli output restano impegnati
+        funded.outputs[0].value = Amount::ONE_SAT;
+        assert_ne!(digest(&tx, 0), digest(&funded, 0));
+        assert!(matches!(
+            signature_hash_with_type(&tx, 5, b"script", sighash_type),
+            Err(SighashError::InputOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sign_input_with_type() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+        let script_pubkey = ScriptTemplate::p2pk(&pubkey.serialize());
+        let tx = two_by_two();
+
+        let sighash_type = SighashType::NONE.anyone_can_pay();
+        let script_sig = sign_input_with_type(&secp, &secret_key, &tx, 1, &script_pubkey, sighash_type).unwrap();
+        assert_eq!(script_sig.last(), Some(&(SIGHASH_NONE | SIGHASH_ANYONECANPAY)));
+        let digest = signature_hash_with_type(&tx, 1, &script_pubkey, sighash_type).unwrap();
+        let signature = Signature::from_der(&script_sig[1..script_sig.len() - 1]).unwrap();
+        assert!(secp.verify_ecdsa(&Message::from_slice(&digest).unwrap(), &signature, &pubkey).is_ok());
+
+        let mut single_output = tx.clone();
+        single_output.outputs.truncate(1);
+        let unsigned = sign_input_with_type(&secp, &secret_key, &single_output, 1, &script_pubkey, SighashType::SINGLE);
+        assert!(unsigned.is_none());
+    }
+}
@@ -0,0 +1,298 @@
+//! Filtro cuckoo degli outpoint spesi di recente
+//!
+//! Durante i picchi di transazioni in arrivo molte provano a spendere
+//! output appena spesi da un block o da un'altra transazione in pool. Il
+//! filtro ricorda, in due byte per voce, gli outpoint spesi negli ultimi
+//! block e dalla mempool: la mempool lo consulta prima di validare una
+//! transazione. Un negativo esclude la doppia spesa di un output recente
+//! senza letture in più; un positivo fa leggere subito l'UTXO set, che
+//! rifiuta la spesa prima di firme e script e passa la voce letta alla
+//! validazione. Un positivo può essere falso (circa 1 su 8.000 con
+//! fingerprint a 16 bit e bucket da 4): [`SpentFilterStats`] conta i falsi
+//! positivi.
+//!
+//! A differenza di un filtro di Bloom un filtro cuckoo permette di togliere
+//! le voci: gli outpoint escono quando il loro block lascia la finestra, è
+//! scollegato da un reorg o la transazione che li spende lascia la pool.
+
+use crate::{Block, OutPoint};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::BuildHasher;
+
+/// Block recenti i cui outpoint spesi restano nel filtro
+pub const DEFAULT_SPENT_FILTER_BLOCKS: usize = 100;
+
+/// Fingerprint per bucket
+const BUCKET_SIZE: usize = 4;
+
+/// Bucket iniziali (16.384 voci)
+const MIN_BUCKETS: usize = 1 << 12;
+
+/// Spostamenti tentati prima di considerare pieno il filtro
+const MAX_KICKS: usize = 500;
+
+/// Contatori del filtro
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpentFilterStats {
+    /// Outpoint consultati
+    pub lookups: u64,
+    /// Consultazioni che escludono una spesa recente
+    pub negatives: u64,
+    /// Positivi smentiti dall'UTXO set
+    pub false_positives: u64,
+    /// Outpoint nel filtro
+    pub items: usize,
+    /// Voci disponibili
+    pub capacity: usize,
+}
+
+impl SpentFilterStats {
+    /// Frazione degli outpoint non spesi di recente che il filtro ha segnalato
+    pub fn false_positive_rate(&self) -> f64 {
+        let unspent = self.negatives + self.false_positives;
+        if unspent == 0 {
+            0.0
+        } else {
+            self.false_positives as f64 / unspent as f64
+        }
+    }
+}
+
+/// Filtro cuckoo degli outpoint spesi negli ultimi block e dalla mempool
+#[derive(Debug, Clone)]
+pub struct SpentFilter {
+    /// Fingerprint per bucket (0 è uno slot vuoto)
+    buckets: Vec<[u16; BUCKET_SIZE]>,
+    /// Hasher con chiave casuale: chi invia transazioni non può costruire collisioni
+    hasher: RandomState,
+    /// Block recenti da ricordare
+    window: usize,
+    /// Outpoint spesi da ogni block della finestra, per altezza
+    blocks: VecDeque<(u64, Vec<OutPoint>)>,
+    /// Un fingerprint non ha trovato posto: fino alla ricostruzione ogni consultazione è positiva
+    saturated: bool,
+    /// Contatori
+    stats: SpentFilterStats,
+}
+
+impl Default for SpentFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_SPENT_FILTER_BLOCKS)
+    }
+}
+
+impl SpentFilter {
+    /// Crea un filtro vuoto che ricorda gli ultimi `window` block
+    pub fn new(window: usize) -> Self {
+        Self {
+            buckets: vec![[0; BUCKET_SIZE]; MIN_BUCKETS],
+            hasher: RandomState::new(),
+            window,
+            blocks: VecDeque::new(),
+            saturated: false,
+            stats: SpentFilterStats { capacity: MIN_BUCKETS * BUCKET_SIZE, ..SpentFilterStats::default() },
+        }
+    }
+
+    /// Contatori correnti
+    pub fn stats(&self) -> SpentFilterStats {
+        self.stats
+    }
+
+    /// Vero se il filtro va ricostruito con [`Self::rebuild`]
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Vero se `outpoint` può essere stato speso di recente, falso se di certo non lo è
+    pub fn may_contain(&mut self, outpoint: &OutPoint) -> bool {
+        self.stats.lookups += 1;
+        let (fingerprint, first, second) = self.locate(outpoint);
+        let found = self.saturated
+            || self.buckets[first].contains(&fingerprint)
+            || self.buckets[second].contains(&fingerprint);
+        if !found {
+            self.stats.negatives += 1;
+        }
+        found
+    }
+
+    /// Annota un positivo smentito dall'UTXO set
+    pub fn record_false_positive(&mut self) {
+        self.stats.false_positives += 1;
+    }
+
+    /// Aggiunge un outpoint speso
+    pub fn insert(&mut self, outpoint: &OutPoint) {
+        let (mut fingerprint, first, second) = self.locate(outpoint);
+        self.stats.items += 1;
+        if self.put(first, fingerprint) || self.put(second, fingerprint) {
+            return;
+        }
+
+        // Sposta un fingerprint nel suo bucket alternativo finché uno non trova posto
+        let mut index = if fingerprint & 1 == 0 { first } else { second };
+        for kick in 0..MAX_KICKS {
+            let slot = (fingerprint as usize + kick) % BUCKET_SIZE;
+            std::mem::swap(&mut fingerprint, &mut self.buckets[index][slot]);
+            index = self.alternate(index, fingerprint);
+            if self.put(index, fingerprint) {
+                return;
+            }
+        }
+        self.saturated = true;
+    }
+
+    /// Toglie un outpoint aggiunto con [`Self::insert`]
+    pub fn remove(&mut self, outpoint: &OutPoint) {
+        let (fingerprint, first, second) = self.locate(outpoint);
+        for index in [first, second] {
+            if let Some(slot) = self.buckets[index].iter_mut().find(|slot| **slot == fingerprint) {
+                *slot = 0;
+                self.stats.items = self.stats.items.saturating_sub(1);
+                return;
+            }
+        }
+    }
+
+    /// Aggiunge gli outpoint spesi da un block collegato, dimenticando il block uscito dalla finestra
+    pub fn connect_block(&mut self, block: &Block) {
+        let spent: Vec<OutPoint> = block.transactions
+            .iter()
+            .filter(|tx| !tx.is_coinbase())
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.previous_output.clone()))
+            .collect();
+        for outpoint in &spent {
+            self.insert(outpoint);
+        }
+        self.blocks.push_back((block.header.height, spent));
+        while self.blocks.len() > self.window {
+            if let Some((_, spent)) = self.blocks.pop_front() {
+                for outpoint in &spent {
+                    self.remove(outpoint);
+                }
+            }
+        }
+    }
+
+    /// Dimentica i block scollegati, dall'altezza `height` in su
+    pub fn disconnect_from(&mut self, height: u64) {
+        while self.blocks.back().is_some_and(|(block_height, _)| *block_height >= height) {
+            if let Some((_, spent)) = self.blocks.pop_back() {
+                for outpoint in &spent {
+                    self.remove(outpoint);
+                }
+            }
+        }
+    }
+
+    /// Ricostruisce il filtro con il doppio delle voci dalla finestra di block e da `pending`
+    /// (gli outpoint spesi dalla mempool)
+    pub fn rebuild<'a>(&mut self, pending: impl Iterator<Item = &'a OutPoint>) {
+        let buckets = (self.buckets.len() * 2).max(MIN_BUCKETS);
+        log::debug!("Spent outpoint filter full, growing to {} entries", buckets * BUCKET_SIZE);
+        self.buckets = vec![[0; BUCKET_SIZE]; buckets];
+        self.saturated = false;
+        self.stats.items = 0;
+        self.stats.capacity = buckets * BUCKET_SIZE;
+
+        let blocks = std::mem::take(&mut self.blocks);
+        for outpoint in blocks.iter().flat_map(|(_, spent)| spent) {
+            self.insert(outpoint);
+        }
+        for outpoint in pending {
+            self.insert(outpoint);
+        }
+        self.blocks = blocks;
+    }
+
+    /// Fingerprint e bucket candidati di un outpoint
+    fn locate(&self, outpoint: &OutPoint) -> (u16, usize, usize) {
+        let hash = self.hasher.hash_one(outpoint);
+        let fingerprint = ((hash >> 48) as u16).max(1);
+        let first = hash as usize & (self.buckets.len() - 1);
+        (fingerprint, first, self.alternate(first, fingerprint))
+    }
+
+    /// L'altro bucket di un fingerprint (l'operazione è simmetrica)
+    fn alternate(&self, index: usize, fingerprint: u16) -> usize {
+        (index ^ (fingerprint as usize).wrapping_mul(0x5bd1_e995)) & (self.buckets.len() - 1)
+    }
+
+    /// Mette un fingerprint in uno slot vuoto del bucket, se c'è
+    fn put(&mut self, index: usize, fingerprint: u16) -> bool {
+        match self.buckets[index].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Transaction, TxInput, TxOutput};
+
+    fn outpoint(n: u32) -> OutPoint {
+        OutPoint::new([(n % 251) as u8; 32], n)
+    }
+
+    fn block(height: u64, spent: &[OutPoint]) -> Block {
+        let mut block = Block::genesis();
+        block.header.height = height;
+        block.transactions.push(Transaction::new(
+            spent.iter().map(|outpoint| TxInput::new(outpoint.clone(), vec![])).collect(),
+            vec![TxOutput::to_address(1, b"alice")],
+            0,
+        ));
+        block
+    }
+
+    #[test]
+    fn test_spent_filter_window() {
+        let mut filter = SpentFilter::new(2);
+        filter.insert(&outpoint(1));
+        assert!(filter.may_contain(&outpoint(1)));
+        filter.remove(&outpoint(1));
+        assert!(!filter.may_contain(&outpoint(1)));
+
+        filter.connect_block(&block(1, &[outpoint(10)]));
+        filter.connect_block(&block(2, &[outpoint(20)]));
+        assert!(filter.may_contain(&outpoint(10)) && filter.may_contain(&outpoint(20)));
+
+        // Il block più vecchio esce dalla finestra, quello scollegato sparisce
+        filter.connect_block(&block(3, &[outpoint(30)]));
+        assert!(!filter.may_contain(&outpoint(10)));
+        filter.disconnect_from(3);
+        assert!(!filter.may_contain(&outpoint(30)));
+        assert!(filter.may_contain(&outpoint(20)));
+        assert_eq!(filter.stats().items, 1);
+    }
+
+    #[test]
+    fn test_spent_filter_growth_and_rate() {
+        let mut filter = SpentFilter::new(1);
+        let spent: Vec<OutPoint> = (0..30_000).map(outpoint).collect();
+        for (index, outpoint) in spent.iter().enumerate() {
+            filter.insert(outpoint);
+            if filter.is_saturated() {
+                filter.rebuild(spent[..=index].iter());
+            }
+        }
+        // Oltre la capacità iniziale il filtro cresce senza perdere voci
+        assert!(filter.stats().capacity > MIN_BUCKETS * BUCKET_SIZE);
+        assert_eq!(filter.stats().items, spent.len());
+        assert!(spent.iter().all(|outpoint| filter.may_contain(outpoint)));
+
+        let positives = (100_000..200_000).filter(|n| filter.may_contain(&outpoint(*n))).count();
+        (0..positives).for_each(|_| filter.record_false_positive());
+        let stats = filter.stats();
+        assert_eq!(stats.negatives + stats.false_positives, 100_000);
+        assert!(stats.false_positive_rate() < 0.01, "{}", stats.false_positive_rate());
+    }
+}
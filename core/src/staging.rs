@@ -0,0 +1,364 @@
+//! Area di staging dei block per il download parallelo durante l'IBD
+//!
+//! Durante la sincronizzazione iniziale i block arrivano da più peer in
+//! ordine qualsiasi. Quelli che superano i controlli indipendenti dal
+//! contesto vengono salvati nella column family di staging, indicizzati per
+//! hash, e connessi in sequenza con la `BlockPipeline` appena il loro parent
+//! diventa il tip.
+//!
+//! L'area è limitata su disco: al più `max_blocks` block, non oltre
+//! `max_ahead` sopra il tip né più di `max_depth` sotto. I block che non
+//! potranno più essere connessi (già connessi per un'altra via, o troppo
+//! sotto il tip) vengono scartati da `evict_orphaned`; ad area piena lascia
+//! il posto il block più in basso già superato dal tip.
+
+use crate::pipeline::{check_known_invalid, BlockPipeline, PipelineError, ProcessedBlock};
+use crate::storage::{BlockchainDB, StorageError};
+use crate::validation::{BlockValidator, ValidationError};
+use crate::Block;
+use std::collections::BTreeMap;
+
+/// Numero massimo di default di block in staging
+pub const DEFAULT_MAX_STAGED_BLOCKS: usize = 1_024;
+
+/// Distanza massima di default sopra il tip di un block in staging
+pub const DEFAULT_MAX_STAGING_AHEAD: u64 = 2_048;
+
+/// Profondità massima di default sotto il tip di un block in staging
+pub const DEFAULT_MAX_STAGED_DEPTH: u64 = 288;
+
+/// Block in staging nell'indice in memoria
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StagedEntry {
+    /// Hash del block
+    hash: [u8; 32],
+    /// Hash del parent
+    previous_hash: [u8; 32],
+}
+
+/// Esito di `connect_ready`
+#[derive(Debug, Default)]
+pub struct ConnectReport {
+    /// Block connessi, in ordine di altezza
+    pub connected: Vec<ProcessedBlock>,
+    /// Block scartati perché invalidi
+    pub rejected: Vec<([u8; 32], PipelineError)>,
+}
+
+/// Area di staging dei block scaricati ma non connessi
+#[derive(Debug, Clone)]
+pub struct BlockStaging {
+    /// Numero massimo di block in staging
+    max_blocks: usize,
+    /// Distanza massima sopra il tip
+    max_ahead: u64,
+    /// Profondità massima sotto il tip
+    max_depth: u64,
+    /// Block in staging per altezza
+    by_height: BTreeMap<u64, Vec<StagedEntry>>,
+    /// Block scartati senza essere connessi, fino a `take_dropped`
+    dropped: Vec<[u8; 32]>,
+}
+
+impl BlockStaging {
+    /// Crea un'area di staging vuota con i limiti dati
+    pub fn new(max_blocks: usize, max_ahead: u64, max_depth: u64) -> Self {
+        Self {
+            max_blocks,
+            max_ahead,
+            max_depth,
+            by_height: BTreeMap::new(),
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Ricostruisce l'indice dai block in staging nel database
+    pub fn load(db: &BlockchainDB, max_blocks: usize, max_ahead: u64, max_depth: u64) -> Result<Self, StorageError> {
+        let mut staging = Self::new(max_blocks, max_ahead, max_depth);
+        for header in db.get_staged_headers()? {
+            staging.insert(header.height, header.hash(), header.previous_hash);
+        }
+        Ok(staging)
+    }
+
+    /// Numero di block in staging
+    pub fn len(&self) -> usize {
+        self.by_height.values().map(Vec::len).sum()
+    }
+
+    /// Se non ci sono block in staging
+    pub fn is_empty(&self) -> bool {
+        self.by_height.is_empty()
+    }
+
+    /// Se un block è in staging
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.by_height.values().flatten().any(|entry| entry.hash == *hash)
+    }
+
+    /// Altezza più alta in staging
+    pub fn highest(&self) -> Option<u64> {
+        self.by_height.keys().next_back().copied()
+    }
+
+    /// Mette in staging un block scaricato
+    ///
+    /// Ritorna false se il block era già in staging o nella chain attiva.
+    pub fn stage(&mut self, block: &Block, validator: &BlockValidator, db: &BlockchainDB) -> Result<bool, StagingError> {
+        let hash = block.hash();
+        if self.contains(&hash) || is_active(db, block.header.height, &hash)? {
+            return Ok(false);
+        }
+
+        let tip = db.get_height()?;
+        let height = block.header.height;
+        if height.saturating_add(self.max_depth) < tip {
+            return Err(StagingError::Stale { height, tip });
+        }
+        if height > tip && height - tip > self.max_ahead {
+            return Err(StagingError::TooFarAhead { height, max: tip + self.max_ahead });
+        }
+        if self.len() >= self.max_blocks {
+            // Lascia il posto il block più in basso, se il tip lo ha già superato
+            match self.by_height.first_key_value() {
+                Some((&lowest, entries)) if lowest <= tip && lowest < height => {
+                    let evicted = entries[0].hash;
+                    self.remove(lowest, &evicted, db)?;
+                    self.dropped.push(evicted);
+                }
+                _ => return Err(StagingError::Full(self.max_blocks)),
+            }
+        }
+
+        check_known_invalid(block, db).map_err(StagingError::Pipeline)?;
+        validator.check_structure(block)?;
+        validator.check_scripts(block)?;
+
+        db.stage_block(block)?;
+        self.insert(height, hash, block.header.previous_hash);
+        Ok(true)
+    }
+
+    /// Connette in sequenza i block in staging che estendono il tip
+    ///
+    /// I block scartati dalla pipeline vengono rimossi dallo staging; un
+    /// errore di storage interrompe la connessione.
+    pub fn connect_ready(&mut self, pipeline: &mut BlockPipeline, db: &BlockchainDB) -> Result<ConnectReport, StagingError> {
+        let mut report = ConnectReport::default();
+
+        loop {
+            let metadata = db.get_metadata()?;
+            let next_height = metadata.height + 1;
+            let Some(entry) = self.by_height
+                .get(&next_height)
+                .and_then(|entries| entries.iter().find(|entry| entry.previous_hash == metadata.best_block_hash))
+                .cloned()
+            else {
+                break;
+            };
+
+            let block = db.get_staged_block(&entry.hash)?
+                .ok_or(StorageError::BlockNotFound { hash: entry.hash })?;
+            let result = pipeline.process(&block, db);
+            self.remove(next_height, &entry.hash, db)?;
+
+            match result {
+                Ok(processed) => report.connected.push(processed),
+                Err(error @ PipelineError::Storage { .. }) => return Err(StagingError::Pipeline(error)),
+                Err(error) => report.rejected.push((entry.hash, error)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Scarta i block che non potranno più essere connessi
+    ///
+    /// Sono orfani i block già connessi alla chain attiva per un'altra via e
+    /// quelli più di `max_depth` sotto il tip. Ritorna il numero di block scartati.
+    pub fn evict_orphaned(&mut self, db: &BlockchainDB) -> Result<usize, StagingError> {
+        let tip = db.get_height()?;
+        let mut orphaned = Vec::new();
+        for (&height, entries) in self.by_height.range(..=tip) {
+            for entry in entries {
+                if is_active(db, height, &entry.hash)? {
+                    orphaned.push((height, entry.hash, false));
+                } else if height.saturating_add(self.max_depth) < tip {
+                    orphaned.push((height, entry.hash, true));
+                }
+            }
+        }
+
+        for (height, hash, dropped) in &orphaned {
+            self.remove(*height, hash, db)?;
+            if *dropped {
+                self.dropped.push(*hash);
+            }
+        }
+        Ok(orphaned.len())
+    }
+
+    /// Hash dei block scartati senza essere connessi dall'ultima chiamata
+    ///
+    /// Chi indicizza gli header dei block in staging (es. `HeaderCache`) li
+    /// rimuove, così un peer può inviarli di nuovo.
+    pub fn take_dropped(&mut self) -> Vec<[u8; 32]> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Aggiunge un block all'indice
+    fn insert(&mut self, height: u64, hash: [u8; 32], previous_hash: [u8; 32]) {
+        self.by_height.entry(height).or_default().push(StagedEntry { hash, previous_hash });
+    }
+
+    /// Rimuove un block dall'indice e dal database
+    fn remove(&mut self, height: u64, hash: &[u8; 32], db: &BlockchainDB) -> Result<(), StorageError> {
+        db.remove_staged_block(hash)?;
+        if let Some(entries) = self.by_height.get_mut(&height) {
+            entries.retain(|entry| entry.hash != *hash);
+            if entries.is_empty() {
+                self.by_height.remove(&height);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for BlockStaging {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_STAGED_BLOCKS, DEFAULT_MAX_STAGING_AHEAD, DEFAULT_MAX_STAGED_DEPTH)
+    }
+}
+
+/// Se `hash` è il block della chain attiva ad altezza `height`
+fn is_active(db: &BlockchainDB, height: u64, hash: &[u8; 32]) -> Result<bool, StorageError> {
+    Ok(db.get_header_by_height(height)?.is_some_and(|header| header.hash() == *hash))
+}
+
+/// Errori dell'area di staging
+#[derive(Debug, thiserror::Error)]
+pub enum StagingError {
+    #[error("Block at height {height} is too far below the tip ({tip})")]
+    Stale { height: u64, tip: u64 },
+
+    #[error("Block at height {height} is too far ahead (maximum {max})")]
+    TooFarAhead { height: u64, max: u64 },
+
+    #[error("Staging area is full ({0} blocks)")]
+    Full(usize),
+
+    #[error("Invalid block: {0}")]
+    Invalid(#[from] ValidationError),
+
+    #[error("Pipeline error: {0}")]
+    Pipeline(PipelineError),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::block_subsidy;
+    use crate::{ChainParams, Transaction};
+    use tempfile::TempDir;
+
+    fn build_chain(parent: &Block, count: u64, miner: &[u8]) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..count {
+            let previous = blocks.last().unwrap_or(parent);
+            let height = previous.header.height + 1;
+            let coinbase = Transaction::coinbase(miner, height, block_subsidy(height));
+            blocks.push(Block::new(previous.hash(), vec![coinbase], parent.header.bits, height));
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_out_of_order_blocks_connect_sequentially() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let validator = BlockValidator::new(ChainParams::mainnet());
+        let mut pipeline = BlockPipeline::new(validator.clone());
+        let mut staging = BlockStaging::default();
+        let blocks = build_chain(&genesis, 3, b"miner");
+
+        // Arrivano 3 e 2: nulla da connettere finché manca 1
+        assert!(staging.stage(&blocks[2], &validator, &db).unwrap());
+        assert!(staging.stage(&blocks[1], &validator, &db).unwrap());
+        assert!(!staging.stage(&blocks[1], &validator, &db).unwrap());
+        assert!(staging.connect_ready(&mut pipeline, &db).unwrap().connected.is_empty());
+
+        staging.stage(&blocks[0], &validator, &db).unwrap();
+        let report = staging.connect_ready(&mut pipeline, &db).unwrap();
+        assert_eq!(report.connected.iter().map(|block| block.height).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(staging.is_empty());
+        assert_eq!(db.get_best_block_hash().unwrap(), blocks[2].hash());
+        assert!(db.get_staged_headers().unwrap().is_empty());
+
+        // Già connesso: nulla da mettere in staging
+        assert!(!staging.stage(&blocks[0], &validator, &db).unwrap());
+    }
+
+    #[test]
+    fn test_evict_orphaned_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let validator = BlockValidator::new(ChainParams::mainnet());
+        let mut staging = BlockStaging::new(2, 2, 1);
+        let main = build_chain(&genesis, 3, b"miner");
+        let fork = build_chain(&genesis, 2, b"other");
+
+        staging.stage(&fork[0], &validator, &db).unwrap();
+        staging.stage(&fork[1], &validator, &db).unwrap();
+        assert!(matches!(
+            staging.stage(&build_chain(&fork[1], 1, b"other")[0], &validator, &db),
+            Err(StagingError::TooFarAhead { height: 3, max: 2 })
+        ));
+        // Nessun block superato dal tip da scartare per fare posto
+        assert!(matches!(staging.stage(&main[1], &validator, &db), Err(StagingError::Full(2))));
+
+        // Il ramo principale viene connesso altrove: fork[0] scende oltre la profondità massima
+        for block in &main {
+            db.store_block(block).unwrap();
+        }
+        assert_eq!(staging.evict_orphaned(&db).unwrap(), 1);
+        assert!(!staging.contains(&fork[0].hash()) && staging.contains(&fork[1].hash()));
+        assert!(db.get_staged_block(&fork[0].hash()).unwrap().is_none());
+        assert!(matches!(
+            staging.stage(&fork[0], &validator, &db),
+            Err(StagingError::Stale { height: 1, tip: 3 })
+        ));
+        assert!(!staging.stage(&main[2], &validator, &db).unwrap());
+
+        // Un block in staging connesso per un'altra via viene scartato
+        let next = build_chain(&main[2], 1, b"miner")[0].clone();
+        staging.stage(&next, &validator, &db).unwrap();
+        db.store_block(&next).unwrap();
+        assert_eq!(staging.evict_orphaned(&db).unwrap(), 2);
+        assert!(staging.is_empty());
+
+        // Ad area piena lascia il posto il block più in basso già superato dal tip
+        let side = build_chain(&main[2], 1, b"side")[0].clone();
+        let first = build_chain(&next, 1, b"first")[0].clone();
+        let second = build_chain(&next, 1, b"second")[0].clone();
+        staging.stage(&side, &validator, &db).unwrap();
+        staging.stage(&first, &validator, &db).unwrap();
+        staging.stage(&second, &validator, &db).unwrap();
+        assert!(!staging.contains(&side.hash()));
+        assert_eq!(staging.len(), 2);
+        assert_eq!(staging.take_dropped(), vec![fork[0].hash(), fork[1].hash(), side.hash()]);
+
+        // L'indice si ricostruisce dal database
+        let reloaded = BlockStaging::load(&db, 2, 2, 1).unwrap();
+        assert!(reloaded.contains(&first.hash()) && reloaded.contains(&second.hash()));
+        assert_eq!(reloaded.highest(), Some(5));
+    }
+}
@@ -0,0 +1,517 @@
+//! Chain di un nodo PoW standalone (senza Tendermint)
+//!
+//! In modalità standalone il consenso è la sola proof of work: il nodo mina
+//! template costruiti dalla mempool, riceve block e transazioni dai peer e
+//! segue la chain con più lavoro cumulativo. [`StandaloneChain`] tiene lo
+//! stato condiviso da miner e relay P2P.
+//!
+//! Un block che estende il tip passa dalla pipeline. Un block su un ramo
+//! laterale supera i controlli dell'header (bits del retarget e timestamp
+//! rispetto ai suoi antenati) e quelli indipendenti dal contesto, e resta
+//! nella [`BlockStaging`], limitata e ripulita a ogni cambio di tip; appena
+//! il suo ramo ha più lavoro della chain attiva il nodo si riorganizza
+//! (`reorg::activate_chain`). La mempool segue ogni cambio di tip.
+//!
+//! Database, validazione, header e mempool sono quelli del [`NodeCore`]
+//! condiviso con l'applicazione ABCI; qui restano orfani e staging.
+
+use crate::headers::HeaderCacheError;
+use crate::mempool::{Mempool, MempoolError};
+use crate::mining::{BlockTemplate, MiningError};
+use crate::node::NodeCore;
+use crate::orphan::{BlockOutcome, OrphanPool, DEFAULT_MAX_ORPHAN_BLOCKS};
+use crate::pipeline::{check_known_invalid, PipelineError};
+use crate::reorg::{self, ReorgError};
+use crate::staging::{
+    BlockStaging, StagingError, DEFAULT_MAX_STAGED_BLOCKS, DEFAULT_MAX_STAGED_DEPTH, DEFAULT_MAX_STAGING_AHEAD,
+};
+use crate::storage::{BlockchainDB, InvalidBlock, StorageError};
+use crate::validation::{median_time_past, BlockValidator, ValidationError};
+use crate::{Block, SerializationError, Transaction};
+use std::sync::{Arc, Mutex};
+
+/// Bytes del block riservati alla coinbase nei template
+pub const COINBASE_RESERVED_SIZE: usize = 1_000;
+
+/// Cambio della chain attiva
+#[derive(Debug, Default)]
+pub struct ChainUpdate {
+    /// Block connessi, in ordine di altezza
+    pub connected: Vec<[u8; 32]>,
+    /// Block scollegati da un reorg, dal vecchio tip verso il punto di fork
+    pub disconnected: Vec<[u8; 32]>,
+    /// Block (orfani in attesa) scartati perché invalidi
+    pub rejected: Vec<([u8; 32], PipelineError)>,
+}
+
+/// Esito di un block ricevuto o minato
+#[derive(Debug)]
+pub enum BlockAcceptance {
+    /// Il tip è cambiato
+    Connected(ChainUpdate),
+    /// Block di un ramo laterale con meno lavoro della chain attiva, tenuto in staging
+    SideBranch,
+    /// Parent sconosciuto: il block resta tra gli orfani in attesa di `request`
+    Orphaned {
+        /// Antenato mancante da richiedere ai peer
+        request: [u8; 32],
+    },
+    /// Block già noto
+    Duplicate,
+}
+
+/// Stato della chain di un nodo standalone
+pub struct StandaloneChain {
+    core: NodeCore,
+    orphans: OrphanPool,
+    staging: BlockStaging,
+}
+
+impl StandaloneChain {
+    /// Crea lo stato sul nucleo del nodo
+    ///
+    /// Senza Tendermint la proof of work è l'unica regola che ordina i
+    /// block: il validatore del nucleo deve verificarla
+    /// (`BlockValidator::with_proof_of_work`). I block dei rami laterali
+    /// rimasti in staging dalla sessione precedente vengono ricaricati.
+    pub fn new(core: NodeCore) -> Result<Self, StandaloneError> {
+        let mut staging = BlockStaging::load(
+            core.db(),
+            DEFAULT_MAX_STAGED_BLOCKS,
+            DEFAULT_MAX_STAGING_AHEAD,
+            DEFAULT_MAX_STAGED_DEPTH,
+        )?;
+        staging.evict_orphaned(core.db())?;
+        let mut headers = core.headers().lock().unwrap();
+        for hash in staging.take_dropped() {
+            headers.remove(&hash);
+        }
+        drop(headers);
+        Ok(Self {
+            orphans: OrphanPool::new(DEFAULT_MAX_ORPHAN_BLOCKS),
+            staging,
+            core,
+        })
+    }
+
+    /// Nucleo del nodo
+    pub fn core(&self) -> &NodeCore {
+        &self.core
+    }
+
+    /// Database della chain
+    pub fn db(&self) -> &Arc<BlockchainDB> {
+        self.core.db()
+    }
+
+    /// Mempool del nodo
+    pub fn mempool(&self) -> &Arc<Mutex<Mempool>> {
+        self.core.mempool()
+    }
+
+    /// Validatore dei block
+    pub fn validator(&self) -> &BlockValidator {
+        self.core.validator()
+    }
+
+    /// Locator della chain attiva, da inviare ai peer per chiedere i block mancanti
+    pub fn locator(&self) -> Vec<[u8; 32]> {
+        self.core.headers().lock().unwrap().locator()
+    }
+
+    /// Block della chain attiva dopo il primo hash di `locator` che ne fa
+    /// parte (dopo il genesis se nessuno), in ordine di altezza
+    ///
+    /// Si ferma prima di superare `max_bytes` serializzati, ma ritorna
+    /// sempre almeno un block se ce ne sono.
+    pub fn blocks_after(&self, locator: &[[u8; 32]], max_bytes: usize) -> Result<Vec<Block>, StandaloneError> {
+        let headers = self.core.headers().lock().unwrap();
+        let fork_height = locator
+            .iter()
+            .find(|hash| headers.is_active(hash))
+            .and_then(|hash| headers.get(hash))
+            .map_or(0, |entry| entry.height);
+        drop(headers);
+        let tip_height = self.db().get_height()?;
+
+        let mut blocks = Vec::new();
+        let mut size = 0;
+        for height in fork_height + 1..=tip_height {
+            let block = self.db()
+                .get_block_by_height(height)?
+                .ok_or(StorageError::InvalidData(format!("Missing block at height {}", height)))?;
+            size += block.size()?;
+            if size > max_bytes && !blocks.is_empty() {
+                break;
+            }
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// Template sopra il tip con le transazioni della mempool per fee rate
+    ///
+    /// La coinbase paga a `coinbase_script` subsidy e fee, meno la quota
+    /// della treasury. I bits sono quelli richiesti dal retarget
+    /// (`BlockValidator::expected_bits`) e il timestamp è l'ora corrente,
+    /// portata oltre la median time past se necessario.
+    pub fn block_template(&self, coinbase_script: &[u8]) -> Result<BlockTemplate, StandaloneError> {
+        let metadata = self.db().get_metadata()?;
+        let tip = self.db()
+            .get_header_by_height(metadata.height)?
+            .ok_or(StorageError::BlockNotFound { hash: metadata.best_block_hash })?;
+        let height = metadata.height + 1;
+        let params = self.validator().params();
+
+        let headers = self.core.headers().lock().unwrap();
+        let timestamps = |from, to| headers.timestamps(from, to);
+        let bits = self.validator().expected_bits(height, tip.bits, timestamps)?;
+        let min_time = median_time_past(height, timestamps)?.saturating_add(1);
+        drop(headers);
+
+        let max_size = self.validator().max_block_size().saturating_sub(COINBASE_RESERVED_SIZE);
+        let mempool = self.mempool().lock().unwrap();
+        let selected = mempool.select_for_block(max_size, self.validator().max_block_script_cost());
+        let fees = selected.iter().fold(0u64, |fees, entry| fees.saturating_add(entry.fee));
+        let subsidy = params.subsidy(height);
+        let mut coinbase = Transaction::coinbase(coinbase_script, height, subsidy.saturating_add(fees));
+        if let Some(treasury) = &params.treasury {
+            treasury.apply_to_coinbase(&mut coinbase, subsidy);
+        }
+        let transactions = std::iter::once(coinbase)
+            .chain(selected.into_iter().map(|entry| entry.tx.clone()))
+            .collect();
+
+        let mut template = BlockTemplate::new(metadata.best_block_hash, transactions, bits, height)?;
+        template.header.timestamp = template.header.timestamp.max(min_time);
+        Ok(template)
+    }
+
+    /// Valida e aggiunge alla mempool una transazione ricevuta, ritornando la fee
+    pub fn accept_transaction(&self, tx: Transaction) -> Result<u64, MempoolError> {
+        let tip_height = self.db().get_height()?;
+        self.core.accept_transaction(tx, tip_height)
+    }
+
+    /// Processa un block minato o ricevuto da un peer
+    pub fn accept_block(&mut self, block: Block) -> Result<BlockAcceptance, StandaloneError> {
+        let hash = block.hash();
+        if self.core.headers_mut().contains(&hash) || self.orphans.contains(&hash) {
+            return Ok(BlockAcceptance::Duplicate);
+        }
+
+        let parent = block.header.previous_hash;
+        if parent == self.db().get_best_block_hash()? || !self.core.headers_mut().contains(&parent) {
+            let db = self.core.db().clone();
+            return match self.orphans.process_block(block, self.core.pipeline_mut(), &db)? {
+                BlockOutcome::Processed(report) => {
+                    let connected: Vec<[u8; 32]> = report.connected.iter().map(|processed| processed.hash).collect();
+                    self.on_connected(&connected)?;
+                    Ok(BlockAcceptance::Connected(ChainUpdate {
+                        connected,
+                        disconnected: Vec::new(),
+                        rejected: report.rejected,
+                    }))
+                }
+                BlockOutcome::Orphaned { request } => Ok(BlockAcceptance::Orphaned { request }),
+                BlockOutcome::AlreadyOrphaned => Ok(BlockAcceptance::Duplicate),
+            };
+        }
+
+        self.accept_side_block(block)
+    }
+
+    /// Mette in staging un block di un ramo laterale e riorganizza se il
+    /// ramo supera la chain attiva
+    ///
+    /// Un header che viola le regole di consenso marca il block come
+    /// invalido, come nella pipeline.
+    fn accept_side_block(&mut self, block: Block) -> Result<BlockAcceptance, StandaloneError> {
+        let hash = block.hash();
+        let db = self.core.db().clone();
+        let parent_hash = block.header.previous_hash;
+        let parent = match db.get_block(&parent_hash)? {
+            Some(parent) => parent,
+            None => db
+                .get_staged_block(&parent_hash)?
+                .ok_or(StorageError::BlockNotFound { hash: parent_hash })?,
+        };
+        check_known_invalid(&block, &db)?;
+        let checked = {
+            let headers = self.core.headers().lock().unwrap();
+            self.validator().check_header(&block, Some(&parent.header)).and_then(|()| {
+                self.validator().check_header_context(&block, &parent.header, |from, to| {
+                    headers.branch_timestamps(&parent_hash, from, to)
+                })
+            })
+        };
+        if let Err(error) = &checked {
+            if error.invalidates_hash() {
+                let record = InvalidBlock {
+                    height: block.header.height,
+                    previous_hash: parent_hash,
+                    reason: error.to_string(),
+                };
+                db.mark_block_invalid(&hash, &record)?;
+            }
+        }
+        checked?;
+
+        // Struttura e script sono verificati dalla staging
+        let staged = self.staging.stage(&block, self.core.validator(), &db);
+        self.forget_dropped();
+        if !staged? {
+            return Ok(BlockAcceptance::Duplicate);
+        }
+        let entry = self.core.headers_mut().insert(&block.header)?;
+        let active_work = self.core.headers_mut().tip().map(|(_, tip)| tip.chainwork).unwrap_or_default();
+        let mut acceptance = if entry.chainwork > active_work {
+            self.activate(hash)?
+        } else {
+            log::debug!("Side branch block {} at height {}", hex::encode(hash), block.header.height);
+            BlockAcceptance::SideBranch
+        };
+
+        // Figli arrivati prima del block, ora con il parent noto
+        for child in self.orphans.take_children(&hash) {
+            if let BlockAcceptance::Connected(update) = self.accept_block(child)? {
+                acceptance = match acceptance {
+                    BlockAcceptance::Connected(mut previous) => {
+                        previous.connected.extend(update.connected);
+                        previous.disconnected.extend(update.disconnected);
+                        previous.rejected.extend(update.rejected);
+                        BlockAcceptance::Connected(previous)
+                    }
+                    _ => BlockAcceptance::Connected(update),
+                };
+            }
+        }
+        Ok(acceptance)
+    }
+
+    /// Riorganizza la chain attiva sul ramo che termina in `target`
+    fn activate(&mut self, target: [u8; 32]) -> Result<BlockAcceptance, StandaloneError> {
+        let db = self.core.db().clone();
+        let result = reorg::activate_chain(&db, self.core.pipeline_mut(), target);
+        // Stati e tip cambiati, anche se il ramo è stato rifiutato
+        self.core.reload_headers()?;
+        let report = result?;
+
+        let disconnected = report.disconnected
+            .iter()
+            .filter_map(|hash| db.get_block(hash).transpose())
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let stats = self.mempool().lock().unwrap().update_for_reorg(&disconnected, self.validator(), &db)?;
+        log::info!(
+            "Mempool updated after reorg: {} transactions returned, {} dropped, {} evicted",
+            stats.returned, stats.dropped, stats.evicted
+        );
+
+        let mut update = ChainUpdate {
+            connected: report.connected.iter().map(|processed| processed.hash).collect(),
+            disconnected: report.disconnected,
+            rejected: Vec::new(),
+        };
+        let children = self.orphans.connect_children(target, self.core.pipeline_mut(), &db);
+        let connected: Vec<[u8; 32]> = children.connected.iter().map(|processed| processed.hash).collect();
+        self.on_connected(&connected)?;
+        update.connected.extend(connected);
+        update.rejected = children.rejected;
+        Ok(BlockAcceptance::Connected(update))
+    }
+
+    /// Aggiorna indice degli header e mempool dopo la connessione di block
+    /// sopra il tip, e scarta i block in staging ormai inutili
+    fn on_connected(&mut self, connected: &[[u8; 32]]) -> Result<(), StandaloneError> {
+        for hash in connected {
+            let block = self.db().get_block(hash)?.ok_or(StorageError::BlockNotFound { hash: *hash })?;
+            self.core.block_connected(&block)?;
+        }
+        let evicted = self.staging.evict_orphaned(self.core.db())?;
+        if evicted > 0 {
+            log::debug!("Evicted {} staged blocks", evicted);
+        }
+        self.forget_dropped();
+        Ok(())
+    }
+
+    /// Toglie dall'indice gli header dei block scartati dalla staging
+    fn forget_dropped(&mut self) {
+        let dropped = self.staging.take_dropped();
+        let headers = self.core.headers_mut();
+        for hash in dropped {
+            headers.remove(&hash);
+        }
+    }
+}
+
+/// Errori del nodo standalone
+#[derive(Debug, thiserror::Error)]
+pub enum StandaloneError {
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+
+    #[error("Invalid block: {0}")]
+    Invalid(#[from] ValidationError),
+
+    #[error(transparent)]
+    Reorg(#[from] ReorgError),
+
+    #[error(transparent)]
+    Staging(#[from] StagingError),
+
+    #[error(transparent)]
+    Headers(#[from] HeaderCacheError),
+
+    #[error(transparent)]
+    Mining(#[from] MiningError),
+
+    #[error(transparent)]
+    Mempool(#[from] MempoolError),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::MAX_FUTURE_BLOCK_TIME;
+    use crate::{BlockHeader, ChainParams, Network, PowKind, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    /// Mina un block di `transactions` sopra `parent` con la difficulty di regtest
+    fn mine(parent: &Block, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(parent.hash(), transactions, 0x207fffff, parent.header.height + 1);
+        // Un secondo dopo il parent, così la median time past cresce a ogni block
+        block.header.timestamp = parent.header.timestamp + 1;
+        solve(block)
+    }
+
+    fn solve(mut block: Block) -> Block {
+        while !block.header.meets_difficulty_with(PowKind::Sha256d.algorithm()) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn chain(temp_dir: &TempDir) -> (StandaloneChain, Block) {
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"genesis", 0, 50)], 0x207fffff, 0);
+        db.initialize_with_genesis(&genesis).unwrap();
+        let params = ChainParams::for_network(Network::Regtest).with_coinbase_maturity(1);
+        let validator = BlockValidator::new(params).with_proof_of_work(true);
+        let core = NodeCore::open(db, validator, temp_dir.path().join("mempool.dat")).unwrap();
+        let chain = StandaloneChain::new(core).unwrap();
+        (chain, genesis)
+    }
+
+    #[test]
+    fn test_mine_from_mempool() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut chain, genesis) = chain(&temp_dir);
+
+        let template = chain.block_template(&crate::anyone_can_spend(b"miner")).unwrap();
+        let block = mine(&genesis, template.transactions().to_vec());
+        assert!(matches!(chain.accept_block(block.clone()).unwrap(), BlockAcceptance::Connected(_)));
+        assert!(matches!(chain.accept_block(block.clone()).unwrap(), BlockAcceptance::Duplicate));
+
+        // La transazione in mempool entra nel template successivo con la sua fee
+        let coinbase = block.transactions[0].hash();
+        let reward = block.transactions[0].outputs[0].value.to_sat();
+        let tx = Transaction::new(
+            vec![TxInput::new(crate::OutPoint::new(coinbase, 0), vec![])],
+            vec![TxOutput::new(reward - 5_000, [0; 32], b"alice".to_vec())],
+            0,
+        );
+        assert_eq!(chain.accept_transaction(tx.clone()).unwrap(), 5_000);
+        let template = chain.block_template(b"miner").unwrap();
+        assert_eq!(template.transactions()[1], tx);
+        let subsidy = chain.validator().params().subsidy(2);
+        assert_eq!(template.transactions()[0].outputs[0].value.to_sat(), subsidy + 5_000);
+
+        let next = mine(&block, template.transactions().to_vec());
+        chain.accept_block(next).unwrap();
+        assert!(chain.mempool().lock().unwrap().is_empty());
+        assert_eq!(chain.db().get_height().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_follow_most_work_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut chain, genesis) = chain(&temp_dir);
+        let a1 = mine(&genesis, vec![Transaction::coinbase(b"a", 1, 50)]);
+        chain.accept_block(a1.clone()).unwrap();
+
+        // Ramo laterale di pari lavoro: resta in staging
+        let b1 = mine(&genesis, vec![Transaction::coinbase(b"b", 1, 50)]);
+        assert!(matches!(chain.accept_block(b1.clone()).unwrap(), BlockAcceptance::SideBranch));
+        assert_eq!(chain.db().get_best_block_hash().unwrap(), a1.hash());
+
+        // Il figlio arriva prima del nipote: orfano finché b2 non lo collega
+        let b2 = mine(&b1, vec![Transaction::coinbase(b"b", 2, 50)]);
+        let b3 = mine(&b2, vec![Transaction::coinbase(b"b", 3, 50)]);
+        let BlockAcceptance::Orphaned { request } = chain.accept_block(b3.clone()).unwrap() else {
+            panic!("b3 should be an orphan");
+        };
+        assert_eq!(request, b2.hash());
+
+        let BlockAcceptance::Connected(update) = chain.accept_block(b2.clone()).unwrap() else {
+            panic!("b2 should trigger a reorg");
+        };
+        assert_eq!(update.disconnected, vec![a1.hash()]);
+        assert_eq!(update.connected, vec![b1.hash(), b2.hash(), b3.hash()]);
+        assert_eq!(chain.db().get_best_block_hash().unwrap(), b3.hash());
+        assert_eq!(chain.core().headers().lock().unwrap().tip().unwrap().0, b3.hash());
+        assert!(chain.db().get_staged_block(&b1.hash()).unwrap().is_none());
+
+        // Un peer rimasto su a1 riceve il ramo b dal genesis, entro il limite di bytes
+        let blocks = chain.blocks_after(&[a1.hash(), genesis.hash()], usize::MAX).unwrap();
+        assert_eq!(blocks.iter().map(Block::hash).collect::<Vec<_>>(), update.connected);
+        assert_eq!(chain.blocks_after(&chain.locator(), usize::MAX).unwrap().len(), 0);
+        assert_eq!(chain.blocks_after(&[], 1).unwrap().len(), 1);
+
+        // Un block senza proof of work su un ramo laterale è rifiutato
+        let mut invalid = Block::new(a1.hash(), vec![Transaction::coinbase(b"a", 2, 50)], 0x1d00ffff, 2);
+        invalid.header.nonce = 1;
+        assert!(chain.accept_block(invalid).is_err());
+    }
+
+    #[test]
+    fn test_reject_side_block_with_bad_context() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut chain, genesis) = chain(&temp_dir);
+        let a1 = mine(&genesis, vec![Transaction::coinbase(b"a", 1, 50)]);
+        let a2 = mine(&a1, vec![Transaction::coinbase(b"a", 2, 50)]);
+        chain.accept_block(a1.clone()).unwrap();
+        chain.accept_block(a2.clone()).unwrap();
+
+        // Bits diversi da quelli attesi dal retarget: rifiutato e marcato invalido
+        let mut harder = Block::new(a1.hash(), vec![Transaction::coinbase(b"b", 2, 50)], 0x1f7fffff, 2);
+        harder.header.timestamp = a1.header.timestamp + 1;
+        let harder = solve(harder);
+        let error = chain.accept_block(harder.clone()).unwrap_err();
+        assert!(matches!(error, StandaloneError::Invalid(ValidationError::BadDifficulty { .. })));
+        assert!(chain.db().get_invalid_block(&harder.hash()).unwrap().is_some());
+
+        // Timestamp non oltre la median time past: rifiutato e marcato invalido
+        let mut old = Block::new(a1.hash(), vec![Transaction::coinbase(b"c", 2, 50)], 0x207fffff, 2);
+        old.header.timestamp = genesis.header.timestamp;
+        let old = solve(old);
+        let error = chain.accept_block(old.clone()).unwrap_err();
+        assert!(matches!(error, StandaloneError::Invalid(ValidationError::TimeTooOld { .. })));
+        assert!(chain.db().get_invalid_block(&old.hash()).unwrap().is_some());
+
+        // Troppo nel futuro: rifiutato ma non invalido, potrà essere accettato più avanti
+        let mut future = Block::new(a1.hash(), vec![Transaction::coinbase(b"d", 2, 50)], 0x207fffff, 2);
+        future.header.timestamp = BlockHeader::current_timestamp() + MAX_FUTURE_BLOCK_TIME + 60;
+        let future = solve(future);
+        let error = chain.accept_block(future.clone()).unwrap_err();
+        assert!(matches!(error, StandaloneError::Invalid(ValidationError::TimeTooNew { .. })));
+        assert!(chain.db().get_invalid_block(&future.hash()).unwrap().is_none());
+        assert!(!chain.core().headers().lock().unwrap().contains(&future.hash()));
+    }
+}
@@ -0,0 +1,200 @@
+//! Output di stato per i contratti eUTXO (pattern "state thread")
+//!
+//! Un output di stato porta un datum inline davanti al validator:
+//! `<datum> OP_DROP <validator>`. All'esecuzione il datum viene scartato e
+//! il validator decide come spendere l'output. Con `OP_CHECKSTATEVERIFY`
+//! il validator richiede che la transazione che lo spende ricrei un output
+//! con lo stesso validator e un datum aggiornato: lo stato prosegue di
+//! transazione in transazione come una macchina a stati on-chain.
+//!
+//! I datum occupano spazio nell'UTXO set finché l'output non è speso: oltre
+//! `DATUM_FREE_BYTES` ogni byte richiede una fee aggiuntiva.
+
+use crate::interpreter::{next_instruction, MAX_SCRIPT_ELEMENT_SIZE};
+use crate::script::opcodes::OP_DROP;
+use crate::script::push_data;
+use crate::{Transaction, TxOutput};
+
+/// Bytes di datum per output senza fee aggiuntiva
+pub const DATUM_FREE_BYTES: usize = 64;
+
+/// Fee aggiuntiva in satoshi per ogni byte di datum oltre `DATUM_FREE_BYTES`
+pub const DATUM_FEE_PER_BYTE: u64 = 10;
+
+/// Script di un output di stato, diviso in datum e validator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateScript<'a> {
+    /// Dati dello stato
+    pub datum: &'a [u8],
+    /// Script che valida la spesa
+    pub validator: &'a [u8],
+}
+
+impl<'a> StateScript<'a> {
+    /// Riconosce `<datum> OP_DROP <validator>` con un validator non vuoto
+    pub fn parse(script: &'a [u8]) -> Option<Self> {
+        if script.is_empty() {
+            return None;
+        }
+        let mut pc = 0;
+        let (_, datum) = next_instruction(script, &mut pc).ok()?;
+        let datum = datum?;
+        if script.get(pc) != Some(&OP_DROP) || pc + 1 == script.len() {
+            return None;
+        }
+        Some(Self { datum, validator: &script[pc + 1..] })
+    }
+}
+
+/// Costruisce lo script di un output di stato
+pub fn state_script(datum: &[u8], validator: &[u8]) -> Result<Vec<u8>, StateError> {
+    if datum.len() > MAX_SCRIPT_ELEMENT_SIZE {
+        return Err(StateError::DatumTooLarge(datum.len()));
+    }
+    if validator.is_empty() {
+        return Err(StateError::EmptyValidator);
+    }
+    let mut script = Vec::with_capacity(datum.len() + validator.len() + 4);
+    push_data(&mut script, datum);
+    script.push(OP_DROP);
+    script.extend_from_slice(validator);
+    Ok(script)
+}
+
+/// Validator di uno script: senza datum è lo script intero
+pub fn validator_of(script: &[u8]) -> &[u8] {
+    StateScript::parse(script).map_or(script, |state| state.validator)
+}
+
+/// Dimensione del datum di uno script, 0 se non è un output di stato
+pub fn datum_size(script: &[u8]) -> usize {
+    StateScript::parse(script).map_or(0, |state| state.datum.len())
+}
+
+/// Fee aggiuntiva richiesta dai datum oltre la soglia gratuita
+pub fn datum_surcharge(tx: &Transaction) -> u64 {
+    tx.outputs
+        .iter()
+        .map(|output| datum_size(&output.script_pubkey).saturating_sub(DATUM_FREE_BYTES) as u64)
+        .fold(0u64, |total, bytes| total.saturating_add(bytes.saturating_mul(DATUM_FEE_PER_BYTE)))
+}
+
+/// Output che ricrea `spent` con un nuovo datum, stesso valore e asset
+pub fn continuation(spent: &TxOutput, datum: &[u8]) -> Result<TxOutput, StateError> {
+    let state = StateScript::parse(&spent.script_pubkey).ok_or(StateError::NotStateOutput)?;
+    Ok(TxOutput::new(spent.value, spent.asset_id, state_script(datum, state.validator)?))
+}
+
+/// Indice dell'output di `tx` che continua lo stato di `spent`
+///
+/// La regola di `OP_CHECKSTATEVERIFY` più lo stesso asset: qui l'output
+/// speso è noto, mentre l'opcode vede solo lo script.
+pub fn check_continuation(tx: &Transaction, spent: &TxOutput) -> Result<usize, StateError> {
+    let state = StateScript::parse(&spent.script_pubkey).ok_or(StateError::NotStateOutput)?;
+    tx.outputs
+        .iter()
+        .position(|output| {
+            output.asset_id == spent.asset_id && validator_of(&output.script_pubkey) == state.validator
+        })
+        .ok_or(StateError::MissingContinuation)
+}
+
+/// Errori degli output di stato
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StateError {
+    #[error("Output is not a state output")]
+    NotStateOutput,
+
+    #[error("Transaction does not recreate the state output")]
+    MissingContinuation,
+
+    #[error("Datum of {0} bytes exceeds the script element limit")]
+    DatumTooLarge(usize),
+
+    #[error("State output needs a validator script")]
+    EmptyValidator,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{verify_script, InterpreterError, TransactionChecker, VerifyFlags};
+    use crate::script::opcodes::{OP_CHECKSIG, OP_CHECKSTATEVERIFY};
+    use crate::sighash::{signature_hash, SIGHASH_ALL};
+    use crate::{OutPoint, TxInput};
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+    #[test]
+    fn test_state_script_roundtrip() {
+        let script = state_script(b"counter=1", &[OP_CHECKSTATEVERIFY]).unwrap();
+        let state = StateScript::parse(&script).unwrap();
+        assert_eq!((state.datum, state.validator), (&b"counter=1"[..], &[OP_CHECKSTATEVERIFY][..]));
+        assert_eq!(validator_of(&script), [OP_CHECKSTATEVERIFY]);
+
+        // Datum vuoto e datum lungo (PUSHDATA2)
+        assert_eq!(StateScript::parse(&state_script(b"", b"v").unwrap()).unwrap().datum, b"");
+        let long = vec![7; MAX_SCRIPT_ELEMENT_SIZE];
+        assert_eq!(StateScript::parse(&state_script(&long, b"v").unwrap()).unwrap().datum, &long[..]);
+
+        assert_eq!(state_script(&[0; MAX_SCRIPT_ELEMENT_SIZE + 1], b"v"), Err(StateError::DatumTooLarge(521)));
+        assert_eq!(state_script(b"datum", b""), Err(StateError::EmptyValidator));
+        assert_eq!(StateScript::parse(&[0x01, 0x2a, OP_DROP]), None);
+        assert_eq!(StateScript::parse(&[OP_CHECKSIG]), None);
+        assert_eq!(validator_of(&[OP_CHECKSIG]), [OP_CHECKSIG]);
+    }
+
+    #[test]
+    fn test_datum_surcharge() {
+        let output = |datum: &[u8]| TxOutput::new(1_000, [0; 32], state_script(datum, b"v").unwrap());
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([9; 32], 0), Vec::new())],
+            vec![output(&[1; DATUM_FREE_BYTES]), output(&[2; DATUM_FREE_BYTES + 100]), TxOutput::to_address(1, b"bob")],
+            0,
+        );
+        assert_eq!(datum_size(&tx.outputs[1].script_pubkey), DATUM_FREE_BYTES + 100);
+        assert_eq!(datum_size(&tx.outputs[2].script_pubkey), 0);
+        // Solo i bytes oltre la soglia del secondo datum
+        assert_eq!(datum_surcharge(&tx), 100 * DATUM_FEE_PER_BYTE);
+    }
+
+    #[test]
+    fn test_state_thread() {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let mut validator = vec![OP_CHECKSTATEVERIFY];
+        push_data(&mut validator, &PublicKey::from_secret_key(&secp, &key).serialize());
+        validator.push(OP_CHECKSIG);
+
+        let spent = TxOutput::new(1_000, [0; 32], state_script(b"1", &validator).unwrap());
+        let next = continuation(&spent, b"2").unwrap();
+        assert_eq!((next.value.to_sat(), StateScript::parse(&next.script_pubkey).unwrap().datum), (1_000, &b"2"[..]));
+
+        let sign = |mut tx: Transaction| {
+            let digest = signature_hash(&tx, 0, &spent.script_pubkey);
+            let signature = secp.sign_ecdsa(&Message::from_slice(&digest).unwrap(), &key);
+            let mut der = signature.serialize_der().to_vec();
+            der.push(SIGHASH_ALL);
+            push_data(&mut tx.inputs[0].script_sig, &der);
+            tx
+        };
+        let input = TxInput::new(OutPoint::new([9; 32], 0), Vec::new());
+        let verify = |tx: &Transaction| {
+            let checker = TransactionChecker::new(tx, 0);
+            verify_script(&tx.inputs[0].script_sig, &spent.script_pubkey, VerifyFlags::STANDARD, &checker)
+        };
+
+        // Lo stato prosegue: il validator accetta la spesa
+        let continued = sign(Transaction::new(vec![input.clone()], vec![next.clone()], 0));
+        assert_eq!(check_continuation(&continued, &spent), Ok(0));
+        assert_eq!(verify(&continued), Ok(()));
+
+        // Firma valida ma nessun output ricrea lo stato
+        let ended = sign(Transaction::new(vec![input], vec![TxOutput::to_address(1_000, b"bob")], 0));
+        assert_eq!(check_continuation(&ended, &spent), Err(StateError::MissingContinuation));
+        assert_eq!(verify(&ended), Err(InterpreterError::Continuation));
+
+        let plain = TxOutput::to_address(1_000, b"bob");
+        assert_eq!(continuation(&plain, b"2"), Err(StateError::NotStateOutput));
+        assert_eq!(check_continuation(&continued, &plain), Err(StateError::NotStateOutput));
+    }
+}
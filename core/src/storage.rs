@@ -1,10 +1,16 @@
 //! Blockchain storage layer usando RocksDB
 
-use crate::{Block, Transaction, TxOutput, OutPoint};
+use crate::cache::{CacheConfig, CacheStats, ChainCache};
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::fault::{FaultInjector, WriteFault};
+use crate::{Block, BlockHash, BlockHeader, MuHash, Transaction, TxOutput, OutPoint};
 use rocksdb::{DB, Options, ColumnFamily, ColumnFamilyDescriptor, WriteBatch};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 /// Column families per diversi tipi di dati
 const CF_BLOCKS: &str = "blocks";           // block_hash -> Block
@@ -12,24 +18,132 @@ const CF_BLOCK_INDEX: &str = "block_index"; // height -> block_hash
 const CF_UTXO: &str = "utxo";              // OutPoint -> TxOutput
 const CF_METADATA: &str = "metadata";       // chiavi varie -> valori
 const CF_TX_INDEX: &str = "tx_index";      // tx_hash -> (block_hash, tx_index)
+const CF_STAGED: &str = "staged";          // block_hash -> Block scaricato ma non connesso
+const CF_INVALID: &str = "invalid_blocks"; // block_hash -> InvalidBlock
+const CF_REORGS: &str = "reorgs";          // sequenza -> ReorgRecord
+const CF_MEMPOOL_SPILL: &str = "mempool_spill"; // (feerate, txid) -> transazione espulsa dalla mempool
+const CF_UTXO_HASH: &str = "utxo_hash";    // block_hash -> MuHash del UTXO set dopo il block
+
+/// Tutte le column families del database
+const COLUMN_FAMILIES: [&str; 10] = [
+    CF_BLOCKS, CF_BLOCK_INDEX, CF_UTXO, CF_METADATA, CF_TX_INDEX, CF_STAGED, CF_INVALID, CF_REORGS, CF_MEMPOOL_SPILL,
+    CF_UTXO_HASH,
+];
 
 /// Chiavi per metadata
 const META_BEST_BLOCK: &str = "best_block_hash";
 const META_HEIGHT: &str = "blockchain_height";
 const META_TOTAL_WORK: &str = "total_work";
 const META_GENESIS_HASH: &str = "genesis_hash";
+const META_NETWORK_MAGIC: &str = "network_magic";
+const META_INITIALIZED: &str = "initialized";
 
 /// Blockchain database manager
+///
+/// Modello a scrittore singolo: le operazioni che modificano il chain state
+/// (`store_block`, `flush_block`, `disconnect_tip`, `clear_derived_state`,
+/// ...) sono serializzate da un lock interno, così due thread non possono
+/// intrecciare gli aggiornamenti del best block. Le letture non prendono
+/// il lock.
 pub struct BlockchainDB {
     /// RocksDB instance
     db: Arc<DB>,
+    /// Lock dello scrittore singolo del chain state
+    writer: Mutex<()>,
+    /// Cache LRU di block e header
+    cache: Mutex<ChainCache>,
+    /// Guasti iniettati nei test
+    #[cfg(any(test, feature = "fault-injection"))]
+    faults: Mutex<Option<Arc<FaultInjector>>>,
+}
+
+/// Ogni quante entry una scansione controlla la cancellazione
+const SCAN_CANCEL_CHECK_INTERVAL: u64 = 1_000;
+
+/// Token di cancellazione cooperativa per operazioni lunghe sul database
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Crea nuovo token non cancellato
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Richiede la cancellazione dell'operazione
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Verifica se è stata richiesta la cancellazione
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Risultato di una scansione del UTXO set
+#[derive(Debug, Clone)]
+pub struct UtxoScan {
+    /// UTXO selezionati dal filtro
+    pub matches: Vec<(OutPoint, UtxoEntry)>,
+    /// Numero di UTXO esaminati
+    pub scanned: u64,
+}
+
+/// Statistiche del UTXO set a un tip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoSetStats {
+    /// Altezza del tip a cui si riferiscono
+    pub height: u64,
+    /// Hash del tip
+    pub best_block_hash: [u8; 32],
+    /// Transazioni con almeno un output non speso
+    pub transactions: u64,
+    /// Numero di output non spesi
+    pub txouts: u64,
+    /// Totale SLY nativo
+    pub total_amount: u64,
+    /// Totali degli altri asset per asset id
+    pub asset_amounts: BTreeMap<[u8; 32], u64>,
+    /// Dimensione serializzata (chiavi + valori) in bytes
+    pub serialized_size: u64,
+    /// Commitment del UTXO set: double SHA-256 delle coppie chiave/valore in ordine di chiave
+    pub hash: [u8; 32],
+    /// MuHash delle coppie chiave/valore, uguale a quello salvato per il tip
+    pub muhash: [u8; 32],
+}
+
+/// Scritture di un block preparate ma non ancora applicate al database
+pub struct PendingBlock {
+    /// Batch atomico con block, indici e modifiche al UTXO set
+    batch: WriteBatch,
+    /// Hash del block
+    hash: [u8; 32],
+    /// Altezza del block
+    height: u64,
+    /// Tip del database quando le scritture sono state preparate
+    base_tip: [u8; 32],
+}
+
+impl PendingBlock {
+    /// Hash del block
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Altezza del block
+    pub fn height(&self) -> u64 {
+        self.height
+    }
 }
 
 /// Informazioni su una transazione nell'indice
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TxLocation {
     /// Hash del block contenente la transazione
-    pub block_hash: [u8; 32],
+    pub block_hash: BlockHash,
     /// Indice della transazione nel block
     pub tx_index: u32,
     /// Altezza del block
@@ -49,6 +163,40 @@ pub struct ChainMetadata {
     pub genesis_hash: [u8; 32],
 }
 
+/// Block marcato come invalido
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidBlock {
+    /// Altezza del block
+    pub height: u64,
+    /// Hash del parent
+    pub previous_hash: [u8; 32],
+    /// Motivo del rifiuto
+    pub reason: String,
+}
+
+/// Riorganizzazione della chain attiva registrata nel database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReorgRecord {
+    /// Tip prima della riorganizzazione
+    pub old_tip: BlockHash,
+    /// Altezza del vecchio tip
+    pub old_height: u64,
+    /// Tip dopo la riorganizzazione
+    pub new_tip: BlockHash,
+    /// Altezza del nuovo tip
+    pub new_height: u64,
+    /// Altezza dell'ultimo block comune ai due rami
+    pub fork_height: u64,
+    /// Block scollegati (profondità della riorganizzazione)
+    pub depth: u64,
+    /// Block connessi sul nuovo ramo
+    pub connected: u64,
+    /// Inizio (secondi Unix)
+    pub started_at: u64,
+    /// Fine (secondi Unix)
+    pub finished_at: u64,
+}
+
 /// UTXO entry nel database
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UtxoEntry {
@@ -75,22 +223,178 @@ impl BlockchainDB {
         opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
 
         // Definisci column families
-        let cfs = vec![
-            ColumnFamilyDescriptor::new(CF_BLOCKS, Options::default()),
-            ColumnFamilyDescriptor::new(CF_BLOCK_INDEX, Options::default()),
-            ColumnFamilyDescriptor::new(CF_UTXO, Options::default()),
-            ColumnFamilyDescriptor::new(CF_METADATA, Options::default()),
-            ColumnFamilyDescriptor::new(CF_TX_INDEX, Options::default()),
-        ];
+        let cfs = COLUMN_FAMILIES
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
 
         let db = DB::open_cf_descriptors(&opts, path, cfs)
             .map_err(|e| StorageError::DatabaseOpen(e.to_string()))?;
 
         Ok(Self {
             db: Arc::new(db),
+            writer: Mutex::new(()),
+            cache: Mutex::new(ChainCache::new(CacheConfig::default())),
+            #[cfg(any(test, feature = "fault-injection"))]
+            faults: Mutex::new(None),
         })
     }
 
+    /// Apre il database come istanza secondaria (sola lettura) di un nodo in esecuzione
+    ///
+    /// `secondary_path` è una directory privata per i log dell'istanza secondaria.
+    /// Usare `catch_up_with_primary` per vedere le scritture più recenti del primario.
+    pub fn open_secondary<P: AsRef<Path>>(primary_path: P, secondary_path: P) -> Result<Self, StorageError> {
+        let mut opts = Options::default();
+        opts.set_max_open_files(-1);
+
+        let db = DB::open_cf_as_secondary(&opts, primary_path, secondary_path, COLUMN_FAMILIES)
+            .map_err(|e| StorageError::DatabaseOpen(e.to_string()))?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            writer: Mutex::new(()),
+            cache: Mutex::new(ChainCache::new(CacheConfig::default())),
+            #[cfg(any(test, feature = "fault-injection"))]
+            faults: Mutex::new(None),
+        })
+    }
+
+    /// Allinea un'istanza secondaria alle ultime scritture del primario
+    pub fn catch_up_with_primary(&self) -> Result<(), StorageError> {
+        self.db.try_catch_up_with_primary()
+            .map_err(|e| StorageError::Read(e.to_string()))?;
+        // Il primario può aver cambiato la chain attiva
+        self.lock_cache().invalidate_headers();
+        Ok(())
+    }
+
+    /// Sostituisce le cache di block e header con cache della capacità data
+    pub fn with_cache_config(self, config: CacheConfig) -> Self {
+        *self.lock_cache() = ChainCache::new(config);
+        self
+    }
+
+    /// Contatori e occupazione delle cache
+    pub fn cache_stats(&self) -> CacheStats {
+        self.lock_cache().stats()
+    }
+
+    /// Porta su disco WAL e memtable
+    ///
+    /// Ogni block è scritto con un solo batch atomico e le cache tengono solo
+    /// dati già scritti, quindi non ci sono modifiche a metà da completare:
+    /// dopo il flush il tip nei metadata è l'ultimo block durevole e la
+    /// riapertura non deve rigiocare il WAL, anche se il processo termina
+    /// senza chiudere il database. Non prende il lock dello scrittore, così
+    /// si può chiamare anche da un thread che lo tiene (es. in un panic).
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush_wal(true)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        self.db.flush()
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Verifica che il database appartenga alla rete con i magic bytes dati
+    ///
+    /// Un database senza marker (nuovo o creato da versioni precedenti) viene
+    /// marcato con `magic`; uno di un'altra rete viene rifiutato.
+    pub fn check_network_magic(&self, magic: [u8; 4]) -> Result<(), StorageError> {
+        match self.network_magic()? {
+            Some(found) if found == magic => Ok(()),
+            Some(found) => Err(StorageError::NetworkMismatch { expected: magic, found }),
+            None => {
+                let _writer = self.lock_writer();
+                let metadata_cf = self.get_cf(CF_METADATA)?;
+                self.db.put_cf(metadata_cf, META_NETWORK_MAGIC, magic)
+                    .map_err(|e| StorageError::Write(e.to_string()))
+            }
+        }
+    }
+
+    /// Magic bytes della rete registrati nel database
+    pub fn network_magic(&self) -> Result<Option<[u8; 4]>, StorageError> {
+        let metadata_cf = self.get_cf(CF_METADATA)?;
+        self.db.get_cf(metadata_cf, META_NETWORK_MAGIC)
+            .map_err(|e| StorageError::Read(e.to_string()))?
+            .map(|bytes| {
+                bytes.as_slice().try_into().map_err(|_| {
+                    StorageError::corrupt_metadata(META_NETWORK_MAGIC, format!("{} bytes, expected 4", bytes.len()))
+                })
+            })
+            .transpose()
+    }
+
+    /// Verifica all'avvio la forma dei metadati e la loro coerenza con i block salvati
+    ///
+    /// Fallisce con `StorageError::CorruptMetadata` (che suggerisce reindex o
+    /// restore di un backup) invece di lasciare che il nodo parta da un
+    /// tip sbagliato.
+    pub fn check_db(&self) -> Result<(), StorageError> {
+        self.network_magic()?;
+        let metadata = self.get_metadata()?;
+        if metadata.best_block_hash == [0; 32] {
+            return Ok(());
+        }
+
+        let tip = self.get_block(&metadata.best_block_hash)?.ok_or_else(|| {
+            StorageError::corrupt_metadata(
+                META_BEST_BLOCK,
+                format!("block {} is not stored", hex::encode(metadata.best_block_hash)),
+            )
+        })?;
+        if tip.header.height != metadata.height {
+            return Err(StorageError::corrupt_metadata(
+                META_HEIGHT,
+                format!("{} but the best block is at height {}", metadata.height, tip.header.height),
+            ));
+        }
+        if metadata.genesis_hash != [0; 32] {
+            let genesis = self.get_block_by_height(0)?.map(|block| block.hash());
+            if genesis != Some(metadata.genesis_hash) {
+                return Err(StorageError::corrupt_metadata(META_GENESIS_HASH, "does not match the block at height 0"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apre uno snapshot per letture consistenti su più chiavi
+    pub fn snapshot(&self) -> ChainSnapshot<'_> {
+        #[cfg(any(test, feature = "fault-injection"))]
+        self.delay_read();
+        ChainSnapshot { db: self, snapshot: self.db.snapshot() }
+    }
+
+    /// Installa (o con None rimuove) un injector di guasti per scritture dei block e letture
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn inject_faults(&self, injector: Option<Arc<FaultInjector>>) {
+        *self.faults.lock().unwrap_or_else(PoisonError::into_inner) = injector;
+    }
+
+    #[cfg(any(test, feature = "fault-injection"))]
+    fn fault_injector(&self) -> Option<Arc<FaultInjector>> {
+        self.faults.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    #[cfg(any(test, feature = "fault-injection"))]
+    fn delay_read(&self) {
+        if let Some(injector) = self.fault_injector() {
+            injector.delay_read();
+        }
+    }
+
+    /// Acquisisce il lock dello scrittore singolo
+    ///
+    /// Il lock non protegge dati, quindi un thread andato in panic mentre
+    /// lo teneva non lo rende inutilizzabile.
+    fn lock_writer(&self) -> MutexGuard<'_, ()> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquisisce la cache; anche qui un panic non la rende inutilizzabile
+    fn lock_cache(&self) -> MutexGuard<'_, ChainCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// Ottiene column family handle
     fn get_cf(&self, name: &str) -> Result<&ColumnFamily, StorageError> {
         self.db.cf_handle(name)
@@ -98,10 +402,38 @@ impl BlockchainDB {
     }
 
     /// Salva un nuovo block nella blockchain
+    ///
+    /// Il block deve estendere il tip: un block già in chain, un block a
+    /// un'altezza già occupata o con un parent diverso dal tip è rifiutato
+    /// (`DuplicateBlock`, `StaleBlock`, `OrphanBlock`) invece di riscrivere
+    /// l'indice delle altezze. I cambi di chain passano dal reorg (`reorg`).
     pub fn store_block(&self, block: &Block) -> Result<(), StorageError> {
-        let mut batch = WriteBatch::default();
+        let _writer = self.lock_writer();
+        let pending = self.connect_block_with_txids(block, &block.txids())?;
+        self.write_pending(pending)
+    }
+
+    /// Prepara in memoria le scritture di un block (block, indici, UTXO set)
+    ///
+    /// Il database non cambia finché il risultato non passa a `flush_block`.
+    pub fn connect_block(&self, block: &Block) -> Result<PendingBlock, StorageError> {
+        self.connect_block_with_txids(block, &block.txids())
+    }
+
+    /// Come `connect_block`, con i txid già calcolati (`Block::txids`)
+    pub fn connect_block_with_txids(&self, block: &Block, txids: &[[u8; 32]]) -> Result<PendingBlock, StorageError> {
+        if txids.len() != block.transactions.len() {
+            return Err(StorageError::InvalidData(format!(
+                "{} txids for {} transactions",
+                txids.len(),
+                block.transactions.len()
+            )));
+        }
+        let base_tip = self.get_best_block_hash()?;
         let block_hash = block.hash();
         let height = block.header.height;
+        self.check_extends_tip(&block.header, block_hash)?;
+        let mut batch = WriteBatch::default();
 
         // Serializza il block
         let block_bytes = bincode::serialize(block)
@@ -116,42 +448,131 @@ impl BlockchainDB {
         batch.put_cf(index_cf, &height.to_be_bytes(), &block_hash);
 
         // Aggiorna UTXO set per ogni transazione
-        for (tx_index, transaction) in block.transactions.iter().enumerate() {
+        let mut utxo_hash = self.parent_utxo_hash(&block.header)?;
+        let mut created = BTreeMap::new();
+        for (tx_index, (transaction, txid)) in block.transactions.iter().zip(txids).enumerate() {
             self.update_utxo_for_transaction(
                 &mut batch,
                 transaction,
+                *txid,
                 block_hash,
                 height,
-                tx_index as u32
+                tx_index as u32,
+                &mut UtxoChanges { hash: &mut utxo_hash, created: &mut created },
             )?;
         }
+        for (key, value) in &created {
+            utxo_hash.insert(&utxo_hash_element(key, value));
+        }
+        batch.put_cf(self.get_cf(CF_UTXO_HASH)?, block_hash, utxo_hash.to_bytes());
 
         // Aggiorna metadati se questo è il nuovo best block
         self.update_best_block(&mut batch, block_hash, height)?;
 
-        // Commit atomico
-        self.db.write(batch)
-            .map_err(|e| StorageError::Write(e.to_string()))?;
+        Ok(PendingBlock { batch, hash: block_hash, height, base_tip })
+    }
+
+    /// Verifica che il block `block_hash` con header `header` estenda il tip
+    fn check_extends_tip(&self, header: &BlockHeader, block_hash: [u8; 32]) -> Result<(), StorageError> {
+        let metadata = self.get_metadata()?;
+        let empty = metadata.best_block_hash == [0; 32];
+        let next_height = if empty { 0 } else { metadata.height + 1 };
+        if header.previous_hash == metadata.best_block_hash && header.height == next_height {
+            return Ok(());
+        }
+
+        let index_cf = self.get_cf(CF_BLOCK_INDEX)?;
+        let indexed = self.db.get_cf(index_cf, header.height.to_be_bytes())
+            .map_err(|e| StorageError::Read(e.to_string()))?;
+        if indexed.as_deref() == Some(&block_hash[..]) {
+            return Err(StorageError::DuplicateBlock { hash: block_hash });
+        }
+        if !empty && header.height <= metadata.height {
+            return Err(StorageError::StaleBlock {
+                hash: block_hash,
+                height: header.height,
+                tip_height: metadata.height,
+            });
+        }
+        Err(StorageError::OrphanBlock { hash: block_hash, previous_hash: header.previous_hash })
+    }
+
+    /// Scrive atomicamente un block preparato da `connect_block`
+    ///
+    /// Fallisce con `StorageError::TipChanged` se nel frattempo un altro
+    /// scrittore ha spostato il tip: le scritture preparate non sono più
+    /// valide e il block va ricollegato.
+    pub fn flush_block(&self, pending: PendingBlock) -> Result<(), StorageError> {
+        let _writer = self.lock_writer();
+        self.write_pending(pending)
+    }
 
+    /// Scrive un block preparato; il chiamante tiene il lock dello scrittore
+    fn write_pending(&self, pending: PendingBlock) -> Result<(), StorageError> {
+        let tip = self.get_best_block_hash()?;
+        if tip != pending.base_tip {
+            return Err(StorageError::TipChanged { expected: pending.base_tip, found: tip });
+        }
+        #[cfg(any(test, feature = "fault-injection"))]
+        let fault = self.fault_injector().and_then(|injector| injector.write_fault());
+        #[cfg(any(test, feature = "fault-injection"))]
+        if fault == Some(WriteFault::Error) {
+            return Err(StorageError::Write("injected write failure".to_string()));
+        }
+        self.db.write(pending.batch)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        self.lock_cache().invalidate_height(pending.height);
+        #[cfg(any(test, feature = "fault-injection"))]
+        if fault == Some(WriteFault::Partial) {
+            return Err(StorageError::Write("injected failure after the batch was written".to_string()));
+        }
         Ok(())
     }
 
+    /// MuHash del UTXO set al parent di `header`, da cui parte quello del block
+    ///
+    /// Un database creato prima del hash rolling non ha lo stato del parent:
+    /// viene calcolato una volta scandendo il UTXO set, che è quello del
+    /// parent perché il block estende il tip.
+    fn parent_utxo_hash(&self, header: &BlockHeader) -> Result<MuHash, StorageError> {
+        if header.height == 0 {
+            return Ok(MuHash::new());
+        }
+        if let Some(state) = self.get_utxo_hash_state(&header.previous_hash)? {
+            return Ok(state);
+        }
+        log::info!("Computing the rolling UTXO set hash at height {} from a full scan", header.height - 1);
+        self.snapshot().utxo_set_muhash(&CancellationToken::new())
+    }
+
+    /// Stato MuHash salvato per il block `block_hash`
+    fn get_utxo_hash_state(&self, block_hash: &[u8; 32]) -> Result<Option<MuHash>, StorageError> {
+        self.snapshot().get_utxo_hash_state(block_hash)
+    }
+
+    /// MuHash del UTXO set dopo il block `block_hash`, None per i block mai collegati
+    pub fn get_utxo_hash(&self, block_hash: &[u8; 32]) -> Result<Option<[u8; 32]>, StorageError> {
+        self.snapshot().get_utxo_hash(block_hash)
+    }
+
     /// Aggiorna UTXO set per una transazione
+    #[allow(clippy::too_many_arguments)]
     fn update_utxo_for_transaction(
         &self,
         batch: &mut WriteBatch,
         tx: &Transaction,
+        tx_hash: [u8; 32],
         block_hash: [u8; 32],
         block_height: u64,
         tx_index: u32,
+        changes: &mut UtxoChanges<'_>,
     ) -> Result<(), StorageError> {
         let utxo_cf = self.get_cf(CF_UTXO)?;
         let tx_cf = self.get_cf(CF_TX_INDEX)?;
-        let tx_hash = tx.hash();
 
         // Salva indice transazione: tx_hash -> location
         let tx_location = TxLocation {
-            block_hash,
+            block_hash: block_hash.into(),
             tx_index,
             block_height,
         };
@@ -159,10 +580,17 @@ impl BlockchainDB {
             .map_err(|e| StorageError::Serialization(e.to_string()))?;
         batch.put_cf(tx_cf, &tx_hash, &location_bytes);
 
-        // Rimuovi UTXO spesi (inputs)
+        // Rimuovi UTXO spesi (inputs); quelli creati nello stesso block non entrano nel hash
         if !tx.is_coinbase() {
             for input in &tx.inputs {
                 let outpoint_key = self.outpoint_key(&input.previous_output);
+                if changes.created.remove(&outpoint_key).is_none() {
+                    let spent = self.db.get_cf(utxo_cf, &outpoint_key)
+                        .map_err(|e| StorageError::Read(e.to_string()))?;
+                    if let Some(value) = spent {
+                        changes.hash.remove(&utxo_hash_element(&outpoint_key, &value));
+                    }
+                }
                 batch.delete_cf(utxo_cf, &outpoint_key);
             }
         }
@@ -182,11 +610,66 @@ impl BlockchainDB {
                 .map_err(|e| StorageError::Serialization(e.to_string()))?;
 
             batch.put_cf(utxo_cf, &outpoint_key, &utxo_bytes);
+            changes.created.insert(outpoint_key, utxo_bytes);
         }
 
         Ok(())
     }
 
+    /// Scollega il block di tip, riportando UTXO set e indici al parent
+    ///
+    /// Gli output spesi dal block vengono ricostruiti dall'indice delle
+    /// transazioni. Il block resta salvato e può essere ricollegato.
+    pub fn disconnect_tip(&self) -> Result<Block, StorageError> {
+        let _writer = self.lock_writer();
+        let metadata = self.get_metadata()?;
+        if metadata.height == 0 {
+            return Err(StorageError::InvalidData("cannot disconnect the genesis block".to_string()));
+        }
+        let block = self.get_block(&metadata.best_block_hash)?
+            .ok_or(StorageError::BlockNotFound { hash: metadata.best_block_hash })?;
+
+        let utxo_cf = self.get_cf(CF_UTXO)?;
+        let tx_cf = self.get_cf(CF_TX_INDEX)?;
+        let index_cf = self.get_cf(CF_BLOCK_INDEX)?;
+        let mut batch = WriteBatch::default();
+
+        // In ordine inverso: gli output creati e spesi nello stesso block restano eliminati
+        for (tx, tx_hash) in block.transactions.iter().zip(block.txids()).rev() {
+            for vout in 0..tx.outputs.len() {
+                batch.delete_cf(utxo_cf, self.outpoint_key(&OutPoint::new(tx_hash, vout as u32)));
+            }
+            batch.delete_cf(tx_cf, tx_hash);
+
+            if tx.is_coinbase() {
+                continue;
+            }
+            for input in &tx.inputs {
+                let outpoint = &input.previous_output;
+                let (previous, location) = self.get_transaction(&outpoint.txid)?
+                    .ok_or(StorageError::UtxoNotFound { outpoint: outpoint.clone() })?;
+                let output = previous.outputs.get(outpoint.vout as usize)
+                    .ok_or(StorageError::UtxoNotFound { outpoint: outpoint.clone() })?;
+                let entry = UtxoEntry {
+                    output: output.clone(),
+                    block_height: location.block_height,
+                    is_coinbase: location.tx_index == 0,
+                };
+                let entry_bytes = bincode::serialize(&entry)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                batch.put_cf(utxo_cf, self.outpoint_key(outpoint), entry_bytes);
+            }
+        }
+
+        batch.delete_cf(index_cf, metadata.height.to_be_bytes());
+        self.update_best_block(&mut batch, block.header.previous_hash, metadata.height - 1)?;
+
+        self.db.write(batch)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        self.lock_cache().invalidate_height(metadata.height);
+        Ok(block)
+    }
+
     /// Aggiorna il best block
     fn update_best_block(
         &self,
@@ -202,14 +685,18 @@ impl BlockchainDB {
         Ok(())
     }
 
-    /// Carica un block per hash
+    /// Carica un block per hash, passando dalla cache
     pub fn get_block(&self, block_hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
-        let blocks_cf = self.get_cf(CF_BLOCKS)?;
+        if let Some(block) = self.lock_cache().block(block_hash) {
+            return Ok(Some(block));
+        }
 
+        let blocks_cf = self.get_cf(CF_BLOCKS)?;
         match self.db.get_cf(blocks_cf, block_hash) {
             Ok(Some(block_bytes)) => {
-                let block = bincode::deserialize(&block_bytes)
+                let block: Block = bincode::deserialize(&block_bytes)
                     .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+                self.lock_cache().insert_block(&block);
                 Ok(Some(block))
             }
             Ok(None) => Ok(None),
@@ -218,27 +705,50 @@ impl BlockchainDB {
     }
 
     /// Carica un block per altezza
+    ///
+    /// Il block dell'hash indicizzato è immutabile, quindi indice e block
+    /// non richiedono uno snapshot e il block può venire dalla cache.
     pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, StorageError> {
         let index_cf = self.get_cf(CF_BLOCK_INDEX)?;
-
-        // Prima ottieni l'hash dalla height
-        match self.db.get_cf(index_cf, &height.to_be_bytes()) {
+        match self.db.get_cf(index_cf, height.to_be_bytes()) {
             Ok(Some(hash_bytes)) => {
-                if hash_bytes.len() == 32 {
-                    let mut block_hash = [0u8; 32];
-                    block_hash.copy_from_slice(&hash_bytes);
-                    self.get_block(&block_hash)
-                } else {
-                    Err(StorageError::InvalidData("Invalid block hash length".to_string()))
-                }
+                let block_hash: [u8; 32] = hash_bytes.as_slice().try_into()
+                    .map_err(|_| StorageError::InvalidData("Invalid block hash length".to_string()))?;
+                self.get_block(&block_hash)
             }
             Ok(None) => Ok(None),
             Err(e) => Err(StorageError::Read(e.to_string())),
         }
     }
 
+    /// Carica solo l'header di un block per altezza, passando dalla cache
+    pub fn get_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StorageError> {
+        let generation = {
+            let mut cache = self.lock_cache();
+            if let Some(header) = cache.header(height) {
+                return Ok(Some(header));
+            }
+            cache.generation()
+        };
+
+        let header = self.get_block_by_height(height)?.map(|block| block.header);
+        if let Some(header) = &header {
+            self.lock_cache().insert_header(height, header.clone(), generation);
+        }
+        Ok(header)
+    }
+
+    /// Carica gli header consecutivi nell'intervallo di altezze (estremi inclusi)
+    ///
+    /// Si ferma al primo buco nell'indice.
+    pub fn get_headers_in_range(&self, from_height: u64, to_height: u64) -> Result<Vec<BlockHeader>, StorageError> {
+        self.snapshot().get_headers_in_range(from_height, to_height)
+    }
+
     /// Ottiene un UTXO
     pub fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>, StorageError> {
+        #[cfg(any(test, feature = "fault-injection"))]
+        self.delay_read();
         let utxo_cf = self.get_cf(CF_UTXO)?;
         let key = self.outpoint_key(outpoint);
 
@@ -254,12 +764,17 @@ impl BlockchainDB {
     }
 
     /// Verifica se un UTXO esiste ed è spendibile
-    pub fn is_utxo_spendable(&self, outpoint: &OutPoint, current_height: u64) -> Result<bool, StorageError> {
+    pub fn is_utxo_spendable(
+        &self,
+        outpoint: &OutPoint,
+        current_height: u64,
+        params: &crate::ChainParams,
+    ) -> Result<bool, StorageError> {
         match self.get_utxo(outpoint)? {
             Some(utxo) => {
-                // I coinbase output richiedono 100 blocchi di maturazione
+                // I coinbase output richiedono `coinbase_maturity` blocchi di maturazione
                 if utxo.is_coinbase {
-                    let maturity_height = utxo.block_height + 100;
+                    let maturity_height = utxo.block_height + params.coinbase_maturity_at(utxo.block_height);
                     Ok(current_height >= maturity_height)
                 } else {
                     Ok(true)
@@ -271,64 +786,51 @@ impl BlockchainDB {
 
     /// Ottiene metadati della blockchain
     pub fn get_metadata(&self) -> Result<ChainMetadata, StorageError> {
-        let metadata_cf = self.get_cf(CF_METADATA)?;
-
-        // Best block hash
-        let best_block_hash = self.db.get_cf(metadata_cf, META_BEST_BLOCK)
-            .map_err(|e| StorageError::Read(e.to_string()))?
-            .map(|bytes| {
-                let mut hash = [0u8; 32];
-                hash.copy_from_slice(&bytes[..32]);
-                hash
-            })
-            .unwrap_or([0; 32]);
-
-        // Height
-        let height = self.db.get_cf(metadata_cf, META_HEIGHT)
-            .map_err(|e| StorageError::Read(e.to_string()))?
-            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap_or([0; 8])))
-            .unwrap_or(0);
-
-        // Genesis hash
-        let genesis_hash = self.db.get_cf(metadata_cf, META_GENESIS_HASH)
-            .map_err(|e| StorageError::Read(e.to_string()))?
-            .map(|bytes| {
-                let mut hash = [0u8; 32];
-                hash.copy_from_slice(&bytes[..32]);
-                hash
-            })
-            .unwrap_or([0; 32]);
-
-        Ok(ChainMetadata {
-            best_block_hash,
-            height,
-            total_work: 0, // TODO: calcolare total work
-            genesis_hash,
-        })
+        self.snapshot().get_metadata()
     }
 
     /// Inizializza il database con il genesis block
+    ///
+    /// Su un database già inizializzato verifica solo che il genesis sia lo
+    /// stesso (`StorageError::GenesisMismatch` altrimenti).
     pub fn initialize_with_genesis(&self, genesis: &Block) -> Result<(), StorageError> {
-        let metadata = self.get_metadata()?;
-
-        // Se già inizializzato, non fare nulla
-        if metadata.height > 0 {
-            return Ok(());
+        let _writer = self.lock_writer();
+        let metadata_cf = self.get_cf(CF_METADATA)?;
+        if self.is_initialized()? {
+            self.check_genesis(&genesis.hash())?;
+            // Database creato prima del flag: lo registra
+            return self.db.put_cf(metadata_cf, META_INITIALIZED, [1])
+                .map_err(|e| StorageError::Write(e.to_string()));
         }
 
-        let genesis_hash = genesis.hash();
-
-        // Salva genesis block
-        self.store_block(genesis)?;
+        // Genesis block, relativo hash e flag nei metadati in un'unica scrittura
+        let mut pending = self.connect_block(genesis)?;
+        pending.batch.put_cf(metadata_cf, META_GENESIS_HASH, genesis.hash());
+        pending.batch.put_cf(metadata_cf, META_INITIALIZED, [1]);
+        self.write_pending(pending)
+    }
 
-        // Salva hash genesis nei metadati
+    /// Se il database è stato inizializzato con un genesis
+    ///
+    /// Distingue un database vuoto da una chain con il solo genesis, entrambi
+    /// ad altezza 0. I database creati prima del flag contano come
+    /// inizializzati se hanno l'hash del genesis.
+    pub fn is_initialized(&self) -> Result<bool, StorageError> {
         let metadata_cf = self.get_cf(CF_METADATA)?;
-        let mut batch = WriteBatch::default();
-        batch.put_cf(metadata_cf, META_GENESIS_HASH, &genesis_hash);
-
-        self.db.write(batch)
-            .map_err(|e| StorageError::Write(e.to_string()))?;
+        let flag = self.db.get_cf(metadata_cf, META_INITIALIZED)
+            .map_err(|e| StorageError::Read(e.to_string()))?;
+        Ok(flag.is_some() || self.get_metadata()?.genesis_hash != [0; 32])
+    }
 
+    /// Verifica che un database inizializzato sia stato creato con il genesis `genesis_hash`
+    pub fn check_genesis(&self, genesis_hash: &[u8; 32]) -> Result<(), StorageError> {
+        if !self.is_initialized()? {
+            return Ok(());
+        }
+        let found = self.get_metadata()?.genesis_hash;
+        if found != *genesis_hash {
+            return Err(StorageError::GenesisMismatch { expected: *genesis_hash, found });
+        }
         Ok(())
     }
 
@@ -346,16 +848,493 @@ impl BlockchainDB {
 
     /// Cerca una transazione per hash
     pub fn get_transaction(&self, tx_hash: &[u8; 32]) -> Result<Option<(Transaction, TxLocation)>, StorageError> {
-        let tx_cf = self.get_cf(CF_TX_INDEX)?;
+        self.snapshot().get_transaction(tx_hash)
+    }
+
+    /// Cancella lo stato derivato (UTXO set, indici, metadati) mantenendo i block
+    ///
+    /// Dopo la chiamata il database è vuoto dal punto di vista della chain e va
+    /// ricostruito riapplicando i block (vedi `reindex`).
+    pub fn clear_derived_state(&self) -> Result<(), StorageError> {
+        let _writer = self.lock_writer();
+        for name in [CF_UTXO, CF_TX_INDEX, CF_BLOCK_INDEX, CF_METADATA, CF_UTXO_HASH] {
+            let cf = self.get_cf(name)?;
+            let mut batch = WriteBatch::default();
+            for item in self.db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+                let (key, _) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+                // La rete del database non cambia con il reindex
+                if name == CF_METADATA && *key == *META_NETWORK_MAGIC.as_bytes() {
+                    continue;
+                }
+                batch.delete_cf(cf, key);
+            }
+            self.db.write(batch)
+                .map_err(|e| StorageError::Write(e.to_string()))?;
+        }
+        self.lock_cache().invalidate_headers();
+        Ok(())
+    }
+
+    /// Header di tutti i block salvati, indipendentemente dall'indice per altezza
+    pub fn get_stored_headers(&self) -> Result<Vec<BlockHeader>, StorageError> {
+        let blocks_cf = self.get_cf(CF_BLOCKS)?;
+        let mut headers = Vec::new();
+        for item in self.db.iterator_cf(blocks_cf, rocksdb::IteratorMode::Start) {
+            let (_, block_bytes) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let block: Block = bincode::deserialize(&block_bytes)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+            headers.push(block.header);
+        }
+        Ok(headers)
+    }
+
+    /// Salva un block nell'area di staging (scaricato ma non ancora connesso)
+    pub fn stage_block(&self, block: &Block) -> Result<(), StorageError> {
+        let staged_cf = self.get_cf(CF_STAGED)?;
+        let block_bytes = bincode::serialize(block)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.db.put_cf(staged_cf, block.hash(), block_bytes)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Ottiene un block dall'area di staging
+    pub fn get_staged_block(&self, block_hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        let staged_cf = self.get_cf(CF_STAGED)?;
+        self.db.get_cf(staged_cf, block_hash)
+            .map_err(|e| StorageError::Read(e.to_string()))?
+            .map(|bytes| bincode::deserialize(&bytes)
+                .map_err(|e| StorageError::Deserialization(e.to_string())))
+            .transpose()
+    }
+
+    /// Rimuove un block dall'area di staging
+    pub fn remove_staged_block(&self, block_hash: &[u8; 32]) -> Result<(), StorageError> {
+        let staged_cf = self.get_cf(CF_STAGED)?;
+        self.db.delete_cf(staged_cf, block_hash)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Header di tutti i block nell'area di staging
+    pub fn get_staged_headers(&self) -> Result<Vec<BlockHeader>, StorageError> {
+        let staged_cf = self.get_cf(CF_STAGED)?;
+        let mut headers = Vec::new();
+        for item in self.db.iterator_cf(staged_cf, rocksdb::IteratorMode::Start) {
+            let (_, block_bytes) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let block: Block = bincode::deserialize(&block_bytes)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+            headers.push(block.header);
+        }
+        Ok(headers)
+    }
+
+    /// Marca un block come invalido: non verrà più validato né accettato
+    pub fn mark_block_invalid(&self, block_hash: &[u8; 32], record: &InvalidBlock) -> Result<(), StorageError> {
+        let invalid_cf = self.get_cf(CF_INVALID)?;
+        let record_bytes = bincode::serialize(record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.db.put_cf(invalid_cf, block_hash, record_bytes)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Marcatura di invalidità di un block
+    pub fn get_invalid_block(&self, block_hash: &[u8; 32]) -> Result<Option<InvalidBlock>, StorageError> {
+        let invalid_cf = self.get_cf(CF_INVALID)?;
+        self.db.get_cf(invalid_cf, block_hash)
+            .map_err(|e| StorageError::Read(e.to_string()))?
+            .map(|bytes| bincode::deserialize(&bytes)
+                .map_err(|e| StorageError::Deserialization(e.to_string())))
+            .transpose()
+    }
+
+    /// Se un block è marcato come invalido
+    pub fn is_block_invalid(&self, block_hash: &[u8; 32]) -> Result<bool, StorageError> {
+        Ok(self.get_invalid_block(block_hash)?.is_some())
+    }
+
+    /// Tutti i block marcati come invalidi
+    pub fn get_invalid_blocks(&self) -> Result<Vec<([u8; 32], InvalidBlock)>, StorageError> {
+        let invalid_cf = self.get_cf(CF_INVALID)?;
+        let mut blocks = Vec::new();
+        for item in self.db.iterator_cf(invalid_cf, rocksdb::IteratorMode::Start) {
+            let (key, value) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let hash: [u8; 32] = key.as_ref().try_into()
+                .map_err(|_| StorageError::InvalidData("invalid block key".to_string()))?;
+            let record = bincode::deserialize(&value)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+            blocks.push((hash, record));
+        }
+        Ok(blocks)
+    }
+
+    /// Rimuove la marcatura di un block e dei suoi discendenti marcati
+    ///
+    /// Ritorna gli hash dei block non più marcati.
+    pub fn clear_invalid_block(&self, block_hash: &[u8; 32]) -> Result<Vec<[u8; 32]>, StorageError> {
+        let _writer = self.lock_writer();
+        let invalid = self.get_invalid_blocks()?;
+        if !invalid.iter().any(|(hash, _)| hash == block_hash) {
+            return Ok(Vec::new());
+        }
+
+        let mut cleared = vec![*block_hash];
+        let mut index = 0;
+        while index < cleared.len() {
+            let parent = cleared[index];
+            cleared.extend(invalid.iter()
+                .filter(|(_, record)| record.previous_hash == parent)
+                .map(|(hash, _)| *hash));
+            index += 1;
+        }
+
+        let invalid_cf = self.get_cf(CF_INVALID)?;
+        let mut batch = WriteBatch::default();
+        for hash in &cleared {
+            batch.delete_cf(invalid_cf, hash);
+        }
+        self.db.write(batch)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        Ok(cleared)
+    }
+
+    /// Aggiunge una riorganizzazione al registro
+    pub fn record_reorg(&self, record: &ReorgRecord) -> Result<(), StorageError> {
+        let reorgs_cf = self.get_cf(CF_REORGS)?;
+        let _writer = self.lock_writer();
+        let next = match self.db.iterator_cf(reorgs_cf, rocksdb::IteratorMode::End).next() {
+            Some(item) => {
+                let (key, _) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+                let key: [u8; 8] = key.as_ref().try_into()
+                    .map_err(|_| StorageError::InvalidData("invalid reorg key".to_string()))?;
+                u64::from_be_bytes(key) + 1
+            }
+            None => 0,
+        };
+        let record_bytes = bincode::serialize(record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.db.put_cf(reorgs_cf, next.to_be_bytes(), record_bytes)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Ultime `limit` riorganizzazioni registrate, dalla più recente
+    pub fn get_reorgs(&self, limit: usize) -> Result<Vec<ReorgRecord>, StorageError> {
+        let reorgs_cf = self.get_cf(CF_REORGS)?;
+        let mut reorgs = Vec::new();
+        for item in self.db.iterator_cf(reorgs_cf, rocksdb::IteratorMode::End).take(limit) {
+            let (_, value) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            reorgs.push(bincode::deserialize(&value)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?);
+        }
+        Ok(reorgs)
+    }
+
+    /// Salva una transazione espulsa dalla mempool nel livello su disco
+    ///
+    /// Le chiavi sono ordinate per fee per byte e txid: il livello si
+    /// scorre a partire dalla transazione che paga di più.
+    pub fn spill_transaction(&self, feerate: u64, txid: &[u8; 32], entry: &[u8]) -> Result<(), StorageError> {
+        let spill_cf = self.get_cf(CF_MEMPOOL_SPILL)?;
+        self.db.put_cf(spill_cf, spill_key(feerate, txid), entry)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Legge e toglie una transazione dal livello su disco della mempool
+    pub fn take_spilled_transaction(&self, feerate: u64, txid: &[u8; 32]) -> Result<Option<Vec<u8>>, StorageError> {
+        let spill_cf = self.get_cf(CF_MEMPOOL_SPILL)?;
+        let key = spill_key(feerate, txid);
+        let entry = self.db.get_cf(spill_cf, key)
+            .map_err(|e| StorageError::Read(e.to_string()))?;
+        if entry.is_some() {
+            self.db.delete_cf(spill_cf, key)
+                .map_err(|e| StorageError::Write(e.to_string()))?;
+        }
+        Ok(entry)
+    }
+
+    /// Fee per byte, txid e dimensione di ogni transazione nel livello su disco della mempool
+    pub fn spilled_transactions(&self) -> Result<Vec<(u64, [u8; 32], usize)>, StorageError> {
+        let spill_cf = self.get_cf(CF_MEMPOOL_SPILL)?;
+        let mut spilled = Vec::new();
+        for item in self.db.iterator_cf(spill_cf, rocksdb::IteratorMode::Start) {
+            let (key, entry) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let (feerate, txid) = key.split_at(8.min(key.len()));
+            let (Ok(feerate), Ok(txid)) = (<[u8; 8]>::try_from(feerate), <[u8; 32]>::try_from(txid)) else {
+                return Err(StorageError::InvalidData("invalid mempool spill key".to_string()));
+            };
+            spilled.push((u64::from_be_bytes(feerate), txid, entry.len()));
+        }
+        Ok(spilled)
+    }
+
+    /// Scansiona l'intero UTXO set restituendo le entry accettate da `filter`
+    ///
+    /// La cancellazione tramite `cancel` viene controllata periodicamente e
+    /// interrompe la scansione con `StorageError::Cancelled`.
+    pub fn scan_utxos<F>(&self, cancel: &CancellationToken, filter: F) -> Result<UtxoScan, StorageError>
+    where
+        F: FnMut(&OutPoint, &UtxoEntry) -> bool,
+    {
+        self.snapshot().scan_utxos(cancel, filter)
+    }
+
+    /// Decodifica una chiave del UTXO set
+    fn parse_outpoint_key(key: &[u8]) -> Result<OutPoint, StorageError> {
+        if key.len() != 36 {
+            return Err(StorageError::InvalidData("Invalid outpoint key length".to_string()));
+        }
+        let mut txid = [0u8; 32];
+        txid.copy_from_slice(&key[..32]);
+        let mut vout = [0u8; 4];
+        vout.copy_from_slice(&key[32..]);
+        Ok(OutPoint::new(txid, u32::from_be_bytes(vout)))
+    }
+
+    /// Crea chiave per OutPoint
+    fn outpoint_key(&self, outpoint: &OutPoint) -> Vec<u8> {
+        let mut key = Vec::with_capacity(36); // 32 + 4 bytes
+        key.extend_from_slice(&outpoint.txid);
+        key.extend_from_slice(&outpoint.vout.to_be_bytes());
+        key
+    }
+
+    /// Calcola le statistiche del UTXO set su uno snapshot consistente
+    ///
+    /// `progress` riceve ogni outpoint esaminato (in ordine di txid).
+    pub fn utxo_set_stats<F>(&self, cancel: &CancellationToken, progress: F) -> Result<UtxoSetStats, StorageError>
+    where
+        F: FnMut(&OutPoint),
+    {
+        self.snapshot().utxo_set_stats(cancel, progress)
+    }
+
+    /// Ottiene statistiche del database
+    pub fn get_stats(&self) -> Result<DatabaseStats, StorageError> {
+        self.snapshot().get_stats()
+    }
+}
+
+/// Varianti async per i server tokio
+///
+/// Ogni chiamata gira sul pool di thread bloccanti di tokio, così le
+/// letture RocksDB non fermano i worker async. Richiedono un runtime tokio
+/// attivo e il database condiviso in un `Arc`.
+impl BlockchainDB {
+    /// Esegue `operation` sul pool bloccante
+    pub async fn run_blocking<T, F>(self: &Arc<Self>, operation: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&BlockchainDB) -> Result<T, StorageError> + Send + 'static,
+    {
+        let db = Arc::clone(self);
+        tokio::task::spawn_blocking(move || operation(&db))
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+    }
+
+    /// Variante async di `get_block`
+    pub async fn get_block_async(self: &Arc<Self>, block_hash: [u8; 32]) -> Result<Option<Block>, StorageError> {
+        self.run_blocking(move |db| db.get_block(&block_hash)).await
+    }
+
+    /// Variante async di `get_block_by_height`
+    pub async fn get_block_by_height_async(self: &Arc<Self>, height: u64) -> Result<Option<Block>, StorageError> {
+        self.run_blocking(move |db| db.get_block_by_height(height)).await
+    }
+
+    /// Variante async di `get_headers_in_range`
+    pub async fn get_headers_in_range_async(
+        self: &Arc<Self>,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<BlockHeader>, StorageError> {
+        self.run_blocking(move |db| db.get_headers_in_range(from_height, to_height)).await
+    }
+
+    /// Variante async di `get_utxo`
+    pub async fn get_utxo_async(self: &Arc<Self>, outpoint: OutPoint) -> Result<Option<UtxoEntry>, StorageError> {
+        self.run_blocking(move |db| db.get_utxo(&outpoint)).await
+    }
+
+    /// Variante async di `get_transaction`
+    pub async fn get_transaction_async(
+        self: &Arc<Self>,
+        tx_hash: [u8; 32],
+    ) -> Result<Option<(Transaction, TxLocation)>, StorageError> {
+        self.run_blocking(move |db| db.get_transaction(&tx_hash)).await
+    }
+
+    /// Variante async di `get_metadata`
+    pub async fn get_metadata_async(self: &Arc<Self>) -> Result<ChainMetadata, StorageError> {
+        self.run_blocking(|db| db.get_metadata()).await
+    }
+
+    /// Variante async di `get_stats`
+    pub async fn get_stats_async(self: &Arc<Self>) -> Result<DatabaseStats, StorageError> {
+        self.run_blocking(|db| db.get_stats()).await
+    }
+
+    /// Variante async di `store_block`
+    pub async fn store_block_async(self: &Arc<Self>, block: Block) -> Result<(), StorageError> {
+        self.run_blocking(move |db| db.store_block(&block)).await
+    }
+}
+
+/// Vista del database a un istante, per letture su più chiavi
+///
+/// Tutte le letture vedono lo stato al momento di
+/// [`BlockchainDB::snapshot`]: block connessi o disconnessi nel frattempo
+/// non producono viste incoerenti (metadati di un tip e UTXO di un altro).
+/// Le operazioni su più chiavi di `BlockchainDB` usano ciascuna il proprio
+/// snapshot; chi combina più chiamate deve condividerne uno.
+pub struct ChainSnapshot<'a> {
+    /// Database di origine (column families e codifica delle chiavi)
+    db: &'a BlockchainDB,
+    /// Snapshot RocksDB
+    snapshot: rocksdb::Snapshot<'a>,
+}
+
+impl ChainSnapshot<'_> {
+    /// Carica un block per hash
+    pub fn get_block(&self, block_hash: &[u8; 32]) -> Result<Option<Block>, StorageError> {
+        let blocks_cf = self.db.get_cf(CF_BLOCKS)?;
+
+        match self.snapshot.get_cf(blocks_cf, block_hash) {
+            Ok(Some(block_bytes)) => {
+                let block = bincode::deserialize(&block_bytes)
+                    .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+                Ok(Some(block))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(StorageError::Read(e.to_string())),
+        }
+    }
+
+    /// Carica un block per altezza
+    pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        let index_cf = self.db.get_cf(CF_BLOCK_INDEX)?;
+
+        // Prima ottieni l'hash dalla height
+        match self.snapshot.get_cf(index_cf, &height.to_be_bytes()) {
+            Ok(Some(hash_bytes)) => {
+                if hash_bytes.len() == 32 {
+                    let mut block_hash = [0u8; 32];
+                    block_hash.copy_from_slice(&hash_bytes);
+                    self.get_block(&block_hash)
+                } else {
+                    Err(StorageError::InvalidData("Invalid block hash length".to_string()))
+                }
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(StorageError::Read(e.to_string())),
+        }
+    }
+
+    /// Carica gli header consecutivi nell'intervallo di altezze (estremi inclusi)
+    ///
+    /// Si ferma al primo buco nell'indice.
+    pub fn get_headers_in_range(&self, from_height: u64, to_height: u64) -> Result<Vec<BlockHeader>, StorageError> {
+        let mut headers = Vec::new();
+        for height in from_height..=to_height {
+            match self.get_block_by_height(height)? {
+                Some(block) => headers.push(block.header),
+                None => break,
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Ottiene un UTXO
+    pub fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>, StorageError> {
+        let utxo_cf = self.db.get_cf(CF_UTXO)?;
+        let key = self.db.outpoint_key(outpoint);
+
+        match self.snapshot.get_cf(utxo_cf, &key) {
+            Ok(Some(utxo_bytes)) => {
+                let utxo = bincode::deserialize(&utxo_bytes)
+                    .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+                Ok(Some(utxo))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(StorageError::Read(e.to_string())),
+        }
+    }
+
+    /// Ottiene metadati della blockchain
+    ///
+    /// Valori di lunghezza errata o un best block senza altezza (e
+    /// viceversa) sono `StorageError::CorruptMetadata`.
+    pub fn get_metadata(&self) -> Result<ChainMetadata, StorageError> {
+        let best_block_hash = self.metadata_bytes::<32>(META_BEST_BLOCK)?;
+        let height = self.metadata_bytes::<8>(META_HEIGHT)?.map(u64::from_be_bytes);
+        match (best_block_hash, height) {
+            (Some(_), None) => return Err(StorageError::corrupt_metadata(META_HEIGHT, "missing for the best block")),
+            (None, Some(_)) => return Err(StorageError::corrupt_metadata(META_BEST_BLOCK, "missing for the height")),
+            _ => {}
+        }
+        let genesis_hash = self.metadata_bytes::<32>(META_GENESIS_HASH)?;
+
+        Ok(ChainMetadata {
+            best_block_hash: best_block_hash.unwrap_or([0; 32]),
+            height: height.unwrap_or(0),
+            total_work: 0, // TODO: calcolare total work
+            genesis_hash: genesis_hash.unwrap_or([0; 32]),
+        })
+    }
+
+    /// Legge il metadato `key`, che deve essere lungo `N` bytes
+    fn metadata_bytes<const N: usize>(&self, key: &'static str) -> Result<Option<[u8; N]>, StorageError> {
+        let metadata_cf = self.db.get_cf(CF_METADATA)?;
+        self.snapshot.get_cf(metadata_cf, key)
+            .map_err(|e| StorageError::Read(e.to_string()))?
+            .map(|bytes| {
+                bytes.as_slice().try_into()
+                    .map_err(|_| StorageError::corrupt_metadata(key, format!("{} bytes, expected {}", bytes.len(), N)))
+            })
+            .transpose()
+    }
+
+    /// Stato MuHash salvato per il block `block_hash`
+    fn get_utxo_hash_state(&self, block_hash: &[u8; 32]) -> Result<Option<MuHash>, StorageError> {
+        let hash_cf = self.db.get_cf(CF_UTXO_HASH)?;
+        self.snapshot.get_cf(hash_cf, block_hash)
+            .map_err(|e| StorageError::Read(e.to_string()))?
+            .map(|bytes| {
+                MuHash::from_bytes(&bytes)
+                    .ok_or_else(|| StorageError::Deserialization(format!("UTXO hash state of {} bytes", bytes.len())))
+            })
+            .transpose()
+    }
+
+    /// MuHash del UTXO set dopo il block `block_hash`, None per i block mai collegati
+    pub fn get_utxo_hash(&self, block_hash: &[u8; 32]) -> Result<Option<[u8; 32]>, StorageError> {
+        Ok(self.get_utxo_hash_state(block_hash)?.map(|state| state.finalize()))
+    }
+
+    /// Stato MuHash dell'intero UTXO set allo snapshot
+    fn utxo_set_muhash(&self, cancel: &CancellationToken) -> Result<MuHash, StorageError> {
+        let utxo_cf = self.db.get_cf(CF_UTXO)?;
+        let mut muhash = MuHash::new();
+        for (scanned, item) in self.snapshot.iterator_cf(utxo_cf, rocksdb::IteratorMode::Start).enumerate() {
+            if (scanned as u64).is_multiple_of(SCAN_CANCEL_CHECK_INTERVAL) && cancel.is_cancelled() {
+                return Err(StorageError::Cancelled);
+            }
+            let (key, value) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            muhash.insert(&utxo_hash_element(&key, &value));
+        }
+        Ok(muhash)
+    }
+
+    /// Cerca una transazione per hash
+    pub fn get_transaction(&self, tx_hash: &[u8; 32]) -> Result<Option<(Transaction, TxLocation)>, StorageError> {
+        let tx_cf = self.db.get_cf(CF_TX_INDEX)?;
 
         // Prima cerca la location
-        match self.db.get_cf(tx_cf, tx_hash) {
+        match self.snapshot.get_cf(tx_cf, tx_hash) {
             Ok(Some(location_bytes)) => {
                 let location: TxLocation = bincode::deserialize(&location_bytes)
                     .map_err(|e| StorageError::Deserialization(e.to_string()))?;
 
                 // Carica il block
-                if let Some(block) = self.get_block(&location.block_hash)? {
+                if let Some(block) = self.get_block(location.block_hash.as_byte_array())? {
                     if let Some(tx) = block.transactions.get(location.tx_index as usize) {
                         return Ok(Some((tx.clone(), location)));
                     }
@@ -368,12 +1347,96 @@ impl BlockchainDB {
         }
     }
 
-    /// Crea chiave per OutPoint
-    fn outpoint_key(&self, outpoint: &OutPoint) -> Vec<u8> {
-        let mut key = Vec::with_capacity(36); // 32 + 4 bytes
-        key.extend_from_slice(&outpoint.txid);
-        key.extend_from_slice(&outpoint.vout.to_be_bytes());
-        key
+    /// Scansiona l'intero UTXO set restituendo le entry accettate da `filter`
+    ///
+    /// La cancellazione tramite `cancel` viene controllata periodicamente e
+    /// interrompe la scansione con `StorageError::Cancelled`.
+    pub fn scan_utxos<F>(&self, cancel: &CancellationToken, mut filter: F) -> Result<UtxoScan, StorageError>
+    where
+        F: FnMut(&OutPoint, &UtxoEntry) -> bool,
+    {
+        let utxo_cf = self.db.get_cf(CF_UTXO)?;
+        let mut matches = Vec::new();
+        let mut scanned = 0u64;
+
+        for item in self.snapshot.iterator_cf(utxo_cf, rocksdb::IteratorMode::Start) {
+            if scanned.is_multiple_of(SCAN_CANCEL_CHECK_INTERVAL) && cancel.is_cancelled() {
+                return Err(StorageError::Cancelled);
+            }
+
+            let (key, value) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let outpoint = BlockchainDB::parse_outpoint_key(&key)?;
+            let entry: UtxoEntry = bincode::deserialize(&value)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+
+            scanned += 1;
+            if filter(&outpoint, &entry) {
+                matches.push((outpoint, entry));
+            }
+        }
+
+        Ok(UtxoScan { matches, scanned })
+    }
+
+    /// Calcola le statistiche del UTXO set allo snapshot
+    ///
+    /// `progress` riceve ogni outpoint esaminato (in ordine di txid).
+    pub fn utxo_set_stats<F>(&self, cancel: &CancellationToken, mut progress: F) -> Result<UtxoSetStats, StorageError>
+    where
+        F: FnMut(&OutPoint),
+    {
+        let metadata = self.get_metadata()?;
+        let utxo_cf = self.db.get_cf(CF_UTXO)?;
+
+        let mut stats = UtxoSetStats {
+            height: metadata.height,
+            best_block_hash: metadata.best_block_hash,
+            transactions: 0,
+            txouts: 0,
+            total_amount: 0,
+            asset_amounts: BTreeMap::new(),
+            serialized_size: 0,
+            hash: [0; 32],
+            muhash: [0; 32],
+        };
+        let mut hasher = Sha256::new();
+        let mut muhash = MuHash::new();
+        let mut last_txid = None;
+
+        for item in self.snapshot.iterator_cf(utxo_cf, rocksdb::IteratorMode::Start) {
+            if stats.txouts.is_multiple_of(SCAN_CANCEL_CHECK_INTERVAL) && cancel.is_cancelled() {
+                return Err(StorageError::Cancelled);
+            }
+
+            let (key, value) = item.map_err(|e| StorageError::Read(e.to_string()))?;
+            let outpoint = BlockchainDB::parse_outpoint_key(&key)?;
+            let entry: UtxoEntry = bincode::deserialize(&value)
+                .map_err(|e| StorageError::Deserialization(e.to_string()))?;
+
+            hasher.update(&key);
+            hasher.update(&value);
+            muhash.insert(&utxo_hash_element(&key, &value));
+            stats.serialized_size += (key.len() + value.len()) as u64;
+            stats.txouts += 1;
+            if last_txid != Some(outpoint.txid) {
+                stats.transactions += 1;
+                last_txid = Some(outpoint.txid);
+            }
+
+            let output = &entry.output;
+            let total = if output.is_native_asset() {
+                &mut stats.total_amount
+            } else {
+                stats.asset_amounts.entry(output.asset_id).or_default()
+            };
+            *total = total.saturating_add(output.value.to_sat());
+
+            progress(&outpoint);
+        }
+
+        stats.hash = Sha256::digest(hasher.finalize()).into();
+        stats.muhash = muhash.finalize();
+        Ok(stats)
     }
 
     /// Ottiene statistiche del database
@@ -381,8 +1444,8 @@ impl BlockchainDB {
         let metadata = self.get_metadata()?;
 
         // Count UTXO set size (approssimato)
-        let utxo_cf = self.get_cf(CF_UTXO)?;
-        let iter = self.db.iterator_cf(utxo_cf, rocksdb::IteratorMode::Start);
+        let utxo_cf = self.db.get_cf(CF_UTXO)?;
+        let iter = self.snapshot.iterator_cf(utxo_cf, rocksdb::IteratorMode::Start);
         let utxo_count = iter.count() as u64;
 
         Ok(DatabaseStats {
@@ -436,11 +1499,84 @@ pub enum StorageError {
 
     #[error("UTXO not found: {outpoint:?}")]
     UtxoNotFound { outpoint: OutPoint },
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Background task failed: {0}")]
+    Task(String),
+
+    #[error("Block {} is already in the chain", hex::encode(hash))]
+    DuplicateBlock { hash: [u8; 32] },
+
+    #[error("Block {} at height {height} does not extend the tip at height {tip_height}", hex::encode(hash))]
+    StaleBlock { hash: [u8; 32], height: u64, tip_height: u64 },
+
+    #[error("Block {} has parent {}, which is not the chain tip", hex::encode(hash), hex::encode(previous_hash))]
+    OrphanBlock { hash: [u8; 32], previous_hash: [u8; 32] },
+
+    #[error("Chain tip changed from {expected:?} to {found:?} by another writer")]
+    TipChanged { expected: [u8; 32], found: [u8; 32] },
+
+    #[error(
+        "Database belongs to network {} (magic {}), expected {} (magic {}); check --data-dir and --network",
+        network_name(found), hex::encode(found), network_name(expected), hex::encode(expected)
+    )]
+    NetworkMismatch { expected: [u8; 4], found: [u8; 4] },
+
+    #[error(
+        "Database was created for genesis {}, expected {}; check --data-dir, --network and --genesis-file",
+        hex::encode(found), hex::encode(expected)
+    )]
+    GenesisMismatch { expected: [u8; 32], found: [u8; 32] },
+
+    #[error(
+        "Corrupted database metadata {key}: {reason}; restart with --reindex to rebuild it from the stored blocks \
+         or restore the data directory from a backup"
+    )]
+    CorruptMetadata { key: &'static str, reason: String },
+}
+
+impl StorageError {
+    /// Metadato `key` danneggiato
+    fn corrupt_metadata(key: &'static str, reason: impl Into<String>) -> Self {
+        StorageError::CorruptMetadata { key, reason: reason.into() }
+    }
+}
+
+/// Modifiche di un block al UTXO set, per il hash rolling
+struct UtxoChanges<'a> {
+    /// Stato MuHash, a cui si tolgono gli output spesi che erano nel database
+    hash: &'a mut MuHash,
+    /// Output creati dal block e non ancora spesi (chiave -> entry serializzata)
+    created: &'a mut BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Elemento del MuHash di un UTXO: chiave e entry serializzata, come nel database
+fn utxo_hash_element(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut element = Vec::with_capacity(key.len() + value.len());
+    element.extend_from_slice(key);
+    element.extend_from_slice(value);
+    element
+}
+
+/// Chiave di una transazione nel livello su disco della mempool: fee per byte (big endian) e txid
+fn spill_key(feerate: u64, txid: &[u8; 32]) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&feerate.to_be_bytes());
+    key[8..].copy_from_slice(txid);
+    key
+}
+
+/// Nome della rete per i magic bytes dati, se nota
+fn network_name(magic: &[u8; 4]) -> &'static str {
+    crate::Network::from_magic(*magic).map_or("unknown", |network| network.name())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TxInput;
     use tempfile::TempDir;
 
     fn create_test_db() -> (BlockchainDB, TempDir) {
@@ -462,6 +1598,7 @@ mod tests {
     fn test_genesis_initialization() {
         let (db, _temp) = create_test_db();
         let genesis = Block::genesis();
+        assert!(!db.is_initialized().unwrap());
 
         db.initialize_with_genesis(&genesis).unwrap();
 
@@ -472,6 +1609,15 @@ mod tests {
         // Verifica che il genesis sia salvato
         let stored_genesis = db.get_block_by_height(0).unwrap().unwrap();
         assert_eq!(stored_genesis.hash(), genesis.hash());
+
+        // Altezza 0 ma inizializzato: una nuova inizializzazione verifica solo il genesis
+        assert!(db.is_initialized().unwrap());
+        db.initialize_with_genesis(&genesis).unwrap();
+        let other = Block::new([0; 32], vec![Transaction::coinbase(b"other", 0, 50)], 0x1d00ffff, 0);
+        let error = db.initialize_with_genesis(&other).unwrap_err();
+        assert!(matches!(error, StorageError::GenesisMismatch { expected, found }
+            if expected == other.hash() && found == genesis.hash()));
+        assert!(db.check_genesis(&genesis.hash()).is_ok());
     }
 
     #[test]
@@ -506,7 +1652,7 @@ mod tests {
 
         assert!(utxo.is_some());
         let utxo = utxo.unwrap();
-        assert_eq!(utxo.output.value, 5000000000);
+        assert_eq!(utxo.output.value.to_sat(), 5000000000);
         assert!(utxo.is_coinbase);
     }
 
@@ -523,7 +1669,7 @@ mod tests {
         // Cerca transazione
         let (tx, location) = db.get_transaction(&tx_hash).unwrap().unwrap();
         assert_eq!(tx.hash(), tx_hash);
-        assert_eq!(location.block_hash, block.hash());
+        assert_eq!(location.block_hash, block.block_hash());
         assert_eq!(location.tx_index, 0);
     }
 
@@ -538,11 +1684,17 @@ mod tests {
 
         let outpoint = OutPoint::new(coinbase.hash(), 0);
 
+        let params = crate::ChainParams::mainnet();
+
         // Non dovrebbe essere spendibile subito (height 0 < 100)
-        assert!(!db.is_utxo_spendable(&outpoint, 50).unwrap());
+        assert!(!db.is_utxo_spendable(&outpoint, 50, &params).unwrap());
 
         // Dovrebbe essere spendibile dopo 100 blocks
-        assert!(db.is_utxo_spendable(&outpoint, 100).unwrap());
+        assert!(db.is_utxo_spendable(&outpoint, 100, &params).unwrap());
+
+        // Maturazione ridotta (regtest)
+        let params = crate::ChainParams::regtest().with_coinbase_maturity(1);
+        assert!(db.is_utxo_spendable(&outpoint, 1, &params).unwrap());
     }
 
     #[test]
@@ -557,4 +1709,314 @@ mod tests {
         assert_eq!(stats.total_blocks, 1);
         assert!(stats.utxo_set_size >= 0); // Genesis potrebbe avere 0 UTXO
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_network_magic_marker() {
+        let (db, _temp) = create_test_db();
+        assert_eq!(db.network_magic().unwrap(), None);
+
+        let testnet = crate::Network::Testnet.magic();
+        db.check_network_magic(testnet).unwrap();
+        db.check_network_magic(testnet).unwrap();
+        assert!(matches!(
+            db.check_network_magic(crate::Network::Mainnet.magic()),
+            Err(StorageError::NetworkMismatch { .. })
+        ));
+
+        // Il marker sopravvive al reindex
+        db.clear_derived_state().unwrap();
+        assert_eq!(db.network_magic().unwrap(), Some(testnet));
+    }
+
+    #[test]
+    fn test_corrupt_metadata_detected() {
+        let (db, _temp) = create_test_db();
+        db.check_db().unwrap();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        db.check_db().unwrap();
+        let metadata_cf = db.get_cf(CF_METADATA).unwrap();
+
+        // Hash troncato: errore strutturato invece di un panic
+        db.db.put_cf(metadata_cf, META_BEST_BLOCK, [1; 20]).unwrap();
+        let error = db.get_metadata().unwrap_err();
+        assert!(matches!(error, StorageError::CorruptMetadata { key: META_BEST_BLOCK, .. }));
+        assert!(error.to_string().contains("--reindex"));
+
+        // Altezza che non corrisponde al best block
+        db.db.put_cf(metadata_cf, META_BEST_BLOCK, Block::genesis().hash()).unwrap();
+        db.db.put_cf(metadata_cf, META_HEIGHT, 5u64.to_be_bytes()).unwrap();
+        assert!(db.get_metadata().is_ok());
+        assert!(matches!(db.check_db(), Err(StorageError::CorruptMetadata { key: META_HEIGHT, .. })));
+
+        db.db.delete_cf(metadata_cf, META_HEIGHT).unwrap();
+        assert!(matches!(db.get_metadata(), Err(StorageError::CorruptMetadata { key: META_HEIGHT, .. })));
+        db.db.put_cf(metadata_cf, META_NETWORK_MAGIC, [1; 3]).unwrap();
+        assert!(matches!(db.check_db(), Err(StorageError::CorruptMetadata { key: META_NETWORK_MAGIC, .. })));
+    }
+
+    #[test]
+    fn test_utxo_set_stats() {
+        let (db, _temp) = create_test_db();
+        let empty = db.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap();
+        assert_eq!(empty.txouts, 0);
+
+        let mut coinbase = Transaction::coinbase(b"alice", 0, 5000000000);
+        coinbase.outputs.push(TxOutput::new(7, [9; 32], b"bob".to_vec()));
+        let block = Block::new([0; 32], vec![coinbase], 0x1d00ffff, 0);
+        db.store_block(&block).unwrap();
+        let other = Transaction::coinbase(b"bob", 1, 5000000000);
+        let next = Block::new(block.hash(), vec![other], 0x1d00ffff, 1);
+        db.store_block(&next).unwrap();
+
+        let mut seen = 0;
+        let stats = db.utxo_set_stats(&CancellationToken::new(), |_| seen += 1).unwrap();
+        assert_eq!(seen, 3);
+        assert_eq!(stats.height, 1);
+        assert_eq!(stats.best_block_hash, next.hash());
+        assert_eq!(stats.transactions, 2);
+        assert_eq!(stats.txouts, 3);
+        assert_eq!(stats.total_amount, 10000000000);
+        assert_eq!(stats.asset_amounts[&[9; 32]], 7);
+        assert!(stats.serialized_size > 0);
+        assert_ne!(stats.hash, empty.hash);
+
+        // Stesso UTXO set, stesso commitment
+        assert_eq!(db.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap().hash, stats.hash);
+    }
+
+    #[test]
+    fn test_rolling_utxo_hash() {
+        let (db, _temp) = create_test_db();
+        let scan = |db: &BlockchainDB| db.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap().muhash;
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5000000000);
+        let genesis = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        db.store_block(&genesis).unwrap();
+        assert_eq!(db.get_utxo_hash(&genesis.hash()).unwrap(), Some(scan(&db)));
+
+        // Un output creato e speso nello stesso block non entra nel hash
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase.hash(), 0), vec![])],
+            vec![TxOutput::to_address(4000000000, b"bob")],
+            0,
+        );
+        let chained = Transaction::new(
+            vec![TxInput::new(OutPoint::new(spend.hash(), 0), vec![])],
+            vec![TxOutput::to_address(3000000000, b"carol")],
+            0,
+        );
+        let first = Block::new(
+            genesis.hash(),
+            vec![Transaction::coinbase(b"miner", 1, 5000000000), spend, chained],
+            0x1d00ffff,
+            1,
+        );
+        db.store_block(&first).unwrap();
+        let first_hash = db.get_utxo_hash(&first.hash()).unwrap().unwrap();
+        assert_eq!(first_hash, scan(&db));
+        assert_ne!(first_hash, db.get_utxo_hash(&genesis.hash()).unwrap().unwrap());
+
+        // Dopo il disconnect il tip torna allo stato salvato del parent
+        let second = Block::new(first.hash(), vec![Transaction::coinbase(b"miner", 2, 50)], 0x1d00ffff, 2);
+        db.store_block(&second).unwrap();
+        assert_eq!(db.get_utxo_hash(&second.hash()).unwrap(), Some(scan(&db)));
+        db.disconnect_tip().unwrap();
+        assert_eq!(scan(&db), first_hash);
+
+        // Senza lo stato del parent (database precedente) il block riparte da una scansione
+        db.db.delete_cf(db.get_cf(CF_UTXO_HASH).unwrap(), first.hash()).unwrap();
+        db.store_block(&second).unwrap();
+        assert_eq!(db.get_utxo_hash(&second.hash()).unwrap(), Some(scan(&db)));
+        assert!(db.get_utxo_hash(&[7; 32]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_scan_utxos() {
+        let (db, _temp) = create_test_db();
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5000000000);
+        let block = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        db.store_block(&block).unwrap();
+        let other = Transaction::coinbase(b"bob", 1, 5000000000);
+        db.store_block(&Block::new(block.hash(), vec![other], 0x1d00ffff, 1)).unwrap();
+
+        let scan = db
+            .scan_utxos(&CancellationToken::new(), |_, entry| entry.output.script_pubkey == b"alice")
+            .unwrap();
+        assert_eq!(scan.scanned, 2);
+        assert_eq!(scan.matches.len(), 1);
+        assert_eq!(scan.matches[0].0, OutPoint::new(coinbase.hash(), 0));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(db.scan_utxos(&cancel, |_, _| true), Err(StorageError::Cancelled)));
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let (db, _temp) = create_test_db();
+        let coinbase = Transaction::coinbase(b"alice", 0, 5000000000);
+        let block = Block::new([0; 32], vec![coinbase], 0x1d00ffff, 0);
+        db.store_block(&block).unwrap();
+
+        let snapshot = db.snapshot();
+        let other = Transaction::coinbase(b"bob", 1, 5000000000);
+        let next = Block::new(block.hash(), vec![other.clone()], 0x1d00ffff, 1);
+        db.store_block(&next).unwrap();
+
+        // Lo snapshot non vede il block scritto dopo la sua creazione
+        let metadata = snapshot.get_metadata().unwrap();
+        assert_eq!((metadata.height, metadata.best_block_hash), (0, block.hash()));
+        assert!(snapshot.get_block_by_height(1).unwrap().is_none());
+        assert!(snapshot.get_transaction(&other.hash()).unwrap().is_none());
+        assert_eq!(snapshot.get_stats().unwrap().utxo_set_size, 1);
+        assert_eq!(snapshot.utxo_set_stats(&CancellationToken::new(), |_| {}).unwrap().height, 0);
+
+        assert_eq!(db.get_stats().unwrap().utxo_set_size, 2);
+        assert_eq!(db.snapshot().get_headers_in_range(0, 5).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_stale_pending_block_rejected() {
+        let (db, _temp) = create_test_db();
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+        db.store_block(&genesis).unwrap();
+
+        // Due scrittori preparano block concorrenti sullo stesso tip
+        let first = Block::new(genesis.hash(), vec![Transaction::coinbase(b"alice", 1, 50)], 0x1d00ffff, 1);
+        let second = Block::new(genesis.hash(), vec![Transaction::coinbase(b"bob", 1, 50)], 0x1d00ffff, 1);
+        let first_pending = db.connect_block(&first).unwrap();
+        let second_pending = db.connect_block(&second).unwrap();
+
+        db.flush_block(first_pending).unwrap();
+        let error = db.flush_block(second_pending).unwrap_err();
+        assert!(matches!(error, StorageError::TipChanged { expected, found } if expected == genesis.hash() && found == first.hash()));
+        assert_eq!(db.get_best_block_hash().unwrap(), first.hash());
+        assert!(db.get_transaction(&second.transactions[0].hash()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_store_block_requires_tip_parent() {
+        let (db, _temp) = create_test_db();
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+        db.store_block(&genesis).unwrap();
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"alice", 1, 50)], 0x1d00ffff, 1);
+        db.store_block(&block).unwrap();
+
+        // Stesso block, block concorrente a un'altezza occupata, parent sconosciuto
+        let error = db.store_block(&block).unwrap_err();
+        assert!(matches!(error, StorageError::DuplicateBlock { hash } if hash == block.hash()));
+        let competing = Block::new(genesis.hash(), vec![Transaction::coinbase(b"bob", 1, 50)], 0x1d00ffff, 1);
+        let error = db.store_block(&competing).unwrap_err();
+        assert!(matches!(error, StorageError::StaleBlock { height: 1, tip_height: 1, .. }));
+        let orphan = Block::new([7; 32], vec![Transaction::coinbase(b"carol", 2, 50)], 0x1d00ffff, 2);
+        let error = db.store_block(&orphan).unwrap_err();
+        assert!(matches!(error, StorageError::OrphanBlock { previous_hash, .. } if previous_hash == [7; 32]));
+
+        // L'indice delle altezze non è stato riscritto
+        assert_eq!(db.get_best_block_hash().unwrap(), block.hash());
+        assert_eq!(db.get_block_by_height(1).unwrap().unwrap().hash(), block.hash());
+        assert!(db.get_block(&competing.hash()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_connect_block_with_txids() {
+        let (db, _temp) = create_test_db();
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+        let txids = genesis.txids();
+
+        // Una lista che non corrisponde al block non deve toccare il UTXO set
+        assert!(matches!(db.connect_block_with_txids(&genesis, &[]), Err(StorageError::InvalidData(_))));
+        db.flush_block(db.connect_block_with_txids(&genesis, &txids).unwrap()).unwrap();
+        assert!(db.get_utxo(&OutPoint::new(txids[0], 0)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let (db, _temp) = create_test_db();
+        db.initialize_with_genesis(&Block::genesis()).unwrap();
+        let genesis_utxos = db.get_stats().unwrap().utxo_set_size;
+        let db = Arc::new(db);
+
+        // Ogni writer estende il tip che legge; se un altro lo precede riprova
+        let handles: Vec<_> = (0..8u64)
+            .map(|writer| {
+                let db = Arc::clone(&db);
+                std::thread::spawn(move || {
+                    let mut stored = 0;
+                    while stored < 10 {
+                        let tip = db.get_metadata().unwrap();
+                        let height = tip.height + 1;
+                        let coinbase = Transaction::coinbase(&writer.to_le_bytes(), height, 50);
+                        match db.store_block(&Block::new(tip.best_block_hash, vec![coinbase], 0x1d00ffff, height)) {
+                            Ok(()) => stored += 1,
+                            Err(StorageError::OrphanBlock { .. } | StorageError::StaleBlock { .. }) => {}
+                            Err(e) => panic!("Unexpected error: {}", e),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Hash e altezza del tip provengono sempre dalla stessa scrittura
+        let metadata = db.get_metadata().unwrap();
+        assert_eq!(metadata.height, 80);
+        let tip = db.get_block_by_height(metadata.height).unwrap().unwrap();
+        assert_eq!(tip.hash(), metadata.best_block_hash);
+        assert_eq!(db.get_stats().unwrap().utxo_set_size, genesis_utxos + 80);
+    }
+
+    #[tokio::test]
+    async fn test_async_variants() {
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let coinbase = Transaction::coinbase(b"alice", 0, 5000000000);
+        let block = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+
+        db.store_block_async(block.clone()).await.unwrap();
+        assert_eq!(db.get_metadata_async().await.unwrap().best_block_hash, block.hash());
+        assert_eq!(db.get_block_by_height_async(0).await.unwrap().unwrap().hash(), block.hash());
+        assert!(db.get_block_async([1; 32]).await.unwrap().is_none());
+        assert_eq!(db.get_headers_in_range_async(0, 3).await.unwrap().len(), 1);
+        assert!(db.get_utxo_async(OutPoint::new(coinbase.hash(), 0)).await.unwrap().is_some());
+        assert_eq!(db.get_transaction_async(coinbase.hash()).await.unwrap().unwrap().1.block_height, 0);
+        assert_eq!(db.get_stats_async().await.unwrap().utxo_set_size, 1);
+
+        // Un panic nel pool bloccante diventa un errore
+        let result: Result<(), _> = db.run_blocking(|_| panic!("boom")).await;
+        assert!(matches!(result, Err(StorageError::Task(_))));
+    }
+
+    #[test]
+    fn test_header_cache_follows_active_chain() {
+        let (db, _temp) = create_test_db();
+        let genesis = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+        let first = Block::new(genesis.hash(), vec![Transaction::coinbase(b"alice", 1, 50)], 0x1d00ffff, 1);
+        db.store_block(&genesis).unwrap();
+        db.store_block(&first).unwrap();
+
+        assert_eq!(db.get_header_by_height(1).unwrap().unwrap().hash(), first.hash());
+        assert_eq!(db.get_header_by_height(1).unwrap().unwrap().hash(), first.hash());
+        let stats = db.cache_stats();
+        assert_eq!((stats.header_hits, stats.header_misses), (1, 1));
+
+        // Dopo il disconnect l'altezza è vuota, poi torna con il nuovo block
+        db.disconnect_tip().unwrap();
+        assert!(db.get_header_by_height(1).unwrap().is_none());
+        let second = Block::new(genesis.hash(), vec![Transaction::coinbase(b"bob", 1, 50)], 0x1d00ffff, 1);
+        db.store_block(&second).unwrap();
+        assert_eq!(db.get_header_by_height(1).unwrap().unwrap().hash(), second.hash());
+
+        // Il block scollegato resta leggibile per hash, dalla cache
+        let hits = db.cache_stats().block_hits;
+        assert_eq!(db.get_block(&first.hash()).unwrap().unwrap().hash(), first.hash());
+        assert_eq!(db.cache_stats().block_hits, hits + 1);
+
+        let db = db.with_cache_config(CacheConfig::disabled());
+        db.get_header_by_height(1).unwrap();
+        assert_eq!(db.cache_stats(), CacheStats::default());
+    }
+}
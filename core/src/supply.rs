@@ -0,0 +1,161 @@
+//! Schedule di emissione: subsidy, supply cumulativo e halving
+//!
+//! Funzioni pure delle costanti di crate, senza database: le usano
+//! validazione, consenso, RPC e wallet. Le varianti `*_with_interval`
+//! seguono l'intervallo di halving di una rete (`ChainParams::halving_interval`).
+
+use crate::{HALVING_INTERVAL, INITIAL_BLOCK_REWARD};
+use serde::{Deserialize, Serialize};
+
+/// Halving dopo i quali il subsidy è zero
+pub const MAX_HALVINGS: u64 = 64;
+
+/// Subsidy del block ad altezza `height` (halving ogni `HALVING_INTERVAL` blocks)
+pub fn subsidy_at(height: u64) -> u64 {
+    subsidy_with_interval(height, HALVING_INTERVAL)
+}
+
+/// Come `subsidy_at`, con un halving ogni `halving_interval` blocks
+pub fn subsidy_with_interval(height: u64, halving_interval: u64) -> u64 {
+    let halvings = height / halving_interval;
+    if halvings >= MAX_HALVINGS {
+        0
+    } else {
+        INITIAL_BLOCK_REWARD >> halvings
+    }
+}
+
+/// Supply emesso dai subsidy dei block da 1 a `height` compreso
+///
+/// Il genesis non paga subsidy: il suo premine (vedi `GenesisSpec`) va
+/// sommato a parte.
+pub fn supply_at(height: u64) -> u64 {
+    supply_with_interval(height, HALVING_INTERVAL)
+}
+
+/// Come `supply_at`, con un halving ogni `halving_interval` blocks
+pub fn supply_with_interval(height: u64, halving_interval: u64) -> u64 {
+    let mut supply = 0u64;
+    let mut epoch_start = 1;
+    while epoch_start <= height {
+        let subsidy = subsidy_with_interval(epoch_start, halving_interval);
+        if subsidy == 0 {
+            break;
+        }
+        let epoch_end = (epoch_start / halving_interval + 1) * halving_interval - 1;
+        let blocks = epoch_end.min(height) - epoch_start + 1;
+        supply = supply.saturating_add(subsidy.saturating_mul(blocks));
+        epoch_start = epoch_end + 1;
+    }
+    supply
+}
+
+/// Supply massimo emesso dai subsidy
+pub fn max_supply() -> u64 {
+    supply_at(MAX_HALVINGS * HALVING_INTERVAL)
+}
+
+/// Altezza del primo halving successivo a `height` (None dopo l'ultimo)
+pub fn next_halving_height(height: u64) -> Option<u64> {
+    next_halving_with_interval(height, HALVING_INTERVAL)
+}
+
+/// Come `next_halving_height`, con un halving ogni `halving_interval` blocks
+pub fn next_halving_with_interval(height: u64, halving_interval: u64) -> Option<u64> {
+    let next = (height / halving_interval + 1).checked_mul(halving_interval)?;
+    (subsidy_with_interval(height, halving_interval) > 0).then_some(next)
+}
+
+/// Stima del prossimo halving
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HalvingEstimate {
+    /// Altezza del halving
+    pub height: u64,
+    /// Block mancanti dal tip
+    pub blocks_remaining: u64,
+    /// Subsidy dopo il halving
+    pub subsidy: u64,
+    /// Timestamp stimato (secondi Unix)
+    pub estimated_time: u64,
+}
+
+/// Stima il prossimo halving dopo il tip
+///
+/// Il timestamp proietta i block mancanti con il block time medio dato
+/// (tipicamente misurato sui block recenti).
+pub fn estimate_next_halving(tip_height: u64, tip_time: u64, average_block_time: f64) -> Option<HalvingEstimate> {
+    estimate_next_halving_with_interval(tip_height, tip_time, average_block_time, HALVING_INTERVAL)
+}
+
+/// Come `estimate_next_halving`, con un halving ogni `halving_interval` blocks
+pub fn estimate_next_halving_with_interval(
+    tip_height: u64,
+    tip_time: u64,
+    average_block_time: f64,
+    halving_interval: u64,
+) -> Option<HalvingEstimate> {
+    let height = next_halving_with_interval(tip_height, halving_interval)?;
+    let blocks_remaining = height - tip_height;
+    let seconds = (blocks_remaining as f64 * average_block_time.max(0.0)).round() as u64;
+    Some(HalvingEstimate {
+        height,
+        blocks_remaining,
+        subsidy: subsidy_with_interval(height, halving_interval),
+        estimated_time: tip_time.saturating_add(seconds),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsidy_schedule() {
+        assert_eq!(subsidy_at(0), INITIAL_BLOCK_REWARD);
+        assert_eq!(subsidy_at(HALVING_INTERVAL - 1), INITIAL_BLOCK_REWARD);
+        assert_eq!(subsidy_at(HALVING_INTERVAL), INITIAL_BLOCK_REWARD / 2);
+        assert_eq!(subsidy_at(MAX_HALVINGS * HALVING_INTERVAL), 0);
+    }
+
+    #[test]
+    fn test_supply_at() {
+        assert_eq!(supply_at(0), 0);
+        assert_eq!(supply_at(1), INITIAL_BLOCK_REWARD);
+        assert_eq!(supply_at(HALVING_INTERVAL - 1), (HALVING_INTERVAL - 1) * INITIAL_BLOCK_REWARD);
+        assert_eq!(
+            supply_at(HALVING_INTERVAL + 9),
+            (HALVING_INTERVAL - 1) * INITIAL_BLOCK_REWARD + 10 * (INITIAL_BLOCK_REWARD / 2)
+        );
+
+        // Stessa somma del calcolo block per block nelle prime epoche
+        let brute: u64 = (1..=2 * HALVING_INTERVAL + 5).map(subsidy_at).sum();
+        assert_eq!(supply_at(2 * HALVING_INTERVAL + 5), brute);
+
+        assert_eq!(max_supply(), supply_at(u64::MAX));
+        assert!(max_supply() < 2 * HALVING_INTERVAL * INITIAL_BLOCK_REWARD);
+    }
+
+    #[test]
+    fn test_custom_halving_interval() {
+        assert_eq!(subsidy_with_interval(9, 10), INITIAL_BLOCK_REWARD);
+        assert_eq!(subsidy_with_interval(25, 10), INITIAL_BLOCK_REWARD / 4);
+        let brute: u64 = (1..=35).map(|height| subsidy_with_interval(height, 10)).sum();
+        assert_eq!(supply_with_interval(35, 10), brute);
+        assert_eq!(next_halving_with_interval(25, 10), Some(30));
+        let estimate = estimate_next_halving_with_interval(25, 1_000, 10.0, 10).unwrap();
+        assert_eq!((estimate.blocks_remaining, estimate.estimated_time), (5, 1_050));
+    }
+
+    #[test]
+    fn test_next_halving() {
+        assert_eq!(next_halving_height(0), Some(HALVING_INTERVAL));
+        assert_eq!(next_halving_height(HALVING_INTERVAL), Some(2 * HALVING_INTERVAL));
+        assert_eq!(next_halving_height(MAX_HALVINGS * HALVING_INTERVAL), None);
+
+        let estimate = estimate_next_halving(HALVING_INTERVAL - 10, 1_000, 120.0).unwrap();
+        assert_eq!(estimate.height, HALVING_INTERVAL);
+        assert_eq!(estimate.blocks_remaining, 10);
+        assert_eq!(estimate.subsidy, INITIAL_BLOCK_REWARD / 2);
+        assert_eq!(estimate.estimated_time, 2_200);
+    }
+}
@@ -1,10 +1,15 @@
 //! eUTXO Transaction structures per Sedly blockchain
 
-use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use crate::Amount;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Transazione eUTXO (extended UTXO)
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// I campi serializzati dipendono dal formato: `expiry_height` esiste solo
+/// dalla versione 2, così bytes e txid delle transazioni v1 non cambiano.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Transaction {
     /// Versione del formato transazione
     pub version: u32,
@@ -14,6 +19,9 @@ pub struct Transaction {
     pub outputs: Vec<TxOutput>,
     /// Lock time (0 = valida subito)
     pub lock_time: u64,
+    /// Ultima altezza a cui la transazione può entrare in un block
+    /// (0 = nessuna scadenza; ignorata dai formati senza scadenza)
+    pub expiry_height: u64,
 }
 
 /// Input di transazione (riferimento a UTXO esistente)
@@ -30,8 +38,8 @@ pub struct TxInput {
 /// Output di transazione (nuovo UTXO creato)
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxOutput {
-    /// Valore in satoshi (1 SLY = 100,000,000 satoshi)
-    pub value: u64,
+    /// Valore (1 SLY = 100,000,000 satoshi)
+    pub value: Amount,
     /// Asset ID (per multi-asset, [0;32] = native SLY)
     pub asset_id: [u8; 32],
     /// Script che definisce come spendere questo output
@@ -39,7 +47,7 @@ pub struct TxOutput {
 }
 
 /// Riferimento a un output di transazione precedente
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct OutPoint {
     /// Hash della transazione che contiene l'output
     pub txid: [u8; 32],
@@ -47,6 +55,124 @@ pub struct OutPoint {
     pub vout: u32,
 }
 
+/// Formato di una transazione, scelto dal campo `version`
+///
+/// Decodifica e validazione passano dal formato: un formato futuro (es.
+/// witness o datum) aggiunge qui la sua variante e un ramo nei due punti,
+/// senza toccare le transazioni esistenti.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxFormat {
+    /// Formato iniziale: input, output e lock time
+    V1,
+    /// Formato v1 con altezza di scadenza
+    V2,
+}
+
+impl TxFormat {
+    /// Formato usato dalle nuove transazioni
+    pub const CURRENT: TxFormat = TxFormat::V1;
+
+    /// Formato di una versione, None se sconosciuta a questo nodo
+    pub fn from_version(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(TxFormat::V1),
+            2 => Some(TxFormat::V2),
+            _ => None,
+        }
+    }
+
+    /// Versione che identifica il formato
+    pub fn version(&self) -> u32 {
+        match self {
+            TxFormat::V1 => 1,
+            TxFormat::V2 => 2,
+        }
+    }
+
+    /// Se il formato serializza `expiry_height`
+    pub fn has_expiry(&self) -> bool {
+        matches!(self, TxFormat::V2)
+    }
+}
+
+/// Se la versione serializza `expiry_height` (le versioni sconosciute no)
+fn has_expiry_field(version: u32) -> bool {
+    TxFormat::from_version(version).is_some_and(|format| format.has_expiry())
+}
+
+/// Campi di una transazione, nell'ordine di serializzazione
+const TRANSACTION_FIELDS: &[&str] = &["version", "inputs", "outputs", "lock_time", "expiry_height"];
+
+impl Serialize for Transaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let expiry = has_expiry_field(self.version);
+        let mut state = serializer.serialize_struct("Transaction", if expiry { 5 } else { 4 })?;
+        state.serialize_field("version", &self.version)?;
+        state.serialize_field("inputs", &self.inputs)?;
+        state.serialize_field("outputs", &self.outputs)?;
+        state.serialize_field("lock_time", &self.lock_time)?;
+        if expiry {
+            state.serialize_field("expiry_height", &self.expiry_height)?;
+        } else {
+            state.skip_field("expiry_height")?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // I formati testuali (JSON) hanno i nomi dei campi: basta un default
+        if deserializer.is_human_readable() {
+            let fields = TransactionFields::deserialize(deserializer)?;
+            let expiry = has_expiry_field(fields.version);
+            return Ok(Transaction {
+                version: fields.version,
+                inputs: fields.inputs,
+                outputs: fields.outputs,
+                lock_time: fields.lock_time,
+                expiry_height: if expiry { fields.expiry_height } else { 0 },
+            });
+        }
+        deserializer.deserialize_struct("Transaction", TRANSACTION_FIELDS, TransactionVisitor)
+    }
+}
+
+/// Transazione in un formato testuale, con `expiry_height` facoltativo
+#[derive(Deserialize)]
+struct TransactionFields {
+    version: u32,
+    inputs: Vec<TxInput>,
+    outputs: Vec<TxOutput>,
+    lock_time: u64,
+    #[serde(default)]
+    expiry_height: u64,
+}
+
+/// Decodifica binaria: la versione, letta per prima, dice se segue `expiry_height`
+struct TransactionVisitor;
+
+impl<'de> Visitor<'de> for TransactionVisitor {
+    type Value = Transaction;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a transaction")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Transaction, A::Error> {
+        let version: u32 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let inputs = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let outputs = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let lock_time = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let expiry_height = if has_expiry_field(version) {
+            seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?
+        } else {
+            0
+        };
+        Ok(Transaction { version, inputs, outputs, lock_time, expiry_height })
+    }
+}
+
 /// Tipo di transazione
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransactionType {
@@ -64,23 +190,51 @@ impl Transaction {
         lock_time: u64,
     ) -> Self {
         Self {
-            version: crate::PROTOCOL_VERSION,
+            version: TxFormat::CURRENT.version(),
             inputs,
             outputs,
             lock_time,
+            expiry_height: 0,
         }
     }
 
+    /// Imposta l'altezza di scadenza, passando al formato v2
+    ///
+    /// Dopo `expiry_height` la transazione non può più entrare in un block
+    /// e il mempool la scarta. Va impostata prima di firmare.
+    pub fn with_expiry(mut self, expiry_height: u64) -> Self {
+        self.version = TxFormat::V2.version();
+        self.expiry_height = expiry_height;
+        self
+    }
+
+    /// Ultima altezza a cui la transazione può entrare in un block, None se non scade
+    pub fn expiry(&self) -> Option<u64> {
+        (has_expiry_field(self.version) && self.expiry_height != 0).then_some(self.expiry_height)
+    }
+
+    /// Se la transazione è scaduta per un block ad altezza `height`
+    pub fn is_expired(&self, height: u64) -> bool {
+        self.expiry().is_some_and(|expiry_height| height > expiry_height)
+    }
+
     /// Calcola hash della transazione (double SHA-256)
     pub fn hash(&self) -> [u8; 32] {
         let tx_bytes = bincode::serialize(self)
             .expect("Failed to serialize transaction");
 
         // Double SHA-256 come Bitcoin
-        let hash1 = Sha256::digest(&tx_bytes);
-        let hash2 = Sha256::digest(&hash1);
+        crate::hash::sha256d(&tx_bytes)
+    }
+
+    /// Hash della transazione come `Txid`
+    pub fn txid(&self) -> crate::Txid {
+        self.hash().into()
+    }
 
-        hash2.into()
+    /// Formato della transazione, None per una versione sconosciuta
+    pub fn format(&self) -> Option<TxFormat> {
+        TxFormat::from_version(self.version)
     }
 
     /// Verifica se è una transazione coinbase
@@ -113,7 +267,7 @@ impl Transaction {
 
         // Output con reward
         let reward_output = TxOutput {
-            value: reward,
+            value: Amount::from_sat(reward),
             asset_id: [0; 32], // Native SLY asset
             script_pubkey: reward_address.to_vec(),
         };
@@ -142,58 +296,53 @@ impl Transaction {
 
     /// Crea transazione genesis (prima transazione della blockchain)
     pub fn genesis() -> Self {
-        let genesis_message = b"Sedly - Fair Launch Blockchain";
+        // Genesis non ha output (tutto il supply viene creato tramite mining)
+        Self::genesis_coinbase(b"Sedly - Fair Launch Blockchain", Vec::new())
+    }
 
+    /// Coinbase di un genesis con messaggio `message` e allocazioni iniziali `outputs`
+    ///
+    /// Gli output entrano nel UTXO set ad altezza 0 come output coinbase
+    /// (vedi `ChainParams::mature_genesis_allocations`).
+    pub fn genesis_coinbase(message: &[u8], outputs: Vec<TxOutput>) -> Self {
         let coinbase_input = TxInput {
             previous_output: OutPoint {
                 txid: [0; 32],
                 vout: 0xffffffff,
             },
-            script_sig: genesis_message.to_vec(),
+            script_sig: message.to_vec(),
             sequence: 0xffffffff,
         };
 
-        // Genesis non ha output (tutto il supply viene creato tramite mining)
-        Self::new(
-            vec![coinbase_input],
-            vec![],
-            0,
-        )
+        Self::new(vec![coinbase_input], outputs, 0)
     }
 
     /// Calcola total input value
-    pub fn input_value(&self) -> u64 {
+    pub fn input_value(&self) -> Amount {
         // TODO: Implementare lookup UTXO set per calcolare valore reale
         // Per ora ritorna 0 per coinbase, altrimenti richiede UTXO set
         if self.is_coinbase() {
-            0
+            Amount::ZERO
         } else {
             // Richiede accesso al UTXO set per calcolare
-            0
+            Amount::ZERO
         }
     }
 
     /// Calcola total output value
-    pub fn output_value(&self) -> u64 {
-        self.outputs.iter()
-            .map(|output| output.value)
-            .sum()
+    pub fn output_value(&self) -> Amount {
+        Amount::from_sat(self.outputs.iter()
+            .map(|output| output.value.to_sat())
+            .sum())
     }
 
     /// Calcola fee della transazione
-    pub fn fee(&self) -> u64 {
+    pub fn fee(&self) -> Amount {
         if self.is_coinbase() {
-            0
+            Amount::ZERO
         } else {
-            // fee = input_value - output_value
-            let input_val = self.input_value();
-            let output_val = self.output_value();
-
-            if input_val >= output_val {
-                input_val - output_val
-            } else {
-                0 // Transazione invalida
-            }
+            // fee = input_value - output_value, zero se la transazione è invalida
+            self.input_value().saturating_sub(self.output_value())
         }
     }
 
@@ -211,36 +360,49 @@ impl Transaction {
 
         // Verifica che i valori degli output siano positivi
         for output in &self.outputs {
-            if output.value == 0 {
+            if output.value == Amount::ZERO {
                 return false;
             }
         }
 
-        // TODO: Verifica firme e script
+        // Firme e script dipendono dagli output spesi: li verifica `BlockValidator`
 
         true
     }
 
-    /// Dimensione della transazione in bytes
-    pub fn size(&self) -> usize {
-        bincode::serialize(self)
-            .map(|bytes| bytes.len())
-            .unwrap_or(0)
+    /// Dimensione della transazione serializzata in bytes
+    pub fn size(&self) -> Result<usize, SerializationError> {
+        serialized_size(self)
     }
 }
 
+/// Serializzazione di una transazione o di un block fallita
+///
+/// Una dimensione sconosciuta non va mai trattata come zero: per le policy
+/// di fee e i limiti di dimensione sarebbe una transazione gratuita.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Serialization failed: {0}")]
+pub struct SerializationError(pub String);
+
+/// Dimensione della serializzazione bincode, senza allocare il buffer
+pub(crate) fn serialized_size<T: Serialize>(value: &T) -> Result<usize, SerializationError> {
+    bincode::serialized_size(value)
+        .map(|size| size as usize)
+        .map_err(|e| SerializationError(e.to_string()))
+}
+
 impl TxOutput {
     /// Crea nuovo output
-    pub fn new(value: u64, asset_id: [u8; 32], script_pubkey: Vec<u8>) -> Self {
+    pub fn new(value: impl Into<Amount>, asset_id: [u8; 32], script_pubkey: Vec<u8>) -> Self {
         Self {
-            value,
+            value: value.into(),
             asset_id,
             script_pubkey,
         }
     }
 
     /// Crea output per indirizzo standard (P2PKH-style)
-    pub fn to_address(value: u64, address: &[u8]) -> Self {
+    pub fn to_address(value: impl Into<Amount>, address: &[u8]) -> Self {
         Self::new(
             value,
             [0; 32], // Native SLY asset
@@ -307,7 +469,44 @@ mod tests {
 
         assert!(coinbase.is_coinbase());
         assert_eq!(coinbase.outputs.len(), 1);
-        assert_eq!(coinbase.outputs[0].value, crate::INITIAL_BLOCK_REWARD);
+        assert_eq!(coinbase.outputs[0].value.to_sat(), crate::INITIAL_BLOCK_REWARD);
+    }
+
+    #[test]
+    fn test_size_matches_serialization() {
+        let coinbase = Transaction::coinbase(b"miner", 1, crate::INITIAL_BLOCK_REWARD);
+        assert_eq!(coinbase.size().unwrap(), bincode::serialize(&coinbase).unwrap().len());
+
+        let block = crate::Block::new([0; 32], vec![coinbase], 0x1d00ffff, 1);
+        assert_eq!(block.size().unwrap(), bincode::serialize(&block).unwrap().len());
+    }
+
+    #[test]
+    fn test_expiry_serialization() {
+        let tx = Transaction::new(vec![TxInput::new(OutPoint::new([1; 32], 0), vec![])], vec![], 7);
+        let v1 = bincode::serialize(&tx).unwrap();
+        assert_eq!((tx.expiry(), tx.is_expired(u64::MAX)), (None, false));
+
+        // La scadenza aggiunge 8 bytes e cambia il txid solo dal formato v2
+        let expiring = tx.clone().with_expiry(100);
+        let v2 = bincode::serialize(&expiring).unwrap();
+        assert_eq!(v2.len(), v1.len() + 8);
+        assert_eq!(expiring.size().unwrap(), v2.len());
+        assert_ne!(expiring.hash(), tx.hash());
+        assert_eq!(bincode::deserialize::<Transaction>(&v2).unwrap(), expiring);
+        assert_eq!(bincode::deserialize::<Transaction>(&v1).unwrap(), tx);
+        assert!(!expiring.is_expired(100) && expiring.is_expired(101));
+
+        // Dentro un block la transazione successiva si decodifica dal punto giusto
+        let pair = bincode::serialize(&vec![expiring.clone(), tx.clone()]).unwrap();
+        assert_eq!(bincode::deserialize::<Vec<Transaction>>(&pair).unwrap(), vec![expiring.clone(), tx.clone()]);
+        assert!(bincode::deserialize::<Transaction>(&v2[..v2.len() - 1]).is_err());
+
+        let json = serde_json::to_value(&tx).unwrap();
+        assert!(json.get("expiry_height").is_none());
+        assert_eq!(serde_json::from_value::<Transaction>(json).unwrap(), tx);
+        let json = serde_json::to_value(&expiring).unwrap();
+        assert_eq!(serde_json::from_value::<Transaction>(json).unwrap(), expiring);
     }
 
     #[test]
@@ -323,6 +522,6 @@ mod tests {
     fn test_output_native_asset() {
         let output = TxOutput::to_address(1000, b"test_address");
         assert!(output.is_native_asset());
-        assert_eq!(output.value, 1000);
+        assert_eq!(output.value, Amount::from_sat(1000));
     }
 }
\ No newline at end of file
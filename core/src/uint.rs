@@ -0,0 +1,285 @@
+//! Intero senza segno a 256 bit per target e chainwork
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Not, Shl, Shr, Sub};
+
+/// Intero a 256 bit (limb little-endian da 64 bit)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    /// Zero
+    pub const ZERO: U256 = U256([0; 4]);
+    /// Uno
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    /// Valore massimo (2^256 - 1)
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    /// Crea da 32 bytes big-endian (formato dei target)
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            let mut chunk = [0u8; 8];
+            chunk.copy_from_slice(&bytes[start..start + 8]);
+            *limb = u64::from_be_bytes(chunk);
+        }
+        U256(limbs)
+    }
+
+    /// Converte in 32 bytes big-endian
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Verifica se è zero
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Numero di bit significativi
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return (i as u32) * 64 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+
+    /// Addizione con controllo overflow
+    pub fn checked_add(&self, other: &U256) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        if carry {
+            None
+        } else {
+            Some(U256(result))
+        }
+    }
+
+    /// Sottrazione con controllo underflow
+    pub fn checked_sub(&self, other: &U256) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        if borrow {
+            None
+        } else {
+            Some(U256(result))
+        }
+    }
+
+    /// Addizione saturata a `U256::MAX`
+    pub fn saturating_add(&self, other: &U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    /// Divisione con resto (long division bit a bit)
+    pub fn div_rem(&self, divisor: &U256) -> Option<(U256, U256)> {
+        if divisor.is_zero() {
+            return None;
+        }
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(bit) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= *divisor {
+                remainder = remainder - *divisor;
+                quotient.0[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+
+        Some((quotient, remainder))
+    }
+
+    /// Valore del bit alla posizione data
+    fn bit(&self, index: u32) -> bool {
+        (self.0[(index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+
+    /// 128 bit meno significativi
+    pub fn low_u128(&self) -> u128 {
+        (self.0[0] as u128) | ((self.0[1] as u128) << 64)
+    }
+
+    /// Approssimazione in virgola mobile
+    pub fn to_f64(&self) -> f64 {
+        self.0
+            .iter()
+            .rev()
+            .fold(0.0, |acc, limb| acc * 18446744073709551616.0 + *limb as f64)
+    }
+
+    /// Formato esadecimale big-endian (64 caratteri)
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_be_bytes())
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        U256([value as u64, (value >> 64) as u64, 0, 0])
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for U256 {
+    type Output = U256;
+
+    fn add(self, other: U256) -> U256 {
+        self.checked_add(&other).expect("U256 addition overflow")
+    }
+}
+
+impl Sub for U256 {
+    type Output = U256;
+
+    fn sub(self, other: U256) -> U256 {
+        self.checked_sub(&other).expect("U256 subtraction underflow")
+    }
+}
+
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, other: U256) -> U256 {
+        self.div_rem(&other).expect("U256 division by zero").0
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().take(4usize.saturating_sub(limb_shift)) {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < 4 {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256(0x{})", self.to_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut bytes = [0u8; 32];
+        bytes[3] = 0xff;
+        bytes[31] = 0x01;
+        let value = U256::from_be_bytes(&bytes);
+        assert_eq!(value.to_be_bytes(), bytes);
+        assert_eq!(value.bits(), 232);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = U256::from(u128::MAX);
+        let b = a + U256::ONE;
+        assert_eq!(b, U256::ONE << 128);
+        assert_eq!(b - U256::ONE, a);
+        assert_eq!(b >> 128, U256::ONE);
+        assert!(U256::MAX.checked_add(&U256::ONE).is_none());
+        assert!(U256::ZERO.checked_sub(&U256::ONE).is_none());
+    }
+
+    #[test]
+    fn test_division() {
+        let (q, r) = U256::from(1000u64).div_rem(&U256::from(7u64)).unwrap();
+        assert_eq!(q, U256::from(142u64));
+        assert_eq!(r, U256::from(6u64));
+
+        let big = U256::ONE << 200;
+        assert_eq!(big / (U256::ONE << 100), U256::ONE << 100);
+        assert!(big.div_rem(&U256::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_to_f64() {
+        assert_eq!(U256::from(12345u64).to_f64(), 12345.0);
+        assert_eq!((U256::ONE << 128).to_f64(), 2f64.powi(128));
+    }
+}
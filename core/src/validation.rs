@@ -1,10 +1,1129 @@
 //! Block and transaction validation
 
+use crate::auxpow::AuxPowError;
+use crate::difficulty::DifficultyAdjuster;
+use crate::interpreter::{
+    transaction_script_cost, ExecutionBudget, InterpreterError, VerifyFlags, MAX_BLOCK_SCRIPT_COST, MAX_TX_SCRIPT_COST,
+};
+use crate::params::ChainParams;
+use crate::script::MAX_SCRIPT_SIZE;
+use crate::storage::{BlockchainDB, StorageError, UtxoEntry};
+use crate::{Amount, Block, BlockHeader, OutPoint, SerializationError, Transaction, TxFormat};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Block di cui si prende la mediana dei timestamp (median time past)
+pub const MEDIAN_TIME_SPAN: u64 = 11;
+
+/// Secondi per cui il timestamp di un block può precedere l'ora locale (2 ore)
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+/// Reward del block a una data altezza con l'halving di mainnet (vedi
+/// `supply::subsidy_at` e, per le altre reti, `ChainParams::subsidy`)
+pub fn block_subsidy(height: u64) -> u64 {
+    crate::supply::subsidy_at(height)
+}
+
+/// Risultato della validazione di un block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedBlock {
+    /// Hash del block
+    pub hash: [u8; 32],
+    /// Somma delle fee (SLY nativo) delle transazioni non-coinbase
+    pub total_fees: u64,
+}
+
+/// Esito della verifica degli input di una transazione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckedInputs {
+    /// Fee in SLY nativo
+    pub fee: u64,
+    /// Costo di esecuzione degli script degli input
+    pub script_cost: u64,
+}
+
+/// Validatore contestuale dei block
+///
+/// Verifica struttura, collegamento al parent e spese contro il UTXO set
+/// del database (che deve trovarsi allo stato del parent).
+#[derive(Debug, Clone)]
+pub struct BlockValidator {
+    /// Parametri di consenso
+    params: ChainParams,
+    /// Verifica proof of work (disattivata per i block prodotti da Tendermint)
+    check_proof_of_work: bool,
+    /// Dimensione massima di un block in byte
+    max_block_size: usize,
+    /// Costo massimo degli script delle transazioni di un block
+    max_block_script_cost: u64,
+}
+
+impl BlockValidator {
+    /// Crea validatore per i parametri dati (senza verifica PoW)
+    pub fn new(params: ChainParams) -> Self {
+        Self {
+            params,
+            check_proof_of_work: false,
+            max_block_size: crate::MAX_BLOCK_SIZE,
+            max_block_script_cost: MAX_BLOCK_SCRIPT_COST,
+        }
+    }
+
+    /// Attiva o disattiva la verifica proof of work
+    pub fn with_proof_of_work(mut self, enabled: bool) -> Self {
+        self.check_proof_of_work = enabled;
+        self
+    }
+
+    /// Imposta la dimensione massima dei block (es. modificata dalla governance)
+    pub fn set_max_block_size(&mut self, max_block_size: usize) {
+        self.max_block_size = max_block_size;
+    }
+
+    /// Dimensione massima dei block in vigore
+    pub fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    /// Imposta il costo massimo degli script di un block (default `MAX_BLOCK_SCRIPT_COST`)
+    pub fn set_max_block_script_cost(&mut self, max_block_script_cost: u64) {
+        self.max_block_script_cost = max_block_script_cost;
+    }
+
+    /// Costo massimo degli script di un block in vigore, anche per i
+    /// template (`Mempool::select_for_block`)
+    pub fn max_block_script_cost(&self) -> u64 {
+        self.max_block_script_cost
+    }
+
+    /// Parametri di consenso usati
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    /// Valida un block sopra `parent` (None per il genesis)
+    pub fn validate_block(
+        &self,
+        block: &Block,
+        parent: Option<&BlockHeader>,
+        db: &BlockchainDB,
+    ) -> Result<ValidatedBlock, ValidationError> {
+        self.check_header(block, parent)?;
+        if let Some(parent) = parent {
+            self.check_header_context(block, parent, |from, to| chain_timestamps(db, from, to))?;
+        }
+        let txids = block.txids();
+        self.check_structure_with_txids(block, &txids)?;
+        let validated = self.check_transactions_with_txids(block, &txids, db)?;
+        self.check_scripts(block)?;
+        Ok(validated)
+    }
+
+    /// Verifica le spese del block contro il UTXO set, il valore della
+    /// coinbase, la quota del treasury e il costo degli script del block
+    /// (`max_block_script_cost`)
+    ///
+    /// Presuppone che `check_structure` sia già passato.
+    pub fn check_transactions(&self, block: &Block, db: &BlockchainDB) -> Result<ValidatedBlock, ValidationError> {
+        self.check_transactions_with_txids(block, &block.txids(), db)
+    }
+
+    /// Come `check_transactions`, con i txid già calcolati (`Block::txids`)
+    pub fn check_transactions_with_txids(
+        &self,
+        block: &Block,
+        txids: &[[u8; 32]],
+        db: &BlockchainDB,
+    ) -> Result<ValidatedBlock, ValidationError> {
+        assert_eq!(txids.len(), block.transactions.len(), "txids do not match the block");
+        let hash = block.hash();
+        let height = block.header.height;
+        let mut spends = BlockSpends::new();
+        let mut total_fees: u64 = 0;
+        let mut script_cost: u64 = 0;
+
+        for (tx_index, (tx, &txid)) in block.transactions.iter().zip(txids).enumerate() {
+            if tx_index > 0 {
+                let checked = self.check_block_transaction(tx, txid, height, db, &spends)?;
+                total_fees = total_fees
+                    .checked_add(checked.fee)
+                    .ok_or(ValidationError::ValueOverflow { txid })?;
+                script_cost = script_cost.saturating_add(checked.script_cost);
+                if script_cost > self.max_block_script_cost {
+                    return Err(ValidationError::BlockScriptCost { cost: script_cost, max: self.max_block_script_cost });
+                }
+            }
+            spends.record(tx, txid, height);
+        }
+
+        let coinbase = &block.transactions[0];
+        let coinbase_value = asset_values(coinbase.outputs.iter().map(|output| (output.asset_id, output.value)))
+            .ok_or(ValidationError::ValueOverflow { txid: txids[0] })?
+            .get(&NATIVE_ASSET_ID)
+            .map_or(0, |value| value.to_sat());
+        let subsidy = self.params.subsidy(height);
+        let max_coinbase = subsidy.saturating_add(total_fees);
+        if coinbase_value > max_coinbase {
+            return Err(ValidationError::ExcessiveCoinbase {
+                value: coinbase_value,
+                max: max_coinbase,
+            });
+        }
+
+        if let Some(treasury) = &self.params.treasury {
+            let required = treasury.allocation(subsidy);
+            let paid = treasury.paid_by(coinbase);
+            if paid < required {
+                return Err(ValidationError::TreasuryUnderpaid { paid, required });
+            }
+        }
+
+        Ok(ValidatedBlock { hash, total_fees })
+    }
+
+    /// Verifica le spese di una transazione non coinbase di un block a
+    /// `height`, dopo le transazioni che la precedono registrate in `spends`
+    ///
+    /// Sono le regole di `check_transactions` per una singola transazione:
+    /// chi costruisce un block una transazione alla volta (es. DeliverTx)
+    /// chiama [`BlockSpends::record`] solo per quelle accettate.
+    pub fn check_block_transaction(
+        &self,
+        tx: &Transaction,
+        txid: [u8; 32],
+        height: u64,
+        db: &BlockchainDB,
+        spends: &BlockSpends,
+    ) -> Result<CheckedInputs, ValidationError> {
+        self.check_inputs(tx, txid, height, db, &spends.spent, &spends.created)
+    }
+
+    /// Valida una transazione non confermata che entrerebbe nel block a `height`
+    ///
+    /// `created` contiene gli output non ancora confermati che la transazione
+    /// può spendere (es. parent in mempool). Ritorna la fee in SLY nativo e
+    /// il costo degli script.
+    pub fn validate_transaction(
+        &self,
+        tx: &Transaction,
+        height: u64,
+        db: &BlockchainDB,
+        created: &HashMap<OutPoint, UtxoEntry>,
+    ) -> Result<CheckedInputs, ValidationError> {
+        if tx.is_coinbase() {
+            return Err(ValidationError::UnexpectedCoinbase { txid: tx.hash() });
+        }
+        if !tx.is_valid() {
+            return Err(ValidationError::InvalidTransaction { txid: tx.hash() });
+        }
+        self.check_format(tx, tx.hash(), height)?;
+        self.check_inputs(tx, tx.hash(), height, db, &HashSet::new(), created)
+    }
+
+    /// Verifica che la versione sia ammessa a `height` e i campi del suo formato
+    ///
+    /// Finché non ci sono regole di emissione gli output possono portare
+    /// solo SLY nativo, anche nella coinbase.
+    pub fn check_format(&self, tx: &Transaction, txid: [u8; 32], height: u64) -> Result<(), ValidationError> {
+        let unsupported = || ValidationError::UnsupportedVersion { txid, version: tx.version, height };
+        if !self.params.allowed_tx_versions(height).contains(&tx.version) {
+            return Err(unsupported());
+        }
+        if let Some(output) = tx.outputs.iter().find(|output| !output.is_native_asset()) {
+            return Err(ValidationError::UnsupportedAsset { txid, asset_id: output.asset_id });
+        }
+        match tx.format() {
+            // Nessun campo oltre a quelli verificati da `Transaction::is_valid`
+            Some(TxFormat::V1) => Ok(()),
+            Some(TxFormat::V2) => match tx.expiry() {
+                Some(expiry_height) if height > expiry_height => {
+                    Err(ValidationError::ExpiredTransaction { txid, expiry_height, height })
+                }
+                _ => Ok(()),
+            },
+            // Versione ammessa dai parametri ma sconosciuta a questo nodo
+            None => Err(unsupported()),
+        }
+    }
+
+    /// Verifica i limiti degli script di input e output
+    pub fn check_scripts(&self, block: &Block) -> Result<(), ValidationError> {
+        for tx in &block.transactions {
+            let scripts = tx.inputs.iter().map(|input| &input.script_sig)
+                .chain(tx.outputs.iter().map(|output| &output.script_pubkey));
+            for script in scripts {
+                if script.len() > MAX_SCRIPT_SIZE {
+                    return Err(ValidationError::ScriptTooLarge { txid: tx.hash(), size: script.len() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifica header e collegamento al parent
+    pub fn check_header(&self, block: &Block, parent: Option<&BlockHeader>) -> Result<(), ValidationError> {
+        let header = &block.header;
+        match parent {
+            Some(parent) => {
+                if header.previous_hash != parent.hash() {
+                    return Err(ValidationError::BadParent);
+                }
+                if header.height != parent.height + 1 {
+                    return Err(ValidationError::BadHeight {
+                        expected: parent.height + 1,
+                        got: header.height,
+                    });
+                }
+                if block.auxpow.is_some() && !self.params.is_auxpow_active(header.height) {
+                    return Err(ValidationError::AuxPowNotActive { height: header.height });
+                }
+                // Il genesis è fissato dai parametri e non è minato
+                if self.check_proof_of_work {
+                    self.check_work(block)?;
+                }
+            }
+            None => {
+                if header.previous_hash != [0; 32] {
+                    return Err(ValidationError::BadParent);
+                }
+                if header.height != 0 {
+                    return Err(ValidationError::BadHeight { expected: 0, got: header.height });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifica le regole dell'header che dipendono dagli antenati: bits
+    /// del retarget, timestamp oltre la median time past e non oltre
+    /// `MAX_FUTURE_BLOCK_TIME` dall'ora locale
+    ///
+    /// `timestamps(from, to)` dà i timestamp degli antenati del block alle
+    /// altezze `[from, to)`. Come la proof of work, le regole valgono solo se
+    /// il validatore la verifica: con Tendermint difficulty e tempo dei block
+    /// non ordinano la chain.
+    pub fn check_header_context(
+        &self,
+        block: &Block,
+        parent: &BlockHeader,
+        timestamps: impl Fn(u64, u64) -> Option<Vec<u64>>,
+    ) -> Result<(), ValidationError> {
+        if !self.check_proof_of_work {
+            return Ok(());
+        }
+        let header = &block.header;
+        let expected = self.expected_bits(header.height, parent.bits, &timestamps)?;
+        if header.bits != expected {
+            return Err(ValidationError::BadDifficulty { expected, got: header.bits });
+        }
+        let median = median_time_past(header.height, &timestamps)?;
+        if header.timestamp <= median {
+            return Err(ValidationError::TimeTooOld { timestamp: header.timestamp, median });
+        }
+        let max = BlockHeader::current_timestamp().saturating_add(MAX_FUTURE_BLOCK_TIME);
+        if header.timestamp > max {
+            return Err(ValidationError::TimeTooNew { timestamp: header.timestamp, max });
+        }
+        Ok(())
+    }
+
+    /// Bits richiesti al block a `height` sopra un parent con `parent_bits`
+    ///
+    /// Cambiano solo ai multipli di `difficulty_adjustment_interval`, con il
+    /// retarget sui timestamp dati da `timestamps(from, to)` (vedi
+    /// `check_header_context`). Una finestra che il retarget non può
+    /// misurare lascia i bits del parent.
+    pub fn expected_bits(
+        &self,
+        height: u64,
+        parent_bits: u32,
+        timestamps: impl Fn(u64, u64) -> Option<Vec<u64>>,
+    ) -> Result<u32, ValidationError> {
+        let interval = self.params.difficulty_adjustment_interval;
+        if interval == 0 || !height.is_multiple_of(interval) {
+            return Ok(parent_bits);
+        }
+        let difficulty = DifficultyAdjuster::from_params(&self.params);
+        let Some(start_height) = difficulty.window_start_height(height) else {
+            return Ok(parent_bits);
+        };
+        let window = timestamps(start_height, height).ok_or(ValidationError::UnknownAncestors { height })?;
+        match difficulty.calculate_next_difficulty_from_timestamps(&window, parent_bits) {
+            Ok(adjustment) => Ok(adjustment.new_bits),
+            Err(e) => {
+                log::warn!("Retarget window at height {} not measurable, keeping parent bits: {}", height, e);
+                Ok(parent_bits)
+            }
+        }
+    }
+
+    /// Verifica la proof of work di un block il cui parent non è ancora noto
+    ///
+    /// Senza parent i bits non si possono verificare: si controlla solo che
+    /// il block soddisfi quelli dichiarati, così un orfano costa almeno quel
+    /// lavoro. Non fa nulla se il validatore non verifica la proof of work.
+    pub fn check_orphan_work(&self, block: &Block) -> Result<(), ValidationError> {
+        if self.check_proof_of_work {
+            self.check_work(block)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Verifica la proof of work dell'header, con l'algoritmo in vigore
+    /// alla sua altezza, o per i block merge-minati della prova AuxPoW
+    ///
+    /// La prova AuxPoW è sempre SHA-256d del parent: vale solo alle altezze
+    /// in cui è attiva e l'algoritmo in vigore è SHA-256d.
+    fn check_work(&self, block: &Block) -> Result<(), ValidationError> {
+        let height = block.header.height;
+        let algorithm = self.params.pow_algorithm(height).algorithm();
+        match &block.auxpow {
+            Some(_) if !self.params.is_auxpow_active(height) => Err(ValidationError::AuxPowNotActive { height }),
+            Some(auxpow) => auxpow.check(&block.hash(), &block.header.target()).map_err(ValidationError::InvalidAuxPow),
+            None if block.header.meets_difficulty_with(algorithm) => Ok(()),
+            None => Err(ValidationError::InsufficientWork),
+        }
+    }
+
+    /// Verifica struttura del block indipendente dal contesto
+    pub fn check_structure(&self, block: &Block) -> Result<(), ValidationError> {
+        self.check_structure_with_txids(block, &block.txids())
+    }
+
+    /// Come `check_structure`, con i txid già calcolati (`Block::txids`)
+    pub fn check_structure_with_txids(&self, block: &Block, txids: &[[u8; 32]]) -> Result<(), ValidationError> {
+        assert_eq!(txids.len(), block.transactions.len(), "txids do not match the block");
+        if block.transactions.is_empty() {
+            return Err(ValidationError::NoTransactions);
+        }
+        if crate::hash::merkle_root(txids) != block.header.merkle_root {
+            return Err(ValidationError::BadMerkleRoot);
+        }
+        let size = block.size()?;
+        if size > self.max_block_size {
+            return Err(ValidationError::Oversized { size });
+        }
+        if !block.transactions[0].is_coinbase() {
+            return Err(ValidationError::MissingCoinbase);
+        }
+
+        let mut seen = HashSet::new();
+        for (tx_index, (tx, &txid)) in block.transactions.iter().zip(txids).enumerate() {
+            if tx_index > 0 && tx.is_coinbase() {
+                return Err(ValidationError::MultipleCoinbase);
+            }
+            if !tx.is_valid() {
+                return Err(ValidationError::InvalidTransaction { txid });
+            }
+            self.check_format(tx, txid, block.header.height)?;
+            if !seen.insert(txid) {
+                return Err(ValidationError::DuplicateTransaction { txid });
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifica gli input di una transazione: esistenza, maturità, valore e
+    /// script che li sbloccano
+    ///
+    /// Il valore si conserva per ogni `asset_id`: gli output di un asset non
+    /// superano i suoi input. La fee è la differenza in SLY nativo.
+    fn check_inputs(
+        &self,
+        tx: &Transaction,
+        txid: [u8; 32],
+        height: u64,
+        db: &BlockchainDB,
+        spent: &HashSet<OutPoint>,
+        created: &HashMap<OutPoint, UtxoEntry>,
+    ) -> Result<CheckedInputs, ValidationError> {
+        let mut inputs = Vec::with_capacity(tx.inputs.len());
+        let mut spent_scripts = Vec::with_capacity(tx.inputs.len());
+        let mut spending = HashSet::with_capacity(tx.inputs.len());
+
+        for input in &tx.inputs {
+            let outpoint = &input.previous_output;
+            if spent.contains(outpoint) || !spending.insert(outpoint) {
+                return Err(ValidationError::DoubleSpend { outpoint: outpoint.clone() });
+            }
+
+            let entry = match created.get(outpoint) {
+                Some(entry) => entry.clone(),
+                None => db.get_utxo(outpoint)?
+                    .ok_or_else(|| ValidationError::MissingInput { outpoint: outpoint.clone() })?,
+            };
+
+            let maturity = self.params.coinbase_maturity_at(entry.block_height);
+            if entry.is_coinbase && height < entry.block_height + maturity {
+                return Err(ValidationError::ImmatureCoinbase { outpoint: outpoint.clone() });
+            }
+            inputs.push((entry.output.asset_id, entry.output.value));
+            spent_scripts.push(entry.output.script_pubkey);
+        }
+
+        let input_values = asset_values(inputs.into_iter()).ok_or(ValidationError::ValueOverflow { txid })?;
+        let output_values = asset_values(tx.outputs.iter().map(|output| (output.asset_id, output.value)))
+            .ok_or(ValidationError::ValueOverflow { txid })?;
+        for (asset_id, output_value) in &output_values {
+            let input_value = input_values.get(asset_id).copied().unwrap_or_default();
+            if *asset_id != NATIVE_ASSET_ID && *output_value > input_value {
+                return Err(ValidationError::AssetNotConserved { txid, asset_id: *asset_id });
+            }
+        }
+
+        let input_value = input_values.get(&NATIVE_ASSET_ID).map_or(0, |value| value.to_sat());
+        let output_value = output_values.get(&NATIVE_ASSET_ID).map_or(0, |value| value.to_sat());
+        let fee = input_value
+            .checked_sub(output_value)
+            .ok_or(ValidationError::InsufficientInputs { txid, input_value, output_value })?;
+
+        let mut budget = ExecutionBudget::new(MAX_TX_SCRIPT_COST);
+        let script_cost = transaction_script_cost(tx, &spent_scripts, VerifyFlags::STANDARD, &mut budget)
+            .map_err(|error| ValidationError::ScriptFailed { txid, error })?;
+        Ok(CheckedInputs { fee, script_cost })
+    }
+}
+
+/// Mediana dei timestamp degli ultimi `MEDIAN_TIME_SPAN` block sotto `height`
+///
+/// `timestamps(from, to)` dà i timestamp degli antenati alle altezze `[from, to)`.
+pub fn median_time_past(
+    height: u64,
+    timestamps: impl Fn(u64, u64) -> Option<Vec<u64>>,
+) -> Result<u64, ValidationError> {
+    let mut window = timestamps(height.saturating_sub(MEDIAN_TIME_SPAN), height)
+        .filter(|window| !window.is_empty())
+        .ok_or(ValidationError::UnknownAncestors { height })?;
+    window.sort_unstable();
+    Ok(window[window.len() / 2])
+}
+
+/// Timestamp dei block della chain attiva di `db` alle altezze `[from, to)`
+///
+/// None se ne manca uno. Da usare con `check_header_context` per un block
+/// sopra il tip del database.
+pub fn chain_timestamps(db: &BlockchainDB, from: u64, to: u64) -> Option<Vec<u64>> {
+    (from..to)
+        .map(|height| db.get_header_by_height(height).ok().flatten().map(|header| header.timestamp))
+        .collect()
+}
+
+/// Outpoint spesi e output creati dalle transazioni già verificate di un block
+///
+/// Gli output creati restano anche dopo essere stati spesi: è `spent` a
+/// impedire una seconda spesa.
+#[derive(Debug, Clone, Default)]
+pub struct BlockSpends {
+    spent: HashSet<OutPoint>,
+    created: HashMap<OutPoint, UtxoEntry>,
+}
+
+impl BlockSpends {
+    /// Nessuna transazione registrata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra spese e output di `tx`, incluso nel block a `height`
+    ///
+    /// Una coinbase non spende nulla e i suoi output restano soggetti alla maturità.
+    pub fn record(&mut self, tx: &Transaction, txid: [u8; 32], height: u64) {
+        let is_coinbase = tx.is_coinbase();
+        if !is_coinbase {
+            self.spent.extend(tx.inputs.iter().map(|input| input.previous_output.clone()));
+        }
+        for (vout, output) in tx.outputs.iter().enumerate() {
+            self.created.insert(
+                OutPoint::new(txid, vout as u32),
+                UtxoEntry {
+                    output: output.clone(),
+                    block_height: height,
+                    is_coinbase,
+                },
+            );
+        }
+    }
+
+    /// Output creato da una transazione registrata, anche se già speso
+    pub fn created(&self, outpoint: &OutPoint) -> Option<&UtxoEntry> {
+        self.created.get(outpoint)
+    }
+
+    /// Se una transazione registrata spende già `outpoint`
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.spent.contains(outpoint)
+    }
+}
+
+/// `asset_id` dello SLY nativo
+const NATIVE_ASSET_ID: [u8; 32] = [0; 32];
+
+/// Somma dei valori per `asset_id`, None in caso di overflow
+fn asset_values(values: impl Iterator<Item = ([u8; 32], Amount)>) -> Option<BTreeMap<[u8; 32], Amount>> {
+    let mut totals = BTreeMap::new();
+    for (asset_id, value) in values {
+        let total: &mut Amount = totals.entry(asset_id).or_default();
+        *total = total.checked_add(value)?;
+    }
+    Some(totals)
+}
+
+impl ValidationError {
+    /// Se l'errore rende il block invalido in sé, e non solo non collegabile
+    /// al tip corrente o non verificabile per un errore di storage
+    pub fn is_block_invalid(&self) -> bool {
+        !matches!(
+            self,
+            ValidationError::BadParent
+                | ValidationError::BadHeight { .. }
+                | ValidationError::TimeTooNew { .. }
+                | ValidationError::UnknownAncestors { .. }
+                | ValidationError::Storage(_)
+                | ValidationError::Serialization(_)
+        )
+    }
+
+    /// Se l'errore vale per ogni block con lo stesso hash, e può quindi
+    /// essere registrato come invalidità dell'hash
+    ///
+    /// L'hash impegna solo l'header: un corpo che non corrisponde alla
+    /// merkle root, o che la rispetta duplicando transazioni (CVE-2012-2459),
+    /// rende invalida questa copia del block ma non il block identificato
+    /// dall'header, che un altro peer può ancora inviare intatto. Lo stesso
+    /// vale per la prova AuxPoW, che viaggia fuori dall'header hashato.
+    pub fn invalidates_hash(&self) -> bool {
+        self.is_block_invalid()
+            && !matches!(
+                self,
+                ValidationError::NoTransactions
+                    | ValidationError::BadMerkleRoot
+                    | ValidationError::Oversized { .. }
+                    | ValidationError::DuplicateTransaction { .. }
+                    | ValidationError::InvalidAuxPow(_)
+            )
+    }
+
+    /// Nome stabile della regola violata, usato nel registro dei rifiuti
+    pub fn rule(&self) -> &'static str {
+        match self {
+            ValidationError::NoTransactions => "no-transactions",
+            ValidationError::BadMerkleRoot => "bad-merkle-root",
+            ValidationError::Oversized { .. } => "oversized",
+            ValidationError::BadParent => "bad-parent",
+            ValidationError::BadHeight { .. } => "bad-height",
+            ValidationError::InsufficientWork => "insufficient-work",
+            ValidationError::BadDifficulty { .. } => "bad-diffbits",
+            ValidationError::TimeTooOld { .. } => "time-too-old",
+            ValidationError::TimeTooNew { .. } => "time-too-new",
+            ValidationError::UnknownAncestors { .. } => "unknown-ancestors",
+            ValidationError::AuxPowNotActive { .. } => "auxpow-not-active",
+            ValidationError::InvalidAuxPow(_) => "bad-auxpow",
+            ValidationError::MissingCoinbase => "missing-coinbase",
+            ValidationError::MultipleCoinbase => "multiple-coinbase",
+            ValidationError::UnexpectedCoinbase { .. } => "unexpected-coinbase",
+            ValidationError::InvalidTransaction { .. } => "invalid-transaction",
+            ValidationError::UnsupportedVersion { .. } => "bad-tx-version",
+            ValidationError::ExpiredTransaction { .. } => "tx-expired",
+            ValidationError::DuplicateTransaction { .. } => "duplicate-transaction",
+            ValidationError::DoubleSpend { .. } => "double-spend",
+            ValidationError::MissingInput { .. } => "missing-input",
+            ValidationError::ImmatureCoinbase { .. } => "immature-coinbase",
+            ValidationError::InsufficientInputs { .. } => "insufficient-inputs",
+            ValidationError::UnsupportedAsset { .. } => "unsupported-asset",
+            ValidationError::AssetNotConserved { .. } => "asset-not-conserved",
+            ValidationError::ValueOverflow { .. } => "value-overflow",
+            ValidationError::ExcessiveCoinbase { .. } => "excessive-coinbase",
+            ValidationError::ScriptTooLarge { .. } => "script-too-large",
+            ValidationError::ScriptFailed { .. } => "script-failed",
+            ValidationError::BlockScriptCost { .. } => "block-script-cost",
+            ValidationError::TreasuryUnderpaid { .. } => "treasury-underpaid",
+            ValidationError::Storage(_) => "storage",
+            ValidationError::Serialization(_) => "serialization",
+        }
+    }
+}
+
+/// Errori di validazione
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("Block has no transactions")]
+    NoTransactions,
+
+    #[error("Merkle root mismatch")]
+    BadMerkleRoot,
+
+    #[error("Block too large: {size} bytes")]
+    Oversized { size: usize },
+
+    #[error("Previous hash does not match parent")]
+    BadParent,
+
+    #[error("Bad height: expected {expected}, got {got}")]
+    BadHeight { expected: u64, got: u64 },
+
+    #[error("Proof of work does not meet target")]
+    InsufficientWork,
+
+    #[error("Bad difficulty bits: expected {expected:08x}, got {got:08x}")]
+    BadDifficulty { expected: u32, got: u32 },
+
+    #[error("Block time {timestamp} not after the median time past {median}")]
+    TimeTooOld { timestamp: u64, median: u64 },
+
+    #[error("Block time {timestamp} too far in the future (maximum {max})")]
+    TimeTooNew { timestamp: u64, max: u64 },
+
+    #[error("Ancestors of the block at height {height} are not known")]
+    UnknownAncestors { height: u64 },
+
+    #[error("Merge-mined block at height {height} before AuxPoW activation")]
+    AuxPowNotActive { height: u64 },
+
+    #[error("Invalid AuxPoW: {0}")]
+    InvalidAuxPow(AuxPowError),
+
+    #[error("First transaction is not a coinbase")]
+    MissingCoinbase,
+
+    #[error("Coinbase found after first transaction")]
+    MultipleCoinbase,
+
+    #[error("Coinbase outside of a block: {}", hex::encode(txid))]
+    UnexpectedCoinbase { txid: [u8; 32] },
+
+    #[error("Invalid transaction: {}", hex::encode(txid))]
+    InvalidTransaction { txid: [u8; 32] },
+
+    #[error("Transaction version {version} not allowed at height {height}: {}", hex::encode(txid))]
+    UnsupportedVersion { txid: [u8; 32], version: u32, height: u64 },
+
+    #[error("Transaction expired at height {expiry_height}, cannot enter block {height}: {}", hex::encode(txid))]
+    ExpiredTransaction { txid: [u8; 32], expiry_height: u64, height: u64 },
+
+    #[error("Duplicate transaction: {}", hex::encode(txid))]
+    DuplicateTransaction { txid: [u8; 32] },
+
+    #[error("Input spent twice in block: {outpoint:?}")]
+    DoubleSpend { outpoint: OutPoint },
+
+    #[error("Missing or spent input: {outpoint:?}")]
+    MissingInput { outpoint: OutPoint },
+
+    #[error("Coinbase output spent before maturity: {outpoint:?}")]
+    ImmatureCoinbase { outpoint: OutPoint },
+
+    #[error("Outputs exceed inputs in {}: {output_value} > {input_value}", hex::encode(txid))]
+    InsufficientInputs { txid: [u8; 32], input_value: u64, output_value: u64 },
+
+    #[error("Output of asset {} not allowed in {}", hex::encode(asset_id), hex::encode(txid))]
+    UnsupportedAsset { txid: [u8; 32], asset_id: [u8; 32] },
+
+    #[error("Outputs of asset {} exceed inputs in {}", hex::encode(asset_id), hex::encode(txid))]
+    AssetNotConserved { txid: [u8; 32], asset_id: [u8; 32] },
+
+    #[error("Value overflow in {}", hex::encode(txid))]
+    ValueOverflow { txid: [u8; 32] },
+
+    #[error("Coinbase pays {value}, maximum is {max}")]
+    ExcessiveCoinbase { value: u64, max: u64 },
+
+    #[error("Script of {size} bytes in {}", hex::encode(txid))]
+    ScriptTooLarge { txid: [u8; 32], size: usize },
+
+    #[error("Script verification failed in {}: {error}", hex::encode(txid))]
+    ScriptFailed { txid: [u8; 32], error: InterpreterError },
+
+    #[error("Scripts of the block cost {cost}, maximum is {max}")]
+    BlockScriptCost { cost: u64, max: u64 },
+
+    #[error("Coinbase pays {paid} to the treasury, {required} required")]
+    TreasuryUnderpaid { paid: u64, required: u64 },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::block::bits_to_target;
+    use crate::{anyone_can_spend, PowKind, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    fn create_chain(blocks: u64) -> (BlockchainDB, Vec<Block>, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        let mut chain = vec![genesis];
+        for height in 1..=blocks {
+            let coinbase = Transaction::coinbase(b"miner", height, block_subsidy(height));
+            let mut block = Block::new(chain.last().unwrap().hash(), vec![coinbase], 0x207fffff, height);
+            // Timestamp crescenti, come richiesto dalla median time past
+            block.header.timestamp = chain.last().unwrap().header.timestamp + 600;
+            db.store_block(&block).unwrap();
+            chain.push(block);
+        }
+        (db, chain, temp_dir)
+    }
+
     #[test]
     fn validation_placeholder() {
         // TODO: Implementazione validation completa
         assert_eq!(2 + 2, 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_validate_chain_blocks() {
+        let (db, chain, _temp) = create_chain(2);
+        let validator = BlockValidator::new(ChainParams::regtest());
+
+        let next = Block::new(chain[2].hash(), vec![Transaction::coinbase(b"miner", 3, block_subsidy(3))], 0x1d00ffff, 3);
+        assert!(validator.validate_block(&next, Some(&chain[2].header), &db).is_ok());
+
+        let wrong_height = Block::new(chain[2].hash(), vec![Transaction::coinbase(b"miner", 5, 1)], 0x1d00ffff, 5);
+        assert!(matches!(
+            validator.validate_block(&wrong_height, Some(&chain[2].header), &db),
+            Err(ValidationError::BadHeight { expected: 3, got: 5 })
+        ));
+
+        let greedy = Block::new(chain[2].hash(), vec![Transaction::coinbase(b"miner", 3, block_subsidy(3) + 1)], 0x1d00ffff, 3);
+        assert!(matches!(
+            validator.validate_block(&greedy, Some(&chain[2].header), &db),
+            Err(ValidationError::ExcessiveCoinbase { .. })
+        ));
+    }
+
+    #[test]
+    fn test_header_context() {
+        let validator = BlockValidator::new(ChainParams::regtest()).with_proof_of_work(true);
+        let params = validator.params().clone();
+        let interval = params.difficulty_adjustment_interval;
+        // Block al doppio del tempo previsto: al retarget la difficulty scende
+        let slow = |from: u64, to: u64| Some((from..to).map(|h| 1_000 + h * 2 * params.target_block_time).collect());
+        assert_eq!(validator.expected_bits(interval * 2 + 1, 0x0800ffff, slow).unwrap(), 0x0800ffff);
+        let retarget = validator.expected_bits(interval * 2, 0x0800ffff, slow).unwrap();
+        assert!(bits_to_target(retarget) > bits_to_target(0x0800ffff));
+        assert!(matches!(
+            validator.expected_bits(interval * 2, 0x0800ffff, |_, _| None),
+            Err(ValidationError::UnknownAncestors { .. })
+        ));
+
+        // Median time past sugli ultimi MEDIAN_TIME_SPAN block, in qualunque ordine
+        let shuffled = |from: u64, to: u64| Some((from..to).map(|h| (h * 7) % 13).collect());
+        assert_eq!(median_time_past(20, shuffled).unwrap(), 6);
+        assert!(median_time_past(0, slow).is_err());
+
+        let mut parent = Block::genesis().header;
+        parent.bits = 0x207fffff;
+        let mut block = Block::new(parent.hash(), vec![], 0x207fffff, 31);
+        block.header.timestamp = median_time_past(31, slow).unwrap();
+        let error = validator.check_header_context(&block, &parent, slow).unwrap_err();
+        assert!(matches!(error, ValidationError::TimeTooOld { .. }) && error.invalidates_hash());
+        block.header.timestamp = BlockHeader::current_timestamp() + MAX_FUTURE_BLOCK_TIME + 60;
+        let error = validator.check_header_context(&block, &parent, slow).unwrap_err();
+        assert!(matches!(error, ValidationError::TimeTooNew { .. }) && !error.is_block_invalid());
+        block.header.timestamp = BlockHeader::current_timestamp();
+        assert!(validator.check_header_context(&block, &parent, slow).is_ok());
+        block.header.bits = 0x1f7fffff;
+        assert!(matches!(
+            validator.check_header_context(&block, &parent, slow),
+            Err(ValidationError::BadDifficulty { expected: 0x207fffff, got: 0x1f7fffff })
+        ));
+    }
+
+    #[test]
+    fn test_auxpow_blocks() {
+        let (db, chain, _temp) = create_chain(2);
+        let validator = BlockValidator::new(ChainParams::regtest().with_auxpow(3)).with_proof_of_work(true);
+        let coinbase = Transaction::coinbase(b"miner", 3, block_subsidy(3));
+        // Target facile: metà degli hash lo rispettano
+        let mut block = Block::new(chain[2].hash(), vec![coinbase], 0x207fffff, 3);
+        block.enable_auxpow();
+        let mut forged = block.clone();
+        block.set_auxpow(crate::auxpow::tests::proof(&block.hash(), &block.header.target()));
+        assert!(validator.validate_block(&block, Some(&chain[2].header), &db).is_ok());
+
+        // La prova viaggia con il block e solo con il flag nella versione
+        let decoded = crate::decode_block(&bincode::serialize(&block).unwrap()).unwrap();
+        assert_eq!(decoded.auxpow, block.auxpow);
+        assert!(bincode::serialize(&forged).is_err());
+        let plain = &chain[2];
+        let legacy = bincode::serialize(&(&plain.header, &plain.transactions)).unwrap();
+        assert_eq!(bincode::serialize(plain).unwrap(), legacy);
+        assert!(crate::decode_block(&legacy).unwrap().auxpow.is_none());
+
+        forged.set_auxpow(crate::auxpow::tests::proof(&[7; 32], &block.header.target()));
+        let error = validator.validate_block(&forged, Some(&chain[2].header), &db).unwrap_err();
+        assert!(matches!(error, ValidationError::InvalidAuxPow(AuxPowError::WrongCommitment)));
+        assert_eq!(error.rule(), "bad-auxpow");
+        // La prova non è nell'hash: lo stesso block con una prova valida resta accettabile
+        assert!(error.is_block_invalid() && !error.invalidates_hash());
+
+        let inactive = BlockValidator::new(ChainParams::regtest().with_auxpow(4));
+        assert!(matches!(
+            inactive.validate_block(&block, Some(&chain[2].header), &db),
+            Err(ValidationError::AuxPowNotActive { height: 3 })
+        ));
+
+        // Con un altro algoritmo in vigore la prova SHA-256d del parent non basta
+        let switched = BlockValidator::new(ChainParams::regtest().with_auxpow(3).with_pow_algorithm(3, PowKind::Sha3))
+            .with_proof_of_work(true);
+        assert!(matches!(
+            switched.validate_block(&block, Some(&chain[2].header), &db),
+            Err(ValidationError::AuxPowNotActive { height: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_treasury_enforced_in_coinbase() {
+        let (db, chain, _temp) = create_chain(2);
+        let treasury = crate::TreasuryParams::multisig(2, &[vec![0x02; 33], vec![0x03; 33]], 500).unwrap();
+        let validator = BlockValidator::new(ChainParams::regtest().with_treasury(treasury.clone()));
+
+        let unfunded = Block::new(chain[2].hash(), vec![Transaction::coinbase(b"miner", 3, block_subsidy(3))], 0x1d00ffff, 3);
+        assert!(matches!(
+            validator.validate_block(&unfunded, Some(&chain[2].header), &db),
+            Err(ValidationError::TreasuryUnderpaid { paid: 0, .. })
+        ));
+
+        let mut coinbase = Transaction::coinbase(b"miner", 3, block_subsidy(3));
+        treasury.apply_to_coinbase(&mut coinbase, block_subsidy(3));
+        let funded = Block::new(chain[2].hash(), vec![coinbase], 0x1d00ffff, 3);
+        assert!(validator.validate_block(&funded, Some(&chain[2].header), &db).is_ok());
+    }
+
+    #[test]
+    fn test_tx_version_rules() {
+        let (db, chain, _temp) = create_chain(2);
+        let params = ChainParams::regtest().with_tx_versions(10, 1..=3);
+        let validator = BlockValidator::new(params);
+
+        let mut coinbase = Transaction::coinbase(b"miner", 3, block_subsidy(3));
+        coinbase.version = 2;
+        let block = Block::new(chain[2].hash(), vec![coinbase.clone()], 0x1d00ffff, 3);
+        let error = validator.validate_block(&block, Some(&chain[2].header), &db).unwrap_err();
+        assert!(matches!(error, ValidationError::UnsupportedVersion { version: 2, height: 3, .. }));
+        assert_eq!(error.rule(), "bad-tx-version");
+
+        // Dopo l'attivazione la v2 vale fino all'altezza di scadenza compresa
+        let expiring = coinbase.clone().with_expiry(10);
+        assert!(validator.check_format(&expiring, expiring.hash(), 10).is_ok());
+        let error = validator.check_format(&expiring, expiring.hash(), 11).unwrap_err();
+        assert!(matches!(error, ValidationError::ExpiredTransaction { expiry_height: 10, height: 11, .. }));
+        assert_eq!(error.rule(), "tx-expired");
+        assert!(validator.check_format(&coinbase, coinbase.hash(), 11).is_ok());
+
+        // Versione ammessa dai parametri, ma questo nodo non ne conosce il formato
+        coinbase.version = 3;
+        assert!(matches!(
+            validator.check_format(&coinbase, coinbase.hash(), 10),
+            Err(ValidationError::UnsupportedVersion { version: 3, height: 10, .. })
+        ));
+        coinbase.version = 0;
+        assert!(validator.check_format(&coinbase, coinbase.hash(), 10).is_err());
+        coinbase.version = 1;
+        assert!(validator.check_format(&coinbase, coinbase.hash(), 10).is_ok());
+    }
+
+    #[test]
+    fn test_immature_and_missing_inputs() {
+        let (db, chain, _temp) = create_chain(2);
+        let validator = BlockValidator::new(ChainParams::regtest());
+
+        let coinbase_txid = chain[1].transactions[0].hash();
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase_txid, 0), vec![])],
+            vec![TxOutput::to_address(1000, b"alice")],
+            0,
+        );
+        let block = Block::new(
+            chain[2].hash(),
+            vec![Transaction::coinbase(b"miner", 3, block_subsidy(3)), spend],
+            0x1d00ffff,
+            3,
+        );
+        assert!(matches!(
+            validator.validate_block(&block, Some(&chain[2].header), &db),
+            Err(ValidationError::ImmatureCoinbase { .. })
+        ));
+
+        let missing = Transaction::new(
+            vec![TxInput::new(OutPoint::new([7; 32], 0), vec![])],
+            vec![TxOutput::to_address(1000, b"alice")],
+            0,
+        );
+        let block = Block::new(
+            chain[2].hash(),
+            vec![Transaction::coinbase(b"miner", 3, block_subsidy(3)), missing],
+            0x1d00ffff,
+            3,
+        );
+        assert!(matches!(
+            validator.validate_block(&block, Some(&chain[2].header), &db),
+            Err(ValidationError::MissingInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_genesis_allocations() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let genesis = Block::genesis_with_allocations(vec![TxOutput::to_address(5_000, &anyone_can_spend(b"alice"))]);
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        // L'allocazione entra nel UTXO set ad altezza 0 come output coinbase
+        let allocation = OutPoint::new(genesis.transactions[0].hash(), 0);
+        let entry = db.get_utxo(&allocation).unwrap().unwrap();
+        assert_eq!((entry.output.value.to_sat(), entry.block_height, entry.is_coinbase), (5_000, 0, true));
+
+        let spend = Transaction::new(
+            vec![TxInput::new(allocation, vec![])],
+            vec![TxOutput::to_address(4_000, b"bob")],
+            0,
+        );
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1) + 1_000);
+        let block = Block::new(genesis.hash(), vec![coinbase, spend], 0x1d00ffff, 1);
+        assert!(matches!(
+            BlockValidator::new(ChainParams::regtest()).validate_block(&block, Some(&genesis.header), &db),
+            Err(ValidationError::ImmatureCoinbase { .. })
+        ));
+
+        // Con il flag le allocazioni sono spendibili subito, le altre coinbase no
+        let params = ChainParams::regtest().with_mature_genesis_allocations();
+        assert_eq!((params.coinbase_maturity_at(0), params.coinbase_maturity_at(1)), (0, crate::COINBASE_MATURITY));
+        BlockValidator::new(params).validate_block(&block, Some(&genesis.header), &db).unwrap();
+    }
+
+    #[test]
+    fn test_block_transactions_one_at_a_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let anyone = anyone_can_spend(b"alice");
+        let genesis = Block::genesis_with_allocations(vec![TxOutput::to_address(5_000, &anyone)]);
+        db.initialize_with_genesis(&genesis).unwrap();
+        let validator = BlockValidator::new(ChainParams::regtest().with_mature_genesis_allocations());
+        let allocation = OutPoint::new(genesis.transactions[0].hash(), 0);
+        let spend = |outpoint: &OutPoint, value| Transaction::new(
+            vec![TxInput::new(outpoint.clone(), vec![])],
+            vec![TxOutput::to_address(value, &anyone)],
+            0,
+        );
+        let mut spends = BlockSpends::new();
+
+        // Una transazione che fallisce non lascia traccia
+        let greedy = spend(&allocation, 6_000);
+        assert!(matches!(
+            validator.check_block_transaction(&greedy, greedy.hash(), 1, &db, &spends),
+            Err(ValidationError::InsufficientInputs { input_value: 5_000, output_value: 6_000, .. })
+        ));
+        assert!(!spends.is_spent(&allocation));
+
+        let first = spend(&allocation, 4_000);
+        assert_eq!(validator.check_block_transaction(&first, first.hash(), 1, &db, &spends).unwrap().fee, 1_000);
+        spends.record(&first, first.hash(), 1);
+
+        let second = spend(&allocation, 3_000);
+        assert!(matches!(
+            validator.check_block_transaction(&second, second.hash(), 1, &db, &spends),
+            Err(ValidationError::DoubleSpend { .. })
+        ));
+
+        // Gli output delle transazioni precedenti sono spendibili nello stesso block
+        let child = spend(&OutPoint::new(first.hash(), 0), 3_500);
+        assert_eq!(validator.check_block_transaction(&child, child.hash(), 1, &db, &spends).unwrap().fee, 500);
+
+        // Lo script dell'output speso deve essere soddisfatto
+        let mut unlocked = spend(&OutPoint::new(first.hash(), 0), 3_500);
+        unlocked.inputs[0].script_sig = vec![crate::script::opcodes::OP_0];
+        let error = validator.check_block_transaction(&unlocked, unlocked.hash(), 1, &db, &spends).unwrap_err();
+        assert!(matches!(error, ValidationError::ScriptFailed { .. }));
+        assert_eq!(error.rule(), "script-failed");
+    }
+
+    #[test]
+    fn test_block_script_cost() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let anyone = anyone_can_spend(b"alice");
+        let genesis = Block::genesis_with_allocations(vec![
+            TxOutput::to_address(5_000, &anyone),
+            TxOutput::to_address(5_000, &anyone),
+        ]);
+        db.initialize_with_genesis(&genesis).unwrap();
+        let mut validator = BlockValidator::new(ChainParams::regtest().with_mature_genesis_allocations());
+        let spends: Vec<Transaction> = (0..2)
+            .map(|vout| Transaction::new(
+                vec![TxInput::new(OutPoint::new(genesis.transactions[0].hash(), vout), vec![])],
+                vec![TxOutput::to_address(4_000, &anyone)],
+                0,
+            ))
+            .collect();
+        let cost = validator.validate_transaction(&spends[0], 1, &db, &HashMap::new()).unwrap().script_cost;
+        assert!(cost > 0);
+
+        let coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1) + 2_000);
+        let block = Block::new(genesis.hash(), [vec![coinbase], spends].concat(), 0x1d00ffff, 1);
+        validator.set_max_block_script_cost(2 * cost);
+        validator.validate_block(&block, Some(&genesis.header), &db).unwrap();
+
+        // Ogni transazione sta nel proprio budget, insieme superano quello del block
+        validator.set_max_block_script_cost(2 * cost - 1);
+        let error = validator.validate_block(&block, Some(&genesis.header), &db).unwrap_err();
+        assert!(matches!(error, ValidationError::BlockScriptCost { cost: total, .. } if total == 2 * cost));
+        assert!(error.invalidates_hash());
+    }
+
+    #[test]
+    fn test_asset_conservation() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let anyone = anyone_can_spend(b"alice");
+        let genesis = Block::genesis_with_allocations(vec![
+            TxOutput::to_address(5_000, &anyone),
+            TxOutput::new(500, [7; 32], anyone.clone()),
+        ]);
+        db.initialize_with_genesis(&genesis).unwrap();
+        let validator = BlockValidator::new(ChainParams::regtest().with_mature_genesis_allocations());
+        let native = OutPoint::new(genesis.transactions[0].hash(), 0);
+        let asset = OutPoint::new(genesis.transactions[0].hash(), 1);
+        let spend = |outpoints: &[&OutPoint], outputs| Transaction::new(
+            outpoints.iter().map(|outpoint| TxInput::new((*outpoint).clone(), vec![])).collect(),
+            outputs,
+            0,
+        );
+
+        // Gli input di un asset non pagano output in SLY nativo
+        let swapped = spend(&[&asset], vec![TxOutput::to_address(400, &anyone)]);
+        assert!(matches!(
+            validator.validate_transaction(&swapped, 1, &db, &HashMap::new()),
+            Err(ValidationError::InsufficientInputs { input_value: 0, output_value: 400, .. })
+        ));
+
+        // Gli output di un asset non superano i suoi input
+        let inflated = spend(&[&native, &asset], vec![TxOutput::new(600, [7; 32], anyone.clone())]);
+        let error = validator.check_block_transaction(&inflated, inflated.hash(), 1, &db, &BlockSpends::new())
+            .unwrap_err();
+        assert!(matches!(error, ValidationError::AssetNotConserved { asset_id, .. } if asset_id == [7; 32]));
+
+        // Senza regole di emissione nessun output porta un asset non nativo, nemmeno nella coinbase
+        let transfer = spend(&[&native, &asset], vec![TxOutput::new(500, [7; 32], anyone.clone())]);
+        let error = validator.validate_transaction(&transfer, 1, &db, &HashMap::new()).unwrap_err();
+        assert!(matches!(error, ValidationError::UnsupportedAsset { asset_id, .. } if asset_id == [7; 32]));
+        assert_eq!(error.rule(), "unsupported-asset");
+        let mut coinbase = Transaction::coinbase(b"miner", 1, block_subsidy(1));
+        coinbase.outputs.push(TxOutput::new(1, [7; 32], anyone.clone()));
+        let block = Block::new(genesis.hash(), vec![coinbase], 0x1d00ffff, 1);
+        assert!(matches!(
+            validator.validate_block(&block, Some(&genesis.header), &db),
+            Err(ValidationError::UnsupportedAsset { .. })
+        ));
+
+        let burn = spend(&[&native, &asset], vec![TxOutput::to_address(4_000, &anyone)]);
+        assert_eq!(validator.validate_transaction(&burn, 1, &db, &HashMap::new()).unwrap().fee, 1_000);
+    }
+}
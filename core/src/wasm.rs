@@ -0,0 +1,121 @@
+//! Binding wasm-bindgen per i wallet web
+//!
+//! Costruzione e firma di transazioni dal browser: le chiavi non lasciano
+//! mai la pagina, al nodo arriva solo la transazione serializzata. Tutti
+//! i valori binari (txid, script, chiavi) sono stringhe hex.
+
+use crate::script::hash160;
+use crate::sighash::{sign_input_with_type, SighashType};
+use crate::{OutPoint, ScriptTemplate, Transaction, TxInput, TxOutput};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use wasm_bindgen::prelude::*;
+
+fn decode_hex(value: &str, what: &str) -> Result<Vec<u8>, JsError> {
+    hex::decode(value).map_err(|e| JsError::new(&format!("Invalid {}: {}", what, e)))
+}
+
+fn decode_hash(value: &str, what: &str) -> Result<[u8; 32], JsError> {
+    <[u8; 32]>::try_from(decode_hex(value, what)?)
+        .map_err(|_| JsError::new(&format!("Invalid {}: expected 32 bytes", what)))
+}
+
+fn decode_secret_key(secret_key: &str) -> Result<SecretKey, JsError> {
+    SecretKey::from_slice(&decode_hex(secret_key, "secret key")?)
+        .map_err(|e| JsError::new(&format!("Invalid secret key: {}", e)))
+}
+
+/// Costruttore di transazioni per JavaScript
+#[wasm_bindgen]
+pub struct TransactionBuilder {
+    /// Transazione in costruzione
+    tx: Transaction,
+    /// Script spesi, nell'ordine degli input
+    spent_scripts: Vec<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl TransactionBuilder {
+    /// Transazione vuota con il lock time indicato
+    #[wasm_bindgen(constructor)]
+    pub fn new(lock_time: u64) -> Self {
+        Self { tx: Transaction::new(Vec::new(), Vec::new(), lock_time), spent_scripts: Vec::new() }
+    }
+
+    /// Aggiunge un input che spende `txid:vout`, bloccato da `script_pubkey`
+    #[wasm_bindgen(js_name = addInput)]
+    pub fn add_input(&mut self, txid: &str, vout: u32, script_pubkey: &str) -> Result<(), JsError> {
+        let outpoint = OutPoint::new(decode_hash(txid, "txid")?, vout);
+        self.spent_scripts.push(decode_hex(script_pubkey, "script")?);
+        self.tx.inputs.push(TxInput::new(outpoint, Vec::new()));
+        Ok(())
+    }
+
+    /// Aggiunge un output in SLY nativo
+    #[wasm_bindgen(js_name = addOutput)]
+    pub fn add_output(&mut self, value: u64, script_pubkey: &str) -> Result<(), JsError> {
+        self.add_asset_output(value, &hex::encode([0; 32]), script_pubkey)
+    }
+
+    /// Aggiunge un output di un asset
+    #[wasm_bindgen(js_name = addAssetOutput)]
+    pub fn add_asset_output(&mut self, value: u64, asset_id: &str, script_pubkey: &str) -> Result<(), JsError> {
+        let asset_id = decode_hash(asset_id, "asset id")?;
+        self.tx.outputs.push(TxOutput::new(value, asset_id, decode_hex(script_pubkey, "script")?));
+        Ok(())
+    }
+
+    /// Firma gli input P2PK/P2PKH della chiave; ritorna quanti ne ha firmati
+    ///
+    /// Chiamare dopo aver aggiunto tutti input e output: la firma li impegna.
+    pub fn sign(&mut self, secret_key: &str) -> Result<u32, JsError> {
+        self.sign_with_sighash(secret_key, "ALL")
+    }
+
+    /// Come `sign`, con un sighash type (`ALL`, `NONE`, `SINGLE`, con
+    /// `|ANYONECANPAY` opzionale)
+    #[wasm_bindgen(js_name = signWithSighash)]
+    pub fn sign_with_sighash(&mut self, secret_key: &str, sighash_type: &str) -> Result<u32, JsError> {
+        let sighash_type = sighash_type.parse::<SighashType>().map_err(|e| JsError::new(&e.to_string()))?;
+        let secret_key = decode_secret_key(secret_key)?;
+        let secp = Secp256k1::signing_only();
+        let unsigned = self.tx.clone();
+
+        let mut signed = 0;
+        for (index, script_pubkey) in self.spent_scripts.iter().enumerate() {
+            let script_sig = sign_input_with_type(&secp, &secret_key, &unsigned, index, script_pubkey, sighash_type);
+            if let Some(script_sig) = script_sig {
+                self.tx.inputs[index].script_sig = script_sig;
+                signed += 1;
+            }
+        }
+        Ok(signed)
+    }
+
+    /// Hash della transazione (hex)
+    pub fn txid(&self) -> String {
+        hex::encode(self.tx.hash())
+    }
+
+    /// Transazione serializzata (hex), da inviare al nodo
+    #[wasm_bindgen(js_name = toHex)]
+    pub fn to_hex(&self) -> Result<String, JsError> {
+        bincode::serialize(&self.tx)
+            .map(hex::encode)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// Chiave pubblica compressa (hex) di una chiave privata
+#[wasm_bindgen(js_name = publicKey)]
+pub fn public_key(secret_key: &str) -> Result<String, JsError> {
+    let secret_key = decode_secret_key(secret_key)?;
+    Ok(hex::encode(PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key).serialize()))
+}
+
+/// Script P2PKH (hex) di una chiave pubblica
+#[wasm_bindgen(js_name = p2pkhScript)]
+pub fn p2pkh_script(public_key: &str) -> Result<String, JsError> {
+    let public_key = decode_hex(public_key, "public key")?;
+    PublicKey::from_slice(&public_key).map_err(|e| JsError::new(&format!("Invalid public key: {}", e)))?;
+    Ok(hex::encode(ScriptTemplate::p2pkh(&hash160(&public_key))))
+}
@@ -0,0 +1,17 @@
+//! Sedly Deposits - deposit tracking for exchanges and payment processors
+//!
+//! [`DepositTracker`] follows a [`BlockSource`] (usually the node database
+//! opened as a secondary instance), detects outputs paying a set of watched
+//! scripts, credits them once they reach the configured number of
+//! confirmations and reverts them when a reorg disconnects their block.
+//! Every state change is delivered to a [`DepositHandler`] as a
+//! [`DepositEvent`] with a stable id, so handlers can apply events
+//! idempotently.
+
+pub mod source;
+pub mod tracker;
+
+pub use source::{BlockSource, SourceError};
+pub use tracker::{
+    Deposit, DepositEvent, DepositEventKind, DepositHandler, DepositTracker, SyncReport, TrackerConfig, TrackerError,
+};
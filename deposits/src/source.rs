@@ -0,0 +1,42 @@
+//! Chains the deposit tracker can follow
+
+use sedly_core::{Block, BlockchainDB};
+
+/// Read access to a chain of blocks by height
+pub trait BlockSource {
+    /// Height of the chain tip
+    fn tip_height(&self) -> Result<u64, SourceError>;
+
+    /// Hash of the block at `height`, None above the tip
+    fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, SourceError>;
+
+    /// Block at `height`, None above the tip
+    fn block(&self, height: u64) -> Result<Option<Block>, SourceError>;
+}
+
+impl BlockSource for BlockchainDB {
+    fn tip_height(&self) -> Result<u64, SourceError> {
+        self.get_height().map_err(|e| SourceError(e.to_string()))
+    }
+
+    fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, SourceError> {
+        if height > self.tip_height()? {
+            return Ok(None);
+        }
+        self.get_header_by_height(height)
+            .map(|header| header.map(|header| header.hash()))
+            .map_err(|e| SourceError(e.to_string()))
+    }
+
+    fn block(&self, height: u64) -> Result<Option<Block>, SourceError> {
+        if height > self.tip_height()? {
+            return Ok(None);
+        }
+        self.get_block_by_height(height).map_err(|e| SourceError(e.to_string()))
+    }
+}
+
+/// Failure reading from a block source
+#[derive(Debug, thiserror::Error)]
+#[error("Block source error: {0}")]
+pub struct SourceError(pub String);
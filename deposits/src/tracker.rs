@@ -0,0 +1,565 @@
+//! Deposit detection, confirmation and reorg handling
+
+use crate::source::{BlockSource, SourceError};
+use sedly_core::{Amount, Block, OutPoint};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Default confirmations before a deposit is credited
+pub const DEFAULT_CONFIRMATIONS: u64 = 6;
+/// Default number of recent blocks the tracker can disconnect on a reorg
+pub const DEFAULT_MAX_REORG_DEPTH: u64 = 100;
+
+/// Tracker configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackerConfig {
+    /// Confirmations required to credit a deposit
+    pub confirmations: u64,
+    /// Per-asset overrides of `confirmations`, keyed by hex asset id
+    pub asset_confirmations: BTreeMap<String, u64>,
+    /// Confirmations required for deposits paid by a coinbase, which cannot
+    /// be spent before the network's coinbase maturity
+    #[serde(default = "default_coinbase_maturity")]
+    pub coinbase_maturity: u64,
+    /// Recent blocks remembered to detect reorgs; credited deposits older
+    /// than this are final and forgotten
+    pub max_reorg_depth: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            confirmations: DEFAULT_CONFIRMATIONS,
+            asset_confirmations: BTreeMap::new(),
+            coinbase_maturity: sedly_core::COINBASE_MATURITY,
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+        }
+    }
+}
+
+fn default_coinbase_maturity() -> u64 {
+    sedly_core::COINBASE_MATURITY
+}
+
+impl TrackerConfig {
+    /// Confirmations required for deposits of `asset_id`
+    pub fn confirmations_for(&self, asset_id: &[u8; 32]) -> u64 {
+        self.asset_confirmations.get(&hex::encode(asset_id)).copied().unwrap_or(self.confirmations).max(1)
+    }
+
+    /// Confirmations required to credit `deposit`
+    ///
+    /// A coinbase deposit also waits for `coinbase_maturity`: crediting it
+    /// earlier would hand out funds the account cannot spend yet.
+    pub fn required_confirmations(&self, deposit: &Deposit) -> u64 {
+        let confirmations = self.confirmations_for(&deposit.asset_id);
+        if deposit.coinbase {
+            confirmations.max(self.coinbase_maturity)
+        } else {
+            confirmations
+        }
+    }
+}
+
+/// Output paying a watched script
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    /// Output holding the deposit
+    pub outpoint: OutPoint,
+    /// Label of the watched script (e.g. the customer account)
+    pub label: String,
+    /// Script paid (hex)
+    pub script_pubkey: String,
+    /// Amount
+    pub value: Amount,
+    /// Asset id
+    pub asset_id: [u8; 32],
+    /// Height of the block containing the deposit
+    pub height: u64,
+    /// Hash of that block
+    pub block_hash: [u8; 32],
+    /// Whether the deposit is an output of a coinbase transaction
+    #[serde(default)]
+    pub coinbase: bool,
+    /// Whether the deposit reached its confirmation threshold
+    pub credited: bool,
+}
+
+impl Deposit {
+    /// Stable identifier of the deposit: `txid:vout`
+    pub fn id(&self) -> String {
+        format!("{}:{}", hex::encode(self.outpoint.txid), self.outpoint.vout)
+    }
+
+    /// Confirmations with the chain tip at `tip_height`
+    pub fn confirmations(&self, tip_height: u64) -> u64 {
+        (tip_height + 1).saturating_sub(self.height)
+    }
+}
+
+/// Kind of change reported to the handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositEventKind {
+    /// Deposit included in a block, not credited yet
+    Detected,
+    /// Deposit reached its confirmation threshold: credit the account
+    Credited,
+    /// Block of the deposit disconnected: undo the credit if one was made
+    Reverted,
+}
+
+/// Change of a deposit delivered to the handler
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositEvent {
+    /// What happened
+    pub kind: DepositEventKind,
+    /// Deposit state after the change
+    pub deposit: Deposit,
+    /// Confirmations at the time of the event (0 when reverted)
+    pub confirmations: u64,
+}
+
+impl DepositEvent {
+    /// Stable identifier of the event
+    ///
+    /// The same change of the same deposit in the same block always has the
+    /// same id, so a handler that records processed ids can safely receive
+    /// an event again after a failed or interrupted sync.
+    pub fn id(&self) -> String {
+        let kind = match self.kind {
+            DepositEventKind::Detected => "detected",
+            DepositEventKind::Credited => "credited",
+            DepositEventKind::Reverted => "reverted",
+        };
+        format!("{}:{}:{}", self.deposit.id(), hex::encode(self.deposit.block_hash), kind)
+    }
+}
+
+/// Receiver of deposit events
+///
+/// An event is considered delivered only when `handle` returns Ok; on
+/// error the sync stops and the same event is delivered again by the next
+/// sync.
+pub trait DepositHandler {
+    /// Error returned by the handler
+    type Error: std::fmt::Display;
+
+    /// Apply an event
+    fn handle(&mut self, event: &DepositEvent) -> Result<(), Self::Error>;
+}
+
+/// Outcome of a sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Blocks connected
+    pub connected: u64,
+    /// Blocks disconnected by reorgs
+    pub disconnected: u64,
+    /// Events delivered to the handler
+    pub events: u64,
+}
+
+/// Tracks deposits to a set of watched scripts
+///
+/// The state is serializable: save it after each sync and load it on
+/// restart to resume from the last processed block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositTracker {
+    config: TrackerConfig,
+    /// Watched scripts (hex) with their label
+    watched: BTreeMap<String, String>,
+    /// Hashes of the recent processed blocks by height
+    recent: BTreeMap<u64, [u8; 32]>,
+    /// Deposits that can still change state, by id
+    deposits: BTreeMap<String, Deposit>,
+    /// Next height to process
+    next_height: u64,
+}
+
+impl DepositTracker {
+    /// Create a tracker starting at `start_height`
+    ///
+    /// Deposits in earlier blocks are ignored: use the height at which the
+    /// watched scripts were first handed out.
+    pub fn new(config: TrackerConfig, start_height: u64) -> Self {
+        Self {
+            config,
+            watched: BTreeMap::new(),
+            recent: BTreeMap::new(),
+            deposits: BTreeMap::new(),
+            next_height: start_height,
+        }
+    }
+
+    /// Load a tracker saved with [`DepositTracker::save`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TrackerError> {
+        let data = std::fs::read_to_string(path).map_err(|e| TrackerError::Io(e.to_string()))?;
+        serde_json::from_str(&data).map_err(|e| TrackerError::Serialization(e.to_string()))
+    }
+
+    /// Save the tracker state
+    ///
+    /// The state goes to a temporary file, synced to disk and renamed over
+    /// `path`, so a crash while saving leaves the previous state intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TrackerError> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| TrackerError::Serialization(e.to_string()))?;
+        let io = |e: std::io::Error| TrackerError::Io(e.to_string());
+        let tmp_path = path.as_ref().with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path).map_err(io)?;
+        file.write_all(data.as_bytes()).map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp_path, path).map_err(io)
+    }
+
+    /// Watch a script; deposits to it carry `label`
+    pub fn watch(&mut self, script_pubkey: &[u8], label: impl Into<String>) {
+        self.watched.insert(hex::encode(script_pubkey), label.into());
+    }
+
+    /// Stop watching a script (deposits already detected are still tracked)
+    pub fn unwatch(&mut self, script_pubkey: &[u8]) -> bool {
+        self.watched.remove(&hex::encode(script_pubkey)).is_some()
+    }
+
+    /// Height of the last processed block
+    pub fn tip_height(&self) -> Option<u64> {
+        self.next_height.checked_sub(1)
+    }
+
+    /// Deposits detected and not final yet, credited or not
+    pub fn pending(&self) -> impl Iterator<Item = &Deposit> {
+        self.deposits.values()
+    }
+
+    /// Follow `source` up to its tip, delivering events to `handler`
+    ///
+    /// Blocks no longer in the source chain are disconnected first,
+    /// reverting their deposits.
+    pub fn sync<S, H>(&mut self, source: &S, handler: &mut H) -> Result<SyncReport, TrackerError>
+    where
+        S: BlockSource + ?Sized,
+        H: DepositHandler + ?Sized,
+    {
+        let mut report = SyncReport::default();
+
+        // Disconnect back to the last block still in the source chain
+        while let Some((&height, &hash)) = self.recent.last_key_value() {
+            if source.block_hash(height)? == Some(hash) {
+                break;
+            }
+            self.disconnect(height, handler, &mut report)?;
+        }
+        if self.recent.is_empty() && report.disconnected > 0 {
+            return Err(TrackerError::ReorgTooDeep { height: self.next_height });
+        }
+
+        let tip = source.tip_height()?;
+        while self.next_height <= tip {
+            let Some(block) = source.block(self.next_height)? else {
+                break;
+            };
+            if let Some(&previous) = self.recent.get(&self.next_height.wrapping_sub(1)) {
+                if block.header.previous_hash != previous {
+                    // The source moved during the sync: the next call reorgs
+                    break;
+                }
+            }
+            self.connect(&block, handler, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    fn connect<H: DepositHandler + ?Sized>(
+        &mut self,
+        block: &Block,
+        handler: &mut H,
+        report: &mut SyncReport,
+    ) -> Result<(), TrackerError> {
+        let height = block.header.height;
+        let block_hash = block.hash();
+
+        // Changes are kept only if every event is delivered: a failed block
+        // is processed again, from the start, by the next sync
+        let mut deposits = self.deposits.clone();
+        for tx in &block.transactions {
+            let txid = tx.hash();
+            for (vout, output) in tx.outputs.iter().enumerate() {
+                let script_pubkey = hex::encode(&output.script_pubkey);
+                let Some(label) = self.watched.get(&script_pubkey) else {
+                    continue;
+                };
+                let deposit = Deposit {
+                    outpoint: OutPoint::new(txid, vout as u32),
+                    label: label.clone(),
+                    script_pubkey,
+                    value: output.value,
+                    asset_id: output.asset_id,
+                    height,
+                    block_hash,
+                    coinbase: tx.is_coinbase(),
+                    credited: false,
+                };
+                if deposits.contains_key(&deposit.id()) {
+                    continue;
+                }
+                deliver(handler, report, DepositEventKind::Detected, &deposit, 1)?;
+                deposits.insert(deposit.id(), deposit);
+            }
+        }
+
+        for deposit in deposits.values_mut().filter(|deposit| !deposit.credited) {
+            let confirmations = deposit.confirmations(height);
+            if confirmations >= self.config.required_confirmations(deposit) {
+                let mut credited = deposit.clone();
+                credited.credited = true;
+                deliver(handler, report, DepositEventKind::Credited, &credited, confirmations)?;
+                deposit.credited = true;
+            }
+        }
+
+        // Blocks deeper than the reorg window are final
+        let final_below = (height + 1).saturating_sub(self.config.max_reorg_depth);
+        deposits.retain(|_, deposit| !deposit.credited || deposit.height >= final_below);
+        self.deposits = deposits;
+        self.recent.insert(height, block_hash);
+        self.recent = self.recent.split_off(&final_below);
+        self.next_height = height + 1;
+        report.connected += 1;
+        Ok(())
+    }
+
+    fn disconnect<H: DepositHandler + ?Sized>(
+        &mut self,
+        height: u64,
+        handler: &mut H,
+        report: &mut SyncReport,
+    ) -> Result<(), TrackerError> {
+        let reverted: Vec<String> = self.deposits.values()
+            .filter(|deposit| deposit.height == height)
+            .map(Deposit::id)
+            .collect();
+        for id in reverted {
+            let deposit = &self.deposits[&id];
+            deliver(handler, report, DepositEventKind::Reverted, deposit, 0)?;
+            self.deposits.remove(&id);
+        }
+        if !self.deposits.is_empty() {
+            log::debug!("Disconnected block {} with {} deposits still pending", height, self.deposits.len());
+        }
+
+        self.recent.remove(&height);
+        self.next_height = height;
+        report.disconnected += 1;
+        Ok(())
+    }
+}
+
+/// Deliver an event, mapping a handler failure to a tracker error
+fn deliver<H: DepositHandler + ?Sized>(
+    handler: &mut H,
+    report: &mut SyncReport,
+    kind: DepositEventKind,
+    deposit: &Deposit,
+    confirmations: u64,
+) -> Result<(), TrackerError> {
+    let event = DepositEvent { kind, deposit: deposit.clone(), confirmations };
+    handler.handle(&event).map_err(|e| TrackerError::Handler { event: event.id(), reason: e.to_string() })?;
+    report.events += 1;
+    Ok(())
+}
+
+/// Deposit tracker errors
+#[derive(Debug, thiserror::Error)]
+pub enum TrackerError {
+    #[error(transparent)]
+    Source(#[from] SourceError),
+
+    #[error("Handler failed on event {event}: {reason}")]
+    Handler { event: String, reason: String },
+
+    #[error("Reorg deeper than the tracked window below height {height}")]
+    ReorgTooDeep { height: u64 },
+
+    #[error("I/O error: {0}")]
+    Io(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Transaction, TxInput, TxOutput};
+    use std::collections::{HashMap, HashSet};
+    use tempfile::TempDir;
+
+    /// In-memory chain
+    struct TestChain(Vec<Block>);
+
+    impl TestChain {
+        fn extend(&mut self, payments: &[(&[u8], u64)]) {
+            let height = self.0.len() as u64;
+            let mut transactions = vec![Transaction::coinbase(b"miner", height, 50)];
+            if !payments.is_empty() {
+                let input = TxInput::new(OutPoint::new([height as u8 + 1; 32], 0), Vec::new());
+                let outputs = payments.iter().map(|(script, value)| TxOutput::to_address(*value, script)).collect();
+                transactions.push(Transaction::new(vec![input], outputs, 0));
+            }
+            self.push(transactions);
+        }
+
+        fn push(&mut self, transactions: Vec<Transaction>) {
+            let height = self.0.len() as u64;
+            let previous = self.0.last().map(Block::hash).unwrap_or([0; 32]);
+            self.0.push(Block::new(previous, transactions, 0x1d00ffff, height));
+        }
+    }
+
+    impl BlockSource for TestChain {
+        fn tip_height(&self) -> Result<u64, SourceError> {
+            Ok(self.0.len() as u64 - 1)
+        }
+
+        fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, SourceError> {
+            Ok(self.0.get(height as usize).map(Block::hash))
+        }
+
+        fn block(&self, height: u64) -> Result<Option<Block>, SourceError> {
+            Ok(self.0.get(height as usize).cloned())
+        }
+    }
+
+    /// Ledger applying events idempotently, optionally failing once
+    #[derive(Default)]
+    struct Ledger {
+        processed: HashSet<String>,
+        balances: HashMap<String, u64>,
+        fail_next: bool,
+    }
+
+    impl DepositHandler for Ledger {
+        type Error = String;
+
+        fn handle(&mut self, event: &DepositEvent) -> Result<(), String> {
+            if std::mem::take(&mut self.fail_next) {
+                return Err("database unavailable".to_string());
+            }
+            if !self.processed.insert(event.id()) {
+                return Ok(());
+            }
+            let balance = self.balances.entry(event.deposit.label.clone()).or_default();
+            match event.kind {
+                DepositEventKind::Detected => {}
+                DepositEventKind::Credited => *balance += event.deposit.value.to_sat(),
+                DepositEventKind::Reverted if event.deposit.credited => *balance -= event.deposit.value.to_sat(),
+                DepositEventKind::Reverted => {}
+            }
+            Ok(())
+        }
+    }
+
+    fn config(confirmations: u64) -> TrackerConfig {
+        TrackerConfig { confirmations, ..TrackerConfig::default() }
+    }
+
+    #[test]
+    fn test_credit_after_confirmations() {
+        let mut chain = TestChain(Vec::new());
+        chain.extend(&[]);
+        chain.extend(&[(b"alice", 1_000)]);
+        let mut tracker = DepositTracker::new(config(3), 0);
+        tracker.watch(b"alice", "account-1");
+        let mut ledger = Ledger::default();
+
+        let report = tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(report, SyncReport { connected: 2, disconnected: 0, events: 1 });
+        assert_eq!(ledger.balances["account-1"], 0);
+
+        chain.extend(&[]);
+        chain.extend(&[]);
+        tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(ledger.balances["account-1"], 1_000);
+
+        // The saved state resumes from the same point
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("tracker.json");
+        tracker.save(&path).unwrap();
+        let mut restored = DepositTracker::load(&path).unwrap();
+        assert_eq!(restored.tip_height(), Some(3));
+        assert_eq!(restored.sync(&chain, &mut ledger).unwrap(), SyncReport::default());
+    }
+
+    #[test]
+    fn test_reorg_reverts_credit() {
+        let mut chain = TestChain(Vec::new());
+        chain.extend(&[]);
+        chain.extend(&[(b"alice", 1_000)]);
+        chain.extend(&[]);
+        let mut tracker = DepositTracker::new(config(2), 0);
+        tracker.watch(b"alice", "account-1");
+        let mut ledger = Ledger::default();
+        tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(ledger.balances["account-1"], 1_000);
+
+        // Longer competing branch without the deposit
+        chain.0.truncate(1);
+        chain.extend(&[(b"bob", 5)]);
+        chain.extend(&[]);
+        chain.extend(&[]);
+        let report = tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(report.disconnected, 2);
+        assert_eq!(report.connected, 3);
+        assert_eq!(ledger.balances["account-1"], 0);
+        assert_eq!(tracker.pending().count(), 0);
+    }
+
+    #[test]
+    fn test_failed_handler_is_retried() {
+        let mut chain = TestChain(Vec::new());
+        chain.extend(&[(b"alice", 7)]);
+        let mut tracker = DepositTracker::new(config(1), 0);
+        tracker.watch(b"alice", "account-1");
+        let mut ledger = Ledger { fail_next: true, ..Ledger::default() };
+
+        assert!(matches!(tracker.sync(&chain, &mut ledger), Err(TrackerError::Handler { .. })));
+        assert_eq!(tracker.tip_height(), None);
+
+        tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(ledger.balances["account-1"], 7);
+        assert_eq!(ledger.processed.len(), 2);
+    }
+
+    #[test]
+    fn test_per_asset_confirmations() {
+        let mut config = config(6);
+        config.asset_confirmations.insert(hex::encode([7; 32]), 2);
+        assert_eq!(config.confirmations_for(&[7; 32]), 2);
+        assert_eq!(config.confirmations_for(&[0; 32]), 6);
+    }
+
+    #[test]
+    fn test_coinbase_waits_for_maturity() {
+        let mut chain = TestChain(Vec::new());
+        chain.push(vec![Transaction::coinbase(b"pool", 0, 50)]);
+        let mut tracker = DepositTracker::new(config(6), 0);
+        tracker.watch(b"pool", "account-1");
+        let mut ledger = Ledger::default();
+
+        // Six confirmations are enough for a regular payment, not for a coinbase
+        for _ in 1..99 {
+            chain.extend(&[]);
+        }
+        tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(tracker.pending().count(), 1);
+        assert!(tracker.pending().all(|deposit| deposit.coinbase && !deposit.credited));
+        assert_eq!(ledger.balances["account-1"], 0);
+
+        chain.extend(&[]);
+        tracker.sync(&chain, &mut ledger).unwrap();
+        assert_eq!(ledger.balances["account-1"], 50);
+    }
+}
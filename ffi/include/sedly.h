@@ -0,0 +1,67 @@
+#ifndef SEDLY_FFI_H
+#define SEDLY_FFI_H
+
+/* Generated by cbindgen from sedly-ffi; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Generate a BIP39 mnemonic of `word_count` words
+ */
+char *sedly_generate_mnemonic(uint32_t word_count);
+
+/**
+ * Derive an address; returns a `DerivedAddress` as JSON
+ *
+ * # Safety
+ *
+ * `seed_json` must be NULL or a NUL-terminated `WalletSeed` JSON string.
+ */
+char *sedly_derive_address(const char *seed_json, uint32_t account, bool change, uint32_t index);
+
+/**
+ * Build and sign a payment; returns a `SignedTransaction` as JSON
+ *
+ * # Safety
+ *
+ * `request_json` must be NULL or a NUL-terminated `TransactionRequest`
+ * JSON string.
+ */
+char *sedly_build_transaction(const char *request_json);
+
+/**
+ * Decode a serialized transaction (hex); returns a `ParsedTransaction` as JSON
+ *
+ * # Safety
+ *
+ * `tx_hex` must be NULL or a NUL-terminated string.
+ */
+char *sedly_parse_transaction(const char *tx_hex);
+
+/**
+ * Message of the last error on the calling thread, NULL if the last call succeeded
+ */
+char *sedly_last_error(void);
+
+/**
+ * Release a string returned by this library
+ *
+ * # Safety
+ *
+ * `value` must be NULL or a string returned by a `sedly_*` function, not
+ * released before.
+ */
+void sedly_string_free(char *value);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SEDLY_FFI_H */
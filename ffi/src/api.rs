@@ -0,0 +1,365 @@
+//! Wallet operations exported to foreign languages
+//!
+//! The functions are exported as-is through uniffi (Kotlin, Swift) and
+//! wrapped by the C ABI in [`crate::c`], which exchanges the records as
+//! JSON. Keys are derived on the BIP44 path used by the wallet
+//! (`m/44'/coin'/account'/chain/index`) and binary values are hex strings.
+
+use bip39::{Language, Mnemonic, MnemonicType};
+use secp256k1::{Secp256k1, SecretKey};
+use sedly_core::sighash::sign_input;
+use sedly_core::script::hash160;
+use sedly_core::{decode_transaction, ChainParams, Network, OutPoint, ScriptTemplate, TxOutput};
+use sedly_wallet::{
+    mnemonic_to_seed, Account, AddressChain, ChildNumber, CoinControl, ExtendedPrivKey, TransactionBuilder, WalletUtxo,
+};
+use serde::{Deserialize, Serialize};
+
+/// Network the keys and addresses belong to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum FfiNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl From<FfiNetwork> for Network {
+    fn from(network: FfiNetwork) -> Self {
+        match network {
+            FfiNetwork::Mainnet => Network::Mainnet,
+            FfiNetwork::Testnet => Network::Testnet,
+            FfiNetwork::Regtest => Network::Regtest,
+        }
+    }
+}
+
+/// Wallet seed: BIP39 mnemonic with optional passphrase
+#[derive(Debug, Clone, Serialize, Deserialize, uniffi::Record)]
+pub struct WalletSeed {
+    pub mnemonic: String,
+    #[serde(default)]
+    pub passphrase: String,
+    pub network: FfiNetwork,
+}
+
+/// Address derived from a wallet seed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct DerivedAddress {
+    /// Derivation path from the master key
+    pub path: String,
+    /// Compressed public key (hex)
+    pub public_key: String,
+    /// P2PKH locking script (hex)
+    pub script_pubkey: String,
+}
+
+/// Spendable output owned by the wallet
+#[derive(Debug, Clone, Serialize, Deserialize, uniffi::Record)]
+pub struct Utxo {
+    /// Transaction hash (hex)
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    /// Asset id (hex), empty for native SLY
+    #[serde(default)]
+    pub asset_id: String,
+    /// Locking script (hex)
+    pub script_pubkey: String,
+    /// Height of the containing block
+    pub height: u64,
+    #[serde(default)]
+    pub coinbase: bool,
+    /// Account, chain and index of the key owning the output
+    pub account: u32,
+    #[serde(default)]
+    pub change: bool,
+    pub index: u32,
+}
+
+/// Transaction output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct Output {
+    /// Locking script (hex)
+    pub script_pubkey: String,
+    pub value: u64,
+    /// Asset id (hex), empty for native SLY
+    #[serde(default)]
+    pub asset_id: String,
+}
+
+/// Payment to build and sign
+#[derive(Debug, Clone, Serialize, Deserialize, uniffi::Record)]
+pub struct TransactionRequest {
+    pub seed: WalletSeed,
+    /// Outputs available for coin selection
+    pub utxos: Vec<Utxo>,
+    /// Outputs to pay
+    pub outputs: Vec<Output>,
+    /// Account receiving the change
+    pub change_account: u32,
+    /// Index of the change address on the internal chain
+    pub change_index: u32,
+    /// Fee rate in satoshi per byte
+    pub fee_rate: u64,
+    /// Current chain height, to skip immature coinbase outputs
+    pub tip_height: u64,
+}
+
+/// Signed transaction ready for broadcast
+#[derive(Debug, Clone, Serialize, Deserialize, uniffi::Record)]
+pub struct SignedTransaction {
+    /// Transaction hash (hex)
+    pub txid: String,
+    /// Serialized transaction (hex)
+    pub hex: String,
+    /// Fee paid in native SLY
+    pub fee: u64,
+}
+
+/// Input of a parsed transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ParsedInput {
+    /// Hash of the spent transaction (hex)
+    pub txid: String,
+    pub vout: u32,
+    /// Unlocking script (hex)
+    pub script_sig: String,
+    pub sequence: u32,
+}
+
+/// Decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ParsedTransaction {
+    pub txid: String,
+    pub version: u32,
+    pub lock_time: u64,
+    /// Serialized size in bytes
+    pub size: u64,
+    pub inputs: Vec<ParsedInput>,
+    pub outputs: Vec<Output>,
+}
+
+/// Generate a new English BIP39 mnemonic of 12, 15, 18, 21 or 24 words
+#[uniffi::export]
+pub fn generate_mnemonic(word_count: u32) -> Result<String, FfiError> {
+    let mnemonic_type = MnemonicType::for_word_count(word_count as usize)
+        .map_err(|_| FfiError::InvalidArgument(format!("Unsupported word count: {}", word_count)))?;
+    Ok(Mnemonic::new(mnemonic_type, Language::English).into_phrase())
+}
+
+/// Derive the P2PKH address `index` of a chain of an account
+#[uniffi::export]
+pub fn derive_address(seed: WalletSeed, account: u32, change: bool, index: u32) -> Result<DerivedAddress, FfiError> {
+    let master = master_key(&seed)?;
+    let chain = if change { AddressChain::Internal } else { AddressChain::External };
+    let path = Account::path(master.network, account)
+        .child(ChildNumber::Normal(chain.index()))
+        .child(ChildNumber::Normal(index));
+    let public_key = master.derive_path(&path)?.public_key().serialize();
+
+    Ok(DerivedAddress {
+        path: path.to_string(),
+        public_key: hex::encode(public_key),
+        script_pubkey: hex::encode(ScriptTemplate::p2pkh(&hash160(&public_key))),
+    })
+}
+
+/// Select inputs for a payment, add change and sign every input
+#[uniffi::export]
+pub fn build_transaction(request: TransactionRequest) -> Result<SignedTransaction, FfiError> {
+    let master = master_key(&request.seed)?;
+    let change = derive_address(request.seed.clone(), request.change_account, true, request.change_index)?;
+
+    let mut builder = TransactionBuilder::new(decode_hex(&change.script_pubkey, "change script")?)
+        .fee_rate(request.fee_rate)
+        .tip_height(request.tip_height)
+        .coinbase_maturity(ChainParams::for_network(request.seed.network.into()).coinbase_maturity);
+    for output in &request.outputs {
+        builder = builder.add_output(decode_output(output)?);
+    }
+    let available = request.utxos.iter().map(decode_utxo).collect::<Result<Vec<_>, _>>()?;
+    let built = builder.build(&available, &CoinControl::new())
+        .map_err(|e| FfiError::Build(e.to_string()))?;
+
+    // Sign with the key of each selected input, in input order
+    let secp = Secp256k1::signing_only();
+    let mut tx = built.tx.clone();
+    for (input_index, spent) in built.inputs.iter().enumerate() {
+        let position = available.iter().position(|utxo| utxo.outpoint == spent.outpoint)
+            .expect("Selected input comes from the request");
+        let utxo = &request.utxos[position];
+        let secret_key = utxo_key(&master, utxo)?;
+        tx.inputs[input_index].script_sig = sign_input(&secp, &secret_key, &built.tx, input_index, &spent.output.script_pubkey)
+            .ok_or_else(|| FfiError::Signing(format!("Key {}/{} does not own input {}", utxo.account, utxo.index, input_index)))?;
+    }
+
+    Ok(SignedTransaction {
+        txid: hex::encode(tx.hash()),
+        hex: hex::encode(bincode::serialize(&tx).map_err(|e| FfiError::Encoding(e.to_string()))?),
+        fee: built.fee.to_sat(),
+    })
+}
+
+/// Decode a serialized transaction
+#[uniffi::export]
+pub fn parse_transaction(hex: String) -> Result<ParsedTransaction, FfiError> {
+    let bytes = decode_hex(&hex, "transaction")?;
+    let tx = decode_transaction(&bytes).map_err(|e| FfiError::Encoding(e.to_string()))?;
+
+    Ok(ParsedTransaction {
+        txid: hex::encode(tx.hash()),
+        version: tx.version,
+        lock_time: tx.lock_time,
+        size: bytes.len() as u64,
+        inputs: tx.inputs.iter()
+            .map(|input| ParsedInput {
+                txid: hex::encode(input.previous_output.txid),
+                vout: input.previous_output.vout,
+                script_sig: hex::encode(&input.script_sig),
+                sequence: input.sequence,
+            })
+            .collect(),
+        outputs: tx.outputs.iter()
+            .map(|output| Output {
+                script_pubkey: hex::encode(&output.script_pubkey),
+                value: output.value.to_sat(),
+                asset_id: if output.is_native_asset() { String::new() } else { hex::encode(output.asset_id) },
+            })
+            .collect(),
+    })
+}
+
+fn master_key(seed: &WalletSeed) -> Result<ExtendedPrivKey, FfiError> {
+    let bytes = mnemonic_to_seed(&seed.mnemonic, &seed.passphrase)
+        .map_err(|e| FfiError::InvalidArgument(e.to_string()))?;
+    Ok(ExtendedPrivKey::new_master(seed.network.into(), &bytes)?)
+}
+
+fn utxo_key(master: &ExtendedPrivKey, utxo: &Utxo) -> Result<SecretKey, FfiError> {
+    let chain = if utxo.change { AddressChain::Internal } else { AddressChain::External };
+    let path = Account::path(master.network, utxo.account)
+        .child(ChildNumber::Normal(chain.index()))
+        .child(ChildNumber::Normal(utxo.index));
+    Ok(master.derive_path(&path)?.secret_key)
+}
+
+fn decode_hex(value: &str, what: &str) -> Result<Vec<u8>, FfiError> {
+    hex::decode(value).map_err(|e| FfiError::InvalidArgument(format!("Invalid {}: {}", what, e)))
+}
+
+fn decode_hash(value: &str, what: &str) -> Result<[u8; 32], FfiError> {
+    if value.is_empty() {
+        return Ok([0; 32]);
+    }
+    <[u8; 32]>::try_from(decode_hex(value, what)?)
+        .map_err(|_| FfiError::InvalidArgument(format!("Invalid {}: expected 32 bytes", what)))
+}
+
+fn decode_output(output: &Output) -> Result<TxOutput, FfiError> {
+    Ok(TxOutput::new(
+        output.value,
+        decode_hash(&output.asset_id, "asset id")?,
+        decode_hex(&output.script_pubkey, "script")?,
+    ))
+}
+
+fn decode_utxo(utxo: &Utxo) -> Result<WalletUtxo, FfiError> {
+    Ok(WalletUtxo {
+        outpoint: OutPoint::new(decode_hash(&utxo.txid, "txid")?, utxo.vout),
+        output: decode_output(&Output {
+            script_pubkey: utxo.script_pubkey.clone(),
+            value: utxo.value,
+            asset_id: utxo.asset_id.clone(),
+        })?,
+        height: utxo.height,
+        is_coinbase: utxo.coinbase,
+    })
+}
+
+/// Errors returned to foreign callers
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum FfiError {
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Key derivation failed: {0}")]
+    Key(String),
+
+    #[error("Transaction build failed: {0}")]
+    Build(String),
+
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<sedly_wallet::KeyError> for FfiError {
+    fn from(error: sedly_wallet::KeyError) -> Self {
+        FfiError::Key(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn seed() -> WalletSeed {
+        WalletSeed { mnemonic: MNEMONIC.to_string(), passphrase: String::new(), network: FfiNetwork::Regtest }
+    }
+
+    #[test]
+    fn test_build_and_parse_transaction() {
+        assert_eq!(generate_mnemonic(24).unwrap().split(' ').count(), 24);
+        assert!(matches!(generate_mnemonic(13), Err(FfiError::InvalidArgument(_))));
+
+        let address = derive_address(seed(), 0, false, 3).unwrap();
+        assert_eq!(address.path, "m/44'/1'/0'/0/3");
+        assert_eq!(derive_address(seed(), 0, false, 3).unwrap(), address);
+
+        let utxo = Utxo {
+            txid: hex::encode([1; 32]),
+            vout: 0,
+            value: 100_000,
+            asset_id: String::new(),
+            script_pubkey: address.script_pubkey.clone(),
+            height: 5,
+            coinbase: false,
+            account: 0,
+            change: false,
+            index: 3,
+        };
+        let payment = Output { script_pubkey: hex::encode(b"alice"), value: 40_000, asset_id: String::new() };
+        let request = TransactionRequest {
+            seed: seed(),
+            utxos: vec![utxo.clone()],
+            outputs: vec![payment.clone()],
+            change_account: 0,
+            change_index: 0,
+            fee_rate: 1,
+            tip_height: 10,
+        };
+        let signed = build_transaction(request.clone()).unwrap();
+
+        let parsed = parse_transaction(signed.hex).unwrap();
+        assert_eq!(parsed.txid, signed.txid);
+        assert_eq!(parsed.inputs[0].txid, utxo.txid);
+        assert!(!parsed.inputs[0].script_sig.is_empty());
+        // The change is at a random position
+        let change = derive_address(seed(), 0, true, 0).unwrap();
+        let position = parsed.outputs.iter().position(|output| output.script_pubkey == change.script_pubkey).unwrap();
+        assert_eq!(parsed.outputs[1 - position], payment);
+        assert_eq!(parsed.outputs[position].value, 100_000 - 40_000 - signed.fee);
+
+        // A wrong index derives a key that does not own the output
+        let wrong = TransactionRequest { utxos: vec![Utxo { index: 4, ..utxo }], ..request };
+        assert!(matches!(build_transaction(wrong), Err(FfiError::Signing(_))));
+    }
+}
@@ -0,0 +1,177 @@
+//! C ABI over [`crate::api`]
+//!
+//! Strings are NUL-terminated UTF-8. Records travel as JSON with the field
+//! names of the Rust types. Every returned string is owned by the caller
+//! and must be released with [`sedly_string_free`]. On failure a function
+//! returns NULL and [`sedly_last_error`] describes the error of the calling
+//! thread. A panic inside the library never crosses the ABI: it is
+//! reported as an internal error.
+
+use crate::api::{self, FfiError, TransactionRequest, WalletSeed};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+thread_local! {
+    /// Error of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Run `call` and return its result to C, recording the error on failure
+fn finish(call: impl FnOnce() -> Result<String, FfiError>) -> *mut c_char {
+    let result = panic::catch_unwind(AssertUnwindSafe(call))
+        .unwrap_or_else(|payload| Err(FfiError::Internal(panic_message(payload.as_ref()))))
+        .and_then(|value| CString::new(value).map_err(|e| FfiError::Encoding(e.to_string())));
+    match result {
+        Ok(value) => {
+            LAST_ERROR.with(|last| last.borrow_mut().take());
+            value.into_raw()
+        }
+        Err(error) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(error.to_string()));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Message of a caught panic
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic".to_string())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, FfiError> {
+    serde_json::to_string(value).map_err(|e| FfiError::Encoding(e.to_string()))
+}
+
+/// Borrow a C string argument
+///
+/// # Safety
+///
+/// `value` must be NULL or point to a NUL-terminated string.
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if value.is_null() {
+        return Err(FfiError::InvalidArgument(format!("{} is NULL", name)));
+    }
+    CStr::from_ptr(value).to_str()
+        .map_err(|_| FfiError::InvalidArgument(format!("{} is not UTF-8", name)))
+}
+
+/// Parse a JSON record argument
+///
+/// # Safety
+///
+/// As for [`read_str`].
+unsafe fn read_json<T: serde::de::DeserializeOwned>(value: *const c_char, name: &str) -> Result<T, FfiError> {
+    serde_json::from_str(read_str(value, name)?)
+        .map_err(|e| FfiError::InvalidArgument(format!("Invalid {}: {}", name, e)))
+}
+
+/// Generate a BIP39 mnemonic of `word_count` words
+#[no_mangle]
+pub extern "C" fn sedly_generate_mnemonic(word_count: u32) -> *mut c_char {
+    finish(|| api::generate_mnemonic(word_count))
+}
+
+/// Derive an address; returns a `DerivedAddress` as JSON
+///
+/// # Safety
+///
+/// `seed_json` must be NULL or a NUL-terminated `WalletSeed` JSON string.
+#[no_mangle]
+pub unsafe extern "C" fn sedly_derive_address(
+    seed_json: *const c_char,
+    account: u32,
+    change: bool,
+    index: u32,
+) -> *mut c_char {
+    finish(|| read_json::<WalletSeed>(seed_json, "seed")
+        .and_then(|seed| api::derive_address(seed, account, change, index))
+        .and_then(|address| to_json(&address)))
+}
+
+/// Build and sign a payment; returns a `SignedTransaction` as JSON
+///
+/// # Safety
+///
+/// `request_json` must be NULL or a NUL-terminated `TransactionRequest`
+/// JSON string.
+#[no_mangle]
+pub unsafe extern "C" fn sedly_build_transaction(request_json: *const c_char) -> *mut c_char {
+    finish(|| read_json::<TransactionRequest>(request_json, "request")
+        .and_then(api::build_transaction)
+        .and_then(|signed| to_json(&signed)))
+}
+
+/// Decode a serialized transaction (hex); returns a `ParsedTransaction` as JSON
+///
+/// # Safety
+///
+/// `tx_hex` must be NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sedly_parse_transaction(tx_hex: *const c_char) -> *mut c_char {
+    finish(|| read_str(tx_hex, "transaction")
+        .and_then(|hex| api::parse_transaction(hex.to_string()))
+        .and_then(|parsed| to_json(&parsed)))
+}
+
+/// Message of the last error on the calling thread, NULL if the last call succeeded
+#[no_mangle]
+pub extern "C" fn sedly_last_error() -> *mut c_char {
+    panic::catch_unwind(|| LAST_ERROR.with(|last| {
+        last.borrow().as_ref()
+            .and_then(|message| CString::new(message.as_str()).ok())
+            .map_or(std::ptr::null_mut(), CString::into_raw)
+    }))
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Release a string returned by this library
+///
+/// # Safety
+///
+/// `value` must be NULL or a string returned by a `sedly_*` function, not
+/// released before.
+#[no_mangle]
+pub unsafe extern "C" fn sedly_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(CString::from_raw(value))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(value: *mut c_char) -> Option<String> {
+        if value.is_null() {
+            return None;
+        }
+        let string = unsafe { CStr::from_ptr(value) }.to_str().unwrap().to_string();
+        unsafe { sedly_string_free(value) };
+        Some(string)
+    }
+
+    #[test]
+    fn test_c_abi() {
+        let mnemonic = take(sedly_generate_mnemonic(12)).unwrap();
+        assert!(take(sedly_last_error()).is_none());
+
+        let seed = CString::new(format!(r#"{{"mnemonic":"{}","network":"testnet"}}"#, mnemonic)).unwrap();
+        let address = take(unsafe { sedly_derive_address(seed.as_ptr(), 0, false, 0) }).unwrap();
+        let address: api::DerivedAddress = serde_json::from_str(&address).unwrap();
+        assert_eq!(address.path, "m/44'/1'/0'/0/0");
+
+        // Errors return NULL and the message stays readable
+        assert!(take(unsafe { sedly_parse_transaction(c"zz".as_ptr()) }).is_none());
+        assert!(take(sedly_last_error()).unwrap().starts_with("Invalid argument"));
+        assert!(take(unsafe { sedly_build_transaction(std::ptr::null()) }).is_none());
+        assert_eq!(take(sedly_last_error()).unwrap(), "Invalid argument: request is NULL");
+
+        // A panic becomes an error instead of crossing the ABI
+        assert!(finish(|| panic!("boom")).is_null());
+        assert_eq!(take(sedly_last_error()).unwrap(), "Internal error: boom");
+    }
+}
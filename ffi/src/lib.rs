@@ -0,0 +1,21 @@
+//! Sedly FFI - wallet bindings for mobile and native applications
+//!
+//! Mnemonic generation, address derivation, transaction building and
+//! signing, and transaction parsing, reusing the consensus serialization of
+//! `sedly-core` instead of reimplementing it in each app:
+//!
+//! - Kotlin and Swift through uniffi: build the `cdylib`/`staticlib` and run
+//!   `cargo run -p sedly-ffi --features bindgen --bin uniffi-bindgen --
+//!   generate --library <path to libsedly_ffi> --language kotlin --out-dir out`
+//! - C through the functions of [`c`], declared in `include/sedly.h`
+//!   (regenerate with `cbindgen --config cbindgen.toml --output include/sedly.h`)
+
+pub mod api;
+pub mod c;
+
+pub use api::{
+    build_transaction, derive_address, generate_mnemonic, parse_transaction, DerivedAddress, FfiError, FfiNetwork,
+    Output, ParsedInput, ParsedTransaction, SignedTransaction, TransactionRequest, Utxo, WalletSeed,
+};
+
+uniffi::setup_scaffolding!();
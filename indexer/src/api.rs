@@ -0,0 +1,588 @@
+//! REST explorer API served from the index database
+
+use crate::events::{replay_events, ChainEvent, EventBus};
+use crate::index::{AddressTx, AssetSupply, BlockStats, CoinDaysDestroyed, ExplorerIndex, IndexError, ScriptStats};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::Stream;
+use sedly_core::BlockchainDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tower_http::cors::CorsLayer;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+pub use sedly_core::Page;
+
+/// Default number of history entries returned
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+/// Maximum number of history entries per request
+pub const MAX_HISTORY_LIMIT: usize = 500;
+/// Blocks replayed per database read while an event stream catches up
+pub const REPLAY_CHUNK_BLOCKS: u64 = 100;
+
+/// Error body returned by the API
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiError {
+    /// Error message
+    pub error: String,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ApiError>)>;
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<ApiError>) {
+    (status, Json(ApiError { error: message.into() }))
+}
+
+fn internal(e: IndexError) -> (StatusCode, Json<ApiError>) {
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, (StatusCode, Json<ApiError>)> {
+    hex::decode(value).map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid hex"))
+}
+
+/// Index status
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatusResponse {
+    /// Last indexed height (None if empty)
+    pub indexed_height: Option<u64>,
+    /// Last indexed block hash (hex)
+    pub indexed_hash: Option<String>,
+}
+
+/// Address balances keyed by hex asset id
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AddressResponse {
+    /// Script (hex)
+    pub script_pubkey: String,
+    /// Current balance per asset id (hex)
+    pub balances: BTreeMap<String, u64>,
+    /// Total received per asset id (hex)
+    pub received: BTreeMap<String, u64>,
+    /// Number of transactions
+    pub tx_count: u64,
+}
+
+/// Usage of a validator script by state outputs
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ScriptResponse {
+    /// Validator script (hex)
+    pub validator: String,
+    /// State outputs ever created
+    pub outputs_created: u64,
+    /// State outputs spent
+    pub outputs_spent: u64,
+    /// Unspent state outputs referencing the script
+    pub live_outputs: u64,
+    /// Datum bytes held by the unspent state outputs
+    pub live_datum_bytes: u64,
+    /// Largest datum ever created, in bytes
+    pub max_datum_size: u64,
+}
+
+impl From<ScriptStats> for ScriptResponse {
+    fn from(stats: ScriptStats) -> Self {
+        Self {
+            validator: hex::encode(&stats.validator),
+            outputs_created: stats.outputs_created,
+            outputs_spent: stats.outputs_spent,
+            live_outputs: stats.live_outputs(),
+            live_datum_bytes: stats.live_datum_bytes,
+            max_datum_size: stats.max_datum_size,
+        }
+    }
+}
+
+/// Datum of unspent state outputs
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DatumResponse {
+    /// Datum (hex)
+    pub datum: String,
+    /// Unspent outputs carrying the datum
+    pub references: u64,
+}
+
+/// Query string for history requests
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// Maximum entries to return
+    pub limit: Option<usize>,
+    /// Cursor returned by the previous page
+    pub cursor: Option<String>,
+}
+
+/// Cursor of a history entry: height and position in the block
+fn history_cursor(entry: &AddressTx) -> String {
+    let mut cursor = entry.height.to_be_bytes().to_vec();
+    cursor.extend_from_slice(&entry.tx_index.to_be_bytes());
+    hex::encode(cursor)
+}
+
+fn parse_history_cursor(cursor: &str) -> Result<(u64, u32), (StatusCode, Json<ApiError>)> {
+    let bytes: [u8; 12] = decode_hex(cursor)?
+        .try_into()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid cursor"))?;
+    let height = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+    let tx_index = u32::from_be_bytes(bytes[8..].try_into().unwrap());
+    Ok((height, tx_index))
+}
+
+/// OpenAPI description of the explorer API, generated from the handlers
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Sedly Explorer API", description = "Address, asset and block statistics served by sedly-indexer"),
+    paths(status, address, address_history, asset, block_stats, coin_days_destroyed, script, datum, events),
+    components(schemas(
+        ApiError, StatusResponse, AddressResponse, Page<AddressTx>, AddressTx, AssetSupply, BlockStats,
+        CoinDaysDestroyed, ScriptResponse, DatumResponse, ChainEvent,
+    ))
+)]
+pub struct ApiDoc;
+
+/// Build the explorer router
+pub fn router(index: Arc<ExplorerIndex>) -> Router {
+    Router::new()
+        .route("/api/spec", get(spec))
+        .route("/api/v1/status", get(status))
+        .route("/api/v1/address/:script", get(address))
+        .route("/api/v1/address/:script/txs", get(address_history))
+        .route("/api/v1/asset/:asset_id", get(asset))
+        .route("/api/v1/block/:height/stats", get(block_stats))
+        .route("/api/v1/block/:height/coin-days", get(coin_days_destroyed))
+        .route("/api/v1/script/:validator_hash", get(script))
+        .route("/api/v1/datum/:datum_hash", get(datum))
+        .layer(CorsLayer::permissive())
+        .with_state(index)
+}
+
+/// Sources of the event stream endpoint
+#[derive(Clone)]
+pub struct EventSource {
+    /// Node database the events are rebuilt from
+    pub chain: Arc<BlockchainDB>,
+    /// Explorer index (indexed tip and address history)
+    pub index: Arc<ExplorerIndex>,
+    /// Live events published by the tailer
+    pub bus: EventBus,
+}
+
+/// Query string for event streams
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct EventsQuery {
+    /// First height to deliver; omitted, only new blocks are streamed
+    pub from_height: Option<u64>,
+    /// Only transactions touching this script (hex)
+    pub address: Option<String>,
+}
+
+/// Build the router of the event stream
+pub fn events_router(source: EventSource) -> Router {
+    Router::new()
+        .route("/api/v1/events", get(events))
+        .layer(CorsLayer::permissive())
+        .with_state(source)
+}
+
+/// `GET /api/spec`
+///
+/// OpenAPI document of this API; its version is the indexer crate version.
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// `GET /api/v1/status`
+#[utoipa::path(get, path = "/api/v1/status", responses(
+    (status = 200, description = "Last indexed block", body = StatusResponse),
+    (status = 500, description = "Index database error", body = ApiError),
+))]
+pub async fn status(State(index): State<Arc<ExplorerIndex>>) -> ApiResult<StatusResponse> {
+    let last = index.last_indexed().map_err(internal)?;
+    Ok(Json(StatusResponse {
+        indexed_height: last.map(|(height, _)| height),
+        indexed_hash: last.map(|(_, hash)| hex::encode(hash)),
+    }))
+}
+
+/// `GET /api/v1/address/:script`
+#[utoipa::path(get, path = "/api/v1/address/{script}",
+    params(("script" = String, Path, description = "Script pubkey (hex)")),
+    responses(
+        (status = 200, description = "Address balances", body = AddressResponse),
+        (status = 400, description = "Invalid hex", body = ApiError),
+        (status = 404, description = "Address not found", body = ApiError),
+    ),
+)]
+pub async fn address(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(script): Path<String>,
+) -> ApiResult<AddressResponse> {
+    let script_pubkey = decode_hex(&script)?;
+    let summary = index.get_address(&script_pubkey)
+        .map_err(internal)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Address not found"))?;
+
+    Ok(Json(AddressResponse {
+        script_pubkey: hex::encode(&summary.script_pubkey),
+        balances: summary.balances.iter()
+            .map(|(asset_id, balance)| (hex::encode(asset_id), balance.balance()))
+            .collect(),
+        received: summary.balances.iter()
+            .map(|(asset_id, balance)| (hex::encode(asset_id), balance.received))
+            .collect(),
+        tx_count: summary.tx_count,
+    }))
+}
+
+/// `GET /api/v1/address/:script/txs?limit=N&cursor=C`
+///
+/// History newest first, one page at a time.
+#[utoipa::path(get, path = "/api/v1/address/{script}/txs",
+    params(("script" = String, Path, description = "Script pubkey (hex)"), HistoryQuery),
+    responses(
+        (status = 200, description = "Page of the address history", body = Page<AddressTx>),
+        (status = 400, description = "Invalid hex or cursor", body = ApiError),
+    ),
+)]
+pub async fn address_history(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(script): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> ApiResult<Page<AddressTx>> {
+    let script_pubkey = decode_hex(&script)?;
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+    let before = query.cursor.as_deref().map(parse_history_cursor).transpose()?;
+
+    // One extra entry tells whether there is a next page
+    let mut items = index.get_address_history_before(&script_pubkey, before, limit + 1)
+        .map_err(internal)?;
+    let next_cursor = (items.len() > limit).then(|| history_cursor(&items[limit - 1]));
+    items.truncate(limit);
+    Ok(Json(Page { items, next_cursor }))
+}
+
+/// `GET /api/v1/asset/:asset_id`
+#[utoipa::path(get, path = "/api/v1/asset/{asset_id}",
+    params(("asset_id" = String, Path, description = "Asset id (hex, 32 bytes)")),
+    responses(
+        (status = 200, description = "Asset supply", body = AssetSupply),
+        (status = 400, description = "Invalid asset id", body = ApiError),
+        (status = 404, description = "Asset not found", body = ApiError),
+    ),
+)]
+pub async fn asset(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(asset_id): Path<String>,
+) -> ApiResult<AssetSupply> {
+    let asset_id: [u8; 32] = decode_hex(&asset_id)?
+        .try_into()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Asset id must be 32 bytes"))?;
+    index.get_asset(&asset_id)
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Asset not found"))
+}
+
+/// `GET /api/v1/block/:height/stats`
+#[utoipa::path(get, path = "/api/v1/block/{height}/stats",
+    params(("height" = u64, Path, description = "Block height")),
+    responses(
+        (status = 200, description = "Block statistics", body = BlockStats),
+        (status = 404, description = "Block not indexed", body = ApiError),
+    ),
+)]
+pub async fn block_stats(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(height): Path<u64>,
+) -> ApiResult<BlockStats> {
+    index.get_block_stats(height)
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Block not indexed"))
+}
+
+/// `GET /api/v1/block/:height/coin-days`
+///
+/// Coin-days destroyed by the block, in satoshi-days. Blocks indexed before
+/// coin-days tracking was added have no entry.
+#[utoipa::path(get, path = "/api/v1/block/{height}/coin-days",
+    params(("height" = u64, Path, description = "Block height")),
+    responses(
+        (status = 200, description = "Coin-days destroyed", body = CoinDaysDestroyed),
+        (status = 404, description = "Block not indexed", body = ApiError),
+    ),
+)]
+pub async fn coin_days_destroyed(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(height): Path<u64>,
+) -> ApiResult<CoinDaysDestroyed> {
+    index.get_coin_days_destroyed(height)
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Block not indexed"))
+}
+
+/// `GET /api/v1/script/:validator_hash`
+///
+/// Usage of a validator script by state outputs, keyed by the SHA256 of
+/// the validator (the datum is not part of it).
+#[utoipa::path(get, path = "/api/v1/script/{validator_hash}",
+    params(("validator_hash" = String, Path, description = "SHA256 of the validator script (hex, 32 bytes)")),
+    responses(
+        (status = 200, description = "Script usage", body = ScriptResponse),
+        (status = 400, description = "Invalid hash", body = ApiError),
+        (status = 404, description = "Script never used by a state output", body = ApiError),
+    ),
+)]
+pub async fn script(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(validator_hash): Path<String>,
+) -> ApiResult<ScriptResponse> {
+    let validator_hash: [u8; 32] = decode_hex(&validator_hash)?
+        .try_into()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Script hash must be 32 bytes"))?;
+    index.get_script_stats(&validator_hash)
+        .map_err(internal)?
+        .map(|stats| Json(stats.into()))
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Script not found"))
+}
+
+/// `GET /api/v1/datum/:datum_hash`
+///
+/// Datums are kept only while an unspent state output carries them.
+#[utoipa::path(get, path = "/api/v1/datum/{datum_hash}",
+    params(("datum_hash" = String, Path, description = "SHA256 of the datum (hex, 32 bytes)")),
+    responses(
+        (status = 200, description = "Datum", body = DatumResponse),
+        (status = 400, description = "Invalid hash", body = ApiError),
+        (status = 404, description = "Datum unknown or pruned", body = ApiError),
+    ),
+)]
+pub async fn datum(
+    State(index): State<Arc<ExplorerIndex>>,
+    Path(datum_hash): Path<String>,
+) -> ApiResult<DatumResponse> {
+    let datum_hash: [u8; 32] = decode_hex(&datum_hash)?
+        .try_into()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Datum hash must be 32 bytes"))?;
+    let stored = index.get_datum(&datum_hash)
+        .map_err(internal)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Datum not found"))?;
+    Ok(Json(DatumResponse { datum: hex::encode(&stored.datum), references: stored.references }))
+}
+
+/// `GET /api/v1/events?from_height=H&address=S`
+///
+/// Server-sent event stream of [`ChainEvent`]s. Blocks already indexed from
+/// `from_height` on are replayed first, then the stream continues with live
+/// events, without gaps or duplicates. The SSE id of each event is its block
+/// height: a client that disconnects resumes with `from_height` set to the
+/// last id it saw (the events of that height are sent again). A client too
+/// slow for the live feed receives an `error` event and the stream ends.
+#[utoipa::path(get, path = "/api/v1/events",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "Stream of chain events", content_type = "text/event-stream", body = ChainEvent),
+        (status = 400, description = "Invalid hex", body = ApiError),
+    ),
+)]
+pub async fn events(
+    State(source): State<EventSource>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<ApiError>)> {
+    let address = query.address.as_deref().map(decode_hex).transpose()?;
+
+    // Subscribe before reading the tip: blocks indexed meanwhile are live events
+    let live = source.bus.subscribe();
+    let tip = source.index.last_indexed().map_err(internal)?.map(|(height, _)| height);
+    let live_from = tip.map_or(0, |tip| tip + 1).max(query.from_height.unwrap_or(0));
+
+    let stream = EventStream {
+        source,
+        address,
+        next: query.from_height.unwrap_or(live_from),
+        tip,
+        live_from,
+        live,
+        pending: VecDeque::new(),
+        done: false,
+    };
+    let stream = futures::stream::unfold(stream, |mut stream| async move {
+        let event = stream.next_event().await?;
+        Some((Ok(event), stream))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// State of an event stream: replay up to `tip`, then live events
+struct EventStream {
+    source: EventSource,
+    address: Option<Vec<u8>>,
+    /// Next height to replay
+    next: u64,
+    /// Indexed tip when the stream started
+    tip: Option<u64>,
+    /// First height taken from the live feed
+    live_from: u64,
+    live: broadcast::Receiver<ChainEvent>,
+    /// Replayed events not sent yet
+    pending: VecDeque<ChainEvent>,
+    done: bool,
+}
+
+impl EventStream {
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(sse_event(&event));
+            }
+            if self.done {
+                return None;
+            }
+
+            if let Some(tip) = self.tip.filter(|tip| self.next <= *tip) {
+                let (from, to) = (self.next, tip.min(self.next + REPLAY_CHUNK_BLOCKS - 1));
+                let chain = Arc::clone(&self.source.chain);
+                let index = Arc::clone(&self.source.index);
+                let address = self.address.clone();
+                let replayed = tokio::task::spawn_blocking(move || {
+                    replay_events(&chain, &index, from, to, address.as_deref()).map_err(|e| e.to_string())
+                }).await;
+                match replayed {
+                    Ok(Ok(events)) => {
+                        self.pending.extend(events);
+                        self.next = to + 1;
+                    }
+                    Ok(Err(message)) => return Some(self.fail(message)),
+                    Err(e) => return Some(self.fail(e.to_string())),
+                }
+                continue;
+            }
+
+            match self.live.recv().await {
+                Ok(event) => {
+                    let wanted = self.address.as_deref().is_none_or(|address| event.touches(address));
+                    if event.height() >= self.live_from && wanted {
+                        return Some(sse_event(&event));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    return Some(self.fail(format!("Stream lagged behind by {} events", missed)));
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// End the stream with an `error` event
+    fn fail(&mut self, message: String) -> Event {
+        self.done = true;
+        self.pending.clear();
+        Event::default().event("error").data(message)
+    }
+}
+
+fn sse_event(event: &ChainEvent) -> Event {
+    let kind = match event {
+        ChainEvent::Block { .. } => "block",
+        ChainEvent::Transaction { .. } => "transaction",
+    };
+    Event::default()
+        .event(kind)
+        .id(event.height().to_string())
+        .json_data(event)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Block, Transaction};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_address_endpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = Arc::new(ExplorerIndex::open(temp_dir.path()).unwrap());
+        let block = Block::new([0; 32], vec![Transaction::coinbase(b"miner", 0, 50)], 0x1d00ffff, 0);
+        index.index_block(&block).unwrap();
+
+        let Json(response) = address(State(Arc::clone(&index)), Path(hex::encode(b"miner")))
+            .await
+            .unwrap();
+        assert_eq!(response.balances[&hex::encode([0u8; 32])], 50);
+
+        let missing = address(State(Arc::clone(&index)), Path(hex::encode(b"nobody"))).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let invalid = address(State(index), Path("zz".to_string())).await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_spec_lists_every_route() {
+        let Json(spec) = spec().await;
+        let paths: Vec<&String> = spec.paths.paths.keys().collect();
+        assert_eq!(paths, [
+            "/api/v1/address/{script}",
+            "/api/v1/address/{script}/txs",
+            "/api/v1/asset/{asset_id}",
+            "/api/v1/block/{height}/coin-days",
+            "/api/v1/block/{height}/stats",
+            "/api/v1/datum/{datum_hash}",
+            "/api/v1/events",
+            "/api/v1/script/{validator_hash}",
+            "/api/v1/status",
+        ]);
+        assert_eq!(spec.info.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_replays_then_follows_live() {
+        let chain_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+        let chain = Arc::new(BlockchainDB::open(chain_dir.path()).unwrap());
+        let index = Arc::new(ExplorerIndex::open(index_dir.path()).unwrap());
+        let bus = EventBus::new();
+
+        let mut blocks = vec![Block::genesis()];
+        chain.initialize_with_genesis(&blocks[0]).unwrap();
+        index.index_block(&blocks[0]).unwrap();
+        for height in 1..=3 {
+            let previous = blocks.last().unwrap().hash();
+            blocks.push(Block::new(previous, vec![Transaction::coinbase(b"miner", height, 50)], 0x1d00ffff, height));
+        }
+        for block in &blocks[1..3] {
+            chain.store_block(block).unwrap();
+            index.index_block(block).unwrap();
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/events?from_height=1", listener.local_addr().unwrap());
+        let source = EventSource { chain: Arc::clone(&chain), index: Arc::clone(&index), bus: bus.clone() };
+        tokio::spawn(async move { axum::serve(listener, events_router(source)).await });
+
+        let mut response = reqwest::get(url).await.unwrap();
+        let mut received = String::new();
+        while received.matches("event: ").count() < 4 {
+            received.push_str(std::str::from_utf8(&response.chunk().await.unwrap().unwrap()).unwrap());
+        }
+
+        // Block 3 comes from the bus after the replay of blocks 1 and 2
+        chain.store_block(&blocks[3]).unwrap();
+        index.index_block(&blocks[3]).unwrap();
+        bus.publish(crate::events::block_events(&chain, &blocks[3]).unwrap());
+        while received.matches("event: ").count() < 6 {
+            received.push_str(std::str::from_utf8(&response.chunk().await.unwrap().unwrap()).unwrap());
+        }
+
+        let ids: Vec<&str> = received.lines().filter_map(|line| line.strip_prefix("id: ")).collect();
+        assert_eq!(ids, ["1", "1", "2", "2", "3", "3"]);
+        assert!(received.contains(&hex::encode(blocks[3].hash())));
+    }
+}
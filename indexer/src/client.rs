@@ -0,0 +1,174 @@
+//! Typed HTTP client for the explorer API
+//!
+//! Uses the same request and response types as the server handlers in
+//! [`crate::api`], so it stays in sync with the OpenAPI document served at
+//! `/api/spec`.
+
+use crate::api::{AddressResponse, ApiError, DatumResponse, Page, ScriptResponse, StatusResponse};
+use crate::index::{AddressTx, AssetSupply, BlockStats, CoinDaysDestroyed};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+/// Client for a sedly-indexer explorer API
+#[derive(Debug, Clone)]
+pub struct ExplorerClient {
+    /// Base URL, e.g. `http://127.0.0.1:3001`
+    base_url: String,
+    /// HTTP client
+    http: reqwest::Client,
+}
+
+impl ExplorerClient {
+    /// Create a client for the API served at `base_url`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// `GET /api/v1/status`
+    pub async fn status(&self) -> Result<StatusResponse, ClientError> {
+        self.get("/api/v1/status", &[]).await
+    }
+
+    /// `GET /api/v1/address/:script`, None if the address is unknown
+    pub async fn address(&self, script_pubkey: &[u8]) -> Result<Option<AddressResponse>, ClientError> {
+        let path = format!("/api/v1/address/{}", hex::encode(script_pubkey));
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/v1/address/:script/txs`, one page of the history
+    ///
+    /// Pass the `next_cursor` of a page as `cursor` to get the following one.
+    pub async fn address_history(
+        &self,
+        script_pubkey: &[u8],
+        limit: Option<usize>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AddressTx>, ClientError> {
+        let path = format!("/api/v1/address/{}/txs", hex::encode(script_pubkey));
+        let mut query = Vec::new();
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor.to_string()));
+        }
+        self.get(&path, &query).await
+    }
+
+    /// `GET /api/v1/asset/:asset_id`, None if the asset is unknown
+    pub async fn asset(&self, asset_id: &[u8; 32]) -> Result<Option<AssetSupply>, ClientError> {
+        let path = format!("/api/v1/asset/{}", hex::encode(asset_id));
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/v1/block/:height/stats`, None if the block is not indexed
+    pub async fn block_stats(&self, height: u64) -> Result<Option<BlockStats>, ClientError> {
+        let path = format!("/api/v1/block/{}/stats", height);
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/v1/block/:height/coin-days`, None if the block has no entry
+    pub async fn coin_days_destroyed(&self, height: u64) -> Result<Option<CoinDaysDestroyed>, ClientError> {
+        let path = format!("/api/v1/block/{}/coin-days", height);
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/v1/script/:validator_hash`, None if no state output used the script
+    pub async fn script(&self, validator_hash: &[u8; 32]) -> Result<Option<ScriptResponse>, ClientError> {
+        let path = format!("/api/v1/script/{}", hex::encode(validator_hash));
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/v1/datum/:datum_hash`, None if the datum is unknown or pruned
+    pub async fn datum(&self, datum_hash: &[u8; 32]) -> Result<Option<DatumResponse>, ClientError> {
+        let path = format!("/api/v1/datum/{}", hex::encode(datum_hash));
+        not_found_as_none(self.get(&path, &[]).await)
+    }
+
+    /// `GET /api/spec`, the OpenAPI document of the server
+    pub async fn spec(&self) -> Result<serde_json::Value, ClientError> {
+        self.get("/api/spec", &[]).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, ClientError> {
+        let response = self.http
+            .get(format!("{}{}", self.base_url, path))
+            .query(query)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+        let message = match response.json::<ApiError>().await {
+            Ok(body) => body.error,
+            Err(_) => status.to_string(),
+        };
+        Err(ClientError::Api { status, message })
+    }
+}
+
+fn not_found_as_none<T>(result: Result<T, ClientError>) -> Result<Option<T>, ClientError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ClientError::Api { status: StatusCode::NOT_FOUND, .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Explorer client errors
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("API error {status}: {message}")]
+    Api { status: StatusCode, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::router;
+    use crate::ExplorerIndex;
+    use sedly_core::{Block, Transaction};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_client_against_router() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = Arc::new(ExplorerIndex::open(temp_dir.path()).unwrap());
+        let mut previous_hash = [0; 32];
+        for height in 0..3 {
+            let block = Block::new(previous_hash, vec![Transaction::coinbase(b"miner", height, 50)], 0x1d00ffff, height);
+            index.index_block(&block).unwrap();
+            previous_hash = block.hash();
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = ExplorerClient::new(format!("http://{}/", listener.local_addr().unwrap()));
+        tokio::spawn(async move { axum::serve(listener, router(index)).await });
+
+        assert_eq!(client.status().await.unwrap().indexed_height, Some(2));
+        assert_eq!(client.address(b"miner").await.unwrap().unwrap().tx_count, 3);
+        assert!(client.address(b"nobody").await.unwrap().is_none());
+        assert!(client.block_stats(7).await.unwrap().is_none());
+        assert!(client.script(&[0; 32]).await.unwrap().is_none());
+        assert!(client.datum(&[0; 32]).await.unwrap().is_none());
+
+        let first = client.address_history(b"miner", Some(2), None).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        let rest = client.address_history(b"miner", Some(2), first.next_cursor.as_deref()).await.unwrap();
+        assert_eq!(rest.items.len(), 1);
+        assert!(rest.next_cursor.is_none());
+
+        let invalid = client.address_history(b"miner", None, Some("zz")).await;
+        assert!(matches!(invalid, Err(ClientError::Api { status: StatusCode::BAD_REQUEST, .. })));
+        assert!(client.spec().await.unwrap()["paths"].get("/api/v1/status").is_some());
+    }
+}
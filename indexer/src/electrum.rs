@@ -0,0 +1,472 @@
+//! ElectrumX-compatible protocol adapter
+//!
+//! Electrum-protocol wallets identify a script by its script hash: the
+//! SHA-256 of the script_pubkey, shown in hex with the bytes reversed
+//! (the [`Hash256`] display form). The adapter answers the
+//! `blockchain.scripthash.*` methods from the script hash index of the
+//! [`ExplorerIndex`] (history, unspent outputs, balance and status),
+//! headers and raw transactions from the node database, and forwards
+//! broadcasts to the Tendermint RPC endpoint. Requests and responses are
+//! JSON-RPC objects, one per line, over plain TCP; subscribed scripts and
+//! headers are notified after each indexed block.
+//!
+//! Differences from Bitcoin servers a wallet has to know about: amounts
+//! are native SLY only (outputs of other assets are left out of balances
+//! and unspent lists, so a wallet unaware of assets never spends them as
+//! SLY), headers and transactions are bincode-serialized, and the adapter
+//! sees no mempool, so unconfirmed balances are always 0.
+
+use crate::events::{ChainEvent, EventBus};
+use crate::index::{script_hash, ExplorerIndex, IndexError};
+use sedly_core::{decode_transaction, BlockchainDB, Hash256, Txid};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// Protocol version spoken by the adapter
+pub const PROTOCOL_VERSION: &str = "1.4";
+
+/// Scripts a single connection can subscribe to
+pub const MAX_SUBSCRIPTIONS: usize = 10_000;
+
+/// Longest request line accepted, in bytes
+pub const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Subscriptions of a connection
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Subscribed to new headers
+    headers: bool,
+    /// Subscribed script hashes with the last status sent
+    scripts: HashMap<[u8; 32], Option<String>>,
+}
+
+/// Electrum protocol server over the explorer index
+pub struct ElectrumServer {
+    /// Node database (headers and transactions)
+    chain: Arc<BlockchainDB>,
+    /// Explorer index (script hash history and unspent outputs)
+    index: Arc<ExplorerIndex>,
+    /// Live events published by the tailer, to notify subscriptions
+    bus: EventBus,
+    /// Tendermint RPC endpoint receiving broadcasts, e.g. `http://127.0.0.1:26657`
+    broadcast_url: Option<String>,
+    /// HTTP client for broadcasts
+    http: reqwest::Client,
+}
+
+impl ElectrumServer {
+    /// Create a server answering from `index` and `chain`, notifying on the events of `bus`
+    pub fn new(chain: Arc<BlockchainDB>, index: Arc<ExplorerIndex>, bus: EventBus) -> Self {
+        Self { chain, index, bus, broadcast_url: None, http: reqwest::Client::new() }
+    }
+
+    /// Forward `blockchain.transaction.broadcast` to the Tendermint RPC endpoint at `url`
+    pub fn with_broadcast_url(mut self, url: impl Into<String>) -> Self {
+        self.broadcast_url = Some(url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Accept connections on `listener` until the task is dropped
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = server.connection(stream).await {
+                    log::debug!("Electrum connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Serve one connection: requests in order, notifications after each block
+    async fn connection(&self, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut events = self.bus.subscribe();
+        let mut session = Session::default();
+        let mut line = Vec::new();
+
+        loop {
+            tokio::select! {
+                read = read_line(&mut reader, &mut line) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                    if line.len() > MAX_REQUEST_SIZE {
+                        let error = error_response(Value::Null, -32600, "Request too large");
+                        write_message(&mut writer, &error).await?;
+                        return Ok(());
+                    }
+                    let response = self.respond(&mut session, &line).await;
+                    write_message(&mut writer, &response).await?;
+                    line.clear();
+                }
+                event = events.recv() => {
+                    match event {
+                        Ok(ChainEvent::Block { .. }) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Ok(ChainEvent::Transaction { .. }) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                    }
+                    match self.notifications(&mut session) {
+                        Ok(notifications) => {
+                            for notification in &notifications {
+                                write_message(&mut writer, notification).await?;
+                            }
+                        }
+                        Err(e) => log::warn!("Electrum notifications failed: {}", e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Response to one request line
+    async fn respond(&self, session: &mut Session, line: &[u8]) -> Value {
+        let request: Request = match serde_json::from_slice(line) {
+            Ok(request) => request,
+            Err(e) => return error_response(Value::Null, -32700, &format!("Parse error: {}", e)),
+        };
+        match self.call(session, &request.method, &request.params).await {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": request.id, "result": result}),
+            Err(e) => error_response(request.id, e.code(), &e.to_string()),
+        }
+    }
+
+    /// Run a protocol method for `session`
+    pub async fn call(&self, session: &mut Session, method: &str, params: &Value) -> Result<Value, ElectrumError> {
+        match method {
+            "server.version" => Ok(json!([format!("sedly-indexer {}", env!("CARGO_PKG_VERSION")), PROTOCOL_VERSION])),
+            "server.banner" => Ok(Value::from("Sedly Electrum adapter: native SLY amounts only")),
+            "server.ping" => Ok(Value::Null),
+            "blockchain.headers.subscribe" => {
+                session.headers = true;
+                self.tip_header()
+            }
+            "blockchain.block.header" => {
+                let height: u64 = param(params, 0)?;
+                let header = self.chain.get_header_by_height(height)
+                    .map_err(|e| ElectrumError::Index(IndexError::Chain(e.to_string())))?
+                    .ok_or_else(|| ElectrumError::NotFound(format!("No header at height {}", height)))?;
+                Ok(Value::from(hex::encode(encode(&header)?)))
+            }
+            "blockchain.scripthash.get_balance" => {
+                let script_hash = script_hash_param(params)?;
+                let confirmed = self.index.get_address_by_hash(&script_hash)?
+                    .and_then(|summary| summary.balances.get(&[0; 32]).map(|balance| balance.balance()))
+                    .unwrap_or(0);
+                Ok(json!({"confirmed": confirmed, "unconfirmed": 0}))
+            }
+            "blockchain.scripthash.get_history" => {
+                let script_hash = script_hash_param(params)?;
+                let history: Vec<Value> = self.index.get_script_history_range(&script_hash, 0, u64::MAX)?
+                    .iter()
+                    .map(|entry| json!({"tx_hash": Txid::from(entry.txid), "height": entry.height}))
+                    .collect();
+                Ok(Value::from(history))
+            }
+            "blockchain.scripthash.listunspent" => {
+                let script_hash = script_hash_param(params)?;
+                let unspent: Vec<Value> = self.index.get_script_unspent(&script_hash)?
+                    .iter()
+                    .filter(|utxo| utxo.output.is_native_asset())
+                    .map(|utxo| json!({
+                        "tx_hash": Txid::from(utxo.outpoint.txid),
+                        "tx_pos": utxo.outpoint.vout,
+                        "height": utxo.height,
+                        "value": utxo.output.value.to_sat(),
+                    }))
+                    .collect();
+                Ok(Value::from(unspent))
+            }
+            "blockchain.scripthash.subscribe" => {
+                let script_hash = script_hash_param(params)?;
+                if !session.scripts.contains_key(&script_hash) && session.scripts.len() >= MAX_SUBSCRIPTIONS {
+                    return Err(ElectrumError::BadRequest(format!("More than {} subscriptions", MAX_SUBSCRIPTIONS)));
+                }
+                let status = self.script_status(&script_hash)?;
+                session.scripts.insert(script_hash, status.clone());
+                Ok(Value::from(status))
+            }
+            "blockchain.scripthash.unsubscribe" => {
+                let script_hash = script_hash_param(params)?;
+                Ok(Value::from(session.scripts.remove(&script_hash).is_some()))
+            }
+            "blockchain.transaction.get" => {
+                let txid: Txid = param(params, 0)?;
+                let (tx, _) = self.chain.get_transaction(&txid.to_byte_array())
+                    .map_err(|e| ElectrumError::Index(IndexError::Chain(e.to_string())))?
+                    .ok_or_else(|| ElectrumError::NotFound(format!("Transaction {} not found", txid)))?;
+                Ok(Value::from(hex::encode(encode(&tx)?)))
+            }
+            "blockchain.transaction.broadcast" => {
+                let raw: String = param(params, 0)?;
+                self.broadcast(&raw).await.map(Value::from)
+            }
+            _ => Err(ElectrumError::UnknownMethod(method.to_string())),
+        }
+    }
+
+    /// Notifications owed to `session` after a new block: the tip header and the changed script statuses
+    pub fn notifications(&self, session: &mut Session) -> Result<Vec<Value>, ElectrumError> {
+        let mut notifications = Vec::new();
+        if session.headers {
+            notifications.push(json!({
+                "jsonrpc": "2.0",
+                "method": "blockchain.headers.subscribe",
+                "params": [self.tip_header()?],
+            }));
+        }
+        for (script_hash, sent) in session.scripts.iter_mut() {
+            let status = self.script_status(script_hash)?;
+            if status != *sent {
+                notifications.push(json!({
+                    "jsonrpc": "2.0",
+                    "method": "blockchain.scripthash.subscribe",
+                    "params": [Hash256::from(*script_hash), status],
+                }));
+                *sent = status;
+            }
+        }
+        Ok(notifications)
+    }
+
+    /// Height and serialized header (hex) of the last indexed block
+    fn tip_header(&self) -> Result<Value, ElectrumError> {
+        let (height, _) = self.index.last_indexed()?
+            .ok_or_else(|| ElectrumError::NotFound("Nothing indexed yet".to_string()))?;
+        let header = self.chain.get_header_by_height(height)
+            .map_err(|e| ElectrumError::Index(IndexError::Chain(e.to_string())))?
+            .ok_or_else(|| ElectrumError::NotFound(format!("No header at height {}", height)))?;
+        Ok(json!({"height": height, "hex": hex::encode(encode(&header)?)}))
+    }
+
+    /// Electrum status of a script: SHA-256 of `tx_hash:height:` for each history entry, null without history
+    fn script_status(&self, script_hash: &[u8; 32]) -> Result<Option<String>, ElectrumError> {
+        let history = self.index.get_script_history_range(script_hash, 0, u64::MAX)?;
+        if history.is_empty() {
+            return Ok(None);
+        }
+        let mut hasher = Sha256::new();
+        for entry in &history {
+            hasher.update(format!("{}:{}:", Txid::from(entry.txid), entry.height));
+        }
+        Ok(Some(hex::encode(hasher.finalize())))
+    }
+
+    /// Submit a serialized transaction (hex) to Tendermint and return its txid
+    async fn broadcast(&self, raw: &str) -> Result<String, ElectrumError> {
+        let url = self.broadcast_url.as_ref()
+            .ok_or_else(|| ElectrumError::Broadcast("Broadcast is not enabled on this server".to_string()))?;
+        let bytes = hex::decode(raw).map_err(|e| ElectrumError::InvalidParams(format!("Invalid hex: {}", e)))?;
+        let tx = decode_transaction(&bytes).map_err(|e| ElectrumError::InvalidParams(e.to_string()))?;
+
+        let response: Value = self.http
+            .get(format!("{}/broadcast_tx_sync", url))
+            .query(&[("tx", format!("0x{}", raw))])
+            .send()
+            .await
+            .map_err(|e| ElectrumError::Broadcast(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| ElectrumError::Broadcast(e.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(ElectrumError::Broadcast(error.to_string()));
+        }
+        let result = &response["result"];
+        if result["code"].as_u64() != Some(0) {
+            return Err(ElectrumError::Broadcast(result["log"].as_str().unwrap_or("rejected").to_string()));
+        }
+        Ok(tx.txid().to_string())
+    }
+}
+
+/// JSON-RPC request of an Electrum client
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Positional param `position` of a request
+fn param<T: DeserializeOwned>(params: &Value, position: usize) -> Result<T, ElectrumError> {
+    let value = params.get(position)
+        .ok_or_else(|| ElectrumError::InvalidParams(format!("Missing param {}", position)))?;
+    serde_json::from_value(value.clone()).map_err(|e| ElectrumError::InvalidParams(e.to_string()))
+}
+
+/// Script hash of the first param, from its Electrum (reversed) hex form to [`script_hash`] order
+fn script_hash_param(params: &Value) -> Result<[u8; 32], ElectrumError> {
+    let script_hash: Hash256 = param(params, 0)?;
+    Ok(script_hash.into())
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, ElectrumError> {
+    bincode::serialize(value).map_err(|e| ElectrumError::Index(IndexError::Serialization(e.to_string())))
+}
+
+/// Read up to the end of a line into `line`, keeping what a cancelled read left there
+///
+/// Stops one byte past [`MAX_REQUEST_SIZE`] so an endless line cannot exhaust memory.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, line: &mut Vec<u8>) -> std::io::Result<usize> {
+    let limit = (MAX_REQUEST_SIZE + 1).saturating_sub(line.len()) as u64;
+    reader.take(limit).read_until(b'\n', line).await
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+async fn write_message<W: AsyncWriteExt + Unpin>(writer: &mut W, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+/// Electrum script hash (display form) of `script_pubkey`
+pub fn electrum_script_hash(script_pubkey: &[u8]) -> String {
+    Hash256::from(script_hash(script_pubkey)).to_string()
+}
+
+/// Electrum adapter errors
+#[derive(Debug, thiserror::Error)]
+pub enum ElectrumError {
+    #[error("Unknown method: {0}")]
+    UnknownMethod(String),
+
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("Broadcast failed: {0}")]
+    Broadcast(String),
+
+    #[error(transparent)]
+    Index(#[from] IndexError),
+}
+
+impl ElectrumError {
+    /// JSON-RPC error code, as ElectrumX reports it
+    pub fn code(&self) -> i64 {
+        match self {
+            ElectrumError::UnknownMethod(_) => -32601,
+            ElectrumError::InvalidParams(_) => -32602,
+            ElectrumError::BadRequest(_) | ElectrumError::NotFound(_) | ElectrumError::Broadcast(_) => 1,
+            ElectrumError::Index(_) => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Block, OutPoint, Transaction, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_scripthash_methods_and_notifications() {
+        let chain_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+        let chain = Arc::new(BlockchainDB::open(chain_dir.path()).unwrap());
+        let index = Arc::new(ExplorerIndex::open(index_dir.path()).unwrap());
+        let server = ElectrumServer::new(Arc::clone(&chain), Arc::clone(&index), EventBus::new());
+        let mut session = Session::default();
+
+        let genesis = Block::genesis();
+        chain.initialize_with_genesis(&genesis).unwrap();
+        index.index_block(&genesis).unwrap();
+        let coinbase = Transaction::coinbase(b"alice", 1, 5_000);
+        let block1 = Block::new(genesis.hash(), vec![coinbase.clone()], 0x1d00ffff, 1);
+        chain.store_block(&block1).unwrap();
+        index.index_block(&block1).unwrap();
+
+        let alice = json!([electrum_script_hash(b"alice")]);
+        let status = server.call(&mut session, "blockchain.scripthash.subscribe", &alice).await.unwrap();
+        assert!(status.is_string());
+        let tip = server.call(&mut session, "blockchain.headers.subscribe", &Value::Null).await.unwrap();
+        assert_eq!(tip["height"], 1);
+        let balance = server.call(&mut session, "blockchain.scripthash.get_balance", &alice).await.unwrap();
+        assert_eq!(balance, json!({"confirmed": 5_000, "unconfirmed": 0}));
+        let raw = server.call(&mut session, "blockchain.transaction.get", &json!([coinbase.txid()])).await.unwrap();
+        assert_eq!(raw, Value::from(hex::encode(bincode::serialize(&coinbase).unwrap())));
+
+        // Payment to bob with change to alice, plus an output of another asset
+        let payment = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase.hash(), 0), vec![])],
+            vec![
+                TxOutput::to_address(3_000, b"bob"),
+                TxOutput::to_address(1_900, b"alice"),
+                TxOutput::new(5, [7; 32], b"alice".to_vec()),
+            ],
+            0,
+        );
+        let coinbase2 = Transaction::coinbase(b"miner", 2, 50);
+        let block2 = Block::new(block1.hash(), vec![coinbase2, payment.clone()], 0x1d00ffff, 2);
+        chain.store_block(&block2).unwrap();
+        index.index_block(&block2).unwrap();
+
+        let notifications = server.notifications(&mut session).unwrap();
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0]["params"][0]["height"], 2);
+        assert_eq!(notifications[1]["params"][0], alice[0]);
+        assert_ne!(notifications[1]["params"][1], status);
+        // Nothing new: no script notification
+        assert_eq!(server.notifications(&mut session).unwrap().len(), 1);
+
+        let history = server.call(&mut session, "blockchain.scripthash.get_history", &alice).await.unwrap();
+        assert_eq!(history, json!([
+            {"tx_hash": coinbase.txid(), "height": 1},
+            {"tx_hash": payment.txid(), "height": 2},
+        ]));
+        // The output of the other asset is not among the spendable SLY
+        let unspent = server.call(&mut session, "blockchain.scripthash.listunspent", &alice).await.unwrap();
+        assert_eq!(unspent, json!([{"tx_hash": payment.txid(), "tx_pos": 1, "height": 2, "value": 1_900}]));
+
+        let unknown = server.call(&mut session, "blockchain.scripthash.get_fee", &alice).await;
+        assert_eq!(unknown.unwrap_err().code(), -32601);
+        let invalid = server.call(&mut session, "blockchain.scripthash.listunspent", &json!(["zz"])).await;
+        assert_eq!(invalid.unwrap_err().code(), -32602);
+        let broadcast = server.call(&mut session, "blockchain.transaction.broadcast", &json!(["00"])).await;
+        assert_eq!(broadcast.unwrap_err().code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_line_protocol() {
+        let chain_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+        let chain = Arc::new(BlockchainDB::open(chain_dir.path()).unwrap());
+        let index = Arc::new(ExplorerIndex::open(index_dir.path()).unwrap());
+        let server = Arc::new(ElectrumServer::new(chain, index, EventBus::new()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(server.serve(listener));
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"{\"id\": 1, \"method\": \"server.version\", \"params\": [\"wallet\", \"1.4\"]}\nnot json\n")
+            .await
+            .unwrap();
+
+        let version: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!((version["id"].clone(), version["result"][1].clone()), (json!(1), json!(PROTOCOL_VERSION)));
+        let error: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(error["error"]["code"], -32700);
+    }
+}
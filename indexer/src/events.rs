@@ -0,0 +1,231 @@
+//! Chain events for subscribers, live or replayed from history
+//!
+//! The tailer publishes the events of every block it indexes on an
+//! [`EventBus`]. A subscriber that was offline replays the events it missed
+//! with [`replay_events`], which rebuilds them from the node database (and
+//! from the address history when filtering by address), then switches to
+//! the live bus: both paths produce the same [`ChainEvent`] values.
+
+use crate::index::{ExplorerIndex, IndexError};
+use sedly_core::{Block, BlockchainDB};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Events buffered for slow live subscribers before they lag
+pub const EVENT_BUS_CAPACITY: usize = 4_096;
+
+/// Event of the indexed chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// Block connected to the indexed chain
+    Block {
+        /// Block height
+        height: u64,
+        /// Block hash (hex)
+        hash: String,
+        /// Block timestamp
+        timestamp: u64,
+        /// Number of transactions
+        tx_count: u64,
+    },
+    /// Transaction confirmed in a block
+    Transaction {
+        /// Height of the containing block
+        height: u64,
+        /// Position of the transaction in the block
+        tx_index: u32,
+        /// Transaction hash (hex)
+        txid: String,
+        /// Scripts (hex) paid by the outputs or spent by the inputs
+        addresses: Vec<String>,
+    },
+}
+
+impl ChainEvent {
+    /// Height of the block the event belongs to
+    pub fn height(&self) -> u64 {
+        match self {
+            ChainEvent::Block { height, .. } | ChainEvent::Transaction { height, .. } => *height,
+        }
+    }
+
+    /// Whether the event is a transaction touching `script_pubkey`
+    pub fn touches(&self, script_pubkey: &[u8]) -> bool {
+        let script = hex::encode(script_pubkey);
+        matches!(self, ChainEvent::Transaction { addresses, .. } if addresses.contains(&script))
+    }
+}
+
+/// Broadcast channel of live chain events
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl EventBus {
+    /// Create new bus
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Subscribe to the events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Publish events to the current subscribers
+    pub fn publish(&self, events: Vec<ChainEvent>) {
+        for event in events {
+            // No subscribers is not an error
+            let _ = self.sender.send(event);
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Events of a block: the block itself, then its transactions in order
+///
+/// Scripts spent by the inputs are read from the transaction index of the
+/// node database.
+pub fn block_events(chain: &BlockchainDB, block: &Block) -> Result<Vec<ChainEvent>, IndexError> {
+    let height = block.header.height;
+    let mut events = vec![ChainEvent::Block {
+        height,
+        hash: hex::encode(block.hash()),
+        timestamp: block.header.timestamp,
+        tx_count: block.transactions.len() as u64,
+    }];
+    for tx_index in 0..block.transactions.len() {
+        events.push(transaction_event(chain, block, tx_index)?);
+    }
+    Ok(events)
+}
+
+fn transaction_event(chain: &BlockchainDB, block: &Block, tx_index: usize) -> Result<ChainEvent, IndexError> {
+    let tx = &block.transactions[tx_index];
+    let mut addresses: Vec<String> = Vec::new();
+    let mut add = |script_pubkey: &[u8]| {
+        let script = hex::encode(script_pubkey);
+        if !addresses.contains(&script) {
+            addresses.push(script);
+        }
+    };
+
+    if !tx.is_coinbase() {
+        for input in &tx.inputs {
+            let outpoint = &input.previous_output;
+            let (previous, _) = chain.get_transaction(&outpoint.txid)
+                .map_err(|e| IndexError::Chain(e.to_string()))?
+                .ok_or(IndexError::MissingOutput { outpoint: outpoint.clone() })?;
+            let output = previous.outputs.get(outpoint.vout as usize)
+                .ok_or(IndexError::MissingOutput { outpoint: outpoint.clone() })?;
+            add(&output.script_pubkey);
+        }
+    }
+    for output in &tx.outputs {
+        add(&output.script_pubkey);
+    }
+
+    Ok(ChainEvent::Transaction {
+        height: block.header.height,
+        tx_index: tx_index as u32,
+        txid: hex::encode(tx.hash()),
+        addresses,
+    })
+}
+
+/// Rebuild the events of the blocks from `from_height` to `to_height` (inclusive)
+///
+/// Without `address` every block is read from the node database. With an
+/// address only the transactions touching it are returned, located through
+/// the address history of the index so unrelated blocks are never read.
+pub fn replay_events(
+    chain: &BlockchainDB,
+    index: &ExplorerIndex,
+    from_height: u64,
+    to_height: u64,
+    address: Option<&[u8]>,
+) -> Result<Vec<ChainEvent>, IndexError> {
+    let load_block = |height: u64| -> Result<Block, IndexError> {
+        chain.get_block_by_height(height)
+            .map_err(|e| IndexError::Chain(e.to_string()))?
+            .ok_or_else(|| IndexError::Chain(format!("Block at height {} not found", height)))
+    };
+
+    let mut events = Vec::new();
+    match address {
+        None => {
+            for height in from_height..=to_height {
+                events.extend(block_events(chain, &load_block(height)?)?);
+            }
+        }
+        Some(script_pubkey) => {
+            let mut block: Option<Block> = None;
+            for entry in index.get_address_history_range(script_pubkey, from_height, to_height)? {
+                if block.as_ref().map(|block| block.header.height) != Some(entry.height) {
+                    block = Some(load_block(entry.height)?);
+                }
+                if let Some(block) = &block {
+                    events.push(transaction_event(chain, block, entry.tx_index as usize)?);
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{OutPoint, Transaction, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_replay_matches_live_events() {
+        let chain_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+        let chain = BlockchainDB::open(chain_dir.path()).unwrap();
+        let index = ExplorerIndex::open(index_dir.path()).unwrap();
+        let bus = EventBus::new();
+        let mut live = bus.subscribe();
+
+        let genesis = Block::genesis();
+        chain.initialize_with_genesis(&genesis).unwrap();
+        let coinbase = Transaction::coinbase(b"miner", 1, 50);
+        let payment = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase.hash(), 0), vec![])],
+            vec![TxOutput::to_address(40, b"alice")],
+            0,
+        );
+        let block1 = Block::new(genesis.hash(), vec![coinbase], 0x1d00ffff, 1);
+        let block2 = Block::new(block1.hash(), vec![Transaction::coinbase(b"miner", 2, 50), payment], 0x1d00ffff, 2);
+        for block in [&genesis, &block1, &block2] {
+            if block.header.height > 0 {
+                chain.store_block(block).unwrap();
+            }
+            index.index_block(block).unwrap();
+            bus.publish(block_events(&chain, block).unwrap());
+        }
+
+        let mut published = Vec::new();
+        while let Ok(event) = live.try_recv() {
+            published.push(event);
+        }
+        assert_eq!(replay_events(&chain, &index, 0, 2, None).unwrap(), published);
+
+        // The payment concerns both the spender and the receiver
+        let alice = replay_events(&chain, &index, 0, 2, Some(b"alice")).unwrap();
+        assert_eq!(alice.len(), 1);
+        assert!(alice[0].touches(b"miner"));
+        assert_eq!(alice, published.iter().filter(|event| event.touches(b"alice")).cloned().collect::<Vec<_>>());
+        assert_eq!(replay_events(&chain, &index, 2, 2, Some(b"miner")).unwrap().len(), 2);
+    }
+}
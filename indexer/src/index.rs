@@ -0,0 +1,1056 @@
+//! Explorer index database (address balances, history, asset supplies, block stats)
+//!
+//! State outputs (`<datum> OP_DROP <validator>`, see `sedly_core::state`)
+//! are also counted per validator script, and their datums are stored once
+//! by hash with a reference count: a datum no unspent output refers to any
+//! more is pruned.
+//!
+//! Unspent outputs are also listed per script hash, the key Electrum
+//! wallets query (see [`crate::electrum`]).
+//!
+//! Each indexed block keeps undo data (the outputs it spent and the records
+//! it overwrote) for [`UNDO_DEPTH`] blocks, so a block the node disconnects
+//! in a reorg is rolled back with [`ExplorerIndex::undo_block`].
+
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, DB};
+use sedly_core::{Block, OutPoint, StateScript, TxOutput};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// Column families of the index database
+const CF_OUTPUTS: &str = "outputs";                 // outpoint -> IndexedOutput (unspent only)
+const CF_ADDRESSES: &str = "addresses";             // script_hash -> AddressSummary
+const CF_ADDRESS_HISTORY: &str = "address_history"; // script_hash ++ height ++ tx_index -> AddressTx
+const CF_SCRIPT_UTXOS: &str = "script_utxos";       // script_hash ++ outpoint -> () (unspent only)
+const CF_ASSETS: &str = "assets";                   // asset_id -> AssetSupply
+const CF_BLOCK_STATS: &str = "block_stats";         // height -> BlockStats
+const CF_COIN_DAYS: &str = "coin_days";             // height -> CoinDaysDestroyed
+const CF_SCRIPTS: &str = "scripts";                 // validator hash -> ScriptStats
+const CF_DATUMS: &str = "datums";                   // datum hash -> StoredDatum (referenced only)
+const CF_UNDO: &str = "undo";                       // height -> BlockUndo (last UNDO_DEPTH blocks)
+const CF_META: &str = "meta";                       // keys -> values
+
+const COLUMN_FAMILIES: [&str; 11] = [
+    CF_OUTPUTS, CF_ADDRESSES, CF_ADDRESS_HISTORY, CF_SCRIPT_UTXOS, CF_ASSETS, CF_BLOCK_STATS, CF_COIN_DAYS, CF_SCRIPTS,
+    CF_DATUMS, CF_UNDO, CF_META,
+];
+
+/// Blocks below the indexed tip that can be rolled back; deeper reorgs rebuild the index
+pub const UNDO_DEPTH: u64 = 1_000;
+
+/// Seconds in a day, the unit of coin age
+const SECONDS_PER_DAY: u128 = 86_400;
+
+/// Metadata keys
+const META_LAST_HEIGHT: &str = "last_height";
+const META_LAST_HASH: &str = "last_hash";
+const META_SCRIPT_UTXOS: &str = "script_utxos";
+
+/// Output tracked until it is spent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedOutput {
+    output: TxOutput,
+    height: u64,
+}
+
+/// Records a block overwrote with their previous values (None if it created them)
+type Previous<T> = Vec<([u8; 32], Option<T>)>;
+
+/// Changes of an indexed block, enough to roll it back
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BlockUndo {
+    /// Block indexed before this one
+    previous: Option<(u64, [u8; 32])>,
+    /// Outputs of earlier blocks spent by the block, by outpoint key
+    spent: Vec<(Vec<u8>, IndexedOutput)>,
+    /// Outputs created by the block and left unspent, by outpoint key with their script
+    created: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Address history keys written by the block
+    history: Vec<Vec<u8>>,
+    /// Records the block updated
+    addresses: Previous<AddressSummary>,
+    assets: Previous<AssetSupply>,
+    scripts: Previous<ScriptStats>,
+    datums: Previous<StoredDatum>,
+}
+
+/// Balance of a single asset held by an address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetBalance {
+    /// Total received
+    pub received: u64,
+    /// Total spent
+    pub sent: u64,
+}
+
+impl AssetBalance {
+    /// Current balance
+    pub fn balance(&self) -> u64 {
+        self.received.saturating_sub(self.sent)
+    }
+}
+
+/// Aggregated view of an address (script_pubkey)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AddressSummary {
+    /// Script identifying the address
+    pub script_pubkey: Vec<u8>,
+    /// Balances per asset id
+    pub balances: BTreeMap<[u8; 32], AssetBalance>,
+    /// Number of transactions touching the address
+    pub tx_count: u64,
+}
+
+/// Entry of an address history (native SLY amounts)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AddressTx {
+    /// Transaction hash
+    pub txid: [u8; 32],
+    /// Block height
+    pub height: u64,
+    /// Position of the transaction in the block
+    pub tx_index: u32,
+    /// SLY received by the address
+    pub received: u64,
+    /// SLY spent by the address
+    pub sent: u64,
+}
+
+/// Unspent output of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptUtxo {
+    /// Output location
+    pub outpoint: OutPoint,
+    /// The output itself
+    pub output: TxOutput,
+    /// Height of the block that created it
+    pub height: u64,
+}
+
+/// Supply statistics of an asset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AssetSupply {
+    /// Total value ever created in outputs
+    pub created: u64,
+    /// Total value consumed by inputs
+    pub spent: u64,
+    /// Number of unspent outputs
+    pub unspent_outputs: u64,
+}
+
+impl AssetSupply {
+    /// Value currently held in unspent outputs
+    pub fn circulating(&self) -> u64 {
+        self.created.saturating_sub(self.spent)
+    }
+}
+
+/// Per-block statistics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct BlockStats {
+    /// Block height
+    pub height: u64,
+    /// Block hash
+    pub hash: [u8; 32],
+    /// Block timestamp
+    pub timestamp: u64,
+    /// Number of transactions
+    pub tx_count: u64,
+    /// Number of inputs (coinbase excluded)
+    pub input_count: u64,
+    /// Number of outputs
+    pub output_count: u64,
+    /// Serialized size in bytes
+    pub size: u64,
+    /// Total SLY in outputs
+    pub total_output: u64,
+    /// Total SLY fees paid
+    pub total_fees: u64,
+}
+
+/// Coin-days destroyed by a block
+///
+/// Each native input contributes its value times the days elapsed between
+/// the block that created the output and the spending block. Stored apart
+/// from [`BlockStats`], so blocks indexed by an older version have none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CoinDaysDestroyed {
+    /// Block height
+    pub height: u64,
+    /// Block timestamp
+    pub timestamp: u64,
+    /// Coin-days destroyed, in satoshi-days
+    pub coin_days_destroyed: u64,
+    /// Native SLY spent by the inputs
+    pub value_spent: u64,
+}
+
+/// Usage of a validator script by state outputs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptStats {
+    /// Validator script
+    pub validator: Vec<u8>,
+    /// State outputs ever created
+    pub outputs_created: u64,
+    /// State outputs spent
+    pub outputs_spent: u64,
+    /// Datum bytes held by the unspent state outputs
+    pub live_datum_bytes: u64,
+    /// Largest datum ever created, in bytes
+    pub max_datum_size: u64,
+}
+
+impl ScriptStats {
+    /// Unspent state outputs referencing the script
+    pub fn live_outputs(&self) -> u64 {
+        self.outputs_created.saturating_sub(self.outputs_spent)
+    }
+}
+
+/// Datum shared by the unspent state outputs that carry it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredDatum {
+    /// Datum bytes
+    pub datum: Vec<u8>,
+    /// Unspent outputs carrying the datum
+    pub references: u64,
+}
+
+/// Explorer index backed by its own RocksDB instance
+pub struct ExplorerIndex {
+    db: DB,
+}
+
+impl ExplorerIndex {
+    /// Open or create the index database
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let cfs = COLUMN_FAMILIES
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
+
+        let db = DB::open_cf_descriptors(&opts, path, cfs)
+            .map_err(|e| IndexError::Database(e.to_string()))?;
+
+        let index = Self { db };
+        index.fill_script_utxos()?;
+        Ok(index)
+    }
+
+    /// List the unspent outputs by script hash in an index built before the list existed
+    fn fill_script_utxos(&self) -> Result<(), IndexError> {
+        if self.get::<bool>(CF_META, META_SCRIPT_UTXOS.as_bytes())?.is_some() {
+            return Ok(());
+        }
+        let mut batch = WriteBatch::default();
+        let mut filled = 0u64;
+        for item in self.db.iterator_cf(self.cf(CF_OUTPUTS)?, IteratorMode::Start) {
+            let (key, value) = item.map_err(|e| IndexError::Database(e.to_string()))?;
+            let indexed: IndexedOutput = bincode::deserialize(&value)
+                .map_err(|e| IndexError::Serialization(e.to_string()))?;
+            batch.put_cf(self.cf(CF_SCRIPT_UTXOS)?, script_utxo_key(&indexed.output.script_pubkey, &key), []);
+            filled += 1;
+        }
+        if filled > 0 {
+            log::info!("Listed {} unspent outputs by script hash", filled);
+        }
+        self.put(&mut batch, CF_META, META_SCRIPT_UTXOS.as_bytes(), &true)?;
+        self.db.write(batch)
+            .map_err(|e| IndexError::Database(e.to_string()))
+    }
+
+    fn cf(&self, name: &str) -> Result<&ColumnFamily, IndexError> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| IndexError::Database(format!("Column family not found: {}", name)))
+    }
+
+    fn get<T: for<'de> Deserialize<'de>>(&self, cf: &str, key: &[u8]) -> Result<Option<T>, IndexError> {
+        match self.db.get_cf(self.cf(cf)?, key) {
+            Ok(Some(bytes)) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| IndexError::Serialization(e.to_string())),
+            Ok(None) => Ok(None),
+            Err(e) => Err(IndexError::Database(e.to_string())),
+        }
+    }
+
+    fn put<T: Serialize>(&self, batch: &mut WriteBatch, cf: &str, key: &[u8], value: &T) -> Result<(), IndexError> {
+        let bytes = bincode::serialize(value)
+            .map_err(|e| IndexError::Serialization(e.to_string()))?;
+        batch.put_cf(self.cf(cf)?, key, bytes);
+        Ok(())
+    }
+
+    /// Last indexed block (height, hash), `None` if nothing was indexed yet
+    pub fn last_indexed(&self) -> Result<Option<(u64, [u8; 32])>, IndexError> {
+        let height: Option<u64> = self.get(CF_META, META_LAST_HEIGHT.as_bytes())?;
+        let hash: Option<[u8; 32]> = self.get(CF_META, META_LAST_HASH.as_bytes())?;
+        Ok(height.zip(hash))
+    }
+
+    /// Height of the next block to index
+    pub fn next_height(&self) -> Result<u64, IndexError> {
+        Ok(self.last_indexed()?.map(|(height, _)| height + 1).unwrap_or(0))
+    }
+
+    /// Index a block; blocks must be indexed in height order
+    pub fn index_block(&self, block: &Block) -> Result<(), IndexError> {
+        let height = block.header.height;
+        let block_hash = block.hash();
+
+        let previous = self.last_indexed()?;
+        match previous {
+            Some((last_height, last_hash))
+                if height != last_height + 1 || block.header.previous_hash != last_hash =>
+            {
+                return Err(IndexError::OutOfOrder { expected: last_height + 1, got: height });
+            }
+            Some(_) => {}
+            None if height != 0 => return Err(IndexError::OutOfOrder { expected: 0, got: height }),
+            None => {}
+        }
+
+        let mut batch = WriteBatch::default();
+        let mut created: HashMap<Vec<u8>, IndexedOutput> = HashMap::new();
+        let mut addresses: HashMap<[u8; 32], AddressSummary> = HashMap::new();
+        let mut assets: HashMap<[u8; 32], AssetSupply> = HashMap::new();
+        let mut scripts: HashMap<[u8; 32], ScriptStats> = HashMap::new();
+        let mut datums: HashMap<[u8; 32], StoredDatum> = HashMap::new();
+        let mut undo = BlockUndo { previous, ..BlockUndo::default() };
+
+        let mut stats = BlockStats {
+            height,
+            hash: block_hash,
+            timestamp: block.header.timestamp,
+            tx_count: block.transactions.len() as u64,
+            input_count: 0,
+            output_count: 0,
+            size: block.size().map_err(|e| IndexError::Serialization(e.to_string()))? as u64,
+            total_output: 0,
+            total_fees: 0,
+        };
+        let mut coin_days = 0u128;
+        let mut value_spent = 0u64;
+        let mut creation_times: HashMap<u64, u64> = HashMap::from([(height, block.header.timestamp)]);
+
+        for (tx_index, tx) in block.transactions.iter().enumerate() {
+            let txid = tx.hash();
+            let mut touched: BTreeMap<[u8; 32], AddressTx> = BTreeMap::new();
+            let mut native_in = 0u64;
+
+            // Spend inputs
+            if !tx.is_coinbase() {
+                for input in &tx.inputs {
+                    let key = outpoint_key(&input.previous_output);
+                    let spent = match created.remove(&key) {
+                        Some(spent) => spent,
+                        None => {
+                            let spent = self
+                                .get::<IndexedOutput>(CF_OUTPUTS, &key)?
+                                .ok_or(IndexError::MissingOutput { outpoint: input.previous_output.clone() })?;
+                            undo.spent.push((key.clone(), spent.clone()));
+                            spent
+                        }
+                    };
+                    batch.delete_cf(self.cf(CF_OUTPUTS)?, &key);
+                    batch.delete_cf(self.cf(CF_SCRIPT_UTXOS)?, script_utxo_key(&spent.output.script_pubkey, &key));
+                    stats.input_count += 1;
+
+                    let output = &spent.output;
+                    let script_hash = script_hash(&output.script_pubkey);
+                    let summary = self.load_address(&mut addresses, script_hash, &output.script_pubkey)?;
+                    summary.balances.entry(output.asset_id).or_default().sent += output.value.to_sat();
+
+                    let supply = self.load_asset(&mut assets, output.asset_id)?;
+                    supply.spent += output.value.to_sat();
+                    supply.unspent_outputs = supply.unspent_outputs.saturating_sub(1);
+
+                    if let Some(state) = StateScript::parse(&output.script_pubkey) {
+                        let stats = self.load_script(&mut scripts, state.validator)?;
+                        stats.outputs_spent += 1;
+                        stats.live_datum_bytes = stats.live_datum_bytes.saturating_sub(state.datum.len() as u64);
+                        let datum = self.load_datum(&mut datums, state.datum)?;
+                        datum.references = datum.references.saturating_sub(1);
+                    }
+
+                    if output.is_native_asset() {
+                        native_in += output.value.to_sat();
+                        value_spent += output.value.to_sat();
+                        let created_at = self.creation_time(&mut creation_times, spent.height)?;
+                        let age = block.header.timestamp.saturating_sub(created_at) as u128;
+                        coin_days += output.value.to_sat() as u128 * age / SECONDS_PER_DAY;
+                        history_entry(&mut touched, script_hash, txid, height, tx_index).sent += output.value.to_sat();
+                    } else {
+                        history_entry(&mut touched, script_hash, txid, height, tx_index);
+                    }
+                }
+            }
+
+            // Create outputs
+            let mut native_out = 0u64;
+            for (vout, output) in tx.outputs.iter().enumerate() {
+                let key = outpoint_key(&OutPoint::new(txid, vout as u32));
+                created.insert(key, IndexedOutput { output: output.clone(), height });
+                stats.output_count += 1;
+
+                let script_hash = script_hash(&output.script_pubkey);
+                let summary = self.load_address(&mut addresses, script_hash, &output.script_pubkey)?;
+                summary.balances.entry(output.asset_id).or_default().received += output.value.to_sat();
+
+                let supply = self.load_asset(&mut assets, output.asset_id)?;
+                supply.created += output.value.to_sat();
+                supply.unspent_outputs += 1;
+
+                if let Some(state) = StateScript::parse(&output.script_pubkey) {
+                    let stats = self.load_script(&mut scripts, state.validator)?;
+                    stats.outputs_created += 1;
+                    stats.live_datum_bytes += state.datum.len() as u64;
+                    stats.max_datum_size = stats.max_datum_size.max(state.datum.len() as u64);
+                    self.load_datum(&mut datums, state.datum)?.references += 1;
+                }
+
+                if output.is_native_asset() {
+                    native_out += output.value.to_sat();
+                    history_entry(&mut touched, script_hash, txid, height, tx_index).received += output.value.to_sat();
+                } else {
+                    history_entry(&mut touched, script_hash, txid, height, tx_index);
+                }
+            }
+
+            stats.total_output += native_out;
+            if !tx.is_coinbase() {
+                stats.total_fees += native_in.saturating_sub(native_out);
+            }
+
+            for (script_hash, entry) in touched {
+                if let Some(summary) = addresses.get_mut(&script_hash) {
+                    summary.tx_count += 1;
+                }
+                let key = history_key(&script_hash, height, tx_index as u32);
+                self.put(&mut batch, CF_ADDRESS_HISTORY, &key, &entry)?;
+                undo.history.push(key);
+            }
+        }
+
+        undo.created = created.iter().map(|(key, output)| (key.clone(), output.output.script_pubkey.clone())).collect();
+        undo.addresses = self.previous_values(CF_ADDRESSES, addresses.keys())?;
+        undo.assets = self.previous_values(CF_ASSETS, assets.keys())?;
+        undo.scripts = self.previous_values(CF_SCRIPTS, scripts.keys())?;
+        undo.datums = self.previous_values(CF_DATUMS, datums.keys())?;
+        self.put(&mut batch, CF_UNDO, &height.to_be_bytes(), &undo)?;
+        if let Some(expired) = height.checked_sub(UNDO_DEPTH) {
+            batch.delete_cf(self.cf(CF_UNDO)?, expired.to_be_bytes());
+        }
+
+        for (key, output) in &created {
+            self.put(&mut batch, CF_OUTPUTS, key, output)?;
+            batch.put_cf(self.cf(CF_SCRIPT_UTXOS)?, script_utxo_key(&output.output.script_pubkey, key), []);
+        }
+        for (script_hash, summary) in &addresses {
+            self.put(&mut batch, CF_ADDRESSES, script_hash, summary)?;
+        }
+        for (asset_id, supply) in &assets {
+            self.put(&mut batch, CF_ASSETS, asset_id, supply)?;
+        }
+        for (validator_hash, stats) in &scripts {
+            self.put(&mut batch, CF_SCRIPTS, validator_hash, stats)?;
+        }
+        for (datum_hash, datum) in &datums {
+            // Datum no longer referenced by any unspent output: prune it
+            if datum.references == 0 {
+                batch.delete_cf(self.cf(CF_DATUMS)?, datum_hash);
+            } else {
+                self.put(&mut batch, CF_DATUMS, datum_hash, datum)?;
+            }
+        }
+        self.put(&mut batch, CF_BLOCK_STATS, &height.to_be_bytes(), &stats)?;
+        self.put(&mut batch, CF_COIN_DAYS, &height.to_be_bytes(), &CoinDaysDestroyed {
+            height,
+            timestamp: block.header.timestamp,
+            coin_days_destroyed: u64::try_from(coin_days).unwrap_or(u64::MAX),
+            value_spent,
+        })?;
+        self.put(&mut batch, CF_META, META_LAST_HEIGHT.as_bytes(), &height)?;
+        self.put(&mut batch, CF_META, META_LAST_HASH.as_bytes(), &block_hash)?;
+
+        self.db.write(batch)
+            .map_err(|e| IndexError::Database(e.to_string()))
+    }
+
+    /// Stored values of `keys` in `cf`, before a block overwrites them
+    fn previous_values<'a, T: for<'de> Deserialize<'de>>(
+        &self,
+        cf: &str,
+        keys: impl Iterator<Item = &'a [u8; 32]>,
+    ) -> Result<Previous<T>, IndexError> {
+        keys.map(|key| Ok((*key, self.get(cf, key)?))).collect()
+    }
+
+    /// Roll back the last indexed block, returning the block indexed before it
+    ///
+    /// For blocks the node disconnected in a reorg. Only the last
+    /// [`UNDO_DEPTH`] blocks can be rolled back; past that (or for blocks
+    /// indexed before undo data existed) the error is
+    /// [`IndexError::MissingUndo`] and the index must be rebuilt.
+    pub fn undo_block(&self) -> Result<Option<(u64, [u8; 32])>, IndexError> {
+        let Some((height, _)) = self.last_indexed()? else {
+            return Ok(None);
+        };
+        let undo: BlockUndo = self.get(CF_UNDO, &height.to_be_bytes())?.ok_or(IndexError::MissingUndo { height })?;
+
+        let mut batch = WriteBatch::default();
+        for (key, script_pubkey) in &undo.created {
+            batch.delete_cf(self.cf(CF_OUTPUTS)?, key);
+            batch.delete_cf(self.cf(CF_SCRIPT_UTXOS)?, script_utxo_key(script_pubkey, key));
+        }
+        for (key, output) in &undo.spent {
+            self.put(&mut batch, CF_OUTPUTS, key, output)?;
+            batch.put_cf(self.cf(CF_SCRIPT_UTXOS)?, script_utxo_key(&output.output.script_pubkey, key), []);
+        }
+        for key in &undo.history {
+            batch.delete_cf(self.cf(CF_ADDRESS_HISTORY)?, key);
+        }
+        self.restore(&mut batch, CF_ADDRESSES, &undo.addresses)?;
+        self.restore(&mut batch, CF_ASSETS, &undo.assets)?;
+        self.restore(&mut batch, CF_SCRIPTS, &undo.scripts)?;
+        self.restore(&mut batch, CF_DATUMS, &undo.datums)?;
+        for cf in [CF_BLOCK_STATS, CF_COIN_DAYS, CF_UNDO] {
+            batch.delete_cf(self.cf(cf)?, height.to_be_bytes());
+        }
+        match undo.previous {
+            Some((previous_height, previous_hash)) => {
+                self.put(&mut batch, CF_META, META_LAST_HEIGHT.as_bytes(), &previous_height)?;
+                self.put(&mut batch, CF_META, META_LAST_HASH.as_bytes(), &previous_hash)?;
+            }
+            None => {
+                batch.delete_cf(self.cf(CF_META)?, META_LAST_HEIGHT.as_bytes());
+                batch.delete_cf(self.cf(CF_META)?, META_LAST_HASH.as_bytes());
+            }
+        }
+
+        self.db.write(batch)
+            .map_err(|e| IndexError::Database(e.to_string()))?;
+        Ok(undo.previous)
+    }
+
+    /// Put back the values saved by [`ExplorerIndex::previous_values`]
+    fn restore<T: Serialize>(
+        &self,
+        batch: &mut WriteBatch,
+        cf: &str,
+        values: &Previous<T>,
+    ) -> Result<(), IndexError> {
+        for (key, value) in values {
+            match value {
+                Some(value) => self.put(batch, cf, key, value)?,
+                None => batch.delete_cf(self.cf(cf)?, key),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove everything indexed, to index the chain again from genesis
+    pub fn clear(&self) -> Result<(), IndexError> {
+        let mut batch = WriteBatch::default();
+        for name in COLUMN_FAMILIES {
+            let cf = self.cf(name)?;
+            for item in self.db.iterator_cf(cf, IteratorMode::Start) {
+                let (key, _) = item.map_err(|e| IndexError::Database(e.to_string()))?;
+                batch.delete_cf(cf, key);
+            }
+        }
+        // An empty index has nothing to list by script hash
+        self.put(&mut batch, CF_META, META_SCRIPT_UTXOS.as_bytes(), &true)?;
+        self.db.write(batch)
+            .map_err(|e| IndexError::Database(e.to_string()))
+    }
+
+    /// Timestamp of the indexed block at `height`
+    fn creation_time(&self, cache: &mut HashMap<u64, u64>, height: u64) -> Result<u64, IndexError> {
+        if let Some(timestamp) = cache.get(&height) {
+            return Ok(*timestamp);
+        }
+        let stats = self.get_block_stats(height)?.ok_or(IndexError::MissingBlockStats { height })?;
+        cache.insert(height, stats.timestamp);
+        Ok(stats.timestamp)
+    }
+
+    fn load_address<'a>(
+        &self,
+        cache: &'a mut HashMap<[u8; 32], AddressSummary>,
+        script_hash: [u8; 32],
+        script_pubkey: &[u8],
+    ) -> Result<&'a mut AddressSummary, IndexError> {
+        match cache.entry(script_hash) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let summary = self.get(CF_ADDRESSES, &script_hash)?.unwrap_or_else(|| AddressSummary {
+                    script_pubkey: script_pubkey.to_vec(),
+                    ..AddressSummary::default()
+                });
+                Ok(entry.insert(summary))
+            }
+        }
+    }
+
+    fn load_asset<'a>(
+        &self,
+        cache: &'a mut HashMap<[u8; 32], AssetSupply>,
+        asset_id: [u8; 32],
+    ) -> Result<&'a mut AssetSupply, IndexError> {
+        match cache.entry(asset_id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let supply = self.get(CF_ASSETS, &asset_id)?.unwrap_or_default();
+                Ok(entry.insert(supply))
+            }
+        }
+    }
+
+    fn load_script<'a>(
+        &self,
+        cache: &'a mut HashMap<[u8; 32], ScriptStats>,
+        validator: &[u8],
+    ) -> Result<&'a mut ScriptStats, IndexError> {
+        match cache.entry(script_hash(validator)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let stats = self.get(CF_SCRIPTS, entry.key())?.unwrap_or_else(|| ScriptStats {
+                    validator: validator.to_vec(),
+                    ..ScriptStats::default()
+                });
+                Ok(entry.insert(stats))
+            }
+        }
+    }
+
+    fn load_datum<'a>(
+        &self,
+        cache: &'a mut HashMap<[u8; 32], StoredDatum>,
+        datum: &[u8],
+    ) -> Result<&'a mut StoredDatum, IndexError> {
+        match cache.entry(script_hash(datum)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let stored = self.get(CF_DATUMS, entry.key())?.unwrap_or_else(|| StoredDatum {
+                    datum: datum.to_vec(),
+                    references: 0,
+                });
+                Ok(entry.insert(stored))
+            }
+        }
+    }
+
+    /// Address summary by script_pubkey
+    pub fn get_address(&self, script_pubkey: &[u8]) -> Result<Option<AddressSummary>, IndexError> {
+        self.get_address_by_hash(&script_hash(script_pubkey))
+    }
+
+    /// Address summary by [`script_hash`]
+    pub fn get_address_by_hash(&self, script_hash: &[u8; 32]) -> Result<Option<AddressSummary>, IndexError> {
+        self.get(CF_ADDRESSES, script_hash)
+    }
+
+    /// Unspent outputs of a script by its [`script_hash`], in outpoint order
+    pub fn get_script_unspent(&self, script_hash: &[u8; 32]) -> Result<Vec<ScriptUtxo>, IndexError> {
+        let mut unspent = Vec::new();
+        let mode = IteratorMode::From(&script_hash[..], Direction::Forward);
+        for item in self.db.iterator_cf(self.cf(CF_SCRIPT_UTXOS)?, mode) {
+            let (key, _) = item.map_err(|e| IndexError::Database(e.to_string()))?;
+            if !key.starts_with(script_hash) {
+                break;
+            }
+            let txid: [u8; 32] = key[32..64].try_into().unwrap_or_default();
+            let vout = u32::from_be_bytes(key[64..].try_into().unwrap_or_default());
+            let outpoint = OutPoint::new(txid, vout);
+            let indexed: IndexedOutput = self.get(CF_OUTPUTS, &key[32..])?
+                .ok_or_else(|| IndexError::MissingOutput { outpoint: outpoint.clone() })?;
+            unspent.push(ScriptUtxo { outpoint, output: indexed.output, height: indexed.height });
+        }
+        Ok(unspent)
+    }
+
+    /// Most recent history entries of an address, newest first
+    pub fn get_address_history(&self, script_pubkey: &[u8], limit: usize) -> Result<Vec<AddressTx>, IndexError> {
+        self.get_address_history_before(script_pubkey, None, limit)
+    }
+
+    /// History entries of an address older than `before`, newest first
+    ///
+    /// `before` is the `(height, tx_index)` of the last entry of the previous
+    /// page; `None` starts from the most recent entry.
+    pub fn get_address_history_before(
+        &self,
+        script_pubkey: &[u8],
+        before: Option<(u64, u32)>,
+        limit: usize,
+    ) -> Result<Vec<AddressTx>, IndexError> {
+        let prefix = script_hash(script_pubkey);
+        let seek = match before {
+            Some((height, tx_index)) => history_key(&prefix, height, tx_index),
+            None => {
+                let mut seek = prefix.to_vec();
+                seek.extend_from_slice(&[0xff; 12]);
+                seek
+            }
+        };
+
+        let mut entries = Vec::new();
+        for item in self.db.iterator_cf(self.cf(CF_ADDRESS_HISTORY)?, IteratorMode::From(&seek, Direction::Reverse)) {
+            let (key, value) = item.map_err(|e| IndexError::Database(e.to_string()))?;
+            if !key.starts_with(&prefix) || entries.len() >= limit {
+                break;
+            }
+            if before.is_some() && *key == *seek {
+                continue;
+            }
+            entries.push(bincode::deserialize(&value)
+                .map_err(|e| IndexError::Serialization(e.to_string()))?);
+        }
+        Ok(entries)
+    }
+
+    /// History entries of an address from `from_height` to `to_height` (inclusive), oldest first
+    pub fn get_address_history_range(
+        &self,
+        script_pubkey: &[u8],
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<AddressTx>, IndexError> {
+        self.get_script_history_range(&script_hash(script_pubkey), from_height, to_height)
+    }
+
+    /// History entries of a script by its [`script_hash`] from `from_height` to `to_height`
+    /// (inclusive), oldest first
+    pub fn get_script_history_range(
+        &self,
+        script_hash: &[u8; 32],
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<AddressTx>, IndexError> {
+        let seek = history_key(script_hash, from_height, 0);
+        let end = history_key(script_hash, to_height, u32::MAX);
+
+        let mut entries = Vec::new();
+        for item in self.db.iterator_cf(self.cf(CF_ADDRESS_HISTORY)?, IteratorMode::From(&seek, Direction::Forward)) {
+            let (key, value) = item.map_err(|e| IndexError::Database(e.to_string()))?;
+            if *key > *end {
+                break;
+            }
+            entries.push(bincode::deserialize(&value)
+                .map_err(|e| IndexError::Serialization(e.to_string()))?);
+        }
+        Ok(entries)
+    }
+
+    /// Supply statistics of an asset
+    pub fn get_asset(&self, asset_id: &[u8; 32]) -> Result<Option<AssetSupply>, IndexError> {
+        self.get(CF_ASSETS, asset_id)
+    }
+
+    /// Statistics of an indexed block
+    pub fn get_block_stats(&self, height: u64) -> Result<Option<BlockStats>, IndexError> {
+        self.get(CF_BLOCK_STATS, &height.to_be_bytes())
+    }
+
+    /// Coin-days destroyed by an indexed block
+    pub fn get_coin_days_destroyed(&self, height: u64) -> Result<Option<CoinDaysDestroyed>, IndexError> {
+        self.get(CF_COIN_DAYS, &height.to_be_bytes())
+    }
+
+    /// Usage statistics of a validator script by its hash
+    pub fn get_script_stats(&self, validator_hash: &[u8; 32]) -> Result<Option<ScriptStats>, IndexError> {
+        self.get(CF_SCRIPTS, validator_hash)
+    }
+
+    /// Datum by its hash, None once no unspent output carries it
+    pub fn get_datum(&self, datum_hash: &[u8; 32]) -> Result<Option<StoredDatum>, IndexError> {
+        self.get(CF_DATUMS, datum_hash)
+    }
+}
+
+/// Electrum-style script hash used as address key
+pub fn script_hash(script_pubkey: &[u8]) -> [u8; 32] {
+    Sha256::digest(script_pubkey).into()
+}
+
+fn outpoint_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = Vec::with_capacity(36);
+    key.extend_from_slice(&outpoint.txid);
+    key.extend_from_slice(&outpoint.vout.to_be_bytes());
+    key
+}
+
+fn script_utxo_key(script_pubkey: &[u8], outpoint_key: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(68);
+    key.extend_from_slice(&script_hash(script_pubkey));
+    key.extend_from_slice(outpoint_key);
+    key
+}
+
+fn history_key(script_hash: &[u8; 32], height: u64, tx_index: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(44);
+    key.extend_from_slice(script_hash);
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(&tx_index.to_be_bytes());
+    key
+}
+
+fn history_entry(
+    touched: &mut BTreeMap<[u8; 32], AddressTx>,
+    script_hash: [u8; 32],
+    txid: [u8; 32],
+    height: u64,
+    tx_index: usize,
+) -> &mut AddressTx {
+    touched.entry(script_hash).or_insert(AddressTx {
+        txid,
+        height,
+        tx_index: tx_index as u32,
+        received: 0,
+        sent: 0,
+    })
+}
+
+/// Indexer errors
+#[derive(Debug, thiserror::Error)]
+pub enum IndexError {
+    #[error("Index database error: {0}")]
+    Database(String),
+
+    #[error("Chain database error: {0}")]
+    Chain(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    #[error("Spent output not found in index: {outpoint:?}")]
+    MissingOutput { outpoint: OutPoint },
+
+    #[error("Statistics of block {height} not found in index")]
+    MissingBlockStats { height: u64 },
+
+    #[error("Blocks indexed out of order: expected height {expected}, got {got}")]
+    OutOfOrder { expected: u64, got: u64 },
+
+    #[error("No undo data for indexed block {height}")]
+    MissingUndo { height: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Transaction, TxInput};
+    use tempfile::TempDir;
+
+    fn spend(prev: &Transaction, vout: u32, outputs: Vec<TxOutput>) -> Transaction {
+        Transaction::new(
+            vec![TxInput::new(OutPoint::new(prev.hash(), vout), vec![1])],
+            outputs,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_balances_history_and_fees() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5_000);
+        let block0 = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        index.index_block(&block0).unwrap();
+
+        let payment = spend(&coinbase, 0, vec![
+            TxOutput::to_address(3_000, b"bob"),
+            TxOutput::to_address(1_500, b"alice"),
+        ]);
+        let coinbase1 = Transaction::coinbase(b"alice", 1, 5_000);
+        let block1 = Block::new(block0.hash(), vec![coinbase1, payment.clone()], 0x1d00ffff, 1);
+        index.index_block(&block1).unwrap();
+
+        let alice = index.get_address(b"alice").unwrap().unwrap();
+        assert_eq!(alice.balances[&[0; 32]].balance(), 5_000 + 1_500);
+        assert_eq!(alice.tx_count, 3);
+
+        let bob = index.get_address(b"bob").unwrap().unwrap();
+        assert_eq!(bob.balances[&[0; 32]].balance(), 3_000);
+
+        // The spent coinbase is no longer among the unspent outputs of alice
+        let unspent = index.get_script_unspent(&script_hash(b"alice")).unwrap();
+        assert_eq!(unspent.len(), 2);
+        assert!(unspent.iter().all(|utxo| utxo.height == 1 && utxo.output.script_pubkey == b"alice"));
+        assert!(unspent.iter().any(|utxo| utxo.outpoint == OutPoint::new(payment.hash(), 1)));
+        assert_eq!(index.get_script_unspent(&script_hash(b"bob")).unwrap()[0].output.value.to_sat(), 3_000);
+
+        let history = index.get_address_history(b"alice", 10).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].height, 1);
+        assert_eq!(history.iter().find(|entry| entry.txid == payment.hash()).unwrap().sent, 5_000);
+
+        // Next page, starting after the last entry returned
+        let first = index.get_address_history(b"alice", 2).unwrap();
+        let last = first.last().unwrap();
+        let rest = index.get_address_history_before(b"alice", Some((last.height, last.tx_index)), 10).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0], history[2]);
+
+        let stats = index.get_block_stats(1).unwrap().unwrap();
+        assert_eq!(stats.tx_count, 2);
+        assert_eq!(stats.total_fees, 500);
+
+        let supply = index.get_asset(&[0; 32]).unwrap().unwrap();
+        assert_eq!(supply.circulating(), 10_000 - 500);
+        assert_eq!(supply.unspent_outputs, 3);
+    }
+
+    #[test]
+    fn test_undo_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5_000);
+        let block0 = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        index.index_block(&block0).unwrap();
+        let alice = index.get_address(b"alice").unwrap().unwrap();
+        let unspent = index.get_script_unspent(&script_hash(b"alice")).unwrap();
+        let supply = index.get_asset(&[0; 32]).unwrap().unwrap();
+
+        let payment = spend(&coinbase, 0, vec![TxOutput::to_address(4_000, b"bob")]);
+        let block1 = Block::new(
+            block0.hash(),
+            vec![Transaction::coinbase(b"alice", 1, 5_000), payment],
+            0x1d00ffff,
+            1,
+        );
+        index.index_block(&block1).unwrap();
+
+        // Block 1 leaves the chain: the index goes back to the state of block 0
+        assert_eq!(index.undo_block().unwrap(), Some((0, block0.hash())));
+        assert_eq!(index.last_indexed().unwrap(), Some((0, block0.hash())));
+        assert_eq!(index.get_address(b"alice").unwrap().unwrap().balances, alice.balances);
+        assert!(index.get_address(b"bob").unwrap().is_none());
+        assert_eq!(index.get_script_unspent(&script_hash(b"alice")).unwrap(), unspent);
+        assert!(index.get_script_unspent(&script_hash(b"bob")).unwrap().is_empty());
+        assert_eq!(index.get_address_history(b"alice", 10).unwrap().len(), 1);
+        assert_eq!(index.get_asset(&[0; 32]).unwrap().unwrap(), supply);
+        assert_eq!(index.get_block_stats(1).unwrap(), None);
+
+        // The coinbase can be spent again by a competing block
+        index.index_block(&block1).unwrap();
+        assert_eq!(index.get_address(b"bob").unwrap().unwrap().balances[&[0; 32]].balance(), 4_000);
+
+        assert_eq!(index.undo_block().unwrap(), Some((0, block0.hash())));
+        assert_eq!(index.undo_block().unwrap(), None);
+        assert_eq!(index.last_indexed().unwrap(), None);
+        assert!(index.get_address(b"alice").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_coin_days_destroyed() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5_000);
+        let mut block0 = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        block0.header.timestamp = 1_000;
+        index.index_block(&block0).unwrap();
+
+        // Spent after 3 days, and the change spent in the same block
+        let payment = spend(&coinbase, 0, vec![TxOutput::to_address(4_000, b"bob")]);
+        let chained = spend(&payment, 0, vec![TxOutput::to_address(3_900, b"carol")]);
+        let mut block1 = Block::new(
+            block0.hash(),
+            vec![Transaction::coinbase(b"alice", 1, 5_000), payment, chained],
+            0x1d00ffff,
+            1,
+        );
+        block1.header.timestamp = 1_000 + 3 * 86_400;
+        index.index_block(&block1).unwrap();
+
+        assert_eq!(index.get_coin_days_destroyed(0).unwrap().unwrap().coin_days_destroyed, 0);
+        let cdd = index.get_coin_days_destroyed(1).unwrap().unwrap();
+        assert_eq!(cdd.coin_days_destroyed, 5_000 * 3);
+        assert_eq!(cdd.value_spent, 9_000);
+        assert_eq!(index.get_coin_days_destroyed(2).unwrap(), None);
+    }
+
+    #[test]
+    fn test_script_stats_and_datum_pruning() {
+        use sedly_core::state::{continuation, state_script};
+
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+
+        let coinbase = Transaction::coinbase(b"alice", 0, 5_000);
+        let block0 = Block::new([0; 32], vec![coinbase.clone()], 0x1d00ffff, 0);
+        index.index_block(&block0).unwrap();
+
+        // Two state outputs with the same datum, then one moves on with a new datum
+        let state = TxOutput::new(2_000, [0; 32], state_script(b"shared", b"validator").unwrap());
+        let open = spend(&coinbase, 0, vec![state.clone(), state.clone()]);
+        let coinbase1 = Transaction::coinbase(b"alice", 1, 50);
+        let block1 = Block::new(block0.hash(), vec![coinbase1, open.clone()], 0x1d00ffff, 1);
+        index.index_block(&block1).unwrap();
+
+        let validator_hash = script_hash(b"validator");
+        let datum_hash = script_hash(b"shared");
+        assert_eq!(index.get_datum(&datum_hash).unwrap().unwrap().references, 2);
+
+        let step = spend(&open, 0, vec![continuation(&state, b"next state").unwrap()]);
+        let close = spend(&open, 1, vec![TxOutput::to_address(1_900, b"bob")]);
+        let block2 = Block::new(
+            block1.hash(),
+            vec![Transaction::coinbase(b"alice", 2, 50), step, close],
+            0x1d00ffff,
+            2,
+        );
+        index.index_block(&block2).unwrap();
+
+        let stats = index.get_script_stats(&validator_hash).unwrap().unwrap();
+        assert_eq!(stats.validator, b"validator");
+        assert_eq!((stats.outputs_created, stats.outputs_spent, stats.live_outputs()), (3, 2, 1));
+        assert_eq!((stats.live_datum_bytes, stats.max_datum_size), (10, 10));
+
+        // The shared datum is no longer referenced: pruned
+        assert_eq!(index.get_datum(&datum_hash).unwrap(), None);
+        let next = index.get_datum(&script_hash(b"next state")).unwrap().unwrap();
+        assert_eq!((next.datum.as_slice(), next.references), (&b"next state"[..], 1));
+    }
+
+    #[test]
+    fn test_script_utxos_filled_on_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+        let block = Block::new([0; 32], vec![Transaction::coinbase(b"alice", 0, 5_000)], 0x1d00ffff, 0);
+        index.index_block(&block).unwrap();
+        let unspent = index.get_script_unspent(&script_hash(b"alice")).unwrap();
+
+        // Index built before the list by script hash
+        let mut batch = WriteBatch::default();
+        let key = script_utxo_key(b"alice", &outpoint_key(&unspent[0].outpoint));
+        batch.delete_cf(index.cf(CF_SCRIPT_UTXOS).unwrap(), key);
+        batch.delete_cf(index.cf(CF_META).unwrap(), META_SCRIPT_UTXOS.as_bytes());
+        index.db.write(batch).unwrap();
+        assert!(index.get_script_unspent(&script_hash(b"alice")).unwrap().is_empty());
+        drop(index);
+
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+        assert_eq!(index.get_script_unspent(&script_hash(b"alice")).unwrap(), unspent);
+    }
+
+    #[test]
+    fn test_out_of_order_block_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = ExplorerIndex::open(temp_dir.path()).unwrap();
+
+        let block = Block::new([0; 32], vec![Transaction::coinbase(b"alice", 5, 50)], 0x1d00ffff, 5);
+        assert!(matches!(index.index_block(&block), Err(IndexError::OutOfOrder { expected: 0, got: 5 })));
+    }
+}
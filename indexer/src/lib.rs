@@ -0,0 +1,17 @@
+//! Sedly Indexer - explorer indexes built from the node database
+
+pub mod api;
+pub mod client;
+pub mod electrum;
+pub mod events;
+pub mod index;
+pub mod tailer;
+
+pub use client::{ClientError, ExplorerClient};
+pub use electrum::{ElectrumError, ElectrumServer};
+pub use events::{ChainEvent, EventBus};
+pub use index::{
+    AddressSummary, AddressTx, AssetSupply, BlockStats, CoinDaysDestroyed, ExplorerIndex, IndexError, ScriptStats,
+    ScriptUtxo, StoredDatum,
+};
+pub use tailer::ChainTailer;
@@ -0,0 +1,78 @@
+//! sedly-indexer: block explorer indexer and REST API
+
+use clap::Parser;
+use sedly_core::BlockchainDB;
+use sedly_indexer::{api, ChainTailer, ElectrumServer, EventBus, ExplorerIndex};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Explorer indexer running alongside a Sedly node
+#[derive(Debug, Parser)]
+#[command(name = "sedly-indexer", version)]
+struct Args {
+    /// Data directory of the node database
+    #[arg(long, default_value = "./blockchain_data")]
+    chain_db: PathBuf,
+    /// Directory of the explorer index database
+    #[arg(long, default_value = "./explorer_index")]
+    index_db: PathBuf,
+    /// REST API bind address
+    #[arg(long, default_value = "127.0.0.1:3001")]
+    bind: String,
+    /// Seconds between polls of the node database
+    #[arg(long, default_value_t = 2)]
+    poll_secs: u64,
+    /// Electrum protocol bind address (disabled if omitted)
+    #[arg(long)]
+    electrum_bind: Option<String>,
+    /// Tendermint RPC endpoint receiving transactions broadcast by Electrum wallets
+    #[arg(long)]
+    tendermint_rpc: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let secondary_path = args.index_db.join("chain_secondary");
+    let chain = Arc::new(BlockchainDB::open_secondary(&args.chain_db, &secondary_path)?);
+    let index = Arc::new(ExplorerIndex::open(args.index_db.join("index"))?);
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let bus = EventBus::new();
+    let tailer = ChainTailer::new(Arc::clone(&chain), Arc::clone(&index)).with_events(bus.clone());
+    let poll_interval = Duration::from_secs(args.poll_secs);
+    let tailer_shutdown = Arc::clone(&shutdown);
+    let tailer_handle = std::thread::spawn(move || tailer.run(poll_interval, tailer_shutdown));
+
+    if let Some(bind) = &args.electrum_bind {
+        let mut electrum = ElectrumServer::new(Arc::clone(&chain), Arc::clone(&index), bus.clone());
+        if let Some(url) = &args.tendermint_rpc {
+            electrum = electrum.with_broadcast_url(url);
+        }
+        let listener = tokio::net::TcpListener::bind(bind).await?;
+        log::info!("Electrum protocol listening on {}", bind);
+        tokio::spawn(Arc::new(electrum).serve(listener));
+    }
+
+    let listener = tokio::net::TcpListener::bind(&args.bind).await?;
+    log::info!("Explorer API listening on {}", args.bind);
+
+    let events = api::EventSource { chain, index: Arc::clone(&index), bus };
+    axum::serve(listener, api::router(index).merge(api::events_router(events)))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    shutdown.store(true, Ordering::Relaxed);
+    match tailer_handle.join() {
+        Ok(result) => result?,
+        Err(_) => anyhow::bail!("Indexer thread panicked"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Follows the node database and feeds new blocks into the explorer index
+
+use crate::events::{block_events, EventBus};
+use crate::index::{ExplorerIndex, IndexError};
+use sedly_core::BlockchainDB;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Tails a node database opened as a RocksDB secondary instance
+pub struct ChainTailer {
+    /// Node database (read-only secondary)
+    chain: Arc<BlockchainDB>,
+    /// Explorer index being populated
+    index: Arc<ExplorerIndex>,
+    /// Bus receiving the events of each indexed block
+    events: Option<EventBus>,
+}
+
+impl ChainTailer {
+    /// Create new tailer
+    pub fn new(chain: Arc<BlockchainDB>, index: Arc<ExplorerIndex>) -> Self {
+        Self { chain, index, events: None }
+    }
+
+    /// Publish the events of each indexed block on `events`
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Index every block the node stored since the last call
+    ///
+    /// Returns the number of blocks indexed.
+    pub fn sync(&self) -> Result<u64, IndexError> {
+        self.chain.catch_up_with_primary()
+            .map_err(|e| IndexError::Chain(e.to_string()))?;
+
+        // Roll back indexed blocks the node disconnected in a reorg
+        while let Some((height, hash)) = self.index.last_indexed()? {
+            let block = self.chain.get_block_by_height(height)
+                .map_err(|e| IndexError::Chain(e.to_string()))?;
+            if block.map(|block| block.hash()) == Some(hash) {
+                break;
+            }
+            log::warn!("Block {} at height {} left the chain, rolling it back", hex::encode(hash), height);
+            match self.index.undo_block() {
+                Ok(_) => {}
+                Err(IndexError::MissingUndo { height }) => {
+                    log::warn!("Reorg below the undo data (height {}), rebuilding the index", height);
+                    self.index.clear()?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let tip = self.chain.get_height()
+            .map_err(|e| IndexError::Chain(e.to_string()))?;
+        let mut height = self.index.next_height()?;
+        let mut indexed = 0;
+
+        while height <= tip {
+            let block = match self.chain.get_block_by_height(height)
+                .map_err(|e| IndexError::Chain(e.to_string()))?
+            {
+                Some(block) => block,
+                None => break,
+            };
+
+            self.index.index_block(&block)?;
+            if let Some(events) = &self.events {
+                events.publish(block_events(&self.chain, &block)?);
+            }
+            indexed += 1;
+            height += 1;
+
+            if indexed % 1000 == 0 {
+                log::info!("Indexed up to height {} (tip {})", height - 1, tip);
+            }
+        }
+
+        Ok(indexed)
+    }
+
+    /// Poll the node database until `shutdown` is set
+    ///
+    /// A failed sync is logged and retried at the next poll.
+    pub fn run(&self, poll_interval: Duration, shutdown: Arc<AtomicBool>) -> Result<(), IndexError> {
+        while !shutdown.load(Ordering::Relaxed) {
+            match self.sync() {
+                Ok(0) => {}
+                Ok(indexed) => log::info!("Indexed {} new blocks", indexed),
+                Err(e) => log::error!("Index sync failed: {}", e),
+            }
+            std::thread::sleep(poll_interval);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Block, Transaction};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tailer_follows_primary() {
+        let chain_dir = TempDir::new().unwrap();
+        let secondary_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+
+        let primary = BlockchainDB::open(chain_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        primary.initialize_with_genesis(&genesis).unwrap();
+
+        let secondary = BlockchainDB::open_secondary(chain_dir.path(), secondary_dir.path()).unwrap();
+        let index = Arc::new(ExplorerIndex::open(index_dir.path()).unwrap());
+        let tailer = ChainTailer::new(Arc::new(secondary), Arc::clone(&index));
+
+        assert_eq!(tailer.sync().unwrap(), 1);
+        assert_eq!(tailer.sync().unwrap(), 0);
+
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 50)], 0x1d00ffff, 1);
+        primary.store_block(&block).unwrap();
+
+        assert_eq!(tailer.sync().unwrap(), 1);
+        assert_eq!(index.last_indexed().unwrap(), Some((1, block.hash())));
+        assert_eq!(index.get_address(b"miner").unwrap().unwrap().balances[&[0; 32]].balance(), 50);
+    }
+
+    #[test]
+    fn test_tailer_follows_reorg() {
+        let chain_dir = TempDir::new().unwrap();
+        let secondary_dir = TempDir::new().unwrap();
+        let index_dir = TempDir::new().unwrap();
+
+        let primary = BlockchainDB::open(chain_dir.path()).unwrap();
+        let genesis = Block::genesis();
+        primary.initialize_with_genesis(&genesis).unwrap();
+        let block = Block::new(genesis.hash(), vec![Transaction::coinbase(b"miner", 1, 50)], 0x1d00ffff, 1);
+        primary.store_block(&block).unwrap();
+
+        let secondary = BlockchainDB::open_secondary(chain_dir.path(), secondary_dir.path()).unwrap();
+        let index = Arc::new(ExplorerIndex::open(index_dir.path()).unwrap());
+        let tailer = ChainTailer::new(Arc::new(secondary), Arc::clone(&index));
+        assert_eq!(tailer.sync().unwrap(), 2);
+
+        // The node replaces block 1 with a competing one
+        primary.disconnect_tip().unwrap();
+        let competing = Block::new(genesis.hash(), vec![Transaction::coinbase(b"other", 1, 50)], 0x1d00ffff, 1);
+        primary.store_block(&competing).unwrap();
+
+        assert_eq!(tailer.sync().unwrap(), 1);
+        assert_eq!(index.last_indexed().unwrap(), Some((1, competing.hash())));
+        assert!(index.get_address(b"miner").unwrap().is_none());
+        assert_eq!(index.get_address(b"other").unwrap().unwrap().balances[&[0; 32]].balance(), 50);
+    }
+}
@@ -0,0 +1,97 @@
+//! miner: external proof of work miner for a Sedly node
+//!
+//! Fetches block templates with `getblocktemplate`, hashes them on all
+//! cores and hands solved blocks to `submitblock`. A long poll on the
+//! current template interrupts the round as soon as the node has a newer
+//! one (new tip or more fees), so no time is spent on stale work.
+
+use clap::Parser;
+use sedly_core::mining::MiningError;
+use sedly_core::{ChainParams, Miner, Network, PowKind};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod pool;
+mod worker;
+
+/// Wait before asking again for a template after an RPC error
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// External miner
+#[derive(Debug, Parser)]
+#[command(name = "miner", version)]
+struct Args {
+    /// RPC server address of the node
+    #[arg(long, default_value = "127.0.0.1:8545")]
+    rpc_addr: String,
+    /// Network of the node (mainnet, testnet, regtest)
+    #[arg(long, default_value = "mainnet")]
+    network: Network,
+    /// Proof of work algorithm switch of the node (as its --pow-algorithm)
+    #[arg(long)]
+    pow_algorithm: Option<PowKind>,
+    /// First height mined with --pow-algorithm
+    #[arg(long, default_value_t = 0)]
+    pow_activation_height: u64,
+    /// Hex script the coinbase pays to
+    #[arg(long)]
+    payout: String,
+    /// Hashing threads (default: one per core)
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Seconds spent on a template before rebuilding it
+    #[arg(long, default_value_t = 30)]
+    refresh: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let mut params = ChainParams::for_network(args.network);
+    if let Some(algorithm) = args.pow_algorithm {
+        params = params.with_pow_algorithm(args.pow_activation_height, algorithm);
+    }
+    let payout = hex::decode(&args.payout).map_err(|e| anyhow::anyhow!("Invalid --payout script: {}", e))?;
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let pool = pool::Pool::new(&args.rpc_addr);
+    let mut miner = Miner::new([0; 32], threads);
+    log::info!("Mining for {} to {} with {} threads", args.rpc_addr, args.payout, threads);
+
+    for extra_nonce in 0u64.. {
+        let info = match pool.template(None) {
+            Ok(info) => info,
+            Err(e) => {
+                log::error!("Cannot get a block template: {}", e);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        let template = match worker::build_template(&info, &payout, extra_nonce) {
+            Ok(template) => template,
+            Err(e) => {
+                log::error!("Invalid block template at height {}: {:#}", info.height, e);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        miner.algorithm = params.pow_algorithm(info.height);
+        miner.should_stop = Arc::new(AtomicBool::new(false));
+        pool.watch(info.longpollid.clone(), miner.should_stop.clone());
+
+        match miner.mine_template(&template, Duration::from_secs(args.refresh)) {
+            Ok(result) => {
+                let hash = result.block.block_hash();
+                match pool.submit(&result.block) {
+                    Ok(None) => log::info!("Block {} at height {} accepted", hash, info.height),
+                    Ok(Some(reason)) => log::warn!("Block {} at height {} refused: {}", hash, info.height, reason),
+                    Err(e) => log::error!("Cannot submit block {}: {}", hash, e),
+                }
+            }
+            Err(MiningError::Stopped) => log::debug!("Template at height {} replaced", info.height),
+            Err(MiningError::Timeout) => {}
+            Err(e) => log::error!("Mining failed: {}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Block templates and submissions over the node's JSON-RPC server
+
+use sedly_core::Block;
+use sedly_rpc::handlers::BlockTemplateInfo;
+use sedly_rpc::{ClientError, RpcClient};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Node the miner gets its work from
+#[derive(Debug, Clone)]
+pub struct Pool {
+    client: RpcClient,
+}
+
+impl Pool {
+    /// Pool served by the RPC server at `rpc_addr`
+    pub fn new(rpc_addr: &str) -> Self {
+        Self { client: RpcClient::new(rpc_addr) }
+    }
+
+    /// Current template, or with `longpollid` the first template newer than that one
+    pub fn template(&self, longpollid: Option<&str>) -> Result<BlockTemplateInfo, ClientError> {
+        let params = match longpollid {
+            Some(longpollid) => json!({ "longpollid": longpollid }),
+            None => Value::Null,
+        };
+        Ok(serde_json::from_value(self.client.call("getblocktemplate", params)?)?)
+    }
+
+    /// Submit a mined block: None when the node accepted it, else its reason
+    pub fn submit(&self, block: &Block) -> Result<Option<String>, ClientError> {
+        let data = bincode::serialize(block).map_err(|e| ClientError::Http(e.to_string()))?;
+        let result = self.client.call("submitblock", json!([hex::encode(data)]))?;
+        Ok(result.as_str().map(str::to_string))
+    }
+
+    /// Set `stop` once the node replaces the template `longpollid`
+    ///
+    /// Runs a single long poll in the background. Each round gets its own
+    /// flag, so a poll that outlives its round cannot stop the next one.
+    pub fn watch(&self, longpollid: String, stop: Arc<AtomicBool>) {
+        let pool = self.clone();
+        std::thread::spawn(move || match pool.template(Some(&longpollid)) {
+            Ok(template) if template.longpollid != longpollid => stop.store(true, Ordering::Relaxed),
+            Ok(_) => {}
+            Err(e) => log::debug!("Long poll failed: {}", e),
+        });
+    }
+}
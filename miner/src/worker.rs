@@ -0,0 +1,33 @@
+//! Assembly of the template returned by the node into a block to hash
+
+use anyhow::Context;
+use sedly_core::mining::BlockTemplate;
+use sedly_core::{decode_transaction, Transaction, TxOutput};
+use sedly_rpc::handlers::BlockTemplateInfo;
+
+/// Block template with a coinbase paying `payout` and the node's transactions
+///
+/// The coinbase takes the whole `coinbasevalue` minus the treasury share,
+/// which goes to its own output as the node requires. `extra_nonce` keeps
+/// the coinbases of successive rounds distinct.
+pub fn build_template(info: &BlockTemplateInfo, payout: &[u8], extra_nonce: u64) -> anyhow::Result<BlockTemplate> {
+    let mut coinbase = Transaction::coinbase(payout, info.height, info.coinbasevalue.to_sat());
+    if let Some(treasury) = &info.treasury {
+        let script = hex::decode(&treasury.script_pubkey).context("Invalid treasury script")?;
+        coinbase.outputs[0].value = coinbase.outputs[0].value.saturating_sub(treasury.amount);
+        coinbase.outputs.push(TxOutput::to_address(treasury.amount, &script));
+    }
+
+    let mut transactions = vec![coinbase];
+    for tx in &info.transactions {
+        let bytes = hex::decode(&tx.data).with_context(|| format!("Invalid hex of transaction {}", tx.txid))?;
+        transactions.push(decode_transaction(&bytes).with_context(|| format!("Invalid transaction {}", tx.txid))?);
+    }
+
+    let bits = u32::from_str_radix(&info.bits, 16).context("Invalid bits")?;
+    let mut template = BlockTemplate::new(info.previousblockhash.into(), transactions, bits, info.height)?;
+    // The local clock may be behind the median time past of the node
+    template.header.timestamp = template.header.timestamp.max(info.mintime);
+    template.set_extra_nonce(extra_nonce);
+    Ok(template)
+}
@@ -0,0 +1,482 @@
+//! Peer addresses and the address manager
+//!
+//! A peer is reachable over IPv4, IPv6 or Tor (onion v3 services), so
+//! addresses are not `SocketAddr`s: [`NetAddress`] covers the three
+//! networks and [`PeerAddress`] adds the port. Addresses travel in `addr`
+//! messages as a list of [`AddrEntry`], each tagged with the network in its
+//! encoding, and are kept in the [`AddrManager`] until the node needs new
+//! peers. The manager keeps at most [`MAX_ADDRESSES`], dropping the least
+//! recently seen, so peers cannot grow it without bound. The operator can
+//! restrict connections to some networks (`--onlynet onion`); onion peers
+//! are reached through the Tor proxy.
+
+use crate::peer::Misbehavior;
+use serde::{Deserialize, Serialize};
+use sedly_core::ServiceFlags;
+use sha3::{Digest, Sha3_256};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Command of address messages
+pub const ADDR_COMMAND: &str = "addr";
+
+/// Maximum number of addresses in an `addr` message
+pub const MAX_ADDR_PER_MESSAGE: usize = 1_000;
+
+/// Maximum number of addresses kept by the address manager
+pub const MAX_ADDRESSES: usize = 20_000;
+
+/// Version byte of onion v3 addresses
+const ONION_VERSION: u8 = 3;
+
+/// Alphabet of the base32 encoding of onion addresses (RFC 4648, lowercase)
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Network an address belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddrNetwork {
+    Ipv4,
+    Ipv6,
+    Onion,
+}
+
+impl AddrNetwork {
+    /// All networks
+    pub const ALL: [AddrNetwork; 3] = [Self::Ipv4, Self::Ipv6, Self::Onion];
+
+    /// Name used in the configuration (`--onlynet`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "ipv4",
+            Self::Ipv6 => "ipv6",
+            Self::Onion => "onion",
+        }
+    }
+}
+
+impl FromStr for AddrNetwork {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|network| network.name() == s.to_ascii_lowercase())
+            .ok_or_else(|| AddressError::UnknownNetwork(s.to_string()))
+    }
+}
+
+impl fmt::Display for AddrNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether `only_net` allows connecting to `address` (empty allows all)
+pub fn is_allowed(only_net: &[AddrNetwork], address: &PeerAddress) -> bool {
+    only_net.is_empty() || only_net.contains(&address.addr.network())
+}
+
+/// Host part of a peer address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum NetAddress {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// Onion v3 service, identified by its ed25519 public key
+    Onion([u8; 32]),
+}
+
+impl NetAddress {
+    /// Network of the address
+    pub fn network(&self) -> AddrNetwork {
+        match self {
+            NetAddress::Ipv4(_) => AddrNetwork::Ipv4,
+            NetAddress::Ipv6(_) => AddrNetwork::Ipv6,
+            NetAddress::Onion(_) => AddrNetwork::Onion,
+        }
+    }
+
+    /// Onion address of the service with public key `pubkey`
+    fn onion_host(pubkey: &[u8; 32]) -> String {
+        let mut bytes = pubkey.to_vec();
+        bytes.extend_from_slice(&onion_checksum(pubkey));
+        bytes.push(ONION_VERSION);
+        format!("{}.onion", base32_encode(&bytes))
+    }
+}
+
+impl From<IpAddr> for NetAddress {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => NetAddress::Ipv4(ip),
+            // IPv4 addresses mapped into IPv6 are the same peer
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(NetAddress::Ipv6(ip), NetAddress::Ipv4),
+        }
+    }
+}
+
+impl fmt::Display for NetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetAddress::Ipv4(ip) => write!(f, "{}", ip),
+            NetAddress::Ipv6(ip) => write!(f, "{}", ip),
+            NetAddress::Onion(pubkey) => f.write_str(&Self::onion_host(pubkey)),
+        }
+    }
+}
+
+impl FromStr for NetAddress {
+    type Err = AddressError;
+
+    /// Parse an IP literal or an onion v3 host (`<56 chars>.onion`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AddressError::Invalid(s.to_string());
+        if let Some(host) = s.to_ascii_lowercase().strip_suffix(".onion") {
+            let bytes = base32_decode(host).filter(|bytes| bytes.len() == 35).ok_or_else(invalid)?;
+            let pubkey: [u8; 32] = bytes[..32].try_into().expect("Length checked");
+            if bytes[34] != ONION_VERSION || bytes[32..34] != onion_checksum(&pubkey) {
+                return Err(AddressError::InvalidOnion(s.to_string()));
+            }
+            return Ok(NetAddress::Onion(pubkey));
+        }
+        let ip = s.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')).unwrap_or(s);
+        ip.parse::<IpAddr>().map(NetAddress::from).map_err(|_| invalid())
+    }
+}
+
+/// Address and port of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PeerAddress {
+    /// Host
+    pub addr: NetAddress,
+    /// TCP port
+    pub port: u16,
+}
+
+impl PeerAddress {
+    /// Socket address to connect to directly (None for onion services)
+    pub fn to_socket_addr(&self) -> Option<SocketAddr> {
+        match self.addr {
+            NetAddress::Ipv4(ip) => Some(SocketAddr::new(IpAddr::V4(ip), self.port)),
+            NetAddress::Ipv6(ip) => Some(SocketAddr::new(IpAddr::V6(ip), self.port)),
+            NetAddress::Onion(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(addr: SocketAddr) -> Self {
+        Self { addr: addr.ip().into(), port: addr.port() }
+    }
+}
+
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            NetAddress::Ipv6(ip) => write!(f, "[{}]:{}", ip, self.port),
+            _ => write!(f, "{}:{}", self.addr, self.port),
+        }
+    }
+}
+
+impl FromStr for PeerAddress {
+    type Err = AddressError;
+
+    /// Parse `ip:port`, `[ipv6]:port` or `<onion>.onion:port`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = s.rsplit_once(':').ok_or_else(|| AddressError::Invalid(s.to_string()))?;
+        let port = port.parse().map_err(|_| AddressError::Invalid(s.to_string()))?;
+        if host.contains(':') && !host.starts_with('[') {
+            return Err(AddressError::Invalid(s.to_string()));
+        }
+        Ok(Self { addr: host.parse()?, port })
+    }
+}
+
+/// Address advertised in an `addr` message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddrEntry {
+    /// Last time the peer was seen (Unix seconds)
+    pub time: u64,
+    /// Services advertised by the peer
+    pub services: ServiceFlags,
+    /// Address of the peer
+    pub address: PeerAddress,
+}
+
+/// Payload of an `addr` message
+pub fn encode_addr(entries: &[AddrEntry]) -> Result<Vec<u8>, AddressError> {
+    if entries.len() > MAX_ADDR_PER_MESSAGE {
+        return Err(AddressError::TooMany(entries.len()));
+    }
+    Ok(bincode::serialize(entries).expect("Address serialization cannot fail"))
+}
+
+/// Decode the payload of an `addr` message
+pub fn decode_addr(payload: &[u8]) -> Result<Vec<AddrEntry>, AddressError> {
+    let entries: Vec<AddrEntry> =
+        bincode::deserialize(payload).map_err(|e| AddressError::Malformed(e.to_string()))?;
+    if entries.len() > MAX_ADDR_PER_MESSAGE {
+        return Err(AddressError::TooMany(entries.len()));
+    }
+    Ok(entries)
+}
+
+/// Known peer addresses
+#[derive(Debug, Clone)]
+pub struct AddrManager {
+    /// Most recent entry of each address
+    entries: HashMap<PeerAddress, AddrEntry>,
+    /// Addresses by (time, address): the first is the next to drop
+    by_time: BTreeSet<(u64, PeerAddress)>,
+    /// Most addresses kept
+    max_entries: usize,
+}
+
+impl Default for AddrManager {
+    fn default() -> Self {
+        Self::with_max_entries(MAX_ADDRESSES)
+    }
+}
+
+impl AddrManager {
+    /// Create an empty address manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty address manager keeping at most `max_entries` addresses
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self { entries: HashMap::new(), by_time: BTreeSet::new(), max_entries }
+    }
+
+    /// Add the addresses received at time `now`, returning how many are new
+    ///
+    /// Times in the future are clamped to `now`, so a peer cannot make its
+    /// addresses look fresher than they can be. When the manager is full a
+    /// new address replaces the least recently seen one, if it was seen
+    /// later, and is dropped otherwise.
+    pub fn add(&mut self, entries: impl IntoIterator<Item = AddrEntry>, now: u64) -> usize {
+        let mut added = 0;
+        for mut entry in entries {
+            entry.time = entry.time.min(now);
+            match self.entries.get_mut(&entry.address) {
+                Some(known) if known.time >= entry.time => {}
+                Some(known) => {
+                    self.by_time.remove(&(known.time, known.address));
+                    self.by_time.insert((entry.time, entry.address));
+                    *known = entry;
+                }
+                None => {
+                    if self.entries.len() >= self.max_entries {
+                        match self.by_time.first().copied() {
+                            Some(oldest) if oldest.0 < entry.time => {
+                                self.by_time.remove(&oldest);
+                                self.entries.remove(&oldest.1);
+                            }
+                            _ => continue,
+                        }
+                    }
+                    self.by_time.insert((entry.time, entry.address));
+                    self.entries.insert(entry.address, entry);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    /// Number of known addresses
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no address is known
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Up to `count` addresses allowed by `only_net`, most recently seen first
+    pub fn select(&self, only_net: &[AddrNetwork], count: usize) -> Vec<PeerAddress> {
+        self.sorted()
+            .into_iter()
+            .filter(|entry| is_allowed(only_net, &entry.address))
+            .take(count)
+            .map(|entry| entry.address)
+            .collect()
+    }
+
+    /// Entries to advertise to a peer, most recently seen first
+    pub fn relay_entries(&self) -> Vec<AddrEntry> {
+        let mut entries = self.sorted();
+        entries.truncate(MAX_ADDR_PER_MESSAGE);
+        entries
+    }
+
+    /// Entries from the most recently seen
+    fn sorted(&self) -> Vec<AddrEntry> {
+        let mut entries: Vec<AddrEntry> = self.entries.values().copied().collect();
+        entries.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.address.to_string().cmp(&b.address.to_string())));
+        entries
+    }
+}
+
+/// Handle the payload of an `addr` message received at time `now`
+///
+/// Returns the number of new addresses.
+pub fn receive_addr(addrman: &Mutex<AddrManager>, payload: &[u8], now: u64) -> Result<usize, Misbehavior> {
+    let entries = decode_addr(payload).map_err(|_| Misbehavior::MalformedMessage)?;
+    Ok(addrman.lock().unwrap().add(entries, now))
+}
+
+/// Checksum of an onion v3 address
+fn onion_checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b".onion checksum");
+    hasher.update(pubkey);
+    hasher.update([ONION_VERSION]);
+    let digest = hasher.finalize();
+    [digest[0], digest[1]]
+}
+
+/// Base32 encoding without padding
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+/// Base32 decoding without padding, None on invalid characters
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Errors of peer addresses
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AddressError {
+    #[error("Invalid peer address: {0}")]
+    Invalid(String),
+
+    #[error("Invalid onion v3 address: {0}")]
+    InvalidOnion(String),
+
+    #[error("Unknown network: {0} (expected ipv4, ipv6 or onion)")]
+    UnknownNetwork(String),
+
+    #[error("Malformed addr message: {0}")]
+    Malformed(String),
+
+    #[error("Too many addresses: {0}")]
+    TooMany(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Onion v3 address of the Tor Project (2019)
+    const TOR_ONION: &str = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+
+    #[test]
+    fn test_parse_and_display() {
+        let onion: NetAddress = TOR_ONION.parse().unwrap();
+        assert_eq!(onion.network(), AddrNetwork::Onion);
+        assert_eq!(onion.to_string(), TOR_ONION);
+        assert_eq!(TOR_ONION.to_uppercase().parse::<NetAddress>(), Ok(onion));
+
+        // A changed character breaks the checksum
+        let tampered = TOR_ONION.replacen('2', "3", 1);
+        assert_eq!(tampered.parse::<NetAddress>(), Err(AddressError::InvalidOnion(tampered.clone())));
+        assert!("short.onion".parse::<NetAddress>().is_err());
+
+        for text in ["10.0.0.1:9333", "[2001:db8::1]:9333", &format!("{}:9333", TOR_ONION)] {
+            let address: PeerAddress = text.parse().unwrap();
+            assert_eq!(address.to_string(), text);
+        }
+        let mapped: PeerAddress = "[::ffff:10.0.0.1]:9333".parse().unwrap();
+        assert_eq!(mapped.to_string(), "10.0.0.1:9333");
+        assert_eq!(mapped.to_socket_addr(), Some("10.0.0.1:9333".parse().unwrap()));
+        assert!("2001:db8::1:9333".parse::<PeerAddress>().is_err());
+        assert!("10.0.0.1".parse::<PeerAddress>().is_err());
+
+        assert_eq!("Onion".parse::<AddrNetwork>(), Ok(AddrNetwork::Onion));
+        assert!(matches!("tor".parse::<AddrNetwork>(), Err(AddressError::UnknownNetwork(_))));
+    }
+
+    #[test]
+    fn test_addr_messages_and_manager() {
+        let entry = |address: &str, time| AddrEntry {
+            time,
+            services: ServiceFlags::NETWORK,
+            address: address.parse().unwrap(),
+        };
+        let onion = format!("{}:9333", TOR_ONION);
+        let entries = vec![entry("10.0.0.1:9333", 10), entry("[2001:db8::1]:9333", 20), entry(&onion, 30)];
+        let payload = encode_addr(&entries).unwrap();
+        assert_eq!(decode_addr(&payload), Ok(entries.clone()));
+        assert!(matches!(decode_addr(b"garbage"), Err(AddressError::Malformed(_))));
+        let too_many = vec![entries[0]; MAX_ADDR_PER_MESSAGE + 1];
+        assert_eq!(encode_addr(&too_many), Err(AddressError::TooMany(1_001)));
+
+        let addrman = Mutex::new(AddrManager::new());
+        assert_eq!(receive_addr(&addrman, &payload, 25), Ok(3));
+        assert_eq!(receive_addr(&addrman, &payload, 25), Ok(0));
+        assert_eq!(receive_addr(&addrman, b"garbage", 25), Err(Misbehavior::MalformedMessage));
+
+        let mut addrman = addrman.into_inner().unwrap();
+        addrman.add([entry("10.0.0.1:9333", 40)], 50);
+        let addresses: Vec<String> = addrman.select(&[], 10).iter().map(ToString::to_string).collect();
+        // The future time of the onion address was capped at 25
+        assert_eq!(addresses, vec!["10.0.0.1:9333".to_string(), onion.clone(), "[2001:db8::1]:9333".to_string()]);
+        assert_eq!(addrman.select(&[AddrNetwork::Onion], 10), vec![onion.parse().unwrap()]);
+        assert_eq!(addrman.select(&[AddrNetwork::Ipv4, AddrNetwork::Ipv6], 1).len(), 1);
+        assert_eq!(addrman.relay_entries().len(), 3);
+    }
+
+    #[test]
+    fn test_manager_bounded() {
+        let entry = |last: u8, time| AddrEntry {
+            time,
+            services: ServiceFlags::NETWORK,
+            address: format!("10.0.0.{}:9333", last).parse().unwrap(),
+        };
+        let mut addrman = AddrManager::with_max_entries(2);
+        assert_eq!(addrman.add([entry(1, 10), entry(2, 20)], 100), 2);
+
+        // A fresher address replaces the least recently seen one, an older one is dropped
+        assert_eq!(addrman.add([entry(3, 30)], 100), 1);
+        assert_eq!(addrman.add([entry(4, 5)], 100), 0);
+        let addresses: Vec<String> = addrman.select(&[], 10).iter().map(ToString::to_string).collect();
+        assert_eq!(addresses, vec!["10.0.0.3:9333".to_string(), "10.0.0.2:9333".to_string()]);
+
+        // Refreshing a known address moves it out of the way of the next eviction
+        assert_eq!(addrman.add([entry(2, 40), entry(5, 35)], 100), 1);
+        let addresses: Vec<String> = addrman.select(&[], 10).iter().map(ToString::to_string).collect();
+        assert_eq!(addresses, vec!["10.0.0.2:9333".to_string(), "10.0.0.5:9333".to_string()]);
+        assert_eq!(addrman.len(), 2);
+    }
+}
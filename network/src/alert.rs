@@ -0,0 +1,93 @@
+//! Network alert relay
+//!
+//! Signed alerts travel in `alert` messages whose payload is the
+//! serialized [`SignedAlert`]. Every alert received is verified against
+//! the alert keys of the network and only new ones are relayed, so a
+//! forged alert never travels more than one hop. Newly connected peers are
+//! sent every alert that has not expired yet, cancellations included.
+
+use crate::peer::Misbehavior;
+use crate::protocol::FrameError;
+use crate::stats::send_message;
+use sedly_core::{AlertSet, ChainParams, NetStats, SignedAlert};
+use std::sync::Mutex;
+
+/// Command of alert messages
+pub const ALERT_COMMAND: &str = "alert";
+
+/// Frame the alerts to send to a newly connected `peer` at time `now`
+pub fn alert_messages(
+    stats: &Mutex<NetStats>,
+    peer: &str,
+    params: &ChainParams,
+    alerts: &AlertSet,
+    now: u64,
+) -> Result<Vec<Vec<u8>>, FrameError> {
+    alerts
+        .relayable(now)
+        .into_iter()
+        .map(|alert| send_message(stats, peer, params.magic, ALERT_COMMAND, &alert.to_bytes(), now))
+        .collect()
+}
+
+/// Handle the payload of an `alert` message received at time `now`
+///
+/// Returns true if the alert is new and must be relayed to the other
+/// peers. Expired, cancelled and already known alerts are dropped without
+/// penalty; forged or malformed ones are the sender's misbehavior.
+pub fn receive_alert(
+    alerts: &Mutex<AlertSet>,
+    params: &ChainParams,
+    payload: &[u8],
+    now: u64,
+) -> Result<bool, Misbehavior> {
+    let result = SignedAlert::from_bytes(payload)
+        .and_then(|alert| alerts.lock().unwrap().process(alert, &params.alert_keys, now));
+    match result {
+        Ok(is_new) => Ok(is_new),
+        Err(e) if e.is_misbehavior() => Err(Misbehavior::InvalidAlert),
+        Err(e) => {
+            log::debug!("Ignoring alert: {}", e);
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::receive_message;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use sedly_core::Alert;
+
+    #[test]
+    fn test_alert_relay() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let params = ChainParams::regtest()
+            .with_alert_key(PublicKey::from_secret_key(&secp, &secret_key).serialize().to_vec());
+        let alert = Alert { id: 1, cancel: Vec::new(), expiration: 100, priority: 1, message: "Upgrade".to_string() }
+            .sign(&secp, &secret_key);
+
+        let stats = Mutex::new(NetStats::new());
+        let mut known = AlertSet::new();
+        known.process(alert.clone(), &params.alert_keys, 0).unwrap();
+        let messages = alert_messages(&stats, "peer1", &params, &known, 0).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(alert_messages(&stats, "peer1", &params, &known, 100).unwrap().is_empty());
+
+        let (command, payload) = receive_message(&stats, "peer2", &messages[0], params.magic, 0).unwrap();
+        assert_eq!(command, ALERT_COMMAND);
+        let alerts = Mutex::new(AlertSet::new());
+        assert_eq!(receive_alert(&alerts, &params, &payload, 0), Ok(true));
+        assert_eq!(receive_alert(&alerts, &params, &payload, 0), Ok(false));
+        assert_eq!(receive_alert(&alerts, &params, &payload, 100), Ok(false));
+
+        let mut forged = alert;
+        forged.alert.message = "Downgrade".to_string();
+        assert_eq!(receive_alert(&alerts, &params, &forged.to_bytes(), 0), Err(Misbehavior::InvalidAlert));
+        assert_eq!(receive_alert(&alerts, &params, b"garbage", 0), Err(Misbehavior::InvalidAlert));
+        // Without network keys alerts are ignored, not penalized
+        assert_eq!(receive_alert(&alerts, &ChainParams::regtest(), &payload, 0), Ok(false));
+    }
+}
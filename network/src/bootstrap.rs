@@ -0,0 +1,181 @@
+//! Initial peer discovery
+//!
+//! A node that knows no peers yet asks the DNS seeds of its network for
+//! addresses and falls back to the fixed seeds of [`ChainParams`] when none
+//! answers. Operators can add peers (`--addnode`) or restrict the node to
+//! an explicit list (`--connect`), which also disables the seeds. Onion
+//! peers are taken as they are, since only the Tor proxy can resolve them,
+//! and `--onlynet` drops the addresses of the other networks.
+
+use crate::address::{is_allowed, AddrNetwork, NetAddress, PeerAddress};
+use sedly_core::ChainParams;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Peer configuration given by the operator
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BootstrapConfig {
+    /// Connect only to these peers (`host[:port]`)
+    pub connect: Vec<String>,
+    /// Peers to connect to in addition to the discovered ones
+    pub add_nodes: Vec<String>,
+    /// Skip the DNS seeds (the fixed seeds are still used as fallback)
+    pub no_dns_seed: bool,
+    /// Only connect to peers on these networks (empty allows all)
+    pub only_net: Vec<AddrNetwork>,
+}
+
+impl BootstrapConfig {
+    /// Whether seeds may be used to discover peers
+    pub fn discovery_enabled(&self) -> bool {
+        self.connect.is_empty()
+    }
+}
+
+/// Address of a `host[:port]` peer entry, with the network port as default
+pub fn peer_endpoint(entry: &str, default_port: u16) -> String {
+    let entry = entry.trim();
+    let has_port = match entry.strip_prefix('[') {
+        // IPv6 literal: `[addr]` or `[addr]:port`
+        Some(rest) => rest.contains("]:"),
+        None => entry.matches(':').count() == 1,
+    };
+    if has_port {
+        entry.to_string()
+    } else if entry.contains(':') && !entry.starts_with('[') {
+        format!("[{}]:{}", entry, default_port)
+    } else {
+        format!("{}:{}", entry, default_port)
+    }
+}
+
+/// Resolve `host:port` endpoints to socket addresses
+pub trait Resolver {
+    /// Addresses of `endpoint`, empty if it cannot be resolved
+    fn resolve(&self, endpoint: &str) -> Vec<SocketAddr>;
+}
+
+/// Resolver using the system DNS configuration
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, endpoint: &str) -> Vec<SocketAddr> {
+        match endpoint.to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                log::debug!("Failed to resolve {}: {}", endpoint, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Addresses of a `host:port` endpoint: onion endpoints as they are, the
+/// others through `resolver`
+fn resolve_endpoint(endpoint: &str, resolver: &dyn Resolver) -> Vec<PeerAddress> {
+    match endpoint.parse::<PeerAddress>() {
+        Ok(address @ PeerAddress { addr: NetAddress::Onion(_), .. }) => vec![address],
+        _ => resolver.resolve(endpoint).into_iter().map(PeerAddress::from).collect(),
+    }
+}
+
+/// Addresses to connect to at startup, without duplicates
+///
+/// With `connect` only those peers are returned. Otherwise `add_nodes` come
+/// first, followed by the DNS seed answers or, if no seed returned any
+/// allowed address, by the fixed seeds.
+pub fn initial_peers(params: &ChainParams, config: &BootstrapConfig, resolver: &dyn Resolver) -> Vec<PeerAddress> {
+    let port = params.network.default_port();
+    let resolve_all = |entries: &[String]| -> Vec<PeerAddress> {
+        entries
+            .iter()
+            .flat_map(|entry| resolve_endpoint(&peer_endpoint(entry, port), resolver))
+            .filter(|address| is_allowed(&config.only_net, address))
+            .collect()
+    };
+
+    let mut peers = Vec::new();
+    let mut add = |addrs: Vec<PeerAddress>| {
+        for addr in addrs {
+            if !peers.contains(&addr) {
+                peers.push(addr);
+            }
+        }
+    };
+
+    if !config.discovery_enabled() {
+        add(resolve_all(&config.connect));
+        return peers;
+    }
+    add(resolve_all(&config.add_nodes));
+
+    let seeded = if config.no_dns_seed { Vec::new() } else { resolve_all(&params.dns_seeds) };
+    if seeded.is_empty() {
+        log::info!("No addresses from DNS seeds, using {} fixed seeds", params.fixed_seeds.len());
+        add(resolve_all(&params.fixed_seeds));
+    } else {
+        add(seeded);
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Resolver with fixed answers
+    struct StaticResolver(HashMap<String, Vec<SocketAddr>>);
+
+    impl Resolver for StaticResolver {
+        fn resolve(&self, endpoint: &str) -> Vec<SocketAddr> {
+            self.0.get(endpoint).cloned().unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_peer_endpoint() {
+        assert_eq!(peer_endpoint("seed.example", 9333), "seed.example:9333");
+        assert_eq!(peer_endpoint(" 10.0.0.1:1234 ", 9333), "10.0.0.1:1234");
+        assert_eq!(peer_endpoint("::1", 9333), "[::1]:9333");
+        assert_eq!(peer_endpoint("[::1]", 9333), "[::1]:9333");
+        assert_eq!(peer_endpoint("[::1]:1234", 9333), "[::1]:1234");
+    }
+
+    #[test]
+    fn test_initial_peers() {
+        let params = ChainParams::mainnet();
+        let seed_addr: SocketAddr = "10.0.0.1:9333".parse().unwrap();
+        let fixed_addr: SocketAddr = "10.0.0.2:9333".parse().unwrap();
+        let added_addr: SocketAddr = "10.0.0.3:9333".parse().unwrap();
+        let mut answers = HashMap::new();
+        answers.insert(format!("{}:9333", params.dns_seeds[0]), vec![seed_addr]);
+        answers.insert(format!("{}:9333", params.dns_seeds[1]), vec![seed_addr]);
+        answers.insert(params.fixed_seeds[0].clone(), vec![fixed_addr]);
+        answers.insert("peer.example:9333".to_string(), vec![added_addr]);
+        let resolver = StaticResolver(answers);
+        let peer = PeerAddress::from;
+
+        let added = BootstrapConfig { add_nodes: vec!["peer.example".to_string()], ..BootstrapConfig::default() };
+        assert_eq!(initial_peers(&params, &added, &resolver), vec![peer(added_addr), peer(seed_addr)]);
+
+        // Without answers from the DNS seeds the fixed peers are used
+        let no_dns = BootstrapConfig { no_dns_seed: true, ..BootstrapConfig::default() };
+        assert_eq!(initial_peers(&params, &no_dns, &resolver), vec![peer(fixed_addr)]);
+
+        // --connect excludes seeds and added peers
+        let connect = BootstrapConfig { connect: vec!["peer.example".to_string()], ..added };
+        assert_eq!(initial_peers(&params, &connect, &resolver), vec![peer(added_addr)]);
+
+        // Onion addresses skip the resolver; --onlynet drops the other networks
+        let onion = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+        let only_onion = BootstrapConfig {
+            add_nodes: vec![onion.to_string(), "peer.example".to_string()],
+            only_net: vec![AddrNetwork::Onion],
+            ..BootstrapConfig::default()
+        };
+        let expected: PeerAddress = format!("{}:9333", onion).parse().unwrap();
+        assert_eq!(initial_peers(&params, &only_onion, &resolver), vec![expected]);
+        assert!(initial_peers(&ChainParams::regtest(), &BootstrapConfig::default(), &resolver).is_empty());
+    }
+}
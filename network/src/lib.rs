@@ -0,0 +1,28 @@
+//! Sedly P2P networking
+
+pub mod address;
+pub mod alert;
+pub mod bootstrap;
+pub mod peer;
+pub mod protocol;
+pub mod relay;
+pub mod stats;
+pub mod version;
+
+pub use address::{
+    decode_addr, encode_addr, receive_addr, AddrEntry, AddrManager, AddrNetwork, AddressError, NetAddress, PeerAddress,
+    ADDR_COMMAND,
+};
+pub use alert::{alert_messages, receive_alert, ALERT_COMMAND};
+pub use bootstrap::{initial_peers, BootstrapConfig, Resolver, SystemResolver};
+pub use peer::{Misbehavior, PeerScores, BAN_THRESHOLD};
+pub use protocol::{decode_message, encode_message, FrameError, MessageHeader};
+pub use relay::{
+    read_frame, RelayError, RelayMessage, BLOCKS_COMMAND, BLOCK_COMMAND, GETBLOCKS_COMMAND, TX_COMMAND,
+};
+pub use stats::{receive_message, send_message};
+pub use version::{
+    local_services, negotiate, receive_version, select_peers, HandshakeError, Negotiated, PeerRequest, VersionMessage,
+    PROTOCOL_VERSION, VERACK_COMMAND, VERSION_COMMAND,
+};
+pub use sedly_core::{NetStats, NetTotals, PeerStats, ServiceFlags};
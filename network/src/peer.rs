@@ -0,0 +1,161 @@
+//! Peer misbehavior scoring
+//!
+//! Every peer accumulates a score for protocol and consensus violations.
+//! Once the score reaches `BAN_THRESHOLD` the peer is banned. Relaying a
+//! block that failed consensus validation, or one already marked invalid,
+//! bans immediately.
+
+use sedly_core::{PipelineError, ReorgError, StagingError, StandaloneError};
+use std::collections::HashMap;
+
+/// Score at which a peer is banned
+pub const BAN_THRESHOLD: u32 = 100;
+
+/// Ban duration in seconds (24 hours)
+pub const BAN_DURATION: u64 = 24 * 60 * 60;
+
+/// Misbehavior reported against a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Block that violates consensus rules
+    InvalidBlock,
+    /// Block already marked invalid, or descending from one
+    KnownInvalidBlock,
+    /// Block that cannot be decoded
+    MalformedBlock,
+    /// Message that cannot be framed or decoded
+    MalformedMessage,
+    /// Block far outside the requested download window
+    UnrequestedBlock,
+    /// Alert with a forged signature or malformed content
+    InvalidAlert,
+}
+
+impl Misbehavior {
+    /// Score added for this misbehavior
+    pub fn score(&self) -> u32 {
+        match self {
+            // Honest peers only relay alerts they verified
+            Misbehavior::InvalidBlock | Misbehavior::KnownInvalidBlock | Misbehavior::InvalidAlert => BAN_THRESHOLD,
+            Misbehavior::MalformedBlock => 50,
+            Misbehavior::MalformedMessage => 20,
+            Misbehavior::UnrequestedBlock => 10,
+        }
+    }
+
+    /// Misbehavior of the peer that relayed a block rejected by the pipeline
+    ///
+    /// Storage failures and blocks that simply do not extend the tip are
+    /// not the peer's fault.
+    pub fn from_pipeline_error(error: &PipelineError) -> Option<Self> {
+        match error {
+            PipelineError::Decode(_) => Some(Misbehavior::MalformedBlock),
+            PipelineError::KnownInvalid { .. } => Some(Misbehavior::KnownInvalidBlock),
+            PipelineError::Invalid { error, .. } if error.is_block_invalid() => Some(Misbehavior::InvalidBlock),
+            PipelineError::Invalid { .. } | PipelineError::Storage { .. } => None,
+        }
+    }
+
+    /// Misbehavior of the peer that sent a block refused by the staging area
+    pub fn from_staging_error(error: &StagingError) -> Option<Self> {
+        match error {
+            StagingError::Invalid(error) if error.is_block_invalid() => Some(Misbehavior::InvalidBlock),
+            StagingError::Pipeline(error) => Self::from_pipeline_error(error),
+            StagingError::TooFarAhead { .. } => Some(Misbehavior::UnrequestedBlock),
+            _ => None,
+        }
+    }
+
+    /// Misbehavior of the peer that relayed a block refused by a standalone node
+    pub fn from_standalone_error(error: &StandaloneError) -> Option<Self> {
+        match error {
+            StandaloneError::Invalid(error) if error.is_block_invalid() => Some(Misbehavior::InvalidBlock),
+            StandaloneError::Pipeline(error) | StandaloneError::Reorg(ReorgError::Rejected { error, .. }) => {
+                Self::from_pipeline_error(error)
+            }
+            StandaloneError::Staging(error) => Self::from_staging_error(error),
+            _ => None,
+        }
+    }
+}
+
+/// Misbehavior scores and bans of connected peers
+#[derive(Debug, Clone, Default)]
+pub struct PeerScores {
+    /// Current score by peer
+    scores: HashMap<String, u32>,
+    /// Ban expiry (Unix seconds) by peer
+    banned: HashMap<String, u64>,
+}
+
+impl PeerScores {
+    /// Create an empty score table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current score of a peer
+    pub fn score(&self, peer: &str) -> u32 {
+        self.scores.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Record a misbehavior at time `now`, returning true if the peer is now banned
+    pub fn penalize(&mut self, peer: &str, misbehavior: Misbehavior, now: u64) -> bool {
+        let score = self.scores.entry(peer.to_string()).or_insert(0);
+        *score = score.saturating_add(misbehavior.score());
+        log::debug!("Peer {} misbehaved ({:?}), score {}", peer, misbehavior, score);
+
+        if *score >= BAN_THRESHOLD {
+            self.scores.remove(peer);
+            self.banned.insert(peer.to_string(), now + BAN_DURATION);
+            log::info!("Banned peer {} until {}", peer, now + BAN_DURATION);
+            return true;
+        }
+        false
+    }
+
+    /// Whether a peer is banned at time `now`
+    pub fn is_banned(&self, peer: &str, now: u64) -> bool {
+        self.banned.get(peer).is_some_and(|until| *until > now)
+    }
+
+    /// Lift expired bans, returning how many were removed
+    pub fn expire_bans(&mut self, now: u64) -> usize {
+        let before = self.banned.len();
+        self.banned.retain(|_, until| *until > now);
+        before - self.banned.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Stage, ValidationError};
+
+    #[test]
+    fn test_invalid_block_bans_peer() {
+        let mut scores = PeerScores::new();
+        let error = PipelineError::Invalid { stage: Stage::Contextual, error: ValidationError::NoTransactions };
+        let misbehavior = Misbehavior::from_pipeline_error(&error).unwrap();
+
+        assert!(scores.penalize("peer1", misbehavior, 1_000));
+        assert!(scores.is_banned("peer1", 1_000));
+        assert!(!scores.is_banned("peer1", 1_000 + BAN_DURATION));
+        assert_eq!(scores.expire_bans(1_000 + BAN_DURATION), 1);
+
+        // A block not extending the tip is not the peer's fault
+        let error = PipelineError::Invalid { stage: Stage::Header, error: ValidationError::BadParent };
+        assert_eq!(Misbehavior::from_pipeline_error(&error), None);
+    }
+
+    #[test]
+    fn test_scores_accumulate() {
+        let mut scores = PeerScores::new();
+        for _ in 0..4 {
+            assert!(!scores.penalize("peer2", Misbehavior::MalformedMessage, 0));
+        }
+        assert_eq!(scores.score("peer2"), 80);
+        assert!(scores.penalize("peer2", Misbehavior::MalformedMessage, 0));
+        assert_eq!(scores.score("peer2"), 0);
+    }
+}
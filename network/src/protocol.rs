@@ -0,0 +1,165 @@
+//! P2P message framing
+//!
+//! Every message starts with a fixed 24-byte header:
+//!
+//! | bytes  | field                                      |
+//! |--------|--------------------------------------------|
+//! | 0..4   | network magic (`ChainParams::magic`)       |
+//! | 4..16  | command, ASCII, NUL padded                 |
+//! | 16..20 | payload length, little endian              |
+//! | 20..24 | first 4 bytes of double SHA-256 of payload |
+//!
+//! A peer whose magic differs belongs to another network and is rejected
+//! before any payload is read.
+
+use sedly_core::Network;
+use sha2::{Digest, Sha256};
+
+/// Size of the message header in bytes
+pub const HEADER_LEN: usize = 24;
+
+/// Maximum length of a command name
+pub const COMMAND_LEN: usize = 12;
+
+/// Largest payload accepted from a peer (4 MB)
+pub const MAX_PAYLOAD_LEN: u32 = 4 * 1024 * 1024;
+
+/// Decoded message header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageHeader {
+    /// Network magic
+    pub magic: [u8; 4],
+    /// Command name
+    pub command: String,
+    /// Payload length in bytes
+    pub length: u32,
+    /// Payload checksum
+    pub checksum: [u8; 4],
+}
+
+impl MessageHeader {
+    /// Decode a header, rejecting messages of other networks
+    pub fn decode(bytes: &[u8; HEADER_LEN], expected_magic: [u8; 4]) -> Result<Self, FrameError> {
+        let magic: [u8; 4] = bytes[0..4].try_into().expect("slice of length 4");
+        if magic != expected_magic {
+            return Err(FrameError::MagicMismatch { expected: expected_magic, found: magic });
+        }
+
+        let command_bytes = &bytes[4..16];
+        let end = command_bytes.iter().position(|&b| b == 0).unwrap_or(COMMAND_LEN);
+        if command_bytes[end..].iter().any(|&b| b != 0) || !command_bytes[..end].is_ascii() {
+            return Err(FrameError::InvalidCommand);
+        }
+        let command = String::from_utf8_lossy(&command_bytes[..end]).into_owned();
+
+        let length = u32::from_le_bytes(bytes[16..20].try_into().expect("slice of length 4"));
+        if length > MAX_PAYLOAD_LEN {
+            return Err(FrameError::PayloadTooLarge(length));
+        }
+
+        Ok(Self {
+            magic,
+            command,
+            length,
+            checksum: bytes[20..24].try_into().expect("slice of length 4"),
+        })
+    }
+}
+
+/// Checksum of a payload
+pub fn checksum(payload: &[u8]) -> [u8; 4] {
+    let hash = Sha256::digest(Sha256::digest(payload));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Frame a message for the network with the given magic
+pub fn encode_message(magic: [u8; 4], command: &str, payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+    if command.is_empty() || command.len() > COMMAND_LEN || !command.is_ascii() {
+        return Err(FrameError::InvalidCommand);
+    }
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|&length| length <= MAX_PAYLOAD_LEN)
+        .ok_or(FrameError::PayloadTooLarge(payload.len().min(u32::MAX as usize) as u32))?;
+
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(&magic);
+    let mut command_bytes = [0u8; COMMAND_LEN];
+    command_bytes[..command.len()].copy_from_slice(command.as_bytes());
+    message.extend_from_slice(&command_bytes);
+    message.extend_from_slice(&length.to_le_bytes());
+    message.extend_from_slice(&checksum(payload));
+    message.extend_from_slice(payload);
+    Ok(message)
+}
+
+/// Decode a complete framed message into its command and payload
+pub fn decode_message(bytes: &[u8], expected_magic: [u8; 4]) -> Result<(String, Vec<u8>), FrameError> {
+    let header: &[u8; HEADER_LEN] = bytes
+        .get(..HEADER_LEN)
+        .and_then(|header| header.try_into().ok())
+        .ok_or(FrameError::Truncated)?;
+    let header = MessageHeader::decode(header, expected_magic)?;
+
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() != header.length as usize {
+        return Err(FrameError::Truncated);
+    }
+    if checksum(payload) != header.checksum {
+        return Err(FrameError::BadChecksum);
+    }
+    Ok((header.command, payload.to_vec()))
+}
+
+/// Name of the network using a magic, for error messages
+fn network_name(magic: &[u8; 4]) -> String {
+    Network::from_magic(*magic)
+        .map(|network| network.name().to_string())
+        .unwrap_or_else(|| format!("unknown ({})", hex::encode(magic)))
+}
+
+/// Framing errors
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error("Peer is on network {}, expected {}", network_name(found), network_name(expected))]
+    MagicMismatch { expected: [u8; 4], found: [u8; 4] },
+
+    #[error("Invalid command name")]
+    InvalidCommand,
+
+    #[error("Payload of {0} bytes exceeds the maximum of {} bytes", MAX_PAYLOAD_LEN)]
+    PayloadTooLarge(u32),
+
+    #[error("Truncated message")]
+    Truncated,
+
+    #[error("Payload checksum mismatch")]
+    BadChecksum,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let magic = Network::Testnet.magic();
+        let message = encode_message(magic, "ping", b"nonce").unwrap();
+        assert_eq!(message.len(), HEADER_LEN + 5);
+        assert_eq!(decode_message(&message, magic).unwrap(), ("ping".to_string(), b"nonce".to_vec()));
+
+        let mut corrupted = message.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(decode_message(&corrupted, magic), Err(FrameError::BadChecksum)));
+        assert!(matches!(decode_message(&message[..10], magic), Err(FrameError::Truncated)));
+        assert!(encode_message(magic, "averylongcommand", b"").is_err());
+    }
+
+    #[test]
+    fn test_other_network_rejected() {
+        let message = encode_message(Network::Mainnet.magic(), "version", b"").unwrap();
+        let error = decode_message(&message, Network::Testnet.magic()).unwrap_err();
+        assert!(matches!(error, FrameError::MagicMismatch { .. }));
+        assert_eq!(error.to_string(), "Peer is on network mainnet, expected testnet");
+    }
+}
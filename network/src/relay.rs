@@ -0,0 +1,159 @@
+//! Block and transaction relay between standalone nodes
+//!
+//! Without Tendermint, proof of work nodes gossip directly: a node sends
+//! every block it mines or connects in a `block` message and every
+//! transaction it accepts in a `tx` message, both to all peers but the one
+//! it came from. A node behind a peer (on handshake, or after receiving an
+//! orphan block) sends `getblocks` with its block locator; the peer
+//! answers with one `blocks` message holding the blocks of its active
+//! chain after the fork point, up to the payload limit. The requester asks
+//! again until an empty answer.
+//!
+//! Payloads are bincode, decoded with the limits of `sedly_core::codec`.
+
+use crate::peer::Misbehavior;
+use crate::protocol::{FrameError, MessageHeader, HEADER_LEN, MAX_PAYLOAD_LEN};
+use sedly_core::codec::decode_with_limit;
+use sedly_core::{decode_block, decode_transaction, Block, Transaction};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Command of block announcements
+pub const BLOCK_COMMAND: &str = "block";
+
+/// Command of transaction announcements
+pub const TX_COMMAND: &str = "tx";
+
+/// Command requesting the blocks after a locator
+pub const GETBLOCKS_COMMAND: &str = "getblocks";
+
+/// Command answering `getblocks`
+pub const BLOCKS_COMMAND: &str = "blocks";
+
+/// Most hashes accepted in a block locator
+pub const MAX_LOCATOR_LEN: usize = 101;
+
+/// Relay message
+#[derive(Debug, Clone)]
+pub enum RelayMessage {
+    /// Newly mined or connected block
+    Block(Block),
+    /// Newly accepted transaction
+    Transaction(Transaction),
+    /// Request for the blocks after the first locator hash on the peer's active chain
+    GetBlocks(Vec<[u8; 32]>),
+    /// Answer to `GetBlocks`, in height order (empty when the requester is up to date)
+    Blocks(Vec<Block>),
+}
+
+impl RelayMessage {
+    /// Command of the message
+    pub fn command(&self) -> &'static str {
+        match self {
+            RelayMessage::Block(_) => BLOCK_COMMAND,
+            RelayMessage::Transaction(_) => TX_COMMAND,
+            RelayMessage::GetBlocks(_) => GETBLOCKS_COMMAND,
+            RelayMessage::Blocks(_) => BLOCKS_COMMAND,
+        }
+    }
+
+    /// Serialize as a message payload
+    pub fn payload(&self) -> Vec<u8> {
+        let payload = match self {
+            RelayMessage::Block(block) => bincode::serialize(block),
+            RelayMessage::Transaction(tx) => bincode::serialize(tx),
+            RelayMessage::GetBlocks(locator) => bincode::serialize(locator),
+            RelayMessage::Blocks(blocks) => bincode::serialize(blocks),
+        };
+        payload.expect("Relay message serialization cannot fail")
+    }
+
+    /// Decode the payload of a received message
+    ///
+    /// Returns `Ok(None)` for commands that are not relay messages.
+    pub fn decode(command: &str, payload: &[u8]) -> Result<Option<Self>, Misbehavior> {
+        let message = match command {
+            BLOCK_COMMAND => decode_block(payload).map(RelayMessage::Block),
+            TX_COMMAND => decode_transaction(payload).map(RelayMessage::Transaction),
+            GETBLOCKS_COMMAND => decode_with_limit::<Vec<[u8; 32]>>(payload, MAX_PAYLOAD_LEN as usize)
+                .map(RelayMessage::GetBlocks),
+            BLOCKS_COMMAND => decode_with_limit(payload, MAX_PAYLOAD_LEN as usize).map(RelayMessage::Blocks),
+            _ => return Ok(None),
+        };
+        match message {
+            Ok(RelayMessage::GetBlocks(locator)) if locator.len() > MAX_LOCATOR_LEN => {
+                Err(Misbehavior::MalformedMessage)
+            }
+            Ok(message) => Ok(Some(message)),
+            Err(e) => {
+                log::debug!("Malformed {} message: {}", command, e);
+                Err(Misbehavior::MalformedMessage)
+            }
+        }
+    }
+}
+
+/// Read one framed message (header and payload) from a peer connection
+///
+/// The header is checked before the payload is read, so a peer of another
+/// network or announcing an oversized payload is refused without reading
+/// it. The returned bytes go to `receive_message`, which verifies the
+/// checksum and counts them.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, magic: [u8; 4]) -> Result<Vec<u8>, RelayError> {
+    let mut frame = vec![0u8; HEADER_LEN];
+    reader.read_exact(&mut frame).await?;
+    let header: &[u8; HEADER_LEN] = frame[..].try_into().expect("slice of header length");
+    let header = MessageHeader::decode(header, magic)?;
+    frame.resize(HEADER_LEN + header.length as usize, 0);
+    reader.read_exact(&mut frame[HEADER_LEN..]).await?;
+    Ok(frame)
+}
+
+/// Relay connection errors
+#[derive(Debug, thiserror::Error)]
+pub enum RelayError {
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{decode_message, encode_message};
+    use sedly_core::Network;
+
+    #[tokio::test]
+    async fn test_relay_messages() {
+        let magic = Network::Regtest.magic();
+        let block = Block::genesis();
+        let messages = [
+            RelayMessage::Block(block.clone()),
+            RelayMessage::Transaction(block.transactions[0].clone()),
+            RelayMessage::GetBlocks(vec![block.hash(); 3]),
+            RelayMessage::Blocks(vec![block.clone(), block]),
+        ];
+        let mut stream = Vec::new();
+        for message in &messages {
+            stream.extend(encode_message(magic, message.command(), &message.payload()).unwrap());
+        }
+
+        // Messages arrive one per frame even when concatenated on the connection
+        let mut reader = &stream[..];
+        for message in &messages {
+            let frame = read_frame(&mut reader, magic).await.unwrap();
+            let (command, payload) = decode_message(&frame, magic).unwrap();
+            let decoded = RelayMessage::decode(&command, &payload).unwrap().unwrap();
+            assert_eq!((decoded.command(), decoded.payload()), (message.command(), message.payload()));
+        }
+        assert!(matches!(read_frame(&mut reader, magic).await, Err(RelayError::Io(_))));
+
+        assert!(RelayMessage::decode("ping", b"").unwrap().is_none());
+        assert!(matches!(RelayMessage::decode(BLOCK_COMMAND, b"xx"), Err(Misbehavior::MalformedMessage)));
+        let locator = bincode::serialize(&vec![[0u8; 32]; MAX_LOCATOR_LEN + 1]).unwrap();
+        assert!(matches!(RelayMessage::decode(GETBLOCKS_COMMAND, &locator), Err(Misbehavior::MalformedMessage)));
+        let other = encode_message(Network::Mainnet.magic(), BLOCK_COMMAND, b"").unwrap();
+        assert!(matches!(read_frame(&mut &other[..], magic).await, Err(RelayError::Frame(_))));
+    }
+}
@@ -0,0 +1,69 @@
+//! Bandwidth accounting
+//!
+//! Messages exchanged with peers go through [`send_message`] and
+//! [`receive_message`], which frame or decode them and count their bytes
+//! in the shared [`NetStats`] table read by `getpeerinfo` and
+//! `getnettotals`.
+
+use crate::protocol::{decode_message, encode_message, FrameError};
+use sedly_core::NetStats;
+use std::sync::Mutex;
+
+/// Command recorded for received bytes that do not form a valid message
+pub const INVALID_COMMAND: &str = "*invalid*";
+
+/// Frame a message for `peer`, counting it as sent at time `now`
+pub fn send_message(
+    stats: &Mutex<NetStats>,
+    peer: &str,
+    magic: [u8; 4],
+    command: &str,
+    payload: &[u8],
+    now: u64,
+) -> Result<Vec<u8>, FrameError> {
+    let message = encode_message(magic, command, payload)?;
+    stats.lock().unwrap().record_sent(peer, command, message.len() as u64, now);
+    Ok(message)
+}
+
+/// Decode a message from `peer`, counting it as received at time `now`
+///
+/// Bytes of messages that fail to decode are still counted, under
+/// [`INVALID_COMMAND`], so peers flooding garbage show up in the stats.
+pub fn receive_message(
+    stats: &Mutex<NetStats>,
+    peer: &str,
+    bytes: &[u8],
+    magic: [u8; 4],
+    now: u64,
+) -> Result<(String, Vec<u8>), FrameError> {
+    let result = decode_message(bytes, magic);
+    let command = result.as_ref().map_or(INVALID_COMMAND, |(command, _)| command.as_str());
+    stats.lock().unwrap().record_received(peer, command, bytes.len() as u64, now);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::HEADER_LEN;
+    use sedly_core::Network;
+
+    #[test]
+    fn test_traffic_is_counted() {
+        let stats = Mutex::new(NetStats::new());
+        let magic = Network::Regtest.magic();
+        stats.lock().unwrap().connect("peer1", true, 0);
+
+        let message = send_message(&stats, "peer1", magic, "ping", b"nonce", 1).unwrap();
+        assert_eq!(receive_message(&stats, "peer1", &message, magic, 2).unwrap().0, "ping");
+        assert!(receive_message(&stats, "peer1", &message[..10], magic, 3).is_err());
+
+        let stats = stats.lock().unwrap();
+        let peer = stats.peer("peer1").unwrap();
+        assert_eq!(peer.bytes_sent, (HEADER_LEN + 5) as u64);
+        assert_eq!(peer.bytes_recv, (HEADER_LEN + 5 + 10) as u64);
+        assert_eq!(peer.messages_recv.get(INVALID_COMMAND), Some(&1));
+        assert_eq!(stats.totals().messages_recv, 2);
+    }
+}
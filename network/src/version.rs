@@ -0,0 +1,280 @@
+//! Version handshake and feature negotiation
+//!
+//! On connection both sides send a `version` message with their protocol
+//! version, the services they offer, their height and user agent, and
+//! acknowledge the peer's with `verack`. The connection uses the lower of
+//! the two protocol versions, and an optional feature (compact blocks,
+//! compact filters) is only used when that version supports it and the
+//! peer advertises the matching service. Peers older than
+//! [`MIN_PEER_PROTOCOL_VERSION`] are disconnected, as are connections to
+//! ourselves, recognized by the nonce.
+//!
+//! The advertised services are recorded in [`NetStats`], where
+//! [`select_peers`] picks who to ask for blocks during IBD or for filters.
+
+use crate::peer::Misbehavior;
+use serde::{Deserialize, Serialize};
+use sedly_core::{NetStats, ServiceFlags};
+use std::sync::Mutex;
+
+/// Protocol version of this node
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version accepted from peers
+pub const MIN_PEER_PROTOCOL_VERSION: u32 = 1;
+
+/// First protocol version with compact blocks and compact filters
+pub const FEATURES_VERSION: u32 = 2;
+
+/// Command of version messages
+pub const VERSION_COMMAND: &str = "version";
+
+/// Command acknowledging the peer's version message
+pub const VERACK_COMMAND: &str = "verack";
+
+/// Maximum length of the advertised user agent in bytes
+pub const MAX_USER_AGENT_LEN: usize = 256;
+
+/// User agent advertised by this node
+pub const USER_AGENT: &str = concat!("/sedly:", env!("CARGO_PKG_VERSION"), "/");
+
+/// Payload of a `version` message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMessage {
+    /// Highest protocol version supported by the sender
+    pub version: u32,
+    /// Services offered by the sender
+    pub services: ServiceFlags,
+    /// Sender's clock (Unix seconds)
+    pub timestamp: u64,
+    /// Random value identifying the connection, to detect self-connections
+    pub nonce: u64,
+    /// Height of the sender's best block
+    pub start_height: u64,
+    /// Software name and version of the sender
+    pub user_agent: String,
+}
+
+impl VersionMessage {
+    /// Version message of this node at time `now`
+    pub fn new(services: ServiceFlags, start_height: u64, nonce: u64, now: u64) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            services,
+            timestamp: now,
+            nonce,
+            start_height,
+            user_agent: USER_AGENT.to_string(),
+        }
+    }
+
+    /// Serialize as a message payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Version serialization cannot fail")
+    }
+
+    /// Decode a received payload
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HandshakeError> {
+        let message: Self = bincode::deserialize(bytes).map_err(|e| HandshakeError::Malformed(e.to_string()))?;
+        if message.user_agent.len() > MAX_USER_AGENT_LEN {
+            return Err(HandshakeError::UserAgentTooLong(message.user_agent.len()));
+        }
+        Ok(message)
+    }
+}
+
+/// Services offered by this node
+///
+/// A pruned node only serves recent blocks, so it advertises
+/// `NETWORK_LIMITED` instead of `NETWORK`.
+pub fn local_services(pruned: bool, filters: bool) -> ServiceFlags {
+    let chain = if pruned { ServiceFlags::NETWORK_LIMITED } else { ServiceFlags::NETWORK };
+    let services = chain | ServiceFlags::COMPACT_BLOCKS;
+    if filters {
+        services | ServiceFlags::COMPACT_FILTERS
+    } else {
+        services
+    }
+}
+
+/// Outcome of a successful handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    /// Protocol version used on the connection
+    pub version: u32,
+    /// Services advertised by the peer
+    pub services: ServiceFlags,
+    /// Whether blocks are exchanged as compact blocks
+    pub compact_blocks: bool,
+    /// Whether compact filters can be requested from the peer
+    pub compact_filters: bool,
+}
+
+/// Negotiate the connection parameters from both version messages
+pub fn negotiate(local: &VersionMessage, remote: &VersionMessage) -> Result<Negotiated, HandshakeError> {
+    if remote.nonce == local.nonce {
+        return Err(HandshakeError::SelfConnection);
+    }
+    if remote.version < MIN_PEER_PROTOCOL_VERSION {
+        return Err(HandshakeError::ObsoleteVersion(remote.version));
+    }
+    let version = local.version.min(remote.version);
+    let features = version >= FEATURES_VERSION;
+    Ok(Negotiated {
+        version,
+        services: remote.services,
+        compact_blocks: features
+            && local.services.contains(ServiceFlags::COMPACT_BLOCKS)
+            && remote.services.contains(ServiceFlags::COMPACT_BLOCKS),
+        compact_filters: features && remote.services.contains(ServiceFlags::COMPACT_FILTERS),
+    })
+}
+
+/// Handle the payload of the `version` message received from `peer`
+///
+/// On success the negotiated version and the peer's advertised services
+/// are recorded in `stats`, and the caller answers with `verack`. On
+/// error the connection must be closed.
+pub fn receive_version(
+    stats: &Mutex<NetStats>,
+    peer: &str,
+    local: &VersionMessage,
+    payload: &[u8],
+) -> Result<Negotiated, HandshakeError> {
+    let remote = VersionMessage::from_bytes(payload)?;
+    let negotiated = negotiate(local, &remote)?;
+    log::debug!(
+        "Peer {} version {} ({}), services {}",
+        peer,
+        negotiated.version,
+        remote.user_agent,
+        negotiated.services
+    );
+    stats.lock().unwrap().record_version(
+        peer,
+        negotiated.version,
+        negotiated.services,
+        &remote.user_agent,
+        remote.start_height,
+    );
+    Ok(negotiated)
+}
+
+/// Kind of request to send to a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerRequest {
+    /// Historical blocks during initial block download
+    InitialBlockDownload,
+    /// New blocks as compact blocks
+    CompactBlocks,
+    /// Compact block filters
+    CompactFilters,
+}
+
+impl PeerRequest {
+    /// Services a peer must advertise to serve the request
+    pub fn required_services(self) -> ServiceFlags {
+        match self {
+            // Pruned peers (NETWORK_LIMITED only) cannot serve old blocks
+            PeerRequest::InitialBlockDownload => ServiceFlags::NETWORK,
+            PeerRequest::CompactBlocks => ServiceFlags::COMPACT_BLOCKS,
+            PeerRequest::CompactFilters => ServiceFlags::COMPACT_FILTERS,
+        }
+    }
+
+    /// Lowest negotiated protocol version supporting the request
+    fn min_version(self) -> u32 {
+        match self {
+            PeerRequest::InitialBlockDownload => MIN_PEER_PROTOCOL_VERSION,
+            PeerRequest::CompactBlocks | PeerRequest::CompactFilters => FEATURES_VERSION,
+        }
+    }
+}
+
+/// Peers able to serve `request`, best first (highest chain, then lowest ping)
+pub fn select_peers(stats: &NetStats, request: PeerRequest) -> Vec<String> {
+    stats
+        .peers_with_services(request.required_services())
+        .into_iter()
+        .filter(|peer| peer.version.is_some_and(|version| version >= request.min_version()))
+        .map(|peer| peer.addr.clone())
+        .collect()
+}
+
+/// Errors of the version handshake
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("Malformed version message: {0}")]
+    Malformed(String),
+
+    #[error("User agent too long: {0} bytes")]
+    UserAgentTooLong(usize),
+
+    #[error("Obsolete protocol version {0}")]
+    ObsoleteVersion(u32),
+
+    #[error("Connected to self")]
+    SelfConnection,
+}
+
+impl HandshakeError {
+    /// Misbehavior of the peer, if the error is its fault
+    ///
+    /// Old peers and self-connections are only disconnected.
+    pub fn misbehavior(&self) -> Option<Misbehavior> {
+        match self {
+            HandshakeError::Malformed(_) | HandshakeError::UserAgentTooLong(_) => Some(Misbehavior::MalformedMessage),
+            HandshakeError::ObsoleteVersion(_) | HandshakeError::SelfConnection => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiation() {
+        let local = VersionMessage::new(local_services(false, false), 10, 1, 0);
+        let pruned = VersionMessage::new(local_services(true, true), 20, 2, 0);
+        let negotiated = negotiate(&local, &pruned).unwrap();
+        assert_eq!(negotiated.version, PROTOCOL_VERSION);
+        assert!(negotiated.compact_blocks && negotiated.compact_filters);
+
+        // A peer at version 1 does not use the new features even if it announces them
+        let old = VersionMessage { version: 1, nonce: 3, ..pruned.clone() };
+        let negotiated = negotiate(&local, &old).unwrap();
+        assert_eq!(negotiated.version, 1);
+        assert!(!negotiated.compact_blocks && !negotiated.compact_filters);
+
+        let obsolete = VersionMessage { version: 0, nonce: 4, ..pruned.clone() };
+        assert_eq!(negotiate(&local, &obsolete), Err(HandshakeError::ObsoleteVersion(0)));
+        assert_eq!(negotiate(&local, &local), Err(HandshakeError::SelfConnection));
+        assert_eq!(HandshakeError::SelfConnection.misbehavior(), None);
+    }
+
+    #[test]
+    fn test_peer_selection() {
+        let stats = Mutex::new(NetStats::new());
+        let local = VersionMessage::new(local_services(false, true), 0, 1, 0);
+        let full = VersionMessage::new(local_services(false, false), 100, 2, 0);
+        let pruned = VersionMessage::new(local_services(true, true), 120, 3, 0);
+        for (peer, message) in [("full", &full), ("pruned", &pruned)] {
+            stats.lock().unwrap().connect(peer, false, 0);
+            receive_version(&stats, peer, &local, &message.to_bytes()).unwrap();
+        }
+        stats.lock().unwrap().connect("silent", false, 0);
+
+        let stats = stats.into_inner().unwrap();
+        assert_eq!(select_peers(&stats, PeerRequest::InitialBlockDownload), vec!["full"]);
+        assert_eq!(select_peers(&stats, PeerRequest::CompactFilters), vec!["pruned"]);
+        assert_eq!(select_peers(&stats, PeerRequest::CompactBlocks), vec!["pruned", "full"]);
+        assert_eq!(stats.peer("pruned").unwrap().user_agent, USER_AGENT);
+
+        let stats = Mutex::new(stats);
+        let error = receive_version(&stats, "full", &local, b"garbage").unwrap_err();
+        assert_eq!(error.misbehavior(), Some(Misbehavior::MalformedMessage));
+        let long = VersionMessage { user_agent: "x".repeat(MAX_USER_AGENT_LEN + 1), ..full };
+        assert_eq!(VersionMessage::from_bytes(&long.to_bytes()), Err(HandshakeError::UserAgentTooLong(257)));
+    }
+}
@@ -0,0 +1,130 @@
+//! HTTP Basic authentication of RPC callers
+//!
+//! Credentials come from `--rpc-user`/`--rpc-password` or, without them,
+//! from a cookie file with a random password written in the data directory
+//! at startup. Local tools read the cookie, so only users who can read the
+//! data directory are authenticated.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the cookie file in the data directory
+pub const COOKIE_FILE_NAME: &str = ".cookie";
+
+/// User of the cookie credentials
+pub const COOKIE_USER: &str = "__cookie__";
+
+/// Credentials accepted by the RPC server
+#[derive(Clone)]
+pub struct RpcAuth {
+    user: String,
+    password: String,
+}
+
+impl RpcAuth {
+    /// Credentials with a user and password chosen by the operator
+    pub fn new(user: impl Into<String>, password: impl Into<String>) -> Self {
+        Self { user: user.into(), password: password.into() }
+    }
+
+    /// Generate a random password and write it to the cookie file at `path`
+    ///
+    /// The file replaces the one of a previous run and is readable by its
+    /// owner only.
+    pub fn write_cookie(path: &Path) -> io::Result<Self> {
+        let mut secret = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_| io::Error::other("No system randomness for the RPC cookie"))?;
+        let auth = Self::new(COOKIE_USER, hex::encode(secret));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, auth.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(auth)
+    }
+
+    /// Credentials of the cookie file at `path`
+    pub fn read_cookie(path: &Path) -> io::Result<Self> {
+        let cookie = fs::read_to_string(path)?;
+        cookie.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Malformed RPC cookie"))
+    }
+
+    /// Value of the `Authorization` header carrying these credentials
+    pub fn header_value(&self) -> String {
+        format!("Basic {}", STANDARD.encode(self.to_string()))
+    }
+
+    /// Whether an `Authorization` header carries these credentials
+    ///
+    /// Digests are compared instead of the secrets, so the time taken does
+    /// not tell how much of a guessed password is right.
+    pub fn verify(&self, header: &[u8]) -> bool {
+        let Some(encoded) = header.strip_prefix(b"Basic ") else {
+            return false;
+        };
+        let Ok(decoded) = STANDARD.decode(encoded.trim_ascii()) else {
+            return false;
+        };
+        Sha256::digest(decoded) == Sha256::digest(self.to_string())
+    }
+}
+
+impl std::fmt::Display for RpcAuth {
+    /// `user:password`, as in the cookie file and the Basic header
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.user, self.password)
+    }
+}
+
+impl std::fmt::Debug for RpcAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcAuth").field("user", &self.user).finish_non_exhaustive()
+    }
+}
+
+impl std::str::FromStr for RpcAuth {
+    type Err = ();
+
+    /// Parse `user:password`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, password) = s.split_once(':').ok_or(())?;
+        Ok(Self::new(user, password))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cookie_and_header() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(COOKIE_FILE_NAME);
+        let auth = RpcAuth::write_cookie(&path).unwrap();
+        let read = RpcAuth::read_cookie(&path).unwrap();
+        assert!(auth.verify(read.header_value().as_bytes()));
+        assert!(auth.to_string().starts_with("__cookie__:"));
+
+        // A new run replaces the cookie: the old password no longer works
+        let next = RpcAuth::write_cookie(&path).unwrap();
+        assert!(!next.verify(auth.header_value().as_bytes()));
+
+        let header = RpcAuth::new("alice", "secret").header_value();
+        assert_eq!(header, "Basic YWxpY2U6c2VjcmV0");
+        assert!(RpcAuth::new("alice", "secret").verify(header.as_bytes()));
+        assert!(!RpcAuth::new("alice", "other").verify(header.as_bytes()));
+        assert!(!RpcAuth::new("alice", "secret").verify(b"Bearer YWxpY2U6c2VjcmV0"));
+        assert!(!RpcAuth::new("alice", "secret").verify(b"Basic !!"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Blocking JSON-RPC client for the command line tools
+//!
+//! `sedly` and the external miner talk to a node over the same HTTP
+//! endpoint as any other client. One request per connection keeps the
+//! client free of an HTTP stack: the request is written with
+//! `Connection: close` and the response read until the server closes it.
+
+use crate::auth::RpcAuth;
+use crate::server::{RpcRequest, RpcResponse};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Default time a call may take, long enough for a `getblocktemplate` long poll
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// JSON-RPC client of a Sedly node
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    addr: String,
+    timeout: Duration,
+    auth: Option<RpcAuth>,
+}
+
+impl RpcClient {
+    /// Client of the server listening on `addr` (host:port)
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: DEFAULT_CLIENT_TIMEOUT,
+            auth: None,
+        }
+    }
+
+    /// Authenticate the calls, as needed by the wallet methods
+    pub fn with_auth(mut self, auth: RpcAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Fail calls that get no response within `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Call `method` and return its result
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let request = RpcRequest {
+            jsonrpc: Some("2.0".to_string()),
+            id: Value::from(1),
+            method: method.to_string(),
+            params,
+        };
+        let body = serde_json::to_vec(&request)?;
+
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let authorization = self.auth.as_ref()
+            .map(|auth| format!("Authorization: {}\r\n", auth.header_value()))
+            .unwrap_or_default();
+        let head = format!(
+            concat!(
+                "POST / HTTP/1.1\r\n",
+                "Host: {}\r\n",
+                "{}",
+                "Content-Type: application/json\r\n",
+                "Content-Length: {}\r\n",
+                "Connection: close\r\n\r\n",
+            ),
+            self.addr,
+            authorization,
+            body.len()
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&body)?;
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        let response: RpcResponse = serde_json::from_slice(http_body(&raw)?)?;
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(ClientError::Rpc { code: error.code, message: error.message }),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(Value::Null),
+        }
+    }
+}
+
+/// Body of a raw HTTP response with a 200 status
+fn http_body(raw: &[u8]) -> Result<&[u8], ClientError> {
+    let split = raw.windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| ClientError::Http("Truncated response".to_string()))?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(ClientError::Http(status.to_string()));
+    }
+    if head.lines().any(|line| line.to_ascii_lowercase().starts_with("transfer-encoding:")) {
+        return Err(ClientError::Http("Chunked responses are not supported".to_string()));
+    }
+    Ok(&raw[split + 4..])
+}
+
+/// Errors of an RPC call
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("Invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RpcAuth, RpcConfig, RpcContext, RpcServer};
+    use sedly_core::{BlockchainDB, ChainParams};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_client_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let auth = RpcAuth::new("alice", "secret");
+        let config = RpcConfig { auth: Some(auth.clone()), ..RpcConfig::default() };
+        let server = RpcServer::new(config, RpcContext::new(db, ChainParams::regtest()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = RpcClient::new(listener.local_addr().unwrap().to_string());
+        let router = server.router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let (result, error, locks, wrong) = tokio::task::spawn_blocking(move || {
+            let wrong = client.clone().with_auth(RpcAuth::new("alice", "guess")).call("getnetworkparams", Value::Null);
+            let locks = [
+                client.call("listlockunspent", Value::Null),
+                client.clone().with_auth(auth).call("listlockunspent", Value::Null),
+            ];
+            (client.call("getnetworkparams", Value::Null), client.call("nosuchmethod", Value::Null), locks, wrong)
+        })
+        .await
+        .unwrap();
+        assert!(result.unwrap().is_object());
+        assert!(matches!(error, Err(ClientError::Rpc { code: -32601, .. })));
+        // The wallet methods need credentials, and wrong ones fail the whole request
+        let [anonymous, authenticated] = locks;
+        assert!(matches!(anonymous, Err(ClientError::Rpc { code: -32011, .. })));
+        assert_eq!(authenticated.unwrap(), Value::Array(Vec::new()));
+        assert!(matches!(wrong, Err(ClientError::Http(status)) if status.contains("401")));
+    }
+}
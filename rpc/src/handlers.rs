@@ -0,0 +1,4096 @@
+//! RPC method handlers
+//!
+//! Block hashes, txids and other 32-byte hashes are hex in display order
+//! (byte-reversed, as in Bitcoin Core); see `sedly_core::hashes`.
+
+use crate::server::{RpcContext, RpcError};
+use sedly_core::reorg::{self, ReorgError, ReorgReport};
+use sedly_core::codec::{decode_transaction, MAX_BLOCK_DECODE_SIZE, MAX_TX_DECODE_SIZE};
+use sedly_core::supply::{estimate_next_halving_with_interval, supply_with_interval, MAX_HALVINGS};
+use sedly_core::block::bits_to_target;
+use sedly_core::validation::{chain_timestamps, median_time_past};
+use sedly_core::script::{hash160, script_asm, MAX_SCRIPT_SIZE};
+use sedly_core::{
+    block_stats, Amount, decode_block, BlockOutcome, BlockStatsError, ChainSnapshot,
+    BlockHash, BlockPipeline, CancellationToken, DecodeError, HardwareReport, Hash256, Txid,
+    DifficultyAdjuster, EpochSummary, HalvingEstimate, HeaderCache, HeaderStatus, OutPoint, PipelineError,
+    LongPollId, MempoolError, MAX_BLOCK_SIZE, PROTOCOL_VERSION, ScriptTemplate, SignedAlert, StateScript, StorageError,
+    TipStatus, Transaction, TxInput, TxOutput, UtxoSetStats,
+};
+use sedly_wallet::{
+    account_balances, key_utxos, Balance, BuildError, Descriptor, KeystoreError, PrivateKey, SweepBuilder, SweepError,
+    TransactionBuilder, WalletUtxo,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of blocks scanned by a single history request
+pub const MAX_HISTORY_BLOCKS: u64 = 20_160;
+
+/// Default number of items in a page of a list method
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// Maximum number of items in a page of a list method
+pub const MAX_PAGE_LIMIT: usize = 1_000;
+
+/// Maximum number of transactions of a `testmempoolaccept` call
+pub const MAX_TEST_ACCEPT_TXS: usize = 25;
+
+/// Parse positional or named params into a typed struct
+pub(crate) fn parse_params<T: DeserializeOwned + Default>(params: &Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params.clone()).map_err(|e| RpcError::InvalidParams(e.to_string()))
+}
+
+/// One page of a list method
+///
+/// List methods take optional `cursor` and `limit` params. Pass
+/// `next_cursor` back as `cursor` to get the following page; it is null on
+/// the last page. Cursors are opaque strings.
+pub use sedly_core::Page;
+
+/// Validate the `limit` param of a list method
+fn page_limit(limit: Option<usize>) -> Result<usize, RpcError> {
+    match limit {
+        Some(0) => Err(RpcError::InvalidParams("Limit must be positive".to_string())),
+        Some(limit) => Ok(limit.min(MAX_PAGE_LIMIT)),
+        None => Ok(DEFAULT_PAGE_LIMIT),
+    }
+}
+
+/// Serialize a handler result
+pub(crate) fn to_value<T: Serialize>(value: &T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::Internal(e.to_string()))
+}
+
+/// Params for `getdifficultyhistory`
+#[derive(Debug, Default, Deserialize)]
+struct DifficultyHistoryParams {
+    /// First height (default: tip - MAX_HISTORY_BLOCKS + 1)
+    #[serde(default)]
+    from_height: Option<u64>,
+    /// Last height (default: tip)
+    #[serde(default)]
+    to_height: Option<u64>,
+}
+
+/// Difficulty/hashrate time series for charts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DifficultyHistory {
+    /// First height covered
+    pub from_height: u64,
+    /// Last height covered
+    pub to_height: u64,
+    /// Blocks per epoch
+    pub adjustment_interval: u64,
+    /// One point per (partial) epoch in the range
+    pub epochs: Vec<EpochSummary>,
+}
+
+/// `getdifficultyhistory [from_height] [to_height]`
+///
+/// Per-epoch difficulty, estimated network hashrate and average block
+/// interval computed from stored headers.
+pub fn get_difficulty_history(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: DifficultyHistoryParams = parse_params(params)?;
+
+    let tip = context.db.get_height()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let to_height = params.to_height.unwrap_or(tip).min(tip);
+    let from_height = params
+        .from_height
+        .unwrap_or_else(|| to_height.saturating_sub(MAX_HISTORY_BLOCKS - 1));
+
+    if from_height > to_height {
+        return Err(RpcError::InvalidParams(format!(
+            "from_height {} is above to_height {}", from_height, to_height
+        )));
+    }
+    if to_height - from_height >= MAX_HISTORY_BLOCKS {
+        return Err(RpcError::InvalidParams(format!(
+            "Range too large: at most {} blocks per request", MAX_HISTORY_BLOCKS
+        )));
+    }
+
+    let headers = context.db.get_headers_in_range(from_height, to_height)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+
+    let adjuster = DifficultyAdjuster::from_params(&context.params);
+    let history = DifficultyHistory {
+        from_height,
+        to_height: headers.last().map(|header| header.height).unwrap_or(from_height),
+        adjustment_interval: context.params.difficulty_adjustment_interval,
+        epochs: adjuster.summarize_epochs(&headers),
+    };
+
+    to_value(&history)
+}
+
+/// State of the running `scantxoutset`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ScanState {
+    /// Cancellation requested by `scantxoutset abort`
+    cancel: CancellationToken,
+    /// First two bytes of the last scanned txid (keys are ordered by txid)
+    position: Arc<AtomicU32>,
+}
+
+impl ScanState {
+    /// Estimated progress in percent
+    fn progress(&self) -> f64 {
+        self.position.load(Ordering::Relaxed) as f64 / 65536.0 * 100.0
+    }
+}
+
+/// Params for `scantxoutset`
+#[derive(Debug, Default, Deserialize)]
+struct ScanTxOutSetParams {
+    /// "start", "abort" or "status"
+    #[serde(default)]
+    action: String,
+    /// Descriptors or script hex to look for
+    #[serde(default)]
+    scanobjects: Vec<ScanObject>,
+}
+
+/// Default derivation range for ranged descriptors (`0..=999`)
+const DEFAULT_SCAN_RANGE: u32 = 1000;
+/// Maximum number of scripts derived from a single scan object
+const MAX_SCAN_RANGE: u32 = 100_000;
+
+/// Scan object: a descriptor string or `{"desc": ..., "range": ...}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScanObject {
+    Descriptor(String),
+    Ranged {
+        desc: String,
+        #[serde(default)]
+        range: Option<ScanRange>,
+    },
+}
+
+/// Derivation range: end index or `[begin, end]` (inclusive)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum ScanRange {
+    End(u32),
+    Bounds(u32, u32),
+}
+
+/// Unspent output matched by `scantxoutset`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedUtxo {
+    /// Transaction id
+    pub txid: Txid,
+    /// Output index
+    pub vout: u32,
+    /// Locking script (hex)
+    pub script_pubkey: String,
+    /// Scan object that matched
+    pub desc: String,
+    /// Output value
+    pub amount: Amount,
+    /// Asset id (hex)
+    pub asset_id: String,
+    /// Height of the block that created the output
+    pub height: u64,
+    /// Whether the output is a coinbase output
+    pub coinbase: bool,
+}
+
+/// Result of `scantxoutset start`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanTxOutSetResult {
+    /// False if the scan was aborted
+    pub success: bool,
+    /// Number of unspent outputs scanned
+    pub txouts: u64,
+    /// Chain height when the scan started
+    pub height: u64,
+    /// Best block hash when the scan started
+    pub bestblock: BlockHash,
+    /// Matching unspent outputs
+    pub unspents: Vec<ScannedUtxo>,
+    /// Total of native SLY outputs
+    pub total_amount: Amount,
+    /// Totals of other assets keyed by asset id (hex)
+    pub asset_amounts: BTreeMap<String, Amount>,
+}
+
+/// Expand a scan object into the scripts it matches
+///
+/// Plain hex is accepted as a shorthand for `raw(<hex>)`.
+fn expand_scan_object(object: &ScanObject) -> Result<(String, Vec<Vec<u8>>), RpcError> {
+    let (desc, range) = match object {
+        ScanObject::Descriptor(desc) => (desc, None),
+        ScanObject::Ranged { desc, range } => (desc, *range),
+    };
+    let descriptor = parse_descriptor(desc)?;
+    let range = derivation_range(range)?;
+
+    let scripts = descriptor
+        .script_pubkeys(range)
+        .map_err(|e| RpcError::InvalidParams(format!("Cannot derive '{}': {}", desc, e)))?;
+    Ok((desc.clone(), scripts.into_iter().map(|(_, script)| script).collect()))
+}
+
+/// Parse a descriptor, or plain script hex as a shorthand for `raw(<hex>)`
+fn parse_descriptor(desc: &str) -> Result<Descriptor, RpcError> {
+    if desc.contains('(') {
+        desc.parse().map_err(|e| RpcError::InvalidParams(format!("Invalid descriptor '{}': {}", desc, e)))
+    } else {
+        hex::decode(desc)
+            .map(Descriptor::Raw)
+            .map_err(|_| RpcError::InvalidParams(format!("Invalid scan object: {}", desc)))
+    }
+}
+
+/// Indices derived from a ranged descriptor (default 0..=999)
+fn derivation_range(range: Option<ScanRange>) -> Result<Range<u32>, RpcError> {
+    let range = match range {
+        None => 0..DEFAULT_SCAN_RANGE,
+        Some(ScanRange::End(end)) => 0..end.saturating_add(1),
+        Some(ScanRange::Bounds(begin, end)) if begin <= end => begin..end.saturating_add(1),
+        Some(ScanRange::Bounds(begin, end)) => {
+            return Err(RpcError::InvalidParams(format!("Invalid range [{}, {}]", begin, end)));
+        }
+    };
+    if range.len() > MAX_SCAN_RANGE as usize {
+        return Err(RpcError::InvalidParams(format!("Range too large: at most {} scripts", MAX_SCAN_RANGE)));
+    }
+    Ok(range)
+}
+
+/// `scantxoutset <action> [scanobjects]`
+///
+/// Scans the whole UTXO set for outputs paying to the given descriptors
+/// (ranged ones are expanded over `range`, default 0..=999). Only one scan
+/// runs at a time; `abort` cancels it and `status` reports its progress.
+pub fn scan_tx_out_set(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ScanTxOutSetParams = parse_params(params)?;
+
+    match params.action.as_str() {
+        "start" => start_scan(context, &params.scanobjects),
+        "abort" => {
+            let scan = context.utxo_scan.lock().unwrap();
+            if let Some(state) = scan.as_ref() {
+                state.cancel.cancel();
+            }
+            Ok(Value::Bool(scan.is_some()))
+        }
+        "status" => {
+            let scan = context.utxo_scan.lock().unwrap();
+            Ok(match scan.as_ref() {
+                Some(state) => serde_json::json!({ "progress": state.progress() }),
+                None => Value::Null,
+            })
+        }
+        other => Err(RpcError::InvalidParams(format!(
+            "Invalid action '{}': expected start, abort or status", other
+        ))),
+    }
+}
+
+fn start_scan(context: &RpcContext, objects: &[ScanObject]) -> Result<Value, RpcError> {
+    if objects.is_empty() {
+        return Err(RpcError::InvalidParams("No scan objects given".to_string()));
+    }
+    let mut scripts: HashMap<Vec<u8>, String> = HashMap::new();
+    for object in objects {
+        let (desc, expanded) = expand_scan_object(object)?;
+        for script in expanded {
+            scripts.insert(script, desc.clone());
+        }
+    }
+
+    let state = ScanState::default();
+    {
+        let mut scan = context.utxo_scan.lock().unwrap();
+        if scan.is_some() {
+            return Err(RpcError::InvalidParams("Scan already in progress".to_string()));
+        }
+        *scan = Some(state.clone());
+    }
+
+    // Tip and UTXO set from the same snapshot, so the result is never torn
+    let snapshot = context.db.snapshot();
+    let result = snapshot.get_metadata().and_then(|metadata| {
+        let scan = snapshot.scan_utxos(&state.cancel, |outpoint, entry| {
+            let position = u16::from_be_bytes([outpoint.txid[0], outpoint.txid[1]]);
+            state.position.store(position as u32, Ordering::Relaxed);
+            scripts.contains_key(&entry.output.script_pubkey)
+        });
+        scan.map(|scan| (metadata, scan))
+    });
+    *context.utxo_scan.lock().unwrap() = None;
+
+    let (metadata, scan) = match result {
+        Ok(result) => result,
+        Err(StorageError::Cancelled) => return Ok(serde_json::json!({ "success": false })),
+        Err(e) => return Err(RpcError::DatabaseError(e.to_string())),
+    };
+
+    let mut total_amount = Amount::ZERO;
+    let mut asset_amounts: BTreeMap<String, Amount> = BTreeMap::new();
+    let unspents = scan.matches.into_iter()
+        .map(|(outpoint, entry)| {
+            let output = entry.output;
+            if output.is_native_asset() {
+                total_amount = total_amount.saturating_add(output.value);
+            } else {
+                let total = asset_amounts.entry(hex::encode(output.asset_id)).or_default();
+                *total = total.saturating_add(output.value);
+            }
+            ScannedUtxo {
+                txid: outpoint.txid.into(),
+                vout: outpoint.vout,
+                desc: scripts[&output.script_pubkey].clone(),
+                script_pubkey: hex::encode(&output.script_pubkey),
+                amount: output.value,
+                asset_id: hex::encode(output.asset_id),
+                height: entry.block_height,
+                coinbase: entry.is_coinbase,
+            }
+        })
+        .collect();
+
+    to_value(&ScanTxOutSetResult {
+        success: true,
+        txouts: scan.scanned,
+        height: metadata.height,
+        bestblock: metadata.best_block_hash.into(),
+        unspents,
+        total_amount,
+        asset_amounts,
+    })
+}
+
+/// Result of `gettxoutsetinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxOutSetInfo {
+    /// Chain height of the statistics
+    pub height: u64,
+    /// Best block hash of the statistics
+    pub bestblock: BlockHash,
+    /// Transactions with at least one unspent output
+    pub transactions: u64,
+    /// Number of unspent outputs
+    pub txouts: u64,
+    /// Serialized size of the UTXO set in bytes
+    pub bogosize: u64,
+    /// Commitment to the serialized UTXO set
+    pub hash_serialized: Hash256,
+    /// MuHash of the UTXO set, as kept per block by `getutxosethash`
+    pub muhash: Hash256,
+    /// Total of native SLY outputs
+    pub total_amount: Amount,
+    /// Totals of other assets keyed by asset id (hex)
+    pub asset_amounts: BTreeMap<String, Amount>,
+}
+
+impl From<&UtxoSetStats> for TxOutSetInfo {
+    fn from(stats: &UtxoSetStats) -> Self {
+        Self {
+            height: stats.height,
+            bestblock: stats.best_block_hash.into(),
+            transactions: stats.transactions,
+            txouts: stats.txouts,
+            bogosize: stats.serialized_size,
+            hash_serialized: stats.hash.into(),
+            muhash: stats.muhash.into(),
+            total_amount: Amount::from_sat(stats.total_amount),
+            asset_amounts: stats.asset_amounts
+                .iter()
+                .map(|(asset_id, amount)| (hex::encode(asset_id), Amount::from_sat(*amount)))
+                .collect(),
+        }
+    }
+}
+
+/// `gettxoutsetinfo`
+///
+/// Statistics and commitment of the UTXO set at the tip, computed by a
+/// full scan of a database snapshot. The result is cached until the tip
+/// changes; while a scan runs, other calls report its progress instead.
+pub fn get_tx_out_set_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let tip = context.db.get_best_block_hash()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    if let Some(stats) = context.utxo_stats.lock().unwrap().as_ref() {
+        if stats.best_block_hash == tip {
+            return to_value(&TxOutSetInfo::from(stats));
+        }
+    }
+
+    let state = ScanState::default();
+    {
+        let mut scan = context.utxo_stats_scan.lock().unwrap();
+        if let Some(running) = scan.as_ref() {
+            return Ok(serde_json::json!({ "in_progress": true, "progress": running.progress() }));
+        }
+        *scan = Some(state.clone());
+    }
+
+    let result = context.db.utxo_set_stats(&state.cancel, |outpoint| {
+        let position = u16::from_be_bytes([outpoint.txid[0], outpoint.txid[1]]);
+        state.position.store(position as u32, Ordering::Relaxed);
+    });
+    *context.utxo_stats_scan.lock().unwrap() = None;
+
+    let stats = result.map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let info = TxOutSetInfo::from(&stats);
+    *context.utxo_stats.lock().unwrap() = Some(stats);
+    to_value(&info)
+}
+
+/// Params for `getutxosethash`
+#[derive(Debug, Default, Deserialize)]
+struct UtxoSetHashParams {
+    /// Block height or hash (hex), the tip if omitted
+    hash_or_height: Option<HashOrHeight>,
+}
+
+/// Result of `getutxosethash`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoSetHashInfo {
+    /// Block height
+    pub height: u64,
+    /// Block hash
+    pub blockhash: BlockHash,
+    /// MuHash of the UTXO set after the block
+    pub muhash: Hash256,
+}
+
+/// `getutxosethash ( hash_or_height )`
+///
+/// Rolling MuHash of the UTXO set after a block, maintained as blocks are
+/// connected. Unlike `gettxoutsetinfo` it needs no scan, so nodes can
+/// compare their state at any height cheaply.
+pub fn get_utxo_set_hash(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: UtxoSetHashParams = parse_params(params)?;
+
+    let snapshot = context.db.snapshot();
+    let header = match &params.hash_or_height {
+        None => {
+            let metadata = snapshot.get_metadata()
+                .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+            snapshot.get_block(&metadata.best_block_hash)
+        }
+        Some(HashOrHeight::Height(height)) => snapshot.get_block_by_height(*height),
+        Some(HashOrHeight::Hash(hash)) => snapshot.get_block(&parse_block_hash(hash)?),
+    }
+    .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| RpcError::NotFound("Block not found".to_string()))?
+    .header;
+
+    let block_hash = header.hash();
+    let muhash = snapshot.get_utxo_hash(&block_hash)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| RpcError::NotFound("No UTXO set hash for the block".to_string()))?;
+    to_value(&UtxoSetHashInfo {
+        height: header.height,
+        blockhash: block_hash.into(),
+        muhash: muhash.into(),
+    })
+}
+
+/// Result of `gettreasuryinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreasuryInfo {
+    /// Whether the network funds a treasury
+    pub enabled: bool,
+    /// Treasury locking script (hex)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script_pubkey: Option<String>,
+    /// Signatures required to spend, for multisig treasuries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<usize>,
+    /// Custodian public keys (hex), for multisig treasuries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pubkeys: Vec<String>,
+    /// Share of the block subsidy, in basis points
+    pub share_bps: u64,
+    /// Allocation owed by the next block
+    pub next_allocation: Amount,
+    /// Unspent native SLY held by the treasury
+    pub balance: Amount,
+    /// Number of unspent treasury outputs
+    pub utxos: u64,
+    /// Chain height of the balance
+    pub height: u64,
+    /// Best block hash of the balance
+    pub bestblock: BlockHash,
+}
+
+/// `gettreasuryinfo`
+///
+/// Treasury script and share from the chain params, and its balance
+/// computed by scanning the UTXO set.
+pub fn get_treasury_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let snapshot = context.db.snapshot();
+    let metadata = snapshot.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+
+    let Some(treasury) = &context.params.treasury else {
+        return to_value(&TreasuryInfo {
+            enabled: false,
+            script_pubkey: None,
+            threshold: None,
+            pubkeys: Vec::new(),
+            share_bps: 0,
+            next_allocation: Amount::ZERO,
+            balance: Amount::ZERO,
+            utxos: 0,
+            height: metadata.height,
+            bestblock: metadata.best_block_hash.into(),
+        });
+    };
+
+    let scan = snapshot
+        .scan_utxos(&CancellationToken::new(), |_, entry| {
+            entry.output.is_native_asset() && entry.output.script_pubkey == treasury.script_pubkey
+        })
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let balance = scan.matches.iter()
+        .fold(Amount::ZERO, |total, (_, entry)| total.saturating_add(entry.output.value));
+
+    let (threshold, pubkeys) = match ScriptTemplate::classify(&treasury.script_pubkey) {
+        ScriptTemplate::Multisig { threshold, pubkeys } => (Some(threshold), pubkeys.iter().map(hex::encode).collect()),
+        _ => (None, Vec::new()),
+    };
+
+    to_value(&TreasuryInfo {
+        enabled: true,
+        script_pubkey: Some(hex::encode(&treasury.script_pubkey)),
+        threshold,
+        pubkeys,
+        share_bps: treasury.share_bps,
+        next_allocation: Amount::from_sat(treasury.allocation(context.params.subsidy(metadata.height + 1))),
+        balance,
+        utxos: scan.matches.len() as u64,
+        height: metadata.height,
+        bestblock: metadata.best_block_hash.into(),
+    })
+}
+
+/// Result of `getnetworkparams`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkParamsInfo {
+    /// Network name
+    pub network: String,
+    /// Network magic bytes (hex)
+    pub magic: String,
+    /// Default P2P port
+    pub default_port: u16,
+    /// Protocol version
+    pub protocol_version: u32,
+    /// Target time between blocks, in seconds
+    pub target_block_time: u64,
+    /// Blocks per difficulty adjustment
+    pub difficulty_adjustment_interval: u64,
+    /// Maximum difficulty change factor per adjustment
+    pub max_difficulty_adjustment: f64,
+    /// Difficulty algorithm
+    pub difficulty_algorithm: DifficultyAlgorithmInfo,
+    /// Blocks between subsidy halvings
+    pub halving_interval: u64,
+    /// Subsidy of the first epoch
+    pub initial_block_reward: Amount,
+    /// Subsidy of the next block
+    pub next_block_subsidy: Amount,
+    /// Maximum block size in bytes currently enforced
+    pub max_block_size: usize,
+    /// Confirmations before a coinbase output can be spent
+    pub coinbase_maturity: u64,
+    /// Minimum transaction fee
+    pub min_tx_fee: Amount,
+    /// Share of the subsidy funding the treasury, in basis points (0 if disabled)
+    pub treasury_share_bps: u64,
+    /// Scheduled soft forks
+    pub softforks: Vec<SoftForkInfo>,
+    /// Chain height the activation status refers to
+    pub height: u64,
+}
+
+/// Difficulty algorithm of `getnetworkparams`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DifficultyAlgorithmInfo {
+    /// Proof of work hash function
+    pub pow: String,
+    /// Window measured by the retarget
+    pub retarget_window: String,
+}
+
+/// Soft fork entry of `getnetworkparams`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftForkInfo {
+    /// Deployment name
+    pub name: String,
+    /// First block enforcing the new rules
+    pub activation_height: u64,
+    /// Whether the next block enforces the new rules
+    pub active: bool,
+}
+
+/// `getnetworkparams`
+///
+/// Consensus parameters of the network, so wallets and explorers don't have
+/// to hardcode them. The maximum block size is the one currently enforced,
+/// which governance may have raised.
+pub fn get_network_params(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let metadata = context.db.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let max_block_size = context.pipeline.lock().unwrap().validator().max_block_size();
+    let params = &context.params;
+    let next_height = metadata.height + 1;
+
+    to_value(&NetworkParamsInfo {
+        network: params.network.name().to_string(),
+        magic: hex::encode(params.magic),
+        default_port: params.network.default_port(),
+        protocol_version: sedly_core::PROTOCOL_VERSION,
+        target_block_time: params.target_block_time,
+        difficulty_adjustment_interval: params.difficulty_adjustment_interval,
+        max_difficulty_adjustment: params.max_difficulty_adjustment,
+        difficulty_algorithm: DifficultyAlgorithmInfo {
+            pow: "sha256d".to_string(),
+            retarget_window: params.retarget_window.name().to_string(),
+        },
+        halving_interval: params.halving_interval,
+        initial_block_reward: Amount::from_sat(sedly_core::INITIAL_BLOCK_REWARD),
+        next_block_subsidy: Amount::from_sat(params.subsidy(next_height)),
+        max_block_size,
+        coinbase_maturity: params.coinbase_maturity,
+        min_tx_fee: Amount::from_sat(sedly_core::MIN_TX_FEE),
+        treasury_share_bps: params.treasury.as_ref().map_or(0, |treasury| treasury.share_bps),
+        softforks: params.soft_forks.iter()
+            .map(|fork| SoftForkInfo {
+                name: fork.name.clone(),
+                activation_height: fork.activation_height,
+                active: fork.is_active(next_height),
+            })
+            .collect(),
+        height: metadata.height,
+    })
+}
+
+/// Params for `getsupplyinfo`
+#[derive(Debug, Default, Deserialize)]
+struct SupplyInfoParams {
+    /// Height to report subsidy and supply at (default: tip)
+    #[serde(default)]
+    height: Option<u64>,
+}
+
+/// Result of `getsupplyinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplyInfo {
+    /// Height the subsidy and supply refer to
+    pub height: u64,
+    /// Subsidy of the block at `height`
+    pub subsidy: Amount,
+    /// Subsidy issued by blocks 1 to `height`, genesis premine excluded
+    pub supply: Amount,
+    /// Total subsidy ever issued
+    pub max_supply: Amount,
+    /// Current chain height
+    pub tip_height: u64,
+    /// Average block interval of the last epoch, in seconds
+    pub average_block_time: f64,
+    /// Next halving after the tip, if the subsidy is not exhausted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_halving: Option<HalvingEstimate>,
+}
+
+/// `getsupplyinfo [height]`
+///
+/// Subsidy and cumulative supply at any height, past or future, and the
+/// next halving projected from the average interval of the last
+/// `difficulty_adjustment_interval` blocks.
+pub fn get_supply_info(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: SupplyInfoParams = parse_params(params)?;
+
+    let snapshot = context.db.snapshot();
+    let metadata = snapshot.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let window_start = metadata.height.saturating_sub(context.params.difficulty_adjustment_interval);
+    let headers = snapshot.get_headers_in_range(window_start, metadata.height)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+
+    let average_block_time = match (headers.first(), headers.last()) {
+        (Some(first), Some(last)) if headers.len() > 1 && last.timestamp > first.timestamp => {
+            (last.timestamp - first.timestamp) as f64 / (headers.len() - 1) as f64
+        }
+        _ => context.params.target_block_time as f64,
+    };
+    let tip_time = headers.last().map(|header| header.timestamp).unwrap_or_default();
+
+    let height = params.height.unwrap_or(metadata.height);
+    let halving_interval = context.params.halving_interval;
+    to_value(&SupplyInfo {
+        height,
+        subsidy: Amount::from_sat(context.params.subsidy(height)),
+        supply: Amount::from_sat(supply_with_interval(height, halving_interval)),
+        max_supply: Amount::from_sat(supply_with_interval(MAX_HALVINGS * halving_interval, halving_interval)),
+        tip_height: metadata.height,
+        average_block_time,
+        next_halving: estimate_next_halving_with_interval(
+            metadata.height,
+            tip_time,
+            average_block_time,
+            halving_interval,
+        ),
+    })
+}
+
+/// Block selected by height or by hash
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum HashOrHeight {
+    Height(u64),
+    Hash(String),
+}
+
+/// Params for `getblockstats`
+#[derive(Debug, Default, Deserialize)]
+struct BlockStatsParams {
+    /// Block height or hash (hex)
+    hash_or_height: Option<HashOrHeight>,
+}
+
+/// Result of `getblockstats`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockStatsInfo {
+    /// Block height
+    pub height: u64,
+    /// Block hash
+    pub blockhash: BlockHash,
+    /// Block timestamp
+    pub time: u64,
+    /// Number of transactions, coinbase included
+    pub txs: u64,
+    /// Number of inputs, coinbase excluded
+    pub ins: u64,
+    /// Number of outputs, coinbase included
+    pub outs: u64,
+    /// Serialized block size in bytes
+    pub total_size: u64,
+    /// Block weight
+    pub total_weight: u64,
+    /// Native SLY paid by non-coinbase outputs
+    pub total_out: Amount,
+    /// Sum of the fees
+    pub totalfee: Amount,
+    /// Block subsidy
+    pub subsidy: Amount,
+    /// Lowest fee of a transaction
+    pub minfee: Amount,
+    /// Highest fee of a transaction
+    pub maxfee: Amount,
+    /// Lowest feerate, in satoshi per byte
+    pub minfeerate: u64,
+    /// Median feerate, in satoshi per byte
+    pub medianfeerate: u64,
+    /// Highest feerate, in satoshi per byte
+    pub maxfeerate: u64,
+}
+
+/// `getblockstats hash_or_height`
+///
+/// Per-block statistics for explorer charts, computed from the stored block
+/// and the outputs it spends.
+pub fn get_block_stats(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: BlockStatsParams = parse_params(params)?;
+
+    let hash_or_height = params.hash_or_height
+        .ok_or_else(|| RpcError::InvalidParams("Missing hash_or_height".to_string()))?;
+
+    let snapshot = context.db.snapshot();
+    let block = match &hash_or_height {
+        HashOrHeight::Height(height) => snapshot.get_block_by_height(*height),
+        HashOrHeight::Hash(hash) => snapshot.get_block(&parse_block_hash(hash)?),
+    }
+    .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| RpcError::InvalidParams("Block not found".to_string()))?;
+
+    let stats = block_stats(&snapshot, &block).map_err(|e| match e {
+        BlockStatsError::Storage(e) => RpcError::DatabaseError(e.to_string()),
+        e => RpcError::Internal(e.to_string()),
+    })?;
+    to_value(&BlockStatsInfo {
+        height: stats.height,
+        blockhash: stats.hash.into(),
+        time: stats.time,
+        txs: stats.txs,
+        ins: stats.ins,
+        outs: stats.outs,
+        total_size: stats.total_size,
+        total_weight: stats.total_weight,
+        total_out: Amount::from_sat(stats.total_out),
+        totalfee: Amount::from_sat(stats.total_fee),
+        subsidy: Amount::from_sat(stats.subsidy),
+        minfee: Amount::from_sat(stats.min_fee),
+        maxfee: Amount::from_sat(stats.max_fee),
+        minfeerate: stats.min_feerate,
+        medianfeerate: stats.median_feerate,
+        maxfeerate: stats.max_feerate,
+    })
+}
+
+/// Entry of `getchaintips`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTipInfo {
+    /// Height of the tip
+    pub height: u64,
+    /// Hash of the tip
+    pub hash: BlockHash,
+    /// Blocks between the tip and the active chain (0 for the active tip)
+    pub branchlen: u64,
+    /// Cumulative work up to the tip (hex)
+    pub chainwork: String,
+    /// `active`, `valid-fork`, `headers-only` or `invalid`
+    pub status: TipStatus,
+}
+
+/// `getchaintips`
+///
+/// All known chain tips, including stale forks and branches known only
+/// by their headers, highest first.
+pub fn get_chain_tips(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let headers = synced_headers(context)?;
+    let tips: Vec<ChainTipInfo> = headers
+        .as_ref()
+        .map(|headers| headers.tips())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tip| ChainTipInfo {
+            height: tip.height,
+            hash: tip.hash.into(),
+            branchlen: tip.branch_len,
+            chainwork: tip.chainwork.to_hex(),
+            status: tip.status,
+        })
+        .collect();
+    to_value(&tips)
+}
+
+/// Verification progress from which the node counts as synced
+pub const READY_SYNC_PROGRESS: f64 = 0.999;
+
+/// Result of `getblockchaininfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockchainInfo {
+    /// Network name
+    pub chain: String,
+    /// Height of the active chain
+    pub blocks: u64,
+    /// Height of the best known header that is not invalid
+    pub headers: u64,
+    /// Hash of the active tip
+    pub bestblockhash: BlockHash,
+    /// Timestamp of the active tip
+    pub time: u64,
+    /// Cumulative work of the active chain (hex)
+    pub chainwork: String,
+    /// Estimated progress (0 to 1) of the active chain towards the network tip
+    pub verificationprogress: f64,
+    /// Whether the progress is still below [`READY_SYNC_PROGRESS`]
+    pub initialblockdownload: bool,
+}
+
+/// `getblockchaininfo`
+///
+/// Active tip and sync state. The verification progress compares the
+/// chainwork of the tip with the work the network should have added since,
+/// extrapolated from the difficulty of the recent blocks. In consensus mode
+/// it compares it with the best known header instead.
+pub fn get_blockchain_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    to_value(&blockchain_info(context)?)
+}
+
+/// State reported by `getblockchaininfo` and the readiness endpoint
+pub(crate) fn blockchain_info(context: &RpcContext) -> Result<BlockchainInfo, RpcError> {
+    let headers = synced_headers(context)?;
+    let cache = headers.as_ref().ok_or_else(|| RpcError::NotFound("No active chain".to_string()))?;
+    let (hash, tip) = cache.tip().ok_or_else(|| RpcError::NotFound("No active chain".to_string()))?;
+    let best_header = cache.tips()
+        .into_iter()
+        .filter(|tip| tip.status != TipStatus::Invalid)
+        .map(|tip| tip.height)
+        .max()
+        .unwrap_or(tip.height);
+    let progress = if context.clock_sync {
+        cache.sync_progress(unix_now(), context.params.target_block_time)
+    } else {
+        cache.header_progress()
+    };
+
+    Ok(BlockchainInfo {
+        chain: context.params.network.name().to_string(),
+        blocks: tip.height,
+        headers: best_header,
+        bestblockhash: hash.into(),
+        time: tip.timestamp,
+        chainwork: tip.chainwork.to_hex(),
+        verificationprogress: progress,
+        initialblockdownload: progress < READY_SYNC_PROGRESS,
+    })
+}
+
+/// Params for methods taking a single block hash
+#[derive(Debug, Default, Deserialize)]
+struct BlockHashParams {
+    /// Block hash (hex)
+    blockhash: String,
+}
+
+/// Decode a block hash given in display order
+fn parse_block_hash(hash: &str) -> Result<[u8; 32], RpcError> {
+    hash.parse::<BlockHash>()
+        .map(BlockHash::to_byte_array)
+        .map_err(|e| RpcError::InvalidParams(format!("Invalid block hash {}: {}", hash, e)))
+}
+
+/// `reconsiderblock "blockhash"`
+///
+/// Clear the invalid mark of a block and of its descendants, so they are
+/// validated again when next received (e.g. after a validation bug fix).
+pub fn reconsider_block(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    require_chain_control(context, "reconsiderblock")?;
+    let params: BlockHashParams = parse_params(params)?;
+    let hash = parse_block_hash(&params.blockhash)?;
+
+    let cleared = context.db.clear_invalid_block(&hash)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    if !cleared.is_empty() {
+        log::info!("Cleared invalid mark of {} blocks from {}", cleared.len(), params.blockhash);
+        // Statuses changed: reload the header index on next use
+        *context.headers.lock().unwrap() = None;
+    }
+    Ok(Value::Null)
+}
+
+/// Bytes of a template left for the header and the coinbase
+const TEMPLATE_RESERVED_SIZE: usize = 1_000;
+
+/// Interval between two checks of a waiting long poll
+const LONGPOLL_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Params for `getblocktemplate`
+#[derive(Debug, Default, Deserialize)]
+struct BlockTemplateParams {
+    /// `longpollid` of the template the miner is working on
+    #[serde(default)]
+    longpollid: Option<String>,
+}
+
+/// Transaction of a block template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateTx {
+    /// Serialized transaction (hex)
+    pub data: String,
+    /// Transaction id
+    pub txid: Txid,
+    /// Fee paid in native SLY
+    pub fee: Amount,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// 1-based positions of the template transactions it spends from
+    pub depends: Vec<usize>,
+}
+
+/// Treasury payment the coinbase of the template must include
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateTreasury {
+    /// Locking script of the treasury (hex)
+    pub script_pubkey: String,
+    /// Minimum amount
+    pub amount: Amount,
+}
+
+/// Result of `getblocktemplate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTemplateInfo {
+    /// Block version
+    pub version: u32,
+    /// Hash of the tip the block builds on
+    pub previousblockhash: BlockHash,
+    /// Height of the block
+    pub height: u64,
+    /// Compact target (hex)
+    pub bits: String,
+    /// Target the block hash must not exceed (hex)
+    pub target: String,
+    /// Current UNIX time, at least `mintime`
+    pub curtime: u64,
+    /// Minimum timestamp of the block: one second past the median time past
+    pub mintime: u64,
+    /// Maximum value of the coinbase: subsidy plus fees
+    pub coinbasevalue: Amount,
+    /// Treasury share of the subsidy, if the network has a treasury
+    pub treasury: Option<TemplateTreasury>,
+    /// Transactions to include after the coinbase, in order
+    pub transactions: Vec<TemplateTx>,
+    /// Maximum block size in bytes
+    pub sizelimit: usize,
+    /// Id to pass back to wait for the next template
+    pub longpollid: String,
+}
+
+/// `getblocktemplate ( "longpollid" )`
+///
+/// Block template for external miners: the tip to build on, the mempool
+/// transactions with the highest fee rate and the coinbase value. The miner
+/// adds its coinbase and searches the nonce, then calls `submitblock`.
+///
+/// With the `longpollid` of a previous template the call is a long poll:
+/// it only returns once that template is stale, when the tip changes or
+/// the collectable fees grow by `LONGPOLL_FEE_INCREASE_PERCENT`, or after
+/// the long poll timeout of the server with a template that may be
+/// unchanged. Miners keep a long poll open instead of polling.
+pub fn get_block_template(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: BlockTemplateParams = parse_params(params)?;
+    if let Some(longpollid) = &params.longpollid {
+        let previous = longpollid.parse::<LongPollId>()
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+        let deadline = Instant::now() + context.longpoll_timeout;
+        loop {
+            let (tip, fees) = template_state(context)?;
+            if previous.is_stale(&tip, fees) || Instant::now() >= deadline {
+                break;
+            }
+            std::thread::sleep(LONGPOLL_CHECK_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+    to_value(&block_template(context)?)
+}
+
+/// Tip and fees of the template that would be built now
+fn template_state(context: &RpcContext) -> Result<([u8; 32], u64), RpcError> {
+    let tip = context.db.get_best_block_hash()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let max_script_cost = context.pipeline.lock().unwrap().validator().max_block_script_cost();
+    let fees = context.mempool.as_ref()
+        .map(|mempool| {
+            let mempool = mempool.lock().unwrap();
+            mempool.select_for_block(MAX_BLOCK_SIZE - TEMPLATE_RESERVED_SIZE, max_script_cost)
+                .iter()
+                .map(|entry| entry.fee)
+                .sum()
+        })
+        .unwrap_or(0);
+    Ok((tip, fees))
+}
+
+/// Build a template on the current tip
+fn block_template(context: &RpcContext) -> Result<BlockTemplateInfo, RpcError> {
+    let metadata = context.db.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let tip = context.db.get_block(&metadata.best_block_hash)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| RpcError::NotFound("Chain has no tip".to_string()))?;
+    let height = metadata.height + 1;
+    let timestamps = |from, to| chain_timestamps(&context.db, from, to);
+    let (bits, max_script_cost) = {
+        let pipeline = context.pipeline.lock().unwrap();
+        let validator = pipeline.validator();
+        let bits = validator.expected_bits(height, tip.header.bits, timestamps)
+            .map_err(|e| RpcError::Internal(e.to_string()))?;
+        (bits, validator.max_block_script_cost())
+    };
+    let mintime = median_time_past(height, timestamps)
+        .map_err(|e| RpcError::Internal(e.to_string()))?
+        .saturating_add(1);
+
+    let mut transactions: Vec<TemplateTx> = Vec::new();
+    let mut fees = 0;
+    if let Some(mempool) = &context.mempool {
+        let mempool = mempool.lock().unwrap();
+        let mut positions = HashMap::new();
+        for entry in mempool.select_for_block(MAX_BLOCK_SIZE - TEMPLATE_RESERVED_SIZE, max_script_cost) {
+            let txid = entry.tx.hash();
+            let mut depends: Vec<usize> = entry.tx.inputs.iter()
+                .filter_map(|input| positions.get(&input.previous_output.txid).copied())
+                .collect();
+            depends.sort_unstable();
+            depends.dedup();
+            let data = bincode::serialize(&entry.tx).map_err(|e| RpcError::Internal(e.to_string()))?;
+            transactions.push(TemplateTx {
+                data: hex::encode(data),
+                txid: txid.into(),
+                fee: Amount::from_sat(entry.fee),
+                size: entry.size,
+                depends,
+            });
+            positions.insert(txid, transactions.len());
+            fees += entry.fee;
+        }
+    }
+
+    let subsidy = context.params.subsidy(height);
+    let treasury = context.params.treasury.as_ref().map(|treasury| TemplateTreasury {
+        script_pubkey: hex::encode(&treasury.script_pubkey),
+        amount: Amount::from_sat(treasury.allocation(subsidy)),
+    });
+    Ok(BlockTemplateInfo {
+        version: PROTOCOL_VERSION,
+        previousblockhash: metadata.best_block_hash.into(),
+        height,
+        bits: format!("{:08x}", bits),
+        target: hex::encode(bits_to_target(bits)),
+        curtime: unix_now().max(mintime),
+        mintime,
+        coinbasevalue: Amount::from_sat(subsidy.saturating_add(fees)),
+        treasury,
+        transactions,
+        sizelimit: MAX_BLOCK_SIZE,
+        longpollid: LongPollId { tip: metadata.best_block_hash, fees }.to_string(),
+    })
+}
+
+/// Params for `submitblock`
+#[derive(Debug, Default, Deserialize)]
+struct SubmitBlockParams {
+    /// Serialized block (hex)
+    hexdata: String,
+}
+
+/// `submitblock "hexdata"`
+///
+/// Validate and connect a serialized block. As in Bitcoin Core, returns null
+/// when the block is accepted and otherwise a short result: "duplicate",
+/// "duplicate-invalid", "inconclusive" (held until its parent arrives) or
+/// the rejection reason. A node that connects blocks itself receives the
+/// block through its `BlockSubmitter`.
+pub fn submit_block(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: SubmitBlockParams = parse_params(params)?;
+    // Checked before hex decoding to avoid allocating an oversized buffer
+    if params.hexdata.len() / 2 > MAX_BLOCK_DECODE_SIZE {
+        let error = DecodeError::Oversized { size: params.hexdata.len() / 2, max: MAX_BLOCK_DECODE_SIZE };
+        return Err(RpcError::PayloadTooLarge(error.to_string()));
+    }
+    let bytes = hex::decode(&params.hexdata)
+        .map_err(|e| RpcError::InvalidParams(format!("Invalid block hex: {}", e)))?;
+    let block = decode_block(&bytes).map_err(|e| match e {
+        DecodeError::Oversized { .. } => RpcError::PayloadTooLarge(e.to_string()),
+        DecodeError::Malformed(_) | DecodeError::UnknownVersion(_) => {
+            RpcError::InvalidParams(format!("Block decode failed: {}", e))
+        }
+    })?;
+    let hash = block.hash();
+
+    if let Some(submitter) = &context.block_submitter {
+        return submitter(block).map(|result| result.map_or(Value::Null, Value::from)).map_err(RpcError::Internal);
+    }
+    require_chain_control(context, "submitblock")?;
+
+    let stored = context.db.get_block(&hash)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    if stored.is_some() {
+        return Ok(Value::from("duplicate"));
+    }
+
+    let mut pipeline = context.pipeline.lock().unwrap();
+    let outcome = context.orphans.lock().unwrap().process_block(block, &mut pipeline, &context.db);
+    match outcome {
+        Ok(BlockOutcome::Processed(report)) => {
+            log::info!("Submitted block {} connected ({} blocks total)", BlockHash::from(hash), report.connected.len());
+            Ok(Value::Null)
+        }
+        Ok(BlockOutcome::Orphaned { .. }) => Ok(Value::from("inconclusive")),
+        Ok(BlockOutcome::AlreadyOrphaned) => Ok(Value::from("duplicate-inconclusive")),
+        Err(PipelineError::KnownInvalid { hash: invalid, .. }) if invalid == hash => Ok(Value::from("duplicate-invalid")),
+        Err(PipelineError::Storage { error, .. }) => Err(RpcError::DatabaseError(error.to_string())),
+        Err(error) => Ok(Value::from(error.to_string())),
+    }
+}
+
+/// `invalidateblock "blockhash"`
+///
+/// Permanently mark a block as invalid, as if it violated a consensus rule.
+/// If it is in the active chain, it is disconnected with its descendants
+/// and the best remaining valid chain becomes active.
+pub fn invalidate_block(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    require_chain_control(context, "invalidateblock")?;
+    let params: BlockHashParams = parse_params(params)?;
+    let hash = parse_block_hash(&params.blockhash)?;
+
+    let mut pipeline = context.pipeline.lock().unwrap();
+    let result = reorg::invalidate_block(&context.db, hash, "invalidated by operator").and_then(|disconnected| {
+        let mut report = reorg::activate_best_chain(&context.db, &mut pipeline)?;
+        report.disconnected.splice(0..0, disconnected);
+        Ok(report)
+    });
+    // Statuses (and possibly the tip) changed: reload the header index on next use
+    *context.headers.lock().unwrap() = None;
+    let report = result.map_err(reorg_error)?;
+    update_mempool_for_reorg(context, &pipeline, &report);
+    Ok(Value::Null)
+}
+
+/// `preciousblock "blockhash"`
+///
+/// Treat a block as if it were received before any other with the same
+/// work: if its chain has at least as much work as the active one, it
+/// becomes the active chain. A block with less work is left alone.
+pub fn precious_block(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    require_chain_control(context, "preciousblock")?;
+    let params: BlockHashParams = parse_params(params)?;
+    let hash = parse_block_hash(&params.blockhash)?;
+
+    let (entry, tip) = {
+        let headers = synced_headers(context)?;
+        let cache = headers.as_ref().expect("header index loaded");
+        let entry = cache.get(&hash).copied()
+            .ok_or_else(|| RpcError::NotFound(format!("Block not found: {}", params.blockhash)))?;
+        (entry, cache.tip().map(|(tip, entry)| (tip, entry.chainwork)))
+    };
+    match entry.status {
+        HeaderStatus::Valid => {}
+        HeaderStatus::Invalid => return Err(RpcError::InvalidParams("Block is marked invalid".to_string())),
+        HeaderStatus::HeadersOnly => return Err(RpcError::InvalidParams("Block data not available".to_string())),
+    }
+    if tip.is_some_and(|(tip, work)| tip == hash || entry.chainwork < work) {
+        return Ok(Value::Null);
+    }
+
+    let mut pipeline = context.pipeline.lock().unwrap();
+    let result = reorg::activate_chain(&context.db, &mut pipeline, hash);
+    *context.headers.lock().unwrap() = None;
+    let report = result.map_err(reorg_error)?;
+    update_mempool_for_reorg(context, &pipeline, &report);
+    Ok(Value::Null)
+}
+
+/// Refuse a method that changes the chain when the node connects blocks itself
+fn require_chain_control(context: &RpcContext, method: &str) -> Result<(), RpcError> {
+    if context.chain_control {
+        Ok(())
+    } else {
+        Err(RpcError::InvalidRequest(format!("{} is disabled: this node connects blocks itself", method)))
+    }
+}
+
+/// Return the transactions of disconnected blocks to the attached mempool
+/// and drop the ones the new chain confirms or conflicts with
+///
+/// The reorg already happened: a failure here is logged, not reported.
+fn update_mempool_for_reorg(context: &RpcContext, pipeline: &BlockPipeline, report: &ReorgReport) {
+    let Some(mempool) = context.mempool.as_ref() else {
+        return;
+    };
+    if report.disconnected.is_empty() && report.connected.is_empty() {
+        return;
+    }
+    let blocks = report.disconnected.iter()
+        .filter_map(|hash| context.db.get_block(hash).transpose())
+        .collect::<Result<Vec<_>, StorageError>>();
+    let result = blocks.map_err(MempoolError::from)
+        .and_then(|blocks| mempool.lock().unwrap().update_for_reorg(&blocks, pipeline.validator(), &context.db));
+    match result {
+        Ok(stats) => log::info!(
+            "Mempool updated after reorg: {} transactions returned, {} dropped, {} evicted",
+            stats.returned, stats.dropped, stats.evicted
+        ),
+        Err(e) => log::warn!("Failed to update mempool after reorg: {}", e),
+    }
+}
+
+/// Params for `getreorgs`
+#[derive(Debug, Default, Deserialize)]
+struct ReorgsParams {
+    /// Maximum number of entries
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Entry of `getreorgs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReorgInfo {
+    /// Tip before the reorg
+    pub old_tip: BlockHash,
+    /// Height of the old tip
+    pub old_height: u64,
+    /// Tip after the reorg
+    pub new_tip: BlockHash,
+    /// Height of the new tip
+    pub new_height: u64,
+    /// Height of the last block shared by both branches
+    pub fork_height: u64,
+    /// Blocks disconnected
+    pub depth: u64,
+    /// Blocks connected on the new branch
+    pub connected: u64,
+    /// Whether the depth reaches the alarm threshold
+    pub deep: bool,
+    /// UNIX time the reorg started
+    pub started_at: u64,
+    /// UNIX time the reorg finished
+    pub finished_at: u64,
+}
+
+/// `getreorgs ( limit )`
+///
+/// Reorganizations of the active chain recorded by this node, newest first.
+pub fn get_reorgs(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ReorgsParams = parse_params(params)?;
+    let limit = page_limit(params.limit)?;
+
+    let reorgs = context.db.get_reorgs(limit)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let pipeline = context.pipeline.lock().unwrap();
+    let alarm = pipeline.reorg_alarm();
+    let reorgs: Vec<ReorgInfo> = reorgs.iter()
+        .map(|record| ReorgInfo {
+            old_tip: record.old_tip,
+            old_height: record.old_height,
+            new_tip: record.new_tip,
+            new_height: record.new_height,
+            fork_height: record.fork_height,
+            depth: record.depth,
+            connected: record.connected,
+            deep: alarm.is_deep(record),
+            started_at: record.started_at,
+            finished_at: record.finished_at,
+        })
+        .collect();
+    to_value(&reorgs)
+}
+
+/// Map a reorg failure to an RPC error
+fn reorg_error(error: ReorgError) -> RpcError {
+    match error {
+        ReorgError::UnknownBlock(hash) => RpcError::NotFound(format!("Block not found: {}", BlockHash::from(hash))),
+        ReorgError::Genesis => RpcError::InvalidParams(error.to_string()),
+        ReorgError::Storage(error) => RpcError::DatabaseError(error.to_string()),
+        ReorgError::Rejected { .. } => RpcError::Internal(error.to_string()),
+    }
+}
+
+/// Params for `walletpassphrase`
+#[derive(Debug, Default, Deserialize)]
+struct WalletPassphraseParams {
+    /// Keystore passphrase
+    passphrase: String,
+    /// Seconds before the keystore locks again
+    timeout: u64,
+}
+
+/// `walletpassphrase "passphrase" timeout`
+///
+/// Unlock the keystore for signing for `timeout` seconds, after which it
+/// locks again. A new call replaces the previous timeout.
+pub fn wallet_passphrase(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: WalletPassphraseParams = parse_params(params)?;
+    if params.timeout == 0 {
+        return Err(RpcError::InvalidParams("Timeout must be positive".to_string()));
+    }
+
+    let mut keystore = context.keystore.lock().unwrap();
+    let (keystore, _) = keystore.as_mut().ok_or_else(no_keystore)?;
+    keystore.unlock(&params.passphrase, Duration::from_secs(params.timeout))
+        .map_err(keystore_error)?;
+    Ok(Value::Null)
+}
+
+/// `walletlock`
+///
+/// Lock the keystore immediately.
+pub fn wallet_lock(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let mut keystore = context.keystore.lock().unwrap();
+    let (keystore, _) = keystore.as_mut().ok_or_else(no_keystore)?;
+    keystore.lock();
+    Ok(Value::Null)
+}
+
+/// Params for `walletpassphrasechange`
+#[derive(Debug, Default, Deserialize)]
+struct WalletPassphraseChangeParams {
+    /// Current passphrase
+    oldpassphrase: String,
+    /// New passphrase
+    newpassphrase: String,
+}
+
+/// `walletpassphrasechange "oldpassphrase" "newpassphrase"`
+///
+/// Re-encrypt every key under the new passphrase and save the keystore.
+/// The keystore is locked afterwards.
+pub fn wallet_passphrase_change(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: WalletPassphraseChangeParams = parse_params(params)?;
+    if params.newpassphrase.is_empty() {
+        return Err(RpcError::InvalidParams("Passphrase must not be empty".to_string()));
+    }
+
+    let mut keystore = context.keystore.lock().unwrap();
+    let (keystore, path) = keystore.as_mut().ok_or_else(no_keystore)?;
+    // Save the re-encrypted copy first so a failed save keeps the old
+    // passphrase both on disk and in memory
+    let kdf = keystore.kdf();
+    let updated = keystore.rekeyed(&params.oldpassphrase, &params.newpassphrase, kdf)
+        .and_then(|updated| updated.save(path.as_path()).map(|()| updated))
+        .map_err(keystore_error)?;
+    *keystore = updated;
+    log::info!("Wallet passphrase changed, keystore saved to {}", path.display());
+    Ok(Value::Null)
+}
+
+/// Params for `importprivkey`
+#[derive(Debug, Default, Deserialize)]
+struct ImportPrivKeyParams {
+    /// Private key in WIF
+    privkey: String,
+    /// Keystore label (default: hash160 of the public key, hex)
+    #[serde(default)]
+    label: Option<String>,
+    /// Scan the UTXO set for outputs of the key (default: true)
+    #[serde(default)]
+    rescan: Option<bool>,
+}
+
+/// Result of `importprivkey`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportPrivKeyInfo {
+    /// Keystore label of the key
+    pub label: String,
+    /// Compressed public key (hex)
+    pub pubkey: String,
+    /// P2PKH and P2PK scripts spendable with the key (hex)
+    pub scripts: Vec<String>,
+    /// Unspent outputs of the key, null without rescan
+    pub unspents: Option<usize>,
+    /// Native amount of those outputs, null without rescan
+    pub total_amount: Option<Amount>,
+}
+
+/// `importprivkey "privkey" ("label") (rescan)`
+///
+/// Add a WIF private key, e.g. from a paper wallet, to the keystore (which
+/// must be unlocked) and save it. With `rescan` the UTXO set is scanned for
+/// the outputs the key can spend. Imported funds stay spendable by anyone
+/// else holding the key: move them with `sweepprivkey`.
+pub fn import_priv_key(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ImportPrivKeyParams = parse_params(params)?;
+    let key = parse_private_key(context, &params.privkey)?;
+    let public_key = key.public_key().serialize();
+    let label = params.label.unwrap_or_else(|| hex::encode(hash160(&public_key)));
+
+    {
+        let mut keystore = context.keystore.lock().unwrap();
+        let (keystore, path) = keystore.as_mut().ok_or_else(no_keystore)?;
+        keystore.add_secret_key(&label, &key.secret_key).map_err(keystore_error)?;
+        if let Err(e) = keystore.save(path.as_path()) {
+            let _ = keystore.remove(&label);
+            return Err(keystore_error(e));
+        }
+    }
+    log::info!("Imported private key '{}'", label);
+
+    let (unspents, total_amount) = if params.rescan.unwrap_or(true) {
+        let utxos = key_utxos(&context.db, &key).map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+        let total = utxos.iter()
+            .filter(|utxo| utxo.output.is_native_asset())
+            .fold(Amount::ZERO, |total, utxo| total.saturating_add(utxo.output.value));
+        (Some(utxos.len()), Some(total))
+    } else {
+        (None, None)
+    };
+
+    to_value(&ImportPrivKeyInfo {
+        label,
+        pubkey: hex::encode(public_key),
+        scripts: key.script_pubkeys().iter().map(hex::encode).collect(),
+        unspents,
+        total_amount,
+    })
+}
+
+/// Descriptor to import with `importdescriptors`
+#[derive(Debug, Deserialize)]
+struct ImportDescriptorRequest {
+    /// Descriptor with public keys only, or script hex
+    desc: String,
+    /// Derivation range of a ranged descriptor (default 0..=999)
+    #[serde(default)]
+    range: Option<ScanRange>,
+    /// Keystore label (default: the descriptor checksum)
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// Params for `importdescriptors`
+#[derive(Debug, Default, Deserialize)]
+struct ImportDescriptorsParams {
+    /// Descriptors to import
+    requests: Vec<ImportDescriptorRequest>,
+}
+
+/// Watch-only descriptor in `importdescriptors` and `listdescriptors`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedDescriptorInfo {
+    /// Keystore label
+    pub label: String,
+    /// Descriptor with checksum
+    pub desc: String,
+    /// Derived indices `[begin, end]` (inclusive)
+    pub range: (u32, u32),
+}
+
+/// `importdescriptors [{"desc": ..., "range": ..., "label": ...}]`
+///
+/// Add watch-only descriptors to the keystore and save it. Their outputs
+/// become the default wallet of `listunspent`, `getbalances` and
+/// `fundrawtransaction`. The keystore need not be unlocked: descriptors
+/// with private keys are rejected, since descriptors are stored in clear.
+/// Either every descriptor is imported or none.
+pub fn import_descriptors(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ImportDescriptorsParams = parse_params(params)?;
+    let mut imports = Vec::with_capacity(params.requests.len());
+    for request in &params.requests {
+        let descriptor = parse_descriptor(&request.desc)?;
+        let range = derivation_range(request.range)?;
+        let desc = descriptor.to_string();
+        let label = request.label.clone()
+            .unwrap_or_else(|| desc.rsplit('#').next().unwrap_or_default().to_string());
+        imports.push((label, descriptor, range));
+    }
+
+    let mut keystore = context.keystore.lock().unwrap();
+    let (keystore, path) = keystore.as_mut().ok_or_else(no_keystore)?;
+    let mut imported = Vec::with_capacity(imports.len());
+    let result = imports.iter()
+        .try_for_each(|(label, descriptor, range)| {
+            keystore.import_descriptor(label, descriptor, range.clone())?;
+            imported.push(label);
+            Ok(())
+        })
+        .and_then(|()| keystore.save(path.as_path()));
+    if let Err(error) = result {
+        for label in imported {
+            keystore.remove_descriptor(label);
+        }
+        return Err(match error {
+            KeystoreError::InvalidKey(_) | KeystoreError::InvalidLabel => RpcError::InvalidParams(error.to_string()),
+            _ => keystore_error(error),
+        });
+    }
+    log::info!("Imported {} watch-only descriptors", imports.len());
+
+    to_value(&imports.into_iter()
+        .map(|(label, descriptor, range)| WatchedDescriptorInfo {
+            label,
+            desc: descriptor.to_string(),
+            range: (range.start, range.end - 1),
+        })
+        .collect::<Vec<_>>())
+}
+
+/// `listdescriptors`
+///
+/// Watch-only descriptors imported with `importdescriptors`.
+pub fn list_descriptors(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let keystore = context.keystore.lock().unwrap();
+    let (keystore, _) = keystore.as_ref().ok_or_else(no_keystore)?;
+    to_value(&keystore.descriptors()
+        .map(|(label, watched)| WatchedDescriptorInfo {
+            label: label.to_string(),
+            desc: watched.desc.clone(),
+            range: (watched.range.start, watched.range.end.saturating_sub(1)),
+        })
+        .collect::<Vec<_>>())
+}
+
+/// Params for `sweepprivkey`
+#[derive(Debug, Default, Deserialize)]
+struct SweepPrivKeyParams {
+    /// Private key in WIF, or the label of a key in the keystore
+    key: String,
+    /// Script receiving the funds (hex)
+    destination: String,
+    /// Fee rate in satoshi per byte (default: the wallet default)
+    #[serde(default)]
+    fee_rate: Option<u64>,
+}
+
+/// Result of `sweepprivkey`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepInfo {
+    /// Sweep transaction id
+    pub txid: Txid,
+    /// Signed transaction (hex)
+    pub hex: String,
+    /// Native amount paid to the destination
+    pub amount: Amount,
+    /// Fee paid
+    pub fee: Amount,
+    /// Outputs spent
+    pub inputs: usize,
+    /// Immature coinbase outputs left on the key
+    pub immature: usize,
+    /// Whether the transaction was added to the node mempool
+    pub in_mempool: bool,
+}
+
+/// `sweepprivkey "key" "destination" (fee_rate)`
+///
+/// Build and sign a transaction moving every spendable output of a key to
+/// `destination`: one output for SLY, minus the fee, and one per other
+/// asset. `key` is a WIF key or, for authenticated callers, the label of an
+/// imported key (the keystore must then be unlocked). Outputs already spent
+/// in the mempool are skipped. If the node shares its mempool the
+/// transaction is added to it, otherwise broadcast `hex` yourself.
+pub fn sweep_priv_key(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    sweep(context, params, true)
+}
+
+/// `sweepprivkey` for unauthenticated callers: `key` must be a WIF key,
+/// the labels of the keystore are refused
+pub fn sweep_wif_key(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    sweep(context, params, false)
+}
+
+/// Sweep the key of `params`, looking up labels in the keystore if `use_keystore`
+fn sweep(context: &RpcContext, params: &Value, use_keystore: bool) -> Result<Value, RpcError> {
+    let params: SweepPrivKeyParams = parse_params(params)?;
+    let key = match parse_private_key(context, &params.key) {
+        Ok(key) => key,
+        Err(error) if !use_keystore => return Err(error),
+        Err(error) => {
+            let mut keystore = context.keystore.lock().unwrap();
+            match keystore.as_mut() {
+                Some((keystore, _)) if keystore.contains(&params.key) => {
+                    let secret_key = keystore.secret_key(&params.key).map_err(keystore_error)?;
+                    PrivateKey::new(context.params.network, secret_key)
+                }
+                _ => return Err(error),
+            }
+        }
+    };
+    let destination = hex::decode(&params.destination)
+        .ok()
+        .filter(|script| !script.is_empty())
+        .ok_or_else(|| RpcError::InvalidParams(format!("Invalid destination script: {}", params.destination)))?;
+
+    let tip = context.db.get_height().map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let mut utxos = key_utxos(&context.db, &key).map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    if let Some(mempool) = &context.mempool {
+        let mempool = mempool.lock().unwrap();
+        utxos.retain(|utxo| mempool.spender(&utxo.outpoint).is_none());
+    }
+    let mut builder = SweepBuilder::new(destination)
+        .tip_height(tip)
+        .coinbase_maturity(context.params.coinbase_maturity);
+    if let Some(fee_rate) = params.fee_rate {
+        builder = builder.fee_rate(fee_rate);
+    }
+    let sweep = builder.build(&key, &utxos).map_err(|e| match e {
+        SweepError::Storage(e) => RpcError::DatabaseError(e.to_string()),
+        SweepError::NoFunds { .. } | SweepError::InsufficientFunds { .. } => RpcError::InvalidParams(e.to_string()),
+        _ => RpcError::Internal(e.to_string()),
+    })?;
+
+    let data = bincode::serialize(&sweep.tx).map_err(|e| RpcError::Internal(e.to_string()))?;
+    let in_mempool = match &context.mempool {
+        Some(mempool) => {
+            let pipeline = context.pipeline.lock().unwrap();
+            mempool.lock().unwrap().add(sweep.tx.clone(), tip, pipeline.validator(), &context.db)
+                .map_err(|e| RpcError::InvalidParams(format!("Sweep rejected by the mempool: {}", e)))?;
+            true
+        }
+        None => false,
+    };
+    log::info!("Swept {} from a private key in {}", sweep.amount, sweep.tx.txid());
+
+    to_value(&SweepInfo {
+        txid: sweep.tx.txid(),
+        hex: hex::encode(data),
+        amount: sweep.amount,
+        fee: sweep.fee,
+        inputs: sweep.inputs.len(),
+        immature: sweep.immature.len(),
+        in_mempool,
+    })
+}
+
+/// Parse a WIF private key for the network of the node
+fn parse_private_key(context: &RpcContext, wif: &str) -> Result<PrivateKey, RpcError> {
+    let key: PrivateKey = wif.parse().map_err(|e| RpcError::InvalidParams(format!("Invalid private key: {}", e)))?;
+    if !key.is_for(context.params.network) {
+        return Err(RpcError::InvalidParams(format!("Private key is not for {}", context.params.network.name())));
+    }
+    Ok(key)
+}
+
+/// Error for wallet methods on a node without keystore
+fn no_keystore() -> RpcError {
+    RpcError::NotFound("No wallet keystore loaded".to_string())
+}
+
+/// Map a keystore failure to an RPC error
+fn keystore_error(error: KeystoreError) -> RpcError {
+    match error {
+        KeystoreError::WrongPassphrase | KeystoreError::DuplicateKey(_) => RpcError::InvalidParams(error.to_string()),
+        KeystoreError::Io(_) => RpcError::DatabaseError(error.to_string()),
+        _ => RpcError::Internal(error.to_string()),
+    }
+}
+
+/// Output reference in `lockunspent` and `listlockunspent`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutPointParam {
+    /// Transaction id
+    pub txid: Txid,
+    /// Output index
+    pub vout: u32,
+}
+
+/// Params for `lockunspent`
+#[derive(Debug, Default, Deserialize)]
+struct LockUnspentParams {
+    /// True to unlock, false to lock
+    unlock: bool,
+    /// Outputs to (un)lock; empty with `unlock` releases every lock
+    #[serde(default)]
+    transactions: Vec<OutPointParam>,
+}
+
+/// `lockunspent unlock [{"txid":"hex","vout":n},...]`
+///
+/// Lock or unlock unspent outputs for coin selection. Locked outputs are
+/// never picked automatically but can still be chosen as explicit inputs.
+/// Locks live in memory only and are cleared on restart. Every output is
+/// checked before any lock changes.
+pub fn lock_unspent(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: LockUnspentParams = parse_params(params)?;
+    let mut coin_control = context.coin_control.lock().unwrap();
+    if params.unlock && params.transactions.is_empty() {
+        coin_control.unfreeze_all();
+        return Ok(Value::Bool(true));
+    }
+
+    let mut outpoints = Vec::with_capacity(params.transactions.len());
+    for param in &params.transactions {
+        let outpoint = OutPoint::new(param.txid.to_byte_array(), param.vout);
+        let unspent = context.db.get_utxo(&outpoint)
+            .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+        if unspent.is_none() {
+            return Err(RpcError::InvalidParams(format!("Unknown or spent output {}:{}", param.txid, param.vout)));
+        }
+        if params.unlock && !coin_control.is_frozen(&outpoint) {
+            return Err(RpcError::InvalidParams(format!("Output {}:{} is not locked", param.txid, param.vout)));
+        }
+        if !params.unlock && coin_control.is_frozen(&outpoint) {
+            return Err(RpcError::InvalidParams(format!("Output {}:{} is already locked", param.txid, param.vout)));
+        }
+        outpoints.push(outpoint);
+    }
+
+    for outpoint in outpoints {
+        if params.unlock {
+            coin_control.unfreeze(&outpoint);
+        } else {
+            coin_control.freeze(outpoint);
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// `listlockunspent`
+///
+/// Outputs currently locked with `lockunspent`.
+pub fn list_lock_unspent(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let locked: Vec<OutPointParam> = context.coin_control.lock().unwrap()
+        .frozen()
+        .into_iter()
+        .map(|outpoint| OutPointParam { txid: outpoint.txid.into(), vout: outpoint.vout })
+        .collect();
+    to_value(&locked)
+}
+
+/// Params for `listmempool`
+#[derive(Debug, Default, Deserialize)]
+struct ListMempoolParams {
+    /// Cursor returned by the previous page
+    #[serde(default)]
+    cursor: Option<String>,
+    /// Maximum number of transactions in the page
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Transaction listed by `listmempool`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolTx {
+    /// Transaction id
+    pub txid: Txid,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// Fee paid in native SLY
+    pub fee: Amount,
+    /// UNIX time the transaction was received
+    pub time: u64,
+    /// Tip height when the transaction was received
+    pub height: u64,
+}
+
+/// `listmempool ( "cursor" limit )`
+///
+/// Page through the mempool transactions in txid order. The cursor is the
+/// last txid of the previous page, so transactions added or removed between
+/// calls never shift the following pages.
+pub fn list_mempool(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ListMempoolParams = parse_params(params)?;
+    let limit = page_limit(params.limit)?;
+    let after = params.cursor.as_deref()
+        .map(|cursor| {
+            cursor.parse::<Txid>()
+                .map(Txid::to_byte_array)
+                .map_err(|e| RpcError::InvalidParams(format!("Invalid cursor {}: {}", cursor, e)))
+        })
+        .transpose()?;
+
+    let mempool = context.mempool.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No mempool attached to the RPC server".to_string()))?
+        .lock()
+        .unwrap();
+    let mut entries: Vec<_> = mempool.entries()
+        .map(|entry| (entry.tx.hash(), entry))
+        .filter(|(txid, _)| after.is_none_or(|after| *txid > after))
+        .collect();
+    entries.sort_unstable_by_key(|(txid, _)| *txid);
+
+    let next_cursor = (entries.len() > limit).then(|| Txid::from(entries[limit - 1].0).to_string());
+    let items = entries.into_iter()
+        .take(limit)
+        .map(|(txid, entry)| MempoolTx {
+            txid: txid.into(),
+            size: entry.size,
+            fee: Amount::from_sat(entry.fee),
+            time: entry.received_at,
+            height: entry.height,
+        })
+        .collect();
+    to_value(&Page { items, next_cursor })
+}
+
+/// Spent outpoint filter counters in `getmempoolinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpentFilterInfo {
+    /// Outpoints looked up
+    pub lookups: u64,
+    /// Lookups that ruled out a recent spend
+    pub negatives: u64,
+    /// Positives the UTXO set proved wrong
+    pub false_positives: u64,
+    /// Share of the outpoints not recently spent that the filter flagged
+    pub false_positive_rate: f64,
+    /// Outpoints in the filter
+    pub items: usize,
+    /// Filter capacity
+    pub capacity: usize,
+}
+
+/// Result of `getmempoolinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolInfo {
+    /// Transactions in the mempool
+    pub size: usize,
+    /// Sum of the serialized transaction sizes
+    pub bytes: usize,
+    /// Maximum mempool size in bytes
+    pub maxmempool: usize,
+    /// Low-feerate transactions evicted to the disk tier
+    pub spilled: usize,
+    /// Sum of the serialized sizes of the spilled transactions
+    pub spilledbytes: usize,
+    /// Filter of the outpoints recently spent by blocks and mempool transactions
+    pub spentfilter: SpentFilterInfo,
+}
+
+/// `getmempoolinfo`
+///
+/// Size of the mempool and of its disk tier, and counters of the filter
+/// that checks incoming transactions for spends of recently spent outputs
+/// before validating them.
+pub fn get_mempool_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let mempool = context.mempool.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No mempool attached to the RPC server".to_string()))?
+        .lock()
+        .unwrap();
+    let stats = mempool.spent_filter_stats();
+    to_value(&MempoolInfo {
+        size: mempool.len(),
+        bytes: mempool.total_size(),
+        maxmempool: mempool.max_size(),
+        spilled: mempool.spilled_len(),
+        spilledbytes: mempool.spilled_size(),
+        spentfilter: SpentFilterInfo {
+            lookups: stats.lookups,
+            negatives: stats.negatives,
+            false_positives: stats.false_positives,
+            false_positive_rate: stats.false_positive_rate(),
+            items: stats.items,
+            capacity: stats.capacity,
+        },
+    })
+}
+
+/// Params for `testmempoolaccept`
+#[derive(Debug, Default, Deserialize)]
+struct TestMempoolAcceptParams {
+    /// Serialized transactions (hex), tested in order as a package
+    rawtxs: Vec<String>,
+}
+
+/// Verdict of `testmempoolaccept` for one transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolAcceptResult {
+    /// Transaction id
+    pub txid: Txid,
+    /// Whether the mempool would accept the transaction
+    pub allowed: bool,
+    /// Serialized size in bytes, if allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    /// Fee paid in native SLY, if allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<Amount>,
+    /// Fee per 1000 bytes, if allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feerate: Option<u64>,
+    /// Why the transaction would be rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_reason: Option<String>,
+}
+
+/// `testmempoolaccept ["rawtx",...]`
+///
+/// Run the mempool acceptance checks on up to `MAX_TEST_ACCEPT_TXS`
+/// transactions without adding them, so they can be validated before an
+/// irreversible broadcast. Transactions are tested in order as a package:
+/// each may spend outputs of the earlier allowed ones. Nothing reaches the
+/// mempool or the rejection log.
+pub fn test_mempool_accept(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: TestMempoolAcceptParams = parse_params(params)?;
+    if params.rawtxs.is_empty() || params.rawtxs.len() > MAX_TEST_ACCEPT_TXS {
+        return Err(RpcError::InvalidParams(format!(
+            "Expected 1 to {} transactions, got {}",
+            MAX_TEST_ACCEPT_TXS,
+            params.rawtxs.len()
+        )));
+    }
+    let mut txs = Vec::with_capacity(params.rawtxs.len());
+    for raw in &params.rawtxs {
+        if raw.len() / 2 > MAX_TX_DECODE_SIZE {
+            let error = DecodeError::Oversized { size: raw.len() / 2, max: MAX_TX_DECODE_SIZE };
+            return Err(RpcError::PayloadTooLarge(error.to_string()));
+        }
+        let bytes = hex::decode(raw)
+            .map_err(|e| RpcError::InvalidParams(format!("Invalid transaction hex: {}", e)))?;
+        txs.push(decode_transaction(&bytes)
+            .map_err(|e| RpcError::InvalidParams(format!("Transaction decode failed: {}", e)))?);
+    }
+
+    let tip = context.db.get_height().map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let mempool = context.mempool.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No mempool attached to the RPC server".to_string()))?;
+    // Same lock order as the block and sweep paths: pipeline, then mempool
+    let pipeline = context.pipeline.lock().unwrap();
+    let mempool = mempool.lock().unwrap();
+    let results: Vec<MempoolAcceptResult> = mempool.test_accept(&txs, tip, pipeline.validator(), &context.db)
+        .into_iter()
+        .zip(&txs)
+        .map(|(result, tx)| match result {
+            Ok(entry) => MempoolAcceptResult {
+                txid: tx.txid(),
+                allowed: true,
+                size: Some(entry.size),
+                fee: Some(Amount::from_sat(entry.fee)),
+                feerate: Some(entry.feerate()),
+                reject_reason: None,
+            },
+            Err(error) => MempoolAcceptResult {
+                txid: tx.txid(),
+                allowed: false,
+                size: None,
+                fee: None,
+                feerate: None,
+                reject_reason: Some(error.to_string()),
+            },
+        })
+        .collect();
+    to_value(&results)
+}
+
+/// Peer listed by `getpeerinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerInfo {
+    /// Peer address
+    pub addr: String,
+    /// Whether the peer opened the connection
+    pub inbound: bool,
+    /// UNIX time of the connection
+    pub conn_time: u64,
+    /// UNIX time of the last message sent (0 if none)
+    pub last_send: u64,
+    /// UNIX time of the last message received (0 if none)
+    pub last_recv: u64,
+    /// Bytes sent, headers included
+    pub bytes_sent: u64,
+    /// Bytes received, headers included
+    pub bytes_recv: u64,
+    /// Messages sent by command
+    pub messages_sent: BTreeMap<String, u64>,
+    /// Messages received by command
+    pub messages_recv: BTreeMap<String, u64>,
+    /// Last ping round trip in seconds
+    pub ping_time: Option<f64>,
+    /// Fastest ping round trip in seconds
+    pub min_ping: Option<f64>,
+    /// Height of the last block received from the peer
+    pub synced_height: Option<u64>,
+    /// Negotiated protocol version (None until the handshake completes)
+    pub version: Option<u32>,
+    /// Advertised services as a hex bitmask
+    pub services: String,
+    /// Names of the advertised services
+    pub servicesnames: Vec<String>,
+    /// Advertised user agent
+    pub subver: String,
+    /// Peer height at the time of the handshake
+    pub startingheight: u64,
+}
+
+/// Result of `getnettotals`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetTotalsInfo {
+    /// Bytes sent to all peers since startup
+    pub total_bytes_sent: u64,
+    /// Bytes received from all peers since startup
+    pub total_bytes_recv: u64,
+    /// Messages sent since startup
+    pub total_messages_sent: u64,
+    /// Messages received since startup
+    pub total_messages_recv: u64,
+    /// Currently connected peers
+    pub connections: usize,
+}
+
+/// Peer statistics of the context
+fn net_stats(context: &RpcContext) -> Result<MutexGuard<'_, sedly_core::NetStats>, RpcError> {
+    Ok(context.net_stats.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No peer statistics attached to the RPC server".to_string()))?
+        .lock()
+        .unwrap())
+}
+
+/// `getpeerinfo`
+///
+/// Traffic, latency and sync height of every connected peer, to spot
+/// slow or misbehaving ones.
+pub fn get_peer_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let micros_to_secs = |micros: u64| micros as f64 / 1_000_000.0;
+    let stats = net_stats(context)?;
+    let peers: Vec<PeerInfo> = stats.peers()
+        .into_iter()
+        .map(|peer| PeerInfo {
+            addr: peer.addr.clone(),
+            inbound: peer.inbound,
+            conn_time: peer.connected_at,
+            last_send: peer.last_send,
+            last_recv: peer.last_recv,
+            bytes_sent: peer.bytes_sent,
+            bytes_recv: peer.bytes_recv,
+            messages_sent: peer.messages_sent.clone(),
+            messages_recv: peer.messages_recv.clone(),
+            ping_time: peer.ping_micros.map(micros_to_secs),
+            min_ping: peer.min_ping_micros.map(micros_to_secs),
+            synced_height: peer.sync_height,
+            version: peer.version,
+            services: format!("{:016x}", peer.services.bits()),
+            servicesnames: peer.services.names().into_iter().map(String::from).collect(),
+            subver: peer.user_agent.clone(),
+            startingheight: peer.start_height,
+        })
+        .collect();
+    to_value(&peers)
+}
+
+/// `getnettotals`
+///
+/// Traffic of the node since startup, disconnected peers included.
+pub fn get_net_totals(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let stats = net_stats(context)?;
+    let totals = stats.totals();
+    to_value(&NetTotalsInfo {
+        total_bytes_sent: totals.bytes_sent,
+        total_bytes_recv: totals.bytes_recv,
+        total_messages_sent: totals.messages_sent,
+        total_messages_recv: totals.messages_recv,
+        connections: stats.peers().len(),
+    })
+}
+
+/// Params for `getrejections`
+#[derive(Debug, Default, Deserialize)]
+struct RejectionsParams {
+    /// Maximum number of entries
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Entry of `getrejections`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectionInfo {
+    /// "block" or "transaction"
+    pub kind: String,
+    /// Block hash or txid
+    pub hash: Hash256,
+    /// Height of the block, or height the transaction would have been included at
+    pub height: u64,
+    /// Tip height when it was rejected
+    pub tip_height: u64,
+    /// Violated rule (e.g. "bad-merkle-root")
+    pub rule: String,
+    /// Full error message
+    pub reason: String,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// Whether `hex` holds only the first bytes
+    pub truncated: bool,
+    /// Serialized block or transaction (hex)
+    pub hex: String,
+    /// UNIX time of the rejection
+    pub time: u64,
+}
+
+/// Result of `getrejections`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectionsInfo {
+    /// Entries kept by the ring buffer
+    pub capacity: usize,
+    /// Rejections recorded since startup, including the ones no longer kept
+    pub total: u64,
+    /// Most recent rejections first
+    pub rejections: Vec<RejectionInfo>,
+}
+
+/// `getrejections ( limit )`
+///
+/// Blocks and transactions rejected for breaking a consensus rule, with
+/// the rule, heights and a hex dump, to diagnose why the node forked off
+/// the network. Requires the node to run with a rejection log.
+pub fn get_rejections(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: RejectionsParams = parse_params(params)?;
+    let limit = page_limit(params.limit)?;
+    let log = context.rejections.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No rejection log attached to the RPC server".to_string()))?
+        .lock()
+        .unwrap();
+
+    let rejections = log.recent(limit)
+        .into_iter()
+        .map(|entry| RejectionInfo {
+            kind: entry.item.as_str().to_string(),
+            hash: entry.hash.into(),
+            height: entry.height,
+            tip_height: entry.tip_height,
+            rule: entry.rule.clone(),
+            reason: entry.reason.clone(),
+            size: entry.size,
+            truncated: entry.is_truncated(),
+            hex: hex::encode(&entry.data),
+            time: entry.timestamp,
+        })
+        .collect();
+    to_value(&RejectionsInfo {
+        capacity: log.capacity(),
+        total: log.total(),
+        rejections,
+    })
+}
+
+/// Current UNIX time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Network alert listed by `getnodeinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertInfo {
+    /// Alert id
+    pub id: u32,
+    /// Ids of the alerts it cancels
+    pub cancel: Vec<u32>,
+    /// UNIX time after which the alert is dropped
+    pub expiration: u64,
+    /// Priority, highest shown first
+    pub priority: u32,
+    /// Message for node operators
+    pub message: String,
+}
+
+impl From<&SignedAlert> for AlertInfo {
+    fn from(signed: &SignedAlert) -> Self {
+        let alert = &signed.alert;
+        Self {
+            id: alert.id,
+            cancel: alert.cancel.clone(),
+            expiration: alert.expiration,
+            priority: alert.priority,
+            message: alert.message.clone(),
+        }
+    }
+}
+
+/// Result of `getnodeinfo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    /// Node software version
+    pub version: String,
+    /// Network name
+    pub network: String,
+    /// Chain height
+    pub height: u64,
+    /// Best block hash
+    pub bestblock: BlockHash,
+    /// Connected peers, if the node shares its peer statistics
+    pub connections: Option<usize>,
+    /// Transactions in the mempool, if the node shares its mempool
+    pub mempool_size: Option<usize>,
+    /// Messages of the active alerts, highest priority first, then the hardware warnings
+    pub warnings: Vec<String>,
+    /// Active alerts, highest priority first
+    pub alerts: Vec<AlertInfo>,
+    /// Startup hardware measurements, if the node shares them
+    pub hardware: Option<HardwareReport>,
+}
+
+/// `getnodeinfo`
+///
+/// Version, chain tip and connections of the node, with the messages of
+/// the network alerts currently active and the warnings of the startup
+/// hardware check.
+pub fn get_node_info(context: &RpcContext, _params: &Value) -> Result<Value, RpcError> {
+    let metadata = context.db.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let alerts: Vec<AlertInfo> = context.alerts.as_ref()
+        .map(|alerts| alerts.lock().unwrap().active(unix_now()).into_iter().map(AlertInfo::from).collect())
+        .unwrap_or_default();
+
+    to_value(&NodeInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        network: context.params.network.name().to_string(),
+        height: metadata.height,
+        bestblock: metadata.best_block_hash.into(),
+        connections: context.net_stats.as_ref().map(|stats| stats.lock().unwrap().peers().len()),
+        mempool_size: context.mempool.as_ref().map(|mempool| mempool.lock().unwrap().len()),
+        warnings: alerts.iter()
+            .map(|alert| alert.message.clone())
+            .chain(context.hardware.iter().flat_map(|report| report.warnings.iter().cloned()))
+            .collect(),
+        alerts,
+        hardware: context.hardware.clone(),
+    })
+}
+
+/// Params for `sendalert`
+#[derive(Debug, Default, Deserialize)]
+struct SendAlertParams {
+    /// Serialized signed alert (hex)
+    hexstring: String,
+}
+
+/// Result of `sendalert`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendAlertResult {
+    /// The accepted alert
+    pub alert: AlertInfo,
+    /// Whether the alert was new to the node (and will be relayed to its peers)
+    pub new: bool,
+}
+
+/// `sendalert "hexstring"`
+///
+/// Submit an alert signed with one of the network alert keys. The node
+/// verifies it, applies its cancellations and relays it to its peers.
+pub fn send_alert(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: SendAlertParams = parse_params(params)?;
+    let alerts = context.alerts.as_ref()
+        .ok_or_else(|| RpcError::NotFound("No alert set attached to the RPC server".to_string()))?;
+    let bytes = hex::decode(&params.hexstring).map_err(|e| RpcError::InvalidParams(format!("Invalid hex: {}", e)))?;
+    let alert = SignedAlert::from_bytes(&bytes).map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+
+    let info = AlertInfo::from(&alert);
+    let new = alerts.lock().unwrap()
+        .process(alert, &context.params.alert_keys, unix_now())
+        .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+    to_value(&SendAlertResult { alert: info, new })
+}
+
+/// Decoded script
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptInfo {
+    /// Opcodes and pushes, as in Bitcoin Core (see `sedly_core::script_asm`)
+    pub asm: String,
+    /// Raw script (hex)
+    pub hex: String,
+    /// Template: pubkey, pubkeyhash, multisig, state, nulldata or nonstandard
+    #[serde(rename = "type")]
+    pub script_type: String,
+    /// Signatures needed to spend, for standard templates
+    pub req_sigs: Option<usize>,
+    /// Hash160 of each key that can sign (hex); Sedly has no separate address encoding
+    pub addresses: Vec<String>,
+    /// Datum of a state output (hex)
+    pub datum: Option<String>,
+}
+
+impl ScriptInfo {
+    /// Decode a locking script
+    pub fn from_script(script: &[u8]) -> Self {
+        let mut info = Self::from_template(script, ScriptTemplate::classify(script));
+        if let Some(state) = StateScript::parse(script) {
+            let validator = Self::from_template(state.validator, ScriptTemplate::classify(state.validator));
+            info.script_type = "state".to_string();
+            info.req_sigs = validator.req_sigs;
+            info.addresses = validator.addresses;
+            info.datum = Some(hex::encode(state.datum));
+        } else if script.first() == Some(&sedly_core::script::opcodes::OP_RETURN) {
+            info.script_type = "nulldata".to_string();
+        }
+        info
+    }
+
+    fn from_template(script: &[u8], template: ScriptTemplate) -> Self {
+        let (req_sigs, addresses) = match &template {
+            ScriptTemplate::PayToPubkey(pubkey) => (Some(1), vec![hex::encode(hash160(pubkey))]),
+            ScriptTemplate::PayToPubkeyHash(hash) => (Some(1), vec![hex::encode(hash)]),
+            ScriptTemplate::Multisig { threshold, pubkeys } => {
+                (Some(*threshold), pubkeys.iter().map(|pubkey| hex::encode(hash160(pubkey))).collect())
+            }
+            ScriptTemplate::NonStandard => (None, Vec::new()),
+        };
+        Self {
+            asm: script_asm(script),
+            hex: hex::encode(script),
+            script_type: template.name().to_string(),
+            req_sigs,
+            addresses,
+            datum: None,
+        }
+    }
+}
+
+/// Unlocking script of a decoded input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptSigInfo {
+    /// Opcodes and pushes
+    pub asm: String,
+    /// Raw script (hex)
+    pub hex: String,
+}
+
+/// Input of a decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedInput {
+    /// Transaction id of the spent output (null for a coinbase)
+    pub txid: Option<Txid>,
+    /// Index of the spent output (null for a coinbase)
+    pub vout: Option<u32>,
+    /// Coinbase script (hex), only for a coinbase input
+    pub coinbase: Option<String>,
+    /// Unlocking script, absent for a coinbase input
+    pub script_sig: Option<ScriptSigInfo>,
+    /// Sequence number
+    pub sequence: u32,
+}
+
+/// Output of a decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedOutput {
+    /// Output value
+    pub value: Amount,
+    /// Output index
+    pub n: u32,
+    /// Asset id (hex, all zeros for SLY)
+    pub asset_id: String,
+    /// Locking script
+    pub script_pubkey: ScriptInfo,
+}
+
+/// Result of `decoderawtransaction`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedTransaction {
+    /// Transaction id
+    pub txid: Txid,
+    /// Format version
+    pub version: u32,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// Lock time
+    pub locktime: u64,
+    /// Last height the transaction can be confirmed at (null if it never expires)
+    pub expiryheight: Option<u64>,
+    /// Inputs
+    pub vin: Vec<DecodedInput>,
+    /// Outputs
+    pub vout: Vec<DecodedOutput>,
+}
+
+impl DecodedTransaction {
+    /// Decode the fields of a transaction
+    pub fn from_transaction(tx: &Transaction) -> Result<Self, RpcError> {
+        let coinbase = tx.is_coinbase();
+        let vin = tx.inputs.iter()
+            .map(|input| DecodedInput {
+                txid: (!coinbase).then(|| Txid::from(input.previous_output.txid)),
+                vout: (!coinbase).then_some(input.previous_output.vout),
+                coinbase: coinbase.then(|| hex::encode(&input.script_sig)),
+                script_sig: (!coinbase).then(|| ScriptSigInfo {
+                    asm: script_asm(&input.script_sig),
+                    hex: hex::encode(&input.script_sig),
+                }),
+                sequence: input.sequence,
+            })
+            .collect();
+        let vout = tx.outputs.iter()
+            .enumerate()
+            .map(|(n, output)| DecodedOutput {
+                value: output.value,
+                n: n as u32,
+                asset_id: hex::encode(output.asset_id),
+                script_pubkey: ScriptInfo::from_script(&output.script_pubkey),
+            })
+            .collect();
+        Ok(Self {
+            txid: tx.txid(),
+            version: tx.version,
+            size: tx.size().map_err(|e| RpcError::Internal(e.to_string()))?,
+            locktime: tx.lock_time,
+            expiryheight: tx.expiry(),
+            vin,
+            vout,
+        })
+    }
+}
+
+/// Params for `decoderawtransaction` and `decodescript`
+#[derive(Debug, Default, Deserialize)]
+struct DecodeParams {
+    /// Serialized transaction or script (hex)
+    hexstring: String,
+}
+
+/// `decoderawtransaction "hexstring"`
+///
+/// Decode a serialized transaction without looking it up in the chain or
+/// the mempool: inputs, outputs with their asset ids and decoded scripts.
+/// Nothing is validated beyond the encoding.
+pub fn decode_raw_transaction(_context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: DecodeParams = parse_params(params)?;
+    // Checked before hex decoding to avoid allocating an oversized buffer
+    if params.hexstring.len() / 2 > MAX_TX_DECODE_SIZE {
+        let error = DecodeError::Oversized { size: params.hexstring.len() / 2, max: MAX_TX_DECODE_SIZE };
+        return Err(RpcError::PayloadTooLarge(error.to_string()));
+    }
+    let bytes = hex::decode(&params.hexstring)
+        .map_err(|e| RpcError::InvalidParams(format!("Invalid transaction hex: {}", e)))?;
+    let tx = decode_transaction(&bytes).map_err(|e| match e {
+        DecodeError::Oversized { .. } => RpcError::PayloadTooLarge(e.to_string()),
+        DecodeError::Malformed(_) | DecodeError::UnknownVersion(_) => {
+            RpcError::InvalidParams(format!("Transaction decode failed: {}", e))
+        }
+    })?;
+    to_value(&DecodedTransaction::from_transaction(&tx)?)
+}
+
+/// `decodescript "hexstring"`
+///
+/// Decode a locking script: asm, template, required signatures and the
+/// key hashes that can spend it.
+pub fn decode_script(_context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: DecodeParams = parse_params(params)?;
+    let script = hex::decode(&params.hexstring)
+        .map_err(|e| RpcError::InvalidParams(format!("Invalid script hex: {}", e)))?;
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(RpcError::InvalidParams(format!("Script of {} bytes exceeds {}", script.len(), MAX_SCRIPT_SIZE)));
+    }
+    to_value(&ScriptInfo::from_script(&script))
+}
+
+/// Params for `getrawtransaction`
+#[derive(Debug, Default, Deserialize)]
+struct GetRawTransactionParams {
+    /// Transaction id
+    txid: Txid,
+    /// Return the decoded transaction with its block context instead of hex
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Result of `getrawtransaction` with verbose=true
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawTransactionInfo {
+    /// Decoded transaction
+    #[serde(flatten)]
+    pub decoded: DecodedTransaction,
+    /// Serialized transaction (hex)
+    pub hex: String,
+    /// Whether the transaction is waiting in the mempool
+    pub in_mempool: bool,
+    /// Hash of the including block (null while unconfirmed)
+    pub blockhash: Option<BlockHash>,
+    /// Height of the including block (null while unconfirmed)
+    pub blockheight: Option<u64>,
+    /// Time of the including block (null while unconfirmed)
+    pub blocktime: Option<u64>,
+    /// Blocks from the including block to the tip, itself included (0 while unconfirmed)
+    pub confirmations: u64,
+}
+
+/// `getrawtransaction "txid" ( verbose )`
+///
+/// Serialized transaction (hex), confirmed or in the mempool. With verbose
+/// the transaction is decoded as by `decoderawtransaction` and comes with
+/// its block, height, time and confirmations, so one call serves both
+/// confirmed and pending transactions.
+pub fn get_raw_transaction(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: GetRawTransactionParams = parse_params(params)?;
+    let txid = params.txid.to_byte_array();
+
+    // Chain and tip from the same snapshot: the confirmations cannot mix two tips
+    let snapshot = context.db.snapshot();
+    let confirmed = snapshot.get_transaction(&txid)
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let (tx, location) = match confirmed {
+        Some((tx, location)) => (tx, Some(location)),
+        None => {
+            let pending = context.mempool.as_ref().and_then(|mempool| {
+                mempool.lock().unwrap().get(&txid).map(|entry| entry.tx.clone())
+            });
+            let tx = pending.ok_or_else(|| RpcError::NotFound(format!("Transaction {} not found", params.txid)))?;
+            (tx, None)
+        }
+    };
+    let data = bincode::serialize(&tx).map_err(|e| RpcError::Internal(e.to_string()))?;
+    if !params.verbose {
+        return Ok(Value::from(hex::encode(data)));
+    }
+
+    let mut info = RawTransactionInfo {
+        decoded: DecodedTransaction::from_transaction(&tx)?,
+        hex: hex::encode(data),
+        in_mempool: location.is_none(),
+        blockhash: None,
+        blockheight: None,
+        blocktime: None,
+        confirmations: 0,
+    };
+    if let Some(location) = location {
+        let tip = snapshot.get_metadata()
+            .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+            .height;
+        let header = snapshot.get_headers_in_range(location.block_height, location.block_height)
+            .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+        info.blockhash = Some(location.block_hash);
+        info.blockheight = Some(location.block_height);
+        info.blocktime = header.first().map(|header| header.timestamp);
+        info.confirmations = tip.saturating_sub(location.block_height) + 1;
+    }
+    to_value(&info)
+}
+
+/// Input of `createrawtransaction`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawInputParam {
+    /// Transaction id of the spent output
+    pub txid: Txid,
+    /// Index of the spent output
+    pub vout: u32,
+    /// Sequence number (default: final)
+    #[serde(default)]
+    pub sequence: Option<u32>,
+}
+
+/// Output of `createrawtransaction`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawOutputParam {
+    /// Locking script (hex)
+    pub script_pubkey: String,
+    /// Output value
+    pub amount: Amount,
+    /// Asset id (hex, default: SLY)
+    #[serde(default)]
+    pub asset_id: Option<String>,
+}
+
+/// Params for `createrawtransaction`
+#[derive(Debug, Default, Deserialize)]
+struct CreateRawTransactionParams {
+    /// Inputs to spend, possibly none (see `fundrawtransaction`)
+    #[serde(default)]
+    inputs: Vec<RawInputParam>,
+    /// Outputs to create
+    #[serde(default)]
+    outputs: Vec<RawOutputParam>,
+    /// Lock time
+    #[serde(default)]
+    locktime: u64,
+    /// Last height the transaction can be confirmed at (makes a version 2 transaction)
+    #[serde(default)]
+    expiryheight: Option<u64>,
+}
+
+/// `createrawtransaction [{"txid":"hex","vout":n},...] [{"script_pubkey":"hex","amount":n},...] (locktime expiryheight)`
+///
+/// Build an unsigned transaction from explicit inputs and outputs and
+/// return it serialized (hex). Inputs are not looked up: add missing inputs
+/// and change with `fundrawtransaction`, then sign outside the node.
+pub fn create_raw_transaction(_context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: CreateRawTransactionParams = parse_params(params)?;
+    let inputs = params.inputs.iter()
+        .map(|input| {
+            let mut tx_input = TxInput::new(OutPoint::new(input.txid.to_byte_array(), input.vout), Vec::new());
+            if let Some(sequence) = input.sequence {
+                tx_input.sequence = sequence;
+            }
+            tx_input
+        })
+        .collect();
+    let outputs = params.outputs.iter().map(parse_raw_output).collect::<Result<Vec<_>, _>>()?;
+    let mut tx = Transaction::new(inputs, outputs, params.locktime);
+    if let Some(expiry_height) = params.expiryheight {
+        tx = tx.with_expiry(expiry_height);
+    }
+    let data = bincode::serialize(&tx).map_err(|e| RpcError::Internal(e.to_string()))?;
+    Ok(Value::from(hex::encode(data)))
+}
+
+/// Validate an output of `createrawtransaction`
+fn parse_raw_output(output: &RawOutputParam) -> Result<TxOutput, RpcError> {
+    let script_pubkey = hex::decode(&output.script_pubkey)
+        .ok()
+        .filter(|script| !script.is_empty() && script.len() <= MAX_SCRIPT_SIZE)
+        .ok_or_else(|| RpcError::InvalidParams(format!("Invalid output script: {}", output.script_pubkey)))?;
+    let asset_id = match &output.asset_id {
+        Some(asset_id) => parse_asset_id(asset_id)?,
+        None => [0; 32],
+    };
+    if output.amount == Amount::ZERO {
+        return Err(RpcError::InvalidParams("Output amount must be positive".to_string()));
+    }
+    Ok(TxOutput::new(output.amount, asset_id, script_pubkey))
+}
+
+/// Parse an asset id (64 hex characters)
+fn parse_asset_id(asset_id: &str) -> Result<[u8; 32], RpcError> {
+    hex::decode(asset_id)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| RpcError::InvalidParams(format!("Invalid asset id: {}", asset_id)))
+}
+
+/// Scripts matched by a list of scan objects
+fn expand_scan_objects(objects: &[ScanObject]) -> Result<HashSet<Vec<u8>>, RpcError> {
+    let mut scripts = HashSet::new();
+    for object in objects {
+        scripts.extend(expand_scan_object(object)?.1);
+    }
+    Ok(scripts)
+}
+
+/// Scripts of the wallet outputs: those of `objects`, or without any the
+/// scripts of the descriptors imported with `importdescriptors`
+fn wallet_scripts(context: &RpcContext, objects: &[ScanObject]) -> Result<HashSet<Vec<u8>>, RpcError> {
+    if !objects.is_empty() {
+        return expand_scan_objects(objects);
+    }
+    match context.keystore.lock().unwrap().as_ref() {
+        Some((keystore, _)) => keystore.watched_scripts().map_err(keystore_error),
+        None => Ok(HashSet::new()),
+    }
+}
+
+/// Unspent outputs paying one of `scripts` at `snapshot`
+fn wallet_utxos(snapshot: &ChainSnapshot<'_>, scripts: &HashSet<Vec<u8>>) -> Result<Vec<WalletUtxo>, RpcError> {
+    let scan = snapshot.scan_utxos(&CancellationToken::new(), |_, entry| scripts.contains(&entry.output.script_pubkey))
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    Ok(scan.matches.into_iter()
+        .map(|(outpoint, entry)| WalletUtxo {
+            outpoint,
+            output: entry.output,
+            height: entry.block_height,
+            is_coinbase: entry.is_coinbase,
+        })
+        .collect())
+}
+
+/// Options of `fundrawtransaction`
+#[derive(Debug, Default, Deserialize)]
+struct FundOptions {
+    /// Descriptors or script hex of the wallet outputs to fund from (as in
+    /// `scantxoutset`; default: the imported descriptors)
+    #[serde(default)]
+    descriptors: Vec<ScanObject>,
+    /// Script receiving the change (hex)
+    #[serde(default)]
+    change_script: String,
+    /// Fee rate in satoshi per byte (default: the wallet default)
+    #[serde(default)]
+    fee_rate: Option<u64>,
+}
+
+/// Params for `fundrawtransaction`
+#[derive(Debug, Default, Deserialize)]
+struct FundRawTransactionParams {
+    /// Serialized transaction (hex)
+    hexstring: String,
+    /// Wallet outputs, change script and fee rate
+    #[serde(default)]
+    options: FundOptions,
+}
+
+/// Result of `fundrawtransaction`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundedTransaction {
+    /// Funded, unsigned transaction (hex)
+    pub hex: String,
+    /// Fee paid
+    pub fee: Amount,
+    /// Indices of the change outputs (one per asset with change)
+    pub change_outputs: Vec<u32>,
+    /// Outputs spent, in input order
+    pub inputs: Vec<OutPointParam>,
+}
+
+/// `fundrawtransaction "hexstring" {"descriptors":[...],"change_script":"hex","fee_rate":n}`
+///
+/// Add inputs and change to a transaction so it pays its outputs and a
+/// fee at `fee_rate`. The inputs already in the transaction are kept;
+/// further ones are picked among the unspent outputs matching
+/// `descriptors` (default: the descriptors imported with
+/// `importdescriptors`), skipping outputs locked with `lockunspent`, spent in the
+/// mempool or immature. The wallet transaction builder places the change
+/// (see `sedly_wallet::transactions`), so output order and input sequence
+/// numbers may change. The result is unsigned.
+pub fn fund_raw_transaction(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: FundRawTransactionParams = parse_params(params)?;
+    if params.hexstring.len() / 2 > MAX_TX_DECODE_SIZE {
+        let error = DecodeError::Oversized { size: params.hexstring.len() / 2, max: MAX_TX_DECODE_SIZE };
+        return Err(RpcError::PayloadTooLarge(error.to_string()));
+    }
+    let bytes = hex::decode(&params.hexstring)
+        .map_err(|e| RpcError::InvalidParams(format!("Invalid transaction hex: {}", e)))?;
+    let tx = decode_transaction(&bytes)
+        .map_err(|e| RpcError::InvalidParams(format!("Transaction decode failed: {}", e)))?;
+    let options = params.options;
+    let change_script = hex::decode(&options.change_script)
+        .ok()
+        .filter(|script| !script.is_empty())
+        .ok_or_else(|| RpcError::InvalidParams(format!("Invalid change script: '{}'", options.change_script)))?;
+    let scripts = wallet_scripts(context, &options.descriptors)?;
+
+    let snapshot = context.db.snapshot();
+    let tip = snapshot.get_metadata().map_err(|e| RpcError::DatabaseError(e.to_string()))?.height;
+    let mut available = wallet_utxos(&snapshot, &scripts)?;
+    // Preset inputs need not match the descriptors
+    for input in &tx.inputs {
+        let outpoint = &input.previous_output;
+        if available.iter().any(|utxo| utxo.outpoint == *outpoint) {
+            continue;
+        }
+        let entry = snapshot.get_utxo(outpoint)
+            .map_err(|e| RpcError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| {
+                let txid = Txid::from(outpoint.txid);
+                RpcError::InvalidParams(format!("Unknown or spent input {}:{}", txid, outpoint.vout))
+            })?;
+        available.push(WalletUtxo {
+            outpoint: outpoint.clone(),
+            output: entry.output,
+            height: entry.block_height,
+            is_coinbase: entry.is_coinbase,
+        });
+    }
+    if let Some(mempool) = &context.mempool {
+        let mempool = mempool.lock().unwrap();
+        available.retain(|utxo| mempool.spender(&utxo.outpoint).is_none());
+    }
+
+    let mut builder = TransactionBuilder::new(change_script)
+        .lock_time(tx.lock_time)
+        .tip_height(tip)
+        .coinbase_maturity(context.params.coinbase_maturity);
+    if let Some(fee_rate) = options.fee_rate {
+        builder = builder.fee_rate(fee_rate);
+    }
+    for output in tx.outputs {
+        builder = builder.add_output(output);
+    }
+    for input in &tx.inputs {
+        builder = builder.add_input(input.previous_output.clone());
+    }
+    let coin_control = context.coin_control.lock().unwrap().clone();
+    let built = builder.build(&available, &coin_control).map_err(|e| match e {
+        BuildError::Serialization(_) => RpcError::Internal(e.to_string()),
+        _ => RpcError::InvalidParams(e.to_string()),
+    })?;
+
+    let data = bincode::serialize(&built.tx).map_err(|e| RpcError::Internal(e.to_string()))?;
+    to_value(&FundedTransaction {
+        hex: hex::encode(data),
+        fee: built.fee,
+        change_outputs: built.change_outputs,
+        inputs: built.inputs.iter()
+            .map(|utxo| OutPointParam { txid: utxo.outpoint.txid.into(), vout: utxo.outpoint.vout })
+            .collect(),
+    })
+}
+
+/// Params for `listunspent`
+#[derive(Debug, Default, Deserialize)]
+struct ListUnspentParams {
+    /// Descriptors or script hex of the wallet outputs (as in `scantxoutset`;
+    /// default: the imported descriptors)
+    #[serde(default)]
+    descriptors: Vec<ScanObject>,
+    /// Only outputs of this asset (hex), every asset if omitted
+    #[serde(default)]
+    asset_id: Option<String>,
+}
+
+/// Entry of `listunspent`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnspentInfo {
+    /// Transaction id
+    pub txid: Txid,
+    /// Output index
+    pub vout: u32,
+    /// Locking script (hex)
+    pub script_pubkey: String,
+    /// Output value, in units of the asset
+    pub amount: Amount,
+    /// Asset id (hex, all zeros for native SLY)
+    pub asset_id: String,
+    /// Height of the block that created the output
+    pub height: u64,
+    /// Confirmations at the tip
+    pub confirmations: u64,
+    /// Whether the output is a coinbase output
+    pub coinbase: bool,
+    /// Whether the output is locked with `lockunspent`
+    pub locked: bool,
+    /// Whether coin selection can pick it for the next block (mature and not locked)
+    pub spendable: bool,
+}
+
+/// `listunspent [descriptors] ( "asset_id" )`
+///
+/// Unspent outputs of the wallet described by `descriptors` (default: the
+/// descriptors imported with `importdescriptors`), optionally
+/// restricted to one asset, by asset and then by decreasing amount.
+/// Outputs already spent by a mempool transaction are left out. Amounts of
+/// different assets are never comparable: pass `asset_id` before summing.
+pub fn list_unspent(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: ListUnspentParams = parse_params(params)?;
+    let scripts = wallet_scripts(context, &params.descriptors)?;
+    let asset_id = params.asset_id.as_deref().map(parse_asset_id).transpose()?;
+
+    let snapshot = context.db.snapshot();
+    let tip = snapshot.get_metadata().map_err(|e| RpcError::DatabaseError(e.to_string()))?.height;
+    let mut utxos = wallet_utxos(&snapshot, &scripts)?;
+    utxos.retain(|utxo| asset_id.is_none_or(|asset_id| utxo.output.asset_id == asset_id));
+    if let Some(mempool) = &context.mempool {
+        let mempool = mempool.lock().unwrap();
+        utxos.retain(|utxo| mempool.spender(&utxo.outpoint).is_none());
+    }
+    utxos.sort_by_key(|utxo| (utxo.output.asset_id, std::cmp::Reverse(utxo.output.value)));
+
+    let coin_control = context.coin_control.lock().unwrap();
+    let unspents: Vec<UnspentInfo> = utxos.into_iter()
+        .map(|utxo| {
+            let locked = coin_control.is_frozen(&utxo.outpoint);
+            let mature = utxo.is_mature_with(tip + 1, context.params.coinbase_maturity);
+            UnspentInfo {
+                txid: utxo.outpoint.txid.into(),
+                vout: utxo.outpoint.vout,
+                script_pubkey: hex::encode(&utxo.output.script_pubkey),
+                amount: utxo.output.value,
+                asset_id: hex::encode(utxo.output.asset_id),
+                height: utxo.height,
+                confirmations: tip.saturating_sub(utxo.height) + 1,
+                coinbase: utxo.is_coinbase,
+                locked,
+                spendable: mature && !locked,
+            }
+        })
+        .collect();
+    to_value(&unspents)
+}
+
+/// Params for `getbalances`
+#[derive(Debug, Default, Deserialize)]
+struct GetBalancesParams {
+    /// Descriptors or script hex of the wallet outputs (as in `scantxoutset`;
+    /// default: the imported descriptors)
+    #[serde(default)]
+    descriptors: Vec<ScanObject>,
+}
+
+/// Balance of one asset in `getbalances`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetBalance {
+    /// Spendable in the next block
+    pub confirmed: Amount,
+    /// Received by mempool transactions
+    pub unconfirmed: Amount,
+    /// Coinbase outputs not yet mature
+    pub immature: Amount,
+}
+
+impl From<&Balance> for AssetBalance {
+    fn from(balance: &Balance) -> Self {
+        Self { confirmed: balance.confirmed, unconfirmed: balance.unconfirmed, immature: balance.immature }
+    }
+}
+
+/// Result of `getbalances`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancesInfo {
+    /// Chain height of the balances
+    pub height: u64,
+    /// Native SLY, the only asset that pays fees
+    pub native: AssetBalance,
+    /// Other assets keyed by asset id (hex)
+    pub assets: BTreeMap<String, AssetBalance>,
+}
+
+/// `getbalances [descriptors]`
+///
+/// Balances of the wallet described by `descriptors` (default: the
+/// descriptors imported with `importdescriptors`), split per asset into
+/// confirmed, unconfirmed and immature amounts (see
+/// `sedly_wallet::balance`). Native SLY is reported apart from the other
+/// assets, whose amounts are in their own units.
+pub fn get_balances(context: &RpcContext, params: &Value) -> Result<Value, RpcError> {
+    let params: GetBalancesParams = parse_params(params)?;
+    let scripts = wallet_scripts(context, &params.descriptors)?;
+
+    let height = context.db.get_height().map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let account_of = |script: &[u8]| scripts.contains(script).then_some(0);
+    let balances = match &context.mempool {
+        Some(mempool) => account_balances(&context.db, &mempool.lock().unwrap(), &context.params, account_of),
+        None => account_balances(&context.db, &sedly_core::Mempool::new(), &context.params, account_of),
+    }
+    .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+
+    let mut info = BalancesInfo { height, native: AssetBalance::default(), assets: BTreeMap::new() };
+    for (asset_id, balance) in balances.account(0) {
+        if asset_id == [0; 32] {
+            info.native = AssetBalance::from(&balance);
+        } else {
+            info.assets.insert(hex::encode(asset_id), AssetBalance::from(&balance));
+        }
+    }
+    to_value(&info)
+}
+
+/// Header index of the context, caught up with the database tip
+///
+/// Blocks connected on top of the cached tip are added incrementally; after
+/// a reorg the index is reloaded from the database.
+pub(crate) fn synced_headers(context: &RpcContext) -> Result<MutexGuard<'_, Option<HeaderCache>>, RpcError> {
+    let metadata = context.db.get_metadata()
+        .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+    let mut headers = context.headers.lock().unwrap();
+
+    let cached_tip = headers.as_ref().and_then(|headers| headers.tip()).map(|(hash, entry)| (hash, entry.height));
+    if cached_tip.map(|(hash, _)| hash) == Some(metadata.best_block_hash) {
+        return Ok(headers);
+    }
+
+    if let (Some(cache), Some((_, cached_height))) = (headers.as_mut(), cached_tip) {
+        if cached_height < metadata.height {
+            let new_headers = context.db.get_headers_in_range(cached_height + 1, metadata.height)
+                .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+            let extended = new_headers.iter().all(|header| cache.connect(header).is_ok());
+            if extended && cache.tip().map(|(hash, _)| hash) == Some(metadata.best_block_hash) {
+                return Ok(headers);
+            }
+        }
+    }
+
+    *headers = Some(HeaderCache::load(&context.db).map_err(|e| RpcError::DatabaseError(e.to_string()))?);
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::validation::block_subsidy;
+    use sedly_core::{
+        anyone_can_spend, Block, BlockValidator, BlockchainDB, ChainParams, Transaction, TxInput, TxOutput,
+    };
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn create_test_context(blocks: u64, spacing: u64) -> (RpcContext, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+
+        let mut previous_hash = [0; 32];
+        for height in 0..blocks {
+            let coinbase = Transaction::coinbase(&anyone_can_spend(b"miner"), height, 50);
+            let mut block = Block::new(previous_hash, vec![coinbase], 0x1d00ffff, height);
+            block.header.timestamp = 1704067200 + height * spacing;
+            db.store_block(&block).unwrap();
+            previous_hash = block.hash();
+        }
+
+        (RpcContext::new(db, ChainParams::regtest()), temp_dir)
+    }
+
+    #[test]
+    fn test_difficulty_history() {
+        let (context, _temp) = create_test_context(25, 60);
+
+        let value = get_difficulty_history(&context, &serde_json::json!([0, 24])).unwrap();
+        let history: DifficultyHistory = serde_json::from_value(value).unwrap();
+
+        // Regtest: epochs of 10 blocks
+        assert_eq!(history.adjustment_interval, 10);
+        assert_eq!(history.epochs.len(), 3);
+        assert_eq!(history.epochs[1].average_block_interval, 60.0);
+        assert!(history.epochs[1].estimated_hashrate > 0.0);
+    }
+
+    #[test]
+    fn test_difficulty_history_defaults_to_tip() {
+        let (context, _temp) = create_test_context(5, 120);
+
+        let value = get_difficulty_history(&context, &Value::Null).unwrap();
+        let history: DifficultyHistory = serde_json::from_value(value).unwrap();
+
+        assert_eq!(history.from_height, 0);
+        assert_eq!(history.to_height, 4);
+    }
+
+    #[test]
+    fn test_scan_tx_out_set() {
+        let (context, _temp) = create_test_context(3, 120);
+
+        let value = scan_tx_out_set(
+            &context,
+            &serde_json::json!(["start", [format!("raw({})", hex::encode(anyone_can_spend(b"miner")))]]),
+        ).unwrap();
+        let result: ScanTxOutSetResult = serde_json::from_value(value).unwrap();
+
+        assert!(result.success);
+        assert_eq!(result.txouts, 3);
+        assert_eq!(result.unspents.len(), 3);
+        assert_eq!(result.total_amount, Amount::from_sat(150));
+        assert!(result.unspents.iter().all(|utxo| utxo.coinbase));
+
+        // No scan in progress once it completes
+        assert_eq!(scan_tx_out_set(&context, &serde_json::json!(["status"])).unwrap(), Value::Null);
+        assert_eq!(scan_tx_out_set(&context, &serde_json::json!(["abort"])).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_scan_tx_out_set_ranged_descriptor() {
+        use sedly_wallet::ExtendedPrivKey;
+
+        let (context, _temp) = create_test_context(1, 120);
+        let xpub = ExtendedPrivKey::new_master(sedly_core::Network::Regtest, &[3u8; 32])
+            .unwrap()
+            .to_extended_public();
+        let descriptor: Descriptor = format!("pkh({}/0/*)", xpub).parse().unwrap();
+
+        // Payment to the fifth derived address
+        let payee = descriptor.script_pubkey(5).unwrap();
+        let block = Block::new(
+            context.db.get_best_block_hash().unwrap(),
+            vec![Transaction::coinbase(&payee, 1, 50)],
+            0x1d00ffff,
+            1,
+        );
+        context.db.store_block(&block).unwrap();
+
+        let value = scan_tx_out_set(
+            &context,
+            &serde_json::json!({"action": "start", "scanobjects": [{"desc": descriptor.to_string(), "range": [0, 9]}]}),
+        ).unwrap();
+        let result: ScanTxOutSetResult = serde_json::from_value(value).unwrap();
+        assert_eq!(result.unspents.len(), 1);
+        assert_eq!(result.unspents[0].desc, descriptor.to_string());
+
+        let value = scan_tx_out_set(
+            &context,
+            &serde_json::json!(["start", [{"desc": descriptor.to_string(), "range": 4}]]),
+        ).unwrap();
+        let result: ScanTxOutSetResult = serde_json::from_value(value).unwrap();
+        assert!(result.unspents.is_empty());
+    }
+
+    #[test]
+    fn test_scan_tx_out_set_invalid_params() {
+        let (context, _temp) = create_test_context(1, 120);
+
+        let result = scan_tx_out_set(&context, &serde_json::json!(["start", ["zz"]]));
+        assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+
+        let result = scan_tx_out_set(&context, &serde_json::json!(["rescan"]));
+        assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_tx_out_set_info() {
+        let (context, _temp) = create_test_context(4, 120);
+
+        let value = get_tx_out_set_info(&context, &Value::Null).unwrap();
+        let info: TxOutSetInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(info.height, 3);
+        assert_eq!(info.transactions, 4);
+        assert_eq!(info.txouts, 4);
+        assert_eq!(info.total_amount, Amount::from_sat(200));
+        assert!(info.asset_amounts.is_empty());
+        assert_eq!(info.bestblock, BlockHash::from(context.db.get_best_block_hash().unwrap()));
+
+        // Cached until the tip moves
+        assert!(context.utxo_stats.lock().unwrap().is_some());
+        let block = Block::new(
+            context.db.get_best_block_hash().unwrap(),
+            vec![Transaction::coinbase(b"miner", 4, 50)],
+            0x1d00ffff,
+            4,
+        );
+        context.db.store_block(&block).unwrap();
+        let value = get_tx_out_set_info(&context, &Value::Null).unwrap();
+        let updated: TxOutSetInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(updated.txouts, 5);
+        assert_ne!(updated.hash_serialized, info.hash_serialized);
+    }
+
+    #[test]
+    fn test_utxo_set_hash() {
+        let (context, _temp) = create_test_context(3, 120);
+
+        // The rolling hash of the tip matches the one of a full scan
+        let value = get_utxo_set_hash(&context, &Value::Null).unwrap();
+        let tip: UtxoSetHashInfo = serde_json::from_value(value).unwrap();
+        let info: TxOutSetInfo = serde_json::from_value(get_tx_out_set_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!(tip.height, 2);
+        assert_eq!(tip.blockhash, info.bestblock);
+        assert_eq!(tip.muhash, info.muhash);
+
+        let value = get_utxo_set_hash(&context, &serde_json::json!([1])).unwrap();
+        let first: UtxoSetHashInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(first.height, 1);
+        assert_ne!(first.muhash, tip.muhash);
+        let value = get_utxo_set_hash(&context, &serde_json::json!([first.blockhash.to_string()])).unwrap();
+        assert_eq!(serde_json::from_value::<UtxoSetHashInfo>(value).unwrap().muhash, first.muhash);
+
+        let result = get_utxo_set_hash(&context, &serde_json::json!([9]));
+        assert!(matches!(result, Err(RpcError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_treasury_info() {
+        let (context, _temp) = create_test_context(1, 120);
+        let value = get_treasury_info(&context, &Value::Null).unwrap();
+        let info: TreasuryInfo = serde_json::from_value(value).unwrap();
+        assert!(!info.enabled);
+
+        let keys = vec![vec![0x02; 33], vec![0x03; 33], vec![0x04; 33]];
+        let treasury = sedly_core::TreasuryParams::multisig(2, &keys, 1_000).unwrap();
+        let context = RpcContext::new(context.db.clone(), ChainParams::regtest().with_treasury(treasury.clone()));
+        for height in 1..=2 {
+            let mut coinbase = Transaction::coinbase(b"miner", height, 50);
+            treasury.apply_to_coinbase(&mut coinbase, 50);
+            let block = Block::new(context.db.get_best_block_hash().unwrap(), vec![coinbase], 0x1d00ffff, height);
+            context.db.store_block(&block).unwrap();
+        }
+
+        let value = get_treasury_info(&context, &Value::Null).unwrap();
+        let info: TreasuryInfo = serde_json::from_value(value).unwrap();
+        assert!(info.enabled);
+        assert_eq!(info.threshold, Some(2));
+        assert_eq!(info.pubkeys.len(), 3);
+        assert_eq!(info.balance, Amount::from_sat(10));
+        assert_eq!(info.utxos, 2);
+        assert_eq!(info.height, 2);
+        assert_eq!(info.next_allocation.to_sat(), treasury.allocation(block_subsidy(3)));
+    }
+
+    #[test]
+    fn test_network_params() {
+        let (context, _temp) = create_test_context(3, 120);
+        let context = RpcContext::new(context.db.clone(), ChainParams::regtest().with_soft_fork("strictsig", 3));
+        context.pipeline.lock().unwrap().validator_mut().set_max_block_size(2_000_000);
+
+        let value = get_network_params(&context, &Value::Null).unwrap();
+        let info: NetworkParamsInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(info.network, "regtest");
+        assert_eq!(info.magic, hex::encode(sedly_core::Network::Regtest.magic()));
+        assert_eq!(info.difficulty_adjustment_interval, 10);
+        assert_eq!(info.difficulty_algorithm.retarget_window, "overlapping");
+        assert_eq!(info.max_block_size, 2_000_000);
+        assert_eq!(info.coinbase_maturity, sedly_core::COINBASE_MATURITY);
+        assert_eq!(info.height, 2);
+        assert_eq!(info.softforks.len(), 1);
+        assert!(info.softforks[0].active);
+    }
+
+    #[test]
+    fn test_supply_info() {
+        let (context, _temp) = create_test_context(5, 60);
+        let value = get_supply_info(&context, &Value::Null).unwrap();
+        let info: SupplyInfo = serde_json::from_value(value).unwrap();
+        assert_eq!((info.height, info.tip_height), (4, 4));
+        assert_eq!(info.supply.to_sat(), 4 * sedly_core::INITIAL_BLOCK_REWARD);
+        assert_eq!(info.average_block_time, 60.0);
+        let halving = info.next_halving.unwrap();
+        assert_eq!(halving.height, sedly_core::HALVING_INTERVAL);
+        assert_eq!(halving.blocks_remaining, sedly_core::HALVING_INTERVAL - 4);
+
+        let value = get_supply_info(&context, &serde_json::json!({"height": sedly_core::HALVING_INTERVAL})).unwrap();
+        let info: SupplyInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(info.subsidy.to_sat(), sedly_core::INITIAL_BLOCK_REWARD / 2);
+        assert_eq!(info.tip_height, 4);
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let (context, _temp) = create_test_context(3, 120);
+        let block = context.db.get_block_by_height(2).unwrap().unwrap();
+
+        let value = get_block_stats(&context, &serde_json::json!([2])).unwrap();
+        let stats: BlockStatsInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(stats.blockhash, block.block_hash());
+        assert_eq!((stats.txs, stats.ins, stats.totalfee), (1, 0, Amount::ZERO));
+
+        let value = get_block_stats(&context, &serde_json::json!([block.block_hash().to_string()])).unwrap();
+        let by_hash: BlockStatsInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(by_hash.height, 2);
+
+        assert!(matches!(get_block_stats(&context, &serde_json::json!([9])), Err(RpcError::InvalidParams(_))));
+        assert!(matches!(get_block_stats(&context, &Value::Null), Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_reorgs() {
+        let (context, _temp) = create_test_context(4, 120);
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = context.db.get_block_by_height(2).unwrap().unwrap();
+        invalidate_block(&context, &serde_json::json!([block.block_hash().to_string()])).unwrap();
+
+        let value = get_reorgs(&context, &Value::Null).unwrap();
+        let reorgs: Vec<ReorgInfo> = serde_json::from_value(value).unwrap();
+        assert_eq!(reorgs.len(), 1);
+        assert_eq!(reorgs[0].old_tip, BlockHash::from(tip));
+        assert_eq!((reorgs[0].depth, reorgs[0].new_height, reorgs[0].deep), (2, 1, false));
+    }
+
+    #[test]
+    fn test_chain_tips() {
+        let (context, _temp) = create_test_context(3, 120);
+        let genesis = context.db.get_block_by_height(0).unwrap().unwrap();
+
+        let value = get_chain_tips(&context, &Value::Null).unwrap();
+        let tips: Vec<ChainTipInfo> = serde_json::from_value(value).unwrap();
+        assert_eq!(tips.len(), 1);
+        assert_eq!((tips[0].height, tips[0].branchlen, tips[0].status), (2, 0, TipStatus::Active));
+
+        // One-block fork on top of genesis, known by header only
+        let fork = Block::new(genesis.hash(), vec![Transaction::coinbase(b"other", 1, 50)], 0x1d00ffff, 1);
+        context.headers.lock().unwrap().as_mut().unwrap().insert(&fork.header).unwrap();
+
+        // The tip moves on: the index is extended without losing the fork
+        let block = Block::new(
+            context.db.get_best_block_hash().unwrap(),
+            vec![Transaction::coinbase(b"miner", 3, 50)],
+            0x1d00ffff,
+            3,
+        );
+        context.db.store_block(&block).unwrap();
+
+        let value = get_chain_tips(&context, &Value::Null).unwrap();
+        let tips: Vec<ChainTipInfo> = serde_json::from_value(value).unwrap();
+        assert_eq!(tips.len(), 2);
+        assert_eq!(tips[0].hash, block.block_hash());
+        assert_eq!((tips[1].height, tips[1].branchlen, tips[1].status), (1, 1, TipStatus::HeadersOnly));
+    }
+
+    #[test]
+    fn test_blockchain_info() {
+        let (context, _temp) = create_test_context(3, 120);
+
+        // Tip from 2024: the node is still behind
+        let value = get_blockchain_info(&context, &Value::Null).unwrap();
+        let info: BlockchainInfo = serde_json::from_value(value).unwrap();
+        assert_eq!((info.blocks, info.headers, info.chain.as_str()), (2, 2, "regtest"));
+        assert!(info.initialblockdownload);
+        assert!(info.verificationprogress < 0.01);
+
+        // A freshly found block brings the node up to date
+        let block = Block::new(
+            context.db.get_best_block_hash().unwrap(),
+            vec![Transaction::coinbase(b"miner", 3, 50)],
+            0x1d00ffff,
+            3,
+        );
+        context.db.store_block(&block).unwrap();
+        let value = get_blockchain_info(&context, &Value::Null).unwrap();
+        let info: BlockchainInfo = serde_json::from_value(value).unwrap();
+        assert_eq!((info.blocks, info.bestblockhash), (3, block.block_hash()));
+        assert_eq!(info.verificationprogress, 1.0);
+        assert!(!info.initialblockdownload);
+    }
+
+    #[test]
+    fn test_reconsider_block() {
+        let (context, _temp) = create_test_context(2, 120);
+        let tip = context.db.get_best_block_hash().unwrap();
+        let bad = Block::new(tip, vec![Transaction::coinbase(b"miner", 2, 50)], 0x1d00ffff, 2);
+        context.db.mark_block_invalid(&bad.hash(), &sedly_core::InvalidBlock {
+            height: 2,
+            previous_hash: tip,
+            reason: "test".to_string(),
+        }).unwrap();
+
+        assert!(matches!(
+            reconsider_block(&context, &serde_json::json!(["zz"])),
+            Err(RpcError::InvalidParams(_))
+        ));
+        let bad_hash = serde_json::json!([bad.block_hash()]);
+        assert_eq!(reconsider_block(&context, &bad_hash).unwrap(), Value::Null);
+        assert!(!context.db.is_block_invalid(&bad.hash()).unwrap());
+    }
+
+    #[test]
+    fn test_operator_block_methods() {
+        let (context, _temp) = create_test_context(2, 120);
+        let chain = |parent: [u8; 32], height: u64, count: u64, miner: &[u8]| {
+            let mut blocks: Vec<Block> = Vec::new();
+            for height in height..height + count {
+                let previous = blocks.last().map_or(parent, |block| block.hash());
+                blocks.push(Block::new(previous, vec![Transaction::coinbase(miner, height, 50)], 0x1d00ffff, height));
+            }
+            blocks
+        };
+        let submit = |block: &Block| {
+            let hex = hex::encode(bincode::serialize(block).unwrap());
+            submit_block(&context, &serde_json::json!([hex])).unwrap()
+        };
+        let hash_param = |block: &Block| serde_json::json!([block.block_hash().to_string()]);
+
+        let main = chain(context.db.get_best_block_hash().unwrap(), 2, 2, b"miner");
+        assert_eq!(submit(&main[1]), Value::from("inconclusive"));
+        assert_eq!(submit(&main[0]), Value::Null);
+        assert_eq!(submit(&main[0]), Value::from("duplicate"));
+        assert_eq!(context.db.get_best_block_hash().unwrap(), main[1].hash());
+
+        let oversized = "00".repeat(MAX_BLOCK_DECODE_SIZE + 1);
+        let error = submit_block(&context, &serde_json::json!([oversized])).unwrap_err();
+        assert_eq!(error.code(), RpcError::PayloadTooLarge(String::new()).code());
+        assert!(matches!(submit_block(&context, &serde_json::json!(["00"])), Err(RpcError::InvalidParams(_))));
+
+        // The main branch leaves the active chain and is replaced by a longer fork
+        let parent = main[0].header.previous_hash;
+        assert_eq!(invalidate_block(&context, &hash_param(&main[0])).unwrap(), Value::Null);
+        assert_eq!(context.db.get_best_block_hash().unwrap(), parent);
+        assert_eq!(submit(&main[1]), Value::from("duplicate"));
+        let fork = chain(parent, 2, 3, b"other");
+        for block in &fork {
+            assert_eq!(submit(block), Value::Null);
+        }
+        assert!(matches!(precious_block(&context, &hash_param(&main[1])), Err(RpcError::InvalidParams(_))));
+
+        // With less work preciousblock does not change the active chain
+        reconsider_block(&context, &hash_param(&main[0])).unwrap();
+        precious_block(&context, &hash_param(&main[1])).unwrap();
+        assert_eq!(context.db.get_best_block_hash().unwrap(), fork[2].hash());
+
+        invalidate_block(&context, &hash_param(&fork[0])).unwrap();
+        assert_eq!(context.db.get_best_block_hash().unwrap(), main[1].hash());
+        reconsider_block(&context, &hash_param(&fork[0])).unwrap();
+        precious_block(&context, &hash_param(&fork[2])).unwrap();
+        assert_eq!(context.db.get_best_block_hash().unwrap(), fork[2].hash());
+
+        let tips = get_chain_tips(&context, &Value::Null).unwrap();
+        assert_eq!(tips.as_array().unwrap().len(), 2);
+        let unknown = serde_json::json!([BlockHash::from([9; 32])]);
+        assert!(matches!(invalidate_block(&context, &unknown), Err(RpcError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_chain_owned_by_node() {
+        let (context, _temp) = create_test_context(2, 120);
+        let context = context.without_chain_control();
+        let tip = context.db.get_best_block_hash().unwrap();
+        let tip_param = serde_json::json!([BlockHash::from(tip).to_string()]);
+        assert!(matches!(invalidate_block(&context, &tip_param), Err(RpcError::InvalidRequest(_))));
+        assert!(matches!(precious_block(&context, &tip_param), Err(RpcError::InvalidRequest(_))));
+        assert!(matches!(reconsider_block(&context, &tip_param), Err(RpcError::InvalidRequest(_))));
+
+        let block = Block::new(tip, vec![Transaction::coinbase(b"miner", 2, 50)], 0x1d00ffff, 2);
+        let hex = serde_json::json!([hex::encode(bincode::serialize(&block).unwrap())]);
+        assert!(matches!(submit_block(&context, &hex), Err(RpcError::InvalidRequest(_))));
+
+        // With a submitter the block goes to the node instead of the local pipeline
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let context = context.with_block_submitter(Arc::new(move |block: Block| {
+            sink.lock().unwrap().push(block.hash());
+            Ok(Some("inconclusive".to_string()))
+        }));
+        assert_eq!(submit_block(&context, &hex).unwrap(), Value::from("inconclusive"));
+        assert_eq!(*received.lock().unwrap(), vec![block.hash()]);
+        assert_eq!(context.db.get_best_block_hash().unwrap(), tip);
+    }
+
+    #[test]
+    fn test_reorg_updates_mempool() {
+        let (context, _temp) = create_test_context(103, 60);
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase, 0), vec![])],
+            vec![TxOutput::to_address(40, b"alice")],
+            0,
+        );
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"miner", 103, 50), tx.clone()], 0x1d00ffff, 103);
+        context.db.store_block(&block).unwrap();
+        let mempool = Arc::new(std::sync::Mutex::new(sedly_core::Mempool::new()));
+        let context = context.with_mempool(mempool.clone());
+        let hash_param = serde_json::json!([block.block_hash().to_string()]);
+
+        // The transaction of the disconnected block returns to the pool
+        invalidate_block(&context, &hash_param).unwrap();
+        assert!(mempool.lock().unwrap().contains(&tx.hash()));
+
+        // Once the block is reconsidered, the transaction is confirmed again
+        reconsider_block(&context, &hash_param).unwrap();
+        precious_block(&context, &hash_param).unwrap();
+        assert_eq!(context.db.get_best_block_hash().unwrap(), block.hash());
+        assert!(mempool.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wallet_passphrase() {
+        let (context, temp) = create_test_context(1, 120);
+        assert!(matches!(wallet_lock(&context, &Value::Null), Err(RpcError::NotFound(_))));
+
+        let kdf = sedly_wallet::KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let path = temp.path().join("keystore.json");
+        let context = context.with_keystore(sedly_wallet::Keystore::new("old", kdf).unwrap(), path.clone());
+
+        assert!(matches!(
+            wallet_passphrase(&context, &serde_json::json!(["wrong", 60])),
+            Err(RpcError::InvalidParams(_))
+        ));
+        wallet_passphrase(&context, &serde_json::json!(["old", 60])).unwrap();
+        assert!(!context.keystore.lock().unwrap().as_mut().unwrap().0.is_locked());
+        wallet_lock(&context, &Value::Null).unwrap();
+        assert!(context.keystore.lock().unwrap().as_mut().unwrap().0.is_locked());
+
+        wallet_passphrase_change(&context, &serde_json::json!(["old", "new"])).unwrap();
+        let mut saved = sedly_wallet::Keystore::load(&path).unwrap();
+        assert!(saved.unlock("new", Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_import_descriptors() {
+        let (context, temp) = create_test_context(1, 60);
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"wallet", 1, 50)], 0x1d00ffff, 1);
+        context.db.store_block(&block).unwrap();
+        let wallet = serde_json::json!([[{"desc": hex::encode(b"wallet"), "label": "cold"}]]);
+        assert!(matches!(import_descriptors(&context, &wallet), Err(RpcError::NotFound(_))));
+
+        let kdf = sedly_wallet::KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let path = temp.path().join("keystore.json");
+        let context = context.with_keystore(sedly_wallet::Keystore::new("pass", kdf).unwrap(), path.clone());
+        assert!(serde_json::from_value::<Vec<UnspentInfo>>(list_unspent(&context, &Value::Null).unwrap())
+            .unwrap()
+            .is_empty());
+
+        // Locked keystore: enough for public descriptors
+        let imported: Vec<WatchedDescriptorInfo> =
+            serde_json::from_value(import_descriptors(&context, &wallet).unwrap()).unwrap();
+        assert_eq!(imported[0].label, "cold");
+        assert!(imported[0].desc.starts_with(&format!("raw({})#", hex::encode(b"wallet"))));
+        assert!(sedly_wallet::Keystore::load(&path).unwrap().descriptors().any(|(label, _)| label == "cold"));
+
+        // Without explicit descriptors the imported ones are used
+        let unspents: Vec<UnspentInfo> = serde_json::from_value(list_unspent(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!(unspents.len(), 1);
+        assert_eq!(unspents[0].script_pubkey, hex::encode(b"wallet"));
+
+        // Private keys are refused, and no descriptor of the request is imported
+        let xprv = sedly_wallet::ExtendedPrivKey::new_master(sedly_core::Network::Regtest, &[1; 32]).unwrap();
+        let mixed = serde_json::json!([[{"desc": "00", "label": "other"}, {"desc": format!("pkh({}/0/*)", xprv)}]]);
+        assert!(matches!(import_descriptors(&context, &mixed), Err(RpcError::InvalidParams(_))));
+        let listed: Vec<WatchedDescriptorInfo> =
+            serde_json::from_value(list_descriptors(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!(listed.iter().map(|info| info.label.as_str()).collect::<Vec<_>>(), vec!["cold"]);
+    }
+
+    #[test]
+    fn test_import_and_sweep_priv_key() {
+        let (context, temp) = create_test_context(103, 60);
+        let secret_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let wif = PrivateKey::new(sedly_core::Network::Regtest, secret_key).to_string();
+        let mainnet = PrivateKey::new(sedly_core::Network::Mainnet, secret_key).to_string();
+        let [p2pkh, _] = PrivateKey::new(sedly_core::Network::Regtest, secret_key).script_pubkeys();
+
+        // A P2PKH output of the key, outside a coinbase
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let payment = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase, 0), vec![])],
+            vec![TxOutput::new(80_000, [0; 32], p2pkh)],
+            0,
+        );
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"miner", 103, 50), payment], 0x1d00ffff, 103);
+        context.db.store_block(&block).unwrap();
+
+        let kdf = sedly_wallet::KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let path = temp.path().join("keystore.json");
+        let context = context.with_keystore(sedly_wallet::Keystore::new("pass", kdf).unwrap(), path.clone());
+        assert!(matches!(import_priv_key(&context, &serde_json::json!([wif])), Err(RpcError::Internal(_))));
+        wallet_passphrase(&context, &serde_json::json!(["pass", 60])).unwrap();
+        assert!(matches!(import_priv_key(&context, &serde_json::json!([mainnet])), Err(RpcError::InvalidParams(_))));
+
+        let value = import_priv_key(&context, &serde_json::json!([wif, "paper"])).unwrap();
+        let info: ImportPrivKeyInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(info.label, "paper");
+        assert_eq!((info.unspents, info.total_amount), (Some(1), Some(Amount::from_sat(80_000))));
+        assert!(sedly_wallet::Keystore::load(&path).unwrap().contains("paper"));
+        let duplicate = serde_json::json!([wif, "paper"]);
+        assert!(matches!(import_priv_key(&context, &duplicate), Err(RpcError::InvalidParams(_))));
+
+        // Unauthenticated callers cannot name the keys of the keystore
+        let by_label = serde_json::json!(["paper", hex::encode(b"wallet"), 2]);
+        assert!(matches!(sweep_wif_key(&context, &by_label), Err(RpcError::InvalidParams(_))));
+
+        // Sweep by label, into the node mempool
+        let mempool = Arc::new(std::sync::Mutex::new(sedly_core::Mempool::new()));
+        let context = context.with_mempool(mempool.clone());
+        let value = sweep_priv_key(&context, &by_label).unwrap();
+        let sweep: SweepInfo = serde_json::from_value(value).unwrap();
+        assert!(sweep.in_mempool);
+        assert_eq!((sweep.inputs, sweep.immature), (1, 0));
+        assert_eq!(sweep.amount.saturating_add(sweep.fee), Amount::from_sat(80_000));
+        assert!(mempool.lock().unwrap().contains(&sweep.txid.to_byte_array()));
+
+        // The output is already spent in the mempool
+        let by_wif = serde_json::json!([wif, hex::encode(b"wallet")]);
+        assert!(matches!(sweep_priv_key(&context, &by_wif), Err(RpcError::InvalidParams(_))));
+        let error = sweep_wif_key(&context, &by_wif).unwrap_err();
+        assert!(matches!(error, RpcError::InvalidParams(ref e) if e.contains("No spendable")));
+        let unknown = serde_json::json!(["nokey", "00"]);
+        assert!(matches!(sweep_priv_key(&context, &unknown), Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_create_and_fund_raw_transaction() {
+        let (context, _temp) = create_test_context(1, 60);
+        // Two wallet outputs outside a coinbase
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let payment = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase, 0), vec![])],
+            vec![
+                TxOutput::new(30_000, [0; 32], b"wallet".to_vec()),
+                TxOutput::new(20_000, [0; 32], b"other".to_vec()),
+            ],
+            0,
+        );
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"miner", 1, 50), payment.clone()], 0x1d00ffff, 1);
+        context.db.store_block(&block).unwrap();
+
+        let outputs = serde_json::json!([{"script_pubkey": hex::encode(b"payee"), "amount": 10_000}]);
+        let value = create_raw_transaction(&context, &serde_json::json!([[], outputs, 7])).unwrap();
+        let raw = value.as_str().unwrap().to_string();
+        let tx: Transaction = bincode::deserialize(&hex::decode(&raw).unwrap()).unwrap();
+        assert!(tx.inputs.is_empty());
+        assert_eq!((tx.outputs[0].value, tx.lock_time), (Amount::from_sat(10_000), 7));
+        let zero = serde_json::json!([[], [{"script_pubkey": "00", "amount": 0}]]);
+        assert!(matches!(create_raw_transaction(&context, &zero), Err(RpcError::InvalidParams(_))));
+
+        // Funds from the wallet descriptor, change to the change script
+        let options = serde_json::json!({
+            "descriptors": [hex::encode(b"wallet")],
+            "change_script": hex::encode(b"change"),
+            "fee_rate": 2,
+        });
+        let value = fund_raw_transaction(&context, &serde_json::json!([raw, options])).unwrap();
+        let funded: FundedTransaction = serde_json::from_value(value).unwrap();
+        let tx: Transaction = bincode::deserialize(&hex::decode(&funded.hex).unwrap()).unwrap();
+        assert_eq!(funded.inputs, vec![OutPointParam { txid: payment.txid(), vout: 0 }]);
+        assert_eq!(funded.change_outputs.len(), 1);
+        let change = &tx.outputs[funded.change_outputs[0] as usize];
+        assert_eq!(change.script_pubkey, b"change".to_vec());
+        assert_eq!(change.value.saturating_add(funded.fee), Amount::from_sat(20_000));
+        assert!(funded.fee >= Amount::from_sat(sedly_core::MIN_TX_FEE));
+        assert!(tx.inputs.iter().all(|input| input.script_sig.is_empty()));
+
+        // An input already present is kept, even outside the descriptors
+        let inputs = serde_json::json!([{"txid": payment.txid(), "vout": 1}]);
+        let value = create_raw_transaction(&context, &serde_json::json!([inputs, outputs])).unwrap();
+        let value = fund_raw_transaction(&context, &serde_json::json!([value, options])).unwrap();
+        let funded: FundedTransaction = serde_json::from_value(value).unwrap();
+        assert_eq!(funded.inputs, vec![OutPointParam { txid: payment.txid(), vout: 1 }]);
+
+        // Insufficient funds, unknown input
+        let outputs = serde_json::json!([{"script_pubkey": hex::encode(b"payee"), "amount": 100_000}]);
+        let unfunded = create_raw_transaction(&context, &serde_json::json!([[], outputs])).unwrap();
+        let inputs = serde_json::json!([{"txid": payment.txid(), "vout": 5}]);
+        let unknown = create_raw_transaction(&context, &serde_json::json!([inputs, []])).unwrap();
+        for raw in [unfunded, unknown] {
+            let result = fund_raw_transaction(&context, &serde_json::json!([raw, options]));
+            assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+        }
+    }
+
+    #[test]
+    fn test_list_unspent_and_balances_per_asset() {
+        let (context, _temp) = create_test_context(1, 60);
+        const ASSET: [u8; 32] = [7; 32];
+        // The wallet receives SLY and an asset, plus an immature coinbase
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let payment = Transaction::new(
+            vec![TxInput::new(OutPoint::new(coinbase, 0), vec![])],
+            vec![
+                TxOutput::new(30_000, [0; 32], b"wallet".to_vec()),
+                TxOutput::new(900, ASSET, b"wallet".to_vec()),
+                TxOutput::new(20_000, [0; 32], b"other".to_vec()),
+            ],
+            0,
+        );
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"wallet", 1, 50), payment.clone()], 0x1d00ffff, 1);
+        context.db.store_block(&block).unwrap();
+        context.coin_control.lock().unwrap().freeze(OutPoint::new(payment.hash(), 0));
+
+        let wallet = serde_json::json!({"descriptors": [hex::encode(b"wallet")]});
+        let value = list_unspent(&context, &wallet).unwrap();
+        let unspents: Vec<UnspentInfo> = serde_json::from_value(value).unwrap();
+        assert_eq!(unspents.len(), 3);
+        // SLY first (highest value first), then the asset
+        assert_eq!(unspents[0].amount, Amount::from_sat(30_000));
+        assert!(unspents[0].locked && !unspents[0].spendable);
+        assert!(unspents[1].coinbase && !unspents[1].spendable);
+        assert_eq!((unspents[2].asset_id.clone(), unspents[2].amount), (hex::encode(ASSET), Amount::from_sat(900)));
+        assert!(unspents[2].spendable);
+
+        let only_asset = serde_json::json!({"descriptors": [hex::encode(b"wallet")], "asset_id": hex::encode(ASSET)});
+        let unspents: Vec<UnspentInfo> = serde_json::from_value(list_unspent(&context, &only_asset).unwrap()).unwrap();
+        assert_eq!(unspents.len(), 1);
+        assert_eq!(unspents[0].vout, 1);
+        let invalid = serde_json::json!({"descriptors": [], "asset_id": "07"});
+        assert!(matches!(list_unspent(&context, &invalid), Err(RpcError::InvalidParams(_))));
+
+        // Balances never add up different assets
+        let balances: BalancesInfo = serde_json::from_value(get_balances(&context, &wallet).unwrap()).unwrap();
+        assert_eq!(balances.height, 1);
+        assert_eq!(balances.native.confirmed, Amount::from_sat(30_000));
+        assert_eq!(balances.native.immature, Amount::from_sat(50));
+        assert_eq!(balances.assets.len(), 1);
+        assert_eq!(balances.assets[&hex::encode(ASSET)].confirmed, Amount::from_sat(900));
+    }
+
+    #[test]
+    fn test_decode_raw_transaction() {
+        let (context, _temp) = create_test_context(1, 120);
+        let pubkey = vec![0x02; 33];
+        let state = sedly_core::state::state_script(b"datum", &ScriptTemplate::p2pk(&pubkey)).unwrap();
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new([1; 32], 3), vec![2, 0xaa, 0xbb])],
+            vec![
+                TxOutput::new(40, [0; 32], ScriptTemplate::p2pkh(&hash160(&pubkey))),
+                TxOutput::new(7, [9; 32], state),
+            ],
+            5,
+        );
+        let hex_tx = hex::encode(bincode::serialize(&tx).unwrap());
+
+        let value = decode_raw_transaction(&context, &serde_json::json!([hex_tx])).unwrap();
+        let decoded: DecodedTransaction = serde_json::from_value(value).unwrap();
+        assert_eq!((decoded.txid, decoded.locktime, decoded.size), (tx.txid(), 5, tx.size().unwrap()));
+        assert_eq!(decoded.vin[0].vout, Some(3));
+        assert_eq!(decoded.vin[0].script_sig.as_ref().unwrap().asm, "aabb");
+        let p2pkh = &decoded.vout[0].script_pubkey;
+        assert_eq!((p2pkh.script_type.as_str(), p2pkh.req_sigs), ("pubkeyhash", Some(1)));
+        assert_eq!(p2pkh.addresses, vec![hex::encode(hash160(&pubkey))]);
+        assert_eq!(decoded.vout[1].asset_id, hex::encode([9; 32]));
+        assert_eq!(decoded.vout[1].script_pubkey.script_type, "state");
+        assert_eq!(decoded.vout[1].script_pubkey.datum, Some(hex::encode(b"datum")));
+        assert_eq!(decoded.expiryheight, None);
+
+        // Version 2 transaction with an expiry
+        let expiring = hex::encode(bincode::serialize(&tx.clone().with_expiry(90)).unwrap());
+        let value = decode_raw_transaction(&context, &serde_json::json!([expiring])).unwrap();
+        let decoded: DecodedTransaction = serde_json::from_value(value).unwrap();
+        assert_eq!((decoded.version, decoded.expiryheight), (2, Some(90)));
+
+        // Coinbase: the input script is not a script_sig
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].clone();
+        let hex_coinbase = hex::encode(bincode::serialize(&coinbase).unwrap());
+        let value = decode_raw_transaction(&context, &serde_json::json!([hex_coinbase])).unwrap();
+        let decoded: DecodedTransaction = serde_json::from_value(value).unwrap();
+        assert!(decoded.vin[0].coinbase.is_some() && decoded.vin[0].txid.is_none());
+        assert_eq!(decoded.vout[0].script_pubkey.script_type, "nonstandard");
+
+        for invalid in ["zz", "0100"] {
+            let result = decode_raw_transaction(&context, &serde_json::json!([invalid]));
+            assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+        }
+
+        let value = decode_script(&context, &serde_json::json!({"hexstring": "6a0568656c6c6f"})).unwrap();
+        let script: ScriptInfo = serde_json::from_value(value).unwrap();
+        assert_eq!((script.script_type.as_str(), script.asm.as_str()), ("nulldata", "OP_RETURN 68656c6c6f"));
+        let multisig = ScriptTemplate::multisig(2, &[pubkey.clone(), vec![0x03; 33]]).unwrap();
+        let value = decode_script(&context, &serde_json::json!([hex::encode(multisig)])).unwrap();
+        let script: ScriptInfo = serde_json::from_value(value).unwrap();
+        assert_eq!((script.req_sigs, script.addresses.len()), (Some(2), 2));
+    }
+
+    #[test]
+    fn test_get_raw_transaction() {
+        let (context, _temp) = create_test_context(103, 60);
+        let block = context.db.get_block_by_height(100).unwrap().unwrap();
+        let coinbase = block.transactions[0].clone();
+
+        let value = get_raw_transaction(&context, &serde_json::json!([coinbase.txid()])).unwrap();
+        assert_eq!(value, Value::from(hex::encode(bincode::serialize(&coinbase).unwrap())));
+        let value = get_raw_transaction(&context, &serde_json::json!([coinbase.txid(), true])).unwrap();
+        let info: RawTransactionInfo = serde_json::from_value(value).unwrap();
+        assert_eq!(info.decoded, DecodedTransaction::from_transaction(&coinbase).unwrap());
+        assert_eq!((info.blockhash, info.blockheight), (Some(BlockHash::from(block.hash())), Some(100)));
+        assert_eq!((info.blocktime, info.confirmations), (Some(block.header.timestamp), 3));
+        assert!(!info.in_mempool);
+
+        // Without a mempool an unknown transaction is not found
+        let mature = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let tx = Transaction::new(
+            vec![TxInput::new(OutPoint::new(mature, 0), vec![])],
+            vec![TxOutput::to_address(40, b"alice")],
+            0,
+        );
+        let params = serde_json::json!({"txid": tx.txid(), "verbose": true});
+        assert!(matches!(get_raw_transaction(&context, &params), Err(RpcError::NotFound(_))));
+
+        // Waiting in the mempool: no block and no confirmations
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let mut mempool = sedly_core::Mempool::new();
+        mempool.add(tx.clone(), 102, &validator, &context.db).unwrap();
+        let context = context.with_mempool(Arc::new(std::sync::Mutex::new(mempool)));
+        let info: RawTransactionInfo = serde_json::from_value(get_raw_transaction(&context, &params).unwrap()).unwrap();
+        assert_eq!((info.decoded.txid, info.confirmations), (tx.txid(), 0));
+        assert_eq!((info.blockhash, info.blockheight, info.blocktime), (None, None, None));
+        assert!(info.in_mempool);
+    }
+
+    #[test]
+    fn test_lock_unspent() {
+        let (context, _temp) = create_test_context(2, 120);
+        let block = context.db.get_block_by_height(1).unwrap().unwrap();
+        let txid = block.transactions[0].txid().to_string();
+        let output = serde_json::json!([{"txid": txid, "vout": 0}]);
+
+        lock_unspent(&context, &serde_json::json!([false, output])).unwrap();
+        assert_eq!(list_lock_unspent(&context, &Value::Null).unwrap(), output);
+        assert!(matches!(
+            lock_unspent(&context, &serde_json::json!([false, output])),
+            Err(RpcError::InvalidParams(_))
+        ));
+
+        // No lock changes if an output is invalid
+        let mixed = serde_json::json!([true, [{"txid": txid, "vout": 0}, {"txid": txid, "vout": 7}]]);
+        assert!(matches!(lock_unspent(&context, &mixed), Err(RpcError::InvalidParams(_))));
+        assert_eq!(list_lock_unspent(&context, &Value::Null).unwrap(), output);
+
+        lock_unspent(&context, &serde_json::json!([true])).unwrap();
+        assert_eq!(list_lock_unspent(&context, &Value::Null).unwrap(), serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_list_mempool_pages() {
+        let (context, _temp) = create_test_context(103, 60);
+        assert!(matches!(list_mempool(&context, &Value::Null), Err(RpcError::NotFound(_))));
+
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let mut mempool = sedly_core::Mempool::new();
+        for height in 0..3 {
+            let coinbase = context.db.get_block_by_height(height).unwrap().unwrap().transactions[0].hash();
+            let tx = Transaction::new(
+                vec![TxInput::new(OutPoint::new(coinbase, 0), vec![])],
+                vec![TxOutput::to_address(40, b"alice")],
+                0,
+            );
+            mempool.add(tx, 102, &validator, &context.db).unwrap();
+        }
+        let context = context.with_mempool(Arc::new(std::sync::Mutex::new(mempool)));
+
+        let first: Page<MempoolTx> = serde_json::from_value(list_mempool(&context, &serde_json::json!([null, 2])).unwrap()).unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[0].fee, Amount::from_sat(10));
+        let cursor = first.next_cursor.unwrap();
+        assert_eq!(cursor, first.items[1].txid.to_string());
+
+        let second: Page<MempoolTx> = serde_json::from_value(list_mempool(&context, &serde_json::json!([cursor, 2])).unwrap()).unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert!(second.items[0].txid > first.items[1].txid);
+        assert!(second.next_cursor.is_none());
+
+        assert!(matches!(list_mempool(&context, &serde_json::json!(["zz"])), Err(RpcError::InvalidParams(_))));
+        assert!(matches!(list_mempool(&context, &serde_json::json!([null, 0])), Err(RpcError::InvalidParams(_))));
+
+        let info: MempoolInfo = serde_json::from_value(get_mempool_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!((info.size, info.maxmempool, info.spilled), (3, sedly_core::DEFAULT_MEMPOOL_MAX_SIZE, 0));
+        // Three coinbases never spent: the filter rules out a recent spend of each
+        assert_eq!((info.spentfilter.lookups, info.spentfilter.negatives), (3, 3));
+        assert_eq!(info.spentfilter.items, 3);
+    }
+
+    #[test]
+    fn test_test_mempool_accept() {
+        let (context, _temp) = create_test_context(103, 60);
+        let spend = |outpoint: OutPoint, value: u64| {
+            let output = TxOutput::to_address(value, &anyone_can_spend(b"alice"));
+            Transaction::new(vec![TxInput::new(outpoint, vec![])], vec![output], 0)
+        };
+        let raw = |tx: &Transaction| hex::encode(bincode::serialize(tx).unwrap());
+        let coinbase = context.db.get_block_by_height(0).unwrap().unwrap().transactions[0].hash();
+        let parent = spend(OutPoint::new(coinbase, 0), 40);
+        let child = spend(OutPoint::new(parent.hash(), 0), 30);
+        let unknown = spend(OutPoint::new([9; 32], 0), 30);
+        let params = serde_json::json!([[raw(&parent), raw(&child), raw(&unknown)]]);
+        assert!(matches!(test_mempool_accept(&context, &params), Err(RpcError::NotFound(_))));
+
+        let mempool = Arc::new(std::sync::Mutex::new(sedly_core::Mempool::new()));
+        let context = context.with_mempool(mempool.clone());
+        let value = test_mempool_accept(&context, &params).unwrap();
+        let results: Vec<MempoolAcceptResult> = serde_json::from_value(value).unwrap();
+        assert_eq!(results.len(), 3);
+        // The child spends the parent of the package
+        assert!(results[0].allowed && results[1].allowed);
+        assert_eq!(results[0].txid, parent.txid());
+        assert_eq!((results[0].fee, results[1].fee), (Some(Amount::from_sat(10)), Some(Amount::from_sat(10))));
+        assert!(results[0].feerate.unwrap() > 0);
+        assert!(!results[2].allowed && results[2].reject_reason.is_some() && results[2].fee.is_none());
+        assert!(mempool.lock().unwrap().is_empty());
+
+        let invalid = serde_json::json!([["zz"]]);
+        assert!(matches!(test_mempool_accept(&context, &invalid), Err(RpcError::InvalidParams(_))));
+        let too_many = serde_json::json!([vec![raw(&parent); MAX_TEST_ACCEPT_TXS + 1]]);
+        assert!(matches!(test_mempool_accept(&context, &too_many), Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_block_template_long_poll() {
+        fn template(context: &RpcContext, params: &Value) -> BlockTemplateInfo {
+            serde_json::from_value(get_block_template(context, params).unwrap()).unwrap()
+        }
+        let (mut context, _temp) = create_test_context(103, 60);
+        let validator = BlockValidator::new(ChainParams::regtest());
+        let spend = |outpoint: OutPoint, value: u64| {
+            let output = TxOutput::to_address(value, &anyone_can_spend(b"alice"));
+            Transaction::new(vec![TxInput::new(outpoint, vec![])], vec![output], 0)
+        };
+        let mut mempool = sedly_core::Mempool::new();
+        let mut parents = Vec::new();
+        for height in 0..3 {
+            let coinbase = context.db.get_block_by_height(height).unwrap().unwrap().transactions[0].hash();
+            let tx = spend(OutPoint::new(coinbase, 0), 40);
+            mempool.add(tx.clone(), 102, &validator, &context.db).unwrap();
+            parents.push(tx);
+        }
+        let mempool = Arc::new(std::sync::Mutex::new(mempool));
+        context.mempool = Some(mempool.clone());
+        context.longpoll_timeout = Duration::from_millis(300);
+
+        let first = template(&context, &Value::Null);
+        assert_eq!((first.height, first.transactions.len()), (103, 3));
+        assert_eq!(first.coinbasevalue, Amount::from_sat(block_subsidy(103) + 30));
+        assert_eq!(first.previousblockhash, BlockHash::from(context.db.get_best_block_hash().unwrap()));
+
+        // Unchanged template: the request returns only when the timeout expires
+        let start = Instant::now();
+        let params = serde_json::json!([first.longpollid]);
+        let unchanged = template(&context, &params);
+        assert!(start.elapsed() >= context.longpoll_timeout);
+        assert_eq!(unchanged.longpollid, first.longpollid);
+
+        // A child raising the fees from 30 to 40 makes the template stale
+        context.longpoll_timeout = Duration::from_secs(30);
+        let child = spend(OutPoint::new(parents[1].hash(), 0), 30);
+        mempool.lock().unwrap().add(child.clone(), 102, &validator, &context.db).unwrap();
+        let start = Instant::now();
+        let richer = template(&context, &params);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(richer.coinbasevalue, Amount::from_sat(block_subsidy(103) + 40));
+        let position = |txid: Txid| richer.transactions.iter().position(|tx| tx.txid == txid).unwrap();
+        let (parent, child) = (position(parents[1].txid()), position(child.txid()));
+        assert!(parent < child);
+        assert_eq!(richer.transactions[child].depends, vec![parent + 1]);
+
+        // A new tip releases the waiting long poll
+        let params = serde_json::json!({"longpollid": richer.longpollid});
+        let tip = context.db.get_best_block_hash().unwrap();
+        let block = Block::new(tip, vec![Transaction::coinbase(b"miner", 103, 50)], 0x1d00ffff, 103);
+        let start = Instant::now();
+        let next = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(200));
+                context.db.store_block(&block).unwrap();
+            });
+            template(&context, &params)
+        });
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!((next.height, next.previousblockhash), (104, BlockHash::from(block.hash())));
+
+        assert!(matches!(
+            get_block_template(&context, &serde_json::json!(["zz"])),
+            Err(RpcError::InvalidParams(_))
+        ));
+    }
+
+    #[test]
+    fn test_difficulty_history_invalid_range() {
+        let (context, _temp) = create_test_context(5, 120);
+
+        let result = get_difficulty_history(&context, &serde_json::json!({"from_height": 4, "to_height": 1}));
+        assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_peer_info_and_totals() {
+        let (context, _temp) = create_test_context(1, 60);
+        assert!(matches!(get_peer_info(&context, &Value::Null), Err(RpcError::NotFound(_))));
+
+        let mut stats = sedly_core::NetStats::new();
+        stats.connect("10.0.0.2:9333", true, 100);
+        stats.connect("10.0.0.1:9333", false, 100);
+        stats.record_received("10.0.0.1:9333", "block", 500, 101);
+        stats.record_ping("10.0.0.1:9333", 250_000);
+        stats.record_height("10.0.0.1:9333", 42);
+        let services = sedly_core::ServiceFlags::NETWORK | sedly_core::ServiceFlags::COMPACT_FILTERS;
+        stats.record_version("10.0.0.1:9333", 2, services, "/sedly:0.1.0/", 40);
+        stats.connect("10.0.0.3:9333", false, 100);
+        stats.record_sent("10.0.0.3:9333", "ping", 32, 102);
+        stats.disconnect("10.0.0.3:9333");
+        let context = context.with_net_stats(Arc::new(std::sync::Mutex::new(stats)));
+
+        let peers: Vec<PeerInfo> = serde_json::from_value(get_peer_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].addr, "10.0.0.1:9333");
+        assert_eq!(peers[0].ping_time, Some(0.25));
+        assert_eq!(peers[0].synced_height, Some(42));
+        assert_eq!(peers[0].version, Some(2));
+        assert_eq!(peers[0].services, "0000000000000005");
+        assert_eq!(peers[0].servicesnames, vec!["NETWORK", "COMPACT_FILTERS"]);
+        assert_eq!((peers[0].subver.as_str(), peers[0].startingheight), ("/sedly:0.1.0/", 40));
+        assert!(peers[1].inbound);
+        assert_eq!((peers[1].version, peers[1].services.as_str()), (None, "0000000000000000"));
+
+        let totals: NetTotalsInfo = serde_json::from_value(get_net_totals(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!((totals.total_bytes_sent, totals.total_bytes_recv), (32, 500));
+        assert_eq!(totals.connections, 2);
+    }
+
+    #[test]
+    fn test_rejections() {
+        let (context, _temp) = create_test_context(2, 60);
+        assert!(matches!(get_rejections(&context, &Value::Null), Err(RpcError::NotFound(_))));
+        let rejections = Arc::new(std::sync::Mutex::new(sedly_core::RejectionLog::new(2)));
+        let context = context.with_rejection_log(rejections);
+
+        // Three blocks claiming too much: the buffer keeps the last two
+        let tip = context.db.get_best_block_hash().unwrap();
+        let greedy: Vec<Block> = (0..3u8)
+            .map(|i| Block::new(tip, vec![Transaction::coinbase(&[i], 2, u64::MAX / 2)], 0x1d00ffff, 2))
+            .collect();
+        for block in &greedy {
+            let hex = hex::encode(bincode::serialize(block).unwrap());
+            assert_ne!(submit_block(&context, &serde_json::json!([hex])).unwrap(), Value::Null);
+        }
+
+        let info: RejectionsInfo = serde_json::from_value(get_rejections(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!((info.capacity, info.total, info.rejections.len()), (2, 3, 2));
+        let latest = &info.rejections[0];
+        assert_eq!((latest.kind.as_str(), latest.hash), ("block", Hash256::from(greedy[2].hash())));
+        assert_eq!((latest.rule.as_str(), latest.height, latest.tip_height), ("excessive-coinbase", 2, 1));
+        assert_eq!(latest.hex, hex::encode(bincode::serialize(&greedy[2]).unwrap()));
+        assert!(!latest.truncated);
+
+        let result = get_rejections(&context, &serde_json::json!([1])).unwrap();
+        let info: RejectionsInfo = serde_json::from_value(result).unwrap();
+        assert_eq!(info.rejections.len(), 1);
+    }
+
+    #[test]
+    fn test_node_info_and_alerts() {
+        use secp256k1::{PublicKey, Secp256k1, SecretKey};
+        use sedly_core::{Alert, AlertSet};
+
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &secret_key).serialize().to_vec();
+        let (context, _temp) = create_test_context(2, 60);
+        let context = RpcContext::new(context.db.clone(), ChainParams::regtest().with_alert_key(pubkey));
+
+        let info: NodeInfo = serde_json::from_value(get_node_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!((info.network.as_str(), info.height), ("regtest", 1));
+        assert_eq!((info.connections, info.mempool_size), (None, None));
+        assert!(info.warnings.is_empty());
+
+        let alert = |id: u32, cancel: Vec<u32>, message: &str| {
+            let alert = Alert { id, cancel, expiration: u64::MAX, priority: 1, message: message.to_string() };
+            hex::encode(alert.sign(&secp, &secret_key).to_bytes())
+        };
+        let upgrade = alert(1, Vec::new(), "Upgrade before height 100");
+        assert!(matches!(send_alert(&context, &serde_json::json!([upgrade])), Err(RpcError::NotFound(_))));
+        let context = context.with_alerts(Arc::new(std::sync::Mutex::new(AlertSet::new())));
+
+        let result = send_alert(&context, &serde_json::json!([upgrade])).unwrap();
+        let result: SendAlertResult = serde_json::from_value(result).unwrap();
+        assert!(result.new && result.alert.id == 1);
+        let info: NodeInfo = serde_json::from_value(get_node_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!(info.warnings, vec!["Upgrade before height 100".to_string()]);
+
+        // Signed by another key
+        let forged = Alert { id: 2, cancel: Vec::new(), expiration: u64::MAX, priority: 1, message: "Fake".to_string() }
+            .sign(&secp, &SecretKey::from_slice(&[8; 32]).unwrap());
+        let forged = hex::encode(forged.to_bytes());
+        assert!(matches!(send_alert(&context, &serde_json::json!([forged])), Err(RpcError::InvalidParams(_))));
+
+        send_alert(&context, &serde_json::json!({"hexstring": alert(3, vec![1], "")})).unwrap();
+        let info: NodeInfo = serde_json::from_value(get_node_info(&context, &Value::Null).unwrap()).unwrap();
+        assert!(info.alerts.is_empty());
+        assert!(matches!(send_alert(&context, &serde_json::json!([upgrade])), Err(RpcError::InvalidParams(_))));
+
+        // The hardware check warnings follow the alert ones
+        let report = HardwareReport {
+            measured_at: 0,
+            pow_algorithm: "sha256d".to_string(),
+            hash_rate: 1.0,
+            disk_write_rate: 1e9,
+            disk_available: None,
+            warnings: vec!["Slow hashing".to_string()],
+        };
+        let context = context.with_hardware_report(report.clone());
+        let info: NodeInfo = serde_json::from_value(get_node_info(&context, &Value::Null).unwrap()).unwrap();
+        assert_eq!((info.warnings, info.hardware), (vec!["Slow hashing".to_string()], Some(report)));
+    }
+}
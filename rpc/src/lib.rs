@@ -0,0 +1,10 @@
+//! Sedly RPC - JSON-RPC interface for blockchain queries
+
+pub mod auth;
+pub mod client;
+pub mod handlers;
+pub mod server;
+
+pub use auth::RpcAuth;
+pub use client::{ClientError, RpcClient};
+pub use server::{BlockSubmitter, RpcConfig, RpcContext, RpcError, RpcRequest, RpcResponse, RpcServer};
@@ -0,0 +1,694 @@
+//! JSON-RPC server for Sedly nodes
+
+use crate::auth::RpcAuth;
+use crate::handlers::{self, ScanState};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{extract::State, Json, Router};
+use sedly_core::{
+    AlertSet, Block, BlockPipeline, BlockValidator, BlockchainDB, ChainParams, HardwareReport, HeaderCache, Mempool,
+    NetStats, OrphanPool, RejectionLog, ReorgAlarm, UtxoSetStats,
+};
+use sedly_wallet::{CoinControl, Keystore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tower_http::cors::CorsLayer;
+
+/// Default maximum number of calls in a batch request
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// Default time a `getblocktemplate` long poll waits for a new template
+pub const DEFAULT_LONGPOLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delay before answering a request with wrong credentials, against guessing
+const AUTH_FAILURE_DELAY: Duration = Duration::from_millis(250);
+
+/// Methods that read or spend through the wallet keystore, its descriptors
+/// or its coin control, reserved to authenticated callers
+///
+/// `sweepprivkey` is served to other callers only for a WIF key, which
+/// proves they own it.
+pub const WALLET_METHODS: &[&str] = &[
+    "walletpassphrase",
+    "walletlock",
+    "walletpassphrasechange",
+    "importprivkey",
+    "importdescriptors",
+    "listdescriptors",
+    "sweepprivkey",
+    "lockunspent",
+    "listlockunspent",
+    "fundrawtransaction",
+    "listunspent",
+    "getbalances",
+];
+
+/// Configuration for the RPC server
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    /// HTTP bind address
+    pub bind_addr: String,
+    /// Maximum number of calls accepted in a single batch request
+    pub max_batch_size: usize,
+    /// Credentials of authenticated callers; without them no caller can
+    /// use the wallet methods
+    pub auth: Option<RpcAuth>,
+    /// Browser origins allowed to call the server (CORS), none by default
+    ///
+    /// Cross-origin requests may not carry credentials, so pages on these
+    /// origins never reach the wallet methods.
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: "127.0.0.1:8545".to_string(),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            auth: None,
+            cors_origins: Vec::new(),
+        }
+    }
+}
+
+/// Hands a block from `submitblock` to the node that owns the chain
+///
+/// Returns the `submitblock` result: None when the block is accepted,
+/// otherwise the short reason ("duplicate", "inconclusive", ...). An Err is
+/// a node failure, reported as an RPC error.
+pub type BlockSubmitter = Arc<dyn Fn(Block) -> Result<Option<String>, String> + Send + Sync>;
+
+/// Shared state available to every RPC handler
+pub struct RpcContext {
+    /// Blockchain database
+    pub db: Arc<BlockchainDB>,
+    /// Consensus parameters of the network
+    pub params: ChainParams,
+    /// UTXO set scan in progress (at most one at a time)
+    pub(crate) utxo_scan: Mutex<Option<ScanState>>,
+    /// `gettxoutsetinfo` computation in progress
+    pub(crate) utxo_stats_scan: Mutex<Option<ScanState>>,
+    /// Last `gettxoutsetinfo` result, reused while the tip is unchanged
+    pub(crate) utxo_stats: Mutex<Option<UtxoSetStats>>,
+    /// Header index, loaded on first use and extended as the tip moves
+    pub(crate) headers: Mutex<Option<HeaderCache>>,
+    /// Pipeline for blocks submitted or reconnected by operator methods
+    pub(crate) pipeline: Mutex<BlockPipeline>,
+    /// Submitted blocks waiting for their parent
+    pub(crate) orphans: Mutex<OrphanPool>,
+    /// Encrypted wallet keystore and the file it is saved to
+    pub(crate) keystore: Mutex<Option<(Keystore, PathBuf)>>,
+    /// Outputs locked with `lockunspent`, never picked by coin selection
+    pub(crate) coin_control: Mutex<CoinControl>,
+    /// Node mempool, if the node shares one with the RPC server
+    pub(crate) mempool: Option<Arc<Mutex<Mempool>>>,
+    /// Peer traffic statistics, if the node shares them with the RPC server
+    pub(crate) net_stats: Option<Arc<Mutex<NetStats>>>,
+    /// Consensus-rule rejections, if the node keeps a rejection log
+    pub(crate) rejections: Option<Arc<Mutex<RejectionLog>>>,
+    /// Network alerts, if the node shares them with the RPC server
+    pub(crate) alerts: Option<Arc<Mutex<AlertSet>>>,
+    /// Startup hardware measurements, if the node ran them
+    pub(crate) hardware: Option<HardwareReport>,
+    /// Maximum wait of a `getblocktemplate` long poll
+    pub(crate) longpoll_timeout: Duration,
+    /// Whether sync progress is extrapolated from the age of the tip
+    pub(crate) clock_sync: bool,
+    /// Whether the operator methods may connect and disconnect blocks here
+    pub(crate) chain_control: bool,
+    /// Node receiving submitted blocks instead of the local pipeline
+    pub(crate) block_submitter: Option<BlockSubmitter>,
+}
+
+impl RpcContext {
+    /// Create new handler context
+    pub fn new(db: Arc<BlockchainDB>, params: ChainParams) -> Self {
+        Self {
+            db,
+            utxo_scan: Mutex::new(None),
+            utxo_stats_scan: Mutex::new(None),
+            utxo_stats: Mutex::new(None),
+            headers: Mutex::new(None),
+            pipeline: Mutex::new(BlockPipeline::new(BlockValidator::new(params.clone()))),
+            orphans: Mutex::new(OrphanPool::default()),
+            keystore: Mutex::new(None),
+            coin_control: Mutex::new(CoinControl::new()),
+            mempool: None,
+            net_stats: None,
+            rejections: None,
+            alerts: None,
+            hardware: None,
+            longpoll_timeout: DEFAULT_LONGPOLL_TIMEOUT,
+            clock_sync: true,
+            chain_control: true,
+            block_submitter: None,
+            params,
+        }
+    }
+
+    /// Attach the wallet keystore used by `walletpassphrase` and friends
+    pub fn with_keystore(self, keystore: Keystore, path: PathBuf) -> Self {
+        *self.keystore.lock().unwrap() = Some((keystore, path));
+        self
+    }
+
+    /// Attach the node mempool used by `listmempool` and `getmempoolinfo`
+    pub fn with_mempool(mut self, mempool: Arc<Mutex<Mempool>>) -> Self {
+        self.mempool = Some(mempool);
+        self
+    }
+
+    /// Alarm raised by reorgs performed through the operator methods
+    pub fn with_reorg_alarm(self, alarm: ReorgAlarm) -> Self {
+        self.pipeline.lock().unwrap().set_reorg_alarm(alarm);
+        self
+    }
+
+    /// Attach the peer statistics used by `getpeerinfo` and `getnettotals`
+    pub fn with_net_stats(mut self, net_stats: Arc<Mutex<NetStats>>) -> Self {
+        self.net_stats = Some(net_stats);
+        self
+    }
+
+    /// Attach the rejection log dumped by `getrejections`
+    ///
+    /// Blocks rejected by `submitblock` and the operator methods are
+    /// recorded in it as well.
+    pub fn with_rejection_log(mut self, rejections: Arc<Mutex<RejectionLog>>) -> Self {
+        self.pipeline.lock().unwrap().set_rejection_log(Some(rejections.clone()));
+        self.rejections = Some(rejections);
+        self
+    }
+
+    /// Measure sync progress against the best known header instead of the clock
+    ///
+    /// For consensus mode: Tendermint commits blocks only when there is
+    /// something to commit (`create_empty_blocks = false`), so an old tip
+    /// does not mean the node is behind.
+    pub fn without_clock_sync(mut self) -> Self {
+        self.clock_sync = false;
+        self
+    }
+
+    /// Refuse `submitblock`, `invalidateblock`, `preciousblock` and `reconsiderblock`
+    ///
+    /// For a node that connects blocks itself: changes made through this
+    /// context's own pipeline would bypass the node's header index and
+    /// state. In consensus mode Tendermint alone decides the chain.
+    pub fn without_chain_control(mut self) -> Self {
+        self.chain_control = false;
+        self
+    }
+
+    /// Hand submitted blocks to the node that owns the chain
+    ///
+    /// The other methods that change the chain are refused, as with
+    /// [`RpcContext::without_chain_control`].
+    pub fn with_block_submitter(mut self, submitter: BlockSubmitter) -> Self {
+        self.chain_control = false;
+        self.block_submitter = Some(submitter);
+        self
+    }
+
+    /// Attach the network alerts shown by `getnodeinfo` and extended by `sendalert`
+    pub fn with_alerts(mut self, alerts: Arc<Mutex<AlertSet>>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Attach the startup hardware measurements shown by `getnodeinfo`
+    pub fn with_hardware_report(mut self, report: HardwareReport) -> Self {
+        self.hardware = Some(report);
+        self
+    }
+
+    /// Maximum time a `getblocktemplate` long poll is held without a new template
+    ///
+    /// Keep it below the HTTP timeout of the mining clients.
+    pub fn with_longpoll_timeout(mut self, timeout: Duration) -> Self {
+        self.longpoll_timeout = timeout;
+        self
+    }
+}
+
+/// JSON-RPC 2.0 request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRequest {
+    /// Protocol version (optional, "2.0" assumed)
+    #[serde(default)]
+    pub jsonrpc: Option<String>,
+    /// Request identifier echoed in the response
+    #[serde(default)]
+    pub id: Value,
+    /// Method name
+    pub method: String,
+    /// Positional (array) or named (object) parameters
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// JSON-RPC 2.0 error object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcErrorObject {
+    /// Error code
+    pub code: i64,
+    /// Human readable message
+    pub message: String,
+}
+
+/// JSON-RPC 2.0 response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    /// Protocol version
+    pub jsonrpc: String,
+    /// Identifier of the request
+    pub id: Value,
+    /// Result on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error on failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcErrorObject>,
+}
+
+impl RpcResponse {
+    /// Build response from a handler result
+    pub fn from_result(id: Value, result: Result<Value, RpcError>) -> Self {
+        match result {
+            Ok(value) => Self {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: Some(value),
+                error: None,
+            },
+            Err(e) => Self {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error: Some(RpcErrorObject {
+                    code: e.code(),
+                    message: e.to_string(),
+                }),
+            },
+        }
+    }
+}
+
+/// RPC server serving the JSON-RPC endpoint
+pub struct RpcServer {
+    /// Server configuration
+    config: RpcConfig,
+    /// Handler context
+    context: Arc<RpcContext>,
+}
+
+impl RpcServer {
+    /// Create new RPC server
+    pub fn new(config: RpcConfig, context: RpcContext) -> Self {
+        Self {
+            config,
+            context: Arc::new(context),
+        }
+    }
+
+    /// Build the HTTP router
+    pub fn router(&self) -> Router {
+        let state = ServerState {
+            context: Arc::clone(&self.context),
+            max_batch_size: self.config.max_batch_size,
+            auth: self.config.auth.clone(),
+        };
+        let router = Router::new()
+            .route("/", post(handle_rpc))
+            .route("/ready", get(handle_ready));
+        let origins: Vec<HeaderValue> = self.config.cors_origins.iter()
+            .filter_map(|origin| match HeaderValue::from_str(origin) {
+                Ok(origin) => Some(origin),
+                Err(_) => {
+                    log::warn!("Ignoring invalid CORS origin {}", origin);
+                    None
+                }
+            })
+            .collect();
+        if origins.is_empty() {
+            return router.with_state(state);
+        }
+        // No Authorization header: browsers cannot send credentials cross-origin
+        let cors = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([header::CONTENT_TYPE]);
+        router.layer(cors).with_state(state)
+    }
+
+    /// Start serving requests
+    pub async fn start(&self) -> Result<(), RpcError> {
+        let tip = self.context.db.get_metadata_async()
+            .await
+            .map_err(|e| RpcError::DatabaseError(e.to_string()))?;
+        log::info!("Starting Sedly RPC server on {} at height {}", self.config.bind_addr, tip.height);
+
+        let listener = TcpListener::bind(&self.config.bind_addr)
+            .await
+            .map_err(|e| RpcError::Internal(format!("Failed to bind RPC server: {}", e)))?;
+
+        axum::serve(listener, self.router())
+            .await
+            .map_err(|e| RpcError::Internal(format!("Server error: {}", e)))
+    }
+
+    /// Get server configuration
+    pub fn config(&self) -> &RpcConfig {
+        &self.config
+    }
+}
+
+/// State shared by the HTTP handlers
+#[derive(Clone)]
+struct ServerState {
+    context: Arc<RpcContext>,
+    max_batch_size: usize,
+    auth: Option<RpcAuth>,
+}
+
+/// HTTP entry point for JSON-RPC requests
+///
+/// The body is a single request object or a batch array. Handlers may scan
+/// large parts of the database, so they run on the blocking thread pool
+/// instead of the async workers.
+///
+/// Requests with the configured credentials are authenticated; those
+/// without an `Authorization` header may call every method but the
+/// [`WALLET_METHODS`]; wrong credentials get a 401.
+async fn handle_rpc(State(state): State<ServerState>, headers: HeaderMap, Json(body): Json<Value>) -> Response {
+    let authenticated = match (&state.auth, headers.get(header::AUTHORIZATION)) {
+        (_, None) => false,
+        (Some(auth), Some(credentials)) if auth.verify(credentials.as_bytes()) => true,
+        (_, Some(_)) => {
+            log::warn!("RPC request with wrong credentials");
+            tokio::time::sleep(AUTH_FAILURE_DELAY).await;
+            let challenge = [(header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\"")];
+            return (StatusCode::UNAUTHORIZED, challenge).into_response();
+        }
+    };
+    let response = tokio::task::spawn_blocking(move || {
+        handle_body(&state.context, body, state.max_batch_size, authenticated)
+    })
+        .await
+        .unwrap_or_else(|e| {
+            let error = RpcError::Internal(format!("Handler failed: {}", e));
+            Some(serde_json::to_value(RpcResponse::from_result(Value::Null, Err(error))).unwrap_or_default())
+        });
+    match response {
+        Some(response) => Json(response).into_response(),
+        // Only notifications: nothing to answer
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// HTTP readiness probe (`GET /ready`)
+async fn handle_ready(State(state): State<ServerState>) -> (StatusCode, Json<Value>) {
+    let (status, body) = tokio::task::spawn_blocking(move || readiness(&state.context))
+        .await
+        .unwrap_or_else(|e| (StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({ "error": e.to_string() })));
+    (status, Json(body))
+}
+
+/// Status and body of the readiness probe
+///
+/// 200 with the `getblockchaininfo` result once the verification progress
+/// reaches [`handlers::READY_SYNC_PROGRESS`]; 503 while the node is still
+/// catching up or its chain cannot be read, so load balancers and
+/// dependent services wait for a usable node.
+pub fn readiness(context: &RpcContext) -> (StatusCode, Value) {
+    match handlers::blockchain_info(context) {
+        Ok(info) => {
+            let status = if info.initialblockdownload { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+            (status, serde_json::to_value(info).unwrap_or_default())
+        }
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({ "error": e.to_string() })),
+    }
+}
+
+/// Process a request body: one request object or a batch array
+///
+/// Batch calls run in order and the responses keep the order of the
+/// requests. An empty batch or one larger than `max_batch_size` is rejected
+/// as a whole with a single error response. Notifications (requests without
+/// an `id`) are executed but get no response, so the result is None when
+/// the body holds only notifications. Unless `authenticated`, the
+/// [`WALLET_METHODS`] are refused.
+pub fn handle_body(context: &RpcContext, body: Value, max_batch_size: usize, authenticated: bool) -> Option<Value> {
+    let response = match body {
+        Value::Array(requests) if requests.is_empty() => {
+            RpcResponse::from_result(Value::Null, Err(RpcError::InvalidRequest("Empty batch".to_string())))
+        }
+        Value::Array(requests) if requests.len() > max_batch_size => RpcResponse::from_result(
+            Value::Null,
+            Err(RpcError::InvalidRequest(format!(
+                "Batch of {} calls exceeds the limit of {}", requests.len(), max_batch_size
+            ))),
+        ),
+        Value::Array(requests) => {
+            let responses: Vec<RpcResponse> = requests.into_iter()
+                .filter_map(|request| handle_request(context, request, authenticated))
+                .collect();
+            if responses.is_empty() {
+                return None;
+            }
+            return Some(serde_json::to_value(responses).unwrap_or_default());
+        }
+        request => handle_request(context, request, authenticated)?,
+    };
+    Some(serde_json::to_value(response).unwrap_or_default())
+}
+
+/// Process a single request object, None for a notification
+fn handle_request(context: &RpcContext, request: Value, authenticated: bool) -> Option<RpcResponse> {
+    let notification = request.as_object().is_some_and(|object| !object.contains_key("id"));
+    match serde_json::from_value::<RpcRequest>(request) {
+        Ok(RpcRequest { id, method, params, .. }) => {
+            let result = if authenticated || !WALLET_METHODS.contains(&method.as_str()) {
+                dispatch(context, &method, &params)
+            } else if method == "sweepprivkey" {
+                handlers::sweep_wif_key(context, &params)
+            } else {
+                Err(RpcError::Unauthorized(method))
+            };
+            (!notification).then(|| RpcResponse::from_result(id, result))
+        }
+        Err(e) => Some(RpcResponse::from_result(Value::Null, Err(RpcError::InvalidRequest(e.to_string())))),
+    }
+}
+
+/// Route a method call to its handler
+pub fn dispatch(context: &RpcContext, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getdifficultyhistory" => handlers::get_difficulty_history(context, params),
+        "scantxoutset" => handlers::scan_tx_out_set(context, params),
+        "gettreasuryinfo" => handlers::get_treasury_info(context, params),
+        "getnetworkparams" => handlers::get_network_params(context, params),
+        "getsupplyinfo" => handlers::get_supply_info(context, params),
+        "getblockstats" => handlers::get_block_stats(context, params),
+        "gettxoutsetinfo" => handlers::get_tx_out_set_info(context, params),
+        "getutxosethash" => handlers::get_utxo_set_hash(context, params),
+        "getblockchaininfo" => handlers::get_blockchain_info(context, params),
+        "getchaintips" => handlers::get_chain_tips(context, params),
+        "getreorgs" => handlers::get_reorgs(context, params),
+        "reconsiderblock" => handlers::reconsider_block(context, params),
+        "getblocktemplate" => handlers::get_block_template(context, params),
+        "submitblock" => handlers::submit_block(context, params),
+        "invalidateblock" => handlers::invalidate_block(context, params),
+        "preciousblock" => handlers::precious_block(context, params),
+        "walletpassphrase" => handlers::wallet_passphrase(context, params),
+        "walletlock" => handlers::wallet_lock(context, params),
+        "walletpassphrasechange" => handlers::wallet_passphrase_change(context, params),
+        "importprivkey" => handlers::import_priv_key(context, params),
+        "importdescriptors" => handlers::import_descriptors(context, params),
+        "listdescriptors" => handlers::list_descriptors(context, params),
+        "sweepprivkey" => handlers::sweep_priv_key(context, params),
+        "lockunspent" => handlers::lock_unspent(context, params),
+        "listlockunspent" => handlers::list_lock_unspent(context, params),
+        "listmempool" => handlers::list_mempool(context, params),
+        "getmempoolinfo" => handlers::get_mempool_info(context, params),
+        "testmempoolaccept" => handlers::test_mempool_accept(context, params),
+        "getpeerinfo" => handlers::get_peer_info(context, params),
+        "getnettotals" => handlers::get_net_totals(context, params),
+        "getrejections" => handlers::get_rejections(context, params),
+        "getnodeinfo" => handlers::get_node_info(context, params),
+        "sendalert" => handlers::send_alert(context, params),
+        "getrawtransaction" => handlers::get_raw_transaction(context, params),
+        "decoderawtransaction" => handlers::decode_raw_transaction(context, params),
+        "decodescript" => handlers::decode_script(context, params),
+        "createrawtransaction" => handlers::create_raw_transaction(context, params),
+        "fundrawtransaction" => handlers::fund_raw_transaction(context, params),
+        "listunspent" => handlers::list_unspent(context, params),
+        "getbalances" => handlers::get_balances(context, params),
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+/// RPC errors
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Method {0} requires an authenticated caller")]
+    Unauthorized(String),
+}
+
+impl RpcError {
+    /// JSON-RPC error code
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::InvalidRequest(_) => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::Internal(_) => -32603,
+            RpcError::NotFound(_) => -5,
+            RpcError::DatabaseError(_) => -20,
+            RpcError::PayloadTooLarge(_) => -32010,
+            RpcError::Unauthorized(_) => -32011,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unknown_method() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let context = RpcContext::new(db, ChainParams::regtest());
+
+        let result = dispatch(&context, "nosuchmethod", &Value::Null);
+        let response = RpcResponse::from_result(Value::from(1), result);
+
+        assert!(response.result.is_none());
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+
+    #[test]
+    fn test_readiness() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let context = RpcContext::new(Arc::clone(&db), ChainParams::regtest());
+
+        // Without a chain the node is not ready
+        assert_eq!(readiness(&context).0, StatusCode::SERVICE_UNAVAILABLE);
+
+        let coinbase = sedly_core::Transaction::coinbase(b"miner", 0, 50);
+        db.store_block(&sedly_core::Block::new([0; 32], vec![coinbase], 0x1d00ffff, 0)).unwrap();
+        let (status, body) = readiness(&context);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["blocks"], 0);
+        assert_eq!(body["initialblockdownload"], false);
+
+        // Chain idle for days: in proof of work the node is behind, in
+        // consensus mode (no empty blocks) it is just a chain without traffic
+        let quiet_dir = TempDir::new().unwrap();
+        let quiet_db = Arc::new(BlockchainDB::open(quiet_dir.path()).unwrap());
+        let coinbase = sedly_core::Transaction::coinbase(b"miner", 0, 50);
+        let mut genesis = sedly_core::Block::new([0; 32], vec![coinbase], 0x1d00ffff, 0);
+        genesis.header.timestamp -= 3 * 24 * 60 * 60;
+        quiet_db.store_block(&genesis).unwrap();
+        let proof_of_work = RpcContext::new(Arc::clone(&quiet_db), ChainParams::regtest());
+        assert_eq!(readiness(&proof_of_work).0, StatusCode::SERVICE_UNAVAILABLE);
+        let consensus = RpcContext::new(quiet_db, ChainParams::regtest()).without_clock_sync();
+        assert_eq!(readiness(&consensus).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_request_parsing() {
+        let request: RpcRequest = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":7,"method":"getdifficultyhistory","params":[0,10]}"#
+        ).unwrap();
+
+        assert_eq!(request.method, "getdifficultyhistory");
+        assert_eq!(request.id, Value::from(7));
+        assert!(request.params.is_array());
+    }
+
+    #[test]
+    fn test_batch_requests() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let context = RpcContext::new(db, ChainParams::regtest());
+
+        let batch = serde_json::json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "listlockunspent"},
+            {"jsonrpc": "2.0", "id": 2, "method": "nosuchmethod"},
+            {"jsonrpc": "2.0", "id": 3},
+        ]);
+        let responses = handle_body(&context, batch, 3, true).unwrap();
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"], serde_json::json!([]));
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[2]["error"]["code"], -32600);
+
+        // Empty batches and batches over the limit are rejected as a whole
+        let oversized = Value::Array(vec![serde_json::json!({"id": 1, "method": "listlockunspent"}); 4]);
+        assert_eq!(handle_body(&context, oversized, 3, true).unwrap()["error"]["code"], -32600);
+        assert_eq!(handle_body(&context, serde_json::json!([]), 3, true).unwrap()["error"]["code"], -32600);
+
+        let single = handle_body(&context, serde_json::json!({"id": 9, "method": "listlockunspent"}), 3, true).unwrap();
+        assert_eq!(single["id"], 9);
+
+        // Notifications (without id) get no response, not even in a batch
+        let notification = serde_json::json!({"jsonrpc": "2.0", "method": "listlockunspent"});
+        assert!(handle_body(&context, notification.clone(), 3, true).is_none());
+        let notifications = serde_json::json!([notification.clone(), notification.clone()]);
+        assert!(handle_body(&context, notifications, 3, true).is_none());
+        let mixed = serde_json::json!([notification, {"id": 4, "method": "listlockunspent"}]);
+        let mixed = handle_body(&context, mixed, 3, true);
+        assert_eq!(mixed.unwrap().as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wallet_methods_need_authentication() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let context = RpcContext::new(db, ChainParams::regtest());
+        let call = |method: &str, params: Value, authenticated: bool| {
+            let request = serde_json::json!({"id": 1, "method": method, "params": params});
+            handle_body(&context, request, 3, authenticated).unwrap()
+        };
+
+        assert_eq!(call("listlockunspent", Value::Null, true)["result"], serde_json::json!([]));
+        assert_eq!(call("listlockunspent", Value::Null, false)["error"]["code"], -32011);
+        assert_eq!(call("walletlock", Value::Null, false)["error"]["code"], -32011);
+        assert!(call("getnetworkparams", Value::Null, false)["result"].is_object());
+
+        // A key sweep is served without credentials only for a WIF key, not a label
+        let error = &call("sweepprivkey", serde_json::json!(["label", "00"]), false)["error"];
+        assert_eq!(error["code"], -32602);
+        assert!(error["message"].as_str().unwrap().contains("Invalid private key"));
+    }
+}
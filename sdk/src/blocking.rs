@@ -0,0 +1,232 @@
+//! Blocking variants of the clients, for applications without an async runtime
+//!
+//! Each client owns a single-threaded Tokio runtime and drives the async
+//! client on it. Do not use them from inside an async context: the calls
+//! block the current thread and Tokio refuses to nest runtimes.
+
+use crate::broadcast::Broadcaster;
+use crate::client::{RpcClient, SdkError};
+use sedly_core::{Block, BlockHash, OutPoint, Transaction};
+use sedly_rpc::handlers::{
+    BlockStatsInfo, BlockTemplateInfo, BlockchainInfo, ChainTipInfo, DifficultyHistory, MempoolTx, NetTotalsInfo,
+    NetworkParamsInfo, Page, PeerInfo, ReorgInfo, ScanTxOutSetResult, SupplyInfo, TreasuryInfo, TxOutSetInfo,
+    UtxoSetHashInfo,
+};
+use sedly_rpc::RpcAuth;
+use sedly_wallet::Rebroadcaster;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+
+fn runtime() -> Result<Runtime, SdkError> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| SdkError::Runtime(e.to_string()))
+}
+
+/// Blocking client for a sedly-node JSON-RPC endpoint
+#[derive(Debug)]
+pub struct BlockingRpcClient {
+    inner: RpcClient,
+    runtime: Runtime,
+}
+
+impl BlockingRpcClient {
+    /// Create a client for the endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Result<Self, SdkError> {
+        Ok(Self { inner: RpcClient::new(url), runtime: runtime()? })
+    }
+
+    /// Authenticate the requests, as needed by the wallet methods
+    pub fn with_auth(mut self, auth: RpcAuth) -> Self {
+        self.inner = self.inner.with_auth(auth);
+        self
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        self.runtime.block_on(future)
+    }
+
+    /// See [`RpcClient::call`]
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, SdkError> {
+        self.block_on(self.inner.call(method, params))
+    }
+
+    /// See [`RpcClient::batch`]
+    pub fn batch(&self, calls: &[(&str, Value)]) -> Result<Vec<Result<Value, SdkError>>, SdkError> {
+        self.block_on(self.inner.batch(calls))
+    }
+
+    /// See [`RpcClient::get_difficulty_history`]
+    pub fn get_difficulty_history(
+        &self,
+        from_height: Option<u64>,
+        to_height: Option<u64>,
+    ) -> Result<DifficultyHistory, SdkError> {
+        self.block_on(self.inner.get_difficulty_history(from_height, to_height))
+    }
+
+    /// See [`RpcClient::scan_tx_out_set`]
+    pub fn scan_tx_out_set(&self, scan_objects: &[&str]) -> Result<ScanTxOutSetResult, SdkError> {
+        self.block_on(self.inner.scan_tx_out_set(scan_objects))
+    }
+
+    /// See [`RpcClient::get_treasury_info`]
+    pub fn get_treasury_info(&self) -> Result<TreasuryInfo, SdkError> {
+        self.block_on(self.inner.get_treasury_info())
+    }
+
+    /// See [`RpcClient::get_network_params`]
+    pub fn get_network_params(&self) -> Result<NetworkParamsInfo, SdkError> {
+        self.block_on(self.inner.get_network_params())
+    }
+
+    /// See [`RpcClient::get_supply_info`]
+    pub fn get_supply_info(&self, height: Option<u64>) -> Result<SupplyInfo, SdkError> {
+        self.block_on(self.inner.get_supply_info(height))
+    }
+
+    /// See [`RpcClient::get_reorgs`]
+    pub fn get_reorgs(&self, limit: Option<usize>) -> Result<Vec<ReorgInfo>, SdkError> {
+        self.block_on(self.inner.get_reorgs(limit))
+    }
+
+    /// See [`RpcClient::get_block_stats`]
+    pub fn get_block_stats(&self, height: u64) -> Result<BlockStatsInfo, SdkError> {
+        self.block_on(self.inner.get_block_stats(height))
+    }
+
+    /// See [`RpcClient::get_tx_out_set_info`]
+    pub fn get_tx_out_set_info(&self) -> Result<TxOutSetInfo, SdkError> {
+        self.block_on(self.inner.get_tx_out_set_info())
+    }
+
+    /// See [`RpcClient::get_utxo_set_hash`]
+    pub fn get_utxo_set_hash(&self, height: Option<u64>) -> Result<UtxoSetHashInfo, SdkError> {
+        self.block_on(self.inner.get_utxo_set_hash(height))
+    }
+
+    /// See [`RpcClient::get_blockchain_info`]
+    pub fn get_blockchain_info(&self) -> Result<BlockchainInfo, SdkError> {
+        self.block_on(self.inner.get_blockchain_info())
+    }
+
+    /// See [`RpcClient::get_chain_tips`]
+    pub fn get_chain_tips(&self) -> Result<Vec<ChainTipInfo>, SdkError> {
+        self.block_on(self.inner.get_chain_tips())
+    }
+
+    /// See [`RpcClient::get_block_template`]
+    pub fn get_block_template(&self, longpollid: Option<&str>) -> Result<BlockTemplateInfo, SdkError> {
+        self.block_on(self.inner.get_block_template(longpollid))
+    }
+
+    /// See [`RpcClient::submit_block`]
+    pub fn submit_block(&self, block: &Block) -> Result<Option<String>, SdkError> {
+        self.block_on(self.inner.submit_block(block))
+    }
+
+    /// See [`RpcClient::invalidate_block`]
+    pub fn invalidate_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.block_on(self.inner.invalidate_block(hash))
+    }
+
+    /// See [`RpcClient::reconsider_block`]
+    pub fn reconsider_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.block_on(self.inner.reconsider_block(hash))
+    }
+
+    /// See [`RpcClient::precious_block`]
+    pub fn precious_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.block_on(self.inner.precious_block(hash))
+    }
+
+    /// See [`RpcClient::wallet_passphrase`]
+    pub fn wallet_passphrase(&self, passphrase: &str, timeout: u64) -> Result<(), SdkError> {
+        self.block_on(self.inner.wallet_passphrase(passphrase, timeout))
+    }
+
+    /// See [`RpcClient::wallet_lock`]
+    pub fn wallet_lock(&self) -> Result<(), SdkError> {
+        self.block_on(self.inner.wallet_lock())
+    }
+
+    /// See [`RpcClient::wallet_passphrase_change`]
+    pub fn wallet_passphrase_change(&self, old: &str, new: &str) -> Result<(), SdkError> {
+        self.block_on(self.inner.wallet_passphrase_change(old, new))
+    }
+
+    /// See [`RpcClient::lock_unspent`]
+    pub fn lock_unspent(&self, unlock: bool, outpoints: &[OutPoint]) -> Result<bool, SdkError> {
+        self.block_on(self.inner.lock_unspent(unlock, outpoints))
+    }
+
+    /// See [`RpcClient::list_lock_unspent`]
+    pub fn list_lock_unspent(&self) -> Result<Vec<OutPoint>, SdkError> {
+        self.block_on(self.inner.list_lock_unspent())
+    }
+
+    /// See [`RpcClient::list_mempool`]
+    pub fn list_mempool(&self, cursor: Option<&str>, limit: Option<usize>) -> Result<Page<MempoolTx>, SdkError> {
+        self.block_on(self.inner.list_mempool(cursor, limit))
+    }
+
+    /// See [`RpcClient::get_peer_info`]
+    pub fn get_peer_info(&self) -> Result<Vec<PeerInfo>, SdkError> {
+        self.block_on(self.inner.get_peer_info())
+    }
+
+    /// See [`RpcClient::get_net_totals`]
+    pub fn get_net_totals(&self) -> Result<NetTotalsInfo, SdkError> {
+        self.block_on(self.inner.get_net_totals())
+    }
+}
+
+/// Blocking client submitting transactions to a Tendermint RPC endpoint
+#[derive(Debug)]
+pub struct BlockingBroadcaster {
+    inner: Broadcaster,
+    runtime: Runtime,
+}
+
+impl BlockingBroadcaster {
+    /// Create a broadcaster for the Tendermint RPC endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Result<Self, SdkError> {
+        Ok(Self { inner: Broadcaster::new(url), runtime: runtime()? })
+    }
+
+    /// See [`Broadcaster::broadcast`]
+    pub fn broadcast(&self, tx: &Transaction) -> Result<[u8; 32], SdkError> {
+        self.runtime.block_on(self.inner.broadcast(tx))
+    }
+
+    /// See [`Broadcaster::rebroadcast`]
+    pub fn rebroadcast(
+        &self,
+        client: &BlockingRpcClient,
+        rebroadcaster: &Mutex<Rebroadcaster>,
+        now: u64,
+    ) -> Result<usize, SdkError> {
+        self.runtime.block_on(self.inner.rebroadcast(&client.inner, rebroadcaster, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_client() {
+        // The server runs on a separate runtime: the client blocks the test thread
+        let server = tokio::runtime::Runtime::new().unwrap();
+        let (url, _temp) = server.block_on(crate::client::tests::serve_rpc(2));
+
+        let client = BlockingRpcClient::new(url).unwrap();
+        assert_eq!(client.get_chain_tips().unwrap()[0].height, 1);
+        assert!(client.list_lock_unspent().unwrap().is_empty());
+        assert!(matches!(client.call::<Value>("nosuchmethod", Value::Null), Err(SdkError::Rpc { code: -32601, .. })));
+    }
+}
@@ -0,0 +1,197 @@
+//! Transaction broadcast through the consensus node
+//!
+//! Transactions enter the mempool through Tendermint's `CheckTx`, so they are
+//! submitted to the Tendermint RPC endpoint (`broadcast_tx_sync`) rather than
+//! to the node JSON-RPC server. The ABCI application decodes the bytes as
+//! a bincode-serialized [`Transaction`].
+//!
+//! [`Broadcaster::rebroadcast`] resubmits the wallet transactions still
+//! waiting for a confirmation, as scheduled by a [`Rebroadcaster`].
+
+use crate::client::{RpcClient, SdkError};
+use sedly_core::Transaction;
+use sedly_wallet::{Rebroadcaster, TxStatus};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Result of `broadcast_tx_sync`
+#[derive(Debug, Clone, Deserialize)]
+struct BroadcastResult {
+    /// `CheckTx` result code (0 = accepted)
+    code: u32,
+    /// Rejection reason
+    #[serde(default)]
+    log: String,
+}
+
+/// Tendermint JSON-RPC response envelope
+#[derive(Debug, Deserialize)]
+struct TendermintResponse {
+    result: Option<BroadcastResult>,
+    error: Option<TendermintError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TendermintError {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: String,
+}
+
+/// Client submitting transactions to a Tendermint RPC endpoint
+#[derive(Debug, Clone)]
+pub struct Broadcaster {
+    /// Endpoint URL, e.g. `http://127.0.0.1:26657`
+    url: String,
+    /// HTTP client
+    http: reqwest::Client,
+}
+
+impl Broadcaster {
+    /// Create a broadcaster for the Tendermint RPC endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Submit a signed transaction and wait for its mempool check
+    ///
+    /// Returns the transaction hash once accepted into the mempool, or
+    /// [`SdkError::Rejected`] with the `CheckTx` code and reason.
+    pub async fn broadcast(&self, tx: &Transaction) -> Result<[u8; 32], SdkError> {
+        let bytes = bincode::serialize(tx)?;
+        let response: TendermintResponse = self.http
+            .get(format!("{}/broadcast_tx_sync", self.url))
+            .query(&[("tx", format!("0x{}", hex::encode(bytes)))])
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(SdkError::Rpc { code: error.code, message: format!("{} {}", error.message, error.data) });
+        }
+        let result = response.result
+            .ok_or_else(|| SdkError::Decode("Missing broadcast result".to_string()))?;
+        if result.code != 0 {
+            return Err(SdkError::Rejected { code: result.code, log: result.log });
+        }
+        Ok(tx.hash())
+    }
+
+    /// Resubmit the wallet transactions due for a rebroadcast at time `now`
+    ///
+    /// The node mempool is listed through `client`: transactions no longer
+    /// in it stop being rebroadcast, so the confirmed ones should be marked
+    /// from the wallet history first ([`Rebroadcaster::confirm_found`]). A
+    /// failed submission is logged and retried at the next slot. Returns the
+    /// number of transactions resubmitted.
+    pub async fn rebroadcast(
+        &self,
+        client: &RpcClient,
+        rebroadcaster: &Mutex<Rebroadcaster>,
+        now: u64,
+    ) -> Result<usize, SdkError> {
+        if rebroadcaster.lock().unwrap().is_empty() {
+            return Ok(0);
+        }
+        let mut in_mempool = HashSet::new();
+        let mut cursor = None;
+        loop {
+            let page = client.list_mempool(cursor.as_deref(), None).await?;
+            in_mempool.extend(page.items.into_iter().map(|tx| tx.txid));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        let due = rebroadcaster.lock().unwrap().due(now, |txid| {
+            if in_mempool.contains(txid) {
+                TxStatus::InMempool
+            } else {
+                TxStatus::Missing
+            }
+        });
+        let mut resubmitted = 0;
+        for tx in &due {
+            match self.broadcast(tx).await {
+                Ok(_) => resubmitted += 1,
+                Err(e) => log::warn!("Failed to rebroadcast transaction {}: {}", tx.txid(), e),
+            }
+        }
+        Ok(resubmitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use sedly_core::{OutPoint, TxInput, TxOutput};
+    use sedly_wallet::RebroadcastConfig;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    /// Endpoint that accepts transactions with outputs and rejects the others
+    async fn broadcast_tx_sync(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+        let bytes = hex::decode(query["tx"].trim_start_matches("0x")).unwrap();
+        let tx: Transaction = bincode::deserialize(&bytes).unwrap();
+        let (code, log) = if tx.outputs.is_empty() { (1, "no outputs") } else { (0, "") };
+        Json(json!({"jsonrpc": "2.0", "id": -1, "result": {"code": code, "data": "", "log": log, "hash": ""}}))
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let broadcaster = Broadcaster::new(format!("http://{}/", listener.local_addr().unwrap()));
+        let router = Router::new().route("/broadcast_tx_sync", get(broadcast_tx_sync));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let input = TxInput::new(OutPoint::new([1; 32], 0), vec![]);
+        let tx = Transaction::new(vec![input.clone()], vec![TxOutput::to_address(40, b"alice")], 0);
+        assert_eq!(broadcaster.broadcast(&tx).await.unwrap(), tx.hash());
+
+        let empty = Transaction::new(vec![input], vec![], 0);
+        let rejected = broadcaster.broadcast(&empty).await;
+        assert!(matches!(rejected, Err(SdkError::Rejected { code: 1, ref log }) if log == "no outputs"));
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast() {
+        let input = |byte| TxInput::new(OutPoint::new([byte; 32], 0), vec![]);
+        let pending = Transaction::new(vec![input(1)], vec![TxOutput::to_address(40, b"alice")], 0);
+        let evicted = Transaction::new(vec![input(2)], vec![TxOutput::to_address(40, b"alice")], 0);
+
+        // Node with only `pending` in its mempool
+        let mempool_txid = pending.txid().to_string();
+        let list_mempool = move |Json(request): Json<Value>| async move {
+            let items = json!([{"txid": mempool_txid, "size": 100, "fee": 1_000, "time": 0, "height": 1}]);
+            Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {"items": items, "next_cursor": null}}))
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = Router::new()
+            .route("/", post(list_mempool))
+            .route("/broadcast_tx_sync", get(broadcast_tx_sync));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let config = RebroadcastConfig { interval: 10, max_jitter: 0, ..RebroadcastConfig::default() };
+        let rebroadcaster = Mutex::new(Rebroadcaster::new(config));
+        let (broadcaster, client) = (Broadcaster::new(url.clone()), RpcClient::new(url));
+        assert_eq!(broadcaster.rebroadcast(&client, &rebroadcaster, 0).await.unwrap(), 0);
+
+        rebroadcaster.lock().unwrap().track(pending.clone(), 0);
+        rebroadcaster.lock().unwrap().track(evicted, 0);
+        assert_eq!(broadcaster.rebroadcast(&client, &rebroadcaster, 5).await.unwrap(), 0);
+        assert_eq!(broadcaster.rebroadcast(&client, &rebroadcaster, 10).await.unwrap(), 1);
+        let tracked: Vec<Transaction> = rebroadcaster.lock().unwrap().pending().map(|p| p.tx.clone()).collect();
+        assert_eq!(tracked, vec![pending]);
+    }
+}
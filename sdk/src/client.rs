@@ -0,0 +1,375 @@
+//! Typed async client for the node JSON-RPC interface
+//!
+//! Every method of the server dispatch table has a typed wrapper returning
+//! the same result types the handlers serialize (re-exported from
+//! [`sedly_rpc::handlers`]), so a change on the server side breaks the
+//! build instead of a running application. [`RpcClient::call`] and
+//! [`RpcClient::batch`] remain available for anything not wrapped yet.
+
+use crate::coinjoin::CoinjoinError;
+use sedly_core::{BlockHash, OutPoint, Txid};
+use sedly_rpc::handlers::{
+    BalancesInfo, BlockStatsInfo, BlockTemplateInfo, BlockchainInfo, ChainTipInfo, DifficultyHistory,
+    MempoolAcceptResult, MempoolInfo, MempoolTx, NetTotalsInfo, NetworkParamsInfo, OutPointParam, Page, PeerInfo,
+    RawTransactionInfo, ReorgInfo, ScanTxOutSetResult, SupplyInfo, TreasuryInfo, TxOutSetInfo, UnspentInfo,
+    UtxoSetHashInfo,
+};
+use sedly_rpc::{RpcAuth, RpcRequest, RpcResponse};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Client for a sedly-node JSON-RPC endpoint
+#[derive(Debug)]
+pub struct RpcClient {
+    /// Endpoint URL, e.g. `http://127.0.0.1:8545`
+    url: String,
+    /// HTTP client
+    http: reqwest::Client,
+    /// Id of the next request
+    next_id: AtomicU64,
+    /// Credentials sent with every request
+    auth: Option<RpcAuth>,
+}
+
+impl RpcClient {
+    /// Create a client for the endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), http: reqwest::Client::new(), next_id: AtomicU64::new(1), auth: None }
+    }
+
+    /// Authenticate the requests, as needed by the wallet methods
+    pub fn with_auth(mut self, auth: RpcAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Call a method and decode its result
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, SdkError> {
+        let request = self.request(method, params);
+        let response: RpcResponse = self.post(&serde_json::to_value(&request)?).await?;
+        decode_response(response)
+    }
+
+    /// Call several methods in a single batch request
+    ///
+    /// Results are returned in the order of `calls`, each one decoded on its
+    /// own: a failed call does not fail the others.
+    pub async fn batch(&self, calls: &[(&str, Value)]) -> Result<Vec<Result<Value, SdkError>>, SdkError> {
+        let requests: Vec<RpcRequest> = calls.iter()
+            .map(|(method, params)| self.request(method, params.clone()))
+            .collect();
+        let body = self.post::<Value>(&serde_json::to_value(&requests)?).await?;
+
+        // A rejected batch is answered with a single error object
+        let responses: Vec<RpcResponse> = match body {
+            Value::Array(_) => serde_json::from_value(body)?,
+            body => {
+                let response: RpcResponse = serde_json::from_value(body)?;
+                return Err(match response.error {
+                    Some(error) => SdkError::Rpc { code: error.code, message: error.message },
+                    None => SdkError::Decode("Batch answered with a single response".to_string()),
+                });
+            }
+        };
+        Ok(requests.iter()
+            .map(|request| {
+                responses.iter()
+                    .find(|response| response.id == request.id)
+                    .cloned()
+                    .ok_or_else(|| SdkError::Decode(format!("Missing response to request {}", request.id)))
+                    .and_then(decode_response)
+            })
+            .collect())
+    }
+
+    /// `getdifficultyhistory`, None bounds select the last epochs up to the tip
+    pub async fn get_difficulty_history(
+        &self,
+        from_height: Option<u64>,
+        to_height: Option<u64>,
+    ) -> Result<DifficultyHistory, SdkError> {
+        self.call("getdifficultyhistory", json!({"from_height": from_height, "to_height": to_height})).await
+    }
+
+    /// `scantxoutset start` over descriptors or script hex
+    pub async fn scan_tx_out_set(&self, scan_objects: &[&str]) -> Result<ScanTxOutSetResult, SdkError> {
+        self.call("scantxoutset", json!({"action": "start", "scanobjects": scan_objects})).await
+    }
+
+    /// `gettreasuryinfo`
+    pub async fn get_treasury_info(&self) -> Result<TreasuryInfo, SdkError> {
+        self.call("gettreasuryinfo", Value::Null).await
+    }
+
+    /// `getnetworkparams`
+    pub async fn get_network_params(&self) -> Result<NetworkParamsInfo, SdkError> {
+        self.call("getnetworkparams", Value::Null).await
+    }
+
+    /// `getsupplyinfo [height]`
+    pub async fn get_supply_info(&self, height: Option<u64>) -> Result<SupplyInfo, SdkError> {
+        self.call("getsupplyinfo", json!({"height": height})).await
+    }
+
+    /// `getreorgs ( limit )`
+    pub async fn get_reorgs(&self, limit: Option<usize>) -> Result<Vec<ReorgInfo>, SdkError> {
+        self.call("getreorgs", json!({"limit": limit})).await
+    }
+
+    /// `getblockstats height`
+    pub async fn get_block_stats(&self, height: u64) -> Result<BlockStatsInfo, SdkError> {
+        self.call("getblockstats", json!({"hash_or_height": height})).await
+    }
+
+    /// `gettxoutsetinfo`
+    pub async fn get_tx_out_set_info(&self) -> Result<TxOutSetInfo, SdkError> {
+        self.call("gettxoutsetinfo", Value::Null).await
+    }
+
+    /// `getutxosethash ( height )`, the tip if `height` is None
+    pub async fn get_utxo_set_hash(&self, height: Option<u64>) -> Result<UtxoSetHashInfo, SdkError> {
+        self.call("getutxosethash", json!({"hash_or_height": height})).await
+    }
+
+    /// `getblockchaininfo`
+    pub async fn get_blockchain_info(&self) -> Result<BlockchainInfo, SdkError> {
+        self.call("getblockchaininfo", Value::Null).await
+    }
+
+    /// `getchaintips`
+    pub async fn get_chain_tips(&self) -> Result<Vec<ChainTipInfo>, SdkError> {
+        self.call("getchaintips", Value::Null).await
+    }
+
+    /// `getblocktemplate`; with the `longpollid` of the previous template,
+    /// waits until that template is stale
+    pub async fn get_block_template(&self, longpollid: Option<&str>) -> Result<BlockTemplateInfo, SdkError> {
+        self.call("getblocktemplate", json!({"longpollid": longpollid})).await
+    }
+
+    /// `submitblock`, None when the block is accepted and otherwise the
+    /// server's short result (e.g. "duplicate" or the rejection reason)
+    pub async fn submit_block(&self, block: &sedly_core::Block) -> Result<Option<String>, SdkError> {
+        let hexdata = hex::encode(bincode::serialize(block)?);
+        self.call("submitblock", json!({"hexdata": hexdata})).await
+    }
+
+    /// `testmempoolaccept`, the mempool verdict of each transaction without broadcasting it
+    pub async fn test_mempool_accept(
+        &self,
+        txs: &[sedly_core::Transaction],
+    ) -> Result<Vec<MempoolAcceptResult>, SdkError> {
+        let rawtxs = txs.iter()
+            .map(|tx| bincode::serialize(tx).map(hex::encode))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call("testmempoolaccept", json!({"rawtxs": rawtxs})).await
+    }
+
+    /// `invalidateblock`
+    pub async fn invalidate_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.call_null("invalidateblock", json!({"blockhash": hash})).await
+    }
+
+    /// `reconsiderblock`
+    pub async fn reconsider_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.call_null("reconsiderblock", json!({"blockhash": hash})).await
+    }
+
+    /// `preciousblock`
+    pub async fn precious_block(&self, hash: &BlockHash) -> Result<(), SdkError> {
+        self.call_null("preciousblock", json!({"blockhash": hash})).await
+    }
+
+    /// `walletpassphrase`, unlocking the node keystore for `timeout` seconds
+    pub async fn wallet_passphrase(&self, passphrase: &str, timeout: u64) -> Result<(), SdkError> {
+        self.call_null("walletpassphrase", json!({"passphrase": passphrase, "timeout": timeout})).await
+    }
+
+    /// `walletlock`
+    pub async fn wallet_lock(&self) -> Result<(), SdkError> {
+        self.call_null("walletlock", Value::Null).await
+    }
+
+    /// `walletpassphrasechange`
+    pub async fn wallet_passphrase_change(&self, old: &str, new: &str) -> Result<(), SdkError> {
+        self.call_null("walletpassphrasechange", json!({"oldpassphrase": old, "newpassphrase": new})).await
+    }
+
+    /// `lockunspent`; with `unlock` and no outpoints every lock is released
+    pub async fn lock_unspent(&self, unlock: bool, outpoints: &[OutPoint]) -> Result<bool, SdkError> {
+        let transactions: Vec<OutPointParam> = outpoints.iter()
+            .map(|outpoint| OutPointParam { txid: outpoint.txid.into(), vout: outpoint.vout })
+            .collect();
+        self.call("lockunspent", json!({"unlock": unlock, "transactions": transactions})).await
+    }
+
+    /// `listlockunspent`
+    pub async fn list_lock_unspent(&self) -> Result<Vec<OutPoint>, SdkError> {
+        let locked: Vec<OutPointParam> = self.call("listlockunspent", Value::Null).await?;
+        Ok(locked.into_iter().map(|outpoint| OutPoint::new(outpoint.txid.into(), outpoint.vout)).collect())
+    }
+
+    /// `listunspent` over descriptors or script hex, for one asset (hex) or all of them
+    pub async fn list_unspent(
+        &self,
+        descriptors: &[&str],
+        asset_id: Option<&str>,
+    ) -> Result<Vec<UnspentInfo>, SdkError> {
+        self.call("listunspent", json!({"descriptors": descriptors, "asset_id": asset_id})).await
+    }
+
+    /// `getbalances` over descriptors or script hex, per asset
+    pub async fn get_balances(&self, descriptors: &[&str]) -> Result<BalancesInfo, SdkError> {
+        self.call("getbalances", json!({"descriptors": descriptors})).await
+    }
+
+    /// `listmempool`, one page of the mempool in txid order
+    ///
+    /// Pass the `next_cursor` of a page as `cursor` to get the following one.
+    pub async fn list_mempool(&self, cursor: Option<&str>, limit: Option<usize>) -> Result<Page<MempoolTx>, SdkError> {
+        self.call("listmempool", json!({"cursor": cursor, "limit": limit})).await
+    }
+
+    /// `getmempoolinfo`, size of the mempool and of its disk tier and spent outpoint filter counters
+    pub async fn get_mempool_info(&self) -> Result<MempoolInfo, SdkError> {
+        self.call("getmempoolinfo", Value::Null).await
+    }
+
+    /// `getrawtransaction` with verbose, a confirmed or pending transaction with its block context
+    pub async fn get_raw_transaction(&self, txid: &Txid) -> Result<RawTransactionInfo, SdkError> {
+        self.call("getrawtransaction", json!({"txid": txid, "verbose": true})).await
+    }
+
+    /// `getpeerinfo`, traffic and latency of the connected peers
+    pub async fn get_peer_info(&self) -> Result<Vec<PeerInfo>, SdkError> {
+        self.call("getpeerinfo", Value::Null).await
+    }
+
+    /// `getnettotals`, traffic of the node since startup
+    pub async fn get_net_totals(&self) -> Result<NetTotalsInfo, SdkError> {
+        self.call("getnettotals", Value::Null).await
+    }
+
+    async fn call_null(&self, method: &str, params: Value) -> Result<(), SdkError> {
+        self.call::<Value>(method, params).await.map(|_| ())
+    }
+
+    fn request(&self, method: &str, params: Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: Some("2.0".to_string()),
+            id: Value::from(self.next_id.fetch_add(1, Ordering::Relaxed)),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    async fn post<T: DeserializeOwned>(&self, body: &Value) -> Result<T, SdkError> {
+        let mut request = self.http.post(&self.url).json(body);
+        if let Some(auth) = &self.auth {
+            request = request.header(reqwest::header::AUTHORIZATION, auth.header_value());
+        }
+        let response = request.send().await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+}
+
+/// Result of a response, or its error
+fn decode_response<T: DeserializeOwned>(response: RpcResponse) -> Result<T, SdkError> {
+    if let Some(error) = response.error {
+        return Err(SdkError::Rpc { code: error.code, message: error.message });
+    }
+    Ok(serde_json::from_value(response.result.unwrap_or(Value::Null))?)
+}
+
+/// SDK errors
+#[derive(Debug, thiserror::Error)]
+pub enum SdkError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+
+    #[error("Decode error: {0}")]
+    Decode(String),
+
+    #[error("Transaction rejected with code {code}: {log}")]
+    Rejected { code: u32, log: String },
+
+    #[error("Signing error: {0}")]
+    Signing(String),
+
+    #[error("Coinjoin error: {0}")]
+    Coinjoin(#[from] CoinjoinError),
+
+    #[error("Runtime error: {0}")]
+    Runtime(String),
+}
+
+impl From<serde_json::Error> for SdkError {
+    fn from(error: serde_json::Error) -> Self {
+        SdkError::Decode(error.to_string())
+    }
+}
+
+impl From<bincode::Error> for SdkError {
+    fn from(error: bincode::Error) -> Self {
+        SdkError::Decode(error.to_string())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sedly_core::{Block, BlockchainDB, ChainParams, TipStatus, Transaction};
+    use sedly_rpc::{RpcConfig, RpcContext, RpcServer};
+
+    /// Credentials of the servers started by `serve_rpc`
+    pub(crate) fn test_auth() -> RpcAuth {
+        RpcAuth::new("sdk", "secret")
+    }
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Serve a node RPC with `blocks` blocks, returning its URL
+    pub(crate) async fn serve_rpc(blocks: u64) -> (String, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(BlockchainDB::open(temp_dir.path()).unwrap());
+        let mut previous_hash = [0; 32];
+        for height in 0..blocks {
+            let block = Block::new(previous_hash, vec![Transaction::coinbase(b"miner", height, 50)], 0x1d00ffff, height);
+            db.store_block(&block).unwrap();
+            previous_hash = block.hash();
+        }
+        let config = RpcConfig { auth: Some(test_auth()), ..RpcConfig::default() };
+        let server = RpcServer::new(config, RpcContext::new(db, ChainParams::regtest()));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, server.router()).await });
+        (url, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_client_against_server() {
+        let (url, _temp) = serve_rpc(3).await;
+        let client = RpcClient::new(url).with_auth(test_auth());
+
+        let tips = client.get_chain_tips().await.unwrap();
+        assert_eq!((tips[0].height, tips[0].status), (2, TipStatus::Active));
+        assert_eq!(client.get_blockchain_info().await.unwrap().bestblockhash, tips[0].hash);
+
+        let outpoint = OutPoint::new(Transaction::coinbase(b"miner", 0, 50).hash(), 0);
+        assert!(client.lock_unspent(false, std::slice::from_ref(&outpoint)).await.unwrap());
+        assert_eq!(client.list_lock_unspent().await.unwrap(), vec![outpoint]);
+
+        // Without a mempool the node answers with an RPC error
+        assert!(matches!(client.list_mempool(None, None).await, Err(SdkError::Rpc { code: -5, .. })));
+
+        let results = client.batch(&[("listlockunspent", Value::Null), ("nosuchmethod", Value::Null)]).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().as_array().unwrap().len(), 1);
+        assert!(matches!(results[1], Err(SdkError::Rpc { code: -32601, .. })));
+    }
+}
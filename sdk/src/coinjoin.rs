@@ -0,0 +1,626 @@
+//! Collaborative (coinjoin) transaction construction
+//!
+//! Several participants pay into a single transaction whose mixed outputs
+//! all have the same value (the round denomination), so an observer cannot
+//! tell which input paid which of them. A [`CoinjoinRound`] coordinates the
+//! construction in phases:
+//!
+//! 1. input registration: each participant registers its inputs and a
+//!    change script and receives an anonymous [`OutputToken`]. Inputs are
+//!    checked against the UTXO set, not taken on the participant's word;
+//! 2. output registration: tokens are redeemed for the mixed output
+//!    scripts, ideally over a separate connection (e.g. a new Tor circuit).
+//!    The round keeps only the hashes of unredeemed tokens and never ties an
+//!    output to a participant. This is not a blind signature scheme: a
+//!    coordinator logging the tokens it hands out can still link them;
+//! 3. signing: inputs and outputs are put in a canonical order, every
+//!    participant checks the transaction with
+//!    [`CoinjoinParticipant::verify`] and signs only its own inputs;
+//! 4. complete: once every input is signed the transaction can be
+//!    broadcast.
+//!
+//! Output registration and signing last [`RoundParams::phase_timeout`] at
+//! most: [`CoinjoinRound::expire`] fails a round stuck on a participant that
+//! stopped responding, dropping it when it can be told.
+//!
+//! The module holds the protocol state only; carrying the messages between
+//! participants and coordinator is up to the application.
+
+use crate::client::SdkError;
+use crate::signing::Signer;
+use ring::rand::{SecureRandom, SystemRandom};
+use sedly_core::{Amount, OutPoint, Transaction, TxInput, TxOutput};
+use sedly_wallet::transactions::DUST_THRESHOLD;
+use sedly_wallet::WalletUtxo;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Asset of the mixed outputs (native SLY)
+const NATIVE_ASSET: [u8; 32] = [0; 32];
+
+/// Participant of a round, as numbered by the coordinator
+pub type ParticipantId = u32;
+
+/// Anonymous token redeemed for one mixed output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputToken(pub [u8; 32]);
+
+impl OutputToken {
+    /// Hash kept by the coordinator until the token is redeemed
+    fn commitment(&self) -> [u8; 32] {
+        Sha256::digest(self.0).into()
+    }
+}
+
+/// Terms of a round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundParams {
+    /// Value of every mixed output
+    pub denomination: Amount,
+    /// Share of the transaction fee paid by each participant
+    pub fee_per_participant: Amount,
+    /// Participants needed to close input registration
+    pub min_participants: usize,
+    /// Participants accepted at most
+    pub max_participants: usize,
+    /// Time given to output registration and to signing
+    pub phase_timeout: Duration,
+}
+
+impl RoundParams {
+    /// Change owed to a participant spending `inputs`, None if it would be
+    /// dust (left to the fee)
+    pub fn change_for(&self, inputs: &[WalletUtxo]) -> Result<Option<Amount>, CoinjoinError> {
+        let total = Amount::checked_sum(inputs.iter().map(|utxo| utxo.output.value)).ok_or(CoinjoinError::Overflow)?;
+        let needed = self.denomination.checked_add(self.fee_per_participant).ok_or(CoinjoinError::Overflow)?;
+        let change = total.checked_sub(needed).ok_or(CoinjoinError::InsufficientFunds { needed, available: total })?;
+        Ok((change >= DUST_THRESHOLD).then_some(change))
+    }
+}
+
+/// Phase of a round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundPhase {
+    InputRegistration,
+    OutputRegistration,
+    Signing,
+    Complete,
+    /// A phase ran out of time; the round has to start over
+    Failed,
+}
+
+/// Inputs and change of a participant
+#[derive(Debug, Clone)]
+struct Registration {
+    inputs: Vec<WalletUtxo>,
+    change: Option<TxOutput>,
+}
+
+/// Coordinator state of a coinjoin round
+#[derive(Debug, Clone)]
+pub struct CoinjoinRound {
+    /// Terms of the round
+    params: RoundParams,
+    /// Current phase
+    phase: RoundPhase,
+    /// When the current phase started
+    phase_started: Instant,
+    /// Registered participants
+    registrations: BTreeMap<ParticipantId, Registration>,
+    /// Commitments of the tokens not redeemed yet
+    tokens: HashSet<[u8; 32]>,
+    /// Mixed outputs registered so far
+    outputs: Vec<TxOutput>,
+    /// Transaction being signed, once assembled
+    tx: Option<Transaction>,
+    /// Owner of each input of `tx`
+    input_owners: Vec<ParticipantId>,
+}
+
+impl CoinjoinRound {
+    /// Open a round in input registration
+    pub fn new(params: RoundParams) -> Self {
+        Self {
+            params,
+            phase: RoundPhase::InputRegistration,
+            phase_started: Instant::now(),
+            registrations: BTreeMap::new(),
+            tokens: HashSet::new(),
+            outputs: Vec::new(),
+            tx: None,
+            input_owners: Vec::new(),
+        }
+    }
+
+    /// Terms of the round
+    pub fn params(&self) -> &RoundParams {
+        &self.params
+    }
+
+    /// Current phase
+    pub fn phase(&self) -> RoundPhase {
+        self.phase
+    }
+
+    /// Number of registered participants
+    pub fn participants(&self) -> usize {
+        self.registrations.len()
+    }
+
+    /// Register the inputs of a participant, with the script receiving its change
+    ///
+    /// `utxo_set` looks an outpoint up in the UTXO set of the node (None if
+    /// it is unknown or spent): every input must be unspent and match it in
+    /// value, asset and script, or the change would be paid out of the other
+    /// participants' funds. Returns the participant id, needed to submit
+    /// signatures, and the token to redeem for the mixed output.
+    pub fn register_inputs(
+        &mut self,
+        inputs: Vec<WalletUtxo>,
+        change_script: Vec<u8>,
+        mut utxo_set: impl FnMut(&OutPoint) -> Option<WalletUtxo>,
+    ) -> Result<(ParticipantId, OutputToken), CoinjoinError> {
+        self.expect_phase(RoundPhase::InputRegistration)?;
+        if self.registrations.len() >= self.params.max_participants {
+            return Err(CoinjoinError::RoundFull);
+        }
+        if inputs.is_empty() {
+            return Err(CoinjoinError::NoInputs);
+        }
+        let mut seen: HashSet<&OutPoint> = self.registrations
+            .values()
+            .flat_map(|registration| &registration.inputs)
+            .map(|utxo| &utxo.outpoint)
+            .collect();
+        for utxo in &inputs {
+            match utxo_set(&utxo.outpoint) {
+                None => return Err(CoinjoinError::UnknownInput(utxo.outpoint.clone())),
+                Some(unspent) if unspent != *utxo => return Err(CoinjoinError::InputMismatch(utxo.outpoint.clone())),
+                Some(_) => {}
+            }
+            if utxo.output.asset_id != NATIVE_ASSET {
+                return Err(CoinjoinError::NotNative(utxo.outpoint.clone()));
+            }
+            if !seen.insert(&utxo.outpoint) {
+                return Err(CoinjoinError::DuplicateInput(utxo.outpoint.clone()));
+            }
+        }
+        let change = self.params
+            .change_for(&inputs)?
+            .map(|value| TxOutput::new(value, NATIVE_ASSET, change_script));
+
+        let mut token = [0u8; 32];
+        SystemRandom::new().fill(&mut token).map_err(|_| CoinjoinError::Randomness)?;
+        let token = OutputToken(token);
+        let id = self.registrations.keys().next_back().map_or(0, |last| last + 1);
+        self.registrations.insert(id, Registration { inputs, change });
+        self.tokens.insert(token.commitment());
+        Ok((id, token))
+    }
+
+    /// Close input registration once enough participants joined
+    pub fn start_output_registration(&mut self) -> Result<(), CoinjoinError> {
+        self.expect_phase(RoundPhase::InputRegistration)?;
+        if self.registrations.len() < self.params.min_participants {
+            return Err(CoinjoinError::NotEnoughParticipants(self.registrations.len()));
+        }
+        self.phase = RoundPhase::OutputRegistration;
+        self.phase_started = Instant::now();
+        Ok(())
+    }
+
+    /// Redeem a token for a mixed output paying `script_pubkey`
+    ///
+    /// The transaction is assembled when the last token is redeemed.
+    pub fn register_output(&mut self, token: &OutputToken, script_pubkey: Vec<u8>) -> Result<(), CoinjoinError> {
+        self.expect_phase(RoundPhase::OutputRegistration)?;
+        let reused = self.outputs.iter()
+            .chain(self.registrations.values().filter_map(|registration| registration.change.as_ref()))
+            .chain(self.registrations.values().flat_map(|registration| &registration.inputs).map(|utxo| &utxo.output))
+            .any(|output| output.script_pubkey == script_pubkey);
+        if reused {
+            return Err(CoinjoinError::ReusedScript);
+        }
+        if !self.tokens.remove(&token.commitment()) {
+            return Err(CoinjoinError::UnknownToken);
+        }
+        self.outputs.push(TxOutput::new(self.params.denomination, NATIVE_ASSET, script_pubkey));
+        if self.tokens.is_empty() {
+            self.assemble();
+        }
+        Ok(())
+    }
+
+    /// Transaction of the round: unsigned while signing, signed once complete
+    pub fn transaction(&self) -> Option<&Transaction> {
+        self.tx.as_ref()
+    }
+
+    /// Add the unlocking scripts of a participant, as `(input index, script_sig)`
+    ///
+    /// Returns true once every input is signed and the round is complete.
+    pub fn add_signatures(
+        &mut self,
+        participant: ParticipantId,
+        signatures: Vec<(usize, Vec<u8>)>,
+    ) -> Result<bool, CoinjoinError> {
+        self.expect_phase(RoundPhase::Signing)?;
+        for (index, script_sig) in &signatures {
+            if self.input_owners.get(*index) != Some(&participant) || script_sig.is_empty() {
+                return Err(CoinjoinError::NotOwnInput(*index));
+            }
+        }
+        let tx = self.tx.as_mut().expect("Transaction assembled before signing");
+        for (index, script_sig) in signatures {
+            tx.inputs[index].script_sig = script_sig;
+        }
+        if tx.inputs.iter().all(|input| !input.script_sig.is_empty()) {
+            self.phase = RoundPhase::Complete;
+        }
+        Ok(self.phase == RoundPhase::Complete)
+    }
+
+    /// Fail the round if the current phase is still open past its deadline at `now`
+    ///
+    /// Returns the participants dropped for not responding. Tokens are
+    /// anonymous, so an output registration running out of time cannot be
+    /// blamed on anyone; a signing one drops the participants with an
+    /// unsigned input, and the others can join the next round. Input
+    /// registration has no deadline: it ends with
+    /// [`start_output_registration`](Self::start_output_registration).
+    pub fn expire(&mut self, now: Instant) -> Vec<ParticipantId> {
+        let running = matches!(self.phase, RoundPhase::OutputRegistration | RoundPhase::Signing);
+        if !running || now < self.phase_started + self.params.phase_timeout {
+            return Vec::new();
+        }
+        let mut dropped = Vec::new();
+        if let (RoundPhase::Signing, Some(tx)) = (self.phase, &self.tx) {
+            for (input, owner) in tx.inputs.iter().zip(&self.input_owners) {
+                if input.script_sig.is_empty() && !dropped.contains(owner) {
+                    dropped.push(*owner);
+                }
+            }
+            for id in &dropped {
+                self.registrations.remove(id);
+            }
+        }
+        self.phase = RoundPhase::Failed;
+        dropped
+    }
+
+    /// Build the transaction with inputs by outpoint and outputs by value and script
+    fn assemble(&mut self) {
+        let mut inputs: Vec<(ParticipantId, &WalletUtxo)> = self.registrations
+            .iter()
+            .flat_map(|(id, registration)| registration.inputs.iter().map(move |utxo| (*id, utxo)))
+            .collect();
+        inputs.sort_by_key(|(_, utxo)| (utxo.outpoint.txid, utxo.outpoint.vout));
+        let mut outputs: Vec<TxOutput> = self.outputs.clone();
+        outputs.extend(self.registrations.values().filter_map(|registration| registration.change.clone()));
+        outputs.sort_by(|a, b| (a.value, &a.script_pubkey).cmp(&(b.value, &b.script_pubkey)));
+
+        self.input_owners = inputs.iter().map(|(id, _)| *id).collect();
+        let tx_inputs = inputs.iter().map(|(_, utxo)| TxInput::new(utxo.outpoint.clone(), Vec::new())).collect();
+        self.tx = Some(Transaction::new(tx_inputs, outputs, 0));
+        self.phase = RoundPhase::Signing;
+        self.phase_started = Instant::now();
+    }
+
+    fn expect_phase(&self, phase: RoundPhase) -> Result<(), CoinjoinError> {
+        if self.phase != phase {
+            return Err(CoinjoinError::WrongPhase(self.phase));
+        }
+        Ok(())
+    }
+}
+
+/// Participant side of a round
+#[derive(Debug, Clone)]
+pub struct CoinjoinParticipant {
+    /// Inputs registered with the coordinator
+    pub inputs: Vec<WalletUtxo>,
+    /// Script receiving the mixed output
+    pub output_script: Vec<u8>,
+    /// Script receiving the change
+    pub change_script: Vec<u8>,
+}
+
+impl CoinjoinParticipant {
+    /// Check that `tx` spends our inputs once each and pays our mixed output and change
+    pub fn verify(&self, tx: &Transaction, params: &RoundParams) -> Result<(), CoinjoinError> {
+        for utxo in &self.inputs {
+            let spends = tx.inputs.iter().filter(|input| input.previous_output == utxo.outpoint).count();
+            if spends != 1 {
+                return Err(CoinjoinError::MissingInput(utxo.outpoint.clone()));
+            }
+        }
+        let mixed = TxOutput::new(params.denomination, NATIVE_ASSET, self.output_script.clone());
+        if !tx.outputs.contains(&mixed) {
+            return Err(CoinjoinError::MissingOutput);
+        }
+        if let Some(change) = params.change_for(&self.inputs)? {
+            if !tx.outputs.contains(&TxOutput::new(change, NATIVE_ASSET, self.change_script.clone())) {
+                return Err(CoinjoinError::MissingOutput);
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify `tx` and sign our inputs only, as `(input index, script_sig)`
+    pub fn sign(
+        &self,
+        tx: &Transaction,
+        params: &RoundParams,
+        signer: &dyn Signer,
+    ) -> Result<Vec<(usize, Vec<u8>)>, SdkError> {
+        self.verify(tx, params)?;
+        let mut signatures = Vec::with_capacity(self.inputs.len());
+        for (index, input) in tx.inputs.iter().enumerate() {
+            let Some(utxo) = self.inputs.iter().find(|utxo| utxo.outpoint == input.previous_output) else {
+                continue;
+            };
+            let script_sig = signer.sign_input(tx, index, &utxo.output)?
+                .ok_or_else(|| SdkError::Signing(format!("No key for input {}", index)))?;
+            signatures.push((index, script_sig));
+        }
+        Ok(signatures)
+    }
+}
+
+/// Errors of a coinjoin round
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CoinjoinError {
+    #[error("Round is in phase {0:?}")]
+    WrongPhase(RoundPhase),
+
+    #[error("Round is full")]
+    RoundFull,
+
+    #[error("Only {0} participants registered")]
+    NotEnoughParticipants(usize),
+
+    #[error("No inputs registered")]
+    NoInputs,
+
+    #[error("Input {}:{} is not native SLY", hex::encode(.0.txid), .0.vout)]
+    NotNative(OutPoint),
+
+    #[error("Input {}:{} is already registered", hex::encode(.0.txid), .0.vout)]
+    DuplicateInput(OutPoint),
+
+    #[error("Input {}:{} is not in the UTXO set", hex::encode(.0.txid), .0.vout)]
+    UnknownInput(OutPoint),
+
+    #[error("Input {}:{} does not match the UTXO set", hex::encode(.0.txid), .0.vout)]
+    InputMismatch(OutPoint),
+
+    #[error("Insufficient funds: need {needed}, available {available}")]
+    InsufficientFunds { needed: Amount, available: Amount },
+
+    #[error("Amount overflow")]
+    Overflow,
+
+    #[error("Unknown or already redeemed output token")]
+    UnknownToken,
+
+    #[error("Output script is already used in the round")]
+    ReusedScript,
+
+    #[error("Input {0} does not belong to the participant")]
+    NotOwnInput(usize),
+
+    #[error("Transaction does not spend input {}:{}", hex::encode(.0.txid), .0.vout)]
+    MissingInput(OutPoint),
+
+    #[error("Transaction does not pay the expected output")]
+    MissingOutput,
+
+    #[error("No system randomness for the output token")]
+    Randomness,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::KeySigner;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use sedly_core::script::hash160;
+    use sedly_core::{verify_script, ScriptTemplate, TransactionChecker, VerifyFlags};
+
+    fn params() -> RoundParams {
+        RoundParams {
+            denomination: Amount::from_sat(100_000),
+            fee_per_participant: Amount::from_sat(1_000),
+            min_participants: 3,
+            max_participants: 5,
+            phase_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Participant with a key and a UTXO of `value` satoshi
+    fn participant(byte: u8, value: u64) -> (CoinjoinParticipant, KeySigner) {
+        let key = SecretKey::from_slice(&[byte; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &key).serialize();
+        let utxo = WalletUtxo {
+            outpoint: OutPoint::new([byte; 32], 0),
+            output: TxOutput::new(value, NATIVE_ASSET, ScriptTemplate::p2pkh(&hash160(&pubkey))),
+            height: 1,
+            is_coinbase: false,
+        };
+        let participant = CoinjoinParticipant {
+            inputs: vec![utxo],
+            output_script: vec![byte, 1],
+            change_script: vec![byte, 2],
+        };
+        (participant, KeySigner::new().with_key(key))
+    }
+
+    /// UTXO set holding the inputs of `participants`
+    fn chain(participants: &[(CoinjoinParticipant, KeySigner)]) -> Vec<WalletUtxo> {
+        participants.iter().flat_map(|(participant, _)| participant.inputs.clone()).collect()
+    }
+
+    /// Lookup in a UTXO set made of `utxos`
+    fn utxo_set(utxos: &[WalletUtxo]) -> impl FnMut(&OutPoint) -> Option<WalletUtxo> + '_ {
+        |outpoint| utxos.iter().find(|utxo| utxo.outpoint == *outpoint).cloned()
+    }
+
+    /// Round in signing with the given participants
+    fn signing_round(participants: &[(CoinjoinParticipant, KeySigner)]) -> (CoinjoinRound, Vec<ParticipantId>) {
+        let chain = chain(participants);
+        let mut round = CoinjoinRound::new(params());
+        let mut registered = Vec::new();
+        for (participant, _) in participants {
+            let change_script = participant.change_script.clone();
+            let registration = round.register_inputs(participant.inputs.clone(), change_script, utxo_set(&chain));
+            registered.push(registration.unwrap());
+        }
+        round.start_output_registration().unwrap();
+        for ((participant, _), (_, token)) in participants.iter().zip(&registered) {
+            round.register_output(token, participant.output_script.clone()).unwrap();
+        }
+        (round, registered.into_iter().map(|(id, _)| id).collect())
+    }
+
+    #[test]
+    fn test_round() {
+        let mut round = CoinjoinRound::new(params());
+        let participants = [participant(3, 150_000), participant(1, 101_000), participant(2, 120_000)];
+        let (poor, _) = participant(4, 100_500);
+        let mut chain = chain(&participants);
+        chain.extend(poor.inputs.clone());
+        let mut registered = Vec::new();
+        for (participant, _) in &participants {
+            let change_script = participant.change_script.clone();
+            let registration = round.register_inputs(participant.inputs.clone(), change_script, utxo_set(&chain));
+            registered.push(registration.unwrap());
+        }
+        let (duplicate, _) = participant(1, 101_000);
+        assert!(matches!(
+            round.register_inputs(duplicate.inputs, vec![9], utxo_set(&chain)),
+            Err(CoinjoinError::DuplicateInput(_))
+        ));
+        assert!(matches!(
+            round.register_inputs(poor.inputs.clone(), vec![9], utxo_set(&chain)),
+            Err(CoinjoinError::InsufficientFunds { .. })
+        ));
+
+        // Claiming more than the UTXO holds, or an output spent meanwhile, is refused
+        let mut inflated = poor.inputs.clone();
+        inflated[0].output.value = Amount::from_sat(500_000);
+        assert_eq!(
+            round.register_inputs(inflated, vec![9], utxo_set(&chain)),
+            Err(CoinjoinError::InputMismatch(poor.inputs[0].outpoint.clone()))
+        );
+        let (spent, _) = participant(5, 500_000);
+        assert_eq!(
+            round.register_inputs(spent.inputs.clone(), vec![9], utxo_set(&chain)),
+            Err(CoinjoinError::UnknownInput(spent.inputs[0].outpoint.clone()))
+        );
+        assert_eq!(round.participants(), 3);
+        assert_eq!(
+            round.register_output(&registered[0].1, vec![3, 1]),
+            Err(CoinjoinError::WrongPhase(RoundPhase::InputRegistration))
+        );
+        round.start_output_registration().unwrap();
+
+        // Tokens are redeemed in any order, once each
+        for ((participant, _), (_, token)) in participants.iter().zip(&registered).rev() {
+            round.register_output(token, participant.output_script.clone()).unwrap();
+        }
+        assert_eq!(round.phase(), RoundPhase::Signing);
+        let late = round.register_output(&registered[0].1, vec![7]);
+        assert_eq!(late, Err(CoinjoinError::WrongPhase(RoundPhase::Signing)));
+
+        let tx = round.transaction().unwrap().clone();
+        assert_eq!(tx.inputs.len(), 3);
+        // Three mixed outputs and two changes: the zero change of the second participant goes to the fee
+        let mixed = tx.outputs.iter().filter(|output| output.value == params().denomination).count();
+        assert_eq!((mixed, tx.outputs.len()), (3, 5));
+        assert!(tx.inputs.windows(2).all(|pair| pair[0].previous_output.txid < pair[1].previous_output.txid));
+
+        // Everyone signs their own inputs only
+        let (first, first_signer) = &participants[0];
+        let signatures = first.sign(&tx, &params(), first_signer).unwrap();
+        assert_eq!(signatures.len(), 1);
+        let (second_id, _) = registered[1];
+        let stolen = round.add_signatures(second_id, signatures.clone());
+        assert_eq!(stolen, Err(CoinjoinError::NotOwnInput(signatures[0].0)));
+        for ((participant, signer), (id, _)) in participants.iter().zip(&registered) {
+            let signatures = participant.sign(&tx, &params(), signer).unwrap();
+            round.add_signatures(*id, signatures).unwrap();
+        }
+        assert_eq!(round.phase(), RoundPhase::Complete);
+
+        let signed = round.transaction().unwrap();
+        for (index, input) in signed.inputs.iter().enumerate() {
+            let (participant, _) = participants.iter()
+                .find(|(participant, _)| participant.inputs[0].outpoint == input.previous_output)
+                .unwrap();
+            let checker = TransactionChecker::new(signed, index);
+            let script_pubkey = &participant.inputs[0].output.script_pubkey;
+            assert_eq!(verify_script(&input.script_sig, script_pubkey, VerifyFlags::STANDARD, &checker), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_expire() {
+        let participants = [participant(1, 150_000), participant(2, 101_000), participant(3, 120_000)];
+        let (mut round, ids) = signing_round(&participants);
+        let deadline = Instant::now() + params().phase_timeout;
+        assert_eq!(round.expire(Instant::now()), Vec::<ParticipantId>::new());
+        assert_eq!(round.phase(), RoundPhase::Signing);
+
+        // The second participant never signs and is dropped at the deadline
+        let tx = round.transaction().unwrap().clone();
+        for index in [0, 2] {
+            let (participant, signer) = &participants[index];
+            round.add_signatures(ids[index], participant.sign(&tx, &params(), signer).unwrap()).unwrap();
+        }
+        assert_eq!(round.expire(deadline), vec![ids[1]]);
+        assert_eq!((round.phase(), round.participants()), (RoundPhase::Failed, 2));
+        let late = participants[1].0.sign(&tx, &params(), &participants[1].1).unwrap();
+        assert_eq!(round.add_signatures(ids[1], late), Err(CoinjoinError::WrongPhase(RoundPhase::Failed)));
+
+        // An unredeemed token cannot be blamed on anyone: the round just fails
+        let chain = chain(&participants);
+        let mut round = CoinjoinRound::new(params());
+        for (participant, _) in &participants {
+            let change_script = participant.change_script.clone();
+            round.register_inputs(participant.inputs.clone(), change_script, utxo_set(&chain)).unwrap();
+        }
+        assert_eq!(round.expire(deadline), Vec::<ParticipantId>::new());
+        assert_eq!(round.phase(), RoundPhase::InputRegistration);
+        round.start_output_registration().unwrap();
+        assert_eq!(round.expire(Instant::now() + params().phase_timeout), Vec::<ParticipantId>::new());
+        assert_eq!((round.phase(), round.participants()), (RoundPhase::Failed, 3));
+    }
+
+    #[test]
+    fn test_participant_rejects_unfair_transaction() {
+        let (participant, signer) = participant(1, 150_000);
+        let change = params().change_for(&participant.inputs).unwrap().unwrap();
+        assert_eq!(change, Amount::from_sat(49_000));
+        let fair = Transaction::new(
+            vec![TxInput::new(participant.inputs[0].outpoint.clone(), Vec::new())],
+            vec![
+                TxOutput::new(params().denomination, NATIVE_ASSET, participant.output_script.clone()),
+                TxOutput::new(change, NATIVE_ASSET, participant.change_script.clone()),
+            ],
+            0,
+        );
+        assert_eq!(participant.verify(&fair, &params()), Ok(()));
+
+        // A coordinator withholding the change does not get the signature
+        let mut unfair = fair.clone();
+        unfair.outputs[1].value = Amount::from_sat(40_000);
+        assert!(matches!(
+            participant.sign(&unfair, &params(), &signer),
+            Err(SdkError::Coinjoin(CoinjoinError::MissingOutput))
+        ));
+        let mut missing = fair;
+        missing.inputs.clear();
+        assert!(matches!(participant.verify(&missing, &params()), Err(CoinjoinError::MissingInput(_))));
+    }
+}
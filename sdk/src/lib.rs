@@ -0,0 +1,50 @@
+//! Sedly SDK - clients and transaction helpers for external applications
+//!
+//! - [`RpcClient`]: typed async client for the node JSON-RPC interface,
+//!   with batch support; [`blocking::BlockingRpcClient`] wraps it for
+//!   synchronous code
+//! - [`TransactionBuilder`]: coin selection and change, from the wallet
+//! - [`KeySigner`] and [`sign_built`]: signing with local keys, or any
+//!   [`Signer`] implementation
+//! - [`Broadcaster`]: submission of signed transactions to the mempool,
+//!   and rebroadcast of the unconfirmed ones with a [`Rebroadcaster`]
+//! - [`coinjoin`]: collaborative transactions where each participant signs
+//!   only its own inputs
+//!
+//! ```no_run
+//! # async fn example(built: sedly_sdk::BuiltTransaction, key: secp256k1::SecretKey) -> Result<(), sedly_sdk::SdkError> {
+//! use sedly_sdk::{sign_built, Broadcaster, KeySigner};
+//!
+//! let tx = sign_built(&built, &KeySigner::new().with_key(key))?;
+//! let txid = Broadcaster::new("http://127.0.0.1:26657").broadcast(&tx).await?;
+//! # let _ = txid;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod blocking;
+pub mod broadcast;
+pub mod client;
+pub mod coinjoin;
+pub mod signing;
+
+pub use broadcast::Broadcaster;
+pub use client::{RpcClient, SdkError};
+pub use coinjoin::{CoinjoinError, CoinjoinParticipant, CoinjoinRound, OutputToken, RoundParams, RoundPhase};
+pub use signing::{sign_built, sign_transaction, KeySigner, Signer};
+
+pub use sedly_core::sighash::{
+    signature_hash, signature_hash_with_type, SighashType, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE,
+};
+pub use sedly_core::{OutPoint, Transaction, TxInput, TxOutput};
+pub use sedly_rpc::handlers::{
+    AssetBalance, BalancesInfo, BlockStatsInfo, BlockTemplateInfo, BlockchainInfo, ChainTipInfo, DecodedTransaction,
+    DifficultyHistory, MempoolAcceptResult, MempoolInfo, MempoolTx, NetTotalsInfo, NetworkParamsInfo, Page, PeerInfo,
+    RawTransactionInfo, ReorgInfo, ScanTxOutSetResult, SupplyInfo, TreasuryInfo, TxOutSetInfo, UnspentInfo,
+    UtxoSetHashInfo,
+};
+pub use sedly_rpc::RpcAuth;
+pub use sedly_wallet::{
+    BuildError, BuiltTransaction, CoinControl, PrivacyOptions, RebroadcastConfig, Rebroadcaster, TransactionBuilder,
+    WalletUtxo,
+};
@@ -0,0 +1,143 @@
+//! Transaction signing
+//!
+//! Inputs are signed over the digests of [`sedly_core::sighash`],
+//! `SIGHASH_ALL` unless the signer is given another [`SighashType`].
+//! [`KeySigner`] signs pay-to-pubkey and pay-to-pubkey-hash outputs with
+//! local keys; other key stores (HSMs, remote signers) plug in by
+//! implementing [`Signer`].
+
+use crate::client::SdkError;
+use secp256k1::{PublicKey, Secp256k1, SecretKey, SignOnly};
+use sedly_core::script::hash160;
+use sedly_core::sighash::{sign_input_with_type, SighashType};
+use sedly_core::{ScriptTemplate, Transaction, TxOutput};
+use sedly_wallet::{BuiltTransaction, ExtendedPrivKey};
+use std::collections::HashMap;
+
+/// Source of unlocking scripts
+pub trait Signer {
+    /// Unlocking script for input `input_index` of `tx` spending `spent`,
+    /// None if the signer does not hold the key of the output
+    fn sign_input(&self, tx: &Transaction, input_index: usize, spent: &TxOutput) -> Result<Option<Vec<u8>>, SdkError>;
+}
+
+/// Sign every input of `tx`; `spent` holds the spent outputs in input order
+///
+/// Fails without modifying `tx` if any input cannot be signed.
+pub fn sign_transaction(tx: &mut Transaction, spent: &[TxOutput], signer: &dyn Signer) -> Result<(), SdkError> {
+    if spent.len() != tx.inputs.len() {
+        return Err(SdkError::Signing(format!("{} spent outputs for {} inputs", spent.len(), tx.inputs.len())));
+    }
+    let mut script_sigs = Vec::with_capacity(spent.len());
+    for (input_index, output) in spent.iter().enumerate() {
+        let script_sig = signer.sign_input(tx, input_index, output)?
+            .ok_or_else(|| SdkError::Signing(format!("No key for input {}", input_index)))?;
+        script_sigs.push(script_sig);
+    }
+    for (input, script_sig) in tx.inputs.iter_mut().zip(script_sigs) {
+        input.script_sig = script_sig;
+    }
+    Ok(())
+}
+
+/// Sign a transaction produced by [`sedly_wallet::TransactionBuilder`]
+pub fn sign_built(built: &BuiltTransaction, signer: &dyn Signer) -> Result<Transaction, SdkError> {
+    let spent: Vec<TxOutput> = built.inputs.iter().map(|utxo| utxo.output.clone()).collect();
+    let mut tx = built.tx.clone();
+    sign_transaction(&mut tx, &spent, signer)?;
+    Ok(tx)
+}
+
+/// Signer holding secret keys in memory
+#[derive(Debug)]
+pub struct KeySigner {
+    /// Keys by the locking scripts they can spend
+    keys: HashMap<Vec<u8>, SecretKey>,
+    /// Sighash type of the signatures
+    sighash_type: SighashType,
+    secp: Secp256k1<SignOnly>,
+}
+
+impl KeySigner {
+    /// Create a signer without keys
+    pub fn new() -> Self {
+        Self { keys: HashMap::new(), sighash_type: SighashType::ALL, secp: Secp256k1::signing_only() }
+    }
+
+    /// Sign with `sighash_type` instead of `SIGHASH_ALL`
+    ///
+    /// E.g. `SighashType::ALL.anyone_can_pay()` lets others add inputs to a
+    /// crowdfunding transaction without invalidating the signature.
+    pub fn with_sighash_type(mut self, sighash_type: SighashType) -> Self {
+        self.sighash_type = sighash_type;
+        self
+    }
+
+    /// Add a key, spending its P2PK and P2PKH outputs
+    pub fn with_key(mut self, secret_key: SecretKey) -> Self {
+        let pubkey = PublicKey::from_secret_key(&self.secp, &secret_key).serialize();
+        self.keys.insert(ScriptTemplate::p2pk(&pubkey), secret_key);
+        self.keys.insert(ScriptTemplate::p2pkh(&hash160(&pubkey)), secret_key);
+        self
+    }
+
+    /// Add the key of a derived wallet account
+    pub fn with_extended_key(self, key: &ExtendedPrivKey) -> Self {
+        self.with_key(key.secret_key)
+    }
+}
+
+impl Default for KeySigner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signer for KeySigner {
+    fn sign_input(&self, tx: &Transaction, input_index: usize, spent: &TxOutput) -> Result<Option<Vec<u8>>, SdkError> {
+        Ok(self.keys.get(&spent.script_pubkey)
+            .and_then(|secret_key| {
+                sign_input_with_type(&self.secp, secret_key, tx, input_index, &spent.script_pubkey, self.sighash_type)
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::sighash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY};
+    use sedly_core::{OutPoint, TxInput};
+
+    #[test]
+    fn test_sign_transaction() {
+        let first = SecretKey::from_slice(&[7; 32]).unwrap();
+        let second = SecretKey::from_slice(&[8; 32]).unwrap();
+        let secp = Secp256k1::new();
+        let p2pk = ScriptTemplate::p2pk(&PublicKey::from_secret_key(&secp, &first).serialize());
+        let p2pkh = ScriptTemplate::p2pkh(&hash160(&PublicKey::from_secret_key(&secp, &second).serialize()));
+        let spent = vec![TxOutput::new(50, [0; 32], p2pk), TxOutput::new(30, [0; 32], p2pkh)];
+        let mut tx = Transaction::new(
+            vec![
+                TxInput::new(OutPoint::new([1; 32], 0), vec![]),
+                TxInput::new(OutPoint::new([2; 32], 1), vec![]),
+            ],
+            vec![TxOutput::to_address(70, b"alice")],
+            0,
+        );
+
+        // Without the second key the transaction is left untouched
+        let partial = KeySigner::new().with_key(first);
+        assert!(matches!(sign_transaction(&mut tx, &spent, &partial), Err(SdkError::Signing(_))));
+        assert!(tx.inputs.iter().all(|input| input.script_sig.is_empty()));
+
+        let signer = partial.with_key(second);
+        sign_transaction(&mut tx, &spent, &signer).unwrap();
+        // P2PK: the signature only; P2PKH: signature and compressed pubkey
+        assert_eq!(tx.inputs[0].script_sig.last(), Some(&SIGHASH_ALL));
+        assert_eq!(tx.inputs[1].script_sig.len(), tx.inputs[1].script_sig[0] as usize + 1 + 34);
+
+        let anyone_can_pay = KeySigner::new().with_key(first).with_sighash_type(SighashType::ALL.anyone_can_pay());
+        let script_sig = anyone_can_pay.sign_input(&tx, 0, &spent[0]).unwrap().unwrap();
+        assert_eq!(script_sig.last(), Some(&(SIGHASH_ALL | SIGHASH_ANYONECANPAY)));
+    }
+}
@@ -0,0 +1,210 @@
+//! Saldi del wallet per account e asset
+//!
+//! Un solo totale inganna chi mina: le ricompense restano non spendibili
+//! per `ChainParams::coinbase_maturity` blocchi. Il saldo di ogni account e
+//! asset è quindi diviso in:
+//!
+//! - confermato: UTXO spendibili nel prossimo block;
+//! - non confermato: output delle transazioni in mempool;
+//! - immaturo: output coinbase non ancora maturi.
+//!
+//! Un UTXO già speso da una transazione in mempool non conta più come
+//! confermato: il resto che torna al wallet compare tra i non confermati.
+
+use crate::discovery::AddressScanner;
+use sedly_core::{Amount, BlockchainDB, CancellationToken, ChainParams, Mempool, OutPoint, StorageError};
+use std::collections::BTreeMap;
+
+/// Saldo di un asset, diviso per stato
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    /// Spendibile nel prossimo block
+    pub confirmed: Amount,
+    /// Ricevuto da transazioni in mempool
+    pub unconfirmed: Amount,
+    /// Coinbase non ancora mature
+    pub immature: Amount,
+}
+
+impl Balance {
+    /// Somma delle tre categorie
+    pub fn total(&self) -> Amount {
+        self.confirmed.saturating_add(self.unconfirmed).saturating_add(self.immature)
+    }
+
+    /// Somma categoria per categoria
+    pub fn add(&mut self, other: &Balance) {
+        self.confirmed = self.confirmed.saturating_add(other.confirmed);
+        self.unconfirmed = self.unconfirmed.saturating_add(other.unconfirmed);
+        self.immature = self.immature.saturating_add(other.immature);
+    }
+}
+
+/// Saldi per account e asset (`[0; 32]` = SLY nativo)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountBalances {
+    /// Saldo per (account, asset)
+    balances: BTreeMap<(u32, [u8; 32]), Balance>,
+}
+
+impl AccountBalances {
+    /// Saldo di un asset in un account (zero se assente)
+    pub fn get(&self, account: u32, asset_id: &[u8; 32]) -> Balance {
+        self.balances.get(&(account, *asset_id)).copied().unwrap_or_default()
+    }
+
+    /// Saldi per asset di un account
+    pub fn account(&self, account: u32) -> BTreeMap<[u8; 32], Balance> {
+        self.balances
+            .range((account, [0; 32])..=(account, [0xff; 32]))
+            .map(|((_, asset_id), balance)| (*asset_id, *balance))
+            .collect()
+    }
+
+    /// Saldo di un asset sommato su tutti gli account
+    pub fn total(&self, asset_id: &[u8; 32]) -> Balance {
+        let mut total = Balance::default();
+        for balance in self.balances.iter().filter(|((_, asset), _)| asset == asset_id).map(|(_, balance)| balance) {
+            total.add(balance);
+        }
+        total
+    }
+
+    /// Account con almeno un saldo
+    pub fn accounts(&self) -> Vec<u32> {
+        let mut accounts: Vec<u32> = self.balances.keys().map(|(account, _)| *account).collect();
+        accounts.dedup();
+        accounts
+    }
+
+    /// Itera su (account, asset, saldo) in ordine di account e asset
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &[u8; 32], &Balance)> {
+        self.balances.iter().map(|((account, asset_id), balance)| (*account, asset_id, balance))
+    }
+
+    fn entry(&mut self, account: u32, asset_id: [u8; 32]) -> &mut Balance {
+        self.balances.entry((account, asset_id)).or_default()
+    }
+}
+
+/// Saldi degli script osservati da `scanner`, dal UTXO set e dalla mempool
+pub fn wallet_balances(
+    db: &BlockchainDB,
+    mempool: &Mempool,
+    params: &ChainParams,
+    scanner: &AddressScanner,
+) -> Result<AccountBalances, StorageError> {
+    account_balances(db, mempool, params, |script| scanner.path_of(script).map(|path| path.account))
+}
+
+/// Saldi per account dal UTXO set e dalla mempool
+///
+/// `account_of` dice a quale account appartiene uno script (None se non è
+/// del wallet). La maturità delle coinbase è valutata per il block
+/// successivo al tip.
+pub fn account_balances<F>(
+    db: &BlockchainDB,
+    mempool: &Mempool,
+    params: &ChainParams,
+    mut account_of: F,
+) -> Result<AccountBalances, StorageError>
+where
+    F: FnMut(&[u8]) -> Option<u32>,
+{
+    let spend_height = db.get_height()? + 1;
+    let scan = db.scan_utxos(&CancellationToken::new(), |_, entry| account_of(&entry.output.script_pubkey).is_some())?;
+
+    let mut balances = AccountBalances::default();
+    for (outpoint, entry) in &scan.matches {
+        if mempool.spender(outpoint).is_some() {
+            continue;
+        }
+        let Some(account) = account_of(&entry.output.script_pubkey) else {
+            continue;
+        };
+        let balance = balances.entry(account, entry.output.asset_id);
+        let matures_at = entry.block_height + params.coinbase_maturity_at(entry.block_height);
+        if entry.is_coinbase && spend_height < matures_at {
+            balance.immature = balance.immature.saturating_add(entry.output.value);
+        } else {
+            balance.confirmed = balance.confirmed.saturating_add(entry.output.value);
+        }
+    }
+
+    for mempool_entry in mempool.entries() {
+        let txid = mempool_entry.tx.hash();
+        for (vout, output) in mempool_entry.tx.outputs.iter().enumerate() {
+            if mempool.spender(&OutPoint::new(txid, vout as u32)).is_some() {
+                continue;
+            }
+            if let Some(account) = account_of(&output.script_pubkey) {
+                let balance = balances.entry(account, output.asset_id);
+                balance.unconfirmed = balance.unconfirmed.saturating_add(output.value);
+            }
+        }
+    }
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{anyone_can_spend, Block, BlockValidator, Transaction, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    const ASSET: [u8; 32] = [7; 32];
+    const COIN: Amount = Amount::from_sat(50_000);
+
+    fn account_of(script: &[u8]) -> Option<u32> {
+        match script {
+            b"alice" => Some(1),
+            _ if script == anyone_can_spend(b"miner") => Some(0),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_account_balances() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let params = ChainParams::regtest().with_coinbase_maturity(2);
+        let genesis = Block::genesis();
+        db.initialize_with_genesis(&genesis).unwrap();
+
+        // Coinbase da 50_000 satoshi ai block 1-4; il block 2 paga anche un asset ad alice
+        let mut chain = vec![genesis];
+        for height in 1..=4 {
+            let mut coinbase = Transaction::coinbase(&anyone_can_spend(b"miner"), height, COIN.to_sat());
+            if height == 2 {
+                coinbase.outputs.push(TxOutput::new(10, ASSET, b"alice".to_vec()));
+            }
+            let block = Block::new(chain.last().unwrap().hash(), vec![coinbase], 0x207fffff, height);
+            db.store_block(&block).unwrap();
+            chain.push(block);
+        }
+
+        // Al block 5 sono maturi i coinbase fino al block 3
+        let mut mempool = Mempool::new();
+        let balances = account_balances(&db, &mempool, &params, account_of).unwrap();
+        let miner = balances.get(0, &[0; 32]);
+        assert_eq!(miner.confirmed.to_sat(), COIN.to_sat() * 3);
+        assert_eq!(miner.immature, COIN);
+        assert_eq!(miner.unconfirmed, Amount::ZERO);
+        assert_eq!(balances.get(1, &ASSET).immature, Amount::ZERO);
+        assert_eq!(balances.get(1, &ASSET).confirmed, Amount::from_sat(10));
+        assert_eq!(balances.accounts(), vec![0, 1]);
+
+        // Una spesa in mempool sposta il valore da confermato a non confermato
+        let spend = Transaction::new(
+            vec![TxInput::new(OutPoint::new(chain[1].transactions[0].hash(), 0), vec![])],
+            vec![TxOutput::new(COIN.to_sat() - 5_000, [0; 32], b"alice".to_vec())],
+            0,
+        );
+        mempool.add(spend, 4, &BlockValidator::new(params.clone()), &db).unwrap();
+        let balances = account_balances(&db, &mempool, &params, account_of).unwrap();
+        assert_eq!(balances.get(0, &[0; 32]).confirmed.to_sat(), COIN.to_sat() * 2);
+        assert_eq!(balances.get(1, &[0; 32]).unconfirmed.to_sat(), COIN.to_sat() - 5_000);
+        assert_eq!(balances.account(1).len(), 2);
+        assert_eq!(balances.total(&[0; 32]).total().to_sat(), COIN.to_sat() * 4 - 5_000);
+    }
+}
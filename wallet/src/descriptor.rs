@@ -0,0 +1,433 @@
+//! Output script descriptor (pk, pkh, multi, sortedmulti, raw)
+//!
+//! Sintassi compatibile con i descriptor di Bitcoin Core, incluse origine
+//! della chiave (`[fingerprint/path]`), derivazione da xpub con wildcard
+//! (`/*`, `/*'`) e checksum (`#xxxxxxxx`).
+
+use crate::keys::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, KeyError};
+use secp256k1::PublicKey;
+use sedly_core::script::{hash160, ScriptError, ScriptTemplate, MAX_MULTISIG_KEYS};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Caratteri ammessi nei descriptor, nell'ordine usato dal checksum
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// Alfabeto del checksum
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Lunghezza del checksum
+const CHECKSUM_LEN: usize = 8;
+
+fn poly_mod(mut c: u64, value: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+    if c0 & 1 != 0 { c ^= 0xf5_dee5_1989; }
+    if c0 & 2 != 0 { c ^= 0xa9_fdca_3312; }
+    if c0 & 4 != 0 { c ^= 0x1b_ab10_e32d; }
+    if c0 & 8 != 0 { c ^= 0x37_06b1_677a; }
+    if c0 & 16 != 0 { c ^= 0x64_4d62_6ffd; }
+    c
+}
+
+/// Calcola il checksum di un descriptor (None se contiene caratteri non ammessi)
+pub fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1u64;
+    let mut class = 0u64;
+    let mut class_count = 0;
+
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch)? as u64;
+        c = poly_mod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = poly_mod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = poly_mod(c, class);
+    }
+    for _ in 0..CHECKSUM_LEN {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    Some(
+        (0..CHECKSUM_LEN)
+            .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+/// Origine di una chiave (fingerprint del master e percorso)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyOrigin {
+    /// Fingerprint della chiave master
+    pub fingerprint: [u8; 4],
+    /// Percorso dal master alla chiave
+    pub path: DerivationPath,
+}
+
+/// Materiale di una chiave nel descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// Chiave pubblica singola (compressa)
+    Single(PublicKey),
+    /// Chiave pubblica estesa
+    Xpub(ExtendedPubKey),
+    /// Chiave privata estesa (permette derivazioni hardened)
+    Xprv(ExtendedPrivKey),
+}
+
+/// Wildcard finale per descriptor con range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wildcard {
+    /// Nessun wildcard
+    None,
+    /// `/*`
+    Unhardened,
+    /// `/*'` (richiede xprv)
+    Hardened,
+}
+
+/// Espressione di chiave di un descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorKey {
+    /// Origine opzionale `[fingerprint/path]`
+    pub origin: Option<KeyOrigin>,
+    /// Chiave
+    pub source: KeySource,
+    /// Percorso di derivazione dopo la chiave estesa
+    pub path: DerivationPath,
+    /// Wildcard finale
+    pub wildcard: Wildcard,
+}
+
+impl DescriptorKey {
+    /// Verifica se la chiave dipende dall'indice di derivazione
+    pub fn is_ranged(&self) -> bool {
+        self.wildcard != Wildcard::None
+    }
+
+    /// Chiave pubblica per l'indice dato
+    pub fn derive_public_key(&self, index: u32) -> Result<PublicKey, DescriptorError> {
+        let path = match self.wildcard {
+            Wildcard::None => self.path.clone(),
+            Wildcard::Unhardened => self.path.child(ChildNumber::Normal(index)),
+            Wildcard::Hardened => self.path.child(ChildNumber::Hardened(index)),
+        };
+
+        match &self.source {
+            KeySource::Single(public_key) => Ok(*public_key),
+            KeySource::Xpub(xpub) => Ok(xpub.derive_path(&path)?.public_key),
+            KeySource::Xprv(xprv) => Ok(xprv.derive_path(&path)?.public_key()),
+        }
+    }
+}
+
+impl FromStr for DescriptorKey {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, key) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (origin, key) = rest
+                    .split_once(']')
+                    .ok_or_else(|| DescriptorError::Syntax("Unclosed key origin".to_string()))?;
+                (Some(parse_origin(origin)?), key)
+            }
+            None => (None, s),
+        };
+
+        let mut parts = key.split('/');
+        let encoded = parts.next().unwrap_or_default();
+        let mut steps: Vec<&str> = parts.collect();
+
+        let wildcard = match steps.last() {
+            Some(&"*") => Wildcard::Unhardened,
+            Some(&"*'") | Some(&"*h") | Some(&"*H") => Wildcard::Hardened,
+            _ => Wildcard::None,
+        };
+        if wildcard != Wildcard::None {
+            steps.pop();
+        }
+        let path = DerivationPath::from(
+            steps.iter().map(|step| step.parse()).collect::<Result<Vec<ChildNumber>, _>>()?,
+        );
+
+        let source = if encoded.starts_with("xpub") || encoded.starts_with("tpub") {
+            KeySource::Xpub(encoded.parse()?)
+        } else if encoded.starts_with("xprv") || encoded.starts_with("tprv") {
+            KeySource::Xprv(encoded.parse()?)
+        } else {
+            if !path.children().is_empty() || wildcard != Wildcard::None {
+                return Err(DescriptorError::Syntax("Derivation requires an extended key".to_string()));
+            }
+            let bytes = hex::decode(encoded)
+                .map_err(|_| DescriptorError::Syntax(format!("Invalid key: {}", encoded)))?;
+            if bytes.len() != 33 {
+                return Err(DescriptorError::Syntax("Only compressed public keys are supported".to_string()));
+            }
+            KeySource::Single(PublicKey::from_slice(&bytes).map_err(|e| KeyError::InvalidKey(e.to_string()))?)
+        };
+
+        if matches!(source, KeySource::Xpub(_)) && (path.has_hardened() || wildcard == Wildcard::Hardened) {
+            return Err(KeyError::HardenedFromPublic.into());
+        }
+
+        Ok(Self { origin, source, path, wildcard })
+    }
+}
+
+fn parse_origin(origin: &str) -> Result<KeyOrigin, DescriptorError> {
+    let (fingerprint_hex, path) = match origin.split_once('/') {
+        Some((fingerprint, path)) => (fingerprint, path.parse()?),
+        None => (origin, DerivationPath::master()),
+    };
+    let fingerprint: [u8; 4] = hex::decode(fingerprint_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| DescriptorError::Syntax(format!("Invalid fingerprint: {}", fingerprint_hex)))?;
+    Ok(KeyOrigin { fingerprint, path })
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = &self.origin {
+            write!(f, "[{}{}]", hex::encode(origin.fingerprint), origin.path.to_suffix())?;
+        }
+        match &self.source {
+            KeySource::Single(public_key) => write!(f, "{}", hex::encode(public_key.serialize()))?,
+            KeySource::Xpub(xpub) => write!(f, "{}", xpub)?,
+            KeySource::Xprv(xprv) => write!(f, "{}", xprv)?,
+        }
+        write!(f, "{}", self.path.to_suffix())?;
+        match self.wildcard {
+            Wildcard::None => Ok(()),
+            Wildcard::Unhardened => write!(f, "/*"),
+            Wildcard::Hardened => write!(f, "/*'"),
+        }
+    }
+}
+
+/// Output script descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Descriptor {
+    /// `pk(KEY)`: pay-to-pubkey
+    Pk(DescriptorKey),
+    /// `pkh(KEY)`: pay-to-pubkey-hash
+    Pkh(DescriptorKey),
+    /// `multi(k,KEY,...)` o `sortedmulti(k,KEY,...)`
+    Multi {
+        /// Firme richieste
+        threshold: usize,
+        /// Chiavi nell'ordine del descriptor
+        keys: Vec<DescriptorKey>,
+        /// Ordina le chiavi derivate (BIP67)
+        sorted: bool,
+    },
+    /// `raw(HEX)`: script esplicito
+    Raw(Vec<u8>),
+}
+
+impl Descriptor {
+    /// Chiavi del descriptor
+    pub fn keys(&self) -> Vec<&DescriptorKey> {
+        match self {
+            Descriptor::Pk(key) | Descriptor::Pkh(key) => vec![key],
+            Descriptor::Multi { keys, .. } => keys.iter().collect(),
+            Descriptor::Raw(_) => Vec::new(),
+        }
+    }
+
+    /// Verifica se il descriptor produce script diversi per indice
+    pub fn is_ranged(&self) -> bool {
+        self.keys().iter().any(|key| key.is_ranged())
+    }
+
+    /// Verifica se contiene chiavi private
+    pub fn has_private_keys(&self) -> bool {
+        self.keys().iter().any(|key| matches!(key.source, KeySource::Xprv(_)))
+    }
+
+    /// Script per l'indice dato (ignorato se il descriptor non ha range)
+    pub fn script_pubkey(&self, index: u32) -> Result<Vec<u8>, DescriptorError> {
+        match self {
+            Descriptor::Pk(key) => Ok(ScriptTemplate::p2pk(&key.derive_public_key(index)?.serialize())),
+            Descriptor::Pkh(key) => {
+                Ok(ScriptTemplate::p2pkh(&hash160(&key.derive_public_key(index)?.serialize())))
+            }
+            Descriptor::Multi { threshold, keys, sorted } => {
+                let mut pubkeys = keys
+                    .iter()
+                    .map(|key| key.derive_public_key(index).map(|pubkey| pubkey.serialize().to_vec()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if *sorted {
+                    pubkeys.sort();
+                }
+                Ok(ScriptTemplate::multisig(*threshold, &pubkeys)?)
+            }
+            Descriptor::Raw(script) => Ok(script.clone()),
+        }
+    }
+
+    /// Script per un intervallo di indici (un solo script se senza range)
+    pub fn script_pubkeys(&self, range: Range<u32>) -> Result<Vec<(u32, Vec<u8>)>, DescriptorError> {
+        if !self.is_ranged() {
+            return Ok(vec![(0, self.script_pubkey(0)?)]);
+        }
+        range.map(|index| Ok((index, self.script_pubkey(index)?))).collect()
+    }
+
+    /// Rappresentazione senza checksum
+    fn body(&self) -> String {
+        match self {
+            Descriptor::Pk(key) => format!("pk({})", key),
+            Descriptor::Pkh(key) => format!("pkh({})", key),
+            Descriptor::Multi { threshold, keys, sorted } => {
+                let keys: Vec<String> = keys.iter().map(ToString::to_string).collect();
+                let name = if *sorted { "sortedmulti" } else { "multi" };
+                format!("{}({},{})", name, threshold, keys.join(","))
+            }
+            Descriptor::Raw(script) => format!("raw({})", hex::encode(script)),
+        }
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = DescriptorError;
+
+    /// Il checksum è opzionale, ma se presente deve essere corretto
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let body = match s.split_once('#') {
+            Some((body, checksum)) => {
+                let expected = descriptor_checksum(body)
+                    .ok_or_else(|| DescriptorError::Syntax("Invalid character in descriptor".to_string()))?;
+                if checksum != expected {
+                    return Err(DescriptorError::InvalidChecksum { expected, got: checksum.to_string() });
+                }
+                body
+            }
+            None => s,
+        };
+
+        let (name, args) = body
+            .strip_suffix(')')
+            .and_then(|inner| inner.split_once('('))
+            .ok_or_else(|| DescriptorError::Syntax(format!("Expected function call: {}", body)))?;
+
+        match name {
+            "pk" => Ok(Descriptor::Pk(args.parse()?)),
+            "pkh" => Ok(Descriptor::Pkh(args.parse()?)),
+            "multi" | "sortedmulti" => {
+                let mut args = args.split(',');
+                let threshold: usize = args
+                    .next()
+                    .and_then(|threshold| threshold.parse().ok())
+                    .ok_or_else(|| DescriptorError::Syntax("Invalid multisig threshold".to_string()))?;
+                let keys = args.map(DescriptorKey::from_str).collect::<Result<Vec<_>, _>>()?;
+                if keys.is_empty() || keys.len() > MAX_MULTISIG_KEYS || threshold == 0 || threshold > keys.len() {
+                    return Err(ScriptError::InvalidThreshold { threshold, keys: keys.len() }.into());
+                }
+                Ok(Descriptor::Multi { threshold, keys, sorted: name == "sortedmulti" })
+            }
+            "raw" => hex::decode(args)
+                .map(Descriptor::Raw)
+                .map_err(|_| DescriptorError::Syntax(format!("Invalid script hex: {}", args))),
+            other => Err(DescriptorError::Syntax(format!("Unknown descriptor function: {}", other))),
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    /// Forma canonica con checksum
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.body();
+        let checksum = descriptor_checksum(&body).unwrap_or_default();
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+/// Errori dei descriptor
+#[derive(Debug, thiserror::Error)]
+pub enum DescriptorError {
+    #[error("Syntax error: {0}")]
+    Syntax(String),
+
+    #[error("Invalid checksum: expected {expected}, got {got}")]
+    InvalidChecksum { expected: String, got: String },
+
+    #[error("Key error: {0}")]
+    Key(#[from] KeyError),
+
+    #[error("Script error: {0}")]
+    Script(#[from] ScriptError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::Network;
+
+    // BIP32 vettore 1, m/0'
+    const XPUB: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert!("raw(deadbeef)#89f8spxm".parse::<Descriptor>().is_ok());
+        assert!(matches!(
+            "raw(deadbeef)#89f8spxx".parse::<Descriptor>(),
+            Err(DescriptorError::InvalidChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ranged_xpub() {
+        let descriptor: Descriptor = format!("pk([3442193e/0']{}/*)", XPUB).parse().unwrap();
+        assert!(descriptor.is_ranged());
+
+        // m/0'/1 del vettore BIP32
+        let script = descriptor.script_pubkey(1).unwrap();
+        assert_eq!(
+            hex::encode(&script),
+            "2103501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711cac"
+        );
+        assert_eq!(descriptor.script_pubkeys(0..3).unwrap().len(), 3);
+
+        // Roundtrip della forma canonica
+        let canonical = descriptor.to_string();
+        assert_eq!(canonical.parse::<Descriptor>().unwrap(), descriptor);
+
+        assert!(format!("pkh({}/*')", XPUB).parse::<Descriptor>().is_err());
+    }
+
+    #[test]
+    fn test_multi_and_pkh() {
+        let master = ExtendedPrivKey::new_master(Network::Mainnet, &[1u8; 32]).unwrap();
+        let a = master.derive_child(ChildNumber::Normal(0)).unwrap().public_key();
+        let b = master.derive_child(ChildNumber::Normal(1)).unwrap().public_key();
+        let (a_hex, b_hex) = (hex::encode(a.serialize()), hex::encode(b.serialize()));
+
+        let multi: Descriptor = format!("multi(1,{},{})", b_hex, a_hex).parse().unwrap();
+        let sorted: Descriptor = format!("sortedmulti(1,{},{})", b_hex, a_hex).parse().unwrap();
+        let mut expected = vec![a.serialize().to_vec(), b.serialize().to_vec()];
+        expected.sort();
+        assert_eq!(sorted.script_pubkey(0).unwrap(), ScriptTemplate::multisig(1, &expected).unwrap());
+        assert_ne!(multi.script_pubkey(0).unwrap(), sorted.script_pubkey(0).unwrap());
+
+        let pkh: Descriptor = format!("pkh({})", a_hex).parse().unwrap();
+        assert_eq!(
+            ScriptTemplate::classify(&pkh.script_pubkey(0).unwrap()),
+            ScriptTemplate::PayToPubkeyHash(hash160(&a.serialize()))
+        );
+
+        assert!(format!("multi(3,{},{})", a_hex, b_hex).parse::<Descriptor>().is_err());
+        assert!("wpkh(00)".parse::<Descriptor>().is_err());
+    }
+}
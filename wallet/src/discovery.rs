@@ -0,0 +1,475 @@
+//! Seed BIP39, account BIP44 e scoperta degli indirizzi con gap limit
+//!
+//! Da un mnemonic (validato sulla wordlist inglese, con passphrase BIP39
+//! opzionale) si ricava il seed e da
+//! questo gli account `m/44'/coin'/account'`, ciascuno con una catena
+//! esterna (ricezione) e una interna (resto). Al ripristino il rescan
+//! osserva per ogni catena gli indirizzi fino a `gap_limit` oltre l'ultimo
+//! usato, e un nuovo account solo se il precedente è stato usato.
+//!
+//! Un indirizzo della finestra allargata può comparire in un block già
+//! scansionato: il rescan ripete quindi la scansione finché la finestra non
+//! smette di crescere. Gli output trovati portano l'altezza del block che
+//! li spende, se c'è.
+
+use crate::descriptor::{Descriptor, DescriptorError, DescriptorKey, KeyOrigin, KeySource, Wildcard};
+use crate::keys::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, KeyError};
+use bip39::{Language, Mnemonic, Seed};
+use sedly_core::{Amount, BlockchainDB, Network, OutPoint, StorageError};
+use std::collections::{BTreeMap, HashMap};
+
+/// Gap limit di default (come BIP44)
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Purpose BIP44
+const BIP44_PURPOSE: u32 = 44;
+
+/// Coin type BIP44 di Sedly su mainnet, distinto da quello di Bitcoin (0)
+/// così lo stesso mnemonic non deriva le stesse chiavi sulle due chain
+pub const MAINNET_COIN_TYPE: u32 = 0x5ed1;
+
+/// Seed BIP39 di un mnemonic con passphrase opzionale (vuota se assente)
+///
+/// Il mnemonic deve essere composto da parole della wordlist inglese con
+/// checksum valido; spazi tra le parole e forme Unicode vengono
+/// normalizzati (NFKD) sia nel mnemonic sia nella passphrase.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> Result<[u8; 64], DiscoveryError> {
+    let mnemonic = Mnemonic::from_phrase(mnemonic, Language::English)
+        .map_err(|e| DiscoveryError::InvalidMnemonic(e.to_string()))?;
+    let mut seed = [0u8; 64];
+    seed.copy_from_slice(Seed::new(&mnemonic, passphrase).as_bytes());
+    Ok(seed)
+}
+
+/// Coin type BIP44 della rete ([`MAINNET_COIN_TYPE`] per mainnet, 1 per le reti di test)
+pub fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Mainnet => MAINNET_COIN_TYPE,
+        Network::Testnet | Network::Regtest => 1,
+    }
+}
+
+/// Catena di indirizzi di un account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressChain {
+    /// Indirizzi di ricezione (`/0/*`)
+    External,
+    /// Indirizzi di resto (`/1/*`)
+    Internal,
+}
+
+impl AddressChain {
+    /// Entrambe le catene
+    pub const ALL: [AddressChain; 2] = [AddressChain::External, AddressChain::Internal];
+
+    /// Indice della catena nel percorso
+    pub fn index(&self) -> u32 {
+        match self {
+            AddressChain::External => 0,
+            AddressChain::Internal => 1,
+        }
+    }
+}
+
+/// Account BIP44 (`m/44'/coin'/account'`)
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// Numero dell'account
+    pub index: u32,
+    /// Chiave privata estesa dell'account
+    key: ExtendedPrivKey,
+    /// Origine della chiave rispetto al master
+    origin: KeyOrigin,
+}
+
+impl Account {
+    /// Deriva l'account `index` dalla chiave master
+    pub fn derive(master: &ExtendedPrivKey, index: u32) -> Result<Self, KeyError> {
+        let path = Self::path(master.network, index);
+        Ok(Self {
+            index,
+            key: master.derive_path(&path)?,
+            origin: KeyOrigin { fingerprint: master.fingerprint(), path },
+        })
+    }
+
+    /// Percorso dell'account dal master
+    pub fn path(network: Network, index: u32) -> DerivationPath {
+        DerivationPath::from(vec![
+            ChildNumber::Hardened(BIP44_PURPOSE),
+            ChildNumber::Hardened(coin_type(network)),
+            ChildNumber::Hardened(index),
+        ])
+    }
+
+    /// Chiave pubblica estesa dell'account (watch-only)
+    pub fn xpub(&self) -> ExtendedPubKey {
+        self.key.to_extended_public()
+    }
+
+    /// Descriptor `pkh` con range di una catena, con origine della chiave
+    pub fn descriptor(&self, chain: AddressChain) -> Descriptor {
+        Descriptor::Pkh(DescriptorKey {
+            origin: Some(self.origin.clone()),
+            source: KeySource::Xpub(self.xpub()),
+            path: DerivationPath::from(vec![ChildNumber::Normal(chain.index())]),
+            wildcard: Wildcard::Unhardened,
+        })
+    }
+}
+
+/// Posizione di un indirizzo derivato
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddressPath {
+    /// Account
+    pub account: u32,
+    /// Catena
+    pub chain: AddressChain,
+    /// Indice nella catena
+    pub index: u32,
+}
+
+/// Finestra di una catena osservata
+#[derive(Debug, Clone)]
+struct WatchedChain {
+    /// Descriptor della catena
+    descriptor: Descriptor,
+    /// Primo indice non ancora derivato
+    derived: u32,
+    /// Ultimo indice usato
+    last_used: Option<u32>,
+    /// Primo indice non ancora consegnato da `next_unused`
+    issued: u32,
+}
+
+/// Indirizzi osservati durante un rescan, con finestre a gap limit
+#[derive(Debug, Clone)]
+pub struct AddressScanner {
+    /// Chiave master da cui derivare nuovi account
+    master: ExtendedPrivKey,
+    /// Indirizzi consecutivi non usati da osservare oltre l'ultimo usato
+    gap_limit: u32,
+    /// Catene osservate per (account, catena)
+    chains: BTreeMap<(u32, AddressChain), WatchedChain>,
+    /// Script osservati
+    scripts: HashMap<Vec<u8>, AddressPath>,
+}
+
+impl AddressScanner {
+    /// Osserva l'account 0 del master con il gap limit dato
+    pub fn new(master: ExtendedPrivKey, gap_limit: u32) -> Result<Self, DiscoveryError> {
+        let mut scanner = Self {
+            master,
+            gap_limit: gap_limit.max(1),
+            chains: BTreeMap::new(),
+            scripts: HashMap::new(),
+        };
+        scanner.add_account(0)?;
+        Ok(scanner)
+    }
+
+    /// Scanner per il ripristino da mnemonic e passphrase BIP39
+    pub fn from_mnemonic(network: Network, mnemonic: &str, passphrase: &str, gap_limit: u32) -> Result<Self, DiscoveryError> {
+        let master = ExtendedPrivKey::new_master(network, &mnemonic_to_seed(mnemonic, passphrase)?)?;
+        Self::new(master, gap_limit)
+    }
+
+    /// Numero di script osservati
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    /// Se non osserva alcuno script
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Account osservati
+    pub fn accounts(&self) -> u32 {
+        self.chains.keys().map(|(account, _)| account + 1).max().unwrap_or(0)
+    }
+
+    /// Indirizzo di uno script osservato (senza segnarlo come usato)
+    pub fn path_of(&self, script_pubkey: &[u8]) -> Option<AddressPath> {
+        self.scripts.get(script_pubkey).copied()
+    }
+
+    /// Ultimo indice usato di una catena
+    pub fn last_used(&self, account: u32, chain: AddressChain) -> Option<u32> {
+        self.chains.get(&(account, chain)).and_then(|watched| watched.last_used)
+    }
+
+    /// Descriptor delle catene osservate
+    pub fn descriptors(&self) -> Vec<(u32, AddressChain, &Descriptor)> {
+        self.chains.iter().map(|((account, chain), watched)| (*account, *chain, &watched.descriptor)).collect()
+    }
+
+    /// Indirizzo nuovo di una catena, es. per il resto di una transazione
+    ///
+    /// È il primo dopo l'ultimo usato e dopo quelli già consegnati, così due
+    /// transazioni non mandano mai il resto allo stesso indirizzo; la
+    /// finestra osservata si allarga per includerlo.
+    pub fn next_unused(&mut self, account: u32, chain: AddressChain) -> Result<(AddressPath, Vec<u8>), DiscoveryError> {
+        if !self.chains.contains_key(&(account, chain)) {
+            self.add_account(account)?;
+        }
+        let watched = self.chains.get_mut(&(account, chain)).expect("watched chain");
+        let index = watched.last_used.map_or(0, |last| last + 1).max(watched.issued);
+        watched.issued = index + 1;
+        let script = watched.descriptor.script_pubkey(index)?;
+        self.extend(account, chain)?;
+        Ok((AddressPath { account, chain, index }, script))
+    }
+
+    /// Segna come usato uno script, se osservato, allargando la finestra
+    /// della sua catena e aggiungendo l'account successivo al primo uso
+    pub fn observe(&mut self, script_pubkey: &[u8]) -> Result<Option<AddressPath>, DiscoveryError> {
+        let Some(path) = self.scripts.get(script_pubkey).copied() else {
+            return Ok(None);
+        };
+
+        let account_was_used = AddressChain::ALL.iter().any(|chain| self.last_used(path.account, *chain).is_some());
+        let watched = self.chains.get_mut(&(path.account, path.chain)).expect("chain of a watched script");
+        if watched.last_used.is_none_or(|last| path.index > last) {
+            watched.last_used = Some(path.index);
+            self.extend(path.account, path.chain)?;
+        }
+        if !account_was_used && !self.chains.contains_key(&(path.account + 1, AddressChain::External)) {
+            self.add_account(path.account + 1)?;
+        }
+        Ok(Some(path))
+    }
+
+    /// Aggiunge un account con entrambe le catene
+    fn add_account(&mut self, index: u32) -> Result<(), DiscoveryError> {
+        let account = Account::derive(&self.master, index)?;
+        for chain in AddressChain::ALL {
+            self.chains.insert((index, chain), WatchedChain {
+                descriptor: account.descriptor(chain),
+                derived: 0,
+                last_used: None,
+                issued: 0,
+            });
+            self.extend(index, chain)?;
+        }
+        Ok(())
+    }
+
+    /// Deriva gli script della catena fino a `gap_limit` oltre l'ultimo usato
+    fn extend(&mut self, account: u32, chain: AddressChain) -> Result<(), DiscoveryError> {
+        let watched = self.chains.get_mut(&(account, chain)).expect("watched chain");
+        let next = watched.last_used.map_or(0, |last| last + 1).max(watched.issued);
+        let target = next.saturating_add(self.gap_limit);
+        for index in watched.derived..target {
+            let script = watched.descriptor.script_pubkey(index)?;
+            self.scripts.insert(script, AddressPath { account, chain, index });
+        }
+        watched.derived = watched.derived.max(target);
+        Ok(())
+    }
+}
+
+/// Output trovato dal rescan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundOutput {
+    /// Output
+    pub outpoint: OutPoint,
+    /// Valore
+    pub value: Amount,
+    /// Asset dell'output ([0; 32] = SLY nativo)
+    pub asset_id: [u8; 32],
+    /// Altezza del block
+    pub height: u64,
+    /// Indirizzo che lo riceve
+    pub path: AddressPath,
+    /// Altezza del block che lo spende, None se non speso
+    pub spent_height: Option<u64>,
+}
+
+/// Esito di un rescan
+#[derive(Debug, Clone, Default)]
+pub struct RescanSummary {
+    /// Output ricevuti dagli indirizzi del wallet, spesi o no, in ordine di chain
+    pub outputs: Vec<FoundOutput>,
+    /// Scansioni complete della chain eseguite
+    pub passes: u32,
+}
+
+impl RescanSummary {
+    /// Output trovati non ancora spesi sulla chain attiva
+    pub fn unspent(&self) -> impl Iterator<Item = &FoundOutput> {
+        self.outputs.iter().filter(|output| output.spent_height.is_none())
+    }
+}
+
+/// Scansiona la chain attiva per gli output degli indirizzi osservati
+///
+/// La scansione si ripete finché la finestra degli indirizzi cresce, così
+/// un indirizzo usato prima del suo predecessore nella catena non va perso.
+/// Gli input di ogni transazione segnano come spesi gli output già trovati.
+pub fn rescan(db: &BlockchainDB, scanner: &mut AddressScanner) -> Result<RescanSummary, DiscoveryError> {
+    let tip = db.get_height()?;
+    let mut summary = RescanSummary::default();
+    let mut found: BTreeMap<(u64, usize, u32), FoundOutput> = BTreeMap::new();
+    let mut positions: HashMap<OutPoint, (u64, usize, u32)> = HashMap::new();
+
+    loop {
+        let watched = scanner.len();
+        summary.passes += 1;
+        for height in 0..=tip {
+            let block = db.get_block_by_height(height)?.ok_or(DiscoveryError::MissingBlock(height))?;
+            for (tx_index, tx) in block.transactions.iter().enumerate() {
+                for input in &tx.inputs {
+                    if let Some(position) = positions.get(&input.previous_output) {
+                        found.get_mut(position).expect("found output").spent_height = Some(height);
+                    }
+                }
+                for (vout, output) in tx.outputs.iter().enumerate() {
+                    if let Some(path) = scanner.observe(&output.script_pubkey)? {
+                        let position = (height, tx_index, vout as u32);
+                        let outpoint = OutPoint::new(tx.hash(), vout as u32);
+                        positions.insert(outpoint.clone(), position);
+                        found.entry(position).or_insert_with(|| FoundOutput {
+                            outpoint,
+                            value: output.value,
+                            asset_id: output.asset_id,
+                            height,
+                            path,
+                            spent_height: None,
+                        });
+                    }
+                }
+            }
+        }
+        if scanner.len() == watched {
+            break;
+        }
+    }
+
+    summary.outputs = found.into_values().collect();
+    Ok(summary)
+}
+
+/// Errori della scoperta degli indirizzi
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoveryError {
+    #[error("Key error: {0}")]
+    Key(#[from] KeyError),
+
+    #[error("Descriptor error: {0}")]
+    Descriptor(#[from] DescriptorError),
+
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+
+    #[error("Block at height {0} missing from the active chain")]
+    MissingBlock(u64),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::{Block, Transaction, TxInput, TxOutput};
+    use tempfile::TempDir;
+
+    // Vettore BIP39 con passphrase "TREZOR"
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_bip39_seed() {
+        let seed = mnemonic_to_seed(MNEMONIC, "TREZOR").unwrap();
+        assert_eq!(
+            hex::encode(seed),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        assert_eq!(
+            ExtendedPrivKey::new_master(Network::Mainnet, &seed).unwrap().to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+
+        // Spazi diversi, stesso seed; passphrase diversa, wallet diverso
+        let spaced = format!("  {}\n", MNEMONIC.replace(' ', "   "));
+        assert_eq!(mnemonic_to_seed(&spaced, "TREZOR").unwrap(), seed);
+        assert_ne!(mnemonic_to_seed(MNEMONIC, "").unwrap(), seed);
+
+        // Passphrase in forma composta e decomposta: stesso seed (NFKD)
+        let composed = mnemonic_to_seed(MNEMONIC, "caf\u{e9}").unwrap();
+        assert_eq!(mnemonic_to_seed(MNEMONIC, "cafe\u{301}").unwrap(), composed);
+
+        // Parola fuori dalla wordlist e checksum sbagliato
+        let unknown = MNEMONIC.replace("about", "abouts");
+        assert!(matches!(mnemonic_to_seed(&unknown, ""), Err(DiscoveryError::InvalidMnemonic(_))));
+        let checksum = MNEMONIC.replace("about", "abandon");
+        assert!(matches!(mnemonic_to_seed(&checksum, ""), Err(DiscoveryError::InvalidMnemonic(_))));
+    }
+
+    #[test]
+    fn test_coin_type() {
+        assert_eq!(Account::path(Network::Mainnet, 0).to_string(), "m/44'/24273'/0'");
+        assert_eq!(Account::path(Network::Testnet, 2).to_string(), "m/44'/1'/2'");
+    }
+
+    #[test]
+    fn test_rescan_with_gap_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let master = ExtendedPrivKey::new_master(Network::Regtest, &mnemonic_to_seed(MNEMONIC, "").unwrap()).unwrap();
+        let script = |account: u32, chain: AddressChain, index: u32| {
+            Account::derive(&master, account).unwrap().descriptor(chain).script_pubkey(index).unwrap()
+        };
+
+        // L'indirizzo 7 è usato prima del 4, che allarga la finestra fino a 8;
+        // l'account 1 è usato prima dell'account 0
+        let payments = [
+            script(1, AddressChain::External, 0),
+            script(0, AddressChain::External, 7),
+            script(0, AddressChain::External, 4),
+            script(0, AddressChain::Internal, 1),
+            script(0, AddressChain::External, 20),
+        ];
+        // Il primo output ricevuto viene speso nel block 3
+        let mut previous_hash = [0; 32];
+        let mut first_received = None;
+        for (height, script_pubkey) in payments.iter().enumerate() {
+            let mut coinbase = Transaction::coinbase(b"miner", height as u64, 50);
+            coinbase.outputs.push(TxOutput::new(10, [0; 32], script_pubkey.clone()));
+            let mut transactions = vec![coinbase.clone()];
+            if height == 3 {
+                let input = TxInput::new(first_received.clone().unwrap(), vec![]);
+                transactions.push(Transaction::new(vec![input], vec![TxOutput::to_address(10, b"shop")], 0));
+            }
+            first_received.get_or_insert(OutPoint::new(coinbase.hash(), 1));
+            let block = Block::new(previous_hash, transactions, 0x207fffff, height as u64);
+            db.store_block(&block).unwrap();
+            previous_hash = block.hash();
+        }
+
+        let mut scanner = AddressScanner::new(master.clone(), 5).unwrap();
+        let summary = rescan(&db, &mut scanner).unwrap();
+        assert_eq!(summary.outputs.len(), 4);
+        assert_eq!(summary.passes, 3);
+        assert_eq!(summary.outputs[0].outpoint, first_received.unwrap());
+        assert_eq!(summary.outputs[0].spent_height, Some(3));
+        assert_eq!(summary.unspent().count(), 3);
+        assert_eq!(scanner.last_used(0, AddressChain::External), Some(7));
+        assert_eq!(scanner.last_used(0, AddressChain::Internal), Some(1));
+        assert_eq!(scanner.last_used(1, AddressChain::External), Some(0));
+        assert_eq!(scanner.accounts(), 3);
+
+        // Indirizzi di resto sempre nuovi, e osservati
+        let (first, first_script) = scanner.next_unused(0, AddressChain::Internal).unwrap();
+        let (second, second_script) = scanner.next_unused(0, AddressChain::Internal).unwrap();
+        assert_eq!((first.index, second.index), (2, 3));
+        assert_eq!(first_script, script(0, AddressChain::Internal, 2));
+        assert_ne!(first_script, second_script);
+        assert_eq!(scanner.observe(&second_script).unwrap(), Some(second));
+
+        // Con un gap limit più ampio si trova anche l'indirizzo 20
+        let mut scanner = AddressScanner::new(master, DEFAULT_GAP_LIMIT).unwrap();
+        assert_eq!(rescan(&db, &mut scanner).unwrap().outputs.len(), 5);
+        assert_eq!(scanner.last_used(0, AddressChain::External), Some(20));
+    }
+}
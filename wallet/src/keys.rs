@@ -0,0 +1,663 @@
+//! Chiavi e derivazione gerarchica (BIP32)
+//!
+//! Le chiavi singole (es. da paper wallet) si scambiano nel formato WIF di
+//! Bitcoin: prefisso di rete, 32 bytes di chiave, flag di chiave compressa
+//! e checksum in base58check.
+
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sedly_core::script::hash160;
+use sedly_core::{Network, ScriptTemplate};
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+
+/// Bit che marca un indice hardened
+pub const HARDENED_BIT: u32 = 0x8000_0000;
+
+/// Version bytes delle chiavi estese (xpub/xprv, tpub/tprv)
+const VERSION_MAINNET_PUBLIC: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const VERSION_MAINNET_PRIVATE: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const VERSION_TESTNET_PUBLIC: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const VERSION_TESTNET_PRIVATE: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+
+/// Lunghezza di una chiave estesa serializzata (senza checksum)
+const EXTENDED_KEY_LEN: usize = 78;
+
+/// Prefissi delle chiavi private WIF
+const WIF_MAINNET_PREFIX: u8 = 0x80;
+const WIF_TESTNET_PREFIX: u8 = 0xef;
+
+/// Suffisso WIF di una chiave con pubkey compressa
+const WIF_COMPRESSED_FLAG: u8 = 0x01;
+
+/// Indice di un figlio nella derivazione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChildNumber {
+    /// Derivazione normale (possibile anche da chiave pubblica)
+    Normal(u32),
+    /// Derivazione hardened (richiede la chiave privata)
+    Hardened(u32),
+}
+
+impl ChildNumber {
+    /// Crea da indice raw (bit hardened incluso)
+    pub fn from_index(index: u32) -> Self {
+        if index & HARDENED_BIT != 0 {
+            ChildNumber::Hardened(index & !HARDENED_BIT)
+        } else {
+            ChildNumber::Normal(index)
+        }
+    }
+
+    /// Indice raw (bit hardened incluso)
+    pub fn index(&self) -> u32 {
+        match self {
+            ChildNumber::Normal(index) => *index,
+            ChildNumber::Hardened(index) => index | HARDENED_BIT,
+        }
+    }
+
+    /// Verifica se è hardened
+    pub fn is_hardened(&self) -> bool {
+        matches!(self, ChildNumber::Hardened(_))
+    }
+}
+
+impl FromStr for ChildNumber {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, hardened) = match s.strip_suffix(['\'', 'h', 'H']) {
+            Some(digits) => (digits, true),
+            None => (s, false),
+        };
+        let index: u32 = digits
+            .parse()
+            .map_err(|_| KeyError::InvalidChildNumber(s.to_string()))?;
+        if index & HARDENED_BIT != 0 {
+            return Err(KeyError::InvalidChildNumber(s.to_string()));
+        }
+        Ok(if hardened { ChildNumber::Hardened(index) } else { ChildNumber::Normal(index) })
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildNumber::Normal(index) => write!(f, "{}", index),
+            ChildNumber::Hardened(index) => write!(f, "{}'", index),
+        }
+    }
+}
+
+/// Percorso di derivazione (es. `m/44'/0'/0'/0`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl DerivationPath {
+    /// Percorso vuoto (la chiave stessa)
+    pub fn master() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Indici del percorso
+    pub fn children(&self) -> &[ChildNumber] {
+        &self.0
+    }
+
+    /// Nuovo percorso con un figlio aggiunto
+    pub fn child(&self, child: ChildNumber) -> Self {
+        let mut children = self.0.clone();
+        children.push(child);
+        Self(children)
+    }
+
+    /// Verifica se contiene passi hardened
+    pub fn has_hardened(&self) -> bool {
+        self.0.iter().any(ChildNumber::is_hardened)
+    }
+
+    /// Formato per i descriptor (`/0/1'`, vuoto per il master)
+    pub fn to_suffix(&self) -> String {
+        self.0.iter().map(|child| format!("/{}", child)).collect()
+    }
+}
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(children: Vec<ChildNumber>) -> Self {
+        Self(children)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = KeyError;
+
+    /// Accetta `m/0'/1`, `0'/1` e `m`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.strip_prefix('m').map(|rest| rest.strip_prefix('/').unwrap_or(rest)).unwrap_or(s);
+        if rest.is_empty() {
+            return Ok(Self::master());
+        }
+        rest.split('/')
+            .map(ChildNumber::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m{}", self.to_suffix())
+    }
+}
+
+/// Chiave privata estesa BIP32
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedPrivKey {
+    /// Rete (determina i version bytes)
+    pub network: Network,
+    /// Profondità nell'albero (0 = master)
+    pub depth: u8,
+    /// Fingerprint della chiave parent
+    pub parent_fingerprint: [u8; 4],
+    /// Indice con cui è stata derivata
+    pub child_number: ChildNumber,
+    /// Chain code
+    pub chain_code: [u8; 32],
+    /// Chiave privata
+    pub secret_key: SecretKey,
+}
+
+/// Chiave pubblica estesa BIP32
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPubKey {
+    /// Rete (determina i version bytes)
+    pub network: Network,
+    /// Profondità nell'albero (0 = master)
+    pub depth: u8,
+    /// Fingerprint della chiave parent
+    pub parent_fingerprint: [u8; 4],
+    /// Indice con cui è stata derivata
+    pub child_number: ChildNumber,
+    /// Chain code
+    pub chain_code: [u8; 32],
+    /// Chiave pubblica
+    pub public_key: PublicKey,
+}
+
+/// HMAC-SHA512 diviso in (chiave, chain code)
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    let output = mac.finalize().into_bytes();
+
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+/// Fingerprint di una chiave pubblica (primi 4 bytes di hash160)
+fn key_fingerprint(public_key: &PublicKey) -> [u8; 4] {
+    let hash = hash160(&public_key.serialize());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+impl ExtendedPrivKey {
+    /// Deriva la chiave master da un seed (16-64 bytes)
+    pub fn new_master(network: Network, seed: &[u8]) -> Result<Self, KeyError> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(KeyError::InvalidSeed(seed.len()));
+        }
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", seed);
+
+        Ok(Self {
+            network,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: ChildNumber::Normal(0),
+            chain_code,
+            secret_key: SecretKey::from_slice(&key).map_err(|e| KeyError::InvalidKey(e.to_string()))?,
+        })
+    }
+
+    /// Chiave pubblica corrispondente
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.secret_key)
+    }
+
+    /// Fingerprint di questa chiave
+    pub fn fingerprint(&self) -> [u8; 4] {
+        key_fingerprint(&self.public_key())
+    }
+
+    /// Deriva un figlio
+    pub fn derive_child(&self, child: ChildNumber) -> Result<Self, KeyError> {
+        let mut data = Vec::with_capacity(37);
+        if child.is_hardened() {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key.secret_bytes());
+        } else {
+            data.extend_from_slice(&self.public_key().serialize());
+        }
+        data.extend_from_slice(&child.index().to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| KeyError::InvalidDerivation)?;
+        let secret_key = self.secret_key.add_tweak(&tweak).map_err(|_| KeyError::InvalidDerivation)?;
+
+        Ok(Self {
+            network: self.network,
+            depth: self.depth.checked_add(1).ok_or(KeyError::MaxDepthExceeded)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: child,
+            chain_code,
+            secret_key,
+        })
+    }
+
+    /// Deriva lungo un percorso
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, KeyError> {
+        path.children().iter().try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+
+    /// Chiave pubblica estesa corrispondente
+    pub fn to_extended_public(&self) -> ExtendedPubKey {
+        ExtendedPubKey {
+            network: self.network,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.public_key(),
+        }
+    }
+
+    fn encode(&self) -> [u8; EXTENDED_KEY_LEN] {
+        let version = match self.network {
+            Network::Mainnet => VERSION_MAINNET_PRIVATE,
+            Network::Testnet | Network::Regtest => VERSION_TESTNET_PRIVATE,
+        };
+        let mut key_data = [0u8; 33];
+        key_data[1..].copy_from_slice(&self.secret_key.secret_bytes());
+        encode_extended(version, self.depth, self.parent_fingerprint, self.child_number, &self.chain_code, &key_data)
+    }
+}
+
+impl fmt::Debug for ExtendedPrivKey {
+    /// Non espone la chiave privata nei log
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedPrivKey")
+            .field("network", &self.network)
+            .field("depth", &self.depth)
+            .field("fingerprint", &hex::encode(self.fingerprint()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bs58::encode(self.encode()).with_check().into_string())
+    }
+}
+
+impl FromStr for ExtendedPrivKey {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decoded = decode_extended(s)?;
+        let network = match decoded.version {
+            VERSION_MAINNET_PRIVATE => Network::Mainnet,
+            VERSION_TESTNET_PRIVATE => Network::Testnet,
+            _ => return Err(KeyError::WrongKeyType),
+        };
+        if decoded.key_data[0] != 0 {
+            return Err(KeyError::InvalidKey("Private key must be prefixed by 0x00".to_string()));
+        }
+
+        Ok(Self {
+            network,
+            depth: decoded.depth,
+            parent_fingerprint: decoded.parent_fingerprint,
+            child_number: decoded.child_number,
+            chain_code: decoded.chain_code,
+            secret_key: SecretKey::from_slice(&decoded.key_data[1..])
+                .map_err(|e| KeyError::InvalidKey(e.to_string()))?,
+        })
+    }
+}
+
+impl ExtendedPubKey {
+    /// Fingerprint di questa chiave
+    pub fn fingerprint(&self) -> [u8; 4] {
+        key_fingerprint(&self.public_key)
+    }
+
+    /// Deriva un figlio (solo derivazione normale)
+    pub fn derive_child(&self, child: ChildNumber) -> Result<Self, KeyError> {
+        if child.is_hardened() {
+            return Err(KeyError::HardenedFromPublic);
+        }
+
+        let mut data = Vec::with_capacity(37);
+        data.extend_from_slice(&self.public_key.serialize());
+        data.extend_from_slice(&child.index().to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| KeyError::InvalidDerivation)?;
+        let public_key = self.public_key
+            .add_exp_tweak(&Secp256k1::verification_only(), &tweak)
+            .map_err(|_| KeyError::InvalidDerivation)?;
+
+        Ok(Self {
+            network: self.network,
+            depth: self.depth.checked_add(1).ok_or(KeyError::MaxDepthExceeded)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: child,
+            chain_code,
+            public_key,
+        })
+    }
+
+    /// Deriva lungo un percorso (solo passi normali)
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, KeyError> {
+        path.children().iter().try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+
+    fn encode(&self) -> [u8; EXTENDED_KEY_LEN] {
+        let version = match self.network {
+            Network::Mainnet => VERSION_MAINNET_PUBLIC,
+            Network::Testnet | Network::Regtest => VERSION_TESTNET_PUBLIC,
+        };
+        encode_extended(
+            version,
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            &self.chain_code,
+            &self.public_key.serialize(),
+        )
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bs58::encode(self.encode()).with_check().into_string())
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decoded = decode_extended(s)?;
+        let network = match decoded.version {
+            VERSION_MAINNET_PUBLIC => Network::Mainnet,
+            VERSION_TESTNET_PUBLIC => Network::Testnet,
+            _ => return Err(KeyError::WrongKeyType),
+        };
+
+        Ok(Self {
+            network,
+            depth: decoded.depth,
+            parent_fingerprint: decoded.parent_fingerprint,
+            child_number: decoded.child_number,
+            chain_code: decoded.chain_code,
+            public_key: PublicKey::from_slice(&decoded.key_data)
+                .map_err(|e| KeyError::InvalidKey(e.to_string()))?,
+        })
+    }
+}
+
+/// Chiave privata singola in formato WIF
+///
+/// Sedly usa solo pubkey compresse: le chiavi WIF non compresse sono rifiutate.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PrivateKey {
+    /// Rete (determina il prefisso)
+    pub network: Network,
+    /// Chiave privata
+    pub secret_key: SecretKey,
+}
+
+impl PrivateKey {
+    /// Chiave per la rete data
+    pub fn new(network: Network, secret_key: SecretKey) -> Self {
+        Self { network, secret_key }
+    }
+
+    /// Chiave pubblica corrispondente
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.secret_key)
+    }
+
+    /// Script degli output spendibili con la chiave (P2PKH e P2PK)
+    pub fn script_pubkeys(&self) -> [Vec<u8>; 2] {
+        let public_key = self.public_key().serialize();
+        [ScriptTemplate::p2pkh(&hash160(&public_key)), ScriptTemplate::p2pk(&public_key)]
+    }
+
+    /// Se la chiave è per `network` (testnet e regtest hanno lo stesso prefisso)
+    pub fn is_for(&self, network: Network) -> bool {
+        (self.network == Network::Mainnet) == (network == Network::Mainnet)
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    /// Non espone la chiave privata nei log
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("network", &self.network)
+            .field("fingerprint", &hex::encode(key_fingerprint(&self.public_key())))
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.network {
+            Network::Mainnet => WIF_MAINNET_PREFIX,
+            Network::Testnet | Network::Regtest => WIF_TESTNET_PREFIX,
+        };
+        let mut bytes = Vec::with_capacity(34);
+        bytes.push(prefix);
+        bytes.extend_from_slice(&self.secret_key.secret_bytes());
+        bytes.push(WIF_COMPRESSED_FLAG);
+        f.write_str(&bs58::encode(bytes).with_check().into_string())
+    }
+}
+
+impl FromStr for PrivateKey {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| KeyError::InvalidEncoding(e.to_string()))?;
+        let network = match bytes.first() {
+            Some(&WIF_MAINNET_PREFIX) => Network::Mainnet,
+            Some(&WIF_TESTNET_PREFIX) => Network::Testnet,
+            _ => return Err(KeyError::WrongKeyType),
+        };
+        match bytes.len() {
+            34 if bytes[33] == WIF_COMPRESSED_FLAG => {}
+            33 => return Err(KeyError::InvalidKey("Uncompressed keys are not supported".to_string())),
+            len => return Err(KeyError::InvalidEncoding(format!("Unexpected WIF length of {} bytes", len))),
+        }
+
+        Ok(Self {
+            network,
+            secret_key: SecretKey::from_slice(&bytes[1..33]).map_err(|e| KeyError::InvalidKey(e.to_string()))?,
+        })
+    }
+}
+
+/// Campi di una chiave estesa decodificata
+struct DecodedExtendedKey {
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: ChildNumber,
+    chain_code: [u8; 32],
+    key_data: [u8; 33],
+}
+
+fn encode_extended(
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: ChildNumber,
+    chain_code: &[u8; 32],
+    key_data: &[u8; 33],
+) -> [u8; EXTENDED_KEY_LEN] {
+    let mut bytes = [0u8; EXTENDED_KEY_LEN];
+    bytes[..4].copy_from_slice(&version);
+    bytes[4] = depth;
+    bytes[5..9].copy_from_slice(&parent_fingerprint);
+    bytes[9..13].copy_from_slice(&child_number.index().to_be_bytes());
+    bytes[13..45].copy_from_slice(chain_code);
+    bytes[45..].copy_from_slice(key_data);
+    bytes
+}
+
+fn decode_extended(s: &str) -> Result<DecodedExtendedKey, KeyError> {
+    let bytes = bs58::decode(s)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| KeyError::InvalidEncoding(e.to_string()))?;
+    if bytes.len() != EXTENDED_KEY_LEN {
+        return Err(KeyError::InvalidEncoding(format!("Expected {} bytes, got {}", EXTENDED_KEY_LEN, bytes.len())));
+    }
+
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&bytes[..4]);
+    let mut parent_fingerprint = [0u8; 4];
+    parent_fingerprint.copy_from_slice(&bytes[5..9]);
+    let mut index = [0u8; 4];
+    index.copy_from_slice(&bytes[9..13]);
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&bytes[13..45]);
+    let mut key_data = [0u8; 33];
+    key_data.copy_from_slice(&bytes[45..]);
+
+    Ok(DecodedExtendedKey {
+        version,
+        depth: bytes[4],
+        parent_fingerprint,
+        child_number: ChildNumber::from_index(u32::from_be_bytes(index)),
+        chain_code,
+        key_data,
+    })
+}
+
+/// Errori delle chiavi
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("Invalid seed length: {0} bytes")]
+    InvalidSeed(usize),
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid child number: {0}")]
+    InvalidChildNumber(String),
+
+    #[error("Cannot derive hardened child from a public key")]
+    HardenedFromPublic,
+
+    #[error("Derived key is invalid, use the next index")]
+    InvalidDerivation,
+
+    #[error("Maximum derivation depth exceeded")]
+    MaxDepthExceeded,
+
+    #[error("Invalid extended key encoding: {0}")]
+    InvalidEncoding(String),
+
+    #[error("Unexpected extended key type")]
+    WrongKeyType,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vettore di test 1 di BIP32
+    const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    #[test]
+    fn test_bip32_vector() {
+        let master = ExtendedPrivKey::new_master(Network::Mainnet, &hex::decode(SEED).unwrap()).unwrap();
+        assert_eq!(
+            master.to_extended_public().to_string(),
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"
+        );
+
+        let hardened = master.derive_path(&"m/0'".parse().unwrap()).unwrap();
+        assert_eq!(
+            hardened.to_string(),
+            "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"
+        );
+
+        // La derivazione pubblica coincide con quella privata
+        let from_public = hardened.to_extended_public().derive_child(ChildNumber::Normal(1)).unwrap();
+        let from_private = hardened.derive_child(ChildNumber::Normal(1)).unwrap().to_extended_public();
+        assert_eq!(from_public, from_private);
+        assert_eq!(
+            from_public.to_string(),
+            "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ"
+        );
+    }
+
+    #[test]
+    fn test_extended_key_roundtrip() {
+        let master = ExtendedPrivKey::new_master(Network::Testnet, &[7u8; 32]).unwrap();
+        let encoded = master.to_string();
+        assert!(encoded.starts_with("tprv"));
+        assert_eq!(encoded.parse::<ExtendedPrivKey>().unwrap(), master);
+
+        let xpub = master.to_extended_public();
+        assert!(xpub.to_string().starts_with("tpub"));
+        assert_eq!(xpub.to_string().parse::<ExtendedPubKey>().unwrap(), xpub);
+        assert!(matches!(xpub.to_string().parse::<ExtendedPrivKey>(), Err(KeyError::WrongKeyType)));
+        assert!(matches!(xpub.derive_child(ChildNumber::Hardened(0)), Err(KeyError::HardenedFromPublic)));
+    }
+
+    #[test]
+    fn test_wif() {
+        // Chiave compressa 1 di Bitcoin (mainnet e testnet)
+        let secret_key = SecretKey::from_slice(&[0; 31].into_iter().chain([1]).collect::<Vec<u8>>()).unwrap();
+        let key = PrivateKey::new(Network::Mainnet, secret_key);
+        assert_eq!(key.to_string(), "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn");
+        assert_eq!(key.to_string().parse::<PrivateKey>().unwrap(), key);
+        let testnet = PrivateKey::new(Network::Regtest, secret_key).to_string();
+        assert_eq!(testnet, "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA");
+        assert!(testnet.parse::<PrivateKey>().unwrap().is_for(Network::Regtest));
+        assert!(!key.is_for(Network::Testnet));
+
+        // Non compressa, checksum errato, chiave estesa
+        assert!(matches!(
+            "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf".parse::<PrivateKey>(),
+            Err(KeyError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWo".parse::<PrivateKey>(),
+            Err(KeyError::InvalidEncoding(_))
+        ));
+        let xprv = ExtendedPrivKey::new_master(Network::Mainnet, &[7u8; 32]).unwrap().to_string();
+        assert!(xprv.parse::<PrivateKey>().is_err());
+    }
+
+    #[test]
+    fn test_derivation_path() {
+        let path: DerivationPath = "m/44'/0h/0/5".parse().unwrap();
+        assert_eq!(path.children().len(), 4);
+        assert!(path.has_hardened());
+        assert_eq!(path.to_string(), "m/44'/0'/0/5");
+        assert_eq!("m".parse::<DerivationPath>().unwrap(), DerivationPath::master());
+        assert!("m/x".parse::<DerivationPath>().is_err());
+    }
+}
@@ -0,0 +1,544 @@
+//! Keystore cifrato delle chiavi private
+//!
+//! Ogni chiave è cifrata con AES-256-GCM usando una chiave derivata dalla
+//! passphrase con Argon2id (salt casuale per keystore, nonce casuale per
+//! chiave, etichetta come dati autenticati). Per firmare il keystore va
+//! sbloccato per un tempo limitato, come `walletpassphrase` di Bitcoin Core:
+//! scaduto il timeout torna bloccato da solo. Il cambio di passphrase
+//! ricifra tutte le chiavi con un nuovo salt.
+//!
+//! Il keystore conserva anche i descriptor importati in sola lettura
+//! (watch-only): sono dati pubblici, salvati in chiaro e disponibili anche
+//! a keystore bloccato, che indicano quali output appartengono al wallet.
+
+use crate::descriptor::{Descriptor, DescriptorError};
+use crate::keys::ExtendedPrivKey;
+use argon2::{Algorithm, Argon2, Params, Version};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Durata massima di uno sblocco (come Bitcoin Core, circa 3 anni)
+pub const MAX_UNLOCK_TIMEOUT: Duration = Duration::from_secs(100_000_000);
+
+/// Lunghezza del salt della derivazione
+const SALT_LEN: usize = 16;
+
+/// Etichetta riservata al valore di verifica della passphrase
+const CHECK_LABEL: &str = "";
+
+/// Valore cifrato per verificare la passphrase anche a keystore vuoto
+const CHECK_PLAINTEXT: &[u8] = b"sedly-keystore";
+
+/// Parametri Argon2id della derivazione della chiave di cifratura
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memoria in KiB
+    pub memory_kib: u32,
+    /// Numero di passate
+    pub iterations: u32,
+    /// Parallelismo
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// Raccomandazione OWASP per Argon2id (19 MiB, 2 passate)
+    fn default() -> Self {
+        Self {
+            memory_kib: 19_456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Deriva la chiave di cifratura dalla passphrase
+    fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<[u8; 32], KeystoreError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// Segreto cifrato
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Sealed {
+    /// Nonce AES-GCM (hex)
+    nonce: String,
+    /// Testo cifrato con il tag di autenticazione (hex)
+    ciphertext: String,
+}
+
+/// Descriptor importato in sola lettura
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchedDescriptor {
+    /// Descriptor con checksum
+    pub desc: String,
+    /// Indici derivati, per i descriptor con range
+    pub range: Range<u32>,
+}
+
+impl WatchedDescriptor {
+    /// Script osservati: uno per indice del range, uno solo senza range
+    pub fn script_pubkeys(&self) -> Result<Vec<Vec<u8>>, KeystoreError> {
+        let invalid = |e: DescriptorError| KeystoreError::InvalidKey(e.to_string());
+        let descriptor: Descriptor = self.desc.parse().map_err(invalid)?;
+        let scripts = descriptor.script_pubkeys(self.range.clone()).map_err(invalid)?;
+        Ok(scripts.into_iter().map(|(_, script)| script).collect())
+    }
+}
+
+/// Chiave di cifratura in memoria durante uno sblocco
+struct Unlocked {
+    /// Chiave derivata dalla passphrase
+    key: [u8; 32],
+    /// Istante in cui il keystore torna bloccato
+    until: Instant,
+}
+
+impl Drop for Unlocked {
+    fn drop(&mut self) {
+        self.key = [0; 32];
+    }
+}
+
+/// Keystore cifrato
+#[derive(Serialize, Deserialize)]
+pub struct Keystore {
+    /// Parametri della derivazione
+    kdf: KdfParams,
+    /// Salt della derivazione (hex)
+    salt: String,
+    /// Valore di verifica della passphrase
+    check: Sealed,
+    /// Chiavi cifrate per etichetta
+    keys: BTreeMap<String, Sealed>,
+    /// Descriptor watch-only per etichetta (in chiaro)
+    #[serde(default)]
+    descriptors: BTreeMap<String, WatchedDescriptor>,
+    /// Stato di sblocco (mai salvato)
+    #[serde(skip)]
+    unlocked: Option<Unlocked>,
+}
+
+impl Keystore {
+    /// Crea un keystore vuoto protetto da `passphrase`, inizialmente bloccato
+    pub fn new(passphrase: &str, kdf: KdfParams) -> Result<Self, KeystoreError> {
+        let salt = random_bytes::<SALT_LEN>()?;
+        let key = kdf.derive(passphrase, &salt)?;
+        Ok(Self {
+            kdf,
+            salt: hex::encode(salt),
+            check: seal(&key, CHECK_LABEL, CHECK_PLAINTEXT)?,
+            keys: BTreeMap::new(),
+            descriptors: BTreeMap::new(),
+            unlocked: None,
+        })
+    }
+
+    /// Carica un keystore salvato (bloccato)
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, KeystoreError> {
+        let data = std::fs::read_to_string(path).map_err(|e| KeystoreError::Io(e.to_string()))?;
+        serde_json::from_str(&data).map_err(|e| KeystoreError::Serialization(e.to_string()))
+    }
+
+    /// Salva il keystore (solo dati cifrati)
+    ///
+    /// Il file temporaneo viene sincronizzato su disco e poi rinominato: un
+    /// crash durante il salvataggio lascia intatto il keystore precedente.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), KeystoreError> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| KeystoreError::Serialization(e.to_string()))?;
+        let io = |e: std::io::Error| KeystoreError::Io(e.to_string());
+        let tmp_path = path.as_ref().with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path).map_err(io)?;
+        file.write_all(data.as_bytes()).map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp_path, path).map_err(io)
+    }
+
+    /// Parametri della derivazione
+    pub fn kdf(&self) -> KdfParams {
+        self.kdf
+    }
+
+    /// Etichette delle chiavi salvate
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.keys.keys().map(String::as_str)
+    }
+
+    /// Se una chiave è presente
+    pub fn contains(&self, label: &str) -> bool {
+        self.keys.contains_key(label)
+    }
+
+    /// Sblocca il keystore per `timeout` (al più `MAX_UNLOCK_TIMEOUT`)
+    ///
+    /// Un nuovo sblocco sostituisce il precedente, anche se più lungo.
+    pub fn unlock(&mut self, passphrase: &str, timeout: Duration) -> Result<(), KeystoreError> {
+        let key = self.verify_passphrase(passphrase)?;
+        self.unlocked = Some(Unlocked {
+            key,
+            until: Instant::now() + timeout.min(MAX_UNLOCK_TIMEOUT),
+        });
+        Ok(())
+    }
+
+    /// Blocca subito il keystore
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    /// Se il keystore è bloccato, bloccandolo se il timeout è scaduto
+    pub fn is_locked(&mut self) -> bool {
+        self.unlocked_key().is_err()
+    }
+
+    /// Tempo rimanente prima del blocco automatico (None se bloccato)
+    pub fn unlocked_for(&mut self) -> Option<Duration> {
+        self.unlocked_key().ok()?;
+        self.unlocked.as_ref().map(|unlocked| unlocked.until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Aggiunge una chiave privata (richiede il keystore sbloccato)
+    pub fn add_secret_key(&mut self, label: &str, secret_key: &SecretKey) -> Result<(), KeystoreError> {
+        self.insert(label, &secret_key.secret_bytes())
+    }
+
+    /// Chiave privata con l'etichetta data (richiede il keystore sbloccato)
+    pub fn secret_key(&mut self, label: &str) -> Result<SecretKey, KeystoreError> {
+        let bytes = self.open(label)?;
+        SecretKey::from_slice(&bytes).map_err(|e| KeystoreError::InvalidKey(e.to_string()))
+    }
+
+    /// Aggiunge una chiave privata estesa (richiede il keystore sbloccato)
+    pub fn add_extended_key(&mut self, label: &str, key: &ExtendedPrivKey) -> Result<(), KeystoreError> {
+        self.insert(label, key.to_string().as_bytes())
+    }
+
+    /// Chiave privata estesa con l'etichetta data (richiede il keystore sbloccato)
+    pub fn extended_key(&mut self, label: &str) -> Result<ExtendedPrivKey, KeystoreError> {
+        let bytes = self.open(label)?;
+        std::str::from_utf8(&bytes)
+            .map_err(|e| KeystoreError::InvalidKey(e.to_string()))?
+            .parse()
+            .map_err(|e: crate::keys::KeyError| KeystoreError::InvalidKey(e.to_string()))
+    }
+
+    /// Rimuove una chiave (richiede il keystore sbloccato)
+    pub fn remove(&mut self, label: &str) -> Result<(), KeystoreError> {
+        self.unlocked_key()?;
+        self.keys.remove(label).map(|_| ()).ok_or_else(|| KeystoreError::UnknownKey(label.to_string()))
+    }
+
+    /// Importa un descriptor in sola lettura (non richiede lo sblocco)
+    ///
+    /// `range` vale per i descriptor con range. I descriptor con chiavi
+    /// private sono rifiutati: verrebbero salvati in chiaro.
+    pub fn import_descriptor(
+        &mut self,
+        label: &str,
+        descriptor: &Descriptor,
+        range: Range<u32>,
+    ) -> Result<(), KeystoreError> {
+        if label.is_empty() {
+            return Err(KeystoreError::InvalidLabel);
+        }
+        if descriptor.has_private_keys() {
+            return Err(KeystoreError::InvalidKey("watch-only descriptors must not contain private keys".to_string()));
+        }
+        if self.descriptors.contains_key(label) {
+            return Err(KeystoreError::DuplicateKey(label.to_string()));
+        }
+        let watched = WatchedDescriptor { desc: descriptor.to_string(), range };
+        watched.script_pubkeys()?;
+        self.descriptors.insert(label.to_string(), watched);
+        Ok(())
+    }
+
+    /// Rimuove un descriptor watch-only, se presente
+    pub fn remove_descriptor(&mut self, label: &str) -> bool {
+        self.descriptors.remove(label).is_some()
+    }
+
+    /// Descriptor watch-only per etichetta
+    pub fn descriptors(&self) -> impl Iterator<Item = (&str, &WatchedDescriptor)> {
+        self.descriptors.iter().map(|(label, watched)| (label.as_str(), watched))
+    }
+
+    /// Script di tutti i descriptor watch-only
+    pub fn watched_scripts(&self) -> Result<HashSet<Vec<u8>>, KeystoreError> {
+        let mut scripts = HashSet::new();
+        for watched in self.descriptors.values() {
+            scripts.extend(watched.script_pubkeys()?);
+        }
+        Ok(scripts)
+    }
+
+    /// Cambia la passphrase e i parametri di derivazione, ricifrando tutte
+    /// le chiavi con un nuovo salt e nuovi nonce
+    ///
+    /// Al termine il keystore è bloccato.
+    pub fn change_passphrase(&mut self, old: &str, new: &str, kdf: KdfParams) -> Result<(), KeystoreError> {
+        *self = self.rekeyed(old, new, kdf)?;
+        Ok(())
+    }
+
+    /// Copia bloccata del keystore cifrata con la nuova passphrase, senza
+    /// modificare questo
+    ///
+    /// Permette di salvare la copia prima di sostituirla in memoria.
+    pub fn rekeyed(&self, old: &str, new: &str, kdf: KdfParams) -> Result<Self, KeystoreError> {
+        let old_key = self.verify_passphrase(old)?;
+        let secrets = self.keys
+            .iter()
+            .map(|(label, sealed)| Ok((label.clone(), unseal(&old_key, label, sealed)?)))
+            .collect::<Result<Vec<_>, KeystoreError>>()?;
+
+        let salt = random_bytes::<SALT_LEN>()?;
+        let new_key = kdf.derive(new, &salt)?;
+        let mut keys = BTreeMap::new();
+        for (label, secret) in secrets {
+            let sealed = seal(&new_key, &label, &secret)?;
+            keys.insert(label, sealed);
+        }
+
+        Ok(Self {
+            kdf,
+            salt: hex::encode(salt),
+            check: seal(&new_key, CHECK_LABEL, CHECK_PLAINTEXT)?,
+            keys,
+            descriptors: self.descriptors.clone(),
+            unlocked: None,
+        })
+    }
+
+    /// Deriva la chiave di cifratura verificando la passphrase
+    fn verify_passphrase(&self, passphrase: &str) -> Result<[u8; 32], KeystoreError> {
+        let salt = hex::decode(&self.salt).map_err(|e| KeystoreError::Serialization(e.to_string()))?;
+        let key = self.kdf.derive(passphrase, &salt)?;
+        match unseal(&key, CHECK_LABEL, &self.check) {
+            Ok(check) if check == CHECK_PLAINTEXT => Ok(key),
+            _ => Err(KeystoreError::WrongPassphrase),
+        }
+    }
+
+    /// Chiave di cifratura se lo sblocco non è scaduto
+    fn unlocked_key(&mut self) -> Result<[u8; 32], KeystoreError> {
+        match &self.unlocked {
+            Some(unlocked) if Instant::now() < unlocked.until => Ok(unlocked.key),
+            Some(_) => {
+                self.lock();
+                Err(KeystoreError::Locked)
+            }
+            None => Err(KeystoreError::Locked),
+        }
+    }
+
+    /// Cifra e aggiunge un segreto
+    fn insert(&mut self, label: &str, secret: &[u8]) -> Result<(), KeystoreError> {
+        let key = self.unlocked_key()?;
+        if label == CHECK_LABEL {
+            return Err(KeystoreError::InvalidLabel);
+        }
+        if self.keys.contains_key(label) {
+            return Err(KeystoreError::DuplicateKey(label.to_string()));
+        }
+        let sealed = seal(&key, label, secret)?;
+        self.keys.insert(label.to_string(), sealed);
+        Ok(())
+    }
+
+    /// Decifra un segreto
+    fn open(&mut self, label: &str) -> Result<Vec<u8>, KeystoreError> {
+        let key = self.unlocked_key()?;
+        let sealed = self.keys.get(label).ok_or_else(|| KeystoreError::UnknownKey(label.to_string()))?;
+        unseal(&key, label, sealed)
+    }
+}
+
+impl fmt::Debug for Keystore {
+    /// Non espone dati cifrati né la chiave di sblocco nei log
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("kdf", &self.kdf)
+            .field("keys", &self.keys.len())
+            .field("descriptors", &self.descriptors.len())
+            .field("unlocked", &self.unlocked.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Cifra un segreto legandolo alla sua etichetta
+fn seal(key: &[u8; 32], label: &str, plaintext: &[u8]) -> Result<Sealed, KeystoreError> {
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let mut buffer = plaintext.to_vec();
+    aead_key(key)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(label.as_bytes()), &mut buffer)
+        .map_err(|_| KeystoreError::Crypto)?;
+    Ok(Sealed {
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(buffer),
+    })
+}
+
+/// Decifra un segreto, verificandone etichetta e integrità
+fn unseal(key: &[u8; 32], label: &str, sealed: &Sealed) -> Result<Vec<u8>, KeystoreError> {
+    let nonce = hex::decode(&sealed.nonce)
+        .ok()
+        .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+        .ok_or(KeystoreError::Crypto)?;
+    let mut buffer = hex::decode(&sealed.ciphertext).map_err(|_| KeystoreError::Crypto)?;
+    let plaintext = aead_key(key)?
+        .open_in_place(nonce, Aad::from(label.as_bytes()), &mut buffer)
+        .map_err(|_| KeystoreError::Crypto)?;
+    Ok(plaintext.to_vec())
+}
+
+/// Chiave AES-256-GCM
+fn aead_key(key: &[u8; 32]) -> Result<LessSafeKey, KeystoreError> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| KeystoreError::Crypto)
+}
+
+/// Bytes casuali dal generatore del sistema
+fn random_bytes<const N: usize>() -> Result<[u8; N], KeystoreError> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| KeystoreError::Crypto)?;
+    Ok(bytes)
+}
+
+/// Errori del keystore
+#[derive(Debug, thiserror::Error)]
+pub enum KeystoreError {
+    #[error("Keystore is locked, unlock it with the passphrase first")]
+    Locked,
+
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("Unknown key: {0}")]
+    UnknownKey(String),
+
+    #[error("Key already exists: {0}")]
+    DuplicateKey(String),
+
+    #[error("Key label must not be empty")]
+    InvalidLabel,
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Key derivation failed: {0}")]
+    Kdf(String),
+
+    #[error("Encryption failed or data corrupted")]
+    Crypto,
+
+    #[error("I/O error: {0}")]
+    Io(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sedly_core::Network;
+    use tempfile::TempDir;
+
+    // Parametri leggeri per i test
+    const TEST_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    #[test]
+    fn test_timed_unlock() {
+        let mut keystore = Keystore::new("correct horse", TEST_KDF).unwrap();
+        let secret = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        assert!(keystore.is_locked());
+        assert!(matches!(keystore.add_secret_key("a", &secret), Err(KeystoreError::Locked)));
+        assert!(matches!(keystore.unlock("wrong", Duration::from_secs(60)), Err(KeystoreError::WrongPassphrase)));
+
+        keystore.unlock("correct horse", Duration::from_secs(60)).unwrap();
+        keystore.add_secret_key("a", &secret).unwrap();
+        assert_eq!(keystore.secret_key("a").unwrap(), secret);
+        assert!(keystore.unlocked_for().unwrap() <= Duration::from_secs(60));
+
+        // Con timeout scaduto il keystore si blocca da solo
+        keystore.unlock("correct horse", Duration::ZERO).unwrap();
+        assert!(matches!(keystore.secret_key("a"), Err(KeystoreError::Locked)));
+        assert!(keystore.unlocked_for().is_none());
+    }
+
+    #[test]
+    fn test_change_passphrase_and_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("keystore.json");
+        let master = ExtendedPrivKey::new_master(Network::Testnet, &[7u8; 32]).unwrap();
+
+        let mut keystore = Keystore::new("old", TEST_KDF).unwrap();
+        keystore.unlock("old", Duration::from_secs(60)).unwrap();
+        keystore.add_extended_key("master", &master).unwrap();
+        assert!(matches!(keystore.add_extended_key("master", &master), Err(KeystoreError::DuplicateKey(_))));
+        let before = serde_json::to_string(&keystore).unwrap();
+        assert!(!before.contains(&master.to_string()));
+
+        let rotated = KdfParams { memory_kib: 128, ..TEST_KDF };
+        // rekeyed non tocca l'originale, ancora sbloccato con la vecchia passphrase
+        let mut copy = keystore.rekeyed("old", "new", rotated).unwrap();
+        assert!(copy.is_locked());
+        assert_eq!(serde_json::to_string(&keystore).unwrap(), before);
+        assert_eq!(keystore.extended_key("master").unwrap(), master);
+
+        keystore.change_passphrase("old", "new", rotated).unwrap();
+        assert!(keystore.is_locked());
+        assert_ne!(serde_json::to_string(&keystore).unwrap(), before);
+        keystore.save(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let mut loaded = Keystore::load(&path).unwrap();
+        assert_eq!(loaded.kdf(), rotated);
+        assert!(matches!(loaded.unlock("old", Duration::from_secs(60)), Err(KeystoreError::WrongPassphrase)));
+        loaded.unlock("new", Duration::from_secs(60)).unwrap();
+        assert_eq!(loaded.extended_key("master").unwrap(), master);
+        assert_eq!(loaded.labels().collect::<Vec<_>>(), vec!["master"]);
+    }
+
+    #[test]
+    fn test_watch_only_descriptors() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("keystore.json");
+        let master = ExtendedPrivKey::new_master(Network::Testnet, &[7u8; 32]).unwrap();
+        let ranged: Descriptor = format!("pkh({}/0/*)", master.to_extended_public()).parse().unwrap();
+        let private: Descriptor = format!("pkh({}/0/*)", master).parse().unwrap();
+
+        // Bloccato: i descriptor pubblici si importano comunque, quelli con xprv no
+        let mut keystore = Keystore::new("passphrase", TEST_KDF).unwrap();
+        keystore.import_descriptor("receive", &ranged, 0..5).unwrap();
+        assert!(matches!(keystore.import_descriptor("receive", &ranged, 0..5), Err(KeystoreError::DuplicateKey(_))));
+        assert!(matches!(keystore.import_descriptor("hot", &private, 0..5), Err(KeystoreError::InvalidKey(_))));
+        assert!(keystore.labels().next().is_none());
+        keystore.save(&path).unwrap();
+
+        let loaded = Keystore::load(&path).unwrap();
+        let scripts = loaded.watched_scripts().unwrap();
+        assert_eq!(scripts.len(), 5);
+        assert!(scripts.contains(&ranged.script_pubkey(4).unwrap()));
+        assert_eq!(loaded.descriptors().next().unwrap(), ("receive", &WatchedDescriptor {
+            desc: ranged.to_string(),
+            range: 0..5,
+        }));
+    }
+}
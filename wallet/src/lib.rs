@@ -0,0 +1,26 @@
+//! Sedly Wallet - chiavi, descriptor e gestione fondi
+
+pub mod balance;
+pub mod descriptor;
+pub mod discovery;
+pub mod keys;
+pub mod keystore;
+pub mod rebroadcast;
+pub mod sweep;
+pub mod transactions;
+
+pub use balance::{account_balances, wallet_balances, AccountBalances, Balance};
+pub use descriptor::{Descriptor, DescriptorError, DescriptorKey};
+pub use discovery::{
+    mnemonic_to_seed, rescan, Account, AddressChain, AddressPath, AddressScanner, DiscoveryError, FoundOutput,
+    RescanSummary, DEFAULT_GAP_LIMIT,
+};
+pub use keys::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, KeyError, PrivateKey};
+pub use keystore::{KdfParams, Keystore, KeystoreError, WatchedDescriptor, MAX_UNLOCK_TIMEOUT};
+pub use rebroadcast::{
+    PendingTx, RebroadcastConfig, Rebroadcaster, TxStatus, DEFAULT_REBROADCAST_INTERVAL, DEFAULT_REBROADCAST_JITTER,
+};
+pub use sweep::{key_utxos, Sweep, SweepBuilder, SweepError};
+pub use transactions::{
+    BuildError, BuiltTransaction, CoinControl, PrivacyOptions, TransactionBuilder, WalletUtxo, CHANGE_ROUNDING,
+};
@@ -0,0 +1,292 @@
+//! Ritrasmissione periodica delle transazioni del wallet
+//!
+//! Una transazione inviata dal wallet può non raggiungere i miner (peer
+//! disconnessi, mempool riavviate): finché non è confermata viene
+//! ritrasmessa a intervalli regolari. Ogni intervallo ha un ritardo casuale
+//! (jitter) derivato da un sale segreto, così un osservatore della rete non
+//! riconosce l'origine delle transazioni dalla periodicità dei re-invii.
+//!
+//! Una transazione smette di essere ritrasmessa quando è confermata (dallo
+//! storico del wallet), quando non è più nella mempool del nodo (espulsa o
+//! in conflitto), quando supera la sua altezza di scadenza o dopo
+//! `max_age` secondi. Chi non vuole rivelare nulla con
+//! i re-invii può disattivare la ritrasmissione
+//! ([`RebroadcastConfig::disabled`]).
+
+use crate::discovery::FoundOutput;
+use ring::rand::{SecureRandom, SystemRandom};
+use sedly_core::mempool::MEMPOOL_EXPIRY;
+use sedly_core::{Transaction, Txid};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Intervallo di default tra due ritrasmissioni (30 minuti)
+pub const DEFAULT_REBROADCAST_INTERVAL: u64 = 30 * 60;
+
+/// Jitter massimo di default aggiunto all'intervallo (15 minuti)
+pub const DEFAULT_REBROADCAST_JITTER: u64 = 15 * 60;
+
+/// Configurazione della ritrasmissione
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebroadcastConfig {
+    /// Se le transazioni vengono ritrasmesse
+    pub enabled: bool,
+    /// Secondi minimi tra due ritrasmissioni della stessa transazione
+    pub interval: u64,
+    /// Secondi massimi di ritardo casuale aggiunti all'intervallo
+    pub max_jitter: u64,
+    /// Secondi dal primo invio dopo i quali la transazione è abbandonata
+    pub max_age: u64,
+}
+
+impl Default for RebroadcastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: DEFAULT_REBROADCAST_INTERVAL,
+            max_jitter: DEFAULT_REBROADCAST_JITTER,
+            max_age: MEMPOOL_EXPIRY,
+        }
+    }
+}
+
+impl RebroadcastConfig {
+    /// Nessuna ritrasmissione
+    pub fn disabled() -> Self {
+        Self { enabled: false, ..Self::default() }
+    }
+}
+
+/// Stato di una transazione secondo il nodo e lo storico del wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    /// In mempool, in attesa di conferma
+    InMempool,
+    /// Inclusa in un block
+    Confirmed,
+    /// Né in mempool né confermata (espulsa o in conflitto)
+    Missing,
+}
+
+/// Transazione inviata in attesa di conferma
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTx {
+    /// Transazione
+    pub tx: Transaction,
+    /// Timestamp UNIX del primo invio
+    pub first_sent: u64,
+    /// Timestamp UNIX della prossima ritrasmissione
+    pub next_attempt: u64,
+    /// Ritrasmissioni eseguite
+    pub attempts: u32,
+}
+
+/// Transazioni del wallet da ritrasmettere
+#[derive(Debug, Clone)]
+pub struct Rebroadcaster {
+    /// Configurazione
+    config: RebroadcastConfig,
+    /// Sale segreto del jitter
+    salt: [u8; 32],
+    /// Transazioni non confermate per txid
+    pending: BTreeMap<Txid, PendingTx>,
+}
+
+impl Rebroadcaster {
+    /// Crea un rebroadcaster con un sale casuale
+    pub fn new(config: RebroadcastConfig) -> Self {
+        let mut salt = [0u8; 32];
+        if SystemRandom::new().fill(&mut salt).is_err() {
+            log::warn!("No system randomness, rebroadcast jitter is predictable");
+        }
+        Self::with_salt(config, salt)
+    }
+
+    /// Crea un rebroadcaster con il sale `salt` (jitter riproducibile)
+    pub fn with_salt(config: RebroadcastConfig, salt: [u8; 32]) -> Self {
+        Self { config, salt, pending: BTreeMap::new() }
+    }
+
+    /// Configurazione in uso
+    pub fn config(&self) -> &RebroadcastConfig {
+        &self.config
+    }
+
+    /// Registra una transazione inviata al tempo `now`
+    ///
+    /// Ritorna false se la ritrasmissione è disattivata.
+    pub fn track(&mut self, tx: Transaction, now: u64) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let txid = tx.txid();
+        let next_attempt = self.next_attempt(&txid, 0, now);
+        self.pending.entry(txid).or_insert(PendingTx { tx, first_sent: now, next_attempt, attempts: 0 });
+        true
+    }
+
+    /// Smette di ritrasmettere una transazione confermata
+    pub fn mark_confirmed(&mut self, txid: &Txid) -> bool {
+        self.pending.remove(txid).is_some()
+    }
+
+    /// Segna come confermate le transazioni che hanno creato gli output
+    /// trovati da un rescan (il resto torna a un indirizzo del wallet)
+    pub fn confirm_found(&mut self, outputs: &[FoundOutput]) -> usize {
+        outputs
+            .iter()
+            .filter(|output| self.mark_confirmed(&Txid::from(output.outpoint.txid)))
+            .count()
+    }
+
+    /// Transazioni da ritrasmettere al tempo `now`
+    ///
+    /// `status` dà lo stato di ogni transazione in attesa: quelle
+    /// confermate, non più in mempool o troppo vecchie vengono abbandonate,
+    /// le altre sono ritornate se il loro momento è arrivato e
+    /// riprogrammate dopo un nuovo intervallo con jitter.
+    pub fn due(&mut self, now: u64, status: impl Fn(&Txid) -> TxStatus) -> Vec<Transaction> {
+        let mut due = Vec::new();
+        let pending = std::mem::take(&mut self.pending);
+        for (txid, mut entry) in pending {
+            match status(&txid) {
+                TxStatus::Confirmed => {
+                    log::debug!("Transaction {} confirmed, no longer rebroadcast", txid);
+                    continue;
+                }
+                TxStatus::Missing => {
+                    log::warn!("Transaction {} left the mempool unconfirmed, no longer rebroadcast", txid);
+                    continue;
+                }
+                TxStatus::InMempool => {}
+            }
+            if now.saturating_sub(entry.first_sent) >= self.config.max_age {
+                log::warn!("Transaction {} still unconfirmed, no longer rebroadcast", txid);
+                continue;
+            }
+            if entry.next_attempt <= now {
+                entry.attempts += 1;
+                entry.next_attempt = self.next_attempt(&txid, entry.attempts, now);
+                due.push(entry.tx.clone());
+            }
+            self.pending.insert(txid, entry);
+        }
+        due
+    }
+
+    /// Abbandona le transazioni scadute con il tip a `tip_height`
+    ///
+    /// Oltre la sua altezza di scadenza una transazione non può più essere
+    /// confermata: a differenza di una sparita dalla mempool, si può
+    /// ricostruire e rispedire senza rischio di pagare due volte. Ritorna
+    /// le transazioni abbandonate.
+    pub fn expire(&mut self, tip_height: u64) -> Vec<Transaction> {
+        let expired: Vec<Txid> = self.pending
+            .iter()
+            .filter(|(_, entry)| entry.tx.is_expired(tip_height + 1))
+            .map(|(txid, _)| *txid)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|txid| {
+                log::info!("Transaction {} expired unconfirmed, no longer rebroadcast", txid);
+                self.pending.remove(&txid).map(|entry| entry.tx)
+            })
+            .collect()
+    }
+
+    /// Transazioni in attesa di conferma
+    pub fn pending(&self) -> impl Iterator<Item = &PendingTx> {
+        self.pending.values()
+    }
+
+    /// Numero di transazioni in attesa di conferma
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Se non ci sono transazioni in attesa
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Momento della ritrasmissione successiva alla `attempt`-esima
+    fn next_attempt(&self, txid: &Txid, attempt: u32, now: u64) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(txid.as_byte_array());
+        hasher.update(attempt.to_le_bytes());
+        let digest = hasher.finalize();
+        let random = u64::from_le_bytes(digest[..8].try_into().expect("Digest is 32 bytes"));
+        let jitter = random % self.config.max_jitter.saturating_add(1);
+        now.saturating_add(self.config.interval).saturating_add(jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::{AddressChain, AddressPath};
+    use sedly_core::{Amount, OutPoint, TxInput, TxOutput};
+    use std::collections::HashMap;
+
+    fn transaction(byte: u8) -> Transaction {
+        let input = TxInput::new(OutPoint::new([byte; 32], 0), vec![]);
+        Transaction::new(vec![input], vec![TxOutput::to_address(40, b"alice")], 0)
+    }
+
+    #[test]
+    fn test_rebroadcast_schedule() {
+        let config = RebroadcastConfig { interval: 100, max_jitter: 50, max_age: 1_000, ..RebroadcastConfig::default() };
+        let mut rebroadcaster = Rebroadcaster::with_salt(config, [7; 32]);
+        let (first, second, third) = (transaction(1), transaction(2), transaction(3));
+        for tx in [&first, &second, &third] {
+            assert!(rebroadcaster.track(tx.clone(), 0));
+        }
+        let mut status: HashMap<Txid, TxStatus> =
+            [&first, &second, &third].iter().map(|tx| (tx.txid(), TxStatus::InMempool)).collect();
+
+        // Nessuna ritrasmissione prima dell'intervallo, tutte entro intervallo + jitter
+        assert!(rebroadcaster.due(99, |txid| status[txid]).is_empty());
+        assert_eq!(rebroadcaster.due(150, |txid| status[txid]).len(), 3);
+        assert!(rebroadcaster.pending().all(|pending| pending.attempts == 1 && (250..=300).contains(&pending.next_attempt)));
+
+        // Il jitter dipende dal sale: un osservatore non prevede i re-invii
+        let next: Vec<u64> = rebroadcaster.pending().map(|pending| pending.next_attempt).collect();
+        let mut other = Rebroadcaster::with_salt(rebroadcaster.config().clone(), [8; 32]);
+        for tx in [&first, &second, &third] {
+            other.track(tx.clone(), 0);
+        }
+        other.due(150, |_| TxStatus::InMempool);
+        assert_ne!(other.pending().map(|pending| pending.next_attempt).collect::<Vec<_>>(), next);
+
+        // Confermata dallo storico del wallet, espulsa dalla mempool
+        let change = FoundOutput {
+            outpoint: OutPoint::new(first.hash(), 0),
+            value: Amount::from_sat(40),
+            asset_id: [0; 32],
+            height: 5,
+            path: AddressPath { account: 0, chain: AddressChain::Internal, index: 0 },
+            spent_height: None,
+        };
+        assert_eq!(rebroadcaster.confirm_found(&[change]), 1);
+        status.insert(second.txid(), TxStatus::Missing);
+        assert_eq!(rebroadcaster.due(300, |txid| status[txid]), vec![third.clone()]);
+        assert_eq!(rebroadcaster.len(), 1);
+
+        // Abbandonata dopo max_age
+        assert!(rebroadcaster.due(1_000, |txid| status[txid]).is_empty());
+        assert!(rebroadcaster.is_empty());
+
+        // Scaduta: non può più essere confermata, si può rispedire
+        let expiring = transaction(4).with_expiry(20);
+        rebroadcaster.track(expiring.clone(), 1_000);
+        assert!(rebroadcaster.expire(19).is_empty());
+        assert_eq!(rebroadcaster.expire(20), vec![expiring]);
+        assert!(rebroadcaster.is_empty());
+
+        let mut disabled = Rebroadcaster::new(RebroadcastConfig::disabled());
+        assert!(!disabled.track(third, 0));
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Sweep dei fondi di una chiave importata
+//!
+//! Importare la chiave di un paper wallet non basta a metterne al sicuro i
+//! fondi: chiunque abbia visto la carta può ancora spenderli. Lo sweep
+//! sposta tutti gli output spendibili con la chiave (P2PKH e P2PK) in una
+//! sola transazione firmata verso uno script del wallet: un output per
+//! l'SLY, meno la fee, e uno per ogni altro asset.
+
+use crate::keys::PrivateKey;
+use crate::transactions::{WalletUtxo, DEFAULT_FEE_RATE, DUST_THRESHOLD, INPUT_SIGNATURE_SIZE};
+use secp256k1::Secp256k1;
+use sedly_core::sighash::sign_input;
+use sedly_core::{
+    Amount, BlockchainDB, CancellationToken, OutPoint, SerializationError, StorageError, Transaction, TxInput,
+    TxOutput, COINBASE_MATURITY, MIN_TX_FEE,
+};
+use std::collections::BTreeMap;
+
+/// Asset nativo (SLY)
+const NATIVE_ASSET: [u8; 32] = [0; 32];
+
+/// Output del UTXO set spendibili con `key`
+pub fn key_utxos(db: &BlockchainDB, key: &PrivateKey) -> Result<Vec<WalletUtxo>, StorageError> {
+    let scripts = key.script_pubkeys();
+    let scan = db.scan_utxos(&CancellationToken::new(), |_, entry| scripts.contains(&entry.output.script_pubkey))?;
+    Ok(scan.matches
+        .into_iter()
+        .map(|(outpoint, entry)| WalletUtxo {
+            outpoint,
+            output: entry.output,
+            height: entry.block_height,
+            is_coinbase: entry.is_coinbase,
+        })
+        .collect())
+}
+
+/// Transazione di sweep firmata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sweep {
+    /// Transazione firmata, pronta per la mempool
+    pub tx: Transaction,
+    /// UTXO spesi, nell'ordine degli input
+    pub inputs: Vec<WalletUtxo>,
+    /// SLY ricevuti dallo script di destinazione
+    pub amount: Amount,
+    /// Fee pagata
+    pub fee: Amount,
+    /// Coinbase immature lasciate sulla chiave
+    pub immature: Vec<OutPoint>,
+}
+
+/// Costruttore di transazioni di sweep
+#[derive(Debug, Clone)]
+pub struct SweepBuilder {
+    /// Script che riceve i fondi
+    destination: Vec<u8>,
+    /// Fee rate in satoshi per byte
+    fee_rate: u64,
+    /// Altezza del block in cui la transazione può entrare (`u64::MAX` se non nota)
+    spend_height: u64,
+    /// Blocchi di maturazione delle coinbase
+    coinbase_maturity: u64,
+}
+
+impl SweepBuilder {
+    /// Crea un builder che manda i fondi a `destination`
+    pub fn new(destination: Vec<u8>) -> Self {
+        Self {
+            destination,
+            fee_rate: DEFAULT_FEE_RATE,
+            spend_height: u64::MAX,
+            coinbase_maturity: COINBASE_MATURITY,
+        }
+    }
+
+    /// Imposta il fee rate in satoshi per byte
+    pub fn fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Lascia le coinbase non ancora mature per un block a `tip_height + 1`
+    pub fn tip_height(mut self, tip_height: u64) -> Self {
+        self.spend_height = tip_height.saturating_add(1);
+        self
+    }
+
+    /// Imposta i blocchi di maturazione delle coinbase (`ChainParams::coinbase_maturity`)
+    pub fn coinbase_maturity(mut self, coinbase_maturity: u64) -> Self {
+        self.coinbase_maturity = coinbase_maturity;
+        self
+    }
+
+    /// Costruisce e firma lo sweep degli output di `available` spendibili con `key`
+    ///
+    /// Gli output di altri script sono ignorati. La fee è pagata in SLY:
+    /// se l'SLY non la copre con un resto sopra la soglia di dust lo sweep
+    /// fallisce, anche se la chiave ha altri asset.
+    pub fn build(&self, key: &PrivateKey, available: &[WalletUtxo]) -> Result<Sweep, SweepError> {
+        let scripts = key.script_pubkeys();
+        let (inputs, immature): (Vec<&WalletUtxo>, Vec<&WalletUtxo>) = available
+            .iter()
+            .filter(|utxo| scripts.contains(&utxo.output.script_pubkey))
+            .partition(|utxo| utxo.is_mature_with(self.spend_height, self.coinbase_maturity));
+        let immature: Vec<OutPoint> = immature.into_iter().map(|utxo| utxo.outpoint.clone()).collect();
+        if inputs.is_empty() {
+            return Err(SweepError::NoFunds { immature: immature.len() });
+        }
+
+        let mut totals: BTreeMap<[u8; 32], Amount> = BTreeMap::from([(NATIVE_ASSET, Amount::ZERO)]);
+        for utxo in &inputs {
+            let total = totals.entry(utxo.output.asset_id).or_default();
+            *total = total.saturating_add(utxo.output.value);
+        }
+        let outputs: Vec<TxOutput> = totals
+            .iter()
+            .map(|(asset_id, value)| TxOutput::new(*value, *asset_id, self.destination.clone()))
+            .collect();
+        let tx_inputs = inputs.iter().map(|utxo| TxInput::new(utxo.outpoint.clone(), vec![])).collect();
+        let mut tx = Transaction::new(tx_inputs, outputs, 0);
+
+        let size = tx.size()? + tx.inputs.len() * INPUT_SIGNATURE_SIZE;
+        let fee = Amount::from_sat((size as u64).saturating_mul(self.fee_rate)).max(Amount::from_sat(MIN_TX_FEE));
+        let available = totals[&NATIVE_ASSET];
+        let amount = available.checked_sub(fee).filter(|amount| *amount >= DUST_THRESHOLD)
+            .ok_or(SweepError::InsufficientFunds { available, fee })?;
+        tx.outputs[0].value = amount;
+
+        let secp = Secp256k1::signing_only();
+        for (index, utxo) in inputs.iter().enumerate() {
+            let script_sig = sign_input(&secp, &key.secret_key, &tx, index, &utxo.output.script_pubkey)
+                .ok_or_else(|| SweepError::Signing(utxo.outpoint.clone()))?;
+            tx.inputs[index].script_sig = script_sig;
+        }
+
+        Ok(Sweep {
+            tx,
+            inputs: inputs.into_iter().cloned().collect(),
+            amount,
+            fee,
+            immature,
+        })
+    }
+}
+
+/// Errori dello sweep
+#[derive(Debug, thiserror::Error)]
+pub enum SweepError {
+    #[error("No spendable outputs for the key ({immature} immature coinbase outputs)")]
+    NoFunds { immature: usize },
+
+    #[error("Native balance {available} does not cover the fee {fee}")]
+    InsufficientFunds { available: Amount, fee: Amount },
+
+    #[error("Cannot sign input {}:{}", hex::encode(.0.txid), .0.vout)]
+    Signing(OutPoint),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::SecretKey;
+    use sedly_core::{Block, Network};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sweep_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = BlockchainDB::open(temp_dir.path()).unwrap();
+        let key = PrivateKey::new(Network::Regtest, SecretKey::from_slice(&[3; 32]).unwrap());
+        let [p2pkh, p2pk] = key.script_pubkeys();
+
+        // Coinbase P2PK e un asset P2PKH al block 0, SLY P2PKH al block 1
+        let mut coinbase = Transaction::coinbase(&p2pk, 0, 60_000);
+        coinbase.outputs.push(TxOutput::new(5, [7; 32], p2pkh.clone()));
+        let genesis = Block::new([0; 32], vec![coinbase], 0x207fffff, 0);
+        db.store_block(&genesis).unwrap();
+        let mut coinbase = Transaction::coinbase(b"miner", 1, 50);
+        coinbase.outputs.push(TxOutput::new(40_000, NATIVE_ASSET, p2pkh.clone()));
+        db.store_block(&Block::new(genesis.hash(), vec![coinbase], 0x207fffff, 1)).unwrap();
+
+        let utxos = key_utxos(&db, &key).unwrap();
+        assert_eq!(utxos.len(), 3);
+
+        // Con maturità 2 al block 2 solo la coinbase del genesis è spendibile
+        let builder = SweepBuilder::new(b"wallet".to_vec()).coinbase_maturity(2).fee_rate(10);
+        let sweep = builder.clone().tip_height(1).build(&key, &utxos).unwrap();
+        assert_eq!(sweep.inputs.len(), 2);
+        assert_eq!(sweep.immature.len(), 1);
+        assert_eq!(sweep.amount.saturating_add(sweep.fee), Amount::from_sat(60_000));
+        assert_eq!(sweep.tx.outputs.len(), 2);
+        assert_eq!(sweep.tx.outputs[1], TxOutput::new(5, [7; 32], b"wallet".to_vec()));
+        assert!(sweep.tx.inputs.iter().all(|input| !input.script_sig.is_empty()));
+        assert!(sweep.fee.to_sat() >= sweep.tx.size().unwrap() as u64 * 10);
+
+        let sweep = builder.clone().tip_height(2).build(&key, &utxos).unwrap();
+        assert_eq!(sweep.amount.saturating_add(sweep.fee), Amount::from_sat(100_000));
+
+        // Niente da spendere, o SLY sotto la fee
+        assert!(matches!(builder.clone().tip_height(0).build(&key, &utxos[..0]), Err(SweepError::NoFunds { .. })));
+        assert!(matches!(
+            builder.fee_rate(1_000).tip_height(2).build(&key, &utxos),
+            Err(SweepError::InsufficientFunds { .. })
+        ));
+    }
+}
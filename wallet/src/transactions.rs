@@ -0,0 +1,693 @@
+//! Costruzione delle transazioni e coin control
+//!
+//! Il `TransactionBuilder` sceglie gli input tra gli UTXO del wallet per
+//! coprire output e fee, per ogni asset, e aggiunge gli output di resto.
+//! Con il coin control l'utente può congelare UTXO (mai scelti in
+//! automatico, es. output grandi o coinbase da tenere da parte) e indicare
+//! esplicitamente gli input da spendere, anche congelati.
+//!
+//! Per i contratti eUTXO il builder continua anche gli output di stato:
+//! spende l'output e lo ricrea con lo stesso validator e un nuovo datum
+//! (vedi `sedly_core::state`), pagando la fee con gli UTXO del wallet.
+//!
+//! Per la privacy il resto va a un indirizzo nuovo (mai uno degli input
+//! spesi o dei destinatari), finisce in una posizione casuale tra gli output
+//! e il resto nativo è arrotondato, la differenza in fee: un osservatore non
+//! distingue il resto dal pagamento per posizione o per numero di decimali.
+//! Ogni comportamento si disattiva con [`PrivacyOptions`].
+//!
+//! Il resto può andare a un indirizzo derivato da un descriptor
+//! ([`TransactionBuilder::with_change_descriptor`]).
+//!
+//! Le transazioni prodotte non sono firmate.
+
+use crate::descriptor::{Descriptor, DescriptorError};
+use ring::rand::{SecureRandom, SystemRandom};
+use sedly_core::state::{continuation, datum_surcharge};
+use sedly_core::{
+    Amount, OutPoint, SerializationError, StateError, Transaction, TxInput, TxOutput, COINBASE_MATURITY, MIN_TX_FEE,
+};
+use std::collections::{BTreeMap, HashSet};
+
+/// Asset nativo (SLY)
+const NATIVE_ASSET: [u8; 32] = [0; 32];
+
+/// Stima dei bytes aggiunti dallo script_sig firmato di un input (firma + pubkey)
+pub const INPUT_SIGNATURE_SIZE: usize = 107;
+
+/// Resto nativo sotto cui conviene lasciarlo in fee
+pub const DUST_THRESHOLD: Amount = Amount::from_sat(546);
+
+/// Fee rate di default in satoshi per byte
+pub const DEFAULT_FEE_RATE: u64 = 1;
+
+/// Multiplo a cui viene arrotondato per difetto il resto nativo
+pub const CHANGE_ROUNDING: Amount = Amount::from_sat(1_000);
+
+/// Comportamenti del builder a tutela della privacy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivacyOptions {
+    /// Rifiuta uno script di resto già usato da un input o da un destinatario
+    pub fresh_change: bool,
+    /// Mette gli output di resto in posizioni casuali
+    pub shuffle_change: bool,
+    /// Arrotonda il resto nativo a `CHANGE_ROUNDING`, lasciando la differenza in fee
+    pub round_change: bool,
+}
+
+impl Default for PrivacyOptions {
+    fn default() -> Self {
+        Self { fresh_change: true, shuffle_change: true, round_change: true }
+    }
+}
+
+impl PrivacyOptions {
+    /// Nessuna tutela: resto in coda, esatto e su qualunque script
+    pub fn none() -> Self {
+        Self { fresh_change: false, shuffle_change: false, round_change: false }
+    }
+}
+
+/// UTXO del wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletUtxo {
+    /// Output spendibile
+    pub outpoint: OutPoint,
+    /// Output
+    pub output: TxOutput,
+    /// Altezza del block che lo contiene
+    pub height: u64,
+    /// Se è l'output di una coinbase
+    pub is_coinbase: bool,
+}
+
+impl WalletUtxo {
+    /// Se è spendibile in un block all'altezza `spend_height`
+    pub fn is_mature(&self, spend_height: u64) -> bool {
+        self.is_mature_with(spend_height, COINBASE_MATURITY)
+    }
+
+    /// Come `is_mature`, con la maturazione delle coinbase della rete
+    /// (`ChainParams::coinbase_maturity`)
+    pub fn is_mature_with(&self, spend_height: u64, coinbase_maturity: u64) -> bool {
+        !self.is_coinbase || spend_height >= self.height + coinbase_maturity
+    }
+}
+
+/// UTXO congelati, esclusi dalla selezione automatica
+#[derive(Debug, Clone, Default)]
+pub struct CoinControl {
+    /// Output congelati
+    frozen: HashSet<OutPoint>,
+}
+
+impl CoinControl {
+    /// Nessun output congelato
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Congela un output, ritorna false se lo era già
+    pub fn freeze(&mut self, outpoint: OutPoint) -> bool {
+        self.frozen.insert(outpoint)
+    }
+
+    /// Scongela un output, ritorna false se non era congelato
+    pub fn unfreeze(&mut self, outpoint: &OutPoint) -> bool {
+        self.frozen.remove(outpoint)
+    }
+
+    /// Scongela tutti gli output
+    pub fn unfreeze_all(&mut self) {
+        self.frozen.clear();
+    }
+
+    /// Se un output è congelato
+    pub fn is_frozen(&self, outpoint: &OutPoint) -> bool {
+        self.frozen.contains(outpoint)
+    }
+
+    /// Output congelati, ordinati per txid e vout
+    pub fn frozen(&self) -> Vec<OutPoint> {
+        let mut frozen: Vec<OutPoint> = self.frozen.iter().cloned().collect();
+        frozen.sort_by_key(|outpoint| (outpoint.txid, outpoint.vout));
+        frozen
+    }
+}
+
+/// Transazione costruita
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltTransaction {
+    /// Transazione non firmata
+    pub tx: Transaction,
+    /// UTXO spesi, nell'ordine degli input
+    pub inputs: Vec<WalletUtxo>,
+    /// Fee pagata (SLY nativo)
+    pub fee: Amount,
+    /// Indici degli output di resto
+    pub change_outputs: Vec<u32>,
+}
+
+/// Costruttore di transazioni con coin control
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    /// Output richiesti
+    outputs: Vec<TxOutput>,
+    /// Input scelti esplicitamente
+    selected: Vec<OutPoint>,
+    /// Output di stato spesi e ricreati, prima degli altri input
+    continuations: Vec<WalletUtxo>,
+    /// Se si possono aggiungere input oltre a quelli scelti
+    add_inputs: bool,
+    /// Script che riceve il resto
+    change_script: Vec<u8>,
+    /// Fee rate in satoshi per byte
+    fee_rate: u64,
+    /// Fee minima assoluta
+    min_fee: Amount,
+    /// Lock time
+    lock_time: u64,
+    /// Ultima altezza a cui la transazione può essere confermata
+    expiry_height: Option<u64>,
+    /// Altezza del block in cui la transazione può entrare (per la maturità,
+    /// `u64::MAX` se non nota)
+    spend_height: u64,
+    /// Blocchi di maturazione delle coinbase
+    coinbase_maturity: u64,
+    /// Tutele della privacy
+    privacy: PrivacyOptions,
+}
+
+impl TransactionBuilder {
+    /// Crea un builder che manda il resto a `change_script`
+    pub fn new(change_script: Vec<u8>) -> Self {
+        Self {
+            outputs: Vec::new(),
+            selected: Vec::new(),
+            continuations: Vec::new(),
+            add_inputs: true,
+            change_script,
+            fee_rate: DEFAULT_FEE_RATE,
+            min_fee: Amount::from_sat(MIN_TX_FEE),
+            lock_time: 0,
+            expiry_height: None,
+            spend_height: u64::MAX,
+            coinbase_maturity: COINBASE_MATURITY,
+            privacy: PrivacyOptions::default(),
+        }
+    }
+
+    /// Crea un builder che manda il resto all'indirizzo `index` di un
+    /// descriptor, es. la catena interna (`.../1/*`) di un account
+    pub fn with_change_descriptor(descriptor: &Descriptor, index: u32) -> Result<Self, DescriptorError> {
+        Ok(Self::new(descriptor.script_pubkey(index)?))
+    }
+
+    /// Aggiunge un output
+    pub fn add_output(mut self, output: TxOutput) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Spende esplicitamente un UTXO, anche se congelato
+    pub fn add_input(mut self, outpoint: OutPoint) -> Self {
+        if !self.selected.contains(&outpoint) {
+            self.selected.push(outpoint);
+        }
+        self
+    }
+
+    /// Spende l'output di stato `state` e lo ricrea con `datum`
+    ///
+    /// L'output di stato non deve essere tra gli UTXO del wallet: il suo
+    /// valore passa all'output ricreato e la fee resta a carico del wallet.
+    pub fn continue_state(mut self, state: WalletUtxo, datum: &[u8]) -> Result<Self, BuildError> {
+        self.outputs.push(continuation(&state.output, datum)?);
+        self.continuations.push(state);
+        Ok(self)
+    }
+
+    /// Se false usa solo gli input scelti esplicitamente
+    pub fn add_inputs(mut self, enabled: bool) -> Self {
+        self.add_inputs = enabled;
+        self
+    }
+
+    /// Imposta il fee rate in satoshi per byte
+    pub fn fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Imposta la fee minima assoluta
+    pub fn min_fee(mut self, min_fee: impl Into<Amount>) -> Self {
+        self.min_fee = min_fee.into();
+        self
+    }
+
+    /// Imposta il lock time
+    pub fn lock_time(mut self, lock_time: u64) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Fa scadere la transazione dopo il block `expiry_height` (formato v2)
+    ///
+    /// Se non è confermata entro quell'altezza esce dalle mempool e non può
+    /// più essere inclusa: il wallet può rispedire il pagamento senza
+    /// rischiare di pagare due volte. La rete deve ammettere la versione 2.
+    pub fn expiry_height(mut self, expiry_height: u64) -> Self {
+        self.expiry_height = Some(expiry_height);
+        self
+    }
+
+    /// Esclude le coinbase non ancora mature per un block a `tip_height + 1`
+    pub fn tip_height(mut self, tip_height: u64) -> Self {
+        self.spend_height = tip_height.saturating_add(1);
+        self
+    }
+
+    /// Imposta i blocchi di maturazione delle coinbase (`ChainParams::coinbase_maturity`)
+    pub fn coinbase_maturity(mut self, coinbase_maturity: u64) -> Self {
+        self.coinbase_maturity = coinbase_maturity;
+        self
+    }
+
+    /// Imposta le tutele della privacy
+    pub fn privacy(mut self, privacy: PrivacyOptions) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    /// Costruisce la transazione scegliendo tra `available`
+    ///
+    /// Gli input scelti esplicitamente vengono sempre spesi; gli altri sono
+    /// aggiunti dal valore più alto, saltando quelli congelati e le coinbase
+    /// immature, finché ogni asset copre i suoi output (e l'SLY anche la fee).
+    pub fn build(&self, available: &[WalletUtxo], coin_control: &CoinControl) -> Result<BuiltTransaction, BuildError> {
+        if self.outputs.is_empty() {
+            return Err(BuildError::NoOutputs);
+        }
+        if self.outputs.iter().any(|output| output.value == Amount::ZERO) {
+            return Err(BuildError::ZeroValueOutput);
+        }
+
+        let mut inputs = self.continuations.clone();
+        for outpoint in &self.selected {
+            let utxo = available
+                .iter()
+                .find(|utxo| utxo.outpoint == *outpoint)
+                .ok_or_else(|| BuildError::UnknownInput(outpoint.clone()))?;
+            if !utxo.is_mature_with(self.spend_height, self.coinbase_maturity) {
+                return Err(BuildError::ImmatureInput(outpoint.clone()));
+            }
+            inputs.push(utxo.clone());
+        }
+
+        let mut candidates: Vec<&WalletUtxo> = available
+            .iter()
+            .filter(|utxo| !self.selected.contains(&utxo.outpoint))
+            .filter(|utxo| !self.continuations.iter().any(|state| state.outpoint == utxo.outpoint))
+            .filter(|utxo| !coin_control.is_frozen(&utxo.outpoint))
+            .filter(|utxo| utxo.is_mature_with(self.spend_height, self.coinbase_maturity))
+            .collect();
+        candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.output.value));
+
+        loop {
+            let (tx, change_outputs, fee) = self.assemble(&inputs)?;
+            match self.deficit(&inputs, fee) {
+                None => {
+                    if self.privacy.fresh_change && !change_outputs.is_empty() {
+                        self.check_fresh_change(&inputs)?;
+                    }
+                    let (tx, change_outputs) = self.place_change(tx, change_outputs);
+                    return Ok(BuiltTransaction { tx, inputs, fee, change_outputs });
+                }
+                Some((asset_id, needed, have)) => {
+                    let next = self.add_inputs
+                        .then(|| candidates.iter().position(|utxo| utxo.output.asset_id == asset_id))
+                        .flatten();
+                    match next {
+                        Some(position) => inputs.push(candidates.remove(position).clone()),
+                        None => return Err(BuildError::InsufficientFunds { asset_id, needed, available: have }),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Errore se lo script di resto è quello di un input o di un destinatario
+    fn check_fresh_change(&self, inputs: &[WalletUtxo]) -> Result<(), BuildError> {
+        let reused = inputs
+            .iter()
+            .map(|utxo| &utxo.output)
+            .chain(&self.outputs)
+            .any(|output| output.script_pubkey == self.change_script);
+        if reused {
+            return Err(BuildError::ChangeAddressReuse);
+        }
+        Ok(())
+    }
+
+    /// Sposta gli output di resto (in coda) in posizioni casuali, se richiesto
+    fn place_change(&self, mut tx: Transaction, change_outputs: Vec<u32>) -> (Transaction, Vec<u32>) {
+        if !self.privacy.shuffle_change || change_outputs.is_empty() {
+            return (tx, change_outputs);
+        }
+        let change: Vec<TxOutput> = tx.outputs.split_off(tx.outputs.len() - change_outputs.len());
+        let mut positions = Vec::with_capacity(change.len());
+        for output in change {
+            let position = random_index(tx.outputs.len() + 1);
+            for placed in positions.iter_mut().filter(|placed| **placed >= position) {
+                *placed += 1;
+            }
+            tx.outputs.insert(position, output);
+            positions.push(position);
+        }
+        (tx, positions.into_iter().map(|position| position as u32).collect())
+    }
+
+    /// Transazione con gli input dati, gli output di resto e la fee pagata
+    ///
+    /// Il resto nativo sotto `DUST_THRESHOLD` resta in fee, come la parte
+    /// tolta dall'arrotondamento.
+    fn assemble(&self, inputs: &[WalletUtxo]) -> Result<(Transaction, Vec<u32>, Amount), SerializationError> {
+        let surplus = self.surplus(inputs);
+        let mut outputs = self.outputs.clone();
+        let mut change_outputs = Vec::new();
+        for (asset_id, value) in &surplus {
+            if *asset_id != NATIVE_ASSET && *value > Amount::ZERO {
+                change_outputs.push(outputs.len() as u32);
+                outputs.push(TxOutput::new(*value, *asset_id, self.change_script.clone()));
+            }
+        }
+
+        let tx_inputs: Vec<TxInput> = inputs.iter().map(|utxo| TxInput::new(utxo.outpoint.clone(), Vec::new())).collect();
+        let native_surplus = surplus.get(&NATIVE_ASSET).copied().unwrap_or_default();
+
+        // Prima con il resto nativo, poi senza se il resto sarebbe polvere
+        let mut with_change = outputs.clone();
+        with_change.push(TxOutput::new(native_surplus.max(Amount::ONE_SAT), NATIVE_ASSET, self.change_script.clone()));
+        let tx = self.unsigned(tx_inputs.clone(), with_change);
+        let fee = self.fee_for(&tx)?;
+        let mut change_value = native_surplus.saturating_sub(fee);
+        if self.privacy.round_change {
+            change_value = Amount::from_sat(change_value.to_sat() - change_value.to_sat() % CHANGE_ROUNDING.to_sat());
+        }
+        if change_value >= DUST_THRESHOLD {
+            let mut tx = tx;
+            let change = tx.outputs.len() - 1;
+            tx.outputs[change].value = change_value;
+            change_outputs.push(change as u32);
+            return Ok((tx, change_outputs, native_surplus.saturating_sub(change_value)));
+        }
+
+        let tx = self.unsigned(tx_inputs, outputs);
+        let fee = native_surplus.max(self.fee_for(&tx)?);
+        Ok((tx, change_outputs, fee))
+    }
+
+    /// Transazione non firmata con lock time e scadenza del builder
+    fn unsigned(&self, inputs: Vec<TxInput>, outputs: Vec<TxOutput>) -> Transaction {
+        let tx = Transaction::new(inputs, outputs, self.lock_time);
+        match self.expiry_height {
+            Some(expiry_height) => tx.with_expiry(expiry_height),
+            None => tx,
+        }
+    }
+
+    /// Fee richiesta per una transazione non firmata, sovrapprezzo dei datum compreso
+    fn fee_for(&self, tx: &Transaction) -> Result<Amount, SerializationError> {
+        let size = tx.size()? + tx.inputs.len() * INPUT_SIGNATURE_SIZE;
+        let fee = Amount::from_sat((size as u64).saturating_mul(self.fee_rate)).max(self.min_fee);
+        Ok(fee.saturating_add(Amount::from_sat(datum_surcharge(tx))))
+    }
+
+    /// Valore degli input meno quello degli output richiesti, per asset
+    fn surplus(&self, inputs: &[WalletUtxo]) -> BTreeMap<[u8; 32], Amount> {
+        let mut balance: BTreeMap<[u8; 32], i128> = BTreeMap::new();
+        for utxo in inputs {
+            *balance.entry(utxo.output.asset_id).or_default() += utxo.output.value.to_sat() as i128;
+        }
+        for output in &self.outputs {
+            *balance.entry(output.asset_id).or_default() -= output.value.to_sat() as i128;
+        }
+        balance.into_iter().map(|(asset_id, value)| (asset_id, Amount::from_sat(value.max(0) as u64))).collect()
+    }
+
+    /// Primo asset non coperto dagli input: (asset, necessario, disponibile)
+    fn deficit(&self, inputs: &[WalletUtxo], fee: Amount) -> Option<([u8; 32], Amount, Amount)> {
+        let mut needed: BTreeMap<[u8; 32], Amount> = BTreeMap::from([(NATIVE_ASSET, fee)]);
+        for output in &self.outputs {
+            let entry = needed.entry(output.asset_id).or_default();
+            *entry = entry.saturating_add(output.value);
+        }
+        needed.into_iter().find_map(|(asset_id, needed)| {
+            let have = inputs
+                .iter()
+                .filter(|utxo| utxo.output.asset_id == asset_id)
+                .fold(Amount::ZERO, |have, utxo| have.saturating_add(utxo.output.value));
+            (have < needed).then_some((asset_id, needed, have))
+        })
+    }
+}
+
+/// Indice casuale in `0..bound`
+fn random_index(bound: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return bound - 1;
+    }
+    (u64::from_le_bytes(bytes) % bound as u64) as usize
+}
+
+/// Errori della costruzione di una transazione
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    #[error("Transaction has no outputs")]
+    NoOutputs,
+
+    #[error("Output value must be positive")]
+    ZeroValueOutput,
+
+    #[error("Selected input {}:{} is not a wallet UTXO", hex::encode(.0.txid), .0.vout)]
+    UnknownInput(OutPoint),
+
+    #[error("Selected input {}:{} is an immature coinbase output", hex::encode(.0.txid), .0.vout)]
+    ImmatureInput(OutPoint),
+
+    #[error("Insufficient funds for asset {}: need {needed}, available {available}", hex::encode(asset_id))]
+    InsufficientFunds { asset_id: [u8; 32], needed: Amount, available: Amount },
+
+    #[error("Change script is already used by an input or a recipient")]
+    ChangeAddressReuse,
+
+    #[error(transparent)]
+    State(#[from] StateError),
+
+    #[error(transparent)]
+    Serialization(#[from] SerializationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(id: u8, value: u64) -> WalletUtxo {
+        WalletUtxo {
+            outpoint: OutPoint::new([id; 32], 0),
+            output: TxOutput::new(value, NATIVE_ASSET, b"wallet".to_vec()),
+            height: 1,
+            is_coinbase: false,
+        }
+    }
+
+    fn asset_utxo(id: u8, value: u64, asset_id: [u8; 32]) -> WalletUtxo {
+        WalletUtxo { output: TxOutput::new(value, asset_id, b"wallet".to_vec()), ..utxo(id, 0) }
+    }
+
+    #[test]
+    fn test_inputs_selected_per_asset() {
+        const ASSET: [u8; 32] = [7; 32];
+        const OTHER: [u8; 32] = [8; 32];
+        let available = vec![
+            asset_utxo(1, 1_000_000, OTHER),
+            asset_utxo(2, 500, ASSET),
+            asset_utxo(3, 300, ASSET),
+            utxo(4, 20_000),
+        ];
+        let coin_control = CoinControl::new();
+        let builder = TransactionBuilder::new(b"change".to_vec())
+            .add_output(TxOutput::new(600, ASSET, b"payee".to_vec()))
+            .privacy(PrivacyOptions::none());
+        let built = builder.build(&available, &coin_control).unwrap();
+
+        // Gli input dell'asset coprono l'output, la fee la paga solo l'SLY
+        assert_eq!(built.inputs, vec![available[3].clone(), available[1].clone(), available[2].clone()]);
+        let asset_change = &built.tx.outputs[built.change_outputs[0] as usize];
+        assert_eq!((asset_change.asset_id, asset_change.value.to_sat()), (ASSET, 200));
+        let native_change = &built.tx.outputs[built.change_outputs[1] as usize];
+        assert_eq!(native_change.value.checked_add(built.fee), Some(Amount::from_sat(20_000)));
+        assert!(built.tx.outputs.iter().all(|output| output.asset_id != OTHER));
+
+        // Senza SLY la fee non è coperta, qualunque altro asset ci sia
+        assert!(matches!(
+            builder.build(&available[..3], &coin_control),
+            Err(BuildError::InsufficientFunds { asset_id: NATIVE_ASSET, available, .. }) if available == Amount::ZERO
+        ));
+        let short = builder.add_output(TxOutput::new(300, ASSET, b"payee".to_vec()));
+        assert!(matches!(
+            short.build(&available, &coin_control),
+            Err(BuildError::InsufficientFunds { asset_id: ASSET, needed, available })
+                if (needed.to_sat(), available.to_sat()) == (900, 800)
+        ));
+    }
+
+    #[test]
+    fn test_frozen_outputs_are_not_selected() {
+        let available = vec![utxo(1, 100_000), utxo(2, 50_000), utxo(3, 20_000)];
+        let mut coin_control = CoinControl::new();
+        assert!(coin_control.freeze(available[0].outpoint.clone()));
+        assert!(!coin_control.freeze(available[0].outpoint.clone()));
+
+        let builder = TransactionBuilder::new(b"change".to_vec())
+            .add_output(TxOutput::new(30_000, NATIVE_ASSET, b"payee".to_vec()));
+        let built = builder.build(&available, &coin_control).unwrap();
+        assert_eq!(built.inputs, vec![available[1].clone()]);
+        assert_eq!(built.fee, Amount::from_sat(MIN_TX_FEE));
+        assert_eq!(built.tx.outputs[built.change_outputs[0] as usize].value.to_sat(), 50_000 - 30_000 - MIN_TX_FEE);
+
+        // Il congelato resta spendibile solo se scelto esplicitamente
+        let built = builder.clone().add_input(available[0].outpoint.clone()).build(&available, &coin_control).unwrap();
+        assert_eq!(built.inputs, vec![available[0].clone()]);
+
+        let builder = builder.add_output(TxOutput::new(45_000, NATIVE_ASSET, b"payee".to_vec()));
+        assert!(matches!(
+            builder.build(&available, &coin_control),
+            Err(BuildError::InsufficientFunds { available, .. }) if available == Amount::from_sat(70_000)
+        ));
+        coin_control.unfreeze_all();
+        assert_eq!(builder.build(&available, &coin_control).unwrap().inputs.len(), 1);
+    }
+
+    #[test]
+    fn test_explicit_inputs_and_maturity() {
+        let mut coinbase = utxo(1, 5_000_000);
+        coinbase.is_coinbase = true;
+        let available = vec![coinbase.clone(), utxo(2, 10_000), utxo(3, 1_500)];
+        let coin_control = CoinControl::new();
+
+        let builder = TransactionBuilder::new(b"change".to_vec())
+            .add_output(TxOutput::new(9_000, NATIVE_ASSET, b"payee".to_vec()))
+            .tip_height(50);
+        let built = builder.build(&available, &coin_control).unwrap();
+        assert_eq!(built.inputs, vec![available[1].clone()]);
+        // Resto sotto la soglia di polvere: resta in fee
+        assert!(built.change_outputs.is_empty());
+        assert_eq!(built.fee, Amount::from_sat(1_000));
+
+        // Solo gli input scelti: non bastano
+        let only_selected = builder.clone().add_input(available[2].outpoint.clone()).add_inputs(false);
+        assert!(matches!(
+            only_selected.build(&available, &coin_control),
+            Err(BuildError::InsufficientFunds { needed, available, .. })
+                if (needed.to_sat(), available.to_sat()) == (10_000, 1_500)
+        ));
+        assert!(matches!(
+            builder.clone().add_input(coinbase.outpoint.clone()).build(&available, &coin_control),
+            Err(BuildError::ImmatureInput(_))
+        ));
+        // Maturazione della rete (es. regtest con 1 block)
+        let regtest = builder.clone().coinbase_maturity(1).add_input(coinbase.outpoint.clone());
+        assert!(regtest.build(&available, &coin_control).unwrap().inputs.contains(&coinbase));
+        assert_eq!(
+            builder.tip_height(COINBASE_MATURITY).add_input(coinbase.outpoint.clone()).build(&available, &coin_control).unwrap().inputs,
+            vec![coinbase]
+        );
+    }
+
+    #[test]
+    fn test_state_continuation() {
+        use sedly_core::state::{check_continuation, state_script};
+        use sedly_core::StateScript;
+
+        let validator = b"validator".to_vec();
+        let state = WalletUtxo {
+            outpoint: OutPoint::new([9; 32], 0),
+            output: TxOutput::new(5_000, NATIVE_ASSET, state_script(b"1", &validator).unwrap()),
+            height: 1,
+            is_coinbase: false,
+        };
+        let available = vec![utxo(1, 20_000)];
+
+        let builder = TransactionBuilder::new(b"change".to_vec()).continue_state(state.clone(), b"2").unwrap();
+        let built = builder.build(&available, &CoinControl::new()).unwrap();
+        assert_eq!(built.inputs, vec![state.clone(), available[0].clone()]);
+        let position = check_continuation(&built.tx, &state.output).unwrap();
+        let next = StateScript::parse(&built.tx.outputs[position].script_pubkey).unwrap();
+        assert_eq!((next.datum, next.validator), (&b"2"[..], &validator[..]));
+        // Il valore dello stato resta nello stato, la fee la paga il wallet
+        assert_eq!(built.tx.outputs[position].value, Amount::from_sat(5_000));
+        assert_eq!(built.tx.outputs[built.change_outputs[0] as usize].value.to_sat(), 20_000 - MIN_TX_FEE);
+
+        assert!(matches!(
+            TransactionBuilder::new(b"change".to_vec()).continue_state(available[0].clone(), b"2"),
+            Err(BuildError::State(StateError::NotStateOutput))
+        ));
+    }
+
+    #[test]
+    fn test_change_to_descriptor() {
+        use crate::keys::ExtendedPrivKey;
+        use sedly_core::Network;
+
+        let master = ExtendedPrivKey::new_master(Network::Testnet, &[1u8; 32]).unwrap();
+        let internal: Descriptor = format!("pkh({}/1/*)", master.to_extended_public()).parse().unwrap();
+        let built = TransactionBuilder::with_change_descriptor(&internal, 3)
+            .unwrap()
+            .add_output(TxOutput::new(30_000, NATIVE_ASSET, b"payee".to_vec()))
+            .build(&[utxo(1, 100_000)], &CoinControl::new())
+            .unwrap();
+        let change = &built.tx.outputs[built.change_outputs[0] as usize];
+        assert_eq!(change.script_pubkey, internal.script_pubkey(3).unwrap());
+    }
+
+    #[test]
+    fn test_change_privacy() {
+        let available = vec![utxo(1, 100_000)];
+        let coin_control = CoinControl::new();
+        let builder = TransactionBuilder::new(b"change".to_vec())
+            .add_output(TxOutput::new(30_000, NATIVE_ASSET, b"payee".to_vec()))
+            .add_output(TxOutput::new(12_345, NATIVE_ASSET, b"other".to_vec()))
+            .fee_rate(10);
+
+        // Resto arrotondato, la differenza va in fee
+        let exact = builder.clone().privacy(PrivacyOptions::none()).build(&available, &coin_control).unwrap();
+        assert_eq!(exact.change_outputs, vec![2]);
+        let rounded = builder.build(&available, &coin_control).unwrap();
+        let change = rounded.tx.outputs[rounded.change_outputs[0] as usize].value;
+        assert_eq!(change.to_sat() % CHANGE_ROUNDING.to_sat(), 0);
+        assert!(change < exact.tx.outputs[2].value);
+        assert_eq!(change.checked_add(rounded.fee), exact.tx.outputs[2].value.checked_add(exact.fee));
+
+        // Il resto finisce in ogni posizione, gli altri output restano in ordine
+        let mut positions = HashSet::new();
+        for _ in 0..64 {
+            let built = builder.build(&available, &coin_control).unwrap();
+            let position = built.change_outputs[0] as usize;
+            assert_eq!(built.tx.outputs[position].script_pubkey, b"change");
+            let payments: Vec<&TxOutput> =
+                built.tx.outputs.iter().enumerate().filter(|(i, _)| *i != position).map(|(_, o)| o).collect();
+            assert_eq!(payments, vec![&builder.outputs[0], &builder.outputs[1]]);
+            positions.insert(position);
+        }
+        assert_eq!(positions.len(), 3);
+
+        // Resto a un indirizzo già usato
+        let reused = TransactionBuilder::new(b"wallet".to_vec())
+            .add_output(TxOutput::new(30_000, NATIVE_ASSET, b"payee".to_vec()));
+        assert!(matches!(reused.build(&available, &coin_control), Err(BuildError::ChangeAddressReuse)));
+        let allowed = reused.privacy(PrivacyOptions { fresh_change: false, ..PrivacyOptions::default() });
+        assert!(allowed.build(&available, &coin_control).is_ok());
+    }
+}